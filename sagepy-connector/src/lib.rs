@@ -12,6 +12,14 @@ mod py_spectrum;
 mod py_fdr;
 mod py_lfq;
 mod py_tmt;
+mod py_io;
+mod py_export;
+mod py_retention_alignment;
+mod py_utility;
+mod py_spectral_library;
+mod py_intensity;
+mod py_retention_model;
+mod py_mobility_model;
 
 use py_enzyme::enzyme;
 use py_fasta::fasta;
@@ -24,6 +32,14 @@ use py_spectrum::spectrum;
 use py_fdr::fdr;
 use py_lfq::lfq;
 use py_tmt::tmt;
+use py_io::io;
+use py_export::export;
+use py_retention_alignment::retention_alignment;
+use py_utility::utility;
+use py_spectral_library::spectral_library;
+use py_intensity::intensity;
+use py_retention_model::retention_model;
+use py_mobility_model::mobility_model;
 
 #[pymodule]
 fn sagepy_connector(py: Python, m: &PyModule) -> PyResult<()> {
@@ -87,5 +103,45 @@ fn sagepy_connector(py: Python, m: &PyModule) -> PyResult<()> {
     tmt(py, &py_tmt_submodule)?;
     m.add_submodule(py_tmt_submodule)?;
 
+    // py_io submodule //
+    let py_io_submodule = PyModule::new(py, "py_io")?;
+    io(py, &py_io_submodule)?;
+    m.add_submodule(py_io_submodule)?;
+
+    // py_export submodule //
+    let py_export_submodule = PyModule::new(py, "py_export")?;
+    export(py, &py_export_submodule)?;
+    m.add_submodule(py_export_submodule)?;
+
+    // py_retention_alignment submodule //
+    let py_retention_alignment_submodule = PyModule::new(py, "py_retention_alignment")?;
+    retention_alignment(py, &py_retention_alignment_submodule)?;
+    m.add_submodule(py_retention_alignment_submodule)?;
+
+    // py_utility submodule //
+    let py_utility_submodule = PyModule::new(py, "py_utility")?;
+    utility(py, &py_utility_submodule)?;
+    m.add_submodule(py_utility_submodule)?;
+
+    // py_spectral_library submodule //
+    let py_spectral_library_submodule = PyModule::new(py, "py_spectral_library")?;
+    spectral_library(py, &py_spectral_library_submodule)?;
+    m.add_submodule(py_spectral_library_submodule)?;
+
+    // py_intensity submodule //
+    let py_intensity_submodule = PyModule::new(py, "py_intensity")?;
+    intensity(py, &py_intensity_submodule)?;
+    m.add_submodule(py_intensity_submodule)?;
+
+    // py_retention_model submodule //
+    let py_retention_model_submodule = PyModule::new(py, "py_retention_model")?;
+    retention_model(py, &py_retention_model_submodule)?;
+    m.add_submodule(py_retention_model_submodule)?;
+
+    // py_mobility_model submodule //
+    let py_mobility_model_submodule = PyModule::new(py, "py_mobility_model")?;
+    mobility_model(py, &py_mobility_model_submodule)?;
+    m.add_submodule(py_mobility_model_submodule)?;
+
     Ok(())
 }
@@ -1,19 +1,35 @@
 use numpy::{IntoPyArray, PyArray1};
-use std::collections::HashMap;
-
-use crate::py_enzyme::PyEnzymeParameters;
-use crate::py_fasta::PyFasta;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::py_enzyme::{
+    enzyme_rule, non_specific_missed_cleavages, parse_specificity, PyEnzymeParameters, NON_SPECIFIC_RESIDUES,
+};
+use crate::py_fasta::{fasta_hash, PyFasta};
 use crate::py_ion_series::PyKind;
 use crate::py_mass::PyTolerance;
 use crate::py_modification::PyModificationSpecificity;
-use crate::py_peptide::PyPeptide;
+use crate::py_peptide::{detectability, PyPeptide};
+use crate::py_scoring::{check_peptide_indices, PyFeature};
+use pyo3::exceptions::PyValueError;
 use pyo3::prelude::*;
 use pyo3::types::PyDict;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
 use sage_core::database::{
     Builder, EnzymeBuilder, IndexedDatabase, Parameters, PeptideIx, Theoretical,
 };
+use sage_core::enzyme::Position;
 use sage_core::fasta::Fasta;
-use sage_core::ion_series::Kind;
+use sage_core::ion_series::{IonSeries, Kind};
+use sage_core::modification::ModificationSpecificity;
+use sage_core::peptide::Peptide;
 
 #[pyclass]
 #[derive(Clone)]
@@ -73,6 +89,472 @@ impl PyIndexedQuery {
 #[pyclass]
 pub struct PyIndexedDatabase {
     pub inner: IndexedDatabase,
+    pub fasta_hash: String,
+    pub built: u64,
+    /// Light <-> heavy peptide pairs (both directions) of a database built with SILAC labels
+    pub silac_partners: HashMap<u32, u32>,
+    /// Fragment index bounds of the parameters the database was built with
+    pub(crate) fragment_settings: FragmentSettings,
+    /// Databases derived from `inner`, built on first use (see `derived`). The cache is never
+    /// evicted: it holds one re-indexed database per ion kind set and one subset per missed
+    /// cleavage pruning, length range and, with an ion mobility tolerance, per ion mobility band
+    /// range and charge of the searched spectra. Free it with `clear_derived_cache`
+    derived: Mutex<Vec<(DerivedKey, Arc<OnceLock<Arc<DerivedDatabase>>>)>>,
+    /// Predicted inverse ion mobility of each peptide at charges 1 to `IMS_CHARGES`, see
+    /// `precompute_ims_predictions`
+    pub(crate) ims_predictions: Option<Vec<[f32; IMS_CHARGES]>>,
+    /// Source database of each protein accession of each peptide (aligned with `proteins`) of a
+    /// database created by `merge_databases`, None for all others
+    pub(crate) protein_sources: Option<Vec<Vec<u8>>>,
+}
+
+/// Number of precursor charges (1 to 6) with a cached ion mobility prediction per peptide
+pub(crate) const IMS_CHARGES: usize = 6;
+
+/// Fragment index bounds of the `Parameters` a database was built with, needed to index its
+/// peptides with further ion kinds
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) struct FragmentSettings {
+    pub min_ion_index: usize,
+    pub min_mz: f32,
+    pub max_mz: f32,
+}
+
+impl From<&Parameters> for FragmentSettings {
+    fn from(parameters: &Parameters) -> Self {
+        FragmentSettings {
+            min_ion_index: parameters.min_ion_index,
+            min_mz: parameters.fragment_min_mz,
+            max_mz: parameters.fragment_max_mz,
+        }
+    }
+}
+
+impl FragmentSettings {
+    /// Settings of a database built with unknown parameters: the default minimum ion index and
+    /// the m/z range of its fragments
+    fn infer(db: &IndexedDatabase) -> Self {
+        let (min_mz, max_mz) = db
+            .fragments
+            .iter()
+            .fold((f32::MAX, f32::MIN), |(lo, hi), f| (lo.min(f.fragment_mz), hi.max(f.fragment_mz)));
+        FragmentSettings {
+            min_ion_index: Builder::default().make_parameters().min_ion_index,
+            min_mz,
+            max_mz,
+        }
+    }
+}
+
+/// Describes a database derived from the peptides of a `PyIndexedDatabase`, the default key
+/// describes the database itself
+#[derive(Clone, Default, PartialEq)]
+pub(crate) struct DerivedKey {
+    /// Ion kinds the peptides are indexed with, e.g. c and z ions for electron based activation,
+    /// None for those of the database
+    pub ion_kinds: Option<Vec<Kind>>,
+    /// Maximum missed cleavages and abundance prior of missed cleavage pruning (see
+    /// `prune_by_missed_cleavage_probability`)
+    pub mc_prune: Option<(u8, f32)>,
+    /// Inclusive range of peptide lengths kept
+    pub lengths: Option<(usize, usize)>,
+    /// Ion mobility bands of the peptides kept, requires ion mobility predictions
+    pub ims: Option<ImsBands>,
+}
+
+/// Peptides whose predicted inverse ion mobility at `charge` falls into the bands `first` to
+/// `last` (inclusive) of `width` 1/K0, e.g. those of a precursor ion mobility window
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct ImsBands {
+    pub charge: u8,
+    pub width: f32,
+    pub first: i64,
+    pub last: i64,
+}
+
+impl ImsBands {
+    /// The bands covering `center - tolerance` to `center + tolerance`
+    pub fn covering(charge: u8, center: f32, tolerance: f32, width: f32) -> Self {
+        ImsBands {
+            charge,
+            width,
+            first: ((center - tolerance) / width).floor() as i64,
+            last: ((center + tolerance) / width).floor() as i64,
+        }
+    }
+
+    fn contains(&self, predictions: &[f32; IMS_CHARGES]) -> bool {
+        let ims = predictions[(self.charge.clamp(1, IMS_CHARGES as u8) - 1) as usize];
+        (self.first..=self.last).contains(&((ims / self.width).floor() as i64))
+    }
+}
+
+impl DerivedKey {
+    fn is_subset(&self) -> bool {
+        self.mc_prune.is_some() || self.lengths.is_some() || self.ims.is_some()
+    }
+
+    fn keeps(&self, peptide: &Peptide, ims_predictions: Option<&[f32; IMS_CHARGES]>) -> bool {
+        self.mc_prune.map_or(true, |(max_allowed, prior)| {
+            is_missed_cleavage_candidate(peptide.missed_cleavages, max_allowed, prior)
+        }) && self
+            .lengths
+            .map_or(true, |(min, max)| (min..=max).contains(&peptide.sequence.len()))
+            && match (self.ims, ims_predictions) {
+                (Some(bands), Some(predictions)) => bands.contains(predictions),
+                _ => true,
+            }
+    }
+}
+
+/// A database derived from a `PyIndexedDatabase`. Subsets carry the index of each of their
+/// peptides in the original database, otherwise peptide indices are those of the original
+pub(crate) struct DerivedDatabase {
+    pub db: IndexedDatabase,
+    pub original_index: Option<Vec<u32>>,
+}
+
+impl PyIndexedDatabase {
+    /// Wrap a database built from the given FASTA contents
+    pub(crate) fn from_fasta(inner: IndexedDatabase, fasta: &str) -> Self {
+        Self::with_fasta_hash(inner, fasta_hash(fasta.lines()))
+    }
+
+    /// Wrap a database built from a FASTA with the given `fasta_hash`
+    pub(crate) fn with_fasta_hash(inner: IndexedDatabase, fasta_hash: String) -> Self {
+        PyIndexedDatabase {
+            fragment_settings: FragmentSettings::infer(&inner),
+            inner,
+            fasta_hash,
+            built: unix_timestamp(),
+            silac_partners: HashMap::new(),
+            derived: Mutex::new(Vec::new()),
+            ims_predictions: None,
+            protein_sources: None,
+        }
+    }
+
+    /// The same database, recording the fragment index bounds of the `parameters` it was built with
+    pub(crate) fn built_with(self, parameters: &Parameters) -> Self {
+        PyIndexedDatabase {
+            fragment_settings: FragmentSettings::from(parameters),
+            ..self
+        }
+    }
+
+    /// Cache the ion mobility predictions of each peptide alongside the database, which is not
+    /// copied. Cached ion mobility subsets were derived from earlier predictions and are dropped
+    pub(crate) fn set_ims_predictions<F>(&mut self, predict: F)
+    where
+        F: Fn(&Peptide) -> [f32; IMS_CHARGES] + Send + Sync,
+    {
+        self.ims_predictions = Some(self.inner.peptides.par_iter().map(predict).collect());
+        self.derived.get_mut().unwrap().retain(|(key, _)| key.ims.is_none());
+    }
+
+    /// The database derived as described by `key`, built on first use and cached, so that
+    /// searches (e.g. `PyScorer.score` once per spectrum) do not re-index the peptides each time
+    pub(crate) fn derived(&self, key: &DerivedKey) -> Arc<DerivedDatabase> {
+        let cell = {
+            let mut derived = self.derived.lock().unwrap();
+            match derived.iter().find(|(k, _)| k == key) {
+                Some((_, cell)) => cell.clone(),
+                None => {
+                    let cell = Arc::new(OnceLock::new());
+                    derived.push((key.clone(), cell.clone()));
+                    cell
+                }
+            }
+        };
+        // built without holding the lock, concurrent searches needing the same database wait
+        // for a single build
+        cell.get_or_init(|| Arc::new(self.build_derived(key))).clone()
+    }
+
+    fn build_derived(&self, key: &DerivedKey) -> DerivedDatabase {
+        match key.is_subset() {
+            true => {
+                let reindexed = key.ion_kinds.clone().map(|ion_kinds| {
+                    self.derived(&DerivedKey {
+                        ion_kinds: Some(ion_kinds),
+                        ..DerivedKey::default()
+                    })
+                });
+                let source = reindexed.as_ref().map_or(&self.inner, |r| &r.db);
+                // re-indexing keeps the peptide order, so ion mobility predictions apply to the
+                // source as well
+                let keeps = |i: usize, p: &Peptide| key.keeps(p, self.ims_predictions.as_ref().map(|v| &v[i]));
+                // subset_database preserves the peptide order, the i-th peptide of the
+                // sub-database is the i-th peptide of the source accepted by the key
+                let original_index = (0..source.peptides.len() as u32)
+                    .filter(|&i| keeps(i as usize, &source.peptides[i as usize]))
+                    .collect();
+                DerivedDatabase {
+                    db: subset_database(source, keeps),
+                    original_index: Some(original_index),
+                }
+            }
+            false => DerivedDatabase {
+                db: self.with_ion_kinds(key.ion_kinds.clone().unwrap_or_else(|| self.inner.ion_kinds.clone())),
+                original_index: None,
+            },
+        }
+    }
+
+    /// Index the peptides with other fragment ion kinds, e.g. c and z ions for electron based
+    /// activation, within the fragment bounds the database was built with. The peptide order and
+    /// thus every `PeptideIx` stays the same
+    fn with_ion_kinds(&self, ion_kinds: Vec<Kind>) -> IndexedDatabase {
+        let settings = &self.fragment_settings;
+        let fragments = theoretical_fragments(
+            &self.inner.peptides,
+            &ion_kinds,
+            settings.min_ion_index,
+            settings.min_mz,
+            settings.max_mz,
+        );
+
+        // peptides are already sorted by mass, the stable sort of index_peptides keeps their order
+        index_peptides(
+            self.inner.peptides.clone(),
+            fragments,
+            ion_kinds,
+            self.inner.potential_mods.clone(),
+            self.inner.bucket_size,
+            self.inner.generate_decoys,
+            self.inner.decoy_tag.clone(),
+        )
+    }
+
+    /// Wrap a database whose source FASTA is unknown, the hash is derived from the protein
+    /// accessions and peptide sequences of the index instead
+    pub(crate) fn from_index(inner: IndexedDatabase) -> Self {
+        let mut hasher = Sha256::new();
+        for peptide in inner.peptides.iter() {
+            hasher.update(&peptide.sequence[..]);
+            for protein in peptide.proteins.iter() {
+                hasher.update(b"|");
+                hasher.update(protein.as_bytes());
+            }
+            hasher.update(b"\n");
+        }
+        Self::with_fasta_hash(inner, format!("{:x}", hasher.finalize()))
+    }
+
+    fn write_file(&self, path: &str, parameters_hash: Option<String>) -> PyResult<()> {
+        let mut silac_partners: Vec<(u32, u32)> = self
+            .silac_partners
+            .iter()
+            .filter(|(light, heavy)| light < heavy)
+            .map(|(light, heavy)| (*light, *heavy))
+            .collect();
+        silac_partners.sort_unstable();
+        let header = DatabaseFileHeader {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            fasta_hash: self.fasta_hash.clone(),
+            checksum: self.checksum(),
+            parameters_hash,
+            built: self.built,
+            silac_partners,
+            fragment_settings: self.fragment_settings,
+        };
+        let mut writer = BufWriter::new(File::create(path).map_err(|e| database_file_error(path, e))?);
+        writer
+            .write_all(DATABASE_FILE_MAGIC)
+            .map_err(|e| database_file_error(path, e))?;
+        bincode::serialize_into(&mut writer, &header).map_err(|e| database_file_error(path, e))?;
+        bincode::serialize_into(&mut writer, &StoredDatabase::from(&self.inner))
+            .map_err(|e| database_file_error(path, e))?;
+        writer.flush().map_err(|e| database_file_error(path, e))
+    }
+
+    /// Number of distinct target proteins in the database
+    fn num_proteins(&self) -> usize {
+        self.inner
+            .peptides
+            .iter()
+            .filter(|p| !p.decoy)
+            .flat_map(|p| p.proteins.iter())
+            .collect::<HashSet<_>>()
+            .len()
+    }
+}
+
+fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Leading bytes of a serialised database file
+const DATABASE_FILE_MAGIC: &[u8; 8] = b"SAGEPYDB";
+
+#[derive(Serialize, Deserialize)]
+struct DatabaseFileHeader {
+    version: String,
+    fasta_hash: String,
+    checksum: String,
+    /// Hash of the parameters the database was built with, if known
+    parameters_hash: Option<String>,
+    built: u64,
+    /// SILAC peptide pairs, each stored once
+    silac_partners: Vec<(u32, u32)>,
+    fragment_settings: FragmentSettings,
+}
+
+/// Serialisable mirror of `Peptide`
+#[derive(Serialize, Deserialize)]
+struct StoredPeptide {
+    decoy: bool,
+    sequence: Vec<u8>,
+    modifications: Vec<f32>,
+    nterm: Option<f32>,
+    cterm: Option<f32>,
+    monoisotopic: f32,
+    missed_cleavages: u8,
+    position: u8,
+    proteins: Vec<String>,
+    semi_enzymatic: bool,
+}
+
+impl From<&Peptide> for StoredPeptide {
+    fn from(peptide: &Peptide) -> Self {
+        StoredPeptide {
+            decoy: peptide.decoy,
+            sequence: peptide.sequence.to_vec(),
+            modifications: peptide.modifications.clone(),
+            nterm: peptide.nterm,
+            cterm: peptide.cterm,
+            monoisotopic: peptide.monoisotopic,
+            missed_cleavages: peptide.missed_cleavages,
+            position: match peptide.position {
+                Position::Nterm => 0,
+                Position::Cterm => 1,
+                Position::Full => 2,
+                Position::Internal => 3,
+            },
+            proteins: peptide.proteins.iter().map(|p| p.to_string()).collect(),
+            semi_enzymatic: peptide.semi_enzymatic,
+        }
+    }
+}
+
+impl From<StoredPeptide> for Peptide {
+    fn from(peptide: StoredPeptide) -> Self {
+        Peptide {
+            decoy: peptide.decoy,
+            sequence: Arc::from(peptide.sequence.into_boxed_slice()),
+            modifications: peptide.modifications,
+            nterm: peptide.nterm,
+            cterm: peptide.cterm,
+            monoisotopic: peptide.monoisotopic,
+            missed_cleavages: peptide.missed_cleavages,
+            position: match peptide.position {
+                0 => Position::Nterm,
+                1 => Position::Cterm,
+                2 => Position::Full,
+                _ => Position::Internal,
+            },
+            proteins: peptide.proteins.into_iter().map(Arc::new).collect(),
+            semi_enzymatic: peptide.semi_enzymatic,
+        }
+    }
+}
+
+/// Serialisable mirror of `IndexedDatabase`
+#[derive(Serialize, Deserialize)]
+struct StoredDatabase {
+    peptides: Vec<StoredPeptide>,
+    fragments: Vec<(u32, f32)>,
+    ion_kinds: Vec<Kind>,
+    min_value: Vec<f32>,
+    potential_mods: Vec<(String, f32)>,
+    bucket_size: usize,
+    generate_decoys: bool,
+    decoy_tag: String,
+}
+
+impl From<&IndexedDatabase> for StoredDatabase {
+    fn from(db: &IndexedDatabase) -> Self {
+        StoredDatabase {
+            peptides: db.peptides.iter().map(StoredPeptide::from).collect(),
+            fragments: db
+                .fragments
+                .iter()
+                .map(|f| (f.peptide_index.0, f.fragment_mz))
+                .collect(),
+            ion_kinds: db.ion_kinds.clone(),
+            min_value: db.min_value.clone(),
+            potential_mods: db
+                .potential_mods
+                .iter()
+                .map(|(m, mass)| (m.to_string(), *mass))
+                .collect(),
+            bucket_size: db.bucket_size,
+            generate_decoys: db.generate_decoys,
+            decoy_tag: db.decoy_tag.clone(),
+        }
+    }
+}
+
+impl TryFrom<StoredDatabase> for IndexedDatabase {
+    type Error = PyErr;
+
+    fn try_from(db: StoredDatabase) -> Result<Self, Self::Error> {
+        let mut potential_mods = Vec::with_capacity(db.potential_mods.len());
+        for (m, mass) in db.potential_mods.iter() {
+            let specificity = ModificationSpecificity::from_str(m).map_err(|_| {
+                PyValueError::new_err(format!("Invalid modification string: {}", m))
+            })?;
+            potential_mods.push((specificity, *mass));
+        }
+        Ok(IndexedDatabase {
+            peptides: db.peptides.into_iter().map(Peptide::from).collect(),
+            fragments: db
+                .fragments
+                .into_iter()
+                .map(|(idx, fragment_mz)| Theoretical {
+                    peptide_index: PeptideIx(idx),
+                    fragment_mz,
+                })
+                .collect(),
+            ion_kinds: db.ion_kinds,
+            min_value: db.min_value,
+            potential_mods,
+            bucket_size: db.bucket_size,
+            generate_decoys: db.generate_decoys,
+            decoy_tag: db.decoy_tag,
+        })
+    }
+}
+
+fn database_file_error(path: &str, e: impl std::fmt::Display) -> PyErr {
+    PyValueError::new_err(format!("Failed to access database file {}: {}", path, e))
+}
+
+/// Read the header of a database file, leaving the reader positioned at the database
+fn read_database_header(path: &str) -> PyResult<(DatabaseFileHeader, BufReader<File>)> {
+    let mut reader = BufReader::new(File::open(path).map_err(|e| database_file_error(path, e))?);
+    let mut magic = [0u8; 8];
+    reader
+        .read_exact(&mut magic)
+        .map_err(|e| database_file_error(path, e))?;
+    if &magic != DATABASE_FILE_MAGIC {
+        return Err(PyValueError::new_err(format!("{} is not a sagepy database file", path)));
+    }
+    let header: DatabaseFileHeader =
+        bincode::deserialize_from(&mut reader).map_err(|e| database_file_error(path, e))?;
+    Ok((header, reader))
+}
+
+/// SHA-256 hex digest of the serialised database parameters, including the FASTA contents
+fn parameters_hash(parameters: &PyParameters) -> PyResult<String> {
+    let json = serde_json::to_string(&ParameterSettings::from(parameters))
+        .map_err(|e| PyValueError::new_err(e.to_string()))?;
+    let mut hasher = Sha256::new();
+    hasher.update(json.as_bytes());
+    Ok(format!("{:x}", hasher.finalize()))
 }
 
 #[pymethods]
@@ -88,30 +570,108 @@ impl PyIndexedDatabase {
         generate_decoys: bool,
         decoy_tag: String,
     ) -> PyResult<Self> {
-        Ok(PyIndexedDatabase {
-            inner: IndexedDatabase {
-                peptides: peptides.into_iter().map(|p| p.inner).collect(),
-                fragments: fragments.into_iter().map(|f| f.inner).collect(),
-                ion_kinds: ion_kinds.into_iter().map(|k| k.inner).collect(),
-                min_value,
-                potential_mods: potential_mods
-                    .into_iter()
-                    .map(|(k, v)| (k.inner, v))
-                    .collect(),
-                bucket_size,
-                generate_decoys,
-                decoy_tag,
-            },
-        })
+        Ok(PyIndexedDatabase::from_index(IndexedDatabase {
+            peptides: peptides.into_iter().map(|p| p.inner).collect(),
+            fragments: fragments.into_iter().map(|f| f.inner).collect(),
+            ion_kinds: ion_kinds.into_iter().map(|k| k.inner).collect(),
+            min_value,
+            potential_mods: potential_mods
+                .into_iter()
+                .map(|(k, v)| (k.inner, v))
+                .collect(),
+            bucket_size,
+            generate_decoys,
+            decoy_tag,
+        }))
     }
 
     #[staticmethod]
     pub fn from_parameters(parameters: PyParameters, fasta: PyFasta) -> PyResult<Self> {
+        let fragment_settings = FragmentSettings::from(&parameters.inner);
         Ok(PyIndexedDatabase {
-            inner: parameters.inner.build(fasta.inner),
+            fragment_settings,
+            ..PyIndexedDatabase::with_fasta_hash(parameters.inner.build(fasta.inner), fasta.fasta_hash)
         })
     }
 
+    /// SHA-256 digest of the FASTA content the database was built from
+    #[getter]
+    pub fn fasta_hash(&self) -> String {
+        self.fasta_hash.clone()
+    }
+
+    /// Build time as unix timestamp (seconds)
+    #[getter]
+    pub fn built(&self) -> u64 {
+        self.built
+    }
+
+    /// SHA-256 digest of the FASTA content and the serialised database: the peptide table
+    /// (resulting from enzyme, static and variable modifications and length and mass bounds), the
+    /// fragment index and the ion kind, modification and decoy settings. Identifies the database
+    /// contents independent of when it was built
+    pub fn checksum(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.fasta_hash.as_bytes());
+        bincode::serialize_into(&mut hasher, &StoredDatabase::from(&self.inner))
+            .expect("serialising a database into a hasher cannot fail");
+        format!("{:x}", hasher.finalize())
+    }
+
+    /// Serialise the database to a file, see `from_file`
+    pub fn to_file(&self, path: &str) -> PyResult<()> {
+        self.write_file(path, None)
+    }
+
+    /// Load a database written by `to_file`. Files written by a different sagepy-connector version
+    /// or whose contents do not match the stored checksum are rejected
+    #[staticmethod]
+    pub fn from_file(path: &str) -> PyResult<Self> {
+        let (header, mut reader) = read_database_header(path)?;
+        if header.version != env!("CARGO_PKG_VERSION") {
+            return Err(PyValueError::new_err(format!(
+                "Database file {} was written by version {}, expected {}",
+                path,
+                header.version,
+                env!("CARGO_PKG_VERSION")
+            )));
+        }
+        let stored: StoredDatabase =
+            bincode::deserialize_from(&mut reader).map_err(|e| database_file_error(path, e))?;
+
+        let db = PyIndexedDatabase {
+            inner: stored.try_into()?,
+            fasta_hash: header.fasta_hash,
+            built: header.built,
+            silac_partners: header
+                .silac_partners
+                .iter()
+                .flat_map(|(a, b)| [(*a, *b), (*b, *a)])
+                .collect(),
+            fragment_settings: header.fragment_settings,
+            derived: Mutex::new(Vec::new()),
+            ims_predictions: None,
+            protein_sources: None,
+        };
+        if db.checksum() != header.checksum {
+            return Err(PyValueError::new_err(format!(
+                "Database file {} does not match its checksum",
+                path
+            )));
+        }
+        Ok(db)
+    }
+
+    #[getter]
+    pub fn version_string(&self) -> String {
+        format!(
+            "{} proteins, hash={}, built={}",
+            self.num_proteins(),
+            &self.fasta_hash[..8],
+            self.built
+        )
+    }
+
     pub fn query(
         &self,
         precursor_mass: f32,
@@ -136,6 +696,49 @@ impl PyIndexedDatabase {
         })
     }
 
+    /// Drop the databases derived for searches (re-indexed for other ion kinds or restricted to
+    /// candidate subsets), they are rebuilt on next use. Searches still running keep theirs
+    pub fn clear_derived_cache(&self) {
+        self.derived.lock().unwrap().clear();
+    }
+
+    /// Number of databases derived for searches and cached, see `clear_derived_cache`
+    #[getter]
+    pub fn num_derived_databases(&self) -> usize {
+        self.derived.lock().unwrap().len()
+    }
+
+    /// The heavy variant of a light peptide and vice versa, if the database was built with
+    /// SILAC labels and the peptide holds a labelled residue
+    pub fn get_silac_partner(&self, peptide_idx: PyPeptideIx) -> Option<PyPeptideIx> {
+        self.silac_partners
+            .get(&peptide_idx.inner.0)
+            .map(|idx| PyPeptideIx { inner: PeptideIx(*idx) })
+    }
+
+    /// Protein accessions of a peptide, each with the index (`source_db_id`) of the database it
+    /// came from in `merge_databases`, 0 for all proteins of a database that was not merged.
+    /// Sources are not written by `to_file`
+    pub fn get_protein_sources(&self, peptide_idx: PyPeptideIx) -> PyResult<Vec<(String, u8)>> {
+        let idx = peptide_idx.inner.0 as usize;
+        let peptide = self.inner.peptides.get(idx).ok_or_else(|| {
+            PyValueError::new_err(format!(
+                "peptide index {} out of range ({} peptides)",
+                idx,
+                self.inner.peptides.len()
+            ))
+        })?;
+        Ok(peptide
+            .proteins
+            .iter()
+            .enumerate()
+            .map(|(k, protein)| {
+                let source = self.protein_sources.as_ref().map_or(0, |sources| sources[idx][k]);
+                (protein.to_string(), source)
+            })
+            .collect())
+    }
+
     pub fn __getitem__(&self, index: PyPeptideIx) -> PyPeptide {
         PyPeptide {
             inner: self.inner[index.inner].clone(),
@@ -287,6 +890,9 @@ impl PyIndexedDatabase {
 
 }
 
+/// Maximum peptide length sage uses when none is configured
+const DEFAULT_MAX_PEPTIDE_LEN: usize = 50;
+
 #[pyclass]
 #[derive(Clone, Debug)]
 pub struct PyEnzymeBuilder {
@@ -304,27 +910,62 @@ impl PyEnzymeBuilder {
         restrict: Option<char>,
         c_terminal: Option<bool>,
         semi_enzymatic: Option<bool>,
+        specificity: Option<&str>,
     ) -> PyResult<Self> {
+        let mut inner = EnzymeBuilder {
+            missed_cleavages,
+            min_len,
+            max_len,
+            cleave_at,
+            restrict,
+            c_terminal,
+            semi_enzymatic,
+        };
+        match specificity.map(parse_specificity).transpose()? {
+            Some((semi, false)) => inner.semi_enzymatic = Some(semi),
+            Some((_, true)) => {
+                let max_len = max_len.unwrap_or(DEFAULT_MAX_PEPTIDE_LEN);
+                inner.missed_cleavages = Some(non_specific_missed_cleavages(missed_cleavages.unwrap_or(0), max_len));
+                inner.cleave_at = Some(NON_SPECIFIC_RESIDUES.to_string());
+                inner.restrict = None;
+                inner.c_terminal = Some(true);
+                inner.semi_enzymatic = Some(false);
+            }
+            None => {}
+        }
+        Ok(PyEnzymeBuilder { inner })
+    }
+
+    #[staticmethod]
+    pub fn from_default_trypsin() -> PyResult<Self> {
+        Ok(PyEnzymeBuilder {
+            inner: EnzymeBuilder::default(),
+        })
+    }
+
+    /// Builder with the cleavage rule of a named enzyme, e.g. "gluc" or "aspn", see `enzyme_rule`
+    #[staticmethod]
+    pub fn from_name(
+        name: &str,
+        missed_cleavages: Option<u8>,
+        min_len: Option<usize>,
+        max_len: Option<usize>,
+        semi_enzymatic: Option<bool>,
+    ) -> PyResult<Self> {
+        let (cleave_at, restrict, c_terminal) = enzyme_rule(name)?;
         Ok(PyEnzymeBuilder {
             inner: EnzymeBuilder {
                 missed_cleavages,
                 min_len,
                 max_len,
-                cleave_at,
+                cleave_at: Some(cleave_at.to_string()),
                 restrict,
-                c_terminal,
+                c_terminal: Some(c_terminal),
                 semi_enzymatic,
             },
         })
     }
 
-    #[staticmethod]
-    pub fn from_default_trypsin() -> PyResult<Self> {
-        Ok(PyEnzymeBuilder {
-            inner: EnzymeBuilder::default(),
-        })
-    }
-
     pub fn get_enzyme_parameters(&self) -> PyResult<PyEnzymeParameters> {
         Ok(PyEnzymeParameters {
             inner: self.clone().inner.into(),
@@ -360,6 +1001,22 @@ impl PyEnzymeBuilder {
     pub fn c_terminal(&self) -> Option<bool> {
         self.inner.c_terminal
     }
+
+    #[getter]
+    pub fn semi_enzymatic(&self) -> Option<bool> {
+        self.inner.semi_enzymatic
+    }
+
+    #[getter]
+    pub fn specificity(&self) -> &str {
+        if self.inner.cleave_at.as_deref() == Some(NON_SPECIFIC_RESIDUES) {
+            "none"
+        } else if self.inner.semi_enzymatic.unwrap_or(false) {
+            "semi"
+        } else {
+            "full"
+        }
+    }
 }
 
 #[pyclass]
@@ -414,10 +1071,177 @@ impl PyTheoretical {
     }
 }
 
+/// A SILAC amino acid label, the mass shift is added to every occurrence of the residue
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SilacLabel {
+    pub amino_acid: char,
+    pub mass_shift: f32,
+    pub is_heavy: bool,
+}
+
+#[pyclass]
+#[derive(Clone)]
+pub struct PySilacLabel {
+    pub inner: SilacLabel,
+}
+
+#[pymethods]
+impl PySilacLabel {
+    #[new]
+    pub fn new(amino_acid: char, mass_shift: f32, is_heavy: bool) -> PyResult<Self> {
+        if !amino_acid.is_ascii_uppercase() {
+            return Err(PyValueError::new_err(format!(
+                "Invalid SILAC amino acid: {}",
+                amino_acid
+            )));
+        }
+        Ok(PySilacLabel {
+            inner: SilacLabel {
+                amino_acid,
+                mass_shift,
+                is_heavy,
+            },
+        })
+    }
+
+    #[getter]
+    pub fn amino_acid(&self) -> char {
+        self.inner.amino_acid
+    }
+
+    #[getter]
+    pub fn mass_shift(&self) -> f32 {
+        self.inner.mass_shift
+    }
+
+    #[getter]
+    pub fn is_heavy(&self) -> bool {
+        self.inner.is_heavy
+    }
+}
+
 #[pyclass]
 #[derive(Clone, Debug)]
 pub struct PyParameters {
     pub inner: Parameters,
+    pub silac_labels: Vec<SilacLabel>,
+    /// Peptides with more potential variable modification sites are only indexed unmodified
+    pub max_variable_mod_sites: Option<u8>,
+}
+
+/// Upper limit of `max_variable_mods`, the number of modified variants grows exponentially in it
+pub const MAX_VARIABLE_MODS: usize = 4;
+
+fn check_max_variable_mods(max_variable_mods: usize) -> PyResult<()> {
+    if max_variable_mods > MAX_VARIABLE_MODS {
+        return Err(PyValueError::new_err(format!(
+            "max_variable_mods must be at most {}, got {}",
+            MAX_VARIABLE_MODS, max_variable_mods
+        )));
+    }
+    Ok(())
+}
+
+/// Serialisable mirror of `Parameters`, modifications are stored by their string representation
+/// in ordered maps so that the JSON output is stable
+#[derive(Serialize, Deserialize)]
+pub(crate) struct ParameterSettings {
+    bucket_size: usize,
+    enzyme: EnzymeBuilder,
+    fragment_min_mz: f32,
+    fragment_max_mz: f32,
+    peptide_min_mass: f32,
+    peptide_max_mass: f32,
+    ion_kinds: Vec<Kind>,
+    min_ion_index: usize,
+    static_mods: BTreeMap<String, f32>,
+    variable_mods: BTreeMap<String, Vec<f32>>,
+    max_variable_mods: usize,
+    decoy_tag: String,
+    generate_decoys: bool,
+    fasta: String,
+    #[serde(default)]
+    silac_labels: Vec<SilacLabel>,
+    #[serde(default)]
+    max_variable_mod_sites: Option<u8>,
+}
+
+impl From<&PyParameters> for ParameterSettings {
+    fn from(py_parameters: &PyParameters) -> Self {
+        let parameters = &py_parameters.inner;
+        ParameterSettings {
+            bucket_size: parameters.bucket_size,
+            enzyme: parameters.enzyme.clone(),
+            fragment_min_mz: parameters.fragment_min_mz,
+            fragment_max_mz: parameters.fragment_max_mz,
+            peptide_min_mass: parameters.peptide_min_mass,
+            peptide_max_mass: parameters.peptide_max_mass,
+            ion_kinds: parameters.ion_kinds.clone(),
+            min_ion_index: parameters.min_ion_index,
+            static_mods: parameters
+                .static_mods
+                .iter()
+                .map(|(k, v)| (k.to_string(), *v))
+                .collect(),
+            variable_mods: parameters
+                .variable_mods
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.clone()))
+                .collect(),
+            max_variable_mods: parameters.max_variable_mods,
+            decoy_tag: parameters.decoy_tag.clone(),
+            generate_decoys: parameters.generate_decoys,
+            fasta: parameters.fasta.clone(),
+            silac_labels: py_parameters.silac_labels.clone(),
+            max_variable_mod_sites: py_parameters.max_variable_mod_sites,
+        }
+    }
+}
+
+impl TryFrom<ParameterSettings> for PyParameters {
+    type Error = PyErr;
+
+    fn try_from(settings: ParameterSettings) -> Result<Self, Self::Error> {
+        let parse = |s: &String| {
+            ModificationSpecificity::from_str(s).map_err(|_| {
+                PyValueError::new_err(format!("Invalid modification string: {}", s))
+            })
+        };
+
+        check_max_variable_mods(settings.max_variable_mods)?;
+
+        let mut static_mods = HashMap::new();
+        for (k, v) in settings.static_mods.iter() {
+            static_mods.insert(parse(k)?, *v);
+        }
+
+        let mut variable_mods = HashMap::new();
+        for (k, v) in settings.variable_mods.iter() {
+            variable_mods.insert(parse(k)?, v.clone());
+        }
+
+        let inner = Parameters {
+            bucket_size: settings.bucket_size,
+            enzyme: settings.enzyme,
+            fragment_min_mz: settings.fragment_min_mz,
+            fragment_max_mz: settings.fragment_max_mz,
+            peptide_min_mass: settings.peptide_min_mass,
+            peptide_max_mass: settings.peptide_max_mass,
+            ion_kinds: settings.ion_kinds,
+            min_ion_index: settings.min_ion_index,
+            static_mods,
+            variable_mods,
+            max_variable_mods: settings.max_variable_mods,
+            decoy_tag: settings.decoy_tag,
+            generate_decoys: settings.generate_decoys,
+            fasta: settings.fasta,
+        };
+        Ok(PyParameters {
+            inner,
+            silac_labels: settings.silac_labels,
+            max_variable_mod_sites: settings.max_variable_mod_sites,
+        })
+    }
 }
 
 #[pymethods]
@@ -438,7 +1262,10 @@ impl PyParameters {
         generate_decoys: bool,
         fasta: String,
         ion_kinds: Option<Vec<PyKind>>,
+        silac_labels: Option<Vec<PySilacLabel>>,
+        max_variable_mod_sites: Option<u8>,
     ) -> PyResult<Self> {
+        check_max_variable_mods(max_variable_mods)?;
         Ok(PyParameters {
             inner: Parameters {
                 bucket_size,
@@ -468,15 +1295,35 @@ impl PyParameters {
                 generate_decoys,
                 fasta,
             },
+            silac_labels: silac_labels
+                .unwrap_or_default()
+                .into_iter()
+                .map(|l| l.inner)
+                .collect(),
+            max_variable_mod_sites,
         })
     }
     #[staticmethod]
     pub fn from_default() -> PyResult<Self> {
         Ok(PyParameters {
             inner: Builder::default().make_parameters(),
+            silac_labels: Vec::new(),
+            max_variable_mod_sites: None,
         })
     }
 
+    pub fn to_json(&self) -> PyResult<String> {
+        serde_json::to_string_pretty(&ParameterSettings::from(self))
+            .map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    #[staticmethod]
+    pub fn from_json(json: &str) -> PyResult<Self> {
+        let settings: ParameterSettings =
+            serde_json::from_str(json).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        settings.try_into()
+    }
+
     pub fn digest(&self) -> PyResult<Vec<PyPeptide>> {
         let fasta = Fasta::parse(
             self.inner.fasta.clone(),
@@ -488,13 +1335,50 @@ impl PyParameters {
     }
 
     pub fn build_indexed_database(&self) -> PyResult<PyIndexedDatabase> {
-        Ok(PyIndexedDatabase {
-            inner: self.inner.clone().build(Fasta::parse(
-                self.inner.fasta.clone(),
-                self.inner.decoy_tag.clone(),
-                self.inner.generate_decoys,
-            )),
-        })
+        if !self.silac_labels.is_empty() || self.max_variable_mod_sites.is_some() {
+            let (inner, pairs) = build_silac_database(&self.inner, &self.silac_labels, self.max_variable_mod_sites);
+            let mut db = PyIndexedDatabase::from_fasta(inner, &self.inner.fasta).built_with(&self.inner);
+            db.silac_partners = pairs
+                .into_iter()
+                .flat_map(|(light, heavy)| [(light, heavy), (heavy, light)])
+                .collect();
+            return Ok(db);
+        }
+        let inner = self.inner.clone().build(Fasta::parse(
+            self.inner.fasta.clone(),
+            self.inner.decoy_tag.clone(),
+            self.inner.generate_decoys,
+        ));
+        Ok(PyIndexedDatabase::from_fasta(inner, &self.inner.fasta).built_with(&self.inner))
+    }
+
+    /// Load the database from the cache file at `path` if it was built with these parameters
+    /// (FASTA included) by this version, otherwise build it and write the cache file
+    pub fn build_indexed_database_cached(&self, path: &str) -> PyResult<PyIndexedDatabase> {
+        let hash = parameters_hash(self)?;
+        if Path::new(path).exists() {
+            let header = read_database_header(path).ok().map(|(header, _)| header);
+            if let Some(header) = header.as_ref().filter(|h| h.fasta_hash != fasta_hash(self.inner.fasta.lines())) {
+                log::warn!(
+                    "Database cache {} was built from a different FASTA (hash {}), rebuilding it",
+                    path,
+                    &header.fasta_hash[..8.min(header.fasta_hash.len())]
+                );
+            }
+            let fresh = header.map_or(false, |header| {
+                header.version == env!("CARGO_PKG_VERSION")
+                    && header.parameters_hash.as_deref() == Some(hash.as_str())
+            });
+            if fresh {
+                if let Ok(db) = PyIndexedDatabase::from_file(path) {
+                    return Ok(db);
+                }
+            }
+        }
+
+        let db = self.build_indexed_database()?;
+        db.write_file(path, Some(hash))?;
+        Ok(db)
     }
 
     #[getter]
@@ -576,10 +1460,919 @@ impl PyParameters {
         self.inner.generate_decoys
     }
 
+    #[getter]
+    pub fn silac_labels(&self) -> Vec<PySilacLabel> {
+        self.silac_labels
+            .iter()
+            .map(|l| PySilacLabel { inner: l.clone() })
+            .collect()
+    }
+
     #[getter]
     pub fn fasta(&self) -> String {
         self.inner.fasta.clone()
     }
+
+    #[getter]
+    pub fn max_variable_mod_sites(&self) -> Option<u8> {
+        self.max_variable_mod_sites
+    }
+}
+
+fn is_nterm(specificity: &ModificationSpecificity) -> bool {
+    matches!(
+        specificity,
+        ModificationSpecificity::PeptideN(_) | ModificationSpecificity::ProteinN(_)
+    )
+}
+
+fn is_cterm(specificity: &ModificationSpecificity) -> bool {
+    matches!(
+        specificity,
+        ModificationSpecificity::PeptideC(_) | ModificationSpecificity::ProteinC(_)
+    )
+}
+
+/// Number of residues of a peptide that a variable modification can be placed on, plus one for
+/// each terminus with a variable terminal modification
+fn variable_mod_sites(sequence: &[u8], parameters: &Parameters) -> usize {
+    let residues = sequence
+        .iter()
+        .filter(|r| {
+            parameters
+                .variable_mods
+                .contains_key(&ModificationSpecificity::Residue(**r))
+        })
+        .count();
+    let nterm = parameters.variable_mods.keys().any(is_nterm) as usize;
+    let cterm = parameters.variable_mods.keys().any(is_cterm) as usize;
+    residues + nterm + cterm
+}
+
+/// Number of variable modifications of a peptide: residues and termini whose mass shift is not
+/// explained by a static modification. SILAC labels count as modifications
+fn variable_mod_count(peptide: &Peptide, parameters: &Parameters) -> u8 {
+    let differs = |mass: f32, fixed: f32| (mass - fixed).abs() > 1e-4;
+    let residues = peptide
+        .sequence
+        .iter()
+        .zip(peptide.modifications.iter())
+        .filter(|(residue, mass)| {
+            let fixed = parameters
+                .static_mods
+                .get(&ModificationSpecificity::Residue(**residue))
+                .copied()
+                .unwrap_or(0.0);
+            differs(**mass, fixed)
+        })
+        .count();
+    let terminal = |mass: Option<f32>, is_terminus: fn(&ModificationSpecificity) -> bool| {
+        mass.filter(|m| *m != 0.0).map_or(0, |m| {
+            let fixed = parameters
+                .static_mods
+                .iter()
+                .any(|(specificity, f)| is_terminus(specificity) && !differs(m, *f));
+            (!fixed) as usize
+        })
+    };
+    (residues + terminal(peptide.nterm, is_nterm) + terminal(peptide.cterm, is_cterm)) as u8
+}
+
+/// Theoretical fragments of `peptides` used for preliminary scoring, `peptide_index` is the
+/// position in `peptides`. The first `min_ion_index` ions of each series are skipped, as in
+/// `Parameters::build`
+pub(crate) fn theoretical_fragments(
+    peptides: &[Peptide],
+    ion_kinds: &[Kind],
+    min_ion_index: usize,
+    min_mz: f32,
+    max_mz: f32,
+) -> Vec<Theoretical> {
+    let mut fragments = Vec::new();
+    for (idx, peptide) in peptides.iter().enumerate() {
+        for kind in ion_kinds.iter() {
+            let ions = IonSeries::new(peptide, *kind)
+                .enumerate()
+                .filter(|(i, ion)| {
+                    let ordinal = match kind {
+                        Kind::A | Kind::B | Kind::C => i + 1,
+                        Kind::X | Kind::Y | Kind::Z => peptide.sequence.len() - i - 1,
+                    };
+                    ordinal > min_ion_index && ion.monoisotopic_mass >= min_mz && ion.monoisotopic_mass <= max_mz
+                })
+                .map(|(_, ion)| Theoretical {
+                    peptide_index: PeptideIx(idx as u32),
+                    fragment_mz: ion.monoisotopic_mass,
+                });
+            fragments.extend(ions);
+        }
+    }
+    fragments
+}
+
+/// Build an indexed database from peptides and their theoretical fragments, where
+/// `fragments[i].peptide_index` refers to a position in `peptides`. Peptides are sorted by
+/// monoisotopic mass and fragments are bucketed by m/z, mirroring `Parameters::build`
+pub(crate) fn index_peptides(
+    peptides: Vec<Peptide>,
+    fragments: Vec<Theoretical>,
+    ion_kinds: Vec<Kind>,
+    potential_mods: Vec<(ModificationSpecificity, f32)>,
+    bucket_size: usize,
+    generate_decoys: bool,
+    decoy_tag: String,
+) -> IndexedDatabase {
+    let mut order: Vec<usize> = (0..peptides.len()).collect();
+    order.sort_by(|a, b| peptides[*a].monoisotopic.total_cmp(&peptides[*b].monoisotopic));
+
+    let mut new_index = vec![0u32; peptides.len()];
+    for (new, old) in order.iter().enumerate() {
+        new_index[*old] = new as u32;
+    }
+
+    let mut slots: Vec<Option<Peptide>> = peptides.into_iter().map(Some).collect();
+    let peptides: Vec<Peptide> = order.iter().map(|i| slots[*i].take().unwrap()).collect();
+
+    let mut fragments: Vec<Theoretical> = fragments
+        .into_iter()
+        .map(|f| Theoretical {
+            peptide_index: PeptideIx(new_index[f.peptide_index.0 as usize]),
+            fragment_mz: f.fragment_mz,
+        })
+        .collect();
+
+    fragments.sort_by(|a, b| a.fragment_mz.total_cmp(&b.fragment_mz));
+
+    let bucket_size = bucket_size.max(1);
+    let min_value = fragments
+        .chunks_mut(bucket_size)
+        .map(|chunk| {
+            let min = chunk[0].fragment_mz;
+            chunk.sort_by(|a, b| a.peptide_index.0.cmp(&b.peptide_index.0));
+            min
+        })
+        .collect::<Vec<_>>();
+
+    IndexedDatabase {
+        peptides,
+        fragments,
+        ion_kinds,
+        min_value,
+        potential_mods,
+        bucket_size,
+        generate_decoys,
+        decoy_tag,
+    }
+}
+
+/// Copy of `peptide` with the SILAC labels of the given channel applied, None if the peptide
+/// holds no labelled residue
+fn label_peptide(peptide: &Peptide, labels: &[SilacLabel], heavy: bool) -> Option<Peptide> {
+    let mut labelled = peptide.clone();
+    let mut found = false;
+    for (residue, modification) in labelled.sequence.iter().zip(labelled.modifications.iter_mut()) {
+        for label in labels
+            .iter()
+            .filter(|l| l.is_heavy == heavy && l.amino_acid as u8 == *residue)
+        {
+            *modification += label.mass_shift;
+            labelled.monoisotopic += label.mass_shift;
+            found = true;
+        }
+    }
+    found.then_some(labelled)
+}
+
+/// Build the database of `parameters` with a light and a heavy variant of every peptide holding
+/// a SILAC labelled residue, mirroring `Parameters::build`. Also returns the (light, heavy)
+/// peptide index pairs. With `max_variable_mod_sites`, peptides with more potential variable
+/// modification sites are only indexed without variable modifications
+fn build_silac_database(
+    parameters: &Parameters,
+    labels: &[SilacLabel],
+    max_variable_mod_sites: Option<u8>,
+) -> (IndexedDatabase, Vec<(u32, u32)>) {
+    let fasta = Fasta::parse(
+        parameters.fasta.clone(),
+        parameters.decoy_tag.clone(),
+        parameters.generate_decoys,
+    );
+
+    let mut variants = Vec::new();
+    let mut pairs = Vec::new();
+    let digest = parameters.digest(&fasta).into_iter().filter(|peptide| {
+        max_variable_mod_sites.map_or(true, |max_sites| {
+            variable_mod_sites(&peptide.sequence, parameters) <= max_sites as usize
+                || variable_mod_count(peptide, parameters) == 0
+        })
+    });
+    for peptide in digest {
+        let heavy = label_peptide(&peptide, labels, true)
+            .filter(|p| p.monoisotopic <= parameters.peptide_max_mass);
+        variants.push(label_peptide(&peptide, labels, false).unwrap_or(peptide));
+        if let Some(heavy) = heavy {
+            pairs.push((variants.len() - 1, variants.len()));
+            variants.push(heavy);
+        }
+    }
+
+    // sorted by mass up front, so that `index_peptides` keeps the peptide order of the pairs
+    let mut order: Vec<usize> = (0..variants.len()).collect();
+    order.sort_by(|a, b| variants[*a].monoisotopic.total_cmp(&variants[*b].monoisotopic));
+    let mut new_index = vec![0u32; variants.len()];
+    for (new, old) in order.iter().enumerate() {
+        new_index[*old] = new as u32;
+    }
+    let pairs = pairs
+        .into_iter()
+        .map(|(light, heavy)| (new_index[light], new_index[heavy]))
+        .collect();
+    let mut slots: Vec<Option<Peptide>> = variants.into_iter().map(Some).collect();
+    let peptides: Vec<Peptide> = order.iter().map(|i| slots[*i].take().unwrap()).collect();
+
+    let fragments = theoretical_fragments(
+        &peptides,
+        &parameters.ion_kinds,
+        parameters.min_ion_index,
+        parameters.fragment_min_mz,
+        parameters.fragment_max_mz,
+    );
+
+    let potential_mods = parameters
+        .static_mods
+        .iter()
+        .map(|(m, mass)| (m.clone(), *mass))
+        .chain(
+            parameters
+                .variable_mods
+                .iter()
+                .flat_map(|(m, masses)| masses.iter().map(move |mass| (m.clone(), *mass))),
+        )
+        .chain(
+            labels
+                .iter()
+                .filter(|l| l.mass_shift != 0.0)
+                .map(|l| (ModificationSpecificity::Residue(l.amino_acid as u8), l.mass_shift)),
+        )
+        .collect();
+
+    let db = index_peptides(
+        peptides,
+        fragments,
+        parameters.ion_kinds.clone(),
+        potential_mods,
+        parameters.bucket_size,
+        parameters.generate_decoys,
+        parameters.decoy_tag.clone(),
+    );
+    (db, pairs)
+}
+
+/// Number of indexed peptides per number of variable modifications, see `variable_mod_count`
+#[pyfunction]
+pub fn count_modified_peptides(db: &PyIndexedDatabase, parameters: &PyParameters) -> HashMap<u8, u64> {
+    let mut counts = HashMap::new();
+    for peptide in db.inner.peptides.iter() {
+        *counts.entry(variable_mod_count(peptide, &parameters.inner)).or_insert(0) += 1;
+    }
+    counts
+}
+
+/// Fraction of target PSMs with spectrum_q <= fdr_threshold per number of variable modifications
+/// of their peptide
+#[pyfunction]
+pub fn report_variable_mod_coverage(
+    db: &PyIndexedDatabase,
+    parameters: &PyParameters,
+    psms: Vec<PyFeature>,
+    fdr_threshold: f32,
+) -> PyResult<HashMap<u8, f32>> {
+    check_peptide_indices(&db.inner, &psms)?;
+    let mut counts: HashMap<u8, usize> = HashMap::new();
+    let mut total = 0;
+    for psm in psms
+        .iter()
+        .filter(|p| p.inner.label == 1 && p.inner.spectrum_q <= fdr_threshold)
+    {
+        let peptide = &db.inner[psm.inner.peptide_idx];
+        *counts.entry(variable_mod_count(peptide, &parameters.inner)).or_insert(0) += 1;
+        total += 1;
+    }
+    Ok(counts
+        .into_iter()
+        .map(|(mods, count)| (mods, count as f32 / total as f32))
+        .collect())
+}
+
+/// SHA-256 digest of the FASTA content a database was built from
+#[pyfunction]
+pub fn database_hash(db: &PyIndexedDatabase) -> String {
+    db.fasta_hash.clone()
+}
+
+/// Candidates whose missed cleavage prior falls below this value (relative to a fully cleaved
+/// peptide) are pruned
+pub const MC_PRUNE_THRESHOLD: f32 = 0.05;
+
+/// Prior probability of observing a peptide with `missed_cleavages` missed cleavages, relative
+/// to a fully cleaved one: P(observed | k MCs) ∝ exp(-abundance_prior * k)
+pub fn missed_cleavage_weight(missed_cleavages: u8, abundance_prior: f32) -> f32 {
+    (-abundance_prior * missed_cleavages as f32).exp()
+}
+
+/// Whether a peptide with `missed_cleavages` missed cleavages is kept by missed cleavage pruning:
+/// at most `max_allowed` missed cleavages and a prior of at least `MC_PRUNE_THRESHOLD`
+pub(crate) fn is_missed_cleavage_candidate(missed_cleavages: u8, max_allowed: u8, abundance_prior: f32) -> bool {
+    missed_cleavages <= max_allowed && missed_cleavage_weight(missed_cleavages, abundance_prior) >= MC_PRUNE_THRESHOLD
+}
+
+/// Remove candidates with more than `max_allowed` missed cleavages or a missed cleavage prior
+/// below `MC_PRUNE_THRESHOLD`, the remaining candidates are ordered by decreasing prior
+#[pyfunction]
+pub fn prune_by_missed_cleavage_probability(
+    candidates: Vec<PyPeptideIx>,
+    db: &PyIndexedDatabase,
+    max_allowed: u8,
+    abundance_prior: f32,
+) -> Vec<PyPeptideIx> {
+    let mut weighted: Vec<(f32, PyPeptideIx)> = candidates
+        .into_iter()
+        .filter_map(|ix| {
+            let missed_cleavages = db.inner[ix.inner].missed_cleavages;
+            is_missed_cleavage_candidate(missed_cleavages, max_allowed, abundance_prior)
+                .then(|| (missed_cleavage_weight(missed_cleavages, abundance_prior), ix))
+        })
+        .collect();
+
+    weighted.sort_by(|a, b| b.0.total_cmp(&a.0));
+    weighted.into_iter().map(|(_, ix)| ix).collect()
+}
+
+/// Create a sub-database containing only peptides with a predicted detectability of at least
+/// `min_detectability`
+#[pyfunction]
+pub fn filter_database_by_detectability(db: &PyIndexedDatabase, min_detectability: f32) -> PyIndexedDatabase {
+    let inner = subset_database(&db.inner, |_, peptide| detectability(&peptide.sequence) >= min_detectability);
+    PyIndexedDatabase {
+        fragment_settings: db.fragment_settings,
+        ..PyIndexedDatabase::with_fasta_hash(inner, db.fasta_hash.clone())
+    }
+}
+
+/// Create a sub-database containing only the peptides of `db` accepted by `keep`, which is given
+/// the index and the peptide
+pub(crate) fn subset_database<F: Fn(usize, &Peptide) -> bool>(db: &IndexedDatabase, keep: F) -> IndexedDatabase {
+    let mut new_index: Vec<Option<u32>> = vec![None; db.peptides.len()];
+    let mut peptides = Vec::new();
+
+    for (i, peptide) in db.peptides.iter().enumerate() {
+        if keep(i, peptide) {
+            new_index[i] = Some(peptides.len() as u32);
+            peptides.push(peptide.clone());
+        }
+    }
+
+    let fragments = db
+        .fragments
+        .iter()
+        .filter_map(|f| {
+            new_index[f.peptide_index.0 as usize].map(|idx| Theoretical {
+                peptide_index: PeptideIx(idx),
+                fragment_mz: f.fragment_mz,
+            })
+        })
+        .collect();
+
+    index_peptides(
+        peptides,
+        fragments,
+        db.ion_kinds.clone(),
+        db.potential_mods.clone(),
+        db.bucket_size,
+        db.generate_decoys,
+        db.decoy_tag.clone(),
+    )
+}
+
+/// Identity of a peptide across databases: sequence, modifications and decoy status
+fn peptide_key(peptide: &Peptide) -> (Vec<u8>, Vec<u32>, Option<u32>, Option<u32>, bool) {
+    (
+        peptide.sequence.to_vec(),
+        peptide.modifications.iter().map(|m| m.to_bits()).collect(),
+        peptide.nterm.map(|m| m.to_bits()),
+        peptide.cterm.map(|m| m.to_bits()),
+        peptide.decoy,
+    )
+}
+
+/// Merge several (e.g. organism-specific) databases into one combined index. Peptides found in
+/// more than one database are stored once, carrying the protein accessions of all sources, the
+/// position of each source in `databases` is kept as `source_db_id` of its proteins (see
+/// `get_protein_sources`)
+#[pyfunction]
+pub fn merge_databases(databases: Vec<PyRef<PyIndexedDatabase>>) -> PyResult<PyIndexedDatabase> {
+    merge_indexed(&databases.iter().map(|db| &**db).collect::<Vec<_>>())
+}
+
+fn merge_indexed(databases: &[&PyIndexedDatabase]) -> PyResult<PyIndexedDatabase> {
+    let first = match databases.first() {
+        Some(db) => &db.inner,
+        None => return Err(PyValueError::new_err("At least one database is required")),
+    };
+
+    for db in databases.iter().skip(1) {
+        if db.inner.ion_kinds != first.ion_kinds {
+            return Err(PyValueError::new_err(
+                "Databases must be built with the same ion kinds",
+            ));
+        }
+        if db.inner.decoy_tag != first.decoy_tag {
+            return Err(PyValueError::new_err(
+                "Databases must be built with the same decoy tag",
+            ));
+        }
+    }
+
+    if databases.len() > u8::MAX as usize + 1 {
+        return Err(PyValueError::new_err(format!(
+            "At most {} databases can be merged, got {}",
+            u8::MAX as usize + 1,
+            databases.len()
+        )));
+    }
+
+    let mut peptides: Vec<Peptide> = Vec::new();
+    // source database of each protein of each merged peptide, aligned with `proteins`
+    let mut sources: Vec<Vec<u8>> = Vec::new();
+    let mut fragments: Vec<Theoretical> = Vec::new();
+    let mut seen: HashMap<(Vec<u8>, Vec<u32>, Option<u32>, Option<u32>, bool), usize> =
+        HashMap::new();
+    let mut potential_mods: Vec<(ModificationSpecificity, f32)> = Vec::new();
+
+    for (source_db_id, db) in databases.iter().enumerate() {
+        let source_db_id = source_db_id as u8;
+        // position of each peptide of this database in the merged peptide list, `None` if the
+        // peptide was already contributed (together with its fragments) by a previous database
+        let mut new_index: Vec<Option<u32>> = Vec::with_capacity(db.inner.peptides.len());
+
+        for peptide in db.inner.peptides.iter() {
+            match seen.get(&peptide_key(peptide)).copied() {
+                Some(idx) => {
+                    let merged = &mut peptides[idx];
+                    for protein in peptide.proteins.iter() {
+                        if !merged.proteins.contains(protein) {
+                            merged.proteins.push(protein.clone());
+                            sources[idx].push(source_db_id);
+                        }
+                    }
+                    new_index.push(None);
+                }
+                None => {
+                    seen.insert(peptide_key(peptide), peptides.len());
+                    new_index.push(Some(peptides.len() as u32));
+                    sources.push(vec![source_db_id; peptide.proteins.len()]);
+                    peptides.push(peptide.clone());
+                }
+            }
+        }
+
+        fragments.extend(db.inner.fragments.iter().filter_map(|f| {
+            new_index[f.peptide_index.0 as usize].map(|idx| Theoretical {
+                peptide_index: PeptideIx(idx),
+                fragment_mz: f.fragment_mz,
+            })
+        }));
+
+        for m in db.inner.potential_mods.iter() {
+            if !potential_mods.contains(m) {
+                potential_mods.push(m.clone());
+            }
+        }
+    }
+
+    let merged = index_peptides(
+        peptides,
+        fragments,
+        first.ion_kinds.clone(),
+        potential_mods,
+        first.bucket_size,
+        databases.iter().any(|db| db.inner.generate_decoys),
+        first.decoy_tag.clone(),
+    );
+    // indexing sorts the peptides by mass, their sources follow by peptide identity
+    let protein_sources = merged
+        .peptides
+        .iter()
+        .map(|peptide| std::mem::take(&mut sources[seen[&peptide_key(peptide)]]))
+        .collect();
+    Ok(PyIndexedDatabase {
+        fragment_settings: databases[0].fragment_settings,
+        protein_sources: Some(protein_sources),
+        ..PyIndexedDatabase::from_index(merged)
+    })
+}
+
+/// Split PSMs into per-organism lists, given a map from protein accession to organism. PSMs of
+/// peptides shared between organisms are reported for each of them, PSMs without any assigned
+/// protein are collected under "unassigned"
+#[pyfunction]
+pub fn split_results_by_organism(
+    psms: Vec<PyFeature>,
+    db: &PyIndexedDatabase,
+    organism_assignments: BTreeMap<String, String>,
+) -> PyResult<BTreeMap<String, Vec<PyFeature>>> {
+    check_peptide_indices(&db.inner, &psms)?;
+    let decoy_tag = db.inner.decoy_tag.as_str();
+    let mut result: BTreeMap<String, Vec<PyFeature>> = BTreeMap::new();
+
+    for psm in psms {
+        let peptide = &db.inner[psm.inner.peptide_idx];
+
+        let mut organisms: Vec<&String> = peptide
+            .proteins
+            .iter()
+            .filter_map(|p| {
+                let accession = p.strip_prefix(decoy_tag).unwrap_or(p.as_str());
+                organism_assignments.get(accession)
+            })
+            .collect();
+        organisms.sort();
+        organisms.dedup();
+
+        if organisms.is_empty() {
+            result
+                .entry("unassigned".to_string())
+                .or_default()
+                .push(psm);
+        } else {
+            for organism in organisms {
+                result.entry(organism.clone()).or_default().push(psm.clone());
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+/// Sequence coverage of a protein by identified peptides, peptide spans are 0-based and
+/// end-exclusive
+#[pyclass]
+#[derive(Clone, Debug)]
+pub struct PyProteinCoverage {
+    pub coverage_pct: f32,
+    pub covered_positions: Vec<bool>,
+    pub peptide_spans: Vec<(usize, usize)>,
+}
+
+impl PyProteinCoverage {
+    fn compute(protein_sequence: &[u8], peptides: &[&[u8]]) -> Self {
+        let mut covered_positions = vec![false; protein_sequence.len()];
+        let mut peptide_spans = Vec::new();
+
+        for peptide in peptides.iter().filter(|p| !p.is_empty() && p.len() <= protein_sequence.len()) {
+            for start in 0..=protein_sequence.len() - peptide.len() {
+                if &protein_sequence[start..start + peptide.len()] == *peptide {
+                    peptide_spans.push((start, start + peptide.len()));
+                    covered_positions[start..start + peptide.len()].fill(true);
+                }
+            }
+        }
+        peptide_spans.sort_unstable();
+        peptide_spans.dedup();
+
+        let covered = covered_positions.iter().filter(|c| **c).count();
+        let coverage_pct = if covered_positions.is_empty() {
+            0.0
+        } else {
+            100.0 * covered as f32 / covered_positions.len() as f32
+        };
+
+        PyProteinCoverage {
+            coverage_pct,
+            covered_positions,
+            peptide_spans,
+        }
+    }
+}
+
+#[pymethods]
+impl PyProteinCoverage {
+    #[getter]
+    pub fn coverage_pct(&self) -> f32 {
+        self.coverage_pct
+    }
+
+    #[getter]
+    pub fn covered_positions(&self) -> Vec<bool> {
+        self.covered_positions.clone()
+    }
+
+    #[getter]
+    pub fn peptide_spans(&self) -> Vec<(usize, usize)> {
+        self.peptide_spans.clone()
+    }
+
+    /// Runs of uncovered positions of at least `min_gap` residues, as end-exclusive spans
+    pub fn uncovered_regions(&self, min_gap: usize) -> Vec<(usize, usize)> {
+        let mut regions = Vec::new();
+        let mut start = None;
+        for (i, covered) in self.covered_positions.iter().enumerate() {
+            match (covered, start) {
+                (false, None) => start = Some(i),
+                (true, Some(s)) => {
+                    regions.push((s, i));
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+        if let Some(s) = start {
+            regions.push((s, self.covered_positions.len()));
+        }
+        regions.retain(|(s, e)| e - s >= min_gap.max(1));
+        regions
+    }
+}
+
+/// Sequence coverage of a protein by the given peptides, every occurrence of a peptide counts
+#[pyfunction]
+pub fn compute_sequence_coverage(protein_sequence: &str, identified_peptides: Vec<String>) -> PyProteinCoverage {
+    let peptides: Vec<&[u8]> = identified_peptides.iter().map(|p| p.as_bytes()).collect();
+    PyProteinCoverage::compute(protein_sequence.as_bytes(), &peptides)
+}
+
+#[pyfunction]
+pub fn get_uncovered_regions(coverage: &PyProteinCoverage, min_gap: usize) -> Vec<(usize, usize)> {
+    coverage.uncovered_regions(min_gap)
+}
+
+/// Sequence coverage of every target protein with at least one target PSM. The index does not
+/// store protein sequences, so they are taken from the FASTA the database was built from
+#[pyfunction]
+pub fn compute_coverage_for_all_proteins(
+    psms: Vec<PyFeature>,
+    db: &PyIndexedDatabase,
+    fasta: &PyFasta,
+) -> PyResult<HashMap<String, PyProteinCoverage>> {
+    check_peptide_indices(&db.inner, &psms)?;
+    let mut protein_peptides: HashMap<&str, HashSet<&[u8]>> = HashMap::new();
+    for psm in psms.iter().filter(|psm| psm.inner.label == 1) {
+        let peptide = &db.inner[psm.inner.peptide_idx];
+        for protein in peptide.proteins.iter() {
+            protein_peptides
+                .entry(protein.as_str())
+                .or_default()
+                .insert(&peptide.sequence[..]);
+        }
+    }
+
+    Ok(fasta
+        .inner
+        .targets
+        .iter()
+        .filter_map(|(accession, sequence)| {
+            let peptides: Vec<&[u8]> = protein_peptides.get(&**accession)?.iter().copied().collect();
+            Some((
+                accession.to_string(),
+                PyProteinCoverage::compute(sequence.as_bytes(), &peptides),
+            ))
+        })
+        .collect())
+}
+
+/// Occurrence of an identified peptide in a protein, 0-based and end-exclusive. `q_value` is the
+/// best spectrum q-value and `count` the number of PSMs of the peptide, `unique` whether the
+/// peptide maps to this protein only
+#[pyclass]
+#[derive(Clone, Debug)]
+pub struct PyPeptideRegion {
+    #[pyo3(get)]
+    pub start: usize,
+    #[pyo3(get)]
+    pub end: usize,
+    #[pyo3(get)]
+    pub sequence: String,
+    #[pyo3(get)]
+    pub q_value: f32,
+    #[pyo3(get)]
+    pub count: u32,
+    #[pyo3(get)]
+    pub unique: bool,
+}
+
+/// Residue level coverage of a protein with the peptide regions covering it
+#[pyclass]
+#[derive(Clone, Debug)]
+pub struct PyProteinCoverageMap {
+    #[pyo3(get)]
+    pub protein_id: String,
+    #[pyo3(get)]
+    pub sequence: String,
+    #[pyo3(get)]
+    pub covered: Vec<bool>,
+    #[pyo3(get)]
+    pub peptide_regions: Vec<PyPeptideRegion>,
+}
+
+/// Residues per row and pixel sizes of `coverage_to_svg`
+const SVG_RESIDUES_PER_ROW: usize = 50;
+const SVG_RESIDUE_WIDTH: usize = 12;
+const SVG_ROW_HEIGHT: usize = 20;
+const SVG_REGION_HEIGHT: usize = 4;
+
+#[pymethods]
+impl PyProteinCoverageMap {
+    #[getter]
+    pub fn coverage_pct(&self) -> f32 {
+        if self.covered.is_empty() {
+            return 0.0;
+        }
+        100.0 * self.covered.iter().filter(|c| **c).count() as f32 / self.covered.len() as f32
+    }
+
+    /// SVG of the sequence in rows of `residues_per_row` residues, covered residues are shaded and
+    /// each peptide region is drawn as a bar below its residues, blue if unique, orange if shared
+    pub fn to_svg(&self, residues_per_row: Option<usize>) -> String {
+        let per_row = residues_per_row.unwrap_or(SVG_RESIDUES_PER_ROW).max(1);
+        let residues: Vec<char> = self.sequence.chars().collect();
+        let rows = residues.len().div_ceil(per_row).max(1);
+
+        // stack overlapping regions of a row into lanes, first free lane by start position
+        let mut bars: Vec<(usize, usize, usize, usize, &PyPeptideRegion)> = Vec::new();
+        let mut lane_ends: Vec<Vec<usize>> = vec![Vec::new(); rows];
+        let mut sorted: Vec<&PyPeptideRegion> = self.peptide_regions.iter().collect();
+        sorted.sort_by_key(|r| (r.start, r.end));
+        for region in sorted {
+            for row in region.start / per_row..region.end.div_ceil(per_row).min(rows) {
+                let start = region.start.max(row * per_row);
+                let end = region.end.min((row + 1) * per_row);
+                let lane = match lane_ends[row].iter().position(|e| *e <= start) {
+                    Some(lane) => lane,
+                    None => {
+                        lane_ends[row].push(0);
+                        lane_ends[row].len() - 1
+                    }
+                };
+                lane_ends[row][lane] = end;
+                bars.push((row, lane, start, end, region));
+            }
+        }
+        let max_lanes = lane_ends.iter().map(|l| l.len()).max().unwrap_or(0).max(1);
+
+        let row_height = SVG_ROW_HEIGHT + max_lanes * (SVG_REGION_HEIGHT + 1);
+        let width = per_row * SVG_RESIDUE_WIDTH;
+        let height = rows * row_height;
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" font-family=\"monospace\" font-size=\"12\">\n",
+            width, height
+        );
+        svg.push_str(&format!("<title>{}</title>\n", self.protein_id));
+        for (i, residue) in residues.iter().enumerate() {
+            let x = (i % per_row) * SVG_RESIDUE_WIDTH;
+            let y = (i / per_row) * row_height;
+            if self.covered.get(i).copied().unwrap_or(false) {
+                svg.push_str(&format!(
+                    "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"#d9d9d9\"/>\n",
+                    x, y, SVG_RESIDUE_WIDTH, SVG_ROW_HEIGHT
+                ));
+            }
+            svg.push_str(&format!("<text x=\"{}\" y=\"{}\">{}</text>\n", x + 2, y + 14, residue));
+        }
+        for (row, lane, start, end, region) in bars {
+            svg.push_str(&format!(
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"><title>{} ({}-{}) q={:.4} n={}</title></rect>\n",
+                (start % per_row) * SVG_RESIDUE_WIDTH,
+                row * row_height + SVG_ROW_HEIGHT + lane * (SVG_REGION_HEIGHT + 1),
+                (end - start) * SVG_RESIDUE_WIDTH,
+                SVG_REGION_HEIGHT,
+                if region.unique { "#1f77b4" } else { "#ff7f0e" },
+                region.sequence,
+                region.start + 1,
+                region.end,
+                region.q_value,
+                region.count,
+            ));
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+}
+
+/// Coverage maps of all target proteins with target PSMs at `spectrum_q <= fdr_threshold`,
+/// sorted by protein. The index does not store protein sequences, so they are taken from the
+/// FASTA the database was built from
+#[pyfunction]
+pub fn build_coverage_maps(
+    psms: Vec<PyFeature>,
+    db: &PyIndexedDatabase,
+    fasta: &PyFasta,
+    fdr_threshold: f32,
+) -> PyResult<Vec<PyProteinCoverageMap>> {
+    check_peptide_indices(&db.inner, &psms)?;
+    // (best q-value, PSM count, unique) of each peptide sequence of each protein
+    let mut protein_peptides: HashMap<&str, HashMap<&[u8], (f32, u32, bool)>> = HashMap::new();
+    for psm in psms
+        .iter()
+        .filter(|psm| psm.inner.label == 1 && psm.inner.spectrum_q <= fdr_threshold)
+    {
+        let peptide = &db.inner[psm.inner.peptide_idx];
+        for protein in peptide.proteins.iter() {
+            let entry = protein_peptides
+                .entry(protein.as_str())
+                .or_default()
+                .entry(&peptide.sequence[..])
+                .or_insert((f32::MAX, 0, peptide.proteins.len() == 1));
+            entry.0 = entry.0.min(psm.inner.spectrum_q);
+            entry.1 += 1;
+        }
+    }
+
+    let mut maps: Vec<PyProteinCoverageMap> = fasta
+        .inner
+        .targets
+        .iter()
+        .filter_map(|(accession, sequence)| {
+            let peptides = protein_peptides.get(&**accession)?;
+            let protein = sequence.as_bytes();
+            let mut covered = vec![false; protein.len()];
+            let mut peptide_regions = Vec::new();
+            for (peptide, (q_value, count, unique)) in peptides.iter() {
+                if peptide.is_empty() || peptide.len() > protein.len() {
+                    continue;
+                }
+                for start in 0..=protein.len() - peptide.len() {
+                    if &protein[start..start + peptide.len()] == *peptide {
+                        covered[start..start + peptide.len()].fill(true);
+                        peptide_regions.push(PyPeptideRegion {
+                            start,
+                            end: start + peptide.len(),
+                            sequence: String::from_utf8_lossy(peptide).to_string(),
+                            q_value: *q_value,
+                            count: *count,
+                            unique: *unique,
+                        });
+                    }
+                }
+            }
+            peptide_regions.sort_by(|a, b| (a.start, a.end).cmp(&(b.start, b.end)));
+            Some(PyProteinCoverageMap {
+                protein_id: accession.to_string(),
+                sequence: sequence.clone(),
+                covered,
+                peptide_regions,
+            })
+        })
+        .collect();
+    maps.sort_by(|a, b| a.protein_id.cmp(&b.protein_id));
+    Ok(maps)
+}
+
+#[pyfunction]
+pub fn coverage_to_svg(map: &PyProteinCoverageMap, residues_per_row: Option<usize>) -> String {
+    map.to_svg(residues_per_row)
+}
+
+/// Combine coverage maps of the same protein, e.g. of replicates: coverage is the union, regions
+/// at the same span keep the best q-value and sum their PSM counts
+#[pyfunction]
+pub fn merge_coverage_maps(maps: Vec<PyProteinCoverageMap>) -> PyResult<PyProteinCoverageMap> {
+    let first = maps
+        .first()
+        .ok_or_else(|| PyValueError::new_err("merge_coverage_maps requires at least one map"))?;
+    if let Some(other) = maps
+        .iter()
+        .find(|m| m.protein_id != first.protein_id || m.sequence != first.sequence)
+    {
+        return Err(PyValueError::new_err(format!(
+            "Cannot merge coverage maps of different proteins: {} and {}",
+            first.protein_id, other.protein_id
+        )));
+    }
+
+    let mut covered = vec![false; first.covered.len()];
+    let mut regions: BTreeMap<(usize, usize), PyPeptideRegion> = BTreeMap::new();
+    for map in maps.iter() {
+        for (merged, c) in covered.iter_mut().zip(map.covered.iter()) {
+            *merged |= *c;
+        }
+        for region in map.peptide_regions.iter() {
+            regions
+                .entry((region.start, region.end))
+                .and_modify(|r| {
+                    r.q_value = r.q_value.min(region.q_value);
+                    r.count += region.count;
+                    r.unique &= region.unique;
+                })
+                .or_insert_with(|| region.clone());
+        }
+    }
+
+    Ok(PyProteinCoverageMap {
+        protein_id: first.protein_id.clone(),
+        sequence: first.sequence.clone(),
+        covered,
+        peptide_regions: regions.into_values().collect(),
+    })
 }
 
 #[pymodule]
@@ -587,8 +2380,301 @@ pub fn database(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_class::<PyPeptideIx>()?;
     m.add_class::<PyTheoretical>()?;
     m.add_class::<PyParameters>()?;
+    m.add_class::<PySilacLabel>()?;
     m.add_class::<PyEnzymeBuilder>()?;
     m.add_class::<PyIndexedDatabase>()?;
     m.add_class::<PyIndexedQuery>()?;
+    m.add_class::<PyProteinCoverage>()?;
+    m.add_function(wrap_pyfunction!(database_hash, m)?)?;
+    m.add_function(wrap_pyfunction!(merge_databases, m)?)?;
+    m.add_function(wrap_pyfunction!(prune_by_missed_cleavage_probability, m)?)?;
+    m.add_function(wrap_pyfunction!(filter_database_by_detectability, m)?)?;
+    m.add_function(wrap_pyfunction!(split_results_by_organism, m)?)?;
+    m.add_function(wrap_pyfunction!(compute_sequence_coverage, m)?)?;
+    m.add_function(wrap_pyfunction!(get_uncovered_regions, m)?)?;
+    m.add_function(wrap_pyfunction!(compute_coverage_for_all_proteins, m)?)?;
+    m.add_class::<PyPeptideRegion>()?;
+    m.add_class::<PyProteinCoverageMap>()?;
+    m.add_function(wrap_pyfunction!(build_coverage_maps, m)?)?;
+    m.add_function(wrap_pyfunction!(coverage_to_svg, m)?)?;
+    m.add_function(wrap_pyfunction!(merge_coverage_maps, m)?)?;
+    m.add_function(wrap_pyfunction!(count_modified_peptides, m)?)?;
+    m.add_function(wrap_pyfunction!(report_variable_mod_coverage, m)?)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sage_core::mass::{monoisotopic, H2O};
+
+    fn test_peptide(sequence: &str, missed_cleavages: u8, protein: &str) -> Peptide {
+        Peptide {
+            decoy: false,
+            sequence: Arc::from(sequence.as_bytes().to_vec().into_boxed_slice()),
+            modifications: vec![0.0; sequence.len()],
+            nterm: None,
+            cterm: None,
+            monoisotopic: sequence.bytes().map(monoisotopic).sum::<f32>() + H2O,
+            missed_cleavages,
+            position: Position::Full,
+            proteins: vec![Arc::new(protein.to_string())],
+            semi_enzymatic: false,
+        }
+    }
+
+    fn test_database(peptides: Vec<Peptide>) -> PyIndexedDatabase {
+        let ion_kinds = vec![Kind::B, Kind::Y];
+        let fragments = theoretical_fragments(&peptides, &ion_kinds, 2, 150.0, 2000.0);
+        PyIndexedDatabase::from_index(index_peptides(
+            peptides,
+            fragments,
+            ion_kinds,
+            Vec::new(),
+            8192,
+            false,
+            "rev_".to_string(),
+        ))
+    }
+
+    #[test]
+    fn ion_kind_index_is_built_once_within_the_parameter_bounds() {
+        let settings = FragmentSettings {
+            min_ion_index: 1,
+            min_mz: 150.0,
+            max_mz: 2000.0,
+        };
+        let db = PyIndexedDatabase {
+            fragment_settings: settings,
+            ..test_database(vec![test_peptide("PEPTIDEK", 0, "sp|P1|A"), test_peptide("SAMPLER", 0, "sp|P2|B")])
+        };
+        let key = DerivedKey {
+            ion_kinds: Some(vec![Kind::C, Kind::Z]),
+            ..DerivedKey::default()
+        };
+
+        let reindexed = db.derived(&key);
+        assert!(Arc::ptr_eq(&reindexed, &db.derived(&key)));
+        assert_eq!(reindexed.db.ion_kinds, vec![Kind::C, Kind::Z]);
+        let expected = theoretical_fragments(&db.inner.peptides, &[Kind::C, Kind::Z], 1, 150.0, 2000.0);
+        assert_eq!(reindexed.db.fragments.len(), expected.len());
+        assert_eq!(reindexed.db.peptides.len(), db.inner.peptides.len());
+    }
+
+    #[test]
+    fn missed_cleavage_prior_prunes_high_missed_cleavages_first() {
+        let db = test_database(vec![
+            test_peptide("PEPTIDEK", 0, "sp|P1|A"),
+            test_peptide("PEPKTIDKEKR", 3, "sp|P1|A"),
+            test_peptide("PEPKTIDKER", 2, "sp|P1|A"),
+        ]);
+        let missed_cleavages = |ixs: &[PyPeptideIx]| -> Vec<u8> {
+            ixs.iter().map(|ix| db.inner[ix.inner].missed_cleavages).collect()
+        };
+        let all: Vec<PyPeptideIx> = (0..3).map(|i| PyPeptideIx { inner: PeptideIx(i) }).collect();
+
+        // exp(-3) falls below the threshold, exp(-2) does not
+        let kept = prune_by_missed_cleavage_probability(all.clone(), &db, u8::MAX, 1.0);
+        assert_eq!(missed_cleavages(&kept), vec![0, 2]);
+        let kept = prune_by_missed_cleavage_probability(all, &db, 1, 1.0);
+        assert_eq!(missed_cleavages(&kept), vec![0]);
+
+        // scoring searches the same candidates, the sub-database is built once
+        let key = DerivedKey {
+            mc_prune: Some((u8::MAX, 1.0)),
+            ..DerivedKey::default()
+        };
+        let pruned = db.derived(&key);
+        assert!(Arc::ptr_eq(&pruned, &db.derived(&key)));
+        assert_eq!(pruned.db.peptides.len(), 2);
+        assert!(pruned.db.peptides.iter().all(|p| p.missed_cleavages < 3));
+        for (peptide, original) in pruned.db.peptides.iter().zip(pruned.original_index.as_ref().unwrap()) {
+            assert_eq!(peptide.sequence, db.inner.peptides[*original as usize].sequence);
+        }
+    }
+
+    #[test]
+    fn ims_bands_keep_peptides_near_the_observed_mobility() {
+        let db = test_database(vec![
+            test_peptide("PEPTIDEK", 0, "sp|P1|A"),
+            test_peptide("SAMPLER", 0, "sp|P2|B"),
+            test_peptide("PEPTIDEPEPTIDER", 0, "sp|P3|C"),
+        ]);
+        // 1/K0 rising with mass and falling with charge
+        let mut db = db;
+        db.set_ims_predictions(|p| std::array::from_fn(|k| p.monoisotopic / 1000.0 / (k as f32 + 1.0)));
+        assert_eq!(db.inner.peptides.len(), 3);
+
+        // PEPTIDEK (1/K0 0.927 at charge 1) lies within 0.93 +/- 0.05, SAMPLER (0.802) does not
+        let key = DerivedKey {
+            ims: Some(ImsBands::covering(1, 0.93, 0.05, 0.05)),
+            ..DerivedKey::default()
+        };
+        let candidates = db.derived(&key);
+        assert!(Arc::ptr_eq(&candidates, &db.derived(&key)));
+        assert_eq!(candidates.db.peptides.len(), 1);
+        assert_eq!(&*candidates.db.peptides[0].sequence, b"PEPTIDEK");
+        let original = candidates.original_index.as_ref().unwrap()[0] as usize;
+        assert_eq!(&*db.inner.peptides[original].sequence, b"PEPTIDEK");
+
+        // at charge 2 the same window holds the long peptide
+        let key = DerivedKey {
+            ims: Some(ImsBands::covering(2, 0.93, 0.05, 0.05)),
+            ..DerivedKey::default()
+        };
+        let candidates = db.derived(&key);
+        assert_eq!(candidates.db.peptides.len(), 1);
+        assert_eq!(&*candidates.db.peptides[0].sequence, b"PEPTIDEPEPTIDER");
+        assert_eq!(db.num_derived_databases(), 2);
+
+        // new predictions drop the subsets derived from the old ones
+        db.set_ims_predictions(|p| [p.monoisotopic / 1000.0; IMS_CHARGES]);
+        assert_eq!(db.num_derived_databases(), 0);
+        assert_eq!(&*db.derived(&key).db.peptides[0].sequence, b"PEPTIDEK");
+        assert_eq!(db.num_derived_databases(), 1);
+        db.clear_derived_cache();
+        assert_eq!(db.num_derived_databases(), 0);
+    }
+
+    #[test]
+    fn merged_organisms_store_shared_peptides_once_with_their_sources() {
+        let ecoli = test_database(vec![
+            test_peptide("IGGIGTVPVGR", 0, "sp|P0CE47|EFTU1_ECOLI"),
+            test_peptide("AVAAGMNPMDLK", 0, "sp|P0A6F5|CH60_ECOLI"),
+            test_peptide("LSDFGVSR", 0, "sp|P0A9B2|G3P1_ECOLI"),
+        ]);
+        let human = test_database(vec![
+            test_peptide("IGGIGTVPVGR", 0, "sp|P49411|EFTU_HUMAN"),
+            test_peptide("VGVNGFGR", 0, "sp|P04406|G3P_HUMAN"),
+            test_peptide("LQDAYYR", 0, "sp|P60709|ACTB_HUMAN"),
+        ]);
+
+        let merged = merge_indexed(&[&ecoli, &human]).unwrap();
+        assert_eq!(merged.inner.peptides.len(), 5);
+
+        let idx = |sequence: &str| {
+            let idx = merged.inner.peptides.iter().position(|p| &*p.sequence == sequence.as_bytes()).unwrap();
+            PyPeptideIx { inner: PeptideIx(idx as u32) }
+        };
+        let shared = merged.get_protein_sources(idx("IGGIGTVPVGR")).unwrap();
+        assert_eq!(
+            shared,
+            vec![("sp|P0CE47|EFTU1_ECOLI".to_string(), 0), ("sp|P49411|EFTU_HUMAN".to_string(), 1)]
+        );
+        assert_eq!(merged.get_protein_sources(idx("LSDFGVSR")).unwrap(), vec![("sp|P0A9B2|G3P1_ECOLI".to_string(), 0)]);
+        assert_eq!(merged.get_protein_sources(idx("LQDAYYR")).unwrap(), vec![("sp|P60709|ACTB_HUMAN".to_string(), 1)]);
+
+        // the shared peptide contributes its fragments once
+        let shared_fragments = |db: &PyIndexedDatabase, idx: PyPeptideIx| {
+            db.inner.fragments.iter().filter(|f| f.peptide_index == idx.inner).count()
+        };
+        let ecoli_idx = ecoli.inner.peptides.iter().position(|p| &*p.sequence == b"IGGIGTVPVGR").unwrap();
+        assert_eq!(
+            shared_fragments(&merged, idx("IGGIGTVPVGR")),
+            shared_fragments(&ecoli, PyPeptideIx { inner: PeptideIx(ecoli_idx as u32) })
+        );
+
+        // databases that were not merged report a single source
+        assert_eq!(ecoli.get_protein_sources(PyPeptideIx { inner: PeptideIx(0) }).unwrap()[0].1, 0);
+    }
+
+    #[test]
+    fn database_file_round_trip_keeps_hash_and_checksum() {
+        let peptides = || vec![test_peptide("PEPTIDEK", 0, "sp|P1|A"), test_peptide("SAMPLER", 0, "sp|P2|B")];
+        let db = test_database(peptides());
+        let rebuilt = PyIndexedDatabase { built: db.built + 60, ..test_database(peptides()) };
+        let other = test_database(vec![test_peptide("PEPTIDEK", 0, "sp|P1|A")]);
+
+        // the checksum identifies the contents, not the build time
+        assert_eq!(db.checksum(), rebuilt.checksum());
+        assert_ne!(db.checksum(), other.checksum());
+        assert_ne!(db.fasta_hash, other.fasta_hash);
+        assert!(db.version_string().starts_with(&format!("2 proteins, hash={},", &db.fasta_hash[..8])));
+
+        let path = std::env::temp_dir().join(format!("sagepy_database_{}.bin", std::process::id()));
+        let path = path.to_str().unwrap();
+        db.to_file(path).unwrap();
+        let loaded = PyIndexedDatabase::from_file(path);
+        std::fs::remove_file(path).unwrap();
+
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded.fasta_hash, db.fasta_hash);
+        assert_eq!(loaded.built, db.built);
+        assert_eq!(loaded.checksum(), db.checksum());
+        assert_eq!(loaded.inner.peptides.len(), 2);
+    }
+
+    #[test]
+    fn sequence_coverage_counts_every_occurrence_and_reports_gaps() {
+        // PEPK occurs twice, LLR once and the unknown peptide nowhere
+        let coverage = compute_sequence_coverage(
+            "PEPKAAAAPEPKGGLLR",
+            vec!["PEPK".to_string(), "LLR".to_string(), "WWWW".to_string(), "PEPK".to_string()],
+        );
+        assert_eq!(coverage.peptide_spans, vec![(0, 4), (8, 12), (14, 17)]);
+        assert_eq!(coverage.covered_positions.iter().filter(|c| **c).count(), 11);
+        assert!((coverage.coverage_pct - 100.0 * 11.0 / 17.0).abs() < 1e-4);
+
+        assert_eq!(get_uncovered_regions(&coverage, 1), vec![(4, 8), (12, 14)]);
+        assert_eq!(get_uncovered_regions(&coverage, 3), vec![(4, 8)]);
+
+        let uncovered = compute_sequence_coverage("PEPK", vec![]);
+        assert_eq!(uncovered.coverage_pct, 0.0);
+        assert_eq!(uncovered.uncovered_regions(0), vec![(0, 4)]);
+    }
+
+    #[test]
+    fn variable_mod_site_cap_indexes_many_site_peptides_unmodified() {
+        // oxidation of PEPTMIDEK has one site, of MSMAMPLER three
+        let mut parameters = PyParameters::from_default().unwrap();
+        parameters.inner.fasta = ">sp|P1|A\nPEPTMIDEKMSMAMPLER\n".to_string();
+        parameters.inner.enzyme = EnzymeBuilder {
+            missed_cleavages: Some(0),
+            min_len: Some(5),
+            max_len: Some(50),
+            cleave_at: Some("KR".to_string()),
+            restrict: Some('P'),
+            c_terminal: Some(true),
+            semi_enzymatic: Some(false),
+        };
+        parameters.inner.generate_decoys = false;
+        parameters.inner.static_mods = HashMap::new();
+        parameters.inner.variable_mods = HashMap::from([(ModificationSpecificity::Residue(b'M'), vec![15.9949])]);
+        parameters.inner.max_variable_mods = 2;
+
+        let uncapped = parameters.build_indexed_database().unwrap();
+        let counts = count_modified_peptides(&uncapped, &parameters);
+        assert_eq!(counts[&0], 2);
+        assert!(counts[&2] > 0);
+
+        parameters.max_variable_mod_sites = Some(1);
+        let capped = parameters.build_indexed_database().unwrap();
+        assert_eq!(count_modified_peptides(&capped, &parameters), HashMap::from([(0, 2), (1, 1)]));
+        let oxidised = capped.inner.peptides.iter().position(|p| p.modifications.iter().any(|m| *m != 0.0)).unwrap();
+        assert_eq!(&*capped.inner.peptides[oxidised].sequence, b"PEPTMIDEK");
+
+        let psm = |idx: usize, label: i32, spectrum_q: f32| {
+            PyFeature::from(sage_core::scoring::Feature {
+                peptide_idx: PeptideIx(idx as u32),
+                label,
+                spectrum_q,
+                ..crate::py_io::default_feature()
+            })
+        };
+        let unmodified = (oxidised + 1) % 3;
+        let psms = vec![
+            psm(oxidised, 1, 0.001),
+            psm(unmodified, 1, 0.001),
+            psm(unmodified, 1, 0.005),
+            psm(oxidised, 1, 0.5),
+            psm(oxidised, -1, 0.001),
+        ];
+        let coverage = report_variable_mod_coverage(&capped, &parameters, psms, 0.01);
+        assert!((coverage[&0] - 2.0 / 3.0).abs() < 1e-6);
+        assert!((coverage[&1] - 1.0 / 3.0).abs() < 1e-6);
+
+        let mut settings = ParameterSettings::from(&parameters);
+        settings.max_variable_mods = MAX_VARIABLE_MODS + 1;
+        assert!(PyParameters::try_from(settings).is_err());
+    }
+}
@@ -6,7 +6,11 @@ use std::hash::Hash;
 
 use pyo3::exceptions::PyValueError;
 use pyo3::types::PyList;
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
 use sage_core::enzyme::{Digest, Enzyme, EnzymeParameters, Position};
+use sage_core::mass::{monoisotopic, H2O};
+use sage_core::peptide::Peptide;
 use std::collections::hash_map::DefaultHasher;
 use std::hash::Hasher;
 
@@ -134,6 +138,78 @@ impl PyDigest {
     }
 }
 
+/// Cleaving after every residue makes each subsequence a (missed cleavage) digest. Includes
+/// selenocysteine (U), pyrrolysine (O) and unknown residues (X) found in protein databases
+pub const NON_SPECIFIC_RESIDUES: &str = "ACDEFGHIKLMNOPQRSTUVWXY";
+
+/// Missed cleavages needed to digest every peptide up to `max_len` without enzyme specificity:
+/// a non-specific peptide of length n spans n - 1 cleavage sites
+pub fn non_specific_missed_cleavages(missed_cleavages: u8, max_len: usize) -> u8 {
+    missed_cleavages.max(max_len.saturating_sub(1).min(u8::MAX as usize) as u8)
+}
+
+/// Map a specificity string ("full", "semi" or "none") to the semi-enzymatic flag and whether
+/// cleavage is non-specific
+pub fn parse_specificity(specificity: &str) -> PyResult<(bool, bool)> {
+    match specificity.to_lowercase().as_str() {
+        "full" => Ok((false, false)),
+        "semi" => Ok((true, false)),
+        "none" => Ok((false, true)),
+        _ => Err(PyValueError::new_err(format!(
+            "Invalid specificity: {}, allowed values are: full, semi, none",
+            specificity
+        ))),
+    }
+}
+
+/// Non-specific enzymes cleave after every residue, which no rule-based enzyme does
+pub fn is_non_specific(enzyme: &Enzyme) -> bool {
+    enzyme.cleavage_sites(NON_SPECIFIC_RESIDUES).len() == NON_SPECIFIC_RESIDUES.len()
+}
+
+/// Whether the (N-terminus, C-terminus) of a peptide agree with the cleavage rule of an enzyme.
+/// Protein termini are always specific. The database keeps no flanking residues, so only the
+/// terminus decided by the peptide's own residue (the last for C-terminal, the first for
+/// N-terminal enzymes) is checked against the rule; the other is specific unless the peptide is
+/// a semi-enzymatic digest whose checked terminus is specific
+pub fn terminal_specificity(enzyme: &Enzyme, peptide: &Peptide) -> (bool, bool) {
+    let nterm = matches!(peptide.position, Position::Nterm | Position::Full);
+    let cterm = matches!(peptide.position, Position::Cterm | Position::Full);
+    let residue = match enzyme.c_terminal {
+        true => peptide.sequence.last(),
+        false => peptide.sequence.first(),
+    };
+    let own = residue.map_or(false, |r| enzyme.regex.is_match(&(*r as char).to_string()));
+    let other = is_non_specific(enzyme) || !peptide.semi_enzymatic || !own;
+    match enzyme.c_terminal {
+        true => (nterm || other, cterm || own),
+        false => (nterm || own, cterm || other),
+    }
+}
+
+/// Cleavage rule of a named enzyme: (residues, residue preventing cleavage, cleaves C-terminal).
+/// Glu-C cleaves after Glu and Asp in phosphate buffer ("gluc") but only after Glu in ammonium
+/// bicarbonate ("gluc_bicarb"), Asp-N cleaves before Asp ("aspn") and optionally Cys ("aspn_cys").
+/// Lys-C cleaves after and Lys-N before every Lys, regardless of a following Pro
+pub fn enzyme_rule(name: &str) -> PyResult<(&'static str, Option<char>, bool)> {
+    match name.to_lowercase().replace(['-', ' '], "_").as_str() {
+        "trypsin" => Ok(("KR", Some('P'), true)),
+        "trypsin_p" => Ok(("KR", None, true)),
+        "lysc" | "lys_c" => Ok(("K", None, true)),
+        "lysn" | "lys_n" => Ok(("K", None, false)),
+        "chymotrypsin" => Ok(("FWYL", Some('P'), true)),
+        "gluc" | "glu_c" => Ok(("DE", None, true)),
+        "gluc_bicarb" | "glu_c_bicarb" => Ok(("E", None, true)),
+        "aspn" | "asp_n" => Ok(("D", None, false)),
+        "aspn_cys" | "asp_n_cys" => Ok(("CD", None, false)),
+        _ => Err(PyValueError::new_err(format!(
+            "Invalid enzyme: {}, allowed values are: trypsin, trypsin_p, lysc, lysn, chymotrypsin, gluc, \
+             gluc_bicarb, aspn, aspn_cys",
+            name
+        ))),
+    }
+}
+
 #[pyclass]
 #[derive(Clone)]
 pub struct PyEnzyme {
@@ -148,8 +224,16 @@ impl PyEnzyme {
         c_terminal: bool,
         semi_enzymatic: bool,
         skip_suffix: Option<char>,
+        specificity: Option<&str>,
     ) -> PyResult<Self> {
-        match Enzyme::new(cleave, skip_suffix, c_terminal, semi_enzymatic) {
+        let enzyme = match specificity {
+            Some(specificity) => match parse_specificity(specificity)? {
+                (_, true) => Enzyme::new(NON_SPECIFIC_RESIDUES, None, true, false),
+                (semi, false) => Enzyme::new(cleave, skip_suffix, c_terminal, semi),
+            },
+            None => Enzyme::new(cleave, skip_suffix, c_terminal, semi_enzymatic),
+        };
+        match enzyme {
             Some(enzyme) => Ok(PyEnzyme { inner: enzyme }),
             None => Err(PyValueError::new_err("Failed to create Enzyme")),
         }
@@ -170,6 +254,36 @@ impl PyEnzyme {
         self.inner.semi_enzymatic
     }
 
+    #[getter]
+    fn specificity(&self) -> &str {
+        if is_non_specific(&self.inner) {
+            "none"
+        } else if self.inner.semi_enzymatic {
+            "semi"
+        } else {
+            "full"
+        }
+    }
+
+    /// Enzyme with the cleavage rule of a named enzyme, see `enzyme_rule`
+    #[staticmethod]
+    fn from_name(name: &str, semi_enzymatic: bool) -> PyResult<Self> {
+        let (cleave, skip_suffix, c_terminal) = enzyme_rule(name)?;
+        Enzyme::new(cleave, skip_suffix, c_terminal, semi_enzymatic)
+            .map(|inner| PyEnzyme { inner })
+            .ok_or_else(|| PyValueError::new_err("Failed to create Enzyme"))
+    }
+
+    /// Positions (0-based index of the residue a new peptide starts at) the enzyme cuts a sequence
+    fn cleavage_positions(&self, sequence: &str) -> Vec<usize> {
+        self.inner
+            .cleavage_sites(sequence)
+            .into_iter()
+            .map(|s| s.site.end)
+            .filter(|end| *end < sequence.len())
+            .collect()
+    }
+
     fn cleavage_sites(&self, py: Python, sequence: &str) -> PyResult<Py<PyArray2<usize>>> {
         // Call the original cleavage_sites method
         let sites = self.inner.cleavage_sites(sequence);
@@ -197,6 +311,10 @@ pub struct PyEnzymeParameters {
 impl PyEnzymeParameters {
     #[new]
     fn new(missed_cleavages: u8, min_len: usize, max_len: usize, enzyme: Option<PyEnzyme>) -> Self {
+        let missed_cleavages = match &enzyme {
+            Some(e) if is_non_specific(&e.inner) => non_specific_missed_cleavages(missed_cleavages, max_len),
+            _ => missed_cleavages,
+        };
         PyEnzymeParameters {
             inner: EnzymeParameters {
                 missed_cleavages,
@@ -264,11 +382,370 @@ impl PyEnzymeParameters {
     }
 }
 
+/// Cleavage regex of an enzyme and whether it cleaves before Pro
+#[pyfunction]
+pub fn cleavage_rules(enzyme: PyEnzyme) -> (String, bool) {
+    (
+        enzyme.inner.regex.as_str().to_string(),
+        enzyme.inner.skip_suffix != Some('P'),
+    )
+}
+
+/// (start, end, missed cleavages) spans of a sequence of length `len` cut at the given positions
+/// (0-based index of the residue a new peptide starts at), covering up to `missed_cleavages` + 1
+/// segments
+fn digest_spans(
+    len: usize,
+    positions: &[usize],
+    missed_cleavages: u8,
+    min_len: usize,
+    max_len: usize,
+) -> Vec<(usize, usize, u8)> {
+    let mut bounds = vec![0];
+    bounds.extend(positions.iter().copied().filter(|p| *p > 0 && *p < len));
+    bounds.push(len);
+    bounds.dedup();
+
+    let mut spans = Vec::new();
+    for (i, &start) in bounds.iter().enumerate() {
+        for missed in 0..=missed_cleavages as usize {
+            let Some(&end) = bounds.get(i + missed + 1) else {
+                break;
+            };
+            if end - start >= min_len && end - start <= max_len {
+                spans.push((start, end, missed as u8));
+            }
+        }
+    }
+    spans
+}
+
+/// Digests of a sequence cut at the given positions, see `digest_spans`
+fn digest_at_positions(
+    sequence: &str,
+    protein: &str,
+    positions: &[usize],
+    missed_cleavages: u8,
+    min_len: usize,
+    max_len: usize,
+) -> Vec<PyDigest> {
+    let protein = Arc::new(protein.to_string());
+    digest_spans(sequence.len(), positions, missed_cleavages, min_len, max_len)
+        .into_iter()
+        .map(|(start, end, missed)| {
+            let position = match (start == 0, end == sequence.len()) {
+                (true, true) => Position::Full,
+                (true, false) => Position::Nterm,
+                (false, true) => Position::Cterm,
+                (false, false) => Position::Internal,
+            };
+            PyDigest {
+                inner: Digest {
+                    decoy: false,
+                    sequence: sequence[start..end].to_string(),
+                    protein: protein.clone(),
+                    missed_cleavages: missed,
+                    position,
+                    semi_enzymatic: false,
+                },
+            }
+        })
+        .collect()
+}
+
+/// Peptide of a single sequence digest, with its 0-based, end-exclusive span in the protein
+#[pyclass]
+#[derive(Clone, Debug)]
+pub struct PyDigestedPeptide {
+    pub sequence: String,
+    pub start: usize,
+    pub end: usize,
+    pub missed_cleavages: u8,
+    pub mono_mass: f64,
+    pub protein_id: String,
+}
+
+#[pymethods]
+impl PyDigestedPeptide {
+    #[getter]
+    fn sequence(&self) -> &str {
+        &self.sequence
+    }
+
+    #[getter]
+    fn start(&self) -> usize {
+        self.start
+    }
+
+    #[getter]
+    fn end(&self) -> usize {
+        self.end
+    }
+
+    #[getter]
+    fn missed_cleavages(&self) -> u8 {
+        self.missed_cleavages
+    }
+
+    /// Monoisotopic mass of the unmodified peptide
+    #[getter]
+    fn mono_mass(&self) -> f64 {
+        self.mono_mass
+    }
+
+    #[getter]
+    fn protein_id(&self) -> &str {
+        &self.protein_id
+    }
+}
+
+/// Fully specific digest of a sequence at the cleavage sites of an enzyme
+fn digest_peptides(
+    sequence: &str,
+    protein_id: &str,
+    enzyme: &Enzyme,
+    missed_cleavages: u8,
+    min_len: usize,
+    max_len: usize,
+) -> Vec<PyDigestedPeptide> {
+    let positions: Vec<usize> = enzyme
+        .cleavage_sites(sequence)
+        .into_iter()
+        .map(|s| s.site.end)
+        .collect();
+    digest_spans(sequence.len(), &positions, missed_cleavages, min_len, max_len)
+        .into_iter()
+        .map(|(start, end, missed)| PyDigestedPeptide {
+            sequence: sequence[start..end].to_string(),
+            start,
+            end,
+            missed_cleavages: missed,
+            mono_mass: sequence.as_bytes()[start..end]
+                .iter()
+                .map(|r| monoisotopic(*r) as f64)
+                .sum::<f64>()
+                + H2O as f64,
+            protein_id: protein_id.to_string(),
+        })
+        .collect()
+}
+
+/// Digest a single sequence without building a database
+#[pyfunction]
+pub fn digest_sequence(
+    sequence: &str,
+    enzyme: &PyEnzyme,
+    missed_cleavages: u8,
+    min_len: usize,
+    max_len: usize,
+) -> Vec<PyDigestedPeptide> {
+    digest_peptides(sequence, "", &enzyme.inner, missed_cleavages, min_len, max_len)
+}
+
+/// Digest a FASTA entry, given as (accession, sequence) like `PyFasta.targets`
+#[pyfunction]
+pub fn digest_protein(
+    fasta_entry: (String, String),
+    enzyme: &PyEnzyme,
+    missed_cleavages: u8,
+    min_len: usize,
+    max_len: usize,
+) -> Vec<PyDigestedPeptide> {
+    let (accession, sequence) = fasta_entry;
+    digest_peptides(&sequence, &accession, &enzyme.inner, missed_cleavages, min_len, max_len)
+}
+
+/// Digest (accession, sequence) FASTA entries in parallel, the peptides are returned in the order
+/// of the entries
+#[pyfunction]
+pub fn digest_collection_parallel(
+    py: Python,
+    fasta_entries: Vec<(String, String)>,
+    enzyme: &PyEnzyme,
+    missed_cleavages: u8,
+    min_len: usize,
+    max_len: usize,
+    num_threads: usize,
+) -> Vec<PyDigestedPeptide> {
+    let enzyme = &enzyme.inner;
+    let pool = ThreadPoolBuilder::new()
+        .num_threads(num_threads)
+        .build()
+        .unwrap();
+
+    py.allow_threads(|| {
+        pool.install(|| {
+            fasta_entries
+                .par_iter()
+                .flat_map_iter(|(accession, sequence)| {
+                    digest_peptides(sequence, accession, enzyme, missed_cleavages, min_len, max_len)
+                })
+                .collect()
+        })
+    })
+}
+
+/// Double digest with trypsin and Lys-C: cleaves after every Lys (Lys-C ignores a following Pro)
+/// and after Arg not followed by Pro
+#[pyfunction]
+pub fn combined_trypsin_lysc_digest(
+    sequence: &str,
+    protein: &str,
+    missed_cleavages: u8,
+    min_len: usize,
+    max_len: usize,
+) -> Vec<PyDigest> {
+    let residues = sequence.as_bytes();
+    let positions: Vec<usize> = (0..residues.len())
+        .filter(|&i| match residues[i] {
+            b'K' => true,
+            b'R' => residues.get(i + 1) != Some(&b'P'),
+            _ => false,
+        })
+        .map(|i| i + 1)
+        .collect();
+    digest_at_positions(sequence, protein, &positions, missed_cleavages, min_len, max_len)
+}
+
 #[pymodule]
 pub fn enzyme(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_class::<PyDigest>()?;
     m.add_class::<PyPosition>()?;
     m.add_class::<PyEnzyme>()?;
     m.add_class::<PyEnzymeParameters>()?;
+    m.add_function(wrap_pyfunction!(cleavage_rules, m)?)?;
+    m.add_function(wrap_pyfunction!(combined_trypsin_lysc_digest, m)?)?;
+    m.add_class::<PyDigestedPeptide>()?;
+    m.add_function(wrap_pyfunction!(digest_sequence, m)?)?;
+    m.add_function(wrap_pyfunction!(digest_protein, m)?)?;
+    m.add_function(wrap_pyfunction!(digest_collection_parallel, m)?)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn non_specific_enzyme_cleaves_after_every_residue() {
+        let enzyme = Enzyme::new(NON_SPECIFIC_RESIDUES, None, true, false).unwrap();
+        assert!(is_non_specific(&enzyme));
+        assert_eq!(enzyme.cleavage_sites("PEUOXK").len(), 6);
+
+        let trypsin = Enzyme::new("KR", Some('P'), true, false).unwrap();
+        assert!(!is_non_specific(&trypsin));
+    }
+
+    #[test]
+    fn non_specific_missed_cleavages_span_the_longest_peptide() {
+        assert_eq!(non_specific_missed_cleavages(0, 30), 29);
+        assert_eq!(non_specific_missed_cleavages(40, 30), 40);
+        assert_eq!(non_specific_missed_cleavages(0, 1000), u8::MAX);
+    }
+
+    fn digested_sequences(enzyme: Enzyme, missed_cleavages: u8) -> HashSet<String> {
+        let parameters = PyEnzymeParameters::new(missed_cleavages, 3, 12, Some(PyEnzyme { inner: enzyme }));
+        parameters
+            .inner
+            .digest("PEPTIDEKAAAR", Arc::new("sp|P1|A".to_string()))
+            .into_iter()
+            .map(|d| d.sequence)
+            .collect()
+    }
+
+    #[test]
+    fn non_specific_digest_yields_every_subsequence() {
+        let enzyme = Enzyme::new(NON_SPECIFIC_RESIDUES, None, true, false).unwrap();
+        let sequence = "PEPTIDEKAAAR";
+        let expected: HashSet<String> = (3..=12)
+            .flat_map(|len| (0..=sequence.len() - len).map(move |start| sequence[start..start + len].to_string()))
+            .collect();
+
+        assert_eq!(digested_sequences(enzyme, 0), expected);
+    }
+
+    #[test]
+    fn semi_specific_digest_keeps_one_specific_terminus() {
+        let full = digested_sequences(Enzyme::new("KR", Some('P'), true, false).unwrap(), 0);
+        let semi = digested_sequences(Enzyme::new("KR", Some('P'), true, true).unwrap(), 0);
+
+        assert!(full.contains("PEPTIDEK") && !full.contains("PEPTID"));
+        assert!(semi.contains("PEPTIDEK") && semi.contains("PEPTID") && semi.contains("TIDEK"));
+        assert!(!semi.contains("EPTID"));
+    }
+
+    #[test]
+    fn semi_enzymatic_peptides_have_one_specific_terminus() {
+        let trypsin = Enzyme::new("KR", Some('P'), true, true).unwrap();
+        let peptide = |sequence: &str, position: Position| Peptide {
+            decoy: false,
+            sequence: Arc::from(sequence.as_bytes().to_vec().into_boxed_slice()),
+            modifications: vec![0.0; sequence.len()],
+            nterm: None,
+            cterm: None,
+            monoisotopic: 0.0,
+            missed_cleavages: 0,
+            position,
+            proteins: vec![Arc::new("sp|P1|A".to_string())],
+            semi_enzymatic: true,
+        };
+
+        assert_eq!(terminal_specificity(&trypsin, &peptide("TIDEK", Position::Internal)), (false, true));
+        assert_eq!(terminal_specificity(&trypsin, &peptide("PEPTID", Position::Nterm)), (true, false));
+    }
+
+    fn named_digest(name: &str, sequence: &str) -> Vec<String> {
+        let enzyme = PyEnzyme::from_name(name, false).unwrap();
+        digest_sequence(sequence, &enzyme, 0, 1, 50).into_iter().map(|p| p.sequence).collect()
+    }
+
+    #[test]
+    fn glu_c_and_asp_n_cleave_at_acidic_residues() {
+        assert_eq!(named_digest("Glu-C", "AAEGGDLLK"), ["AAE", "GGD", "LLK"]);
+        assert_eq!(named_digest("gluc_bicarb", "AAEGGDLLK"), ["AAE", "GGDLLK"]);
+        assert_eq!(named_digest("Asp-N", "AAEGGDLLK"), ["AAEGG", "DLLK"]);
+        assert_eq!(named_digest("aspn_cys", "AACGGDLLK"), ["AA", "CGG", "DLLK"]);
+        assert!(PyEnzyme::from_name("pepsin", false).is_err());
+    }
+
+    #[test]
+    fn lys_c_and_lys_n_cleave_at_every_lysine() {
+        let sequence = "AAKPGGRLLKEE";
+        assert_eq!(named_digest("trypsin", sequence), ["AAKPGGR", "LLK", "EE"]);
+        assert_eq!(named_digest("Lys-C", sequence), ["AAK", "PGGRLLK", "EE"]);
+        assert_eq!(named_digest("lysn", sequence), ["AA", "KPGGRLL", "KEE"]);
+
+        let double: Vec<String> = combined_trypsin_lysc_digest(sequence, "sp|P1|A", 0, 1, 50)
+            .into_iter()
+            .map(|p| p.inner.sequence)
+            .collect();
+        assert_eq!(double, ["AAK", "PGGR", "LLK", "EE"]);
+
+        assert!(cleavage_rules(PyEnzyme::from_name("lysc", false).unwrap()).1);
+        assert!(!cleavage_rules(PyEnzyme::from_name("trypsin", false).unwrap()).1);
+    }
+
+    #[test]
+    fn single_sequence_digest_keeps_spans_masses_and_length_bounds() {
+        let trypsin = PyEnzyme { inner: Enzyme::new("KR", Some('P'), true, false).unwrap() };
+        // KP is not cleaved, the last segment LLR is shorter than the minimum length
+        let peptides = digest_sequence("AAAKPEPRGGGKLLR", &trypsin, 1, 4, 10);
+        let spans: Vec<(&str, usize, usize, u8)> = peptides
+            .iter()
+            .map(|p| (p.sequence.as_str(), p.start, p.end, p.missed_cleavages))
+            .collect();
+        assert_eq!(
+            spans,
+            vec![("AAAKPEPR", 0, 8, 0), ("GGGK", 8, 12, 0), ("GGGKLLR", 8, 15, 1)]
+        );
+        assert!(peptides.iter().all(|p| p.protein_id.is_empty()));
+
+        let expected = 3.0 * monoisotopic(b'G') as f64 + monoisotopic(b'K') as f64 + H2O as f64;
+        assert!((peptides[1].mono_mass - expected).abs() < 1e-4);
+
+        let protein = digest_protein(("sp|P1|PROT".to_string(), "AAAKPEPRGGGKLLR".to_string()), &trypsin, 1, 4, 10);
+        assert_eq!(protein.len(), 3);
+        assert!(protein.iter().all(|p| p.protein_id == "sp|P1|PROT"));
+    }
+}
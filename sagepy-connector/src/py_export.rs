@@ -0,0 +1,964 @@
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use quick_xml::events::{BytesDecl, BytesEnd, BytesStart, BytesText, Event};
+use quick_xml::{Reader, Writer};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use crate::py_database::PyIndexedDatabase;
+use crate::py_io::default_feature;
+use crate::py_scoring::{
+    check_peptide_indices, feature_values, set_feature_value, PyFeature, FEATURE_NAMES, UNKNOWN_PEPTIDE,
+};
+use sage_core::database::PeptideIx;
+use sage_core::mass::PROTON;
+
+const MZIDENTML_NAMESPACE: &str = "http://psidev.info/psi/pi/mzIdentML/1.2";
+
+/// Controlled vocabulary of the sagepy scores
+const SAGEPY_CV: &str = "SAGEPY";
+const EXTRA_SCORE_NAMES: [&str; 2] = ["discriminant_score", "psm_id"];
+
+/// Fixed accession numbers of the SAGEPY terms, the scores of `FEATURE_NAMES` and
+/// `EXTRA_SCORE_NAMES`. New scores get the next free number, existing numbers never change
+const SAGEPY_TERMS: [(&str, u32); 27] = [
+    ("hyperscore", 1),
+    ("delta_next", 2),
+    ("delta_best", 3),
+    ("matched_peaks", 4),
+    ("longest_b", 5),
+    ("longest_y", 6),
+    ("longest_y_pct", 7),
+    ("missed_cleavages", 8),
+    ("matched_intensity_pct", 9),
+    ("scored_candidates", 10),
+    ("poisson", 11),
+    ("average_ppm", 12),
+    ("delta_mass", 13),
+    ("isotope_error", 14),
+    ("ms2_intensity", 15),
+    ("rt", 16),
+    ("delta_rt_model", 17),
+    ("peptide_len", 18),
+    ("charge", 19),
+    ("posterior_error", 20),
+    ("spectrum_q", 21),
+    ("peptide_q", 22),
+    ("protein_q", 23),
+    ("ms1_isotope_score", 24),
+    ("ms1_intensity_ratio", 25),
+    ("discriminant_score", 26),
+    ("psm_id", 27),
+];
+
+fn sagepy_accession(name: &str) -> Option<String> {
+    SAGEPY_TERMS
+        .iter()
+        .find(|(term, _)| *term == name)
+        .map(|(_, number)| format!("{}:{:07}", SAGEPY_CV, number))
+}
+
+fn xml_error(path: &str) -> impl Fn(quick_xml::Error) -> PyErr + '_ {
+    move |e| PyValueError::new_err(format!("Could not write mzIdentML file {}: {}", path, e))
+}
+
+fn start<'a>(name: &'a str, attributes: &[(&'a str, &'a str)]) -> Event<'a> {
+    Event::Start(BytesStart::new(name).with_attributes(attributes.iter().copied()))
+}
+
+fn empty<'a>(name: &'a str, attributes: &[(&'a str, &'a str)]) -> Event<'a> {
+    Event::Empty(BytesStart::new(name).with_attributes(attributes.iter().copied()))
+}
+
+fn end(name: &str) -> Event<'_> {
+    Event::End(BytesEnd::new(name))
+}
+
+fn cv_param<'a>(cv: &'a str, accession: &'a str, name: &'a str, value: Option<&'a str>) -> Event<'a> {
+    let mut attributes = vec![("cvRef", cv), ("accession", accession), ("name", name)];
+    if let Some(value) = value {
+        attributes.push(("value", value));
+    }
+    empty("cvParam", &attributes)
+}
+
+/// Write PSMs as mzIdentML 1.2. Peptides, their modifications (as unknown modifications with
+/// their mass delta) and proteins are resolved from the database, PSMs are grouped into one
+/// SpectrumIdentificationResult per spectrum and file (one SpectraData per file_id). Matched peaks
+/// (MS:1001121) and the PSM q-value (MS:1002354) are reported with PSI-MS terms, all re-scoring
+/// features, the discriminant score and the PSM id with SAGEPY terms
+#[pyfunction]
+pub fn write_mzidentml(
+    psms: Vec<PyFeature>,
+    db: &PyIndexedDatabase,
+    database_path: &str,
+    output_path: &str,
+    software_version: &str,
+) -> PyResult<()> {
+    check_peptide_indices(&db.inner, &psms)?;
+    let db = &db.inner;
+    let io_error = |e: std::io::Error| {
+        PyValueError::new_err(format!("Could not write mzIdentML file {}: {}", output_path, e))
+    };
+    let xml_error = xml_error(output_path);
+    let file = File::create(output_path).map_err(io_error)?;
+    let mut writer = Writer::new_with_indent(BufWriter::new(file), b' ', 2);
+
+    // proteins, peptides and spectra referenced by the PSMs, in a deterministic order
+    let mut proteins: BTreeMap<&str, usize> = BTreeMap::new();
+    let mut peptides: BTreeMap<u32, ()> = BTreeMap::new();
+    let mut spectra: BTreeMap<(usize, &str), Vec<&PyFeature>> = BTreeMap::new();
+    for psm in &psms {
+        let peptide = db.peptides.get(psm.inner.peptide_idx.0 as usize).ok_or_else(|| {
+            PyValueError::new_err(format!("PSM {} references an unknown peptide", psm.inner.psm_id))
+        })?;
+        peptides.insert(psm.inner.peptide_idx.0, ());
+        for protein in peptide.proteins.iter() {
+            let next = proteins.len();
+            proteins.entry(protein.as_str()).or_insert(next);
+        }
+        spectra
+            .entry((psm.inner.file_id, psm.inner.spec_id.as_str()))
+            .or_default()
+            .push(psm);
+    }
+    let files: BTreeMap<usize, ()> = spectra.keys().map(|(file_id, _)| (*file_id, ())).collect();
+
+    writer
+        .write_event(Event::Decl(BytesDecl::new("1.0", Some("UTF-8"), None)))
+        .map_err(&xml_error)?;
+    writer
+        .write_event(start(
+            "MzIdentML",
+            &[
+                ("id", "sagepy"),
+                ("version", "1.2.0"),
+                ("xmlns", MZIDENTML_NAMESPACE),
+            ],
+        ))
+        .map_err(&xml_error)?;
+
+    // controlled vocabularies
+    let events = [
+        start("cvList", &[]),
+        empty(
+            "cv",
+            &[
+                ("id", "PSI-MS"),
+                ("fullName", "PSI-MS"),
+                ("uri", "https://raw.githubusercontent.com/HUPO-PSI/psi-ms-CV/master/psi-ms.obo"),
+            ],
+        ),
+        empty(
+            "cv",
+            &[
+                ("id", "UO"),
+                ("fullName", "UNIT-ONTOLOGY"),
+                ("uri", "https://raw.githubusercontent.com/bio-ontology-research-group/unit-ontology/master/unit.obo"),
+            ],
+        ),
+        empty(
+            "cv",
+            &[
+                ("id", SAGEPY_CV),
+                ("fullName", "sagepy scores"),
+                ("uri", "https://github.com/theGreatHerrLebert/sagepy"),
+            ],
+        ),
+        end("cvList"),
+        start("AnalysisSoftwareList", &[]),
+        start(
+            "AnalysisSoftware",
+            &[("id", "AS_sagepy"), ("name", "sagepy"), ("version", software_version)],
+        ),
+        start("SoftwareName", &[]),
+        empty("userParam", &[("name", "sagepy")]),
+        end("SoftwareName"),
+        end("AnalysisSoftware"),
+        end("AnalysisSoftwareList"),
+        start("SequenceCollection", &[]),
+    ];
+    for event in events {
+        writer.write_event(event).map_err(&xml_error)?;
+    }
+
+    for (protein, n) in &proteins {
+        let id = format!("DBSeq_{}", n);
+        writer
+            .write_event(empty(
+                "DBSequence",
+                &[("id", id.as_str()), ("accession", *protein), ("searchDatabase_ref", "SDB_1")],
+            ))
+            .map_err(&xml_error)?;
+    }
+
+    for idx in peptides.keys() {
+        let peptide = &db.peptides[*idx as usize];
+        let id = format!("Pep_{}", idx);
+        let sequence = String::from_utf8_lossy(&peptide.sequence);
+        writer.write_event(start("Peptide", &[("id", id.as_str())])).map_err(&xml_error)?;
+        writer.write_event(start("PeptideSequence", &[])).map_err(&xml_error)?;
+        writer
+            .write_event(Event::Text(BytesText::new(&sequence)))
+            .map_err(&xml_error)?;
+        writer.write_event(end("PeptideSequence")).map_err(&xml_error)?;
+
+        // locations are 0 for the N-terminus, 1-based for residues and length + 1 for the C-terminus
+        let residues = peptide
+            .modifications
+            .iter()
+            .enumerate()
+            .map(|(i, m)| (i + 1, *m));
+        let modifications = peptide
+            .nterm
+            .map(|m| (0, m))
+            .into_iter()
+            .chain(residues)
+            .chain(peptide.cterm.map(|m| (peptide.sequence.len() + 1, m)))
+            .filter(|(_, m)| *m != 0.0);
+        for (location, mass) in modifications {
+            let location = location.to_string();
+            let mass = mass.to_string();
+            writer
+                .write_event(start(
+                    "Modification",
+                    &[("location", location.as_str()), ("monoisotopicMassDelta", mass.as_str())],
+                ))
+                .map_err(&xml_error)?;
+            writer
+                .write_event(cv_param("PSI-MS", "MS:1001460", "unknown modification", None))
+                .map_err(&xml_error)?;
+            writer.write_event(end("Modification")).map_err(&xml_error)?;
+        }
+        writer.write_event(end("Peptide")).map_err(&xml_error)?;
+    }
+
+    for idx in peptides.keys() {
+        let peptide = &db.peptides[*idx as usize];
+        let peptide_ref = format!("Pep_{}", idx);
+        let is_decoy = peptide.decoy.to_string();
+        for protein in peptide.proteins.iter() {
+            let n = proteins[protein.as_str()];
+            let id = format!("PE_{}_{}", idx, n);
+            let db_sequence_ref = format!("DBSeq_{}", n);
+            writer
+                .write_event(empty(
+                    "PeptideEvidence",
+                    &[
+                        ("id", id.as_str()),
+                        ("peptide_ref", peptide_ref.as_str()),
+                        ("dBSequence_ref", db_sequence_ref.as_str()),
+                        ("isDecoy", is_decoy.as_str()),
+                    ],
+                ))
+                .map_err(&xml_error)?;
+        }
+    }
+
+    let mut events = vec![
+        end("SequenceCollection"),
+        start("AnalysisCollection", &[]),
+        start(
+            "SpectrumIdentification",
+            &[
+                ("id", "SI_1"),
+                ("spectrumIdentificationProtocol_ref", "SIP_1"),
+                ("spectrumIdentificationList_ref", "SIL_1"),
+            ],
+        ),
+    ];
+    let spectra_data_ids: Vec<String> = files.keys().map(|f| format!("SD_{}", f)).collect();
+    for id in &spectra_data_ids {
+        events.push(empty("InputSpectra", &[("spectraData_ref", id.as_str())]));
+    }
+    events.extend([
+        empty("SearchDatabaseRef", &[("searchDatabase_ref", "SDB_1")]),
+        end("SpectrumIdentification"),
+        end("AnalysisCollection"),
+        start("AnalysisProtocolCollection", &[]),
+        start(
+            "SpectrumIdentificationProtocol",
+            &[("id", "SIP_1"), ("analysisSoftware_ref", "AS_sagepy")],
+        ),
+        start("SearchType", &[]),
+        cv_param("PSI-MS", "MS:1001083", "ms-ms search", None),
+        end("SearchType"),
+        start("Threshold", &[]),
+        cv_param("PSI-MS", "MS:1001494", "no threshold", None),
+        end("Threshold"),
+        end("SpectrumIdentificationProtocol"),
+        end("AnalysisProtocolCollection"),
+        start("DataCollection", &[]),
+        start("Inputs", &[]),
+        start("SearchDatabase", &[("id", "SDB_1"), ("location", database_path)]),
+        start("FileFormat", &[]),
+        cv_param("PSI-MS", "MS:1001348", "FASTA format", None),
+        end("FileFormat"),
+        start("DatabaseName", &[]),
+        empty("userParam", &[("name", database_path)]),
+        end("DatabaseName"),
+        end("SearchDatabase"),
+    ]);
+    for event in events {
+        writer.write_event(event).map_err(&xml_error)?;
+    }
+
+    for (file_id, id) in files.keys().zip(spectra_data_ids.iter()) {
+        let location = format!("file_{}", file_id);
+        let events = [
+            start("SpectraData", &[("id", id.as_str()), ("location", location.as_str())]),
+            start("SpectrumIDFormat", &[]),
+            cv_param("PSI-MS", "MS:1000824", "no nativeID format", None),
+            end("SpectrumIDFormat"),
+            end("SpectraData"),
+        ];
+        for event in events {
+            writer.write_event(event).map_err(&xml_error)?;
+        }
+    }
+
+    let events = [
+        end("Inputs"),
+        start("AnalysisData", &[]),
+        start("SpectrumIdentificationList", &[("id", "SIL_1")]),
+    ];
+    for event in events {
+        writer.write_event(event).map_err(&xml_error)?;
+    }
+
+    for (n, ((file_id, spec_id), mut hits)) in spectra.into_iter().enumerate() {
+        hits.sort_by_key(|psm| psm.inner.rank);
+        let id = format!("SIR_{}", n);
+        let spectra_data_ref = format!("SD_{}", file_id);
+        writer
+            .write_event(start(
+                "SpectrumIdentificationResult",
+                &[("id", id.as_str()), ("spectrumID", spec_id), ("spectraData_ref", spectra_data_ref.as_str())],
+            ))
+            .map_err(&xml_error)?;
+
+        for (k, psm) in hits.iter().enumerate() {
+            let feature = &psm.inner;
+            let charge = feature.charge.max(1) as f32;
+            let id = format!("SII_{}_{}", n, k);
+            let charge_state = feature.charge.to_string();
+            let experimental = ((feature.expmass + charge * PROTON) / charge).to_string();
+            let calculated = ((feature.calcmass + charge * PROTON) / charge).to_string();
+            let peptide_ref = format!("Pep_{}", feature.peptide_idx.0);
+            let rank = feature.rank.to_string();
+            writer
+                .write_event(start(
+                    "SpectrumIdentificationItem",
+                    &[
+                        ("id", id.as_str()),
+                        ("chargeState", charge_state.as_str()),
+                        ("experimentalMassToCharge", experimental.as_str()),
+                        ("calculatedMassToCharge", calculated.as_str()),
+                        ("peptide_ref", peptide_ref.as_str()),
+                        ("rank", rank.as_str()),
+                        ("passThreshold", "true"),
+                    ],
+                ))
+                .map_err(&xml_error)?;
+
+            for protein in db.peptides[feature.peptide_idx.0 as usize].proteins.iter() {
+                let evidence = format!("PE_{}_{}", feature.peptide_idx.0, proteins[protein.as_str()]);
+                writer
+                    .write_event(empty("PeptideEvidenceRef", &[("peptideEvidence_ref", evidence.as_str())]))
+                    .map_err(&xml_error)?;
+            }
+
+            let matched_peaks = feature.matched_peaks.to_string();
+            let spectrum_q = feature.spectrum_q.to_string();
+            writer
+                .write_event(cv_param("PSI-MS", "MS:1001121", "number of matched peaks", Some(&matched_peaks)))
+                .map_err(&xml_error)?;
+            writer
+                .write_event(cv_param("PSI-MS", "MS:1002354", "PSM-level q-value", Some(&spectrum_q)))
+                .map_err(&xml_error)?;
+
+            let values = feature_values(psm)
+                .into_iter()
+                .chain([feature.discriminant_score as f64, feature.psm_id as f64]);
+            let names = FEATURE_NAMES.iter().chain(EXTRA_SCORE_NAMES.iter());
+            for (name, value) in names.copied().zip(values) {
+                let accession = sagepy_accession(name).expect("every sagepy score has a SAGEPY term");
+                let value = value.to_string();
+                writer
+                    .write_event(cv_param(SAGEPY_CV, &accession, name, Some(&value)))
+                    .map_err(&xml_error)?;
+            }
+            writer.write_event(end("SpectrumIdentificationItem")).map_err(&xml_error)?;
+        }
+
+        // retention time of the spectrum, in minutes as reported by sage
+        let rt = hits[0].inner.rt.to_string();
+        writer
+            .write_event(empty(
+                "cvParam",
+                &[
+                    ("cvRef", "PSI-MS"),
+                    ("accession", "MS:1000016"),
+                    ("name", "scan start time"),
+                    ("value", rt.as_str()),
+                    ("unitCvRef", "UO"),
+                    ("unitAccession", "UO:0000031"),
+                    ("unitName", "minute"),
+                ],
+            ))
+            .map_err(&xml_error)?;
+        writer.write_event(end("SpectrumIdentificationResult")).map_err(&xml_error)?;
+    }
+
+    let events = [
+        end("SpectrumIdentificationList"),
+        end("AnalysisData"),
+        end("DataCollection"),
+        end("MzIdentML"),
+    ];
+    for event in events {
+        writer.write_event(event).map_err(&xml_error)?;
+    }
+    writer.into_inner().flush().map_err(io_error)?;
+    Ok(())
+}
+
+fn attribute(element: &BytesStart, name: &str) -> PyResult<Option<String>> {
+    let read_error = |e: quick_xml::Error| PyValueError::new_err(format!("Invalid mzIdentML attribute {}: {}", name, e));
+    match element.try_get_attribute(name).map_err(|e| read_error(e.into()))? {
+        Some(a) => Ok(Some(a.unescape_value().map_err(read_error)?.into_owned())),
+        None => Ok(None),
+    }
+}
+
+fn required_attribute(element: &BytesStart, name: &str) -> PyResult<String> {
+    attribute(element, name)?.ok_or_else(|| {
+        PyValueError::new_err(format!(
+            "mzIdentML element {} is missing attribute {}",
+            String::from_utf8_lossy(element.name().as_ref()),
+            name
+        ))
+    })
+}
+
+/// Read PSMs from an mzIdentML file. Charge, masses (from m/z), rank, retention time and decoy
+/// status are recovered from the standard elements, all SAGEPY scores from their cvParams. Files
+/// are numbered by first appearance of their SpectraData as file_id. The PSMs do not refer to a
+/// database peptide, the PeptideSequence is kept as peptide_sequence
+#[pyfunction]
+pub fn read_mzidentml(path: &str) -> PyResult<Vec<PyFeature>> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| PyValueError::new_err(format!("Could not read mzIdentML file {}: {}", path, e)))?;
+    let read_error = |e: quick_xml::Error| {
+        PyValueError::new_err(format!("Could not parse mzIdentML file {}: {}", path, e))
+    };
+    let mut reader = Reader::from_str(&content);
+    reader.trim_text(true);
+
+    let mut peptide_sequences: HashMap<String, String> = HashMap::new();
+    let mut spectra_data: HashMap<String, usize> = HashMap::new();
+    let mut decoy_evidence: HashMap<String, bool> = HashMap::new();
+    let mut current_peptide: Option<String> = None;
+    let mut in_peptide_sequence = false;
+
+    // state of the current SpectrumIdentificationResult and SpectrumIdentificationItem
+    let mut result: Vec<PyFeature> = Vec::new();
+    let mut item: Option<PyFeature> = None;
+    let mut spec_id = String::new();
+    let mut file_id = 0usize;
+    let mut psms = Vec::new();
+
+    loop {
+        let event = reader.read_event().map_err(read_error)?;
+        match &event {
+            Event::Start(e) | Event::Empty(e) => match e.name().as_ref() {
+                b"Peptide" => current_peptide = Some(required_attribute(e, "id")?),
+                b"PeptideSequence" => in_peptide_sequence = true,
+                b"PeptideEvidence" => {
+                    let is_decoy = attribute(e, "isDecoy")?.map_or(false, |d| d == "true" || d == "1");
+                    decoy_evidence.insert(required_attribute(e, "id")?, is_decoy);
+                }
+                b"SpectrumIdentificationResult" => {
+                    spec_id = required_attribute(e, "spectrumID")?;
+                    let next = spectra_data.len();
+                    file_id = *spectra_data.entry(required_attribute(e, "spectraData_ref")?).or_insert(next);
+                    result.clear();
+                }
+                b"SpectrumIdentificationItem" => {
+                    let parse = |name: &str| -> PyResult<f32> {
+                        required_attribute(e, name)?.parse().map_err(|_| {
+                            PyValueError::new_err(format!("Invalid mzIdentML attribute {}", name))
+                        })
+                    };
+                    let charge = parse("chargeState")? as u8;
+                    let neutral = |mz: f32| (mz - PROTON) * charge.max(1) as f32;
+                    let sequence = peptide_sequences.get(&required_attribute(e, "peptide_ref")?);
+                    let feature = sage_core::scoring::Feature {
+                        peptide_idx: UNKNOWN_PEPTIDE,
+                        peptide_len: sequence.map_or(0, |s| s.len()),
+                        spec_id: spec_id.clone(),
+                        file_id,
+                        rank: parse("rank")? as u32,
+                        charge,
+                        expmass: neutral(parse("experimentalMassToCharge")?),
+                        calcmass: attribute(e, "calculatedMassToCharge")?
+                            .and_then(|mz| mz.parse().ok())
+                            .map_or(0.0, neutral),
+                        ..default_feature()
+                    };
+                    item = Some(PyFeature {
+                        peptide_sequence: sequence.cloned(),
+                        ..PyFeature::from(feature)
+                    });
+                }
+                b"PeptideEvidenceRef" => {
+                    if let Some(item) = item.as_mut() {
+                        let evidence = required_attribute(e, "peptideEvidence_ref")?;
+                        if decoy_evidence.get(&evidence).copied().unwrap_or(false) {
+                            item.inner.label = -1;
+                        }
+                    }
+                }
+                b"cvParam" => {
+                    let accession = required_attribute(e, "accession")?;
+                    let value: Option<f64> = attribute(e, "value")?.and_then(|v| v.parse().ok());
+                    match (item.as_mut(), value) {
+                        (Some(item), Some(value)) if accession.starts_with(SAGEPY_CV) => {
+                            let name = required_attribute(e, "name")?;
+                            match name.as_str() {
+                                "discriminant_score" => item.inner.discriminant_score = value as f32,
+                                "psm_id" => item.inner.psm_id = value as usize,
+                                _ => {
+                                    set_feature_value(item, &name, value);
+                                }
+                            }
+                        }
+                        (None, Some(value)) if accession == "MS:1000016" => {
+                            // scan start time of the result, converted from seconds if necessary
+                            let seconds = attribute(e, "unitAccession")?.as_deref() == Some("UO:0000010");
+                            let rt = if seconds { value / 60.0 } else { value } as f32;
+                            result.iter_mut().for_each(|psm| psm.inner.rt = rt);
+                        }
+                        _ => {}
+                    }
+                }
+                _ => {}
+            },
+            Event::Text(text) if in_peptide_sequence => {
+                if let Some(peptide) = current_peptide.as_ref() {
+                    let sequence = text.unescape().map_err(read_error)?;
+                    peptide_sequences.insert(peptide.clone(), sequence.trim().to_string());
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+
+        let closed = match &event {
+            Event::End(e) => Some(e.name()),
+            Event::Empty(e) => Some(e.name()),
+            _ => None,
+        };
+        if let Some(name) = closed {
+            match name.as_ref() {
+                b"PeptideSequence" => in_peptide_sequence = false,
+                b"Peptide" => current_peptide = None,
+                b"SpectrumIdentificationItem" => result.extend(item.take()),
+                b"SpectrumIdentificationResult" => psms.append(&mut result),
+                _ => {}
+            }
+        }
+    }
+
+    Ok(psms)
+}
+
+/// Columns of the PSM section written by `write_mztab`: the mzTab 1.0 columns followed by the
+/// decoy flag, the PSM q-value and the sagepy peptide index as optional columns
+const MZTAB_PSM_COLUMNS: [&str; 21] = [
+    "sequence",
+    "PSM_ID",
+    "accession",
+    "unique",
+    "database",
+    "database_version",
+    "search_engine",
+    "search_engine_score[1]",
+    "modifications",
+    "retention_time",
+    "charge",
+    "exp_mass_to_charge",
+    "calc_mass_to_charge",
+    "spectra_ref",
+    "pre",
+    "post",
+    "start",
+    "end",
+    "opt_global_cv_MS:1002217_decoy_peptide",
+    "opt_global_cv_MS:1002354_PSM-level_q-value",
+    "opt_global_sagepy_peptide_idx",
+];
+
+/// Write PSMs as mzTab 1.0 (Summary, Identification): the MTD section lists one ms_run per file_id
+/// and the study variables, the PSM section holds one row per PSM and protein accession with the
+/// hyperscore as search engine score. Modifications are written as CHEMMOD mass deltas, retention
+/// times in seconds
+#[pyfunction]
+pub fn write_mztab(
+    psms: Vec<PyFeature>,
+    db: &PyIndexedDatabase,
+    output_path: &str,
+    study_variables: Vec<String>,
+) -> PyResult<()> {
+    check_peptide_indices(&db.inner, &psms)?;
+    let db = &db.inner;
+    let io_error = |e: std::io::Error| {
+        PyValueError::new_err(format!("Could not write mzTab file {}: {}", output_path, e))
+    };
+    let mut writer = BufWriter::new(File::create(output_path).map_err(io_error)?);
+
+    let files: BTreeMap<usize, ()> = psms.iter().map(|p| (p.inner.file_id, ())).collect();
+    let mut metadata = vec![
+        ("mzTab-version".to_string(), "1.0.0".to_string()),
+        ("mzTab-mode".to_string(), "Summary".to_string()),
+        ("mzTab-type".to_string(), "Identification".to_string()),
+        ("description".to_string(), "sagepy search results".to_string()),
+        ("software[1]".to_string(), "[, , sagepy, ]".to_string()),
+        ("psm_search_engine_score[1]".to_string(), "[, , hyperscore, ]".to_string()),
+        (
+            "fixed_mod[1]".to_string(),
+            "[MS, MS:1002453, No fixed modifications searched, ]".to_string(),
+        ),
+        (
+            "variable_mod[1]".to_string(),
+            "[MS, MS:1002454, No variable modifications searched, ]".to_string(),
+        ),
+    ];
+    for file_id in files.keys() {
+        metadata.push((format!("ms_run[{}]-location", file_id + 1), "null".to_string()));
+    }
+    for (i, variable) in study_variables.iter().enumerate() {
+        metadata.push((format!("study_variable[{}]-description", i + 1), variable.clone()));
+    }
+    for (key, value) in &metadata {
+        writeln!(writer, "MTD\t{}\t{}", key, value).map_err(io_error)?;
+    }
+    writeln!(writer).map_err(io_error)?;
+    writeln!(writer, "PSH\t{}", MZTAB_PSM_COLUMNS.join("\t")).map_err(io_error)?;
+
+    for psm in &psms {
+        let peptide = db.peptides.get(psm.inner.peptide_idx.0 as usize).ok_or_else(|| {
+            PyValueError::new_err(format!("PSM {} references an unknown peptide", psm.inner.psm_id))
+        })?;
+        let decoy = psm.inner.label == -1;
+        let sequence = String::from_utf8_lossy(&peptide.sequence);
+        let residues = peptide.modifications.iter().enumerate().map(|(i, m)| (i + 1, *m));
+        let modifications: Vec<String> = peptide
+            .nterm
+            .map(|m| (0, m))
+            .into_iter()
+            .chain(residues)
+            .chain(peptide.cterm.map(|m| (peptide.sequence.len() + 1, m)))
+            .filter(|(_, m)| *m != 0.0)
+            .map(|(location, mass)| format!("{}-CHEMMOD:{:+}", location, mass))
+            .collect();
+        let modifications = match modifications.is_empty() {
+            true => "null".to_string(),
+            false => modifications.join(","),
+        };
+        let charge = psm.inner.charge.max(1) as f32;
+
+        for protein in peptide.proteins.iter() {
+            let accession = match decoy && !protein.starts_with(db.decoy_tag.as_str()) {
+                true => format!("{}{}", db.decoy_tag, protein),
+                false => protein.to_string(),
+            };
+            let row = [
+                sequence.to_string(),
+                psm.inner.psm_id.to_string(),
+                accession,
+                ((peptide.proteins.len() == 1) as u8).to_string(),
+                "null".to_string(),
+                "null".to_string(),
+                "[, , sagepy, ]".to_string(),
+                psm.inner.hyperscore.to_string(),
+                modifications.clone(),
+                (psm.inner.rt * 60.0).to_string(),
+                psm.inner.charge.to_string(),
+                (psm.inner.expmass / charge + PROTON).to_string(),
+                (psm.inner.calcmass / charge + PROTON).to_string(),
+                format!("ms_run[{}]:{}", psm.inner.file_id + 1, psm.inner.spec_id),
+                "null".to_string(),
+                "null".to_string(),
+                "null".to_string(),
+                "null".to_string(),
+                (decoy as u8).to_string(),
+                psm.inner.spectrum_q.to_string(),
+                psm.inner.peptide_idx.0.to_string(),
+            ];
+            writeln!(writer, "PSM\t{}", row.join("\t")).map_err(io_error)?;
+        }
+    }
+    writer.flush().map_err(io_error)?;
+    Ok(())
+}
+
+/// Value of a column of an mzTab PSM row, None if missing, empty or null
+fn mztab_value<'a>(header: &[&str], fields: &[&'a str], name: &str) -> Option<&'a str> {
+    header
+        .iter()
+        .position(|c| *c == name)
+        .and_then(|c| fields.get(c + 1))
+        .copied()
+        .filter(|v| !v.is_empty() && *v != "null")
+}
+
+/// Read the PSM section of an mzTab file. Rows of the same PSM_ID and spectra_ref (one per protein
+/// accession) are merged. Charge, masses (from m/z), retention time, file id and spec id (from
+/// spectra_ref), the first search engine score as hyperscore and, if present, the decoy flag and
+/// q-value are recovered, files written by `write_mztab` also recover the peptide index
+#[pyfunction]
+pub fn read_mztab(path: &str) -> PyResult<Vec<PyFeature>> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| PyValueError::new_err(format!("Could not read mzTab file {}: {}", path, e)))?;
+
+    let mut header: Vec<&str> = Vec::new();
+    let mut seen: HashSet<(String, String)> = HashSet::new();
+    let mut psms = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        let fields: Vec<&str> = line.trim_end_matches('\r').split('\t').collect();
+        match fields[0] {
+            "PSH" => header = fields[1..].to_vec(),
+            "PSM" => {
+                let line_error = |message: String| {
+                    PyValueError::new_err(format!("Invalid mzTab file {} line {}: {}", path, i + 1, message))
+                };
+                if header.is_empty() {
+                    return Err(line_error("PSM row before the PSH header".to_string()));
+                }
+                let parse = |name: &str| -> PyResult<Option<f64>> {
+                    mztab_value(&header, &fields, name)
+                        .map(|v| v.parse().map_err(|_| line_error(format!("could not parse {} {}", name, v))))
+                        .transpose()
+                };
+                let (Some(psm_id), Some(spectra_ref)) = (
+                    mztab_value(&header, &fields, "PSM_ID"),
+                    mztab_value(&header, &fields, "spectra_ref"),
+                ) else {
+                    return Err(line_error("missing PSM_ID or spectra_ref".to_string()));
+                };
+                if !seen.insert((psm_id.to_string(), spectra_ref.to_string())) {
+                    continue;
+                }
+                // ms_run[k]:<spectrum reference>, k is 1-based
+                let (run, spec_id) = spectra_ref
+                    .split_once(':')
+                    .ok_or_else(|| line_error(format!("invalid spectra_ref {}", spectra_ref)))?;
+                let file_id = run
+                    .trim_start_matches("ms_run[")
+                    .trim_end_matches(']')
+                    .parse::<usize>()
+                    .map_err(|_| line_error(format!("invalid spectra_ref {}", spectra_ref)))?
+                    .saturating_sub(1);
+
+                let charge = parse("charge")?.unwrap_or(0.0) as u8;
+                let neutral = |mz: f64| (mz as f32 - PROTON) * charge.max(1) as f32;
+                let mut feature = sage_core::scoring::Feature {
+                    psm_id: psm_id
+                        .parse()
+                        .map_err(|_| line_error(format!("could not parse PSM_ID {}", psm_id)))?,
+                    peptide_len: mztab_value(&header, &fields, "sequence").map_or(0, |s| s.len()),
+                    spec_id: spec_id.to_string(),
+                    file_id,
+                    charge,
+                    expmass: parse("exp_mass_to_charge")?.map_or(0.0, neutral),
+                    calcmass: parse("calc_mass_to_charge")?.map_or(0.0, neutral),
+                    rt: parse("retention_time")?.map_or(0.0, |rt| (rt / 60.0) as f32),
+                    hyperscore: parse("search_engine_score[1]")?.unwrap_or(0.0),
+                    ..default_feature()
+                };
+                if parse("opt_global_cv_MS:1002217_decoy_peptide")? == Some(1.0) {
+                    feature.label = -1;
+                }
+                if let Some(q) = parse("opt_global_cv_MS:1002354_PSM-level_q-value")? {
+                    feature.spectrum_q = q as f32;
+                }
+                if let Some(idx) = parse("opt_global_sagepy_peptide_idx")? {
+                    feature.peptide_idx = PeptideIx(idx as u32);
+                }
+                psms.push(PyFeature::from(feature));
+            }
+            _ => {}
+        }
+    }
+    Ok(psms)
+}
+
+#[pymodule]
+pub fn export(_py: Python, m: &PyModule) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(write_mzidentml, m)?)?;
+    m.add_function(wrap_pyfunction!(read_mzidentml, m)?)?;
+    m.add_function(wrap_pyfunction!(write_mztab, m)?)?;
+    m.add_function(wrap_pyfunction!(read_mztab, m)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sage_core::database::IndexedDatabase;
+    use sage_core::enzyme::Position;
+    use sage_core::ion_series::Kind;
+    use sage_core::peptide::Peptide;
+    use sage_core::scoring::Feature;
+    use std::sync::Arc;
+
+    fn test_database() -> PyIndexedDatabase {
+        let peptide = Peptide {
+            decoy: false,
+            sequence: Arc::from(b"PEPTIDEK".to_vec().into_boxed_slice()),
+            modifications: vec![0.0; 8],
+            nterm: None,
+            cterm: None,
+            monoisotopic: 927.45,
+            missed_cleavages: 0,
+            position: Position::Full,
+            proteins: vec![Arc::new("sp|P1|A".to_string())],
+            semi_enzymatic: false,
+        };
+        let inner = IndexedDatabase {
+            peptides: vec![peptide],
+            fragments: Vec::new(),
+            ion_kinds: vec![Kind::B, Kind::Y],
+            min_value: Vec::new(),
+            potential_mods: Vec::new(),
+            bucket_size: 8192,
+            generate_decoys: false,
+            decoy_tag: "rev_".to_string(),
+        };
+        PyIndexedDatabase::with_fasta_hash(inner, String::new())
+    }
+
+    #[test]
+    fn sagepy_terms_cover_every_score_with_unique_accessions() {
+        let names = FEATURE_NAMES.iter().chain(EXTRA_SCORE_NAMES.iter());
+        assert!(names.copied().all(|name| sagepy_accession(name).is_some()));
+        let numbers: HashSet<u32> = SAGEPY_TERMS.iter().map(|(_, number)| *number).collect();
+        assert_eq!(numbers.len(), SAGEPY_TERMS.len());
+        assert_eq!(sagepy_accession("hyperscore").as_deref(), Some("SAGEPY:0000001"));
+    }
+
+    #[test]
+    fn mzidentml_follows_the_schema_and_round_trips() {
+        let path = std::env::temp_dir().join(format!("sagepy_mzid_{}.mzid", std::process::id()));
+        let path = path.to_str().unwrap();
+        let psm = PyFeature::from(Feature {
+            peptide_idx: PeptideIx(0),
+            peptide_len: 8,
+            spec_id: "scan=7".to_string(),
+            file_id: 3,
+            charge: 2,
+            hyperscore: 42.0,
+            ..default_feature()
+        });
+        write_mzidentml(vec![psm], &test_database(), "db.fasta", path, "0.0.0").unwrap();
+        let content = std::fs::read_to_string(path).unwrap();
+        let psms = read_mzidentml(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        // children of MzIdentML in the sequence required by the mzIdentML 1.2 schema
+        let mut reader = Reader::from_str(&content);
+        reader.trim_text(true);
+        let (mut depth, mut children) = (0, Vec::new());
+        loop {
+            match reader.read_event().unwrap() {
+                Event::Start(e) => {
+                    if depth == 1 {
+                        children.push(String::from_utf8_lossy(e.name().as_ref()).into_owned());
+                    }
+                    depth += 1;
+                }
+                Event::End(_) => depth -= 1,
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+        let expected = [
+            "cvList",
+            "AnalysisSoftwareList",
+            "SequenceCollection",
+            "AnalysisCollection",
+            "AnalysisProtocolCollection",
+            "DataCollection",
+        ];
+        assert_eq!(children, expected);
+
+        assert_eq!(psms.len(), 1);
+        assert_eq!(psms[0].inner.peptide_idx, UNKNOWN_PEPTIDE);
+        assert_eq!(psms[0].peptide_sequence.as_deref(), Some("PEPTIDEK"));
+        assert_eq!(psms[0].inner.spec_id, "scan=7");
+        assert_eq!(psms[0].inner.file_id, 0);
+        assert_eq!(psms[0].inner.charge, 2);
+        assert_eq!(psms[0].inner.hyperscore, 42.0);
+    }
+
+    #[test]
+    fn mztab_has_a_row_per_protein_and_round_trips() {
+        let path = std::env::temp_dir().join(format!("sagepy_mztab_{}.mztab", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut db = test_database();
+        db.inner.peptides[0].proteins.push(Arc::new("sp|P2|B".to_string()));
+        db.inner.peptides[0].modifications[3] = 79.9663;
+        let psm = PyFeature::from(Feature {
+            psm_id: 5,
+            peptide_idx: PeptideIx(0),
+            peptide_len: 8,
+            spec_id: "scan=7".to_string(),
+            file_id: 1,
+            label: -1,
+            charge: 2,
+            calcmass: 1007.4163,
+            expmass: 1007.42,
+            rt: 10.0,
+            hyperscore: 42.0,
+            spectrum_q: 0.004,
+            ..default_feature()
+        });
+        write_mztab(vec![psm], &db, path, vec!["control".to_string()]).unwrap();
+        let content = std::fs::read_to_string(path).unwrap();
+        let psms = read_mztab(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        let lines: Vec<&str> = content.lines().collect();
+        assert!(lines.contains(&"MTD\tmzTab-version\t1.0.0"));
+        assert!(lines.contains(&"MTD\tms_run[2]-location\tnull"));
+        assert!(lines.contains(&"MTD\tstudy_variable[1]-description\tcontrol"));
+        let rows: Vec<Vec<&str>> = lines
+            .iter()
+            .filter(|l| l.starts_with("PSM\t"))
+            .map(|l| l.split('\t').collect())
+            .collect();
+        assert_eq!(rows.len(), 2);
+        let column = |row: &[&str], name: &str| {
+            let c = MZTAB_PSM_COLUMNS.iter().position(|c| *c == name).unwrap();
+            row[c + 1].to_string()
+        };
+        for (row, accession) in rows.iter().zip(["rev_sp|P1|A", "rev_sp|P2|B"]) {
+            assert_eq!(row.len(), MZTAB_PSM_COLUMNS.len() + 1);
+            assert_eq!(column(row, "accession"), accession);
+            assert_eq!(column(row, "unique"), "0");
+            assert_eq!(column(row, "modifications"), "4-CHEMMOD:+79.9663");
+            assert_eq!(column(row, "retention_time"), "600");
+            assert_eq!(column(row, "spectra_ref"), "ms_run[2]:scan=7");
+        }
+
+        // the rows of both proteins are merged into one PSM
+        assert_eq!(psms.len(), 1);
+        let psm = &psms[0].inner;
+        assert_eq!((psm.psm_id, psm.file_id, psm.spec_id.as_str()), (5, 1, "scan=7"));
+        assert_eq!((psm.label, psm.charge, psm.peptide_idx, psm.peptide_len), (-1, 2, PeptideIx(0), 8));
+        assert_eq!((psm.rt, psm.hyperscore, psm.spectrum_q), (10.0, 42.0, 0.004));
+        assert!((psm.calcmass - 1007.4163).abs() < 1e-3 && (psm.expmass - 1007.42).abs() < 1e-3);
+    }
+}
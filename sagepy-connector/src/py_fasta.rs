@@ -1,12 +1,99 @@
 use sage_core::fasta::Fasta;
 
 use crate::py_enzyme::{PyDigest, PyEnzymeParameters};
+use flate2::read::MultiGzDecoder;
+use pyo3::exceptions::PyValueError;
 use pyo3::prelude::*;
+use rayon::ThreadPoolBuilder;
+use sha2::{Digest, Sha256};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::sync::Mutex;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Size in bytes of the batches of whole records a FASTA file is parsed in
+const FASTA_BATCH_BYTES: usize = 16 << 20;
+
+/// Open a (possibly gzip compressed) FASTA file, compressed files are detected by their magic
+/// bytes and decompressed while streaming
+fn open_fasta(path: &str) -> std::io::Result<Box<dyn BufRead + Send>> {
+    let mut magic = [0u8; 2];
+    let is_gzip = File::open(path).and_then(|mut f| f.read(&mut magic))? == 2 && magic == GZIP_MAGIC;
+
+    let file = File::open(path)?;
+    Ok(if is_gzip {
+        Box::new(BufReader::new(MultiGzDecoder::new(BufReader::new(file))))
+    } else {
+        Box::new(BufReader::new(file))
+    })
+}
+
+fn update_fasta_hash(hasher: &mut Sha256, line: &str) {
+    hasher.update(line.as_bytes());
+    hasher.update(b"\n");
+}
+
+/// SHA-256 hex digest of FASTA contents over their trimmed, non-empty lines, independent of line
+/// endings and blank lines
+pub(crate) fn fasta_hash<'a>(lines: impl IntoIterator<Item = &'a str>) -> String {
+    let mut hasher = Sha256::new();
+    for line in lines.into_iter().map(str::trim).filter(|l| !l.is_empty()) {
+        update_fasta_hash(&mut hasher, line);
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+/// Read FASTA records line by line, passing batches of whole records of about `batch_bytes` to
+/// `on_batch`, so that at most one batch is held in memory by the reader. Returns the `fasta_hash`
+/// of the contents
+fn stream_fasta_batches(
+    mut reader: impl BufRead,
+    batch_bytes: usize,
+    mut on_batch: impl FnMut(String),
+) -> std::io::Result<String> {
+    let mut hasher = Sha256::new();
+    let mut batch = String::new();
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 0 {
+        let trimmed = line.trim();
+        if !trimmed.is_empty() {
+            update_fasta_hash(&mut hasher, trimmed);
+            if trimmed.starts_with('>') && batch.len() >= batch_bytes {
+                on_batch(std::mem::take(&mut batch));
+            }
+            batch.push_str(trimmed);
+            batch.push('\n');
+        }
+        line.clear();
+    }
+    if !batch.is_empty() {
+        on_batch(batch);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Concatenate the targets of FASTA parts parsed from consecutive batches
+fn merge_fasta_parts(parts: Vec<Fasta>, decoy_tag: String, generate_decoys: bool) -> Fasta {
+    let mut parts = parts.into_iter();
+    let mut inner = parts
+        .next()
+        .unwrap_or_else(|| Fasta::parse(String::new(), decoy_tag, generate_decoys));
+    for part in parts {
+        inner.targets.extend(part.targets);
+    }
+    inner
+}
 
 #[pyclass]
 #[derive(Clone)]
 pub struct PyFasta {
     pub inner: Fasta,
+    /// See `fasta_hash`, carried over to databases built from this FASTA
+    pub fasta_hash: String,
 }
 
 #[pymethods]
@@ -14,10 +101,78 @@ impl PyFasta {
     #[staticmethod]
     fn parse(contents: String, decoy_tag: String, generate_decoys: bool) -> PyResult<Self> {
         Ok(PyFasta {
+            fasta_hash: fasta_hash(contents.lines()),
             inner: Fasta::parse(contents, decoy_tag, generate_decoys),
         })
     }
 
+    /// Read a FASTA file, gzip compressed files (e.g. `.fa.gz`) are decompressed transparently.
+    /// Records are streamed and parsed in batches, the file contents are never held in memory
+    #[staticmethod]
+    fn read(path: &str, decoy_tag: String, generate_decoys: bool) -> PyResult<Self> {
+        let io_error = |e: std::io::Error| PyValueError::new_err(format!("Failed to read {}: {}", path, e));
+        let mut parts = Vec::new();
+        let fasta_hash = stream_fasta_batches(open_fasta(path).map_err(io_error)?, FASTA_BATCH_BYTES, |batch| {
+            parts.push(Fasta::parse(batch, decoy_tag.clone(), generate_decoys))
+        })
+        .map_err(io_error)?;
+        Ok(PyFasta {
+            inner: merge_fasta_parts(parts, decoy_tag, generate_decoys),
+            fasta_hash,
+        })
+    }
+
+    /// Read a FASTA file like `read`, parsing the streamed batches of records on `num_threads`
+    /// threads while reading continues
+    #[staticmethod]
+    fn read_parallel(path: &str, decoy_tag: String, generate_decoys: bool, num_threads: usize) -> PyResult<Self> {
+        let io_error = |e: std::io::Error| PyValueError::new_err(format!("Failed to read {}: {}", path, e));
+        let reader = open_fasta(path).map_err(io_error)?;
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .build()
+            .unwrap();
+
+        let parts: Mutex<Vec<(usize, Fasta)>> = Mutex::new(Vec::new());
+        let fasta_hash = pool
+            .scope(|scope| {
+                let (parts, decoy_tag) = (&parts, &decoy_tag);
+                let mut index = 0;
+                stream_fasta_batches(reader, FASTA_BATCH_BYTES, |batch| {
+                    let i = index;
+                    index += 1;
+                    scope.spawn(move |_| {
+                        let part = Fasta::parse(batch, decoy_tag.clone(), generate_decoys);
+                        parts.lock().unwrap().push((i, part));
+                    });
+                })
+            })
+            .map_err(io_error)?;
+
+        let mut parts = parts.into_inner().unwrap();
+        parts.sort_unstable_by_key(|(i, _)| *i);
+        Ok(PyFasta {
+            inner: merge_fasta_parts(parts.into_iter().map(|(_, part)| part).collect(), decoy_tag, generate_decoys),
+            fasta_hash,
+        })
+    }
+
+    /// SHA-256 digest of the FASTA contents, equal to `fasta_hash` of databases built from it
+    #[getter(fasta_hash)]
+    fn get_fasta_hash(&self) -> String {
+        self.fasta_hash.clone()
+    }
+
+    /// (accession, sequence) pairs of the target proteins
+    #[getter]
+    fn targets(&self) -> Vec<(String, String)> {
+        self.inner
+            .targets
+            .iter()
+            .map(|(accession, sequence)| (accession.to_string(), sequence.clone()))
+            .collect()
+    }
+
     fn digest(&self, py: Python, enzyme_params: &PyEnzymeParameters) -> PyResult<PyObject> {
         let digests = self.inner.digest(&enzyme_params.inner);
         let py_digests: Vec<PyDigest> =
@@ -26,8 +181,219 @@ impl PyFasta {
     }
 }
 
+/// Word length of the k-mer identity estimate of `cluster_sequences_by_identity`
+const CLUSTER_KMER_LEN: usize = 5;
+
+fn kmer_hashes(sequence: &str) -> HashSet<u64> {
+    sequence
+        .as_bytes()
+        .windows(CLUSTER_KMER_LEN)
+        .map(|kmer| {
+            let mut hasher = DefaultHasher::new();
+            kmer.hash(&mut hasher);
+            hasher.finish()
+        })
+        .collect()
+}
+
+/// Greedy (CD-HIT-like) clustering of (accession, sequence) proteins: proteins are visited by
+/// decreasing length and join the first representative they share enough k-mers with, or become
+/// a new representative. Without alignment, the identity to a representative is estimated from
+/// the fraction f of shared k-mers of the shorter sequence as f^(1/k), as two sequences of
+/// identity t share about t^k of their k-mers. Representatives come first in their cluster
+#[pyfunction]
+pub fn cluster_sequences_by_identity(
+    proteins: Vec<(String, String)>,
+    identity_threshold: f32,
+) -> Vec<Vec<(String, String)>> {
+    let mut order: Vec<usize> = (0..proteins.len()).collect();
+    order.sort_by_key(|&i| std::cmp::Reverse(proteins[i].1.len()));
+
+    let mut clusters: Vec<Vec<usize>> = Vec::new();
+    // k-mer -> clusters whose representative contains it
+    let mut index: HashMap<u64, Vec<usize>> = HashMap::new();
+
+    for i in order {
+        let sequence = &proteins[i].1;
+        let kmers = kmer_hashes(sequence);
+
+        let mut shared: HashMap<usize, usize> = HashMap::new();
+        for kmer in kmers.iter() {
+            for cluster in index.get(kmer).into_iter().flatten() {
+                *shared.entry(*cluster).or_insert(0) += 1;
+            }
+        }
+        let identity = |count: usize| match kmers.len() {
+            0 => 0.0,
+            n => (count as f32 / n as f32).powf(1.0 / CLUSTER_KMER_LEN as f32),
+        };
+        // sequences shorter than a k-mer are only clustered with identical representatives
+        let member_of = match kmers.is_empty() {
+            true => clusters.iter().position(|c| proteins[c[0]].1 == *sequence),
+            false => shared
+                .into_iter()
+                .filter(|(_, count)| identity(*count) >= identity_threshold)
+                .min_by_key(|(cluster, _)| *cluster)
+                .map(|(cluster, _)| cluster),
+        };
+
+        match member_of {
+            Some(cluster) => clusters[cluster].push(i),
+            None => {
+                for kmer in kmers {
+                    index.entry(kmer).or_default().push(clusters.len());
+                }
+                clusters.push(vec![i]);
+            }
+        }
+    }
+
+    clusters
+        .into_iter()
+        .map(|cluster| cluster.into_iter().map(|i| proteins[i].clone()).collect())
+        .collect()
+}
+
+/// Longest protein of each cluster, the first one on ties
+#[pyfunction]
+pub fn select_representatives(clusters: Vec<Vec<(String, String)>>) -> Vec<(String, String)> {
+    clusters
+        .into_iter()
+        .filter_map(|cluster| {
+            cluster
+                .into_iter()
+                .rev()
+                .max_by_key(|(_, sequence)| sequence.len())
+        })
+        .collect()
+}
+
+/// Cluster proteins at a sequence identity and write the representatives as FASTA, with sequences
+/// wrapped at 60 residues
+#[pyfunction]
+pub fn write_nonredundant_fasta(proteins: Vec<(String, String)>, path: &str, identity: f32) -> PyResult<()> {
+    let io_error = |e: std::io::Error| PyValueError::new_err(format!("Could not write {}: {}", path, e));
+    let mut writer = BufWriter::new(File::create(path).map_err(io_error)?);
+
+    let representatives = select_representatives(cluster_sequences_by_identity(proteins, identity));
+    for (accession, sequence) in representatives {
+        writeln!(writer, ">{}", accession).map_err(io_error)?;
+        for line in sequence.as_bytes().chunks(60) {
+            writer.write_all(line).map_err(io_error)?;
+            writer.write_all(b"\n").map_err(io_error)?;
+        }
+    }
+    writer.flush().map_err(io_error)
+}
+
 #[pymodule]
 pub fn fasta(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_class::<PyFasta>()?;
+    m.add_function(wrap_pyfunction!(cluster_sequences_by_identity, m)?)?;
+    m.add_function(wrap_pyfunction!(select_representatives, m)?)?;
+    m.add_function(wrap_pyfunction!(write_nonredundant_fasta, m)?)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fasta_hash_ignores_line_endings_and_blank_lines() {
+        let unix = ">sp|P1|A\nPEPTIDEK\nMKR\n";
+        let windows = ">sp|P1|A\r\nPEPTIDEK\r\n\r\nMKR\r\n";
+        assert_eq!(fasta_hash(unix.lines()), fasta_hash(windows.lines()));
+        assert_ne!(fasta_hash(unix.lines()), fasta_hash(">sp|P1|A\nPEPTIDER\nMKR\n".lines()));
+    }
+
+    #[test]
+    fn fasta_batches_hold_whole_records() {
+        let contents = ">sp|P1|A\r\nPEPTIDEK\r\nMKR\r\n\r\n>sp|P2|B\nSAMPLER\n>sp|P3|C\nLLLK\n";
+        let mut batches = Vec::new();
+        let hash = stream_fasta_batches(std::io::Cursor::new(contents), 10, |batch| batches.push(batch)).unwrap();
+
+        assert_eq!(hash, fasta_hash(contents.lines()));
+        assert_eq!(
+            batches,
+            vec![">sp|P1|A\nPEPTIDEK\nMKR\n", ">sp|P2|B\nSAMPLER\n", ">sp|P3|C\nLLLK\n"]
+        );
+    }
+
+    #[test]
+    fn gzip_fasta_reads_like_the_plain_file() {
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+
+        let contents = ">sp|P1|A\nPEPTIDEK\nMKR\n>sp|P2|B\nSAMPLER\n";
+        let dir = std::env::temp_dir();
+        let plain = dir.join(format!("sagepy_fasta_{}.fa", std::process::id()));
+        let gzip = dir.join(format!("sagepy_fasta_{}.fa.gz", std::process::id()));
+        std::fs::write(&plain, contents).unwrap();
+        // two gzip members, as written by concatenating compressed files
+        let mut compressed = Vec::new();
+        for part in [">sp|P1|A\nPEPTIDEK\nMKR\n", ">sp|P2|B\nSAMPLER\n"] {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(part.as_bytes()).unwrap();
+            compressed.extend(encoder.finish().unwrap());
+        }
+        std::fs::write(&gzip, compressed).unwrap();
+
+        let read = |path: &std::path::Path| PyFasta::read(path.to_str().unwrap(), "rev_".to_string(), false);
+        let (plain_fasta, gzip_fasta) = (read(&plain), read(&gzip));
+        std::fs::remove_file(&plain).unwrap();
+        std::fs::remove_file(&gzip).unwrap();
+
+        let targets = |fasta: &PyFasta| -> Vec<(String, String)> {
+            fasta.inner.targets.iter().map(|(a, s)| (a.to_string(), s.to_string())).collect()
+        };
+        let (plain_fasta, gzip_fasta) = (plain_fasta.unwrap(), gzip_fasta.unwrap());
+        assert_eq!(targets(&gzip_fasta), targets(&plain_fasta));
+        assert_eq!(targets(&gzip_fasta)[1], ("sp|P2|B".to_string(), "SAMPLER".to_string()));
+        assert_eq!(gzip_fasta.fasta_hash, fasta_hash(contents.lines()));
+    }
+
+    #[test]
+    fn identity_clustering_groups_variants_and_fragments_under_the_longest_protein() {
+        let a = "MKTAYIAKQRQISFVKSHFSRQLEERLGLIEVQAPILSRVGDGTQDNLSGAEKAVQVKVKALPDAQFEVV";
+        // a single substitution keeps 61 of 66 5-mers, an estimated identity of about 0.98
+        let variant = format!("{}W{}", &a[..35], &a[36..]);
+        let protein = |accession: &str, sequence: &str| (accession.to_string(), sequence.to_string());
+        let proteins = vec![
+            protein("fragment", &a[..30]),
+            protein("other", "GSHMCCWPNYRDTHEGCMWPYNRHDTGESMCWPYNRHDCT"),
+            protein("a", a),
+            protein("variant", &variant),
+            protein("short", "MK"),
+        ];
+        let accessions = |clusters: &[Vec<(String, String)>]| -> Vec<Vec<String>> {
+            clusters.iter().map(|c| c.iter().map(|(a, _)| a.clone()).collect()).collect()
+        };
+
+        let clusters = cluster_sequences_by_identity(proteins.clone(), 0.95);
+        assert_eq!(
+            accessions(&clusters),
+            vec![vec!["a", "variant", "fragment"], vec!["other"], vec!["short"]]
+        );
+        let strict = cluster_sequences_by_identity(proteins.clone(), 0.99);
+        assert_eq!(
+            accessions(&strict),
+            vec![vec!["a", "fragment"], vec!["variant"], vec!["other"], vec!["short"]]
+        );
+
+        let representatives = select_representatives(clusters);
+        let names: Vec<&str> = representatives.iter().map(|(a, _)| a.as_str()).collect();
+        assert_eq!(names, vec!["a", "other", "short"]);
+
+        let path = std::env::temp_dir().join(format!("sagepy_nonredundant_{}.fasta", std::process::id()));
+        write_nonredundant_fasta(proteins, path.to_str().unwrap(), 0.95).unwrap();
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let expected = format!(
+            ">a\n{}\n{}\n>other\nGSHMCCWPNYRDTHEGCMWPYNRHDTGESMCWPYNRHDCT\n>short\nMK\n",
+            &a[..60],
+            &a[60..]
+        );
+        assert_eq!(written, expected);
+    }
+}
@@ -1,7 +1,19 @@
+use pyo3::exceptions::PyValueError;
 use pyo3::prelude::*;
 use sage_core::fdr::{Competition};
 use sage_core::database::PeptideIx;
-use crate::py_database::{PyPeptideIx};
+use sage_core::scoring::Feature;
+use crate::py_database::{PyIndexedDatabase, PyPeptideIx};
+use crate::py_lfq::peptide_proteins;
+use crate::py_retention_alignment::splitmix64;
+use crate::py_scoring::{check_peptide_indices, feature_values, solve_linear, PyFeature, FEATURE_NAMES};
+use crate::py_utility::median;
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::hash::Hash;
 
 #[pyclass]
 // TODO: Check if it makes sense to tie this to PeptideIx
@@ -66,8 +78,1895 @@ pub fn py_picked_precursor(peaks: &PyDict) -> usize {
 }
 */
 
+fn check_inputs(scores: &[f64], is_decoy: &[bool]) -> PyResult<()> {
+    if scores.len() != is_decoy.len() {
+        return Err(PyValueError::new_err(format!(
+            "scores and is_decoy must have the same length, got {} and {}",
+            scores.len(),
+            is_decoy.len()
+        )));
+    }
+    Ok(())
+}
+
+/// Indices sorted by descending score
+fn order_by_score(scores: &[f64]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..scores.len()).collect();
+    order.sort_by(|&a, &b| scores[b].total_cmp(&scores[a]));
+    order
+}
+
+/// Target-decoy q-values: the FDR at a score threshold is estimated as decoys / targets above it
+fn tda_q_values(scores: &[f64], is_decoy: &[bool]) -> Vec<f64> {
+    scaled_tda_q_values(scores, is_decoy, 1.0)
+}
+
+/// Target-decoy q-values with the FDR estimate decoys / targets scaled by pi0
+fn scaled_tda_q_values(scores: &[f64], is_decoy: &[bool], pi0: f64) -> Vec<f64> {
+    q_values_by(scores, is_decoy, |decoys, targets| pi0 * decoys as f64 / targets.max(1) as f64)
+}
+
+/// Q-values under target-decoy competition, where each spectrum contributes a single winner: the
+/// FDR at a score threshold is estimated as decoy winners / all winners above it
+pub(crate) fn competition_q_values(scores: &[f64], is_decoy: &[bool]) -> Vec<f64> {
+    q_values_by(scores, is_decoy, |decoys, targets| decoys as f64 / (decoys + targets).max(1) as f64)
+}
+
+/// Target-decoy competition: only the best scoring (hyperscore) PSM of each spectrum (file_id,
+/// spec_id) is kept, target or decoy. The winners are returned by descending hyperscore
+pub(crate) fn competition_winners<T>(psms: Vec<T>, feature: impl Fn(&T) -> &Feature) -> Vec<T> {
+    let mut best: HashMap<(usize, String), T> = HashMap::new();
+    for psm in psms {
+        let key = (feature(&psm).file_id, feature(&psm).spec_id.clone());
+        match best.get(&key) {
+            Some(current) if feature(current).hyperscore >= feature(&psm).hyperscore => {}
+            _ => {
+                best.insert(key, psm);
+            }
+        }
+    }
+    let mut winners: Vec<T> = best.into_values().collect();
+    winners.sort_by(|a, b| feature(b).hyperscore.total_cmp(&feature(a).hyperscore));
+    winners
+}
+
+/// Q-values from the FDR estimate `fdr(decoys, targets)` at each score threshold, the q-value of a
+/// score is the minimal FDR at which it is still accepted
+fn q_values_by(scores: &[f64], is_decoy: &[bool], fdr: impl Fn(usize, usize) -> f64) -> Vec<f64> {
+    let order = order_by_score(scores);
+    let mut fdrs = Vec::with_capacity(order.len());
+    let (mut decoys, mut targets) = (0usize, 0usize);
+    for &i in &order {
+        if is_decoy[i] {
+            decoys += 1;
+        } else {
+            targets += 1;
+        }
+        fdrs.push(fdr(decoys, targets));
+    }
+
+    let mut q_values = vec![1.0; scores.len()];
+    let mut q_min = 1.0f64;
+    for (&i, fdr) in order.iter().zip(fdrs.iter()).rev() {
+        q_min = q_min.min(*fdr);
+        q_values[i] = q_min;
+    }
+    q_values
+}
+
+/// Default lambda grid of the pi0 estimation, as in the R qvalue package
+fn default_lambda_range() -> Vec<f64> {
+    (1..=19).map(|i| i as f64 * 0.05).collect()
+}
+
+/// pi0(lambda) = #{p >= lambda} / (m (1 - lambda)) of p-values at each lambda
+fn pi0_curve(p_values: &[f64], lambda_range: &[f64]) -> Vec<f64> {
+    let m = p_values.len().max(1) as f64;
+    lambda_range
+        .iter()
+        .map(|&lambda| {
+            let above = p_values.iter().filter(|&&p| p >= lambda).count() as f64;
+            above / (m * (1.0 - lambda))
+        })
+        .collect()
+}
+
+/// Degrees of freedom of the pi0(lambda) smoothing spline, as in the R qvalue package
+const PI0_SPLINE_DF: f64 = 3.0;
+
+/// Estimate pi0 from p-values: pi0(lambda) is computed over the lambda grid, smoothed by a cubic
+/// smoothing spline with 3 degrees of freedom and evaluated at the largest lambda, like `pi0est`
+/// of the R qvalue package
+fn storey_pi0(p_values: &[f64], lambda_range: &[f64]) -> f64 {
+    smoothed_pi0(lambda_range, &pi0_curve(p_values, lambda_range))
+}
+
+/// pi0 curve smoothed by a cubic smoothing spline with `PI0_SPLINE_DF` degrees of freedom,
+/// evaluated at the largest lambda
+fn smoothed_pi0(lambda_range: &[f64], curve: &[f64]) -> f64 {
+    let mut points: Vec<(f64, f64)> = lambda_range.iter().copied().zip(curve.iter().copied()).collect();
+    points.sort_by(|a, b| a.0.total_cmp(&b.0));
+    // repeated lambdas enter the spline once, with their mean pi0 and their count as weight
+    let mut x: Vec<f64> = Vec::new();
+    let mut y: Vec<f64> = Vec::new();
+    let mut w: Vec<f64> = Vec::new();
+    for (lambda, pi0) in points {
+        match x.last() {
+            Some(last) if *last == lambda => {
+                let k = y.len() - 1;
+                y[k] = (y[k] * w[k] + pi0) / (w[k] + 1.0);
+                w[k] += 1.0;
+            }
+            _ => {
+                x.push(lambda);
+                y.push(pi0);
+                w.push(1.0);
+            }
+        }
+    }
+
+    // too few lambdas to smooth, use the estimate at the largest lambda
+    let pi0 = match x.len() as f64 > PI0_SPLINE_DF {
+        true => smoothing_spline(&x, &y, &w, PI0_SPLINE_DF).and_then(|fitted| fitted.last().copied()),
+        false => None,
+    };
+    pi0.or_else(|| y.last().copied())
+        .unwrap_or(1.0)
+        .clamp(f64::MIN_POSITIVE, 1.0)
+}
+
+/// Penalty matrix K = Q R^-1 Q^T of the natural cubic smoothing spline through the ascending
+/// knots `x`, such that the roughness of the spline interpolating g is g^T K g (Green &
+/// Silverman, 1994)
+fn spline_penalty(x: &[f64]) -> Option<Vec<Vec<f64>>> {
+    let n = x.len();
+    let h: Vec<f64> = x.windows(2).map(|w| w[1] - w[0]).collect();
+    let mut q = vec![vec![0.0; n - 2]; n];
+    let mut r = vec![vec![0.0; n - 2]; n - 2];
+    for c in 0..n - 2 {
+        let j = c + 1;
+        q[j - 1][c] = 1.0 / h[j - 1];
+        q[j][c] = -1.0 / h[j - 1] - 1.0 / h[j];
+        q[j + 1][c] = 1.0 / h[j];
+        r[c][c] = (h[j - 1] + h[j]) / 3.0;
+        if c + 1 < n - 2 {
+            r[c][c + 1] = h[j] / 6.0;
+            r[c + 1][c] = h[j] / 6.0;
+        }
+    }
+    // columns of R^-1 Q^T
+    let z = q
+        .iter()
+        .map(|row| solve_linear(r.clone(), row.clone()))
+        .collect::<Option<Vec<_>>>()?;
+    Some(
+        q.iter()
+            .map(|row| z.iter().map(|col| row.iter().zip(col).map(|(a, b)| a * b).sum()).collect())
+            .collect(),
+    )
+}
+
+/// Fitted values and trace of the smoother matrix (W + alpha K)^-1 W of the smoothing spline
+/// with penalty weight `alpha`
+fn fit_smoothing_spline(k: &[Vec<f64>], y: &[f64], w: &[f64], alpha: f64) -> Option<(Vec<f64>, f64)> {
+    let n = y.len();
+    let system: Vec<Vec<f64>> = (0..n)
+        .map(|i| (0..n).map(|j| alpha * k[i][j] + if i == j { w[i] } else { 0.0 }).collect())
+        .collect();
+    let fitted = solve_linear(system.clone(), y.iter().zip(w).map(|(y, w)| y * w).collect())?;
+    let mut trace = 0.0;
+    for i in 0..n {
+        let mut e = vec![0.0; n];
+        e[i] = w[i];
+        trace += solve_linear(system.clone(), e)?[i];
+    }
+    Some((fitted, trace))
+}
+
+/// Cubic smoothing spline through the ascending, distinct `x`, fitted values at `x`. The penalty
+/// weight is chosen such that the smoother has `df` equivalent degrees of freedom (its trace),
+/// which is how `smooth.spline(x, y, df = df)` of R picks it
+fn smoothing_spline(x: &[f64], y: &[f64], w: &[f64], df: f64) -> Option<Vec<f64>> {
+    if x.len() < 3 {
+        return None;
+    }
+    let k = spline_penalty(x)?;
+    // the trace falls from n (interpolation) to 2 (straight line) with the penalty weight,
+    // bisect on its logarithm relative to the ratio of the weight and penalty scales
+    let scale = w.iter().sum::<f64>() / (0..x.len()).map(|i| k[i][i]).sum::<f64>();
+    let alpha = |t: f64| scale * 10f64.powf(t);
+    let (mut lo, mut hi) = (-10.0f64, 10.0f64);
+    for _ in 0..60 {
+        let mid = 0.5 * (lo + hi);
+        let (_, trace) = fit_smoothing_spline(&k, y, w, alpha(mid))?;
+        match trace > df {
+            true => lo = mid,
+            false => hi = mid,
+        }
+    }
+    fit_smoothing_spline(&k, y, w, alpha(0.5 * (lo + hi))).map(|(fitted, _)| fitted)
+}
+
+/// Empirical p-values of the target scores against the decoy score distribution, with the target
+/// indices ordered by descending score, i.e. ascending p-value
+fn target_p_values(scores: &[f64], is_decoy: &[bool]) -> (Vec<usize>, Vec<f64>) {
+    let mut decoy_scores: Vec<f64> = scores
+        .iter()
+        .zip(is_decoy.iter())
+        .filter(|(_, d)| **d)
+        .map(|(s, _)| *s)
+        .collect();
+    decoy_scores.sort_by(|a, b| a.total_cmp(b));
+    let num_decoys = decoy_scores.len() as f64;
+
+    let targets: Vec<usize> = order_by_score(scores)
+        .into_iter()
+        .filter(|&i| !is_decoy[i])
+        .collect();
+    let p_values: Vec<f64> = targets
+        .iter()
+        .map(|&i| {
+            let at_least = decoy_scores.len() - decoy_scores.partition_point(|d| *d < scores[i]);
+            (at_least as f64 + 1.0) / (num_decoys + 1.0)
+        })
+        .collect();
+    (targets, p_values)
+}
+
+/// pi0 and q-values of ascending p-values, pi0 times the Benjamini-Hochberg adjusted p-values
+/// capped at 1, as computed by `qvalue` of the R qvalue package
+fn storey_q_values_of(p_values: &[f64], lambda_range: &[f64]) -> (f64, Vec<f64>) {
+    let pi0 = storey_pi0(p_values, lambda_range);
+    let m = p_values.len() as f64;
+    let mut q_values = vec![1.0; p_values.len()];
+    let mut q_min = 1.0f64;
+    for (rank, p) in p_values.iter().enumerate().rev() {
+        q_min = q_min.min(m * p / (rank + 1) as f64);
+        q_values[rank] = pi0 * q_min;
+    }
+    (pi0, q_values)
+}
+
+/// Storey-Tibshirani q-values (Storey & Tibshirani, 2002) of target scores. Target p-values are
+/// estimated from the decoy score distribution, pi0 from these p-values over `lambda_range`
+/// (0.05..0.95 if empty), and q-values are pi0-scaled Benjamini-Hochberg adjusted p-values,
+/// monotone in score. Decoys are assigned a q-value of 1
+#[pyfunction]
+pub fn storey_qvalues(scores: Vec<f64>, is_decoy: Vec<bool>, lambda_range: Vec<f64>) -> PyResult<Vec<f64>> {
+    check_inputs(&scores, &is_decoy)?;
+    if lambda_range.iter().any(|l| !(0.0..1.0).contains(l)) {
+        return Err(PyValueError::new_err("lambda_range values must be in [0, 1)"));
+    }
+    let lambda_range = if lambda_range.is_empty() {
+        default_lambda_range()
+    } else {
+        lambda_range
+    };
+
+    let (targets, p_values) = target_p_values(&scores, &is_decoy);
+    let mut q_values = vec![1.0; scores.len()];
+    if p_values.is_empty() {
+        return Ok(q_values);
+    }
+
+    let (_, target_q_values) = storey_q_values_of(&p_values, &lambda_range);
+    for (&i, q) in targets.iter().zip(target_q_values) {
+        q_values[i] = q;
+    }
+
+    Ok(q_values)
+}
+
+/// q-values of scored target and decoy PSMs, `method` is "tda" (target-decoy) or "storey"
+/// (Storey-Tibshirani with the default lambda grid)
+#[pyfunction]
+pub fn calculate_fdr(scores: Vec<f64>, is_decoy: Vec<bool>, method: &str) -> PyResult<Vec<f64>> {
+    check_inputs(&scores, &is_decoy)?;
+    match method.to_lowercase().as_str() {
+        "tda" => Ok(tda_q_values(&scores, &is_decoy)),
+        "storey" => storey_qvalues(scores, is_decoy, Vec::new()),
+        _ => Err(PyValueError::new_err(format!(
+            "Invalid FDR method: {}, allowed values are: tda, storey",
+            method
+        ))),
+    }
+}
+
+/// Number of bootstrap samples of the "bootstrap" pi0 estimate
+const PI0_BOOTSTRAP_SAMPLES: usize = 100;
+/// Seed of the pi0 bootstrap, fixed to keep estimates reproducible
+const PI0_BOOTSTRAP_SEED: u64 = 0x5eed_0000_0000_f0d0;
+
+/// Resample values with replacement
+fn bootstrap_sample(values: &[f64], state: &mut u64) -> Vec<f64> {
+    (0..values.len())
+        .map(|_| values[(splitmix64(state) % values.len() as u64) as usize])
+        .collect()
+}
+
+fn lambda_range_or_default(lambdas: Vec<f64>) -> PyResult<Vec<f64>> {
+    if lambdas.iter().any(|l| !(0.0..1.0).contains(l)) {
+        return Err(PyValueError::new_err("lambda values must be in [0, 1)"));
+    }
+    Ok(if lambdas.is_empty() { default_lambda_range() } else { lambdas })
+}
+
+/// Estimate pi0, the proportion of incorrect target PSMs, from the target p-values against the
+/// decoy score distribution. Returns pi0 and the raw pi0(lambda) at each lambda (0.05..0.95 if
+/// empty). With method "spline" the pi0(lambda) curve is smoothed by a cubic smoothing spline
+/// with 3 degrees of freedom and evaluated at the largest lambda (as the R qvalue package), with
+/// "bootstrap" pi0(lambda) of the lambda minimising the bootstrap mean squared error to the
+/// minimum of the curve is used (Storey et al., 2004)
+#[pyfunction]
+pub fn estimate_pi0(
+    scores: Vec<f64>,
+    is_decoy: Vec<bool>,
+    lambdas: Vec<f64>,
+    method: &str,
+) -> PyResult<(f64, Vec<f64>)> {
+    check_inputs(&scores, &is_decoy)?;
+    let lambdas = lambda_range_or_default(lambdas)?;
+    let (_, p_values) = target_p_values(&scores, &is_decoy);
+    let curve = pi0_curve(&p_values, &lambdas);
+    if p_values.is_empty() {
+        return Ok((1.0, curve));
+    }
+
+    let pi0 = match method.to_lowercase().as_str() {
+        "spline" => smoothed_pi0(&lambdas, &curve),
+        "bootstrap" => {
+            let min_pi0 = curve.iter().copied().fold(f64::INFINITY, f64::min);
+            let mut mse = vec![0.0; lambdas.len()];
+            let mut state = PI0_BOOTSTRAP_SEED;
+            for _ in 0..PI0_BOOTSTRAP_SAMPLES {
+                let sample = pi0_curve(&bootstrap_sample(&p_values, &mut state), &lambdas);
+                for (e, pi0) in mse.iter_mut().zip(sample.iter()) {
+                    *e += (pi0 - min_pi0).powi(2);
+                }
+            }
+            let best = (0..lambdas.len())
+                .min_by(|&a, &b| mse[a].total_cmp(&mse[b]))
+                .unwrap_or(0);
+            curve[best].clamp(f64::MIN_POSITIVE, 1.0)
+        }
+        _ => {
+            return Err(PyValueError::new_err(format!(
+                "Invalid pi0 method: {}, allowed values are: spline, bootstrap",
+                method
+            )))
+        }
+    };
+    Ok((pi0, curve))
+}
+
+/// Bootstrap percentile confidence interval of pi0(lambda): (2.5th percentile, estimate, 97.5th
+/// percentile) of `n_bootstrap` resamples of the target p-values
+#[pyfunction]
+pub fn pi0_confidence_interval(
+    scores: Vec<f64>,
+    is_decoy: Vec<bool>,
+    lambda: f64,
+    n_bootstrap: usize,
+) -> PyResult<(f64, f64, f64)> {
+    check_inputs(&scores, &is_decoy)?;
+    let lambdas = lambda_range_or_default(vec![lambda])?;
+    let (_, p_values) = target_p_values(&scores, &is_decoy);
+    if p_values.is_empty() {
+        return Ok((1.0, 1.0, 1.0));
+    }
+
+    let estimate = pi0_curve(&p_values, &lambdas)[0].min(1.0);
+    let mut state = PI0_BOOTSTRAP_SEED;
+    let mut samples: Vec<f64> = (0..n_bootstrap.max(1))
+        .map(|_| pi0_curve(&bootstrap_sample(&p_values, &mut state), &lambdas)[0].min(1.0))
+        .collect();
+    samples.sort_by(|a, b| a.total_cmp(b));
+    let percentile = |q: f64| samples[((samples.len() - 1) as f64 * q).round() as usize];
+    Ok((percentile(0.025), estimate, percentile(0.975)))
+}
+
+/// Target-decoy spectrum q-values of PSMs by discriminant score, with the decoy / target FDR
+/// estimate scaled by a given pi0 (e.g. of `estimate_pi0`)
+#[pyfunction]
+pub fn calculate_q_values_with_pi0(psms: Vec<PyFeature>, pi0: f64) -> PyResult<Vec<PyFeature>> {
+    if !(0.0..=1.0).contains(&pi0) {
+        return Err(PyValueError::new_err(format!("pi0 must be in [0, 1], got {}", pi0)));
+    }
+    let mut psms = psms;
+    let scores: Vec<f64> = psms.iter().map(|p| p.inner.discriminant_score as f64).collect();
+    let is_decoy: Vec<bool> = psms.iter().map(|p| p.inner.label == -1).collect();
+    let q_values = scaled_tda_q_values(&scores, &is_decoy, pi0);
+    for (psm, q) in psms.iter_mut().zip(q_values) {
+        psm.inner.spectrum_q = q as f32;
+    }
+    Ok(psms)
+}
+
+/// Features derived from the scores themselves, excluded from training
+const SCORE_FEATURES: [&str; 4] = ["posterior_error", "spectrum_q", "peptide_q", "protein_q"];
+
+/// q-value threshold of the target PSMs used as positive training examples
+const TRAINING_FDR: f64 = 0.01;
+const SVM_EPOCHS: usize = 100;
+const SVM_LEARNING_RATE: f64 = 0.1;
+const SVM_LAMBDA: f64 = 1e-4;
+
+/// Standardised training features of all PSMs, followed by a constant bias term
+fn training_matrix(psms: &[PyFeature]) -> Vec<Vec<f64>> {
+    let keep: Vec<usize> = (0..FEATURE_NAMES.len())
+        .filter(|&i| !SCORE_FEATURES.contains(&FEATURE_NAMES[i]))
+        .collect();
+    let mut rows: Vec<Vec<f64>> = psms
+        .par_iter()
+        .map(|psm| {
+            let values = feature_values(psm);
+            keep.iter().map(|&i| values[i]).collect()
+        })
+        .collect();
+
+    let n = rows.len().max(1) as f64;
+    for j in 0..keep.len() {
+        let mean = rows.iter().map(|r| r[j]).sum::<f64>() / n;
+        let sd = (rows.iter().map(|r| (r[j] - mean).powi(2)).sum::<f64>() / n).sqrt();
+        for row in rows.iter_mut() {
+            row[j] = if sd > 0.0 { (row[j] - mean) / sd } else { 0.0 };
+        }
+    }
+    rows.iter_mut().for_each(|row| row.push(1.0));
+    rows
+}
+
+/// Linear SVM (L2-regularised hinge loss) trained by batch sub-gradient descent, classes are
+/// weighted inversely to their size
+fn train_linear_svm(x: &[Vec<f64>], examples: &[(usize, f64)]) -> Vec<f64> {
+    let dim = x.first().map_or(0, |r| r.len());
+    let positives = examples.iter().filter(|(_, y)| *y > 0.0).count().max(1) as f64;
+    let negatives = examples.iter().filter(|(_, y)| *y < 0.0).count().max(1) as f64;
+    let n = examples.len() as f64;
+
+    let mut w = vec![0.0; dim];
+    for epoch in 0..SVM_EPOCHS {
+        let gradient = examples
+            .par_iter()
+            .filter_map(|&(i, y)| {
+                let margin = y * x[i].iter().zip(w.iter()).map(|(a, b)| a * b).sum::<f64>();
+                (margin < 1.0).then(|| {
+                    let c = if y > 0.0 { n / (2.0 * positives) } else { n / (2.0 * negatives) };
+                    x[i].iter().map(|v| -c * y * v).collect::<Vec<f64>>()
+                })
+            })
+            .reduce(
+                || vec![0.0; dim],
+                |a, b| a.iter().zip(b.iter()).map(|(a, b)| a + b).collect(),
+            );
+
+        let rate = SVM_LEARNING_RATE / (1.0 + epoch as f64);
+        for (wj, gj) in w.iter_mut().zip(gradient.iter()) {
+            *wj -= rate * (SVM_LAMBDA * *wj + gj / n);
+        }
+    }
+    w
+}
+
+/// Posterior error probabilities as the local decoy / target ratio in a sliding window of
+/// neighbouring scores, made monotone in score
+fn posterior_error_probabilities(scores: &[f64], is_decoy: &[bool]) -> Vec<f64> {
+    let order = order_by_score(scores);
+    let n = order.len();
+    let half_window = (((n as f64).sqrt() as usize).max(10)) / 2;
+
+    let mut decoys_before = vec![0usize; n + 1];
+    for (k, &i) in order.iter().enumerate() {
+        decoys_before[k + 1] = decoys_before[k] + is_decoy[i] as usize;
+    }
+
+    let mut peps = vec![1.0; n];
+    let mut running_max = 0.0f64;
+    for (k, &i) in order.iter().enumerate() {
+        let lo = k.saturating_sub(half_window);
+        let hi = (k + half_window + 1).min(n);
+        let decoys = (decoys_before[hi] - decoys_before[lo]) as f64;
+        let targets = (hi - lo) as f64 - decoys;
+        let local = (decoys / targets.max(1.0)).min(1.0);
+        // lower scores never have a lower error probability
+        running_max = running_max.max(local);
+        peps[i] = running_max;
+    }
+    peps
+}
+
+/// Number of cross-validation folds of the semi-supervised re-scoring
+const CV_FOLDS: usize = 3;
+
+/// Cross-validation fold of each PSM, spectra (file_id, spec_id) are assigned to folds in turn
+/// so that all PSMs of a spectrum share a fold
+fn cv_folds(psms: &[PyFeature]) -> Vec<usize> {
+    let mut spectra: HashMap<(usize, &str), usize> = HashMap::new();
+    psms.iter()
+        .map(|p| {
+            let next = spectra.len();
+            *spectra.entry((p.inner.file_id, p.inner.spec_id.as_str())).or_insert(next) % CV_FOLDS
+        })
+        .collect()
+}
+
+/// Train a linear SVM on the PSMs `train`, starting from the `initial` scores and repeatedly
+/// separating confident targets (q <= 0.01) from decoys. None if no target was ever confident
+fn train_fold(x: &[Vec<f64>], train: &[usize], is_decoy: &[bool], initial: &[f64], num_iterations: u32) -> Option<Vec<f64>> {
+    let decoys: Vec<bool> = train.iter().map(|&i| is_decoy[i]).collect();
+    let mut scores: Vec<f64> = train.iter().map(|&i| initial[i]).collect();
+    let mut model = None;
+    for _ in 0..num_iterations {
+        let q_values = tda_q_values(&scores, &decoys);
+        let examples: Vec<(usize, f64)> = train
+            .iter()
+            .enumerate()
+            .filter_map(|(k, &i)| match (decoys[k], q_values[k] <= TRAINING_FDR) {
+                (true, _) => Some((i, -1.0)),
+                (false, true) => Some((i, 1.0)),
+                (false, false) => None,
+            })
+            .collect();
+        if examples.iter().all(|(_, y)| *y < 0.0) {
+            break;
+        }
+
+        let w = train_linear_svm(x, &examples);
+        scores = train.par_iter().map(|&i| dot(&x[i], &w)).collect();
+        model = Some(w);
+    }
+    model
+}
+
+fn dot(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b.iter()).map(|(a, b)| a * b).sum()
+}
+
+/// Map scores so that the lowest target score at the training FDR of `reference` becomes 0 and
+/// the median decoy score -1, making the scores of models trained on different folds comparable
+fn normalize_scores(scores: &mut [f64], reference: &[f64], is_decoy: &[bool]) {
+    let q_values = tda_q_values(reference, is_decoy);
+    let threshold = (0..reference.len())
+        .filter(|&i| !is_decoy[i] && q_values[i] <= TRAINING_FDR)
+        .map(|i| reference[i])
+        .min_by(|a, b| a.total_cmp(b));
+    let decoy_median = median((0..reference.len()).filter(|&i| is_decoy[i]).map(|i| reference[i]));
+    if let (Some(threshold), Some(decoy_median)) = (threshold, decoy_median) {
+        if threshold > decoy_median {
+            scores.iter_mut().for_each(|s| *s = (*s - threshold) / (threshold - decoy_median));
+        }
+    }
+}
+
+/// Percolator-like semi-supervised re-scoring with 3-fold cross-validation: for each fold, a
+/// linear SVM is trained on the PSMs of the other folds, starting from the hyperscore and
+/// repeatedly separating confident targets (q <= 0.01) from decoys, and scores the held-out fold.
+/// Fold scores are normalised to a common scale (see `normalize_scores`). Returns the PSMs with
+/// their re-score and updated spectrum q-value and posterior error. The discriminant score is
+/// left unchanged
+#[pyfunction]
+pub fn semi_supervised_fdr(
+    psms: Vec<PyFeature>,
+    num_iterations: u32,
+    num_threads: usize,
+) -> PyResult<Vec<PyFeature>> {
+    let mut psms = psms;
+    let is_decoy: Vec<bool> = psms.iter().map(|p| p.inner.label == -1).collect();
+    if !is_decoy.iter().any(|d| *d) {
+        return Err(PyValueError::new_err("semi-supervised re-scoring requires decoy PSMs"));
+    }
+
+    let pool = ThreadPoolBuilder::new()
+        .num_threads(num_threads)
+        .build()
+        .unwrap();
+
+    let scores = pool.install(|| {
+        let x = training_matrix(&psms);
+        let initial: Vec<f64> = psms.iter().map(|p| p.inner.hyperscore).collect();
+        let folds = cv_folds(&psms);
+        let mut scores = vec![0.0; psms.len()];
+        for fold in 0..CV_FOLDS {
+            let (test, train): (Vec<usize>, Vec<usize>) = (0..psms.len()).partition(|&i| folds[i] == fold);
+            let model = train_fold(&x, &train, &is_decoy, &initial, num_iterations);
+            let score = |i: usize| model.as_ref().map_or(initial[i], |w| dot(&x[i], w));
+
+            let reference: Vec<f64> = train.iter().map(|&i| score(i)).collect();
+            let reference_decoys: Vec<bool> = train.iter().map(|&i| is_decoy[i]).collect();
+            let mut held_out: Vec<f64> = test.iter().map(|&i| score(i)).collect();
+            normalize_scores(&mut held_out, &reference, &reference_decoys);
+            for (&i, s) in test.iter().zip(held_out) {
+                scores[i] = s;
+            }
+        }
+        scores
+    });
+
+    let q_values = tda_q_values(&scores, &is_decoy);
+    let peps = posterior_error_probabilities(&scores, &is_decoy);
+    for (i, psm) in psms.iter_mut().enumerate() {
+        psm.inner.spectrum_q = q_values[i] as f32;
+        psm.inner.posterior_error = peps[i] as f32;
+        psm.re_score = Some(scores[i]);
+    }
+
+    Ok(psms)
+}
+
+/// Occam's razor: each peptide is assigned to the protein with the most unique peptides (those
+/// mapping to no other protein) among `peptide_proteins`, ties are broken by the most distinct
+/// peptides and then by the alphabetically first accession
+fn assign_razor<K: Clone + Eq + Hash>(peptide_proteins: &HashMap<K, Vec<String>>) -> HashMap<K, String> {
+    // (unique, distinct) peptides of each protein
+    let mut counts: HashMap<&str, (usize, usize)> = HashMap::new();
+    for proteins in peptide_proteins.values() {
+        let proteins: HashSet<&String> = proteins.iter().collect();
+        for protein in &proteins {
+            let count = counts.entry(protein.as_str()).or_default();
+            count.0 += (proteins.len() == 1) as usize;
+            count.1 += 1;
+        }
+    }
+    peptide_proteins
+        .iter()
+        .filter_map(|(peptide, proteins)| {
+            let best = proteins
+                .iter()
+                .max_by(|a, b| counts[a.as_str()].cmp(&counts[b.as_str()]).then_with(|| b.cmp(a)))?;
+            Some((peptide.clone(), best.clone()))
+        })
+        .collect()
+}
+
+/// Razor protein of each peptide identified at `peptide_q_cutoff`, decoy proteins carry the
+/// decoy tag. A shared peptide is assigned to the protein with the most unique identified
+/// peptides (see `assign_razor`)
+fn razor_proteins(
+    psms: &[PyFeature],
+    db: &PyIndexedDatabase,
+    peptide_q_cutoff: f32,
+) -> HashMap<PeptideIx, (String, bool)> {
+    let peptides: HashSet<PeptideIx> = psms
+        .iter()
+        .filter(|p| p.inner.peptide_q <= peptide_q_cutoff)
+        .map(|p| p.inner.peptide_idx)
+        .collect();
+
+    assign_razor(&peptide_proteins(db, &peptides, false))
+        .into_iter()
+        .map(|(idx, protein)| {
+            let decoy = db.inner[idx].decoy;
+            let tag = &db.inner.decoy_tag;
+            let protein = match decoy && !protein.starts_with(tag.as_str()) {
+                true => format!("{}{}", tag, protein),
+                false => protein,
+            };
+            (idx, (protein, decoy))
+        })
+        .collect()
+}
+
+/// Razor protein of each identified peptide (stripped sequence) of a protein -> peptides map,
+/// a shared peptide is assigned to the protein with the most unique identified peptides, ties
+/// are broken by the most identified peptides, then alphabetically. Peptides without PSM or
+/// missing from the map are left out
+#[pyfunction]
+pub fn razor_protein_assignment(
+    psms: Vec<PyFeature>,
+    db: &PyIndexedDatabase,
+    protein_peptide_map: HashMap<String, Vec<String>>,
+) -> PyResult<HashMap<String, String>> {
+    check_peptide_indices(&db.inner, &psms)?;
+    let identified: HashSet<String> = psms
+        .iter()
+        .map(|p| String::from_utf8_lossy(&db.inner[p.inner.peptide_idx].sequence).to_string())
+        .collect();
+
+    let mut peptide_proteins: HashMap<String, Vec<String>> = HashMap::new();
+    for (protein, peptides) in &protein_peptide_map {
+        for peptide in peptides.iter().filter(|p| identified.contains(*p)) {
+            peptide_proteins
+                .entry(peptide.clone())
+                .or_default()
+                .push(protein.clone());
+        }
+    }
+    Ok(assign_razor(&peptide_proteins))
+}
+
+/// Minimal set of proteins explaining the peptides of all target PSMs, found greedily by
+/// repeatedly taking the protein explaining the most unexplained peptides (ties broken
+/// alphabetically). Returned sorted by accession
+#[pyfunction]
+pub fn parsimony_protein_set(psms: Vec<PyFeature>, db: &PyIndexedDatabase) -> PyResult<Vec<String>> {
+    check_peptide_indices(&db.inner, &psms)?;
+    let mut unexplained: HashSet<PeptideIx> = psms
+        .iter()
+        .filter(|p| p.inner.label != -1)
+        .map(|p| p.inner.peptide_idx)
+        .collect();
+
+    let mut protein_peptides: HashMap<String, HashSet<PeptideIx>> = HashMap::new();
+    for (idx, proteins) in peptide_proteins(db, &unexplained, false) {
+        for protein in proteins {
+            protein_peptides.entry(protein).or_default().insert(idx);
+        }
+    }
+
+    let mut selected = Vec::new();
+    while !unexplained.is_empty() {
+        let best = protein_peptides
+            .iter()
+            .map(|(protein, peptides)| (protein, peptides.intersection(&unexplained).count()))
+            .filter(|(_, count)| *count > 0)
+            .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(a.0)));
+        let Some((protein, _)) = best else {
+            break;
+        };
+        unexplained.retain(|idx| !protein_peptides[protein].contains(idx));
+        selected.push(protein.clone());
+    }
+    selected.sort();
+    Ok(selected)
+}
+
+/// Protein q-values by target-decoy competition on razor proteins, a protein is scored by the
+/// best discriminant score of its razor peptides
+fn razor_protein_q_values(
+    psms: &[PyFeature],
+    razor: &HashMap<PeptideIx, (String, bool)>,
+) -> HashMap<String, f32> {
+    let mut proteins: HashMap<&str, (f64, bool)> = HashMap::new();
+    for psm in psms {
+        let Some((protein, decoy)) = razor.get(&psm.inner.peptide_idx) else {
+            continue;
+        };
+        let entry = proteins
+            .entry(protein.as_str())
+            .or_insert((f64::NEG_INFINITY, *decoy));
+        entry.0 = entry.0.max(psm.inner.discriminant_score as f64);
+    }
+
+    let (names, (scores, is_decoy)): (Vec<&str>, (Vec<f64>, Vec<bool>)) = proteins.into_iter().unzip();
+    let q_values = tda_q_values(&scores, &is_decoy);
+    names
+        .into_iter()
+        .zip(q_values)
+        .map(|(name, q)| (name.to_string(), q as f32))
+        .collect()
+}
+
+/// Protein-level FDR with razor protein assignment: PSMs of peptides passing `peptide_q_cutoff`
+/// are grouped by the razor protein of their peptide, returns the q-value of each protein
+/// (decoy proteins included, prefixed with the decoy tag)
+#[pyfunction]
+pub fn protein_fdr(psms: Vec<PyFeature>, db: &PyIndexedDatabase, peptide_q_cutoff: f32) -> PyResult<HashMap<String, f32>> {
+    check_peptide_indices(&db.inner, &psms)?;
+    let razor = razor_proteins(&psms, db, peptide_q_cutoff);
+    Ok(razor_protein_q_values(&psms, &razor))
+}
+
+/// Set `protein_q_razor` of each PSM to the q-value of its peptide's razor protein (see
+/// `protein_fdr`), PSMs of peptides failing `peptide_q_cutoff` get 1.0
+#[pyfunction]
+pub fn annotate_protein_q_razor(psms: Vec<PyFeature>, db: &PyIndexedDatabase, peptide_q_cutoff: f32) -> PyResult<Vec<PyFeature>> {
+    check_peptide_indices(&db.inner, &psms)?;
+    let mut psms = psms;
+    let razor = razor_proteins(&psms, db, peptide_q_cutoff);
+    let q_values = razor_protein_q_values(&psms, &razor);
+    for psm in psms.iter_mut() {
+        psm.protein_q_razor = razor
+            .get(&psm.inner.peptide_idx)
+            .and_then(|(protein, _)| q_values.get(protein))
+            .copied()
+            .unwrap_or(1.0);
+    }
+    Ok(psms)
+}
+
+#[derive(Clone, Copy)]
+pub enum GroupingStrategy {
+    ByCharge,
+    /// Peptide length bins of the given width
+    ByLength(usize),
+    /// Modified and unmodified peptides
+    ByModification,
+}
+
+#[pyclass]
+#[derive(Clone)]
+pub struct PyGroupingStrategy {
+    pub inner: GroupingStrategy,
+}
+
+#[pymethods]
+impl PyGroupingStrategy {
+    #[new]
+    pub fn new(strategy: &str, bin_width: Option<usize>) -> PyResult<Self> {
+        let inner = match strategy.to_lowercase().as_str() {
+            "charge" => GroupingStrategy::ByCharge,
+            "length" => match bin_width.unwrap_or(5) {
+                0 => return Err(PyValueError::new_err("bin_width must be positive")),
+                width => GroupingStrategy::ByLength(width),
+            },
+            "modification" => GroupingStrategy::ByModification,
+            _ => {
+                return Err(PyValueError::new_err(format!(
+                    "Invalid grouping strategy: {}, allowed values are: charge, length, modification",
+                    strategy
+                )))
+            }
+        };
+        Ok(PyGroupingStrategy { inner })
+    }
+
+    #[getter]
+    pub fn strategy(&self) -> String {
+        match self.inner {
+            GroupingStrategy::ByCharge => "charge".to_string(),
+            GroupingStrategy::ByLength(_) => "length".to_string(),
+            GroupingStrategy::ByModification => "modification".to_string(),
+        }
+    }
+
+    #[getter]
+    pub fn bin_width(&self) -> Option<usize> {
+        match self.inner {
+            GroupingStrategy::ByLength(width) => Some(width),
+            _ => None,
+        }
+    }
+}
+
+/// Group of a PSM under `strategy`
+fn fdr_group(psm: &PyFeature, strategy: GroupingStrategy, db: Option<&PyIndexedDatabase>) -> usize {
+    match strategy {
+        GroupingStrategy::ByCharge => psm.inner.charge as usize,
+        GroupingStrategy::ByLength(width) => psm.inner.peptide_len / width,
+        GroupingStrategy::ByModification => db
+            .map(|db| db.inner[psm.inner.peptide_idx].modifications.iter().any(|m| *m != 0.0) as usize)
+            .unwrap_or_default(),
+    }
+}
+
+/// Group-wise FDR control: PSMs are split by `strategy` (charge, peptide length bin or modified
+/// vs unmodified), target-decoy q-values on the discriminant score are computed within each group
+/// and stored as spectrum q-values. Returns the PSMs of all groups passing `fdr_threshold`
+#[pyfunction]
+pub fn group_fdr(
+    psms: Vec<PyFeature>,
+    strategy: PyGroupingStrategy,
+    fdr_threshold: f32,
+    db: Option<&PyIndexedDatabase>,
+) -> PyResult<Vec<PyFeature>> {
+    if matches!(strategy.inner, GroupingStrategy::ByModification) && db.is_none() {
+        return Err(PyValueError::new_err("grouping by modification requires the database"));
+    }
+    if let Some(db) = db {
+        check_peptide_indices(&db.inner, &psms)?;
+    }
+
+    let mut groups: HashMap<usize, Vec<PyFeature>> = HashMap::new();
+    for psm in psms {
+        let group = fdr_group(&psm, strategy.inner, db);
+        groups.entry(group).or_default().push(psm);
+    }
+
+    let mut keys: Vec<usize> = groups.keys().copied().collect();
+    keys.sort_unstable();
+
+    let mut result = Vec::new();
+    for key in keys {
+        let mut group = groups.remove(&key).unwrap();
+        let scores: Vec<f64> = group.iter().map(|p| p.inner.discriminant_score as f64).collect();
+        let is_decoy: Vec<bool> = group.iter().map(|p| p.inner.label == -1).collect();
+        let q_values = tda_q_values(&scores, &is_decoy);
+        for (psm, q) in group.iter_mut().zip(q_values) {
+            psm.inner.spectrum_q = q as f32;
+        }
+        result.extend(group.into_iter().filter(|p| p.inner.spectrum_q <= fdr_threshold));
+    }
+
+    Ok(result)
+}
+
+/// Seed of the decoy site draw of `compute_ptm_site_fdr`, fixed to keep results reproducible
+const SITE_DECOY_SEED: u64 = 0x5eed_0000_0000_517e;
+
+/// Highest scoring (position, score) of a PSM's candidate sites
+fn best_site(sites: &[(usize, f32)]) -> Option<(usize, f32)> {
+    sites.iter().copied().max_by(|a, b| a.1.total_cmp(&b.1))
+}
+
+/// Decoy site of each PSM: one of its candidate sites other than the best one (the localized
+/// site), drawn uniformly. None for PSMs with fewer than two candidate sites
+#[pyfunction]
+pub fn randomize_modification_positions(
+    localization_scores: Vec<Vec<(usize, f32)>>,
+    seed: Option<u64>,
+) -> Vec<Option<(usize, f32)>> {
+    let mut state = seed.unwrap_or(SITE_DECOY_SEED);
+    localization_scores
+        .iter()
+        .map(|sites| {
+            let (best, _) = best_site(sites)?;
+            let others: Vec<(usize, f32)> = sites.iter().copied().filter(|(p, _)| *p != best).collect();
+            match others.is_empty() {
+                true => None,
+                false => Some(others[(splitmix64(&mut state) % others.len() as u64) as usize]),
+            }
+        })
+        .collect()
+}
+
+/// Site-level false localization rate by target-decoy competition: the best site of each PSM is
+/// a target, a randomly drawn other site (see `randomize_modification_positions`) a decoy. Every
+/// candidate site gets the q-value of its score on the target-decoy curve (1.0 below the lowest
+/// target) and is flagged as localized if it is at most `site_fdr`
+#[pyfunction]
+pub fn compute_ptm_site_fdr(
+    localization_scores: Vec<Vec<(usize, f32)>>,
+    site_fdr: f32,
+    seed: Option<u64>,
+) -> Vec<Vec<(usize, f32, bool)>> {
+    let decoys = randomize_modification_positions(localization_scores.clone(), seed);
+    let targets: Vec<f64> = localization_scores
+        .iter()
+        .filter_map(|sites| best_site(sites))
+        .map(|(_, score)| score as f64)
+        .collect();
+    let scores: Vec<f64> = targets
+        .iter()
+        .copied()
+        .chain(decoys.iter().flatten().map(|(_, score)| *score as f64))
+        .collect();
+    let is_decoy: Vec<bool> = (0..scores.len()).map(|i| i >= targets.len()).collect();
+    let q_values = tda_q_values(&scores, &is_decoy);
+
+    // q-values of the targets by descending score, the q-value of a score is the one of the
+    // lowest scoring target reaching it
+    let mut curve: Vec<(f64, f64)> = targets.iter().copied().zip(q_values).collect();
+    curve.sort_by(|a, b| b.0.total_cmp(&a.0));
+    let site_q = |score: f32| match curve.partition_point(|(t, _)| *t >= score as f64) {
+        0 => 1.0,
+        k => curve[k - 1].1 as f32,
+    };
+
+    localization_scores
+        .iter()
+        .map(|sites| {
+            sites
+                .iter()
+                .map(|&(position, score)| {
+                    let q = site_q(score);
+                    (position, q, q <= site_fdr)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Candidate sites of a localized PSM: residues with a non-zero localization score and the best
+/// site. PSMs without localization give no sites
+fn localized_sites(psm: &PyFeature) -> Vec<(usize, f32)> {
+    let Some(scores) = psm.localization_scores.as_ref() else {
+        return Vec::new();
+    };
+    scores
+        .iter()
+        .enumerate()
+        .filter(|(i, score)| **score != 0.0 || psm.best_localization_site == Some(*i))
+        .map(|(i, score)| (i, *score))
+        .collect()
+}
+
+/// PSMs whose best localization site passes a site-level false localization rate of `site_fdr`
+/// (see `compute_ptm_site_fdr`), requires PSMs localized by `localize_modification`
+#[pyfunction]
+pub fn filter_localized_psms(psms: Vec<PyFeature>, site_fdr: f32, seed: Option<u64>) -> Vec<PyFeature> {
+    let sites: Vec<Vec<(usize, f32)>> = psms.iter().map(localized_sites).collect();
+    let site_q = compute_ptm_site_fdr(sites, site_fdr, seed);
+    psms.into_iter()
+        .zip(site_q)
+        .filter(|(psm, sites)| {
+            psm.best_localization_site
+                .is_some_and(|best| sites.iter().any(|(p, _, localized)| *p == best && *localized))
+        })
+        .map(|(psm, _)| psm)
+        .collect()
+}
+
+/// Identification summary of a run for quality control
+#[pyclass]
+#[derive(Clone, Debug)]
+pub struct PyIdentificationStats {
+    #[pyo3(get)]
+    pub total_spectra: usize,
+    #[pyo3(get)]
+    pub identified_spectra: usize,
+    #[pyo3(get)]
+    pub identification_rate: f32,
+    /// Distinct (modified) peptides
+    #[pyo3(get)]
+    pub unique_peptides: usize,
+    #[pyo3(get)]
+    pub unique_proteins: usize,
+    /// Median hyperscore of the identifying PSMs
+    #[pyo3(get)]
+    pub median_score: f64,
+}
+
+/// Differences of run b to run a, positive values mean b has more identifications
+#[pyclass]
+#[derive(Clone, Debug)]
+pub struct PyIdentificationComparison {
+    #[pyo3(get)]
+    pub delta_identified_spectra: i64,
+    #[pyo3(get)]
+    pub delta_identification_rate: f32,
+    #[pyo3(get)]
+    pub delta_unique_peptides: i64,
+    #[pyo3(get)]
+    pub delta_unique_proteins: i64,
+    #[pyo3(get)]
+    pub delta_median_score: f64,
+    /// Unique peptides of b relative to a, 0.0 if a has none
+    #[pyo3(get)]
+    pub peptide_ratio: f32,
+}
+
+fn identifies(psm: &PyFeature, fdr_threshold: f32) -> bool {
+    psm.inner.label == 1 && psm.inner.spectrum_q <= fdr_threshold
+}
+
+/// Identification rate of a run: spectra with a target PSM at spectrum_q <= fdr_threshold
+/// relative to `total_spectra` (all acquired MS2 spectra), with the unique peptides and proteins
+/// and the median hyperscore of these PSMs
+#[pyfunction]
+pub fn compute_identification_rate(
+    total_spectra: usize,
+    psms: Vec<PyFeature>,
+    fdr_threshold: f32,
+    db: &PyIndexedDatabase,
+) -> PyResult<PyIdentificationStats> {
+    check_peptide_indices(&db.inner, &psms)?;
+    let confident: Vec<&PyFeature> = psms.iter().filter(|p| identifies(p, fdr_threshold)).collect();
+    let spectra: HashSet<(usize, &str)> = confident
+        .iter()
+        .map(|p| (p.inner.file_id, p.inner.spec_id.as_str()))
+        .collect();
+    let peptides: HashSet<PeptideIx> = confident.iter().map(|p| p.inner.peptide_idx).collect();
+    let proteins: HashSet<String> = peptides
+        .iter()
+        .flat_map(|idx| db.inner[*idx].proteins.iter().map(|p| p.to_string()))
+        .collect();
+
+    let median_score = median(confident.iter().map(|p| p.inner.hyperscore)).unwrap_or(0.0);
+
+    Ok(PyIdentificationStats {
+        total_spectra,
+        identified_spectra: spectra.len(),
+        identification_rate: match total_spectra {
+            0 => 0.0,
+            n => spectra.len() as f32 / n as f32,
+        },
+        unique_peptides: peptides.len(),
+        unique_proteins: proteins.len(),
+        median_score,
+    })
+}
+
+/// Identification rate along the run: the retention time range of the PSMs is split into
+/// `n_bins` equal bins, for each the (bin center, fraction of its spectra with a target PSM at
+/// spectrum_q <= fdr_threshold) is returned. Only spectra with at least one PSM are counted,
+/// empty bins have a rate of 0.0
+#[pyfunction]
+pub fn identification_rate_over_rt(
+    psms: Vec<PyFeature>,
+    n_bins: usize,
+    fdr_threshold: Option<f32>,
+) -> PyResult<Vec<(f32, f32)>> {
+    if n_bins == 0 {
+        return Err(PyValueError::new_err("n_bins must be at least 1"));
+    }
+    let fdr_threshold = fdr_threshold.unwrap_or(0.01);
+
+    // a spectrum is identified if any of its PSMs is
+    let mut spectra: HashMap<(usize, &str), (f32, bool)> = HashMap::new();
+    for psm in &psms {
+        let entry = spectra
+            .entry((psm.inner.file_id, psm.inner.spec_id.as_str()))
+            .or_insert((psm.inner.rt, false));
+        entry.1 |= identifies(psm, fdr_threshold);
+    }
+    if spectra.is_empty() {
+        return Ok(Vec::new());
+    }
+    let (min_rt, max_rt) = spectra
+        .values()
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), (rt, _)| (lo.min(*rt), hi.max(*rt)));
+
+    let width = (max_rt - min_rt) / n_bins as f32;
+    let mut bins = vec![(0usize, 0usize); n_bins];
+    for (rt, identified) in spectra.values() {
+        let bin = match width > 0.0 {
+            true => (((rt - min_rt) / width) as usize).min(n_bins - 1),
+            false => 0,
+        };
+        bins[bin].0 += *identified as usize;
+        bins[bin].1 += 1;
+    }
+    Ok(bins
+        .into_iter()
+        .enumerate()
+        .map(|(i, (identified, total))| {
+            let center = min_rt + (i as f32 + 0.5) * width;
+            let rate = match total {
+                0 => 0.0,
+                n => identified as f32 / n as f32,
+            };
+            (center, rate)
+        })
+        .collect())
+}
+
+/// Compare the identification statistics of two runs, as b minus a
+#[pyfunction]
+pub fn compare_runs(stats_a: &PyIdentificationStats, stats_b: &PyIdentificationStats) -> PyIdentificationComparison {
+    PyIdentificationComparison {
+        delta_identified_spectra: stats_b.identified_spectra as i64 - stats_a.identified_spectra as i64,
+        delta_identification_rate: stats_b.identification_rate - stats_a.identification_rate,
+        delta_unique_peptides: stats_b.unique_peptides as i64 - stats_a.unique_peptides as i64,
+        delta_unique_proteins: stats_b.unique_proteins as i64 - stats_a.unique_proteins as i64,
+        delta_median_score: stats_b.median_score - stats_a.median_score,
+        peptide_ratio: match stats_a.unique_peptides {
+            0 => 0.0,
+            n => stats_b.unique_peptides as f32 / n as f32,
+        },
+    }
+}
+
+/// Proteins reported together because they share most of their identified peptides
+#[pyclass]
+#[derive(Clone, Debug)]
+pub struct PyProteinGroup {
+    /// Member with the most unique peptides
+    #[pyo3(get)]
+    pub representative: String,
+    #[pyo3(get)]
+    pub members: Vec<String>,
+    /// Peptides (stripped sequences) matching more than one protein
+    #[pyo3(get)]
+    pub shared_peptides: Vec<String>,
+    /// Peptides (stripped sequences) matching a single protein of the group
+    #[pyo3(get)]
+    pub unique_peptides: Vec<String>,
+    /// Set by `protein_group_fdr`, 1.0 before
+    #[pyo3(get)]
+    pub group_q_value: f32,
+    /// Best discriminant score of the PSMs of the group's peptides
+    #[pyo3(get)]
+    pub score: f64,
+    #[pyo3(get)]
+    pub decoy: bool,
+}
+
+fn find_root(parents: &mut [usize], i: usize) -> usize {
+    let mut root = i;
+    while parents[root] != root {
+        root = parents[root];
+    }
+    let mut i = i;
+    while parents[i] != root {
+        i = std::mem::replace(&mut parents[i], root);
+    }
+    root
+}
+
+/// Group the proteins of peptides identified at spectrum_q <= fdr_threshold (targets and decoys,
+/// decoy proteins carry the decoy tag). Two proteins are grouped if their shared peptides are more
+/// than `share_threshold` of the peptides of the smaller one, groups are formed transitively.
+/// Groups are returned sorted by descending score, use `protein_group_fdr` for their q-values
+#[pyfunction]
+pub fn infer_protein_groups(
+    psms: Vec<PyFeature>,
+    db: &PyIndexedDatabase,
+    fdr_threshold: f32,
+    share_threshold: f32,
+) -> PyResult<Vec<PyProteinGroup>> {
+    check_peptide_indices(&db.inner, &psms)?;
+    let confident: Vec<&PyFeature> = psms.iter().filter(|p| p.inner.spectrum_q <= fdr_threshold).collect();
+    let peptides: HashSet<PeptideIx> = confident.iter().map(|p| p.inner.peptide_idx).collect();
+
+    let tag = db.inner.decoy_tag.as_str();
+    let mut protein_index: HashMap<String, usize> = HashMap::new();
+    let mut protein_peptides: Vec<HashSet<PeptideIx>> = Vec::new();
+    let mut peptide_protein_ids: HashMap<PeptideIx, Vec<usize>> = HashMap::new();
+    for (idx, proteins) in peptide_proteins(db, &peptides, false) {
+        for protein in proteins {
+            let protein = match db.inner[idx].decoy && !protein.starts_with(tag) {
+                true => format!("{}{}", tag, protein),
+                false => protein,
+            };
+            let next = protein_index.len();
+            let id = *protein_index.entry(protein).or_insert(next);
+            if id == protein_peptides.len() {
+                protein_peptides.push(HashSet::new());
+            }
+            if protein_peptides[id].insert(idx) {
+                peptide_protein_ids.entry(idx).or_default().push(id);
+            }
+        }
+    }
+
+    // merge protein pairs sharing peptides above the threshold
+    let mut parents: Vec<usize> = (0..protein_peptides.len()).collect();
+    let mut compared: HashSet<(usize, usize)> = HashSet::new();
+    for ids in peptide_protein_ids.values() {
+        for (i, a) in ids.iter().enumerate() {
+            for b in &ids[i + 1..] {
+                let pair = (*a.min(b), *a.max(b));
+                if !compared.insert(pair) {
+                    continue;
+                }
+                let (pa, pb) = (&protein_peptides[pair.0], &protein_peptides[pair.1]);
+                let shared = pa.intersection(pb).count() as f32;
+                if shared / pa.len().min(pb.len()) as f32 > share_threshold {
+                    let (ra, rb) = (find_root(&mut parents, pair.0), find_root(&mut parents, pair.1));
+                    parents[ra.max(rb)] = ra.min(rb);
+                }
+            }
+        }
+    }
+
+    let mut members: HashMap<usize, Vec<usize>> = HashMap::new();
+    for id in 0..parents.len() {
+        let root = find_root(&mut parents, id);
+        members.entry(root).or_default().push(id);
+    }
+
+    let mut best_scores: HashMap<PeptideIx, f64> = HashMap::new();
+    for psm in &confident {
+        let entry = best_scores.entry(psm.inner.peptide_idx).or_insert(f64::NEG_INFINITY);
+        *entry = entry.max(psm.inner.discriminant_score as f64);
+    }
+
+    let mut names: Vec<&str> = vec![""; protein_index.len()];
+    for (name, id) in &protein_index {
+        names[*id] = name.as_str();
+    }
+    let is_unique = |idx: &PeptideIx| peptide_protein_ids[idx].len() == 1;
+    let sequence = |idx: &PeptideIx| String::from_utf8_lossy(&db.inner[*idx].sequence).to_string();
+
+    let mut groups: Vec<PyProteinGroup> = members
+        .into_values()
+        .map(|ids| {
+            let representative = *ids
+                .iter()
+                .max_by(|a, b| {
+                    let unique = |id: usize| protein_peptides[id].iter().filter(|p| is_unique(*p)).count();
+                    unique(**a)
+                        .cmp(&unique(**b))
+                        .then_with(|| protein_peptides[**a].len().cmp(&protein_peptides[**b].len()))
+                        .then_with(|| names[**b].cmp(names[**a]))
+                })
+                .expect("protein groups are not empty");
+            let group_peptides: HashSet<PeptideIx> = ids
+                .iter()
+                .flat_map(|id| protein_peptides[*id].iter().copied())
+                .collect();
+            let (unique, shared): (Vec<PeptideIx>, Vec<PeptideIx>) =
+                group_peptides.iter().copied().partition(|p| is_unique(p));
+            let sorted_sequences = |peptides: Vec<PeptideIx>| {
+                let mut sequences: Vec<String> = peptides.iter().map(sequence).collect();
+                sequences.sort();
+                sequences.dedup();
+                sequences
+            };
+            let mut member_names: Vec<String> = ids.iter().map(|id| names[*id].to_string()).collect();
+            member_names.sort();
+
+            PyProteinGroup {
+                representative: names[representative].to_string(),
+                members: member_names,
+                shared_peptides: sorted_sequences(shared),
+                unique_peptides: sorted_sequences(unique),
+                group_q_value: 1.0,
+                score: group_peptides
+                    .iter()
+                    .map(|p| best_scores[p])
+                    .fold(f64::NEG_INFINITY, f64::max),
+                decoy: group_peptides.iter().all(|p| db.inner[*p].decoy),
+            }
+        })
+        .collect();
+
+    groups.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.representative.cmp(&b.representative)));
+    Ok(groups)
+}
+
+/// Group-level q-values by target-decoy competition on the group scores
+#[pyfunction]
+pub fn protein_group_fdr(groups: Vec<PyProteinGroup>) -> Vec<PyProteinGroup> {
+    let mut groups = groups;
+    let scores: Vec<f64> = groups.iter().map(|g| g.score).collect();
+    let is_decoy: Vec<bool> = groups.iter().map(|g| g.decoy).collect();
+    for (group, q) in groups.iter_mut().zip(tda_q_values(&scores, &is_decoy)) {
+        group.group_q_value = q as f32;
+    }
+    groups
+}
+
+/// Write protein groups as a tab separated table, list columns are separated by ';'
+#[pyfunction]
+pub fn export_protein_groups_tsv(groups: Vec<PyProteinGroup>, path: &str) -> PyResult<()> {
+    let io_error = |e: std::io::Error| PyValueError::new_err(format!("Could not write {}: {}", path, e));
+    let mut writer = BufWriter::new(File::create(path).map_err(io_error)?);
+
+    writeln!(
+        writer,
+        "representative\tmembers\tunique_peptides\tshared_peptides\tscore\tgroup_q_value\tdecoy"
+    )
+    .map_err(io_error)?;
+    for group in &groups {
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            group.representative,
+            group.members.join(";"),
+            group.unique_peptides.join(";"),
+            group.shared_peptides.join(";"),
+            group.score,
+            group.group_q_value,
+            group.decoy,
+        )
+        .map_err(io_error)?;
+    }
+    writer.flush().map_err(io_error)
+}
+
 #[pymodule]
 pub fn fdr(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_class::<PyCompetitionPeptideIx>()?;
+    m.add_function(wrap_pyfunction!(storey_qvalues, m)?)?;
+    m.add_function(wrap_pyfunction!(calculate_fdr, m)?)?;
+    m.add_function(wrap_pyfunction!(estimate_pi0, m)?)?;
+    m.add_function(wrap_pyfunction!(pi0_confidence_interval, m)?)?;
+    m.add_function(wrap_pyfunction!(calculate_q_values_with_pi0, m)?)?;
+    m.add_function(wrap_pyfunction!(semi_supervised_fdr, m)?)?;
+    m.add_function(wrap_pyfunction!(protein_fdr, m)?)?;
+    m.add_function(wrap_pyfunction!(annotate_protein_q_razor, m)?)?;
+    m.add_function(wrap_pyfunction!(razor_protein_assignment, m)?)?;
+    m.add_function(wrap_pyfunction!(parsimony_protein_set, m)?)?;
+    m.add_class::<PyGroupingStrategy>()?;
+    m.add_function(wrap_pyfunction!(group_fdr, m)?)?;
+    m.add_function(wrap_pyfunction!(randomize_modification_positions, m)?)?;
+    m.add_function(wrap_pyfunction!(compute_ptm_site_fdr, m)?)?;
+    m.add_function(wrap_pyfunction!(filter_localized_psms, m)?)?;
+    m.add_class::<PyIdentificationStats>()?;
+    m.add_class::<PyIdentificationComparison>()?;
+    m.add_function(wrap_pyfunction!(compute_identification_rate, m)?)?;
+    m.add_function(wrap_pyfunction!(identification_rate_over_rt, m)?)?;
+    m.add_function(wrap_pyfunction!(compare_runs, m)?)?;
+    m.add_class::<PyProteinGroup>()?;
+    m.add_function(wrap_pyfunction!(infer_protein_groups, m)?)?;
+    m.add_function(wrap_pyfunction!(protein_group_fdr, m)?)?;
+    m.add_function(wrap_pyfunction!(export_protein_groups_tsv, m)?)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::py_scoring::tests::{search_database, search_peptide, search_scorer, search_spectrum};
+    use crate::py_scoring::ScoreType;
+    use sage_core::peptide::Peptide;
+    use std::sync::Arc;
+
+    #[test]
+    fn competition_q_values_count_all_winners() {
+        // winners by descending score: T T D T D
+        let scores = [10.0, 9.0, 8.0, 7.0, 6.0];
+        let is_decoy = [false, false, true, false, true];
+        let q = competition_q_values(&scores, &is_decoy);
+        assert_eq!(q, vec![0.0, 0.0, 0.25, 0.25, 0.4]);
+
+        // decoys / targets is the larger separate-search estimate
+        let q = tda_q_values(&scores, &is_decoy);
+        assert_eq!(q, vec![0.0, 0.0, 1.0 / 3.0, 1.0 / 3.0, 2.0 / 3.0]);
+    }
+
+    #[test]
+    fn q_values_are_monotone_in_score() {
+        let scores = [1.0, 5.0, 3.0, 4.0, 2.0];
+        let is_decoy = [true, false, true, false, false];
+        let q = competition_q_values(&scores, &is_decoy);
+        let order = order_by_score(&scores);
+        assert!(order.windows(2).all(|w| q[w[0]] <= q[w[1]]));
+    }
+
+    #[test]
+    fn cv_folds_keep_spectra_together() {
+        let psm = |file_id: usize, spec_id: &str| {
+            PyFeature::from(Feature { file_id, spec_id: spec_id.to_string(), ..crate::py_io::default_feature() })
+        };
+        let psms = [psm(0, "1"), psm(0, "1"), psm(1, "1"), psm(0, "2"), psm(0, "3"), psm(0, "2")];
+        assert_eq!(cv_folds(&psms), vec![0, 0, 1, 2, 0, 2]);
+    }
+
+    #[test]
+    fn normalized_scores_put_threshold_at_zero_and_decoy_median_at_minus_one() {
+        let reference = [10.0, 9.0, 8.0, 2.0, 1.0, 0.0];
+        let is_decoy = [false, false, false, true, true, true];
+        let mut scores = [8.0, 1.0, 15.0];
+        normalize_scores(&mut scores, &reference, &is_decoy);
+        assert_eq!(scores, [0.0, -1.0, 1.0]);
+    }
+
+    #[test]
+    fn semi_supervised_fdr_stores_re_scores_on_the_psms() {
+        let psms: Vec<PyFeature> = (0..60)
+            .map(|i| {
+                let label = if i % 3 == 0 { -1 } else { 1 };
+                let hyperscore = if label == 1 { 30.0 + (i % 7) as f64 } else { 10.0 + (i % 5) as f64 };
+                PyFeature::from(Feature {
+                    spec_id: i.to_string(),
+                    label,
+                    hyperscore,
+                    ..crate::py_io::default_feature()
+                })
+            })
+            .collect();
+
+        let result = semi_supervised_fdr(psms, 3, 1).unwrap();
+        assert_eq!(result.len(), 60);
+        assert!(result.iter().all(|p| p.re_score.is_some_and(|s| s.is_finite())));
+        // well separated targets stay confident, with a lower error probability than decoys
+        let target = result.iter().find(|p| p.inner.label == 1).unwrap();
+        let decoy = result.iter().find(|p| p.inner.label == -1).unwrap();
+        assert!(target.re_score > decoy.re_score);
+        assert!(target.inner.posterior_error <= decoy.inner.posterior_error);
+    }
+
+    #[test]
+    fn charge_groups_recover_targets_a_uniform_fdr_rejects() {
+        let psm = |charge: u8, label: i32, discriminant_score: f32| {
+            PyFeature::from(Feature {
+                charge,
+                label,
+                discriminant_score,
+                ..crate::py_io::default_feature()
+            })
+        };
+        // charge 3 PSMs score lower: their targets interleave with the decoys of charge 2
+        let mut psms: Vec<PyFeature> = (0..10).map(|i| psm(2, 1, 20.0 + i as f32)).collect();
+        psms.extend((0..5).map(|i| psm(2, -1, 5.0 + i as f32)));
+        psms.extend((0..5).map(|i| psm(3, 1, 5.5 + i as f32)));
+        psms.extend((0..5).map(|i| psm(3, -1, i as f32)));
+        let targets = |psms: &[PyFeature]| psms.iter().filter(|p| p.inner.label == 1).count();
+
+        // a single length bin puts all PSMs in one group
+        let uniform = PyGroupingStrategy::new("length", Some(1000)).unwrap();
+        let uniform = group_fdr(psms.clone(), uniform, 0.01, None).unwrap();
+        assert_eq!(targets(&uniform), 11);
+
+        let by_charge = PyGroupingStrategy::new("charge", None).unwrap();
+        let grouped = group_fdr(psms.clone(), by_charge, 0.01, None).unwrap();
+        assert_eq!(grouped.len(), 15);
+        assert_eq!(targets(&grouped), 15);
+        assert!(grouped.iter().all(|p| p.inner.spectrum_q == 0.0));
+
+        let by_modification = PyGroupingStrategy::new("modification", None).unwrap();
+        assert!(group_fdr(psms, by_modification, 0.01, None).is_err());
+        assert!(PyGroupingStrategy::new("length", Some(0)).is_err());
+    }
+
+    /// 100 true positives at p = 0.001 and 100 nulls spread evenly over (0, 1), so that
+    /// pi0(lambda) is 0.5 at every lambda of the default grid
+    fn half_null_p_values() -> Vec<f64> {
+        let mut p_values = vec![0.001; 100];
+        p_values.extend((1..=100).map(|j| (j as f64 - 0.5) / 100.0));
+        p_values
+    }
+
+    #[test]
+    fn storey_q_values_match_r_qvalue() {
+        // R: qvalue(p) with the default lambda grid and smooth.spline(df = 3), which reproduces
+        // the constant pi0 curve: pi0 = 0.5, qvalues = 0.5 * pmin(1, cummin(200 p / rank))
+        let (pi0, q) = storey_q_values_of(&half_null_p_values(), &default_lambda_range());
+        assert!((pi0 - 0.5).abs() < 1e-9, "pi0 {}", pi0);
+        let expected = [(0, 0.001), (99, 0.001), (100, 0.004950495), (149, 0.33), (199, 0.4975)];
+        for (rank, q_value) in expected {
+            assert!((q[rank] - q_value).abs() < 1e-8, "q[{}] = {}", rank, q[rank]);
+        }
+        assert!(q.windows(2).all(|w| w[0] <= w[1]));
+
+        // uniform p-values: the smoothed pi0 exceeds 1 and is capped, as are the q-values
+        let uniform: Vec<f64> = (1..=100).map(|i| i as f64 / 100.0).collect();
+        let (pi0, q) = storey_q_values_of(&uniform, &default_lambda_range());
+        assert_eq!(pi0, 1.0);
+        assert!(q.iter().all(|q| (q - 1.0).abs() < 1e-12));
+    }
+
+    #[test]
+    fn smoothing_spline_keeps_lines_and_smooths_noise() {
+        let x = default_lambda_range();
+        let w = vec![1.0; x.len()];
+        // a straight line is not penalized and passes unchanged
+        let line: Vec<f64> = x.iter().map(|l| 0.3 + 0.2 * l).collect();
+        let fitted = smoothing_spline(&x, &line, &w, 3.0).unwrap();
+        assert!(fitted.iter().zip(&line).all(|(f, y)| (f - y).abs() < 1e-9));
+
+        // the trace falls from interpolation (n) towards the line (2) with the penalty weight
+        let wiggly: Vec<f64> = x
+            .iter()
+            .enumerate()
+            .map(|(i, l)| 0.6 + 0.1 * (7.0 * l).sin() + if i % 3 == 0 { 0.05 } else { -0.03 })
+            .collect();
+        let k = spline_penalty(&x).unwrap();
+        let traces: Vec<f64> = [1e-8, 1e-4, 1e-2, 1.0]
+            .iter()
+            .map(|alpha| fit_smoothing_spline(&k, &wiggly, &w, *alpha).unwrap().1)
+            .collect();
+        assert!(traces.windows(2).all(|t| t[0] > t[1]));
+        assert!(traces[0] < x.len() as f64 + 1e-9 && traces[3] > 2.0);
+
+        let roughness = |y: &[f64]| y.windows(3).map(|w| (w[0] - 2.0 * w[1] + w[2]).powi(2)).sum::<f64>();
+        let fitted = smoothing_spline(&x, &wiggly, &w, 3.0).unwrap();
+        assert!(roughness(&fitted) < 0.1 * roughness(&wiggly));
+    }
+
+    #[test]
+    fn competition_reduces_wrong_target_assignments_of_a_separate_search() {
+        let target = search_peptide("PEPTIDEK", false);
+        let shared_mass = search_peptide("ELVISLIVESK", false);
+        // the reversed peptide (C-terminal residue kept) of the same mass
+        let decoy = search_peptide("SEVILSIVLEK", true);
+
+        // the first spectrum comes from a target peptide, the second from a peptide missing from
+        // the targets: it matches the decoy well and a few ions of the target of the same mass
+        let spectra = [
+            search_spectrum("1", &target, &[(&target, 6)]),
+            search_spectrum("2", &shared_mass, &[(&decoy, 9), (&shared_mass, 2)]),
+        ];
+        let wrong_target_assignments = |psms: &[PyFeature]| {
+            psms.iter()
+                .filter(|psm| psm.inner.label == 1 && psm.inner.spec_id == "2")
+                .count()
+        };
+
+        // separate searches report the best target of each spectrum, whatever the decoys score
+        let targets = search_database(vec![target.clone(), shared_mass.clone()]);
+        let decoys = search_database(vec![decoy.clone()]);
+        let standard = search_scorer(ScoreType::Standard);
+        let separate: Vec<PyFeature> = spectra
+            .iter()
+            .flat_map(|spectrum| {
+                let mut psms = standard.score(&targets, spectrum, None, None);
+                psms.extend(standard.score(&decoys, spectrum, None, None));
+                psms
+            })
+            .collect();
+        assert_eq!(wrong_target_assignments(&separate), 1);
+
+        // under competition each spectrum goes to its best peptide, target or decoy
+        let combined = search_database(vec![target.clone(), shared_mass, decoy]);
+        let tdc = search_scorer(ScoreType::Tdc);
+        let competed: Vec<PyFeature> = spectra
+            .iter()
+            .flat_map(|spectrum| tdc.score(&combined, spectrum, None, None))
+            .collect();
+        assert_eq!(competed.len(), 2);
+        assert_eq!(wrong_target_assignments(&competed), 0);
+        let first = competed.iter().find(|psm| psm.inner.spec_id == "1").unwrap();
+        assert_eq!(first.inner.label, 1);
+        assert_eq!(&*combined.inner[first.inner.peptide_idx].sequence, target.sequence.as_ref());
+        let second = competed.iter().find(|psm| psm.inner.spec_id == "2").unwrap();
+        assert_eq!(second.inner.label, -1);
+    }
+
+    /// Database of (sequence, proteins) peptides, decoys are those of `rev_` proteins
+    fn protein_database(peptides: &[(&str, &[&str])]) -> PyIndexedDatabase {
+        search_database(
+            peptides
+                .iter()
+                .map(|(sequence, proteins)| Peptide {
+                    proteins: proteins.iter().map(|p| Arc::new(p.to_string())).collect(),
+                    ..search_peptide(sequence, proteins[0].starts_with("rev_"))
+                })
+                .collect(),
+        )
+    }
+
+    /// PSM of the database peptide with the given sequence
+    fn peptide_psm(db: &PyIndexedDatabase, sequence: &str, discriminant_score: f32, peptide_q: f32) -> PyFeature {
+        let idx = db.inner.peptides.iter().position(|p| &*p.sequence == sequence.as_bytes()).unwrap();
+        PyFeature::from(Feature {
+            peptide_idx: PeptideIx(idx as u32),
+            label: if db.inner.peptides[idx].decoy { -1 } else { 1 },
+            discriminant_score,
+            peptide_q,
+            ..crate::py_io::default_feature()
+        })
+    }
+
+    #[test]
+    fn razor_protein_has_the_most_unique_peptides() {
+        let peptide_proteins: HashMap<&str, Vec<String>> = [
+            ("A1", &["A"][..]),
+            ("S1", &["A", "C"]),
+            ("S2", &["A", "C"]),
+            ("S3", &["A", "C"]),
+            ("P", &["A", "B"]),
+            ("B1", &["B"]),
+            ("B2", &["B"]),
+            ("Q", &["C", "D"]),
+            ("R", &["F", "E"]),
+        ]
+        .into_iter()
+        .map(|(peptide, proteins)| (peptide, proteins.iter().map(|p| p.to_string()).collect()))
+        .collect();
+
+        let razor = assign_razor(&peptide_proteins);
+        // B has two unique peptides, A one but more peptides in total
+        assert_eq!(razor["P"], "B");
+        assert_eq!(razor["S1"], "A");
+        // without unique peptides the total number of peptides decides, then the accession
+        assert_eq!(razor["Q"], "C");
+        assert_eq!(razor["R"], "E");
+        assert_eq!(razor["A1"], "A");
+    }
+
+    #[test]
+    fn shared_peptides_count_for_their_razor_protein_only() {
+        let (a, b, both) = (&["sp|A|PROTA"][..], &["sp|B|PROTB"][..], &["sp|A|PROTA", "sp|B|PROTB"][..]);
+        let db = protein_database(&[
+            ("AAAAK", a),
+            ("AAAAR", a),
+            ("SSSSK", both),
+            ("SSSSR", both),
+            ("TTTTK", b),
+            ("DDDDK", &["rev_sp|D|PROTD"]),
+        ]);
+        let psms = vec![
+            peptide_psm(&db, "AAAAK", 30.0, 0.001),
+            peptide_psm(&db, "AAAAR", 28.0, 0.001),
+            peptide_psm(&db, "SSSSK", 25.0, 0.001),
+            peptide_psm(&db, "SSSSR", 24.0, 0.001),
+            peptide_psm(&db, "TTTTK", 3.0, 0.001),
+            peptide_psm(&db, "DDDDK", 5.0, 0.001),
+        ];
+
+        // the shared peptides go to A, so B is scored by its own weak peptide, below the decoy
+        let q = protein_fdr(psms.clone(), &db, 0.01).unwrap();
+        assert_eq!(q.len(), 3);
+        assert_eq!(q["sp|A|PROTA"], 0.0);
+        assert_eq!(q["rev_sp|D|PROTD"], 0.5);
+        assert_eq!(q["sp|B|PROTB"], 0.5);
+
+        let mut psms = psms;
+        psms.push(peptide_psm(&db, "TTTTK", 2.0, 0.5));
+        let annotated = annotate_protein_q_razor(psms, &db, 0.01).unwrap();
+        assert_eq!(annotated[2].protein_q_razor, 0.0);
+        assert_eq!(annotated[4].protein_q_razor, 0.5);
+        // the peptide of a PSM has passed the cutoff through its other PSM
+        assert_eq!(annotated[6].protein_q_razor, 0.5);
+    }
+
+    #[test]
+    fn parsimony_explains_a_three_protein_group_with_one_protein() {
+        let db = protein_database(&[
+            ("AAAAK", &["sp|X|PROTX", "sp|Y|PROTY"]),
+            ("SSSSK", &["sp|X|PROTX", "sp|Y|PROTY", "sp|Z|PROTZ"]),
+            ("TTTTK", &["sp|X|PROTX", "sp|Z|PROTZ"]),
+            ("DDDDK", &["rev_sp|D|PROTD"]),
+        ]);
+        let psms: Vec<PyFeature> = ["AAAAK", "SSSSK", "TTTTK", "DDDDK"]
+            .iter()
+            .map(|sequence| peptide_psm(&db, sequence, 10.0, 0.001))
+            .collect();
+
+        // decoy PSMs need no explanation
+        assert_eq!(parsimony_protein_set(psms.clone(), &db).unwrap(), vec!["sp|X|PROTX"]);
+
+        // Y and Z each explain one peptide X cannot without the shared peptides
+        let db = protein_database(&[
+            ("AAAAK", &["sp|Y|PROTY"]),
+            ("SSSSK", &["sp|X|PROTX", "sp|Y|PROTY", "sp|Z|PROTZ"]),
+            ("TTTTK", &["sp|Z|PROTZ"]),
+        ]);
+        let psms: Vec<PyFeature> = ["AAAAK", "SSSSK", "TTTTK"]
+            .iter()
+            .map(|sequence| peptide_psm(&db, sequence, 10.0, 0.001))
+            .collect();
+        assert_eq!(parsimony_protein_set(psms, &db).unwrap(), vec!["sp|Y|PROTY", "sp|Z|PROTZ"]);
+    }
+
+    #[test]
+    fn pi0_of_half_null_targets_is_one_half() {
+        // 1000 decoys spread over (0, 1000), 500 null targets among them and 500 correct targets
+        // above all decoys
+        let mut scores: Vec<f64> = (0..1000).map(|i| i as f64 + 0.5).collect();
+        let mut is_decoy = vec![true; 1000];
+        scores.extend((0..500).map(|i| 2.0 * i as f64 + 0.25));
+        scores.extend((0..500).map(|i| 2000.0 + i as f64));
+        is_decoy.extend(vec![false; 1000]);
+
+        for method in ["spline", "bootstrap"] {
+            let (pi0, curve) = estimate_pi0(scores.clone(), is_decoy.clone(), vec![], method).unwrap();
+            assert_eq!(curve.len(), 19);
+            assert!(curve.iter().all(|pi0| (pi0 - 0.5).abs() < 0.025), "{:?}", curve);
+            assert!((pi0 - 0.5).abs() < 0.05, "{} pi0 {}", method, pi0);
+        }
+        assert!(estimate_pi0(scores.clone(), is_decoy.clone(), vec![], "loess").is_err());
+        assert!(estimate_pi0(scores.clone(), is_decoy.clone(), vec![1.0], "spline").is_err());
+
+        let (lower, estimate, upper) = pi0_confidence_interval(scores.clone(), is_decoy.clone(), 0.5, 200).unwrap();
+        assert!((estimate - 0.5).abs() < 0.01);
+        assert!(lower <= estimate && estimate <= upper);
+        assert!(lower > 0.4 && upper < 0.6);
+
+        // the FDR estimate, and with it every q-value, scales with pi0
+        let psms: Vec<PyFeature> = scores
+            .iter()
+            .zip(is_decoy.iter())
+            .map(|(score, decoy)| {
+                PyFeature::from(Feature {
+                    discriminant_score: *score as f32,
+                    label: if *decoy { -1 } else { 1 },
+                    ..crate::py_io::default_feature()
+                })
+            })
+            .collect();
+        let full = calculate_q_values_with_pi0(psms.clone(), 1.0).unwrap();
+        let half = calculate_q_values_with_pi0(psms.clone(), 0.5).unwrap();
+        for (full, half) in full.iter().zip(half.iter()) {
+            assert!((half.inner.spectrum_q - 0.5 * full.inner.spectrum_q.min(1.0)).abs() < 1e-6);
+        }
+        assert!(calculate_q_values_with_pi0(psms, 1.5).is_err());
+    }
+
+    #[test]
+    fn identification_rate_counts_confidently_identified_spectra() {
+        let db = protein_database(&[
+            ("PEPTIDEK", &["A"]),
+            ("SAMPLERK", &["B"]),
+            ("ELVISLIVEK", &["A", "C"]),
+            ("KEDITPEP", &["rev_A"]),
+        ]);
+        let psm = |spec_id: &str, rt: f32, sequence: &str, hyperscore: f64, spectrum_q: f32| {
+            let mut psm = peptide_psm(&db, sequence, 0.0, 1.0);
+            psm.inner.spec_id = spec_id.to_string();
+            psm.inner.rt = rt;
+            psm.inner.hyperscore = hyperscore;
+            psm.inner.spectrum_q = spectrum_q;
+            psm
+        };
+        // the second PSM of spectrum 1, spectrum 4 and the decoy of spectrum 5 do not identify
+        let psms = vec![
+            psm("1", 1.0, "PEPTIDEK", 10.0, 0.001),
+            psm("1", 1.0, "SAMPLERK", 5.0, 0.5),
+            psm("2", 2.0, "PEPTIDEK", 20.0, 0.002),
+            psm("3", 3.0, "ELVISLIVEK", 30.0, 0.005),
+            psm("4", 9.0, "SAMPLERK", 40.0, 0.2),
+            psm("5", 10.0, "KEDITPEP", 50.0, 0.001),
+        ];
+
+        let a = compute_identification_rate(10, psms.clone(), 0.01, &db);
+        assert_eq!((a.identified_spectra, a.unique_peptides, a.unique_proteins), (3, 2, 2));
+        assert!((a.identification_rate - 0.3).abs() < 1e-6);
+        assert_eq!(a.median_score, 20.0);
+
+        let over_rt = identification_rate_over_rt(psms.clone(), 3, None).unwrap();
+        assert_eq!(over_rt, vec![(2.5, 1.0), (5.5, 0.0), (8.5, 0.0)]);
+        assert!(identification_rate_over_rt(psms.clone(), 0, None).is_err());
+
+        let b = compute_identification_rate(10, psms, 0.003, &db);
+        let comparison = compare_runs(&a, &b);
+        assert_eq!(comparison.delta_identified_spectra, -1);
+        assert_eq!((comparison.delta_unique_peptides, comparison.delta_unique_proteins), (-1, -1));
+        assert!((comparison.delta_identification_rate + 0.1).abs() < 1e-6);
+        assert_eq!(comparison.delta_median_score, -5.0);
+        assert_eq!(comparison.peptide_ratio, 0.5);
+    }
+
+    #[test]
+    fn site_fdr_localizes_clear_sites_and_rejects_ambiguous_ones() {
+        // 20 PSMs with a clear best site and one competing site, the decoy of each is its
+        // competing site, one PSM whose two sites score alike and one with a single site
+        let mut scores: Vec<Vec<(usize, f32)>> = (0..20).map(|i| vec![(0, 10.0 + i as f32), (3, 1.0)]).collect();
+        scores.push(vec![(1, 0.5), (2, 0.4)]);
+        scores.push(vec![(4, 7.0)]);
+
+        let decoys = randomize_modification_positions(scores.clone(), Some(7));
+        assert!(decoys[..20].iter().all(|d| *d == Some((3, 1.0))));
+        assert_eq!(decoys[20], Some((2, 0.4)));
+        assert_eq!(decoys[21], None);
+
+        let sites = compute_ptm_site_fdr(scores.clone(), 0.05, None);
+        assert!(sites[..20].iter().all(|s| s[0].1 == 0.0 && s[0].2));
+        // 20 decoys score above the ambiguous site, next to 22 targets
+        assert!((sites[20][0].1 - 20.0 / 22.0).abs() < 1e-6);
+        assert!(!sites[20][0].2);
+        assert!(sites[21][0].2);
+
+        let psm = |site_scores: &[(usize, f32)]| {
+            let mut localization = vec![0.0; 5];
+            for (position, score) in site_scores {
+                localization[*position] = *score;
+            }
+            let mut psm = PyFeature::from(crate::py_io::default_feature());
+            psm.best_localization_site = best_site(site_scores).map(|(position, _)| position);
+            psm.localization_scores = Some(localization);
+            psm
+        };
+        let mut psms: Vec<PyFeature> = scores.iter().map(|s| psm(s)).collect();
+        psms.push(PyFeature::from(crate::py_io::default_feature()));
+        let localized = filter_localized_psms(psms, 0.05, None);
+        assert_eq!(localized.len(), 21);
+        assert!(localized.iter().all(|p| p.best_localization_site != Some(1)));
+    }
+
+    #[test]
+    fn protein_groups_merge_proteins_sharing_most_of_their_peptides() {
+        let db = protein_database(&[
+            ("PEPTIDEK", &["A"]),
+            ("TESTPEPK", &["A"]),
+            ("LESSPEPTIDEK", &["A", "B"]),
+            ("ELVISLIVESK", &["A", "B"]),
+            ("MEDIEVALK", &["B"]),
+            ("SAMPLER", &["C"]),
+            ("GREATPEPK", &["C", "D"]),
+            ("KEDITPEP", &["rev_E"]),
+            ("QQQQK", &["F"]),
+        ]);
+        let psms: Vec<PyFeature> = [
+            ("PEPTIDEK", 10.0, 0.001),
+            ("TESTPEPK", 8.0, 0.001),
+            ("LESSPEPTIDEK", 7.0, 0.001),
+            ("ELVISLIVESK", 6.0, 0.001),
+            ("MEDIEVALK", 9.0, 0.001),
+            ("SAMPLER", 5.0, 0.001),
+            ("GREATPEPK", 4.0, 0.001),
+            ("KEDITPEP", 7.0, 0.001),
+            ("QQQQK", 20.0, 0.5),
+        ]
+        .into_iter()
+        .map(|(sequence, score, spectrum_q)| {
+            let mut psm = peptide_psm(&db, sequence, score, spectrum_q);
+            psm.inner.spectrum_q = spectrum_q;
+            psm
+        })
+        .collect();
+
+        // A and B share 2 of 3 peptides of B, D shares its only peptide with C
+        let groups = protein_group_fdr(infer_protein_groups(psms.clone(), &db, 0.01, 0.5));
+        assert_eq!(groups.len(), 3);
+        assert_eq!(groups[0].representative, "A");
+        assert_eq!(groups[0].members, vec!["A", "B"]);
+        assert_eq!(groups[0].unique_peptides, vec!["MEDIEVALK", "PEPTIDEK", "TESTPEPK"]);
+        assert_eq!(groups[0].shared_peptides, vec!["ELVISLIVESK", "LESSPEPTIDEK"]);
+        assert_eq!(groups[0].score, 10.0);
+        assert!(groups[1].decoy && groups[1].members == vec!["rev_E"]);
+        assert_eq!(groups[2].representative, "C");
+        assert_eq!(groups[2].members, vec!["C", "D"]);
+        assert_eq!(groups[2].unique_peptides, vec!["SAMPLER"]);
+        assert_eq!(groups[2].shared_peptides, vec!["GREATPEPK"]);
+        // the unconfident PSM of F is left out, the decoy group sits between the target groups
+        let q_values: Vec<f32> = groups.iter().map(|g| g.group_q_value).collect();
+        assert_eq!(q_values, vec![0.0, 0.5, 0.5]);
+
+        let strict = infer_protein_groups(psms, &db, 0.01, 0.9);
+        let representatives: Vec<&str> = strict.iter().map(|g| g.representative.as_str()).collect();
+        assert_eq!(representatives, vec!["A", "B", "rev_E", "C"]);
+
+        let path = std::env::temp_dir().join(format!("sagepy_protein_groups_{}.tsv", std::process::id()));
+        export_protein_groups_tsv(groups, path.to_str().unwrap()).unwrap();
+        let table = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("representative\tmembers"));
+        assert_eq!(lines[1], "A\tA;B\tMEDIEVALK;PEPTIDEK;TESTPEPK\tELVISLIVESK;LESSPEPTIDEK\t10\t0\tfalse");
+    }
+}
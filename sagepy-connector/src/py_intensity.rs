@@ -0,0 +1,140 @@
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+
+fn check_lengths(a: &[f32], b: &[f32]) -> PyResult<()> {
+    if a.len() != b.len() {
+        return Err(PyValueError::new_err(format!(
+            "intensity vectors must have the same length, got {} and {}",
+            a.len(),
+            b.len()
+        )));
+    }
+    Ok(())
+}
+
+/// Normalized spectral contrast angle, 1 for identical and 0 for orthogonal intensity vectors.
+/// With `normalize`, both vectors are scaled to unit L2 norm (plus `epsilon` to guard against
+/// empty vectors), otherwise they are taken to be normalized already
+pub(crate) fn spectral_angle(observed: &[f32], predicted: &[f32], epsilon: f32, normalize: bool) -> f32 {
+    let norm = |v: &[f32]| match normalize {
+        true => v.iter().map(|x| (*x as f64).powi(2)).sum::<f64>().sqrt() + epsilon as f64,
+        false => 1.0,
+    };
+    let (norm_observed, norm_predicted) = (norm(observed), norm(predicted));
+    if norm_observed == 0.0 || norm_predicted == 0.0 {
+        return 0.0;
+    }
+    let dot: f64 = observed
+        .iter()
+        .zip(predicted)
+        .map(|(o, p)| *o as f64 * *p as f64)
+        .sum();
+    let cosine = (dot / (norm_observed * norm_predicted)).clamp(-1.0, 1.0);
+    (1.0 - 2.0 * cosine.acos() / std::f64::consts::PI) as f32
+}
+
+/// Fractional ranks (ties share their mean rank)
+fn ranks(values: &[f32]) -> Vec<f64> {
+    let mut order: Vec<usize> = (0..values.len()).collect();
+    order.sort_by(|&a, &b| values[a].total_cmp(&values[b]));
+    let mut ranks = vec![0.0; values.len()];
+    let mut start = 0;
+    while start < order.len() {
+        let mut end = start + 1;
+        while end < order.len() && values[order[end]] == values[order[start]] {
+            end += 1;
+        }
+        let rank = (start + end - 1) as f64 / 2.0 + 1.0;
+        for &i in &order[start..end] {
+            ranks[i] = rank;
+        }
+        start = end;
+    }
+    ranks
+}
+
+pub(crate) fn pearson(a: &[f64], b: &[f64]) -> f32 {
+    let n = a.len() as f64;
+    if n < 2.0 {
+        return 0.0;
+    }
+    let (mean_a, mean_b) = (a.iter().sum::<f64>() / n, b.iter().sum::<f64>() / n);
+    let (mut cov, mut var_a, mut var_b) = (0.0, 0.0, 0.0);
+    for (x, y) in a.iter().zip(b) {
+        cov += (x - mean_a) * (y - mean_b);
+        var_a += (x - mean_a).powi(2);
+        var_b += (y - mean_b).powi(2);
+    }
+    if var_a == 0.0 || var_b == 0.0 {
+        return 0.0;
+    }
+    (cov / (var_a * var_b).sqrt()) as f32
+}
+
+/// Spectral angle similarity of observed and predicted fragment intensities
+#[pyfunction]
+pub fn spectral_angle_similarity(observed: Vec<f32>, predicted: Vec<f32>, epsilon: f32, normalize: bool) -> PyResult<f32> {
+    check_lengths(&observed, &predicted)?;
+    Ok(spectral_angle(&observed, &predicted, epsilon, normalize))
+}
+
+/// Spectral angle similarity of many observed and predicted intensity vectors in parallel, the
+/// vectors are L2 normalized, `observed_list[i]` pairs with `predicted_list[i]`
+#[pyfunction]
+pub fn batch_spectral_angle(
+    observed_list: Vec<Vec<f32>>,
+    predicted_list: Vec<Vec<f32>>,
+    epsilon: f32,
+    num_threads: usize,
+) -> PyResult<Vec<f32>> {
+    if observed_list.len() != predicted_list.len() {
+        return Err(PyValueError::new_err(format!(
+            "observed_list and predicted_list must have the same length, got {} and {}",
+            observed_list.len(),
+            predicted_list.len()
+        )));
+    }
+    for (observed, predicted) in observed_list.iter().zip(predicted_list.iter()) {
+        check_lengths(observed, predicted)?;
+    }
+
+    let pool = ThreadPoolBuilder::new()
+        .num_threads(num_threads)
+        .build()
+        .unwrap();
+
+    Ok(pool.install(|| {
+        observed_list
+            .par_iter()
+            .zip(predicted_list.par_iter())
+            .map(|(observed, predicted)| spectral_angle(observed, predicted, epsilon, true))
+            .collect()
+    }))
+}
+
+/// Pearson correlation of two intensity vectors, 0.0 if either is constant
+#[pyfunction]
+pub fn pearson_correlation(a: Vec<f32>, b: Vec<f32>) -> PyResult<f32> {
+    check_lengths(&a, &b)?;
+    let a: Vec<f64> = a.iter().map(|x| *x as f64).collect();
+    let b: Vec<f64> = b.iter().map(|x| *x as f64).collect();
+    Ok(pearson(&a, &b))
+}
+
+/// Spearman rank correlation of two intensity vectors, 0.0 if either is constant
+#[pyfunction]
+pub fn spearman_correlation(a: Vec<f32>, b: Vec<f32>) -> PyResult<f32> {
+    check_lengths(&a, &b)?;
+    Ok(pearson(&ranks(&a), &ranks(&b)))
+}
+
+#[pymodule]
+pub fn intensity(_py: Python, m: &PyModule) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(spectral_angle_similarity, m)?)?;
+    m.add_function(wrap_pyfunction!(batch_spectral_angle, m)?)?;
+    m.add_function(wrap_pyfunction!(pearson_correlation, m)?)?;
+    m.add_function(wrap_pyfunction!(spearman_correlation, m)?)?;
+    Ok(())
+}
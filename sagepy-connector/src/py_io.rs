@@ -0,0 +1,442 @@
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use regex::Regex;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::OnceLock;
+
+use crate::py_database::PyIndexedDatabase;
+use crate::py_peptide::tryptic_missed_cleavages;
+use crate::py_scoring::{check_peptide_indices, feature_values, set_feature_value, PyFeature, FEATURE_NAMES, UNKNOWN_PEPTIDE};
+use sage_core::database::{IndexedDatabase, PeptideIx};
+use sage_core::mass::PROTON;
+use sage_core::scoring::Feature;
+
+/// A rank 1 PSM with all scores zeroed and q-values of 1, not associated with a sagepy database
+pub(crate) fn default_feature() -> Feature {
+    Feature {
+        peptide_idx: UNKNOWN_PEPTIDE,
+        psm_id: 0,
+        peptide_len: 0,
+        spec_id: String::new(),
+        file_id: 0,
+        rank: 1,
+        label: 1,
+        expmass: 0.0,
+        calcmass: 0.0,
+        charge: 0,
+        rt: 0.0,
+        aligned_rt: 0.0,
+        predicted_rt: 0.0,
+        delta_rt_model: 0.0,
+        delta_mass: 0.0,
+        isotope_error: 0.0,
+        average_ppm: 0.0,
+        hyperscore: 0.0,
+        delta_next: 0.0,
+        delta_best: 0.0,
+        matched_peaks: 0,
+        longest_b: 0,
+        longest_y: 0,
+        longest_y_pct: 0.0,
+        missed_cleavages: 0,
+        matched_intensity_pct: 0.0,
+        scored_candidates: 0,
+        poisson: 0.0,
+        discriminant_score: 0.0,
+        posterior_error: 1.0,
+        spectrum_q: 1.0,
+        peptide_q: 1.0,
+        protein_q: 1.0,
+        ms2_intensity: 0.0,
+        fragments: None,
+    }
+}
+
+/// Columns of a DIA-NN `report.tsv` used to create PSMs
+struct DiannColumns {
+    precursor_id: usize,
+    modified_sequence: usize,
+    stripped_sequence: Option<usize>,
+    charge: usize,
+    q_value: usize,
+    run: Option<usize>,
+    pg_q_value: Option<usize>,
+    global_q_value: Option<usize>,
+    rt: Option<usize>,
+    precursor_mz: Option<usize>,
+    quantity: Option<usize>,
+    cscore: Option<usize>,
+    pep: Option<usize>,
+}
+
+impl DiannColumns {
+    fn from_header(header: &str) -> PyResult<Self> {
+        let index: HashMap<&str, usize> = header
+            .trim_end_matches(['\r', '\n'])
+            .split('\t')
+            .enumerate()
+            .map(|(i, name)| (name, i))
+            .collect();
+
+        let required = |name: &str| {
+            index.get(name).copied().ok_or_else(|| {
+                PyValueError::new_err(format!("DIA-NN report is missing required column {}", name))
+            })
+        };
+        let optional = |name: &str| index.get(name).copied();
+
+        Ok(DiannColumns {
+            precursor_id: required("Precursor.Id")?,
+            modified_sequence: required("Modified.Sequence")?,
+            stripped_sequence: optional("Stripped.Sequence"),
+            charge: required("Precursor.Charge")?,
+            q_value: required("Q.Value")?,
+            run: optional("Run"),
+            pg_q_value: optional("PG.Q.Value"),
+            global_q_value: optional("Global.Q.Value"),
+            rt: optional("RT"),
+            precursor_mz: optional("Precursor.Mz"),
+            quantity: optional("Precursor.Quantity"),
+            cscore: optional("CScore"),
+            pep: optional("PEP"),
+        })
+    }
+}
+
+/// Convert a DIA-NN modified sequence, e.g. `PEPC(UniMod:4)TIDE`, to UNIMOD bracket notation,
+/// e.g. `PEPC[UNIMOD:4]TIDE`
+#[pyfunction]
+pub fn diann_to_unimod_sequence(modified_sequence: &str) -> String {
+    static UNIMOD: OnceLock<Regex> = OnceLock::new();
+    let re = UNIMOD.get_or_init(|| Regex::new(r"\((?i:unimod):(\d+)\)").unwrap());
+    re.replace_all(modified_sequence, "[UNIMOD:$1]").into_owned()
+}
+
+fn parse_field<T: std::str::FromStr>(fields: &[&str], column: usize, line: usize) -> PyResult<T> {
+    let value = fields.get(column).ok_or_else(|| {
+        PyValueError::new_err(format!("DIA-NN report line {} has too few columns", line))
+    })?;
+    value.trim().parse().map_err(|_| {
+        PyValueError::new_err(format!("DIA-NN report line {}: could not parse value {}", line, value))
+    })
+}
+
+fn parse_optional<T: std::str::FromStr>(fields: &[&str], column: Option<usize>) -> Option<T> {
+    column
+        .and_then(|c| fields.get(c))
+        .and_then(|v| v.trim().parse().ok())
+}
+
+/// Parse the content of a DIA-NN report into PSMs
+fn parse_diann_report(content: &str) -> PyResult<Vec<PyFeature>> {
+    let mut lines = content.lines();
+    let header = lines
+        .next()
+        .ok_or_else(|| PyValueError::new_err("DIA-NN report is empty"))?;
+    let columns = DiannColumns::from_header(header)?;
+
+    let modification = Regex::new(r"\([^)]*\)").unwrap();
+    let mut runs: HashMap<String, usize> = HashMap::new();
+    let mut psms = Vec::new();
+
+    for (i, line) in lines.enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+        let line_number = i + 2;
+        let fields: Vec<&str> = line.trim_end_matches('\r').split('\t').collect();
+
+        let charge: u8 = parse_field(&fields, columns.charge, line_number)?;
+        let spectrum_q: f32 = parse_field(&fields, columns.q_value, line_number)?;
+        let precursor_id: String = parse_field(&fields, columns.precursor_id, line_number)?;
+        let modified_sequence: String = parse_field(&fields, columns.modified_sequence, line_number)?;
+        let stripped_sequence: String = parse_optional(&fields, columns.stripped_sequence)
+            .unwrap_or_else(|| modification.replace_all(&modified_sequence, "").into_owned());
+
+        let run: String = parse_optional(&fields, columns.run).unwrap_or_default();
+        let next_file_id = runs.len();
+        let file_id = *runs.entry(run).or_insert(next_file_id);
+
+        let calcmass = parse_optional::<f32>(&fields, columns.precursor_mz)
+            .map(|mz| (mz - PROTON) * charge as f32)
+            .unwrap_or(0.0);
+
+        let feature = Feature {
+            psm_id: psms.len(),
+            peptide_len: stripped_sequence.len(),
+            spec_id: precursor_id,
+            file_id,
+            // DIA-NN reports identified (target) precursors only
+            label: 1,
+            expmass: calcmass,
+            calcmass,
+            charge,
+            rt: parse_optional(&fields, columns.rt).unwrap_or(0.0),
+            missed_cleavages: tryptic_missed_cleavages(stripped_sequence.as_bytes()) as u8,
+            discriminant_score: parse_optional(&fields, columns.cscore).unwrap_or(0.0),
+            posterior_error: parse_optional(&fields, columns.pep).unwrap_or(1.0),
+            spectrum_q,
+            peptide_q: parse_optional(&fields, columns.global_q_value).unwrap_or(1.0),
+            protein_q: parse_optional(&fields, columns.pg_q_value).unwrap_or(1.0),
+            ms2_intensity: parse_optional(&fields, columns.quantity).unwrap_or(0.0),
+            ..default_feature()
+        };
+
+        psms.push(PyFeature {
+            peptide_sequence: Some(diann_to_unimod_sequence(&modified_sequence)),
+            ..PyFeature::from(feature)
+        });
+    }
+
+    Ok(psms)
+}
+
+/// Read a DIA-NN `report.tsv` into PSMs. The precursor id is stored as spec_id, Q.Value as
+/// spectrum_q, Global.Q.Value as peptide_q, PG.Q.Value as protein_q, CScore as discriminant_score
+/// and Precursor.Quantity as ms2_intensity. Runs are numbered in order of appearance as file_id.
+/// The PSMs do not refer to a database peptide, Modified.Sequence is kept in UNIMOD bracket
+/// notation as peptide_sequence
+#[pyfunction]
+pub fn read_diann_report(path: &str) -> PyResult<Vec<PyFeature>> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| PyValueError::new_err(format!("Could not read DIA-NN report {}: {}", path, e)))?;
+    parse_diann_report(&content)
+}
+
+/// Peptide of a PSM in Percolator notation (flanking residues unknown), with modification masses
+fn pin_peptide(db: &IndexedDatabase, feature: &Feature) -> (String, String) {
+    let peptide = &db[feature.peptide_idx];
+    let mut sequence = String::from("-.");
+    for (i, residue) in peptide.sequence.iter().enumerate() {
+        sequence.push(*residue as char);
+        let m = peptide.modifications.get(i).copied().unwrap_or_default();
+        if m != 0.0 {
+            sequence.push_str(&format!("[{:+.4}]", m));
+        }
+    }
+    sequence.push_str(".-");
+    let proteins = peptide.proteins.iter().map(|p| p.as_str()).collect::<Vec<_>>().join("\t");
+    (sequence, proteins)
+}
+
+/// Write PSMs as Percolator input (PIN): SpecId, Label, ScanNr, the requested re-scoring features
+/// (all of `get_feature_names` if empty), Peptide and Proteins. With a database, every PSM must
+/// refer to one of its peptides. Without a database, the imported peptide sequence or else the
+/// peptide index is written in place of the peptide sequence
+#[pyfunction]
+pub fn write_pin_file(
+    psms: Vec<PyFeature>,
+    path: &str,
+    feature_names: Vec<String>,
+    db: Option<&PyIndexedDatabase>,
+) -> PyResult<()> {
+    let feature_names: Vec<String> = if feature_names.is_empty() {
+        FEATURE_NAMES.iter().map(|s| s.to_string()).collect()
+    } else {
+        feature_names
+    };
+    let indices = feature_names
+        .iter()
+        .map(|name| {
+            FEATURE_NAMES.iter().position(|n| *n == name.as_str()).ok_or_else(|| {
+                PyValueError::new_err(format!("Unknown feature {}, see get_feature_names", name))
+            })
+        })
+        .collect::<PyResult<Vec<usize>>>()?;
+
+    if let Some(db) = db {
+        check_peptide_indices(&db.inner, &psms)?;
+    }
+
+    let io_error = |e: std::io::Error| PyValueError::new_err(format!("Could not write PIN file {}: {}", path, e));
+    let mut writer = BufWriter::new(File::create(path).map_err(io_error)?);
+
+    writeln!(writer, "SpecId\tLabel\tScanNr\t{}\tPeptide\tProteins", feature_names.join("\t")).map_err(io_error)?;
+    for psm in &psms {
+        let values = feature_values(psm);
+        let features: Vec<String> = indices.iter().map(|&i| values[i].to_string()).collect();
+        let (peptide, proteins) = match db {
+            Some(db) => pin_peptide(&db.inner, &psm.inner),
+            None => match &psm.peptide_sequence {
+                Some(sequence) => (sequence.clone(), String::new()),
+                None => (psm.inner.peptide_idx.0.to_string(), String::new()),
+            },
+        };
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            psm.inner.spec_id,
+            psm.inner.label,
+            psm.inner.psm_id,
+            features.join("\t"),
+            peptide,
+            proteins
+        )
+        .map_err(io_error)?;
+    }
+    writer.flush().map_err(io_error)?;
+    Ok(())
+}
+
+/// Read PSMs from a Percolator input (PIN) file: SpecId, Label, ScanNr (as psm_id) and all columns
+/// named like a re-scoring feature are recovered. The peptide index is recovered only if the
+/// Peptide column holds one (files written by `write_pin_file` without database), otherwise the
+/// PSMs do not refer to a database peptide and the Peptide column is kept as peptide_sequence
+#[pyfunction]
+pub fn read_pin_file(path: &str) -> PyResult<Vec<PyFeature>> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| PyValueError::new_err(format!("Could not read PIN file {}: {}", path, e)))?;
+    let mut lines = content.lines();
+    let header: Vec<&str> = lines
+        .next()
+        .ok_or_else(|| PyValueError::new_err("PIN file is empty"))?
+        .trim_end_matches('\r')
+        .split('\t')
+        .collect();
+
+    let column = |name: &str| {
+        header
+            .iter()
+            .position(|c| c.eq_ignore_ascii_case(name))
+            .ok_or_else(|| PyValueError::new_err(format!("PIN file is missing required column {}", name)))
+    };
+    let (spec_id, label, scan_nr, peptide) = (column("SpecId")?, column("Label")?, column("ScanNr")?, column("Peptide")?);
+    let feature_columns: Vec<(usize, &str)> = header
+        .iter()
+        .enumerate()
+        .filter(|(_, name)| FEATURE_NAMES.contains(*name))
+        .map(|(i, name)| (i, *name))
+        .collect();
+
+    let mut psms = Vec::new();
+    for (i, line) in lines.enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+        let line_number = i + 2;
+        let fields: Vec<&str> = line.trim_end_matches('\r').split('\t').collect();
+
+        let mut psm = PyFeature::from(Feature {
+            spec_id: parse_field(&fields, spec_id, line_number)?,
+            label: parse_field(&fields, label, line_number)?,
+            psm_id: parse_field(&fields, scan_nr, line_number)?,
+            ..default_feature()
+        });
+        match parse_optional::<u32>(&fields, Some(peptide)) {
+            Some(idx) => psm.inner.peptide_idx = PeptideIx(idx),
+            None => psm.peptide_sequence = fields.get(peptide).filter(|s| !s.is_empty()).map(|s| s.to_string()),
+        }
+        for &(column, name) in &feature_columns {
+            let value: f64 = parse_field(&fields, column, line_number)?;
+            set_feature_value(&mut psm, name, value);
+        }
+
+        psms.push(psm);
+    }
+
+    Ok(psms)
+}
+
+#[pymodule]
+pub fn io(_py: Python, m: &PyModule) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(read_diann_report, m)?)?;
+    m.add_function(wrap_pyfunction!(diann_to_unimod_sequence, m)?)?;
+    m.add_function(wrap_pyfunction!(write_pin_file, m)?)?;
+    m.add_function(wrap_pyfunction!(read_pin_file, m)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diann_report_keeps_modified_sequence() {
+        let report = "Run\tPrecursor.Id\tModified.Sequence\tPrecursor.Charge\tQ.Value\n\
+                      run_a\tPEPC(UniMod:4)TIDEK2\tPEPC(UniMod:4)TIDEK\t2\t0.001\n";
+        let psms = parse_diann_report(report).unwrap();
+
+        assert_eq!(psms.len(), 1);
+        assert_eq!(psms[0].inner.peptide_idx, UNKNOWN_PEPTIDE);
+        assert_eq!(psms[0].peptide_sequence.as_deref(), Some("PEPC[UNIMOD:4]TIDEK"));
+        assert_eq!(psms[0].inner.peptide_len, 9);
+    }
+
+    #[test]
+    fn pin_round_trip_keeps_imported_sequence() {
+        let path = std::env::temp_dir().join(format!("sagepy_pin_round_trip_{}.pin", std::process::id()));
+        let path = path.to_str().unwrap();
+        let psms = vec![PyFeature {
+            peptide_sequence: Some("PEPC[UNIMOD:4]TIDEK".to_string()),
+            ..PyFeature::from(Feature { spec_id: "run_a.1".to_string(), psm_id: 7, ..default_feature() })
+        }];
+
+        write_pin_file(psms, path, vec!["hyperscore".to_string()], None).unwrap();
+        let read = read_pin_file(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(read.len(), 1);
+        assert_eq!(read[0].inner.spec_id, "run_a.1");
+        assert_eq!(read[0].inner.psm_id, 7);
+        assert_eq!(read[0].inner.peptide_idx, UNKNOWN_PEPTIDE);
+        assert_eq!(read[0].peptide_sequence.as_deref(), Some("PEPC[UNIMOD:4]TIDEK"));
+    }
+
+    #[test]
+    fn pin_round_trip_keeps_labels_peptides_and_all_features() {
+        let path = std::env::temp_dir().join(format!("sagepy_pin_features_{}.pin", std::process::id()));
+        let path = path.to_str().unwrap();
+        let psm = |label: i32, peptide_idx: u32, hyperscore: f64| {
+            let mut psm = PyFeature::from(Feature {
+                peptide_idx: PeptideIx(peptide_idx),
+                spec_id: format!("run_a.{}", peptide_idx),
+                label,
+                hyperscore,
+                delta_next: 3.5,
+                matched_peaks: 12,
+                poisson: -4.25,
+                delta_mass: 1.5,
+                rt: 33.125,
+                charge: 3,
+                spectrum_q: 0.0125,
+                ..default_feature()
+            });
+            psm.ms1_isotope_score = 0.75;
+            psm
+        };
+        let psms = vec![psm(1, 0, 41.5), psm(-1, 1, 12.0)];
+
+        write_pin_file(psms.clone(), path, Vec::new(), None).unwrap();
+        let read = read_pin_file(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(read.len(), 2);
+        for (read, written) in read.iter().zip(psms.iter()) {
+            assert_eq!(read.inner.spec_id, written.inner.spec_id);
+            assert_eq!(read.inner.label, written.inner.label);
+            assert_eq!(read.inner.peptide_idx, written.inner.peptide_idx);
+            assert_eq!(feature_values(read), feature_values(written));
+        }
+    }
+
+    #[test]
+    fn pin_file_with_database_writes_modified_peptides_and_proteins() {
+        use crate::py_scoring::tests::{search_database, search_peptide};
+
+        let path = std::env::temp_dir().join(format!("sagepy_pin_peptides_{}.pin", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut peptide = search_peptide("PEPTMK", false);
+        peptide.modifications[4] = 15.9949;
+        let db = search_database(vec![peptide]);
+        let psm = PyFeature::from(Feature { peptide_idx: PeptideIx(0), ..default_feature() });
+
+        write_pin_file(vec![psm], path, vec!["hyperscore".to_string()], Some(&db)).unwrap();
+        let content = std::fs::read_to_string(path).unwrap();
+        let read = read_pin_file(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        let row: Vec<&str> = content.lines().nth(1).unwrap().split('\t').collect();
+        assert_eq!(row[4..], ["-.PEPTM[+15.9949]K.-", "sp|P1|PEPTMK"]);
+        assert_eq!(read[0].inner.peptide_idx, UNKNOWN_PEPTIDE);
+        assert_eq!(read[0].peptide_sequence.as_deref(), Some("-.PEPTM[+15.9949]K.-"));
+
+        let unknown = PyFeature::from(Feature { peptide_idx: PeptideIx(1), ..default_feature() });
+        assert!(write_pin_file(vec![unknown], path, Vec::new(), Some(&db)).is_err());
+    }
+}
@@ -1,7 +1,16 @@
+use crate::py_enzyme::NON_SPECIFIC_RESIDUES;
+use crate::py_mass::PyTolerance;
 use crate::py_peptide::PyPeptide;
+use crate::py_scoring::{PyFragments, NH3};
+use pyo3::exceptions::PyValueError;
 use pyo3::prelude::*;
-use sage_core::ion_series::{Ion, Kind};
-use sage_core::mass::monoisotopic;
+use sage_core::enzyme::Position;
+use sage_core::ion_series::{Ion, IonSeries, Kind};
+use sage_core::mass::{monoisotopic, H2O, PROTON};
+use sage_core::peptide::Peptide;
+use sage_core::scoring::Fragments;
+use std::collections::HashMap;
+use std::sync::Arc;
 
 #[pyclass]
 #[derive(Clone)]
@@ -12,14 +21,15 @@ pub struct PyKind {
 #[pymethods]
 impl PyKind {
     #[new]
-    fn new(kind: String) -> PyResult<Self> {
+    pub fn new(kind: String) -> PyResult<Self> {
         match kind.to_lowercase().as_str() {
             "a" => Ok(PyKind { inner: Kind::A }),
             "b" => Ok(PyKind { inner: Kind::B }),
             "c" => Ok(PyKind { inner: Kind::C }),
             "x" => Ok(PyKind { inner: Kind::X }),
             "y" => Ok(PyKind { inner: Kind::Y }),
-            "z" => Ok(PyKind { inner: Kind::Z }),
+            // z-dot ions (y - NH3) produced by ETD / ECD
+            "z" | "z-dot" | "zdot" => Ok(PyKind { inner: Kind::Z }),
             _ => Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
                 "Invalid Kind value: {}",
                 kind
@@ -32,19 +42,30 @@ impl PyKind {
 }
 
 #[pyclass]
+#[derive(Clone)]
 pub struct PyIon {
     pub inner: Ion,
+    /// Mass (Da) lost from the regular ion, 0 for regular ions
+    pub neutral_loss: f32,
 }
 
 #[pymethods]
 impl PyIon {
     #[new]
-    fn new(kind: PyKind, monoisotopic_mass: f32) -> PyResult<Self> {
+    fn new(kind: PyKind, monoisotopic_mass: f32, neutral_loss: Option<f32>) -> PyResult<Self> {
         let inner_ion = Ion {
             kind: kind.inner, // Conversion from PyKind to Rust Kind
             monoisotopic_mass,
         };
-        Ok(PyIon { inner: inner_ion })
+        Ok(PyIon {
+            inner: inner_ion,
+            neutral_loss: neutral_loss.unwrap_or_default(),
+        })
+    }
+
+    #[getter]
+    fn neutral_loss(&self) -> f32 {
+        self.neutral_loss
     }
 
     // Getter methods for accessing Ion properties
@@ -132,10 +153,232 @@ impl PyIonSeries {
                     kind: self.kind.inner.clone(),
                     monoisotopic_mass: cm,
                 },
+                neutral_loss: 0.0,
             });
         }
         Ok(ions)
     }
+
+    /// The ions of the series followed by their water and ammonia loss ions, losses are only
+    /// generated for the kind (b or y) of this series
+    pub fn with_neutral_losses(
+        &self,
+        b_loss_water: bool,
+        b_loss_nh3: bool,
+        y_loss_water: bool,
+        y_loss_nh3: bool,
+    ) -> PyResult<Vec<PyIon>> {
+        let (water, ammonia) = match self.kind.inner {
+            Kind::B => (b_loss_water, b_loss_nh3),
+            Kind::Y => (y_loss_water, y_loss_nh3),
+            _ => (false, false),
+        };
+        let losses: Vec<f32> = [(water, H2O), (ammonia, NH3)]
+            .into_iter()
+            .filter_map(|(enabled, loss)| enabled.then_some(loss))
+            .collect();
+
+        let ions = self.get_ion_series()?;
+        let loss_ions = generate_neutral_loss_ions(ions.clone(), losses);
+        Ok(ions.into_iter().chain(loss_ions).collect())
+    }
+}
+
+/// Neutral loss variants of ions, one per ion and loss (Da), ordered by loss
+#[pyfunction]
+pub fn generate_neutral_loss_ions(base_ions: Vec<PyIon>, losses: Vec<f32>) -> Vec<PyIon> {
+    losses
+        .iter()
+        .flat_map(|loss| {
+            base_ions.iter().map(move |ion| PyIon {
+                inner: Ion {
+                    kind: ion.inner.kind,
+                    monoisotopic_mass: ion.inner.monoisotopic_mass - loss,
+                },
+                neutral_loss: ion.neutral_loss + loss,
+            })
+        })
+        .collect()
+}
+
+/// Theoretical fragments of a peptide, without a database search. `modifications` maps 0-based
+/// residue positions to mass deltas, fragment charges default to 1 up to the precursor charge - 1
+/// and ions with an ordinal below `min_ordinal` are skipped. mz_experimental and intensities are 0
+#[pyfunction]
+pub fn compute_theoretical_spectrum(
+    sequence: &str,
+    modifications: HashMap<u32, f32>,
+    charge: u8,
+    fragment_charges: Vec<u8>,
+    ion_kinds: Vec<PyKind>,
+    min_ordinal: usize,
+) -> PyResult<PyFragments> {
+    let sequence = sequence.to_uppercase().into_bytes();
+    if let Some(residue) = sequence.iter().find(|r| !NON_SPECIFIC_RESIDUES.as_bytes().contains(r)) {
+        return Err(PyValueError::new_err(format!("Invalid residue: {}", *residue as char)));
+    }
+    if let Some(position) = modifications.keys().find(|p| **p as usize >= sequence.len()) {
+        return Err(PyValueError::new_err(format!(
+            "Modification position {} is outside of the sequence",
+            position
+        )));
+    }
+
+    let mods: Vec<f32> = (0..sequence.len())
+        .map(|i| modifications.get(&(i as u32)).copied().unwrap_or(0.0))
+        .collect();
+    let mass = sequence.iter().map(|r| monoisotopic(*r)).sum::<f32>() + mods.iter().sum::<f32>() + H2O;
+    let peptide = Peptide {
+        decoy: false,
+        sequence: Arc::from(sequence.into_boxed_slice()),
+        modifications: mods,
+        nterm: None,
+        cterm: None,
+        monoisotopic: mass,
+        missed_cleavages: 0,
+        position: Position::Full,
+        proteins: Vec::new(),
+        semi_enzymatic: false,
+    };
+
+    let fragment_charges = if fragment_charges.is_empty() {
+        (1..charge.max(2)).collect()
+    } else {
+        fragment_charges
+    };
+
+    let mut fragments = Fragments {
+        charges: Vec::new(),
+        kinds: Vec::new(),
+        fragment_ordinals: Vec::new(),
+        intensities: Vec::new(),
+        mz_calculated: Vec::new(),
+        mz_experimental: Vec::new(),
+    };
+    for kind in ion_kinds.iter().map(|k| k.inner) {
+        for (i, ion) in IonSeries::new(&peptide, kind).enumerate() {
+            let ordinal = match kind {
+                Kind::A | Kind::B | Kind::C => i + 1,
+                Kind::X | Kind::Y | Kind::Z => peptide.sequence.len() - i - 1,
+            };
+            if ordinal < min_ordinal {
+                continue;
+            }
+            for &z in fragment_charges.iter().filter(|z| **z > 0) {
+                fragments.charges.push(z as i32);
+                fragments.kinds.push(kind);
+                fragments.fragment_ordinals.push(ordinal as i32);
+                fragments.intensities.push(0.0);
+                fragments.mz_calculated.push((ion.monoisotopic_mass + z as f32 * PROTON) / z as f32);
+                fragments.mz_experimental.push(0.0);
+            }
+        }
+    }
+
+    Ok(PyFragments {
+        neutral_losses: vec![0.0; fragments.charges.len()],
+        inner: fragments,
+    })
+}
+
+/// A theoretical ion of a peptide and the observed peak it matches, or an observed peak no ion
+/// explains (empty ion_type, theoretical_mz set to the observed m/z)
+#[pyclass]
+#[derive(Clone)]
+pub struct PyFragmentAnnotation {
+    #[pyo3(get)]
+    pub ion_type: String,
+    #[pyo3(get)]
+    pub ordinal: u32,
+    #[pyo3(get)]
+    pub charge: i32,
+    #[pyo3(get)]
+    pub theoretical_mz: f32,
+    #[pyo3(get)]
+    pub observed_mz: f32,
+    #[pyo3(get)]
+    pub observed_intensity: f32,
+    #[pyo3(get)]
+    pub ppm_error: f32,
+    #[pyo3(get)]
+    pub matched: bool,
+}
+
+/// Annotate an observed spectrum with the theoretical ions of a peptide (fragment charges 1 up to
+/// the precursor charge - 1). Each ion is matched to the most intense peak within
+/// `fragment_tol`, observed peaks matching no ion are reported as unannotated entries. The
+/// annotation is sorted by theoretical m/z
+#[pyfunction]
+pub fn annotate_spectrum(
+    sequence: &str,
+    modifications: HashMap<u32, f32>,
+    charge: u8,
+    observed_mz: Vec<f32>,
+    observed_intensity: Vec<f32>,
+    fragment_tol: PyTolerance,
+    ion_kinds: Vec<PyKind>,
+) -> PyResult<Vec<PyFragmentAnnotation>> {
+    if observed_mz.len() != observed_intensity.len() {
+        return Err(PyValueError::new_err(format!(
+            "observed_mz and observed_intensity must have the same length, got {} and {}",
+            observed_mz.len(),
+            observed_intensity.len()
+        )));
+    }
+    let theoretical =
+        compute_theoretical_spectrum(sequence, modifications, charge, Vec::new(), ion_kinds, 1)?.inner;
+
+    let mut explained = vec![false; observed_mz.len()];
+    let mut annotation: Vec<PyFragmentAnnotation> = (0..theoretical.mz_calculated.len())
+        .map(|i| {
+            let mz = theoretical.mz_calculated[i];
+            let (lo, hi) = fragment_tol.inner.bounds(mz);
+            let peak = (0..observed_mz.len())
+                .filter(|&j| observed_mz[j] >= lo && observed_mz[j] <= hi)
+                .max_by(|&a, &b| observed_intensity[a].total_cmp(&observed_intensity[b]));
+            if let Some(j) = peak {
+                explained[j] = true;
+            }
+            let (observed, intensity) = peak.map_or((0.0, 0.0), |j| (observed_mz[j], observed_intensity[j]));
+            PyFragmentAnnotation {
+                ion_type: format!("{:?}", theoretical.kinds[i]).to_lowercase(),
+                ordinal: theoretical.fragment_ordinals[i] as u32,
+                charge: theoretical.charges[i],
+                theoretical_mz: mz,
+                observed_mz: observed,
+                observed_intensity: intensity,
+                ppm_error: peak.map_or(0.0, |_| (observed - mz) / mz * 1e6),
+                matched: peak.is_some(),
+            }
+        })
+        .collect();
+
+    annotation.extend(
+        (0..observed_mz.len())
+            .filter(|&j| !explained[j])
+            .map(|j| PyFragmentAnnotation {
+                ion_type: String::new(),
+                ordinal: 0,
+                charge: 0,
+                theoretical_mz: observed_mz[j],
+                observed_mz: observed_mz[j],
+                observed_intensity: observed_intensity[j],
+                ppm_error: 0.0,
+                matched: false,
+            }),
+    );
+    annotation.sort_by(|a, b| a.theoretical_mz.total_cmp(&b.theoretical_mz));
+    Ok(annotation)
+}
+
+/// (m/z, intensity) of the observed peaks no theoretical ion explains
+#[pyfunction]
+pub fn unannotated_peaks(annotation: Vec<PyFragmentAnnotation>) -> Vec<(f32, f32)> {
+    annotation
+        .into_iter()
+        .filter(|a| a.ion_type.is_empty())
+        .map(|a| (a.observed_mz, a.observed_intensity))
+        .collect()
 }
 
 #[pymodule]
@@ -143,5 +386,51 @@ pub fn ion_series(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_class::<PyKind>()?;
     m.add_class::<PyIon>()?;
     m.add_class::<PyIonSeries>()?;
+    m.add_function(wrap_pyfunction!(compute_theoretical_spectrum, m)?)?;
+    m.add_function(wrap_pyfunction!(generate_neutral_loss_ions, m)?)?;
+    m.add_class::<PyFragmentAnnotation>()?;
+    m.add_function(wrap_pyfunction!(annotate_spectrum, m)?)?;
+    m.add_function(wrap_pyfunction!(unannotated_peaks, m)?)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn theoretical_mz(fragments: &PyFragments, kind: Kind, ordinal: i32, charge: i32) -> f32 {
+        let f = &fragments.inner;
+        let i = (0..f.kinds.len())
+            .find(|&i| f.kinds[i] == kind && f.fragment_ordinals[i] == ordinal && f.charges[i] == charge)
+            .unwrap();
+        f.mz_calculated[i]
+    }
+
+    #[test]
+    fn theoretical_spectrum_has_b_and_y_ions_from_the_minimum_ordinal() {
+        let kinds = || vec![PyKind { inner: Kind::B }, PyKind { inner: Kind::Y }];
+        let fragments = compute_theoretical_spectrum("peptidek", HashMap::new(), 2, Vec::new(), kinds(), 2).unwrap();
+
+        // b2 to b7 and y2 to y7, singly charged below a doubly charged precursor
+        assert_eq!(fragments.inner.kinds.len(), 12);
+        assert!(fragments.inner.charges.iter().all(|z| *z == 1));
+        assert!((theoretical_mz(&fragments, Kind::B, 2, 1) - 227.1026).abs() < 1e-3);
+        assert!((theoretical_mz(&fragments, Kind::Y, 2, 1) - 276.1554).abs() < 1e-3);
+
+        // +10 Da on E2 and doubly charged fragments of a triply charged precursor
+        let modifications = HashMap::from([(1, 10.0)]);
+        let modified = compute_theoretical_spectrum("PEPTIDEK", modifications, 3, Vec::new(), kinds(), 2).unwrap();
+        assert_eq!(modified.inner.kinds.len(), 24);
+        assert!((theoretical_mz(&modified, Kind::B, 2, 1) - 237.1026).abs() < 1e-3);
+        assert!((theoretical_mz(&modified, Kind::B, 2, 2) - 119.0550).abs() < 1e-3);
+        assert!((theoretical_mz(&modified, Kind::Y, 2, 1) - 276.1554).abs() < 1e-3);
+    }
+
+    #[test]
+    fn theoretical_spectrum_rejects_unknown_residues_and_positions() {
+        let kinds = || vec![PyKind { inner: Kind::B }];
+        assert!(compute_theoretical_spectrum("PEPTJDEK", HashMap::new(), 2, Vec::new(), kinds(), 1).is_err());
+        let outside = HashMap::from([(8, 10.0)]);
+        assert!(compute_theoretical_spectrum("PEPTIDEK", outside, 2, Vec::new(), kinds(), 1).is_err());
+    }
+}
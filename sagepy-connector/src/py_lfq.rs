@@ -1,7 +1,19 @@
+use pyo3::exceptions::PyValueError;
 use pyo3::prelude::*;
+use rayon::prelude::*;
 use sage_core::lfq::{FeatureMap, IntegrationStrategy, LfqSettings, PeakScoringStrategy, PrecursorId, PrecursorRange};
 use sage_core::lfq::PrecursorId::{Charged, Combined};
-use crate::py_database::PyPeptideIx;
+use crate::py_database::{PyIndexedDatabase, PyPeptideIx};
+use crate::py_enzyme::{digest_protein, PyEnzyme};
+use crate::py_fasta::PyFasta;
+use crate::py_retention_alignment::{Loess, ROBUSTNESS_ITERATIONS};
+use crate::py_scoring::{check_peptide_indices, solve_linear, PyFeature};
+use crate::py_spectrum::PyProcessedSpectrum;
+use crate::py_tmt::{normalize_tmt_intensities, vsn_normalize};
+use crate::py_utility::median;
+use sage_core::database::PeptideIx;
+use sage_core::spectrum::ProcessedSpectrum;
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 #[pyclass]
 pub struct PyPeakScoringStrategy {
@@ -323,6 +335,1193 @@ impl PyQuery {
     }
 }
 
+#[pyclass]
+#[derive(Clone, Debug)]
+pub struct PyFoldChangeResult {
+    pub log2_fc: f32,
+    pub t_statistic: f32,
+    pub p_value: f32,
+    pub adjusted_p_value: f32,
+}
+
+#[pymethods]
+impl PyFoldChangeResult {
+    #[getter]
+    pub fn log2_fc(&self) -> f32 {
+        self.log2_fc
+    }
+
+    #[getter]
+    pub fn t_statistic(&self) -> f32 {
+        self.t_statistic
+    }
+
+    #[getter]
+    pub fn p_value(&self) -> f32 {
+        self.p_value
+    }
+
+    #[getter]
+    pub fn adjusted_p_value(&self) -> f32 {
+        self.adjusted_p_value
+    }
+}
+
+/// Natural logarithm of the gamma function (Lanczos approximation)
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [
+        76.18009172947146,
+        -86.50532032941677,
+        24.01409824083091,
+        -1.231739572450155,
+        0.1208650973866179e-2,
+        -0.5395239384953e-5,
+    ];
+    let tmp = x + 5.5 - (x + 0.5) * (x + 5.5).ln();
+    let mut y = x;
+    let series = COEFFICIENTS.iter().fold(1.000000000190015, |acc, c| {
+        y += 1.0;
+        acc + c / y
+    });
+    -tmp + (2.5066282746310005 * series / x).ln()
+}
+
+/// Continued fraction expansion of the regularized incomplete beta function
+fn beta_continued_fraction(a: f64, b: f64, x: f64) -> f64 {
+    const MAX_ITERATIONS: usize = 200;
+    const EPS: f64 = 3.0e-12;
+    const FP_MIN: f64 = 1.0e-300;
+
+    let (qab, qap, qam) = (a + b, a + 1.0, a - 1.0);
+    let mut c = 1.0;
+    let mut d = 1.0 - qab * x / qap;
+    if d.abs() < FP_MIN {
+        d = FP_MIN;
+    }
+    d = 1.0 / d;
+    let mut h = d;
+
+    for m in 1..=MAX_ITERATIONS {
+        let m = m as f64;
+        let m2 = 2.0 * m;
+
+        let aa = m * (b - m) * x / ((qam + m2) * (a + m2));
+        d = 1.0 + aa * d;
+        if d.abs() < FP_MIN {
+            d = FP_MIN;
+        }
+        c = 1.0 + aa / c;
+        if c.abs() < FP_MIN {
+            c = FP_MIN;
+        }
+        d = 1.0 / d;
+        h *= d * c;
+
+        let aa = -(a + m) * (qab + m) * x / ((a + m2) * (qap + m2));
+        d = 1.0 + aa * d;
+        if d.abs() < FP_MIN {
+            d = FP_MIN;
+        }
+        c = 1.0 + aa / c;
+        if c.abs() < FP_MIN {
+            c = FP_MIN;
+        }
+        d = 1.0 / d;
+        let delta = d * c;
+        h *= delta;
+
+        if (delta - 1.0).abs() < EPS {
+            break;
+        }
+    }
+    h
+}
+
+/// Regularized incomplete beta function I_x(a, b)
+fn incomplete_beta(a: f64, b: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x >= 1.0 {
+        return 1.0;
+    }
+    let front = (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln()).exp();
+    if x < (a + 1.0) / (a + b + 2.0) {
+        front * beta_continued_fraction(a, b, x) / a
+    } else {
+        1.0 - front * beta_continued_fraction(b, a, 1.0 - x) / b
+    }
+}
+
+/// Two-sided p-value of a t-statistic with `df` degrees of freedom
+fn student_t_p_value(t: f64, df: f64) -> f64 {
+    incomplete_beta(df / 2.0, 0.5, df / (df + t * t))
+}
+
+fn mean_and_variance(values: &[f64]) -> (f64, f64) {
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.0);
+    (mean, variance)
+}
+
+/// log2 transform valid (present, positive and finite) intensities
+fn log2_valid(intensities: &[Option<f32>]) -> Vec<f64> {
+    intensities
+        .iter()
+        .flatten()
+        .filter(|v| v.is_finite() && **v > 0.0)
+        .map(|v| (*v as f64).log2())
+        .collect()
+}
+
+/// Welch's t-test on log2 intensities, log2_fc is reported as log2(B / A). If one of the groups
+/// has fewer valid values than required, log2_fc and t_statistic are NaN and the p-value is 1
+fn welch_test(group_a: &[Option<f32>], group_b: &[Option<f32>], min_valid_values: u32) -> PyFoldChangeResult {
+    let a = log2_valid(group_a);
+    let b = log2_valid(group_b);
+    let min_valid = (min_valid_values as usize).max(2);
+
+    if a.len() < min_valid || b.len() < min_valid {
+        return PyFoldChangeResult {
+            log2_fc: f32::NAN,
+            t_statistic: f32::NAN,
+            p_value: 1.0,
+            adjusted_p_value: 1.0,
+        };
+    }
+
+    let (mean_a, var_a) = mean_and_variance(&a);
+    let (mean_b, var_b) = mean_and_variance(&b);
+    let (se_a, se_b) = (var_a / a.len() as f64, var_b / b.len() as f64);
+    let log2_fc = mean_b - mean_a;
+    let se = (se_a + se_b).sqrt();
+
+    let (t, p) = if se > 0.0 {
+        let t = log2_fc / se;
+        let df = (se_a + se_b).powi(2)
+            / (se_a.powi(2) / (a.len() as f64 - 1.0) + se_b.powi(2) / (b.len() as f64 - 1.0));
+        (t, student_t_p_value(t, df))
+    } else if log2_fc != 0.0 {
+        (log2_fc.signum() * f64::INFINITY, 0.0)
+    } else {
+        (0.0, 1.0)
+    };
+
+    PyFoldChangeResult {
+        log2_fc: log2_fc as f32,
+        t_statistic: t as f32,
+        p_value: p as f32,
+        adjusted_p_value: p as f32,
+    }
+}
+
+/// Benjamini-Hochberg adjustment of the p-values of all results
+fn benjamini_hochberg(results: &mut [&mut PyFoldChangeResult]) {
+    let m = results.len() as f32;
+    let mut order: Vec<usize> = (0..results.len()).collect();
+    order.sort_by(|&i, &j| results[i].p_value.total_cmp(&results[j].p_value));
+
+    let mut running_min = 1.0f32;
+    for (rank, &i) in order.iter().enumerate().rev() {
+        let adjusted = results[i].p_value * m / (rank + 1) as f32;
+        running_min = running_min.min(adjusted);
+        results[i].adjusted_p_value = running_min;
+    }
+}
+
+#[pyfunction]
+pub fn fold_change_analysis(
+    group_a_intensities: Vec<Option<f32>>,
+    group_b_intensities: Vec<Option<f32>>,
+    min_valid_values: u32,
+) -> PyFoldChangeResult {
+    welch_test(&group_a_intensities, &group_b_intensities, min_valid_values)
+}
+
+/// Test all proteins for differential abundance between two groups of samples (columns),
+/// p-values are adjusted for multiple testing using Benjamini-Hochberg FDR
+#[pyfunction]
+pub fn batch_fold_change(
+    protein_intensities: BTreeMap<String, Vec<Option<f32>>>,
+    group_a_indices: Vec<usize>,
+    group_b_indices: Vec<usize>,
+) -> BTreeMap<String, PyFoldChangeResult> {
+    let select = |intensities: &[Option<f32>], indices: &[usize]| -> Vec<Option<f32>> {
+        indices.iter().map(|&i| intensities.get(i).copied().flatten()).collect()
+    };
+
+    let mut results: BTreeMap<String, PyFoldChangeResult> = protein_intensities
+        .into_iter()
+        .map(|(protein, intensities)| {
+            let a = select(&intensities, &group_a_indices);
+            let b = select(&intensities, &group_b_indices);
+            (protein, welch_test(&a, &b, 2))
+        })
+        .collect();
+
+    let mut tested: Vec<&mut PyFoldChangeResult> =
+        results.values_mut().filter(|r| !r.log2_fc.is_nan()).collect();
+    benjamini_hochberg(&mut tested);
+
+    results
+}
+
+/// Number of distinct peptide sequences of an in-silico digest without missed cleavages within
+/// the length bounds per (accession, sequence) protein, the theoretically observable peptides of iBAQ
+fn observable_peptide_counts<'a>(
+    proteins: impl IntoIterator<Item = (&'a str, &'a str)>,
+    enzyme: &PyEnzyme,
+    min_len: usize,
+    max_len: usize,
+) -> HashMap<String, usize> {
+    proteins
+        .into_iter()
+        .map(|(accession, sequence)| {
+            let peptides = digest_protein((accession.to_string(), sequence.to_string()), enzyme, 0, min_len, max_len);
+            let distinct: HashSet<String> = peptides.into_iter().map(|p| p.sequence).collect();
+            (accession.to_string(), distinct.len())
+        })
+        .collect()
+}
+
+/// iBAQ (Schwanhäusser et al., 2011): protein intensity divided by its number of theoretically
+/// observable peptides, counted by digesting the target proteins of `fasta`. With `normalize`,
+/// values are scaled to sum to 1e9 (riBAQ). Proteins without observable peptides or missing
+/// from the FASTA are omitted
+#[pyfunction]
+pub fn compute_ibaq(
+    protein_intensities: HashMap<String, f64>,
+    fasta: &PyFasta,
+    enzyme: &PyEnzyme,
+    min_len: usize,
+    max_len: usize,
+    normalize: bool,
+) -> HashMap<String, f64> {
+    let proteins = fasta
+        .inner
+        .targets
+        .iter()
+        .map(|(accession, sequence)| -> (&str, &str) { (accession, sequence) })
+        .filter(|(accession, _)| protein_intensities.contains_key(*accession));
+    let counts = observable_peptide_counts(proteins, enzyme, min_len, max_len);
+    let mut ibaq: HashMap<String, f64> = protein_intensities
+        .into_iter()
+        .filter_map(|(protein, intensity)| {
+            let count = *counts.get(&protein)?;
+            (count > 0).then(|| (protein, intensity / count as f64))
+        })
+        .collect();
+
+    if normalize {
+        let total: f64 = ibaq.values().sum();
+        if total > 0.0 {
+            ibaq.values_mut().for_each(|v| *v = *v / total * 1e9);
+        }
+    }
+    ibaq
+}
+
+/// Intensity per (run, peptide sequence) of target PSMs, the most intense PSM of a peptide in a
+/// run. Modified forms count as the same peptide, one of their database peptides is returned per
+/// sequence to resolve its proteins
+fn peptide_run_intensities(
+    psms: &[PyFeature],
+    db: &PyIndexedDatabase,
+) -> (HashMap<(usize, String), f64>, HashMap<String, PeptideIx>) {
+    let mut intensities: HashMap<(usize, String), f64> = HashMap::new();
+    let mut peptides: HashMap<String, PeptideIx> = HashMap::new();
+    for psm in psms.iter().filter(|p| p.inner.label == 1 && p.inner.ms2_intensity > 0.0) {
+        let idx = psm.inner.peptide_idx;
+        let sequence = String::from_utf8_lossy(&db.inner[idx].sequence).to_string();
+        peptides.entry(sequence.clone()).or_insert(idx);
+        let entry = intensities.entry((psm.inner.file_id, sequence)).or_insert(0.0);
+        *entry = entry.max(psm.inner.ms2_intensity as f64);
+    }
+    (intensities, peptides)
+}
+
+/// Proteins a peptide is quantified for. With `razor`, a shared peptide only counts for the
+/// protein with the most identified peptides (ties broken by name)
+pub(crate) fn peptide_proteins(
+    db: &PyIndexedDatabase,
+    peptides: &HashSet<PeptideIx>,
+    razor: bool,
+) -> HashMap<PeptideIx, Vec<String>> {
+    let proteins_of = |idx: &PeptideIx| -> Vec<String> {
+        db.inner[*idx].proteins.iter().map(|p| p.to_string()).collect()
+    };
+    let mut peptide_counts: HashMap<String, usize> = HashMap::new();
+    for idx in peptides {
+        for protein in proteins_of(idx) {
+            *peptide_counts.entry(protein).or_default() += 1;
+        }
+    }
+
+    peptides
+        .iter()
+        .map(|idx| {
+            let proteins = proteins_of(idx);
+            if !razor || proteins.len() < 2 {
+                return (*idx, proteins);
+            }
+            let best = proteins
+                .into_iter()
+                .max_by(|a, b| peptide_counts[a].cmp(&peptide_counts[b]).then_with(|| b.cmp(a)))
+                .into_iter()
+                .collect();
+            (*idx, best)
+        })
+        .collect()
+}
+
+fn aggregate_top_n(mut values: Vec<f64>, n: usize, aggregation: &str) -> f64 {
+    values.sort_by(|a, b| b.total_cmp(a));
+    values.truncate(n.max(1));
+    match aggregation {
+        "sum" => values.iter().sum(),
+        "median" => median(values).unwrap_or(0.0),
+        _ => values.iter().sum::<f64>() / values.len() as f64,
+    }
+}
+
+fn check_aggregation(aggregation: &str) -> PyResult<()> {
+    match aggregation {
+        "sum" | "mean" | "median" => Ok(()),
+        _ => Err(PyValueError::new_err(format!(
+            "Invalid aggregation: {}, allowed values are: sum, mean, median",
+            aggregation
+        ))),
+    }
+}
+
+/// Top-N protein intensity per run (file_id): the `n` most intense peptides of a protein in a
+/// run are aggregated ("sum", "mean" or "median"), peptide intensities are the highest
+/// ms2_intensity of their target PSMs. With `normalize`, runs are scaled to a common median
+/// protein intensity
+#[pyfunction]
+pub fn top_n_quantification_per_run(
+    psms: Vec<PyFeature>,
+    db: &PyIndexedDatabase,
+    n: usize,
+    use_unique_peptides: bool,
+    aggregation: &str,
+    normalize: bool,
+) -> PyResult<HashMap<String, HashMap<usize, f64>>> {
+    check_peptide_indices(&db.inner, &psms)?;
+    check_aggregation(aggregation)?;
+    let (intensities, sequence_peptides) = peptide_run_intensities(&psms, db);
+    let peptides: HashSet<PeptideIx> = sequence_peptides.values().copied().collect();
+    let proteins = peptide_proteins(db, &peptides, use_unique_peptides);
+
+    let mut grouped: HashMap<(String, usize), Vec<f64>> = HashMap::new();
+    for ((run, sequence), intensity) in intensities {
+        for protein in &proteins[&sequence_peptides[&sequence]] {
+            grouped.entry((protein.clone(), run)).or_default().push(intensity);
+        }
+    }
+
+    let mut result: HashMap<String, HashMap<usize, f64>> = HashMap::new();
+    for ((protein, run), values) in grouped {
+        result
+            .entry(protein)
+            .or_default()
+            .insert(run, aggregate_top_n(values, n, aggregation));
+    }
+
+    if normalize {
+        let runs: HashSet<usize> = result.values().flat_map(|r| r.keys().copied()).collect();
+        let medians: HashMap<usize, f64> = runs
+            .into_iter()
+            .filter_map(|run| {
+                let median = median(result.values().filter_map(|r| r.get(&run).copied()))?;
+                (median > 0.0).then_some((run, median))
+            })
+            .collect();
+        if let Some(target) = median(medians.values().copied()) {
+            for (run, value) in result.values_mut().flat_map(|r| r.iter_mut()) {
+                if let Some(median) = medians.get(run) {
+                    *value *= target / median;
+                }
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Top-N protein intensity over all runs, see `top_n_quantification_per_run`. With `normalize`,
+/// intensities are relative to the median protein intensity
+#[pyfunction]
+pub fn top_n_quantification(
+    psms: Vec<PyFeature>,
+    db: &PyIndexedDatabase,
+    n: usize,
+    use_unique_peptides: bool,
+    aggregation: &str,
+    normalize: bool,
+) -> PyResult<HashMap<String, f64>> {
+    check_peptide_indices(&db.inner, &psms)?;
+    // peptides observed in several runs are quantified by their most intense run
+    let psms: Vec<PyFeature> = psms
+        .into_iter()
+        .map(|mut p| {
+            p.inner.file_id = 0;
+            p
+        })
+        .collect();
+    let mut result: HashMap<String, f64> =
+        top_n_quantification_per_run(psms, db, n, use_unique_peptides, aggregation, false)?
+            .into_iter()
+            .filter_map(|(protein, runs)| Some((protein, runs.get(&0).copied()?)))
+            .collect();
+
+    if normalize {
+        if let Some(median) = median(result.values().copied()).filter(|m| *m > 0.0) {
+            result.values_mut().for_each(|v| *v /= median);
+        }
+    }
+    Ok(result)
+}
+
+/// Quantile normalization of the non-missing (positive) values of each run: a value of fractional
+/// rank p within its run is replaced by the mean over runs of their quantile p
+fn quantile_normalize(intensities: Vec<Vec<f32>>, runs: usize) -> Vec<Vec<f32>> {
+    let sorted: Vec<Vec<f32>> = (0..runs)
+        .map(|r| {
+            let mut values: Vec<f32> = intensities.iter().map(|row| row[r]).filter(|v| *v > 0.0).collect();
+            values.sort_by(|a, b| a.total_cmp(b));
+            values
+        })
+        .collect();
+    let quantile = |values: &[f32], p: f64| -> f32 {
+        let position = p * (values.len() - 1) as f64;
+        let (lo, hi) = (position.floor() as usize, position.ceil() as usize);
+        values[lo] + (values[hi] - values[lo]) * (position - lo as f64) as f32
+    };
+    let observed: Vec<&Vec<f32>> = sorted.iter().filter(|v| !v.is_empty()).collect();
+    let reference = |p: f64| observed.iter().map(|v| quantile(v, p)).sum::<f32>() / observed.len() as f32;
+
+    let mut intensities = intensities;
+    for (r, values) in sorted.iter().enumerate() {
+        for row in intensities.iter_mut().filter(|row| row[r] > 0.0) {
+            let rank = values.partition_point(|v| *v < row[r]);
+            let p = match values.len() {
+                1 => 0.5,
+                n => rank as f64 / (n - 1) as f64,
+            };
+            row[r] = reference(p);
+        }
+    }
+    intensities
+}
+
+/// Normalise a run intensity matrix (one row per peptide or protein, one column per run, 0 for
+/// missing values). `normalization_method` is "none", "median" (equal run medians), "quantile"
+/// (equal run intensity distributions) or "vsn" (variance stabilizing transformation)
+#[pyfunction]
+pub fn normalize_intensities(intensities: Vec<Vec<f32>>, normalization_method: &str) -> PyResult<Vec<Vec<f32>>> {
+    let runs = intensities.first().map_or(0, |r| r.len());
+    if intensities.iter().any(|r| r.len() != runs) {
+        return Err(PyValueError::new_err("all rows must have the same number of runs"));
+    }
+    match normalization_method.to_lowercase().as_str() {
+        "none" => Ok(intensities),
+        "median" => normalize_tmt_intensities(intensities, "median"),
+        "quantile" => Ok(quantile_normalize(intensities, runs)),
+        "vsn" => vsn_normalize(intensities),
+        _ => Err(PyValueError::new_err(format!(
+            "Invalid normalization method: {}, allowed values are: none, median, quantile, vsn",
+            normalization_method
+        ))),
+    }
+}
+
+/// Number of runs of an intensity matrix with optional (missing) values, all rows must agree
+fn check_runs(intensities: &[Vec<Option<f32>>]) -> PyResult<usize> {
+    let runs = intensities.first().map_or(0, |r| r.len());
+    if intensities.iter().any(|r| r.len() != runs) {
+        return Err(PyValueError::new_err("all rows must have the same number of runs"));
+    }
+    Ok(runs)
+}
+
+/// Positive (observed) value of an optional intensity
+fn observed(value: Option<f32>) -> Option<f32> {
+    value.filter(|v| *v > 0.0)
+}
+
+/// Divide the intensities of each run (column) by the median of its observed values
+#[pyfunction]
+pub fn median_centering_normalization(intensities: Vec<Vec<Option<f32>>>) -> PyResult<Vec<Vec<Option<f32>>>> {
+    let runs = check_runs(&intensities)?;
+    let medians: Vec<Option<f64>> = (0..runs)
+        .map(|r| median(intensities.iter().filter_map(|row| observed(row[r])).map(|v| v as f64)))
+        .collect();
+    let mut intensities = intensities;
+    for row in intensities.iter_mut() {
+        for (value, median) in row.iter_mut().zip(medians.iter()) {
+            if let (Some(v), Some(m)) = (value.as_mut(), median.filter(|m| *m > 0.0)) {
+                *v = (*v as f64 / m) as f32;
+            }
+        }
+    }
+    Ok(intensities)
+}
+
+/// Quantile normalization of the observed values of each run (column), see `quantile_normalize`,
+/// missing values stay missing
+#[pyfunction]
+pub fn quantile_normalization(intensities: Vec<Vec<Option<f32>>>) -> PyResult<Vec<Vec<Option<f32>>>> {
+    let runs = check_runs(&intensities)?;
+    let dense: Vec<Vec<f32>> = intensities
+        .iter()
+        .map(|row| row.iter().map(|v| observed(*v).unwrap_or(0.0)).collect())
+        .collect();
+    Ok(quantile_normalize(dense, runs)
+        .into_iter()
+        .zip(intensities.iter())
+        .map(|(normalized, row)| {
+            normalized
+                .into_iter()
+                .zip(row.iter())
+                .map(|(n, v)| observed(*v).map(|_| n))
+                .collect()
+        })
+        .collect())
+}
+
+/// Intensity dependent normalization of each run (column) to `reference_run`: LOESS of the
+/// log2 ratio M on the mean log2 intensity A of the rows observed in both runs, the fitted ratio
+/// is removed from every observed value of the run (at A of the row, or the run's own log2
+/// intensity where the reference is missing)
+#[pyfunction]
+pub fn loess_normalization(
+    intensities: Vec<Vec<Option<f32>>>,
+    reference_run: usize,
+    bandwidth: Option<f32>,
+) -> PyResult<Vec<Vec<Option<f32>>>> {
+    let runs = check_runs(&intensities)?;
+    if reference_run >= runs {
+        return Err(PyValueError::new_err(format!(
+            "reference_run {} out of range for {} runs",
+            reference_run, runs
+        )));
+    }
+    let bandwidth = bandwidth.unwrap_or(0.4);
+
+    let mut intensities = intensities;
+    for r in (0..runs).filter(|r| *r != reference_run) {
+        let points: Vec<(f64, f64)> = intensities
+            .iter()
+            .filter_map(|row| {
+                let (x, y) = (observed(row[r])? as f64, observed(row[reference_run])? as f64);
+                Some(((x.log2() + y.log2()) / 2.0, x.log2() - y.log2()))
+            })
+            .collect();
+        if points.len() < 3 {
+            return Err(PyValueError::new_err(format!(
+                "LOESS normalization requires at least 3 rows observed in run {} and the reference run",
+                r
+            )));
+        }
+        let loess = Loess::fit(points, bandwidth, 1, ROBUSTNESS_ITERATIONS);
+
+        for row in intensities.iter_mut() {
+            let Some(x) = observed(row[r]) else {
+                continue;
+            };
+            let a = match observed(row[reference_run]) {
+                Some(y) => ((x as f64).log2() + (y as f64).log2()) / 2.0,
+                None => (x as f64).log2(),
+            };
+            row[r] = Some((x as f64 / loess.predict(a).exp2()) as f32);
+        }
+    }
+    Ok(intensities)
+}
+
+/// Normalise a run intensity matrix with missing values (one row per peptide or protein, one
+/// column per run). `method` is "median" (divide by run medians), "quantile" (equal run
+/// intensity distributions) or "loess" (intensity dependent, to `reference_run`, default the
+/// first run)
+#[pyfunction]
+pub fn normalize_lfq_intensities(
+    intensities: Vec<Vec<Option<f32>>>,
+    method: &str,
+    reference_run: Option<usize>,
+    bandwidth: Option<f32>,
+) -> PyResult<Vec<Vec<Option<f32>>>> {
+    match method.to_lowercase().as_str() {
+        "median" => median_centering_normalization(intensities),
+        "quantile" => quantile_normalization(intensities),
+        "loess" => loess_normalization(intensities, reference_run.unwrap_or(0), bandwidth),
+        _ => Err(PyValueError::new_err(format!(
+            "Invalid normalization method: {}, allowed values are: median, quantile, loess",
+            method
+        ))),
+    }
+}
+
+/// Top3 quantification: mean of the three most intense razor peptides of each protein
+#[pyfunction]
+pub fn top3_intensity(psms: Vec<PyFeature>, db: &PyIndexedDatabase) -> PyResult<HashMap<String, f64>> {
+    check_peptide_indices(&db.inner, &psms)?;
+    top_n_quantification(psms, db, 3, true, "mean", false)
+}
+
+/// Label mass offsets and extraction settings of a SILAC experiment
+#[pyclass]
+#[derive(Clone, Debug)]
+pub struct PySilacConfig {
+    pub heavy_lys: f32,
+    pub heavy_arg: f32,
+    pub medium_lys: Option<f32>,
+    pub medium_arg: Option<f32>,
+    pub ppm_tolerance: f32,
+    pub rt_window: f32,
+    pub fdr_cutoff: f32,
+    pub normalize: bool,
+    pub impute_missing: bool,
+}
+
+#[pymethods]
+impl PySilacConfig {
+    #[new]
+    pub fn new(
+        heavy_lys: f32,
+        heavy_arg: f32,
+        medium_lys: Option<f32>,
+        medium_arg: Option<f32>,
+        ppm_tolerance: f32,
+        rt_window: f32,
+        fdr_cutoff: f32,
+        normalize: bool,
+        impute_missing: bool,
+    ) -> Self {
+        PySilacConfig {
+            heavy_lys,
+            heavy_arg,
+            medium_lys,
+            medium_arg,
+            ppm_tolerance,
+            rt_window,
+            fdr_cutoff,
+            normalize,
+            impute_missing,
+        }
+    }
+
+    #[getter]
+    pub fn heavy_lys(&self) -> f32 {
+        self.heavy_lys
+    }
+
+    #[getter]
+    pub fn heavy_arg(&self) -> f32 {
+        self.heavy_arg
+    }
+
+    #[getter]
+    pub fn medium_lys(&self) -> Option<f32> {
+        self.medium_lys
+    }
+
+    #[getter]
+    pub fn medium_arg(&self) -> Option<f32> {
+        self.medium_arg
+    }
+
+    #[getter]
+    pub fn ppm_tolerance(&self) -> f32 {
+        self.ppm_tolerance
+    }
+
+    #[getter]
+    pub fn rt_window(&self) -> f32 {
+        self.rt_window
+    }
+
+    #[getter]
+    pub fn fdr_cutoff(&self) -> f32 {
+        self.fdr_cutoff
+    }
+
+    #[getter]
+    pub fn normalize(&self) -> bool {
+        self.normalize
+    }
+
+    #[getter]
+    pub fn impute_missing(&self) -> bool {
+        self.impute_missing
+    }
+}
+
+impl PySilacConfig {
+    fn has_medium(&self) -> bool {
+        self.medium_lys.is_some() || self.medium_arg.is_some()
+    }
+
+    /// Mass offset of a label state (0: light, 1: medium, 2: heavy) for a peptide
+    fn offset(&self, state: usize, lys: usize, arg: usize) -> f32 {
+        let (k, r) = match state {
+            1 => (self.medium_lys.unwrap_or(0.0), self.medium_arg.unwrap_or(0.0)),
+            2 => (self.heavy_lys, self.heavy_arg),
+            _ => (0.0, 0.0),
+        };
+        lys as f32 * k + arg as f32 * r
+    }
+}
+
+#[pyclass]
+#[derive(Clone, Debug)]
+pub struct PySilacRatio {
+    pub protein: String,
+    pub ratio_h_l: f32,
+    pub ratio_m_l: Option<f32>,
+    pub count: u32,
+}
+
+#[pymethods]
+impl PySilacRatio {
+    #[getter]
+    pub fn protein(&self) -> String {
+        self.protein.clone()
+    }
+
+    #[getter]
+    pub fn ratio_h_l(&self) -> f32 {
+        self.ratio_h_l
+    }
+
+    #[getter]
+    pub fn ratio_m_l(&self) -> Option<f32> {
+        self.ratio_m_l
+    }
+
+    /// Number of peptides (per run and charge) with a heavy/light ratio
+    #[getter]
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+}
+
+/// Fill zero intensity scans between two non-zero scans by linear interpolation in time
+fn impute_gaps(xic: &mut [(f32, f32)]) {
+    let observed: Vec<usize> = (0..xic.len()).filter(|&i| xic[i].1 > 0.0).collect();
+    for pair in observed.windows(2) {
+        let (a, b) = (pair[0], pair[1]);
+        let (ta, ia) = xic[a];
+        let (tb, ib) = xic[b];
+        for point in xic.iter_mut().take(b).skip(a + 1) {
+            let f = if tb > ta { (point.0 - ta) / (tb - ta) } else { 0.5 };
+            point.1 = ia + f * (ib - ia);
+        }
+    }
+}
+
+/// Area (trapezoidal rule) of the extracted ion chromatogram of a precursor over MS1 scans
+fn xic_area(ms1: &[&ProcessedSpectrum], mass: f32, charge: u8, ppm: f32, impute: bool) -> f32 {
+    // MS1 peaks are stored as m/z - proton, i.e. the precursor mass divided by its charge
+    let target = mass / charge.max(1) as f32;
+    let delta = target * ppm / 1e6;
+    let mut xic: Vec<(f32, f32)> = ms1
+        .iter()
+        .map(|s| {
+            let start = s.peaks.partition_point(|p| p.mass < target - delta);
+            let intensity = s.peaks[start..]
+                .iter()
+                .take_while(|p| p.mass <= target + delta)
+                .map(|p| p.intensity)
+                .sum();
+            (s.scan_start_time, intensity)
+        })
+        .collect();
+    if impute {
+        impute_gaps(&mut xic);
+    }
+    xic.windows(2)
+        .map(|w| (w[1].0 - w[0].0) * (w[0].1 + w[1].1) / 2.0)
+        .sum()
+}
+
+/// A SILAC peptide of one run and charge, its light, (medium) and heavy forms identified by PSMs
+struct SilacPeptide {
+    lys: usize,
+    arg: usize,
+    light_mass: f32,
+    rts: Vec<f32>,
+    protein: String,
+}
+
+/// SILAC quantification: for each confident target PSM, the label state is inferred from the
+/// lysine/arginine modifications of its peptide. PSMs of the light, (medium) and heavy forms of a
+/// peptide (same run, charge and light mass) are paired, the XICs of all forms are integrated once
+/// per peptide over MS1 scans of the run within `rt_window` of the median PSM retention time, and
+/// protein ratios are the median of the peptide log2 ratios. With `normalize`, ratios are divided
+/// by the global median ratio
+#[pyfunction]
+pub fn quantify_silac(
+    psms: Vec<PyFeature>,
+    db: &PyIndexedDatabase,
+    config: &PySilacConfig,
+    spectra: Vec<PyProcessedSpectrum>,
+) -> PyResult<HashMap<String, PySilacRatio>> {
+    check_peptide_indices(&db.inner, &psms)?;
+    let ms1: Vec<&ProcessedSpectrum> = spectra
+        .iter()
+        .map(|s| &s.inner)
+        .filter(|s| s.level == 1)
+        .collect();
+    let is_label = |mass: f32, label: Option<f32>| label.map_or(false, |l| (mass - l).abs() < 0.01);
+
+    // PSMs of all label states of a peptide, keyed by run, charge and light mass (0.01 Da)
+    let mut peptides: BTreeMap<(usize, u8, i64), SilacPeptide> = BTreeMap::new();
+    for psm in psms
+        .iter()
+        .filter(|p| p.inner.label == 1 && p.inner.spectrum_q <= config.fdr_cutoff)
+    {
+        let peptide = &db.inner[psm.inner.peptide_idx];
+        let (mut lys, mut arg, mut state) = (0usize, 0usize, 0usize);
+        for (residue, mass) in peptide.sequence.iter().zip(peptide.modifications.iter()) {
+            let (heavy, medium) = match residue {
+                b'K' => {
+                    lys += 1;
+                    (config.heavy_lys, config.medium_lys)
+                }
+                b'R' => {
+                    arg += 1;
+                    (config.heavy_arg, config.medium_arg)
+                }
+                _ => continue,
+            };
+            if is_label(*mass, Some(heavy)) {
+                state = 2;
+            } else if is_label(*mass, medium) {
+                state = state.max(1);
+            }
+        }
+        if lys + arg == 0 {
+            continue;
+        }
+
+        let light_mass = psm.inner.calcmass - config.offset(state, lys, arg);
+        let key = (psm.inner.file_id, psm.inner.charge, (light_mass * 100.0).round() as i64);
+        peptides
+            .entry(key)
+            .or_insert_with(|| SilacPeptide {
+                lys,
+                arg,
+                light_mass,
+                rts: Vec::new(),
+                protein: peptide.proteins.iter().map(|p| p.as_str()).collect::<Vec<_>>().join(";"),
+            })
+            .rts
+            .push(psm.inner.rt);
+    }
+
+    // log2 H/L and M/L ratios per protein, one per peptide
+    let mut ratios: HashMap<String, (Vec<f32>, Vec<f32>)> = HashMap::new();
+    for (&(file_id, charge, _), peptide) in &peptides {
+        let rt = median(peptide.rts.iter().copied()).unwrap_or_default() as f32;
+        let scans: Vec<&ProcessedSpectrum> = ms1
+            .iter()
+            .copied()
+            .filter(|s| s.file_id == file_id && (s.scan_start_time - rt).abs() <= config.rt_window)
+            .collect();
+        let area = |state: usize| {
+            xic_area(
+                &scans,
+                peptide.light_mass + config.offset(state, peptide.lys, peptide.arg),
+                charge,
+                config.ppm_tolerance,
+                config.impute_missing,
+            )
+        };
+
+        let light = area(0);
+        if light <= 0.0 {
+            continue;
+        }
+        let entry = ratios.entry(peptide.protein.clone()).or_default();
+        let heavy = area(2);
+        if heavy > 0.0 {
+            entry.0.push((heavy / light).log2());
+        }
+        if config.has_medium() {
+            let medium = area(1);
+            if medium > 0.0 {
+                entry.1.push((medium / light).log2());
+            }
+        }
+    }
+
+    let global_median = |select: fn(&(Vec<f32>, Vec<f32>)) -> &Vec<f32>| {
+        let all = ratios.values().flat_map(|r| select(r).iter().copied());
+        median(all).filter(|_| config.normalize).unwrap_or(0.0) as f32
+    };
+    let h_shift = global_median(|r| &r.0);
+    let m_shift = global_median(|r| &r.1);
+
+    Ok(ratios
+        .into_iter()
+        .filter_map(|(protein, (h, m))| {
+            let ratio_h_l = median(h.iter().copied())? as f32;
+            let ratio_m_l = median(m.iter().copied()).map(|r| 2f32.powf(r as f32 - m_shift));
+            Some((
+                protein.clone(),
+                PySilacRatio {
+                    protein,
+                    ratio_h_l: 2f32.powf(ratio_h_l - h_shift),
+                    ratio_m_l,
+                    count: h.len() as u32,
+                },
+            ))
+        })
+        .collect())
+}
+
+/// MaxLFQ intensities of one protein (rows: peptides, columns: runs). Pairwise run log-ratios are
+/// the median peptide log-ratio over at least `min_ratio_count` shared peptides, the run log-profile
+/// is the least-squares solution within each connected group of runs, rescaled to the summed peptide
+/// intensity of the group
+fn maxlfq_protein(peptides: &[&Vec<Option<f32>>], runs: usize, min_ratio_count: usize) -> Vec<Option<f32>> {
+    let value = |p: &Vec<Option<f32>>, run: usize| p.get(run).copied().flatten().filter(|v| *v > 0.0);
+
+    let mut ratios: Vec<(usize, usize, f32)> = Vec::new();
+    for j in 0..runs {
+        for k in j + 1..runs {
+            let log_ratios: Vec<f32> = peptides
+                .iter()
+                .filter_map(|p| Some((value(p, j)? / value(p, k)?).ln()))
+                .collect();
+            if log_ratios.len() >= min_ratio_count {
+                ratios.push((j, k, median(log_ratios.iter().copied()).unwrap_or(0.0) as f32));
+            }
+        }
+    }
+
+    // connected groups of runs linked by ratios (union-find)
+    let mut parent: Vec<usize> = (0..runs).collect();
+    fn root(parent: &mut [usize], mut i: usize) -> usize {
+        while parent[i] != i {
+            parent[i] = parent[parent[i]];
+            i = parent[i];
+        }
+        i
+    }
+    for &(j, k, _) in &ratios {
+        let (a, b) = (root(&mut parent, j), root(&mut parent, k));
+        parent[a] = b;
+    }
+
+    let mut result = vec![None; runs];
+    let groups: Vec<usize> = (0..runs).map(|i| root(&mut parent, i)).collect();
+    for group in groups.iter().copied().collect::<HashSet<usize>>() {
+        let members: Vec<usize> = (0..runs)
+            .filter(|&i| groups[i] == group && peptides.iter().any(|p| value(p, i).is_some()))
+            .collect();
+        if members.is_empty() {
+            continue;
+        }
+        let position: HashMap<usize, usize> = members.iter().enumerate().map(|(n, &i)| (i, n)).collect();
+
+        // normal equations of sum (x_j - x_k - r_jk)^2, plus the all-ones matrix to fix sum(x) = 0
+        let n = members.len();
+        let mut a = vec![vec![1.0f64; n]; n];
+        let mut b = vec![0.0f64; n];
+        for &(j, k, r) in ratios.iter().filter(|(j, _, _)| groups[*j] == group) {
+            let (j, k) = (position[&j], position[&k]);
+            a[j][j] += 1.0;
+            a[k][k] += 1.0;
+            a[j][k] -= 1.0;
+            a[k][j] -= 1.0;
+            b[j] += r as f64;
+            b[k] -= r as f64;
+        }
+        let x = match solve_linear(a, b) {
+            Some(x) => x,
+            None => continue,
+        };
+
+        let total: f64 = members
+            .iter()
+            .map(|&i| peptides.iter().filter_map(|p| value(p, i)).map(|v| v as f64).sum::<f64>())
+            .sum();
+        let profile_total: f64 = x.iter().map(|v| v.exp()).sum();
+        for (&i, v) in members.iter().zip(x.iter()) {
+            result[i] = Some((v.exp() / profile_total * total) as f32);
+        }
+    }
+    result
+}
+
+/// MaxLFQ protein quantification (Cox et al., 2014). Peptide intensities (one value per run, None
+/// for missing) are grouped by protein; proteins with fewer than `min_peptides_per_protein`
+/// peptides are omitted, which is also the minimum number of shared peptides of a pairwise ratio
+#[pyfunction]
+pub fn maxlfq(
+    peptide_intensities: HashMap<String, Vec<Option<f32>>>,
+    peptide_proteins: HashMap<String, String>,
+    run_names: Vec<String>,
+    min_peptides_per_protein: usize,
+) -> PyResult<HashMap<String, Vec<Option<f32>>>> {
+    let runs = run_names.len();
+    if let Some((peptide, _)) = peptide_intensities.iter().find(|(_, v)| v.len() != runs) {
+        return Err(PyValueError::new_err(format!(
+            "peptide {} must have one intensity per run ({})",
+            peptide, runs
+        )));
+    }
+
+    let mut proteins: HashMap<&str, Vec<&Vec<Option<f32>>>> = HashMap::new();
+    for (peptide, intensities) in peptide_intensities.iter() {
+        if let Some(protein) = peptide_proteins.get(peptide) {
+            proteins.entry(protein.as_str()).or_default().push(intensities);
+        }
+    }
+
+    let min_peptides = min_peptides_per_protein.max(1);
+    Ok(proteins
+        .into_par_iter()
+        .filter(|(_, peptides)| peptides.len() >= min_peptides)
+        .map(|(protein, peptides)| (protein.to_string(), maxlfq_protein(&peptides, runs, min_peptides)))
+        .collect())
+}
+
+/// directLFQ protein profile (Ammar et al., 2023): ion traces (log2) are shifted one after another
+/// onto the median trace of the already normalised ions, starting with the most complete ion, by
+/// the median difference over shared runs. The protein intensity of a run is the median of the
+/// shifted ions, on the intensity scale of the first ion
+fn direct_lfq_protein(ions: &[&Vec<Option<f32>>], runs: usize) -> Vec<Option<f32>> {
+    let log_trace = |ion: &Vec<Option<f32>>| -> Vec<Option<f64>> {
+        (0..runs)
+            .map(|run| ion.get(run).copied().flatten().filter(|v| *v > 0.0).map(|v| (v as f64).log2()))
+            .collect()
+    };
+    let mut pending: Vec<Vec<Option<f64>>> = ions.iter().map(|ion| log_trace(ion)).collect();
+    pending.sort_by_key(|trace| std::cmp::Reverse(trace.iter().flatten().count()));
+    if pending.first().map_or(true, |trace| trace.iter().all(Option::is_none)) {
+        return vec![None; runs];
+    }
+
+    let profile_of = |normalized: &[Vec<Option<f64>>]| -> Vec<Option<f64>> {
+        (0..runs)
+            .map(|run| median(normalized.iter().filter_map(|trace| trace[run])))
+            .collect()
+    };
+    let mut normalized = vec![pending.remove(0)];
+    let mut profile = profile_of(&normalized);
+    loop {
+        let before = pending.len();
+        pending.retain(|trace| {
+            let shift = median(
+                trace
+                    .iter()
+                    .zip(profile.iter())
+                    .filter_map(|(ion, reference)| Some((*reference)? - (*ion)?)),
+            );
+            match shift {
+                Some(shift) => {
+                    normalized.push(trace.iter().map(|v| v.map(|v| v + shift)).collect());
+                    false
+                }
+                None => true,
+            }
+        });
+        profile = profile_of(&normalized);
+        if pending.is_empty() || pending.len() == before {
+            break;
+        }
+    }
+
+    profile.into_iter().map(|v| v.map(|v| v.exp2() as f32)).collect()
+}
+
+/// directLFQ protein quantification: ion intensities (one value per run, None for missing) are
+/// grouped by protein and normalised trace by trace against a reference ion instead of MaxLFQ's
+/// pairwise run ratios. Proteins with fewer than `min_ions_per_protein` ions are omitted
+#[pyfunction]
+pub fn direct_lfq(
+    ion_intensities: HashMap<String, Vec<Option<f32>>>,
+    ion_proteins: HashMap<String, String>,
+    run_names: Vec<String>,
+    min_ions_per_protein: usize,
+) -> PyResult<HashMap<String, Vec<Option<f32>>>> {
+    let runs = run_names.len();
+    if let Some((ion, _)) = ion_intensities.iter().find(|(_, v)| v.len() != runs) {
+        return Err(PyValueError::new_err(format!(
+            "ion {} must have one intensity per run ({})",
+            ion, runs
+        )));
+    }
+
+    let mut proteins: HashMap<&str, Vec<&Vec<Option<f32>>>> = HashMap::new();
+    for (ion, intensities) in ion_intensities.iter() {
+        if let Some(protein) = ion_proteins.get(ion) {
+            proteins.entry(protein.as_str()).or_default().push(intensities);
+        }
+    }
+
+    let min_ions = min_ions_per_protein.max(1);
+    Ok(proteins
+        .into_par_iter()
+        .filter(|(_, ions)| ions.len() >= min_ions)
+        .map(|(protein, ions)| (protein.to_string(), direct_lfq_protein(&ions, runs)))
+        .collect())
+}
+
+/// directLFQ on PSMs: ions are (peptide, charge) precursors of target PSMs with their highest
+/// ms2_intensity per run (file_id), shared peptides count for their razor protein. Returns the
+/// intensity of each protein per run
+#[pyfunction]
+pub fn quantify_direct_lfq(
+    psms: Vec<PyFeature>,
+    db: &PyIndexedDatabase,
+    min_ions_per_protein: usize,
+) -> PyResult<HashMap<String, HashMap<usize, f64>>> {
+    check_peptide_indices(&db.inner, &psms)?;
+    let mut runs: Vec<usize> = psms.iter().map(|p| p.inner.file_id).collect();
+    runs.sort_unstable();
+    runs.dedup();
+    let run_index: HashMap<usize, usize> = runs.iter().enumerate().map(|(i, run)| (*run, i)).collect();
+
+    let mut ions: HashMap<(PeptideIx, u8), Vec<Option<f32>>> = HashMap::new();
+    for psm in psms.iter().filter(|p| p.inner.label == 1 && p.inner.ms2_intensity > 0.0) {
+        let trace = ions
+            .entry((psm.inner.peptide_idx, psm.inner.charge))
+            .or_insert_with(|| vec![None; runs.len()]);
+        let value = &mut trace[run_index[&psm.inner.file_id]];
+        *value = Some(value.unwrap_or(0.0).max(psm.inner.ms2_intensity));
+    }
+
+    let peptides: HashSet<PeptideIx> = ions.keys().map(|(idx, _)| *idx).collect();
+    let proteins = peptide_proteins(db, &peptides, true);
+    let ion_name = |idx: &PeptideIx, charge: u8| format!("{}/{}", idx.0, charge);
+    let ion_proteins: HashMap<String, String> = ions
+        .keys()
+        .filter_map(|(idx, charge)| Some((ion_name(idx, *charge), proteins[idx].first()?.clone())))
+        .collect();
+    let ion_intensities: HashMap<String, Vec<Option<f32>>> = ions
+        .into_iter()
+        .map(|((idx, charge), trace)| (ion_name(&idx, charge), trace))
+        .collect();
+    let run_names = runs.iter().map(|run| run.to_string()).collect();
+
+    let quantities = direct_lfq(ion_intensities, ion_proteins, run_names, min_ions_per_protein)?;
+    Ok(quantities
+        .into_iter()
+        .map(|(protein, values)| {
+            let per_run = runs
+                .iter()
+                .zip(values)
+                .filter_map(|(run, value)| Some((*run, value? as f64)))
+                .collect();
+            (protein, per_run)
+        })
+        .collect())
+}
+
 #[pymodule]
 pub fn lfq(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_class::<PyPeakScoringStrategy>()?;
@@ -332,5 +1531,261 @@ pub fn lfq(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_class::<PyPrecursorRange>()?;
     m.add_class::<PyFeatureMap>()?;
     m.add_class::<PyQuery>()?;
+    m.add_class::<PyFoldChangeResult>()?;
+    m.add_function(wrap_pyfunction!(fold_change_analysis, m)?)?;
+    m.add_function(wrap_pyfunction!(batch_fold_change, m)?)?;
+    m.add_function(wrap_pyfunction!(compute_ibaq, m)?)?;
+    m.add_function(wrap_pyfunction!(maxlfq, m)?)?;
+    m.add_function(wrap_pyfunction!(direct_lfq, m)?)?;
+    m.add_function(wrap_pyfunction!(quantify_direct_lfq, m)?)?;
+    m.add_function(wrap_pyfunction!(top_n_quantification, m)?)?;
+    m.add_function(wrap_pyfunction!(top_n_quantification_per_run, m)?)?;
+    m.add_function(wrap_pyfunction!(top3_intensity, m)?)?;
+    m.add_function(wrap_pyfunction!(normalize_intensities, m)?)?;
+    m.add_function(wrap_pyfunction!(median_centering_normalization, m)?)?;
+    m.add_function(wrap_pyfunction!(quantile_normalization, m)?)?;
+    m.add_function(wrap_pyfunction!(loess_normalization, m)?)?;
+    m.add_function(wrap_pyfunction!(normalize_lfq_intensities, m)?)?;
+    m.add_class::<PySilacConfig>()?;
+    m.add_class::<PySilacRatio>()?;
+    m.add_function(wrap_pyfunction!(quantify_silac, m)?)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sage_core::enzyme::Enzyme;
+    use sage_core::fasta::Fasta;
+
+    #[test]
+    fn observable_peptides_from_digest() {
+        let trypsin = PyEnzyme { inner: Enzyme::new("KR", Some('P'), true, false).unwrap() };
+        let proteins = [("P1", "PEPTIDEKAAAAAAKPEPTIDER"), ("P2", "SAMPLERSAMPLERK")];
+        let counts = observable_peptide_counts(proteins, &trypsin, 6, 30);
+
+        // PEPTIDEK and AAAAAAKPEPTIDER (no cleavage before proline)
+        assert_eq!(counts["P1"], 2);
+        // SAMPLER twice, the C-terminal K is too short
+        assert_eq!(counts["P2"], 1);
+    }
+
+    #[test]
+    fn top_n_aggregation() {
+        let values = vec![1.0, 8.0, 2.0, 4.0, 16.0];
+        assert_eq!(aggregate_top_n(values.clone(), 3, "sum"), 28.0);
+        assert_eq!(aggregate_top_n(values.clone(), 3, "median"), 8.0);
+        assert_eq!(aggregate_top_n(values.clone(), 2, "median"), 12.0);
+        assert!((aggregate_top_n(values.clone(), 3, "mean") - 28.0 / 3.0).abs() < 1e-12);
+        // fewer peptides than n use all of them
+        assert_eq!(aggregate_top_n(values, 10, "sum"), 31.0);
+    }
+
+    #[test]
+    fn shared_median() {
+        assert_eq!(median(Vec::<f64>::new()), None);
+        assert_eq!(median([3.0f32, 1.0, 2.0]), Some(2.0));
+        assert_eq!(median([4.0, 1.0, 3.0, 2.0]), Some(2.5));
+    }
+
+    #[test]
+    fn student_t_p_values_match_the_t_distribution() {
+        // t = 1 at one degree of freedom (Cauchy) and the two-sided 5% critical values
+        assert!((student_t_p_value(1.0, 1.0) - 0.5).abs() < 1e-9);
+        assert!((student_t_p_value(12.706205, 1.0) - 0.05).abs() < 1e-6);
+        assert!((student_t_p_value(2.776445, 4.0) - 0.05).abs() < 1e-6);
+        assert!((student_t_p_value(-2.228139, 10.0) - 0.05).abs() < 1e-6);
+        assert!((student_t_p_value(0.0, 10.0) - 1.0).abs() < 1e-9);
+    }
+
+    /// Three replicates around `level` at a signal-to-noise ratio of 10 (standard deviation of a
+    /// tenth of the level)
+    fn replicates(level: f32, noise: [f32; 3]) -> Vec<Option<f32>> {
+        noise.iter().map(|e| Some(level * (1.0 + e))).collect()
+    }
+
+    #[test]
+    fn twofold_change_is_significant_with_three_replicates() {
+        let a = replicates(1000.0, [-0.1, 0.0, 0.1]);
+        let b = replicates(2000.0, [0.1, -0.1, 0.0]);
+        let result = welch_test(&a, &b, 2);
+
+        assert!((result.log2_fc - 1.0).abs() < 1e-5);
+        // Welch's t = 8.457 at 4 degrees of freedom
+        assert!((result.t_statistic - 8.457).abs() < 1e-3);
+        assert!(result.p_value < 0.05);
+        assert!((result.p_value - 0.0011).abs() < 2e-4, "p-value {}", result.p_value);
+
+        // too few valid values
+        let missing = vec![Some(2000.0), None, Some(0.0)];
+        let result = welch_test(&a, &missing, 2);
+        assert!(result.log2_fc.is_nan());
+        assert_eq!(result.p_value, 1.0);
+    }
+
+    #[test]
+    fn batch_fold_change_adjusts_tested_proteins_only() {
+        let columns = |a: Vec<Option<f32>>, b: Vec<Option<f32>>| a.into_iter().chain(b).collect::<Vec<_>>();
+        let proteins = BTreeMap::from([
+            (
+                "UP".to_string(),
+                columns(replicates(1000.0, [-0.1, 0.0, 0.1]), replicates(2000.0, [0.1, -0.1, 0.0])),
+            ),
+            (
+                "FLAT".to_string(),
+                columns(replicates(1000.0, [-0.1, 0.0, 0.1]), replicates(1000.0, [0.1, -0.1, 0.0])),
+            ),
+            ("MISSING".to_string(), columns(replicates(1000.0, [-0.1, 0.0, 0.1]), vec![Some(900.0), None, None])),
+        ]);
+
+        let results = batch_fold_change(proteins, vec![0, 1, 2], vec![3, 4, 5]);
+        let up = &results["UP"];
+        assert!(up.p_value < 0.05);
+        // two proteins were tested, the smaller p-value is doubled
+        assert!((up.adjusted_p_value - 2.0 * up.p_value).abs() < 1e-6);
+        assert!(up.adjusted_p_value < 0.05);
+
+        assert!(results["FLAT"].log2_fc.abs() < 1e-5);
+        assert!(results["FLAT"].p_value > 0.5);
+        assert!(results["MISSING"].log2_fc.is_nan());
+        assert_eq!(results["MISSING"].adjusted_p_value, 1.0);
+    }
+
+    #[test]
+    fn ibaq_divides_by_observable_peptides() {
+        let trypsin = PyEnzyme { inner: Enzyme::new("KR", Some('P'), true, false).unwrap() };
+        let fasta = PyFasta {
+            inner: Fasta::parse(
+                ">sp|P1|A\nPEPTIDEKAAAAAAKPEPTIDER\n>sp|P2|B\nSAMPLERSAMPLERK\n".to_string(),
+                "rev_".to_string(),
+                false,
+            ),
+            fasta_hash: String::new(),
+        };
+        let intensities: HashMap<String, f64> =
+            [("sp|P1|A", 3e6), ("sp|P2|B", 1e6), ("sp|P3|C", 5e6)].map(|(p, i)| (p.to_string(), i)).into();
+
+        let ibaq = compute_ibaq(intensities.clone(), &fasta, &trypsin, 6, 30, false);
+        // P1 has two observable peptides, P2 one and P3 is not in the FASTA
+        assert_eq!(ibaq.len(), 2);
+        assert_eq!(ibaq["sp|P1|A"], 1.5e6);
+        assert_eq!(ibaq["sp|P2|B"], 1e6);
+
+        let ribaq = compute_ibaq(intensities, &fasta, &trypsin, 6, 30, true);
+        assert!((ribaq["sp|P1|A"] - 6e8).abs() < 1.0);
+        assert!((ribaq["sp|P2|B"] - 4e8).abs() < 1.0);
+    }
+
+    #[test]
+    fn maxlfq_recovers_run_ratios_despite_missing_peptides() {
+        let intensities: HashMap<String, Vec<Option<f32>>> = [
+            ("PEPTIDEK", vec![Some(100.0), Some(200.0), Some(400.0)]),
+            // the most intense peptide is missing in the second run, which biases summed intensities
+            ("SAMPLER", vec![Some(1000.0), None, Some(4000.0)]),
+            ("LLLK", vec![Some(50.0), Some(100.0), Some(200.0)]),
+            ("ELVISK", vec![Some(10.0), Some(10.0), Some(10.0)]),
+        ]
+        .map(|(p, v)| (p.to_string(), v))
+        .into();
+        let proteins: HashMap<String, String> =
+            [("PEPTIDEK", "P1"), ("SAMPLER", "P1"), ("LLLK", "P1"), ("ELVISK", "P2")]
+                .map(|(p, protein)| (p.to_string(), protein.to_string()))
+                .into();
+        let runs = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+
+        let quantities = maxlfq(intensities, proteins, runs, 2).unwrap();
+
+        // P2 has a single peptide
+        assert_eq!(quantities.len(), 1);
+        let p1: Vec<f32> = quantities["P1"].iter().map(|v| v.unwrap()).collect();
+        assert!((p1[1] / p1[0] - 2.0).abs() < 1e-4 && (p1[2] / p1[0] - 4.0).abs() < 1e-4);
+        // scaled to the summed peptide intensity
+        assert!((p1.iter().sum::<f32>() - 6050.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn direct_lfq_recovers_run_ratios_from_ion_traces() {
+        let intensities: HashMap<String, Vec<Option<f32>>> = [
+            ("PEPTIDEK/2", vec![Some(100.0), Some(200.0), Some(400.0)]),
+            // missing in the second run, shifted onto the other ions over the shared runs
+            ("SAMPLER/2", vec![Some(1000.0), None, Some(4000.0)]),
+            ("SAMPLER/3", vec![None, Some(60.0), Some(120.0)]),
+            ("LLLK/2", vec![Some(50.0), Some(100.0), Some(200.0)]),
+            ("ELVISK/2", vec![Some(10.0), Some(10.0), Some(10.0)]),
+        ]
+        .map(|(ion, v)| (ion.to_string(), v))
+        .into();
+        let proteins: HashMap<String, String> = [
+            ("PEPTIDEK/2", "P1"),
+            ("SAMPLER/2", "P1"),
+            ("SAMPLER/3", "P1"),
+            ("LLLK/2", "P1"),
+            ("ELVISK/2", "P2"),
+        ]
+        .map(|(ion, protein)| (ion.to_string(), protein.to_string()))
+        .into();
+        let runs = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+
+        let quantities = direct_lfq(intensities.clone(), proteins.clone(), runs.clone(), 2).unwrap();
+
+        // P2 has a single ion
+        assert_eq!(quantities.len(), 1);
+        let p1: Vec<f32> = quantities["P1"].iter().map(|v| v.unwrap()).collect();
+        assert!((p1[1] / p1[0] - 2.0).abs() < 1e-4 && (p1[2] / p1[0] - 4.0).abs() < 1e-4);
+
+        let quantities = direct_lfq(intensities.clone(), proteins.clone(), runs.clone(), 1).unwrap();
+        assert_eq!(quantities["P2"], vec![Some(10.0); 3]);
+
+        assert!(direct_lfq(intensities, proteins, runs[..2].to_vec(), 1).is_err());
+    }
+
+    #[test]
+    fn run_normalizations_remove_run_scaling_and_keep_missing_values() {
+        // run 1 is twice and run 2 half of run 0, with missing values that leave every run median
+        // at the fifth row
+        let intensities: Vec<Vec<Option<f32>>> = (0..9)
+            .map(|i| {
+                let value = 100.0 * (i + 1) as f32;
+                let run_1 = (i != 0 && i != 8).then_some(2.0 * value);
+                let run_2 = (i != 2 && i != 6).then_some(0.5 * value);
+                vec![Some(value), run_1, run_2]
+            })
+            .collect();
+        let missing = |matrix: &[Vec<Option<f32>>]| -> Vec<Vec<bool>> {
+            matrix.iter().map(|row| row.iter().map(|v| v.is_none()).collect()).collect()
+        };
+
+        let median = normalize_lfq_intensities(intensities.clone(), "median", None, None).unwrap();
+        assert_eq!(missing(&median), missing(&intensities));
+        for (i, row) in median.iter().enumerate() {
+            for value in row.iter().flatten() {
+                assert!((value - (i + 1) as f32 / 5.0).abs() < 1e-6);
+            }
+        }
+
+        let loess = normalize_lfq_intensities(intensities.clone(), "loess", Some(0), None).unwrap();
+        assert_eq!(missing(&loess), missing(&intensities));
+        for (i, row) in loess.iter().enumerate() {
+            for value in row.iter().flatten() {
+                let expected = 100.0 * (i + 1) as f32;
+                assert!((value - expected).abs() < 1e-3 * expected, "row {}: {}", i, value);
+            }
+        }
+
+        // every run ends up with the same smallest and largest value
+        let quantile = normalize_lfq_intensities(intensities.clone(), "quantile", None, None).unwrap();
+        assert_eq!(missing(&quantile), missing(&intensities));
+        let column = |r: usize| -> Vec<f32> { quantile.iter().filter_map(|row| row[r]).collect() };
+        let extremes = |values: Vec<f32>| {
+            let min = values.iter().copied().fold(f32::INFINITY, f32::min);
+            let max = values.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+            (min, max)
+        };
+        assert_eq!(extremes(column(1)), extremes(column(0)));
+        assert_eq!(extremes(column(2)), extremes(column(0)));
+
+        assert!(normalize_lfq_intensities(intensities.clone(), "loess", Some(3), None).is_err());
+        assert!(normalize_lfq_intensities(intensities, "vsn", None, None).is_err());
+        assert!(median_centering_normalization(vec![vec![Some(1.0)], vec![Some(1.0), None]]).is_err());
+    }
+}
@@ -1,6 +1,7 @@
 use pyo3::exceptions::PyValueError;
 use pyo3::prelude::*;
 use pyo3::types::PyList;
+use std::collections::HashMap;
 
 use sage_core::mass::{
     composition, monoisotopic, Composition, Tolerance, H2O, NEUTRON, NH3, PROTON,
@@ -156,6 +157,106 @@ impl PyTolerance {
     }
 }
 
+/// Natural isotope abundances of the elements by nominal mass offset from the lightest isotope
+fn isotope_abundances(element: &str) -> Option<&'static [f64]> {
+    match element {
+        "C" => Some(&[0.9893, 0.0107]),
+        "H" => Some(&[0.999885, 0.000115]),
+        "N" => Some(&[0.99636, 0.00364]),
+        "O" => Some(&[0.99757, 0.00038, 0.00205]),
+        "S" => Some(&[0.9499, 0.0075, 0.0425, 0.0, 0.0001]),
+        "P" => Some(&[1.0]),
+        "Se" => Some(&[0.0089, 0.0, 0.0937, 0.0763, 0.2377, 0.0, 0.4961, 0.0, 0.0873]),
+        _ => None,
+    }
+}
+
+/// Averagine residue composition (C, H, N, O, S) and its monoisotopic mass
+const AVERAGINE: [(&str, f64); 5] = [("C", 4.9384), ("H", 7.7583), ("N", 1.3577), ("O", 1.4773), ("S", 0.0417)];
+const AVERAGINE_MASS: f64 = 111.1254;
+
+fn convolve(a: &[f64], b: &[f64], n: usize) -> Vec<f64> {
+    let mut result = vec![0.0; n.min(a.len() + b.len() - 1)];
+    for (i, x) in a.iter().enumerate() {
+        for (j, y) in b.iter().enumerate().take(result.len().saturating_sub(i)) {
+            result[i + j] += x * y;
+        }
+    }
+    result
+}
+
+/// Isotope distribution (M to M+n-1, not normalized) of a formula, by convolving the element
+/// distributions, raised to their atom counts by repeated squaring
+fn formula_distribution(formula: &[(&str, i32)], n: usize) -> PyResult<Vec<f64>> {
+    let mut distribution = vec![1.0];
+    for (element, count) in formula {
+        let abundances = isotope_abundances(element)
+            .ok_or_else(|| PyValueError::new_err(format!("Unsupported element: {}", element)))?;
+        if *count < 0 {
+            return Err(PyValueError::new_err(format!("Negative count of element {}", element)));
+        }
+        let mut base = abundances.to_vec();
+        let mut exponent = *count as u32;
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                distribution = convolve(&distribution, &base, n);
+            }
+            base = convolve(&base, &base, n);
+            exponent >>= 1;
+        }
+    }
+    distribution.resize(n, 0.0);
+    Ok(distribution)
+}
+
+/// (offset, intensity relative to the most intense peak) pairs of an isotope distribution, offsets
+/// are multiples of the neutron mass divided by the charge
+fn isotope_pattern(distribution: &[f64], charge: u8) -> Vec<(f32, f32)> {
+    let max = distribution.iter().cloned().fold(0.0, f64::max);
+    distribution
+        .iter()
+        .enumerate()
+        .map(|(k, p)| {
+            let offset = k as f32 * NEUTRON / charge.max(1) as f32;
+            (offset, if max > 0.0 { (p / max) as f32 } else { 0.0 })
+        })
+        .collect()
+}
+
+/// Isotope pattern of a neutral mass following the averagine model, as (mass offset, relative
+/// intensity) pairs
+#[pyfunction]
+fn averagine_isotope_pattern(mass: f64, num_isotopes: usize) -> PyResult<Vec<(f32, f32)>> {
+    let units = mass.max(0.0) / AVERAGINE_MASS;
+    let formula: Vec<(&str, i32)> = AVERAGINE
+        .iter()
+        .map(|(element, count)| (*element, (count * units).round() as i32))
+        .collect();
+    Ok(isotope_pattern(&formula_distribution(&formula, num_isotopes)?, 1))
+}
+
+/// Isotope pattern of an elemental formula (e.g. {"C": 50, "H": 80, ...}) at a charge, as (m/z
+/// offset, relative intensity) pairs
+#[pyfunction]
+fn exact_isotope_pattern(formula: HashMap<String, i32>, charge: u8, num_isotopes: usize) -> PyResult<Vec<(f32, f32)>> {
+    let mut formula: Vec<(&str, i32)> = formula.iter().map(|(e, c)| (e.as_str(), *c)).collect();
+    formula.sort_unstable();
+    Ok(isotope_pattern(&formula_distribution(&formula, num_isotopes)?, charge))
+}
+
+/// Cosine similarity of two isotope intensity patterns, the shorter one is padded with zeros
+#[pyfunction]
+fn compare_isotope_patterns(observed: Vec<f32>, theoretical: Vec<f32>) -> f32 {
+    let dot: f32 = observed.iter().zip(theoretical.iter()).map(|(o, t)| o * t).sum();
+    let norm = observed.iter().map(|o| o * o).sum::<f32>().sqrt()
+        * theoretical.iter().map(|t| t * t).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        dot / norm
+    } else {
+        0.0
+    }
+}
+
 #[pymodule]
 pub fn mass(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(h2o, m)?)?;
@@ -163,7 +264,48 @@ pub fn mass(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(neutron, m)?)?;
     m.add_function(wrap_pyfunction!(nh3, m)?)?;
     m.add_function(wrap_pyfunction!(py_monoisotopic, m)?)?;
+    m.add_function(wrap_pyfunction!(averagine_isotope_pattern, m)?)?;
+    m.add_function(wrap_pyfunction!(exact_isotope_pattern, m)?)?;
+    m.add_function(wrap_pyfunction!(compare_isotope_patterns, m)?)?;
     m.add_class::<PyTolerance>()?;
     m.add_class::<PyComposition>()?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn isotope_patterns_follow_the_binomial_carbon_distribution() {
+        // 100 carbons: M+1 / M = 100 p / q and M+2 / M+1 = 99 / 2 * p / q, with p = 0.0107
+        let ratio = 0.0107 / 0.9893;
+        let pattern = exact_isotope_pattern(HashMap::from([("C".to_string(), 100)]), 2, 3).unwrap();
+        assert_eq!(pattern.len(), 3);
+        for (k, (offset, _)) in pattern.iter().enumerate() {
+            assert!((offset - k as f32 * NEUTRON / 2.0).abs() < 1e-6);
+        }
+        assert!((pattern[0].1 - (1.0 / (100.0 * ratio)) as f32).abs() < 1e-4);
+        assert_eq!(pattern[1].1, 1.0);
+        assert!((pattern[2].1 - (49.5 * ratio) as f32).abs() < 1e-4);
+
+        // around 1 kDa the monoisotopic peak is the most intense, followed by about half an M+1
+        let averagine = averagine_isotope_pattern(1000.0, 4).unwrap();
+        assert_eq!(averagine[0], (0.0, 1.0));
+        assert!(averagine[1].1 > 0.45 && averagine[1].1 < 0.65);
+        assert!(averagine.windows(2).all(|w| w[1].1 < w[0].1));
+
+        assert!(exact_isotope_pattern(HashMap::from([("Xx".to_string(), 1)]), 1, 3).is_err());
+        assert!(exact_isotope_pattern(HashMap::from([("C".to_string(), -1)]), 1, 3).is_err());
+    }
+
+    #[test]
+    fn isotope_pattern_similarity_is_a_cosine_with_zero_padding() {
+        assert!((compare_isotope_patterns(vec![1.0, 0.5], vec![2.0, 1.0]) - 1.0).abs() < 1e-6);
+        assert_eq!(compare_isotope_patterns(vec![1.0, 0.0], vec![0.0, 1.0]), 0.0);
+        // the missing third isotope of the observed pattern counts as zero
+        let padded = compare_isotope_patterns(vec![1.0, 1.0], vec![1.0, 1.0, 1.0]);
+        assert!((padded - (2.0 / 6f32.sqrt())).abs() < 1e-6);
+        assert_eq!(compare_isotope_patterns(vec![], vec![1.0]), 0.0);
+    }
+}
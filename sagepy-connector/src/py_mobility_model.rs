@@ -0,0 +1,406 @@
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+use std::collections::HashMap;
+
+use crate::py_database::PyIndexedDatabase;
+use crate::py_scoring::{check_peptide_indices, solve_linear, PyFeature, H2O};
+use crate::py_utility::median;
+use sage_core::mass::monoisotopic;
+use sage_core::peptide::Peptide;
+
+const AMINO_ACIDS: &[u8; 20] = b"ACDEFGHIKLMNPQRSTVWY";
+
+/// Amino acid counts, peptide length, summed modification mass (per 100 Da), inverse charge,
+/// (mass / 100 Da)^(2/3) / charge as collision cross section proxy, intercept
+const NUM_FEATURES: usize = AMINO_ACIDS.len() + 5;
+
+fn encode(sequence: &[u8], modification_mass: f32, mass: f32, charge: u8) -> [f64; NUM_FEATURES] {
+    let mut x = [0.0; NUM_FEATURES];
+    for residue in sequence {
+        if let Some(k) = AMINO_ACIDS.iter().position(|aa| aa == residue) {
+            x[k] += 1.0;
+        }
+    }
+    let charge = charge.max(1) as f64;
+    x[AMINO_ACIDS.len()] = sequence.len() as f64;
+    x[AMINO_ACIDS.len() + 1] = modification_mass as f64 / 100.0;
+    x[AMINO_ACIDS.len() + 2] = 1.0 / charge;
+    x[AMINO_ACIDS.len() + 3] = (mass as f64 / 100.0).powf(2.0 / 3.0) / charge;
+    x[AMINO_ACIDS.len() + 4] = 1.0;
+    x
+}
+
+fn encode_sequence(sequence: &str, modifications: Option<&HashMap<u32, f32>>, charge: u8) -> [f64; NUM_FEATURES] {
+    let modification_mass = modifications.map_or(0.0, |mods| mods.values().sum());
+    let mass = sequence.bytes().map(monoisotopic).sum::<f32>() + H2O + modification_mass;
+    encode(sequence.as_bytes(), modification_mass, mass, charge)
+}
+
+/// Encode `sequences` at their `charges`, `modifications[i]` maps residue positions of
+/// `sequences[i]` to their mass shifts
+fn encode_sequences(
+    sequences: &[String],
+    charges: &[u8],
+    modifications: Option<&[HashMap<u32, f32>]>,
+) -> PyResult<Vec<[f64; NUM_FEATURES]>> {
+    let n = sequences.len();
+    if charges.len() != n || modifications.map_or(false, |m| m.len() != n) {
+        return Err(PyValueError::new_err(format!(
+            "sequences, charges and modifications must have the same length, got {}, {} and {}",
+            n,
+            charges.len(),
+            modifications.map_or(n, <[_]>::len)
+        )));
+    }
+    Ok((0..n)
+        .map(|i| encode_sequence(&sequences[i], modifications.map(|m| &m[i]), charges[i]))
+        .collect())
+}
+
+fn encode_peptide(peptide: &Peptide, charge: u8) -> [f64; NUM_FEATURES] {
+    let modification_mass = peptide.modifications.iter().sum::<f32>()
+        + peptide.nterm.unwrap_or(0.0)
+        + peptide.cterm.unwrap_or(0.0);
+    encode(&peptide.sequence, modification_mass, peptide.monoisotopic, charge)
+}
+
+fn dot(coefficients: &[f64], x: &[f64; NUM_FEATURES]) -> f64 {
+    coefficients.iter().zip(x.iter()).map(|(c, v)| c * v).sum()
+}
+
+fn thread_pool(num_threads: usize) -> rayon::ThreadPool {
+    ThreadPoolBuilder::new().num_threads(num_threads).build().unwrap()
+}
+
+/// (median absolute error, coefficient of determination) of predicted against observed values
+fn prediction_accuracy(predicted: &[f64], observed: &[f64]) -> (f32, f32) {
+    let n = observed.len() as f64;
+    let mean_observed = observed.iter().sum::<f64>() / n;
+    let ss_res: f64 = predicted.iter().zip(observed).map(|(p, o)| (o - p).powi(2)).sum();
+    let ss_tot: f64 = observed.iter().map(|o| (o - mean_observed).powi(2)).sum();
+    let median_absolute_error = median(predicted.iter().zip(observed).map(|(p, o)| (p - o).abs())).unwrap_or(0.0);
+    let r_squared = match ss_tot > 0.0 {
+        true => 1.0 - ss_res / ss_tot,
+        false => 0.0,
+    };
+    (median_absolute_error as f32, r_squared as f32)
+}
+
+/// Linear ion mobility (1/K0) model on amino acid composition, length, modification mass and
+/// charge, with the mass^(2/3) / charge term following the growth of the collision cross section
+/// with mass
+#[pyclass]
+#[derive(Clone)]
+pub struct PyMobilityModel {
+    pub coefficients: Vec<f64>,
+    /// Most frequent charge of the training peptides, assumed by `retrain` without charges
+    pub training_charge: u8,
+}
+
+/// Most frequent charge, the lowest of equally frequent ones
+fn most_frequent_charge(charges: &[u8]) -> u8 {
+    let mut counts = [0usize; 256];
+    for charge in charges {
+        counts[*charge as usize] += 1;
+    }
+    (0..=u8::MAX)
+        .max_by_key(|charge| (counts[*charge as usize], std::cmp::Reverse(*charge)))
+        .unwrap_or_default()
+}
+
+impl PyMobilityModel {
+    fn fit_rows(rows: &[[f64; NUM_FEATURES]], targets: &[f64], ridge: f64, training_charge: u8) -> PyResult<Self> {
+        if rows.len() < NUM_FEATURES {
+            return Err(PyValueError::new_err(format!(
+                "mobility model requires at least {} training peptides, got {}",
+                NUM_FEATURES,
+                rows.len()
+            )));
+        }
+        let mut a = vec![vec![0.0; NUM_FEATURES]; NUM_FEATURES];
+        let mut b = vec![0.0; NUM_FEATURES];
+        for (x, y) in rows.iter().zip(targets) {
+            for i in 0..NUM_FEATURES {
+                b[i] += x[i] * y;
+                for j in 0..NUM_FEATURES {
+                    a[i][j] += x[i] * x[j];
+                }
+            }
+        }
+        // the intercept (last feature) is not penalized
+        for (i, row) in a.iter_mut().enumerate().take(NUM_FEATURES - 1) {
+            row[i] += ridge;
+        }
+        let coefficients = solve_linear(a, b)
+            .ok_or_else(|| PyValueError::new_err("mobility model fit failed, training peptides are degenerate"))?;
+        Ok(PyMobilityModel { coefficients, training_charge })
+    }
+
+    fn predict_row(&self, x: &[f64; NUM_FEATURES]) -> f32 {
+        dot(&self.coefficients, x) as f32
+    }
+
+    /// Normalized least mean squares updates towards `targets`, one pass over the rows per epoch.
+    /// Each step is scaled by the squared norm of its row, which keeps the update stable for any
+    /// `learning_rate` in (0, 2) regardless of the scale of the features
+    fn adapt_rows(&mut self, rows: &[[f64; NUM_FEATURES]], targets: &[f64], num_epochs: u32, learning_rate: f64) {
+        for _ in 0..num_epochs {
+            for (x, y) in rows.iter().zip(targets) {
+                let step = learning_rate * (y - dot(&self.coefficients, x)) / dot(x, x);
+                for (c, v) in self.coefficients.iter_mut().zip(x.iter()) {
+                    *c += step * v;
+                }
+            }
+        }
+    }
+
+    /// Predicted inverse ion mobility of a database peptide at the given charge
+    pub(crate) fn predict_peptide(&self, peptide: &Peptide, charge: u8) -> f32 {
+        self.predict_row(&encode_peptide(peptide, charge))
+    }
+}
+
+#[pymethods]
+impl PyMobilityModel {
+    /// Fit on peptide sequences, their precursor charges and observed inverse ion mobilities,
+    /// `modifications[i]` maps residue positions of `sequences[i]` to their mass shifts
+    #[staticmethod]
+    pub fn fit(
+        sequences: Vec<String>,
+        charges: Vec<u8>,
+        inverse_ion_mobilities: Vec<f32>,
+        modifications: Option<Vec<HashMap<u32, f32>>>,
+        ridge: Option<f64>,
+    ) -> PyResult<Self> {
+        if inverse_ion_mobilities.len() != sequences.len() {
+            return Err(PyValueError::new_err(format!(
+                "sequences and inverse_ion_mobilities must have the same length, got {} and {}",
+                sequences.len(),
+                inverse_ion_mobilities.len()
+            )));
+        }
+        let rows = encode_sequences(&sequences, &charges, modifications.as_deref())?;
+        let targets: Vec<f64> = inverse_ion_mobilities.iter().map(|ims| *ims as f64).collect();
+        Self::fit_rows(&rows, &targets, ridge.unwrap_or(1e-3), most_frequent_charge(&charges))
+    }
+
+    /// Coefficients of the amino acids ACDEFGHIKLMNPQRSTVWY, length, modification mass (per
+    /// 100 Da), inverse charge, (mass / 100 Da)^(2/3) / charge, intercept
+    #[getter]
+    pub fn coefficients(&self) -> Vec<f64> {
+        self.coefficients.clone()
+    }
+
+    /// Most frequent precursor charge of the training peptides
+    #[getter]
+    pub fn training_charge(&self) -> u8 {
+        self.training_charge
+    }
+
+    pub fn predict(&self, sequence: &str, charge: u8, modifications: Option<HashMap<u32, f32>>) -> f32 {
+        self.predict_row(&encode_sequence(sequence, modifications.as_ref(), charge))
+    }
+
+    #[pyo3(signature = (sequences, modifications, charges, num_threads=None))]
+    pub fn predict_batch(
+        &self,
+        py: Python,
+        sequences: Vec<String>,
+        modifications: Option<Vec<HashMap<u32, f32>>>,
+        charges: Vec<u8>,
+        num_threads: Option<usize>,
+    ) -> PyResult<Vec<f32>> {
+        let rows = encode_sequences(&sequences, &charges, modifications.as_deref())?;
+        let pool = thread_pool(num_threads.unwrap_or(4));
+        Ok(py.allow_threads(|| pool.install(|| rows.par_iter().map(|x| self.predict_row(x)).collect())))
+    }
+
+    /// Set `inverse_ion_mobility_predicted` of all PSMs at their precursor charge, and
+    /// `delta_ims_model` (absolute difference to the observed `ims`) of those with an observed
+    /// ion mobility
+    #[pyo3(signature = (psms, db, num_threads=None))]
+    pub fn predict_from_psms(
+        &self,
+        py: Python,
+        psms: Vec<PyFeature>,
+        db: &PyIndexedDatabase,
+        num_threads: Option<usize>,
+    ) -> PyResult<Vec<PyFeature>> {
+        check_peptide_indices(&db.inner, &psms)?;
+        let mut psms = psms;
+        let pool = thread_pool(num_threads.unwrap_or(4));
+        py.allow_threads(|| {
+            pool.install(|| {
+                psms.par_iter_mut().for_each(|psm| {
+                    let predicted = self.predict_peptide(&db.inner[psm.inner.peptide_idx], psm.inner.charge);
+                    psm.inverse_ion_mobility_predicted = Some(predicted);
+                    psm.delta_ims_model = psm.ims.map(|ims| (ims - predicted).abs());
+                })
+            })
+        });
+        Ok(psms)
+    }
+
+    /// (median absolute error, R²) of the predicted against the observed inverse ion mobility of
+    /// the PSMs with an observed `ims`, ideally PSMs the model was not fitted on
+    pub fn evaluate_model(&self, psms: Vec<PyFeature>, db: &PyIndexedDatabase) -> PyResult<(f32, f32)> {
+        check_peptide_indices(&db.inner, &psms)?;
+        let (predicted, observed): (Vec<f64>, Vec<f64>) = psms
+            .iter()
+            .filter_map(|psm| {
+                let observed = psm.ims?;
+                let predicted = self.predict_peptide(&db.inner[psm.inner.peptide_idx], psm.inner.charge);
+                Some((predicted as f64, observed as f64))
+            })
+            .unzip();
+        if observed.is_empty() {
+            return Err(PyValueError::new_err("no PSMs with an observed ion mobility"));
+        }
+        Ok(prediction_accuracy(&predicted, &observed))
+    }
+
+    /// Adapt the coefficients to new observations (e.g. another instrument calibration) by
+    /// `num_epochs` passes of online gradient updates, starting from the current fit. Without
+    /// `charges`, all sequences are taken at the `training_charge`
+    #[pyo3(signature = (sequences, observed_ims, num_epochs, charges=None, modifications=None, learning_rate=None))]
+    pub fn retrain(
+        &mut self,
+        sequences: Vec<String>,
+        observed_ims: Vec<f32>,
+        num_epochs: u32,
+        charges: Option<Vec<u8>>,
+        modifications: Option<Vec<HashMap<u32, f32>>>,
+        learning_rate: Option<f64>,
+    ) -> PyResult<()> {
+        if observed_ims.len() != sequences.len() {
+            return Err(PyValueError::new_err(format!(
+                "sequences and observed_ims must have the same length, got {} and {}",
+                sequences.len(),
+                observed_ims.len()
+            )));
+        }
+        let learning_rate = learning_rate.unwrap_or(0.1);
+        if !(learning_rate > 0.0 && learning_rate < 2.0) {
+            return Err(PyValueError::new_err(format!(
+                "learning_rate must be in (0, 2), got {}",
+                learning_rate
+            )));
+        }
+        let charges = charges.unwrap_or_else(|| vec![self.training_charge; sequences.len()]);
+        let rows = encode_sequences(&sequences, &charges, modifications.as_deref())?;
+        let targets: Vec<f64> = observed_ims.iter().map(|ims| *ims as f64).collect();
+        self.adapt_rows(&rows, &targets, num_epochs, learning_rate);
+        Ok(())
+    }
+}
+
+/// Cache the predicted inverse ion mobility of every peptide at charges 1 to 6 in `db`, used by
+/// the ion mobility filter of `PyScorer` (see `ims_tolerance`). The database is updated in place
+/// rather than copied, the predictions are not written by `to_file`
+#[pyfunction]
+pub fn precompute_ims_predictions(py: Python, mut db: PyRefMut<PyIndexedDatabase>, mobility_model: &PyMobilityModel) {
+    let db = &mut *db;
+    py.allow_threads(|| {
+        db.set_ims_predictions(|peptide| std::array::from_fn(|k| mobility_model.predict_peptide(peptide, k as u8 + 1)))
+    })
+}
+
+#[pymodule]
+pub fn mobility_model(_py: Python, m: &PyModule) -> PyResult<()> {
+    m.add_class::<PyMobilityModel>()?;
+    m.add_function(wrap_pyfunction!(precompute_ims_predictions, m)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::py_retention_alignment::splitmix64;
+
+    /// Random peptides of 7 to 20 residues at charge 2 or 3, with an inverse ion mobility that
+    /// grows with mass / charge and length, plus up to 0.01 noise
+    fn synthetic_peptides(seed: u64, n: usize, offset: f32) -> (Vec<String>, Vec<u8>, Vec<f32>) {
+        let mut state = seed;
+        let mut uniform = || (splitmix64(&mut state) % 1_000_000) as f32 / 1_000_000.0;
+        let (mut sequences, mut charges, mut ims) = (Vec::new(), Vec::new(), Vec::new());
+        for _ in 0..n {
+            let len = 7 + (uniform() * 14.0) as usize;
+            let sequence: String = (0..len)
+                .map(|_| AMINO_ACIDS[(uniform() * 20.0) as usize % 20] as char)
+                .collect();
+            let charge = 2 + (uniform() * 2.0) as u8 % 2;
+            let mass = sequence.bytes().map(monoisotopic).sum::<f32>() + H2O;
+            let noise = (uniform() - 0.5) * 0.02;
+            ims.push(0.35 + 0.3 * (mass / 100.0).powf(2.0 / 3.0) / charge as f32 + 0.004 * len as f32 + noise + offset);
+            sequences.push(sequence);
+            charges.push(charge);
+        }
+        (sequences, charges, ims)
+    }
+
+    fn accuracy(model: &PyMobilityModel, (sequences, charges, ims): &(Vec<String>, Vec<u8>, Vec<f32>)) -> (f32, f32) {
+        let rows = encode_sequences(sequences, charges, None).unwrap();
+        let predicted: Vec<f64> = rows.iter().map(|x| model.predict_row(x) as f64).collect();
+        let observed: Vec<f64> = ims.iter().map(|ims| *ims as f64).collect();
+        prediction_accuracy(&predicted, &observed)
+    }
+
+    #[test]
+    fn held_out_predictions_explain_the_ion_mobility() {
+        let (sequences, charges, ims) = synthetic_peptides(1, 400, 0.0);
+        let model = PyMobilityModel::fit(sequences, charges, ims, None, None).unwrap();
+
+        let (median_absolute_error, r_squared) = accuracy(&model, &synthetic_peptides(2, 200, 0.0));
+        assert!(r_squared > 0.95, "R² {}", r_squared);
+        assert!(median_absolute_error < 0.01, "median absolute error {}", median_absolute_error);
+    }
+
+    #[test]
+    fn retrain_adapts_to_a_calibration_shift() {
+        let (sequences, charges, ims) = synthetic_peptides(1, 400, 0.0);
+        let mut model = PyMobilityModel::fit(sequences, charges, ims, None, None).unwrap();
+        let shifted = synthetic_peptides(3, 200, 0.05);
+        let (before, _) = accuracy(&model, &shifted);
+
+        let (sequences, charges, ims) = synthetic_peptides(4, 400, 0.05);
+        model.retrain(sequences, ims, 20, Some(charges), None, None).unwrap();
+        let (after, r_squared) = accuracy(&model, &shifted);
+
+        assert!(before > 0.04, "median absolute error before retraining {}", before);
+        assert!(after < 0.015, "median absolute error after retraining {}", after);
+        assert!(r_squared > 0.95, "R² {}", r_squared);
+    }
+
+    #[test]
+    fn retrain_without_charges_uses_the_training_charge() {
+        let at_charge = |(sequences, charges, ims): (Vec<String>, Vec<u8>, Vec<f32>), charge: u8| {
+            let keep: Vec<usize> = (0..charges.len()).filter(|i| charges[*i] == charge).collect();
+            (
+                keep.iter().map(|i| sequences[*i].clone()).collect::<Vec<_>>(),
+                vec![charge; keep.len()],
+                keep.iter().map(|i| ims[*i]).collect::<Vec<_>>(),
+            )
+        };
+        let (sequences, charges, ims) = at_charge(synthetic_peptides(1, 400, 0.0), 3);
+        let mut model = PyMobilityModel::fit(sequences, charges, ims, None, None).unwrap();
+        assert_eq!(model.training_charge, 3);
+        let shifted = at_charge(synthetic_peptides(3, 200, 0.05), 3);
+        let (before, _) = accuracy(&model, &shifted);
+
+        let (sequences, _, ims) = at_charge(synthetic_peptides(4, 400, 0.05), 3);
+        model.retrain(sequences, ims, 20, None, None, None).unwrap();
+        let (after, _) = accuracy(&model, &shifted);
+
+        assert!(after < before / 2.0, "median absolute error {} before and {} after retraining", before, after);
+        assert_eq!(most_frequent_charge(&[2, 3, 3, 2]), 2);
+    }
+
+    #[test]
+    fn mismatched_lengths_are_rejected() {
+        let sequences = vec!["PEPTIDE".to_string(), "PEPTIDEK".to_string()];
+        assert!(encode_sequences(&sequences, &[2], None).is_err());
+        assert!(encode_sequences(&sequences, &[2, 3], Some(&[HashMap::new()])).is_err());
+    }
+}
@@ -4,6 +4,7 @@ use pyo3::types::PyDict;
 use sage_core::modification::{validate_mods, InvalidModification, ModificationSpecificity};
 use std::collections::HashMap;
 use std::str::FromStr;
+use std::sync::OnceLock;
 
 #[pyclass]
 #[derive(Clone, Debug, PartialEq, Hash)]
@@ -39,6 +40,233 @@ impl PyModificationSpecificity {
 
 impl Eq for PyModificationSpecificity {}
 
+/// Unimod accessions of common peptide terminal modifications by their nominal mass: acetyl,
+/// carbamyl, amidated, dimethyl, TMT6plex and TMTpro
+const TERMINAL_UNIMOD_BY_NOMINAL_MASS: [(i32, u32); 6] =
+    [(42, 1), (43, 5), (-1, 2), (28, 36), (229, 737), (304, 2016)];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Terminus {
+    N,
+    C,
+}
+
+/// Modification of the peptide N- or C-terminus, stored as the nterm / cterm mass of a peptide
+#[pyclass]
+#[derive(Clone, Debug)]
+pub struct PyTerminalModification {
+    pub terminus: Terminus,
+    #[pyo3(get)]
+    pub mass_shift: f32,
+    #[pyo3(get)]
+    pub unimod_id: Option<u32>,
+}
+
+#[pymethods]
+impl PyTerminalModification {
+    #[new]
+    pub fn new(terminus: &str, mass_shift: f32, unimod_id: Option<u32>) -> PyResult<Self> {
+        let terminus = match terminus.to_uppercase().as_str() {
+            "N" => Terminus::N,
+            "C" => Terminus::C,
+            _ => {
+                return Err(PyValueError::new_err(format!(
+                    "Invalid terminus: {}, allowed values are: N, C",
+                    terminus
+                )))
+            }
+        };
+        Ok(PyTerminalModification {
+            terminus,
+            mass_shift,
+            unimod_id,
+        })
+    }
+
+    #[staticmethod]
+    pub fn acetyl() -> Self {
+        PyTerminalModification::from_mass(Terminus::N, 42.010565)
+    }
+
+    #[staticmethod]
+    pub fn carbamyl() -> Self {
+        PyTerminalModification::from_mass(Terminus::N, 43.005814)
+    }
+
+    #[staticmethod]
+    pub fn amidated() -> Self {
+        PyTerminalModification::from_mass(Terminus::C, -0.984016)
+    }
+
+    #[getter]
+    pub fn terminus(&self) -> &str {
+        match self.terminus {
+            Terminus::N => "N",
+            Terminus::C => "C",
+        }
+    }
+
+    /// UNIMOD bracket annotation, e.g. `[UNIMOD:1]`, `[+mass]` without Unimod accession
+    #[getter]
+    pub fn unimod_annotation(&self) -> String {
+        match self.unimod_id {
+            Some(id) => format!("[UNIMOD:{}]", id),
+            None => format!("[{:+.4}]", self.mass_shift),
+        }
+    }
+}
+
+impl PyTerminalModification {
+    /// Terminal modification of a mass shift, with the Unimod accession of a common terminal
+    /// modification of the same nominal mass
+    pub fn from_mass(terminus: Terminus, mass_shift: f32) -> Self {
+        let unimod_id = TERMINAL_UNIMOD_BY_NOMINAL_MASS
+            .iter()
+            .find(|(nominal, _)| *nominal == mass_shift.round() as i32)
+            .map(|(_, id)| *id);
+        PyTerminalModification {
+            terminus,
+            mass_shift,
+            unimod_id,
+        }
+    }
+}
+
+/// Common Unimod modifications: accession, name, monoisotopic mass shift and the residues they
+/// occur on, `^` / `$` for the peptide N- / C-terminus
+const UNIMOD_MODIFICATIONS: [(u32, &str, f64, &str); 44] = [
+    (1, "Acetyl", 42.010565, "KSTY^"),
+    (2, "Amidated", -0.984016, "$"),
+    (3, "Biotin", 226.077598, "K^"),
+    (4, "Carbamidomethyl", 57.021464, "CKHDE^"),
+    (5, "Carbamyl", 43.005814, "KRC^"),
+    (6, "Carboxymethyl", 58.005479, "C"),
+    (7, "Deamidated", 0.984016, "NQR"),
+    (17, "NIPCAM", 99.068414, "C"),
+    (21, "Phospho", 79.966331, "STYH"),
+    (23, "Dehydrated", -18.010565, "STDY$"),
+    (24, "Propionamide", 71.037114, "C"),
+    (26, "Pyro-carbamidomethyl", 39.994915, "C"),
+    (27, "Glu->pyro-Glu", -18.010565, "E"),
+    (28, "Gln->pyro-Glu", -17.026549, "Q"),
+    (30, "Cation:Na", 21.981943, "DE$"),
+    (34, "Methyl", 14.01565, "KRHDECSTNQ^$"),
+    (35, "Oxidation", 15.994915, "MWHCY"),
+    (36, "Dimethyl", 28.0313, "KRN^"),
+    (37, "Trimethyl", 42.04695, "KR"),
+    (39, "Methylthio", 45.987721, "C"),
+    (40, "Sulfo", 79.956815, "STY"),
+    (41, "Hex", 162.052824, "KNT"),
+    (43, "HexNAc", 203.079373, "NST"),
+    (58, "Propionyl", 56.026215, "K^"),
+    (64, "Succinyl", 100.016044, "K^"),
+    (108, "Nethylmaleimide", 125.047679, "C"),
+    (121, "GG", 114.042927, "KSTC"),
+    (122, "Formyl", 27.994915, "KST^"),
+    (188, "Label:13C(6)", 6.020129, "KRL"),
+    (214, "iTRAQ4plex", 144.102063, "KY^"),
+    (259, "Label:13C(6)15N(2)", 8.014199, "K"),
+    (267, "Label:13C(6)15N(4)", 10.008269, "R"),
+    (312, "Cysteinyl", 119.004099, "C"),
+    (345, "Trioxidation", 47.984744, "CW"),
+    (354, "Nitro", 44.985078, "YW"),
+    (385, "Ammonia-loss", -17.026549, "C"),
+    (425, "Dioxidation", 31.989829, "MWCY"),
+    (530, "Cation:K", 37.955882, "DE$"),
+    (730, "iTRAQ8plex", 304.20536, "KY^"),
+    (737, "TMT6plex", 229.162932, "KST^"),
+    (747, "Malonyl", 86.000394, "K"),
+    (1289, "Butyryl", 70.041865, "K"),
+    (1363, "Crotonyl", 68.026215, "K"),
+    (2016, "TMTpro", 304.207146, "KST^"),
+];
+
+/// `UNIMOD_MODIFICATIONS` sorted by mass, built once when the module is initialized
+fn unimod_by_mass() -> &'static [(u32, &'static str, f64, &'static str)] {
+    static SORTED: OnceLock<Vec<(u32, &'static str, f64, &'static str)>> = OnceLock::new();
+    SORTED.get_or_init(|| {
+        let mut sorted = UNIMOD_MODIFICATIONS.to_vec();
+        sorted.sort_by(|a, b| a.2.total_cmp(&b.2));
+        sorted
+    })
+}
+
+/// Unimod accession of a modification name of `UNIMOD_MODIFICATIONS`, case-insensitive
+pub(crate) fn unimod_id_by_name(name: &str) -> Option<u32> {
+    UNIMOD_MODIFICATIONS
+        .iter()
+        .find(|(_, n, _, _)| n.eq_ignore_ascii_case(name))
+        .map(|(id, _, _, _)| *id)
+}
+
+/// Unimod modification explaining an observed mass shift
+#[pyclass]
+#[derive(Clone, Debug)]
+pub struct PyModificationCandidate {
+    #[pyo3(get)]
+    pub unimod_id: u32,
+    #[pyo3(get)]
+    pub name: String,
+    #[pyo3(get)]
+    pub theoretical_mass: f64,
+    #[pyo3(get)]
+    pub mass_error_ppm: f32,
+    pub residues: &'static str,
+}
+
+#[pymethods]
+impl PyModificationCandidate {
+    /// Residues the modification occurs on, `^` / `$` for the peptide N- / C-terminus
+    #[getter]
+    pub fn residues(&self) -> &str {
+        self.residues
+    }
+
+    /// UNIMOD bracket annotation, e.g. `[UNIMOD:21]`
+    #[getter]
+    pub fn unimod_annotation(&self) -> String {
+        format!("[UNIMOD:{}]", self.unimod_id)
+    }
+}
+
+/// Unimod modifications with a mass within `tolerance_da` of a delta mass, by increasing
+/// absolute mass error
+#[pyfunction]
+pub fn get_modification_candidates(delta_mass: f32, tolerance_da: f32) -> Vec<PyModificationCandidate> {
+    let delta_mass = delta_mass as f64;
+    let tolerance = tolerance_da.abs() as f64;
+    let sorted = unimod_by_mass();
+    let start = sorted.partition_point(|(_, _, mass, _)| *mass < delta_mass - tolerance);
+
+    let mut candidates: Vec<PyModificationCandidate> = sorted[start..]
+        .iter()
+        .take_while(|(_, _, mass, _)| *mass <= delta_mass + tolerance)
+        .map(|(unimod_id, name, mass, residues)| PyModificationCandidate {
+            unimod_id: *unimod_id,
+            name: name.to_string(),
+            theoretical_mass: *mass,
+            mass_error_ppm: ((delta_mass - mass) / mass.abs() * 1e6) as f32,
+            residues,
+        })
+        .collect();
+    candidates.sort_by(|a, b| a.mass_error_ppm.abs().total_cmp(&b.mass_error_ppm.abs()));
+    candidates
+}
+
+/// The candidate of `get_modification_candidates` with the smallest mass error that can occur on
+/// the observed residue (`^` / `$` for the peptide N- / C-terminus)
+#[pyfunction]
+pub fn get_likely_modification(
+    delta_mass: f32,
+    observed_amino_acid: char,
+    tolerance_da: f32,
+) -> Option<PyModificationCandidate> {
+    let residue = observed_amino_acid.to_ascii_uppercase();
+    get_modification_candidates(delta_mass, tolerance_da)
+        .into_iter()
+        .find(|candidate| candidate.residues.contains(residue))
+}
+
 #[pyfunction]
 pub fn py_validate_mods(input: Option<&PyDict>) -> HashMap<PyModificationSpecificity, f32> {
     // unwrap the input
@@ -89,7 +317,13 @@ pub fn py_validate_var_mods(
 #[pymodule]
 pub fn modification(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_class::<PyModificationSpecificity>()?;
+    m.add_class::<PyTerminalModification>()?;
     m.add_wrapped(wrap_pyfunction!(py_validate_mods))?;
     m.add_wrapped(wrap_pyfunction!(py_validate_var_mods))?;
+    // build the sorted Unimod mass list on import rather than in the first lookup
+    unimod_by_mass();
+    m.add_class::<PyModificationCandidate>()?;
+    m.add_wrapped(wrap_pyfunction!(get_modification_candidates))?;
+    m.add_wrapped(wrap_pyfunction!(get_likely_modification))?;
     Ok(())
 }
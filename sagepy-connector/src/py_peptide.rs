@@ -3,6 +3,7 @@ use pyo3::prelude::*;
 use std::sync::Arc;
 
 use crate::py_enzyme::{PyDigest, PyPosition};
+use crate::py_modification::{PyTerminalModification, Terminus};
 use sage_core::peptide::Peptide;
 
 #[pyclass]
@@ -81,6 +82,33 @@ impl PyPeptide {
         self.inner.cterm
     }
 
+    #[getter]
+    pub fn n_term_mod(&self) -> Option<PyTerminalModification> {
+        self.inner
+            .nterm
+            .map(|m| PyTerminalModification::from_mass(Terminus::N, m))
+    }
+
+    #[getter]
+    pub fn c_term_mod(&self) -> Option<PyTerminalModification> {
+        self.inner
+            .cterm
+            .map(|m| PyTerminalModification::from_mass(Terminus::C, m))
+    }
+
+    /// Peptide with a terminal modification, replacing the previous one of that terminus, the
+    /// monoisotopic mass is updated accordingly
+    pub fn with_terminal_modification(&self, modification: PyTerminalModification) -> PyPeptide {
+        let mut peptide = self.inner.clone();
+        let terminal = match modification.terminus {
+            Terminus::N => &mut peptide.nterm,
+            Terminus::C => &mut peptide.cterm,
+        };
+        peptide.monoisotopic += modification.mass_shift - terminal.unwrap_or_default();
+        *terminal = Some(modification.mass_shift);
+        PyPeptide { inner: peptide }
+    }
+
     #[getter]
     pub fn monoisotopic(&self) -> f32 {
         self.inner.monoisotopic
@@ -115,8 +143,157 @@ impl PyPeptide {
     }
 }
 
+/// Kyte-Doolittle hydropathy index of an amino acid residue
+fn kyte_doolittle(residue: u8) -> f32 {
+    match residue {
+        b'A' => 1.8,
+        b'R' => -4.5,
+        b'N' => -3.5,
+        b'D' => -3.5,
+        b'C' => 2.5,
+        b'Q' => -3.5,
+        b'E' => -3.5,
+        b'G' => -0.4,
+        b'H' => -3.2,
+        b'I' => 4.5,
+        b'L' => 3.8,
+        b'K' => -3.9,
+        b'M' => 1.9,
+        b'F' => 2.8,
+        b'P' => -1.6,
+        b'S' => -0.8,
+        b'T' => -0.7,
+        b'W' => -0.9,
+        b'Y' => -1.3,
+        b'V' => 4.2,
+        _ => 0.0,
+    }
+}
+
+/// Number of internal K/R residues not followed by P
+pub fn tryptic_missed_cleavages(sequence: &[u8]) -> usize {
+    sequence
+        .windows(2)
+        .filter(|w| (w[0] == b'K' || w[0] == b'R') && w[1] != b'P')
+        .count()
+}
+
+// logistic model coefficients of the detectability predictor
+const DETECTABILITY_INTERCEPT: f32 = 1.5;
+const DETECTABILITY_OPTIMAL_LENGTH: f32 = 15.0;
+const DETECTABILITY_LENGTH: f32 = -0.02;
+const DETECTABILITY_GRAVY: f32 = 0.4;
+const DETECTABILITY_GRAVY_SQUARED: f32 = -0.3;
+const DETECTABILITY_MISSED_CLEAVAGE: f32 = -0.8;
+const DETECTABILITY_CYS: f32 = -0.4;
+const DETECTABILITY_MET: f32 = -0.3;
+const DETECTABILITY_TRP: f32 = -0.2;
+const DETECTABILITY_NTERM_PRO: f32 = -1.0;
+
+/// Predict the LC-MS detectability (0-1) of a tryptic peptide from its length,
+/// Kyte-Doolittle hydrophobicity, missed cleavages, Cys/Met/Trp content and N-terminal proline
+pub fn detectability(sequence: &[u8]) -> f32 {
+    if sequence.is_empty() {
+        return 0.0;
+    }
+
+    let length = sequence.len() as f32;
+    let gravy = sequence.iter().map(|&r| kyte_doolittle(r)).sum::<f32>() / length;
+    let missed_cleavages = tryptic_missed_cleavages(sequence) as f32;
+    let count = |residue: u8| sequence.iter().filter(|&&r| r == residue).count() as f32;
+
+    let z = DETECTABILITY_INTERCEPT
+        + DETECTABILITY_LENGTH * (length - DETECTABILITY_OPTIMAL_LENGTH).powi(2)
+        + DETECTABILITY_GRAVY * gravy
+        + DETECTABILITY_GRAVY_SQUARED * gravy.powi(2)
+        + DETECTABILITY_MISSED_CLEAVAGE * missed_cleavages
+        + DETECTABILITY_CYS * count(b'C')
+        + DETECTABILITY_MET * count(b'M')
+        + DETECTABILITY_TRP * count(b'W')
+        + if sequence[0] == b'P' { DETECTABILITY_NTERM_PRO } else { 0.0 };
+
+    1.0 / (1.0 + (-z).exp())
+}
+
+#[pyfunction]
+pub fn predict_detectability(sequence: &str) -> f32 {
+    detectability(sequence.as_bytes())
+}
+
 #[pymodule]
 pub fn peptide(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_class::<PyPeptide>()?;
+    m.add_function(wrap_pyfunction!(predict_detectability, m)?)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missed_cleavages_skip_proline_and_the_c_terminus() {
+        assert_eq!(tryptic_missed_cleavages(b"AKPLRK"), 1);
+        assert_eq!(tryptic_missed_cleavages(b"LVNEVTEFAK"), 0);
+    }
+
+    #[test]
+    fn detectability_ranks_well_behaved_tryptic_peptides_first() {
+        // a frequently observed BSA peptide
+        let good = detectability(b"LVNEVTEFAK");
+        // missed cleavages, N-terminal proline, Cys, Met and Trp
+        let poor = detectability(b"PCMKRWCMK");
+        // far beyond the optimal length
+        let long = detectability(b"PEPTIDEKPEPTIDEKPEPTIDEKPEPTIDEK");
+
+        assert!(good > 0.7, "detectability {}", good);
+        assert!(poor < 0.05, "detectability {}", poor);
+        assert!(long < 0.01, "detectability {}", long);
+        assert_eq!(detectability(b""), 0.0);
+    }
+
+    #[test]
+    fn n_terminal_acetylation_is_applied_once() {
+        use crate::py_scoring::H2O;
+        use sage_core::enzyme::Position;
+        use sage_core::ion_series::{IonSeries, Kind};
+        use sage_core::mass::monoisotopic;
+
+        let sequence = "PEPTIDEK";
+        let peptide = PyPeptide {
+            inner: Peptide {
+                decoy: false,
+                sequence: Arc::from(sequence.as_bytes().to_vec().into_boxed_slice()),
+                modifications: vec![0.0; sequence.len()],
+                nterm: None,
+                cterm: None,
+                monoisotopic: sequence.bytes().map(monoisotopic).sum::<f32>() + H2O,
+                missed_cleavages: 0,
+                position: Position::Full,
+                proteins: vec![Arc::new("sp|P1|A".to_string())],
+                semi_enzymatic: false,
+            },
+        };
+        // applying it twice replaces the first acetylation instead of adding another
+        let acetylated = peptide
+            .with_terminal_modification(PyTerminalModification::acetyl())
+            .with_terminal_modification(PyTerminalModification::acetyl());
+
+        assert!((acetylated.inner.monoisotopic - peptide.inner.monoisotopic - 42.0106).abs() < 1e-3);
+        assert_eq!(acetylated.inner.nterm, Some(42.010565));
+        assert_eq!(acetylated.inner.modifications, peptide.inner.modifications);
+        assert_eq!(acetylated.n_term_mod().unwrap().unimod_id, Some(1));
+        assert_eq!(crate::py_utility::unimod_sequence(&acetylated.inner), "[UNIMOD:1]PEPTIDEK");
+
+        // every b ion carries the N-terminus once, y ions do not include it
+        let masses = |peptide: &PyPeptide, kind: Kind| -> Vec<f32> {
+            IonSeries::new(&peptide.inner, kind).map(|ion| ion.monoisotopic_mass).collect()
+        };
+        for (b, b_acetylated) in masses(&peptide, Kind::B).iter().zip(masses(&acetylated, Kind::B)) {
+            assert!((b_acetylated - b - 42.0106).abs() < 1e-3);
+        }
+        for (y, y_acetylated) in masses(&peptide, Kind::Y).iter().zip(masses(&acetylated, Kind::Y)) {
+            assert!((y_acetylated - y).abs() < 1e-3);
+        }
+    }
+}
@@ -0,0 +1,731 @@
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use sage_core::database::PeptideIx;
+use std::collections::{BTreeMap, HashMap};
+use crate::py_scoring::{weighted_polyfit, PyFeature};
+use crate::py_utility::median;
+
+/// Number of robust re-weighting passes of RLOESS
+pub(crate) const ROBUSTNESS_ITERATIONS: usize = 3;
+
+/// Number of equal-count anchor segments of the piecewise linear alignment
+const PIECEWISE_SEGMENTS: usize = 10;
+
+const ALIGNMENT_METHODS: [&str; 3] = ["linear", "piecewise", "loess"];
+
+fn polyval(coefficients: &[f64], x: f64) -> f64 {
+    coefficients.iter().rev().fold(0.0, |acc, c| acc * x + c)
+}
+
+fn tricube(d: f64) -> f64 {
+    if d < 1.0 {
+        (1.0 - d.powi(3)).powi(3)
+    } else {
+        0.0
+    }
+}
+
+fn bisquare(u: f64) -> f64 {
+    if u.abs() < 1.0 {
+        (1.0 - u * u).powi(2)
+    } else {
+        0.0
+    }
+}
+
+/// Local polynomial regression over anchors sorted by x, optionally refined by robust (bisquare)
+/// re-weighting of anchors with large residuals (RLOESS)
+pub(crate) struct Loess {
+    points: Vec<(f64, f64)>,
+    robustness: Vec<f64>,
+    neighbours: usize,
+    degree: usize,
+}
+
+impl Loess {
+    pub(crate) fn fit(mut points: Vec<(f64, f64)>, bandwidth: f32, degree: usize, iterations: usize) -> Self {
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let neighbours = ((points.len() as f64 * bandwidth as f64).ceil() as usize).clamp(degree + 1, points.len());
+        let mut loess = Loess {
+            robustness: vec![1.0; points.len()],
+            points,
+            neighbours,
+            degree,
+        };
+
+        for _ in 0..iterations {
+            let residuals: Vec<f64> = loess.points.iter().map(|(x, y)| y - loess.predict(*x)).collect();
+            let scale = median(residuals.iter().map(|r| r.abs())).unwrap_or(0.0);
+            if scale <= 1e-9 {
+                break;
+            }
+            loess.robustness = residuals.iter().map(|r| bisquare(r / (6.0 * scale))).collect();
+        }
+        loess
+    }
+
+    /// Fit at x on the `neighbours` anchors nearest to x, with tricube distance weights
+    pub(crate) fn predict(&self, x: f64) -> f64 {
+        let n = self.points.len();
+        let (mut lo, mut hi) = {
+            let i = self.points.partition_point(|p| p.0 < x);
+            (i, i)
+        };
+        while hi - lo < self.neighbours {
+            if lo == 0 {
+                hi += 1;
+            } else if hi == n || x - self.points[lo - 1].0 <= self.points[hi].0 - x {
+                lo -= 1;
+            } else {
+                hi += 1;
+            }
+        }
+        let window = &self.points[lo..hi];
+        let max_distance = (x - window[0].0).max(window[window.len() - 1].0 - x).max(1e-9) * 1.0001;
+
+        let local = |robust: bool| {
+            let weights: Vec<f64> = window
+                .iter()
+                .zip(&self.robustness[lo..hi])
+                .map(|(p, r)| tricube((p.0 - x).abs() / max_distance) * if robust { *r } else { 1.0 })
+                .collect();
+            if weights.iter().sum::<f64>() <= 0.0 {
+                return None;
+            }
+            weighted_polyfit(window, &weights, self.degree).or_else(|| weighted_polyfit(window, &weights, 0))
+        };
+        local(true)
+            .or_else(|| local(false))
+            .map_or(window[0].1, |c| polyval(&c, x))
+    }
+}
+
+enum AlignmentModel {
+    Polynomial(Vec<f64>),
+    Piecewise(Vec<(f64, f64)>),
+    Loess(Loess),
+}
+
+impl AlignmentModel {
+    /// Fit one of `ALIGNMENT_METHODS` to (query, reference) anchors
+    fn fit(anchors: Vec<(f64, f64)>, method: &str, bandwidth: f32, degree: usize) -> PyResult<Self> {
+        match method {
+            "linear" => {
+                let weights = vec![1.0; anchors.len()];
+                weighted_polyfit(&anchors, &weights, 1)
+                    .map(AlignmentModel::Polynomial)
+                    .ok_or_else(|| PyValueError::new_err("linear alignment failed, anchors are degenerate"))
+            }
+            "piecewise" => Ok(AlignmentModel::Piecewise(piecewise_nodes(anchors))),
+            _ => Ok(AlignmentModel::Loess(Loess::fit(anchors, bandwidth, degree, ROBUSTNESS_ITERATIONS))),
+        }
+    }
+
+    fn predict(&self, x: f64) -> f64 {
+        match self {
+            AlignmentModel::Polynomial(coefficients) => polyval(coefficients, x),
+            AlignmentModel::Loess(loess) => loess.predict(x),
+            AlignmentModel::Piecewise(nodes) => {
+                if nodes.len() == 1 {
+                    return x - nodes[0].0 + nodes[0].1;
+                }
+                // interpolate between the enclosing nodes, extrapolate with the outer segments
+                let i = nodes.partition_point(|n| n.0 < x).clamp(1, nodes.len() - 1);
+                let ((x0, y0), (x1, y1)) = (nodes[i - 1], nodes[i]);
+                let slope = if x1 > x0 { (y1 - y0) / (x1 - x0) } else { 1.0 };
+                y0 + slope * (x - x0)
+            }
+        }
+    }
+}
+
+/// Median anchor of each of up to `PIECEWISE_SEGMENTS` equal-count segments of the anchors
+fn piecewise_nodes(mut anchors: Vec<(f64, f64)>) -> Vec<(f64, f64)> {
+    anchors.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let segments = PIECEWISE_SEGMENTS.min(anchors.len());
+    let size = (anchors.len() as f64 / segments as f64).ceil() as usize;
+    anchors
+        .chunks(size)
+        .map(|chunk| {
+            let x = median(chunk.iter().map(|a| a.0)).unwrap_or(0.0);
+            let y = median(chunk.iter().map(|a| a.1)).unwrap_or(0.0);
+            (x, y)
+        })
+        .collect()
+}
+
+fn check_loess_parameters(bandwidth: f32, polynomial_degree: usize) -> PyResult<()> {
+    if !(bandwidth > 0.0 && bandwidth <= 1.0) {
+        return Err(PyValueError::new_err("bandwidth must be in (0, 1]"));
+    }
+    if !(1..=2).contains(&polynomial_degree) {
+        return Err(PyValueError::new_err("polynomial_degree must be 1 or 2"));
+    }
+    Ok(())
+}
+
+/// Map `target_rts` of a query run onto a reference run with robust LOESS (tricube weights over the
+/// nearest `bandwidth` fraction of anchors, bisquare re-weighting of outliers). Anchors are the
+/// retention times of the same peptides in both runs, `query_rts[i]` pairs with `reference_rts[i]`
+#[pyfunction]
+pub fn loess_align(
+    reference_rts: Vec<f32>,
+    query_rts: Vec<f32>,
+    target_rts: Vec<f32>,
+    bandwidth: f32,
+    polynomial_degree: Option<usize>,
+) -> PyResult<Vec<f32>> {
+    let degree = polynomial_degree.unwrap_or(1);
+    check_loess_parameters(bandwidth, degree)?;
+    if reference_rts.len() != query_rts.len() {
+        return Err(PyValueError::new_err(format!(
+            "reference_rts and query_rts must have the same length, got {} and {}",
+            reference_rts.len(),
+            query_rts.len()
+        )));
+    }
+    if query_rts.len() < degree + 2 {
+        return Err(PyValueError::new_err(format!(
+            "at least {} anchors are required, got {}",
+            degree + 2,
+            query_rts.len()
+        )));
+    }
+
+    let anchors = query_rts
+        .iter()
+        .zip(reference_rts.iter())
+        .map(|(q, r)| (*q as f64, *r as f64))
+        .collect();
+    let loess = Loess::fit(anchors, bandwidth, degree, ROBUSTNESS_ITERATIONS);
+    Ok(target_rts.iter().map(|rt| loess.predict(*rt as f64) as f32).collect())
+}
+
+/// Linear map of query onto reference retention times fitted by RANSAC, with the number of
+/// anchors it explains
+#[pyclass]
+#[derive(Clone)]
+pub struct PyLinearModel {
+    #[pyo3(get)]
+    pub slope: f32,
+    #[pyo3(get)]
+    pub intercept: f32,
+    #[pyo3(get)]
+    pub num_inliers: usize,
+    #[pyo3(get)]
+    pub num_anchors: usize,
+}
+
+#[pymethods]
+impl PyLinearModel {
+    pub fn predict(&self, rt: f32) -> f32 {
+        self.slope * rt + self.intercept
+    }
+
+    /// Fraction of the anchors within the inlier threshold of the model
+    pub fn inlier_fraction(&self) -> f32 {
+        match self.num_anchors {
+            0 => 0.0,
+            n => self.num_inliers as f32 / n as f32,
+        }
+    }
+}
+
+/// Seed of the anchor sampling of RANSAC, fixed to keep alignments reproducible
+const RANSAC_SEED: u64 = 0x5eed_a116_0000_0001;
+
+/// SplitMix64 step, the pseudo-random source of RANSAC
+pub(crate) fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// RANSAC line through (query, reference) anchors: lines through random anchor pairs are scored
+/// by their number of anchors within `threshold` (min), the best consensus set is refitted by
+/// least squares. Returns the model and the inlier mask, None if fewer than `min_inliers` agree
+fn ransac_fit(
+    anchors: &[(f64, f64)],
+    max_iterations: u32,
+    threshold: f64,
+    min_inliers: usize,
+) -> Option<(PyLinearModel, Vec<bool>)> {
+    let n = anchors.len();
+    if n < 2 {
+        return None;
+    }
+    let inliers_of = |slope: f64, intercept: f64| -> Vec<bool> {
+        anchors
+            .iter()
+            .map(|(x, y)| (y - (slope * x + intercept)).abs() <= threshold)
+            .collect()
+    };
+
+    let mut state = RANSAC_SEED;
+    let mut best: Option<Vec<bool>> = None;
+    let mut best_count = 0;
+    for _ in 0..max_iterations {
+        let i = (splitmix64(&mut state) % n as u64) as usize;
+        let j = (splitmix64(&mut state) % n as u64) as usize;
+        let ((x0, y0), (x1, y1)) = (anchors[i], anchors[j]);
+        if i == j || x0 == x1 {
+            continue;
+        }
+        let slope = (y1 - y0) / (x1 - x0);
+        let mask = inliers_of(slope, y0 - slope * x0);
+        let count = mask.iter().filter(|m| **m).count();
+        if count > best_count {
+            best_count = count;
+            best = Some(mask);
+        }
+    }
+
+    let consensus: Vec<(f64, f64)> = anchors
+        .iter()
+        .zip(best?.iter())
+        .filter(|(_, inlier)| **inlier)
+        .map(|(anchor, _)| *anchor)
+        .collect();
+    let weights = vec![1.0; consensus.len()];
+    let coefficients = weighted_polyfit(&consensus, &weights, 1)?;
+    let mask = inliers_of(coefficients[1], coefficients[0]);
+    let num_inliers = mask.iter().filter(|m| **m).count();
+    if num_inliers < min_inliers.max(2) {
+        return None;
+    }
+
+    let model = PyLinearModel {
+        slope: coefficients[1] as f32,
+        intercept: coefficients[0] as f32,
+        num_inliers,
+        num_anchors: n,
+    };
+    Some((model, mask))
+}
+
+/// Robust linear map of `query_rts` onto `reference_rts` by RANSAC, `query_rts[i]` pairs with
+/// `reference_rts[i]`. Returns the model and the inlier mask of the anchors
+#[pyfunction]
+pub fn ransac_align_rts(
+    reference_rts: Vec<f32>,
+    query_rts: Vec<f32>,
+    max_iterations: u32,
+    inlier_threshold_min: f32,
+    min_inliers: usize,
+) -> PyResult<(PyLinearModel, Vec<bool>)> {
+    if reference_rts.len() != query_rts.len() {
+        return Err(PyValueError::new_err(format!(
+            "reference_rts and query_rts must have the same length, got {} and {}",
+            reference_rts.len(),
+            query_rts.len()
+        )));
+    }
+    let anchors: Vec<(f64, f64)> = query_rts
+        .iter()
+        .zip(reference_rts.iter())
+        .map(|(q, r)| (*q as f64, *r as f64))
+        .collect();
+    ransac_fit(&anchors, max_iterations, inlier_threshold_min as f64, min_inliers).ok_or_else(|| {
+        PyValueError::new_err(format!(
+            "RANSAC found no linear model supported by {} anchors within {} min",
+            min_inliers, inlier_threshold_min
+        ))
+    })
+}
+
+/// (query, reference) retention time anchors of each aligned run
+type RunAnchors = BTreeMap<usize, Vec<(f64, f64)>>;
+
+/// Retention time alignment of runs onto a reference run, anchored on the confidently identified
+/// peptides shared between runs
+#[pyclass]
+#[derive(Clone)]
+pub struct PyRetentionAlignment {
+    pub reference_file_id: Option<usize>,
+    pub bandwidth: f32,
+    pub polynomial_degree: usize,
+    pub fdr_cutoff: f32,
+}
+
+impl PyRetentionAlignment {
+    /// Reference run and the (query, reference) retention time anchors of every other run: the
+    /// median retention times of the rank 1 target peptides at `fdr_cutoff` shared with the
+    /// reference. None if there are no PSMs
+    fn run_anchors(&self, psms: &[PyFeature]) -> PyResult<Option<(usize, RunAnchors)>> {
+        let mut peptide_rts: BTreeMap<usize, HashMap<PeptideIx, Vec<f64>>> = BTreeMap::new();
+        for psm in psms {
+            peptide_rts.entry(psm.inner.file_id).or_default();
+            if psm.inner.label == 1 && psm.inner.rank == 1 && psm.inner.spectrum_q <= self.fdr_cutoff {
+                peptide_rts
+                    .get_mut(&psm.inner.file_id)
+                    .unwrap()
+                    .entry(psm.inner.peptide_idx)
+                    .or_default()
+                    .push(psm.inner.rt as f64);
+            }
+        }
+        let run_rts: BTreeMap<usize, HashMap<PeptideIx, f64>> = peptide_rts
+            .into_iter()
+            .map(|(file_id, peptides)| {
+                let medians = peptides
+                    .into_iter()
+                    .filter_map(|(idx, rts)| Some((idx, median(rts)?)))
+                    .collect();
+                (file_id, medians)
+            })
+            .collect();
+
+        let reference = match self.reference_file_id {
+            Some(file_id) if run_rts.contains_key(&file_id) => file_id,
+            Some(file_id) => {
+                return Err(PyValueError::new_err(format!("no PSMs of reference run {}", file_id)))
+            }
+            None => match run_rts.iter().max_by_key(|(file_id, rts)| (rts.len(), std::cmp::Reverse(**file_id))) {
+                Some((file_id, _)) => *file_id,
+                None => return Ok(None),
+            },
+        };
+
+        let anchors = run_rts
+            .iter()
+            .filter(|(file_id, _)| **file_id != reference)
+            .map(|(file_id, rts)| {
+                let anchors = rts
+                    .iter()
+                    .filter_map(|(idx, rt)| run_rts[&reference].get(idx).map(|reference_rt| (*rt, *reference_rt)))
+                    .collect();
+                (*file_id, anchors)
+            })
+            .collect();
+        Ok(Some((reference, anchors)))
+    }
+}
+
+#[pymethods]
+impl PyRetentionAlignment {
+    #[new]
+    pub fn new(
+        reference_file_id: Option<usize>,
+        bandwidth: f32,
+        polynomial_degree: usize,
+        fdr_cutoff: f32,
+    ) -> PyResult<Self> {
+        check_loess_parameters(bandwidth, polynomial_degree)?;
+        Ok(PyRetentionAlignment {
+            reference_file_id,
+            bandwidth,
+            polynomial_degree,
+            fdr_cutoff,
+        })
+    }
+
+    #[getter]
+    pub fn reference_file_id(&self) -> Option<usize> {
+        self.reference_file_id
+    }
+
+    #[getter]
+    pub fn bandwidth(&self) -> f32 {
+        self.bandwidth
+    }
+
+    #[getter]
+    pub fn polynomial_degree(&self) -> usize {
+        self.polynomial_degree
+    }
+
+    #[getter]
+    pub fn fdr_cutoff(&self) -> f32 {
+        self.fdr_cutoff
+    }
+
+    /// Set `aligned_rt` of all PSMs to the retention time of the reference run (the run with most
+    /// identified peptides unless `reference_file_id` is set). `method` is "linear", "piecewise"
+    /// or "loess", anchors are the median retention times of rank 1 target peptides at
+    /// `fdr_cutoff` spectrum q-value
+    pub fn align(&self, psms: Vec<PyFeature>, method: &str) -> PyResult<Vec<PyFeature>> {
+        let mut psms = psms;
+        let method = method.to_lowercase();
+        if !ALIGNMENT_METHODS.contains(&method.as_str()) {
+            return Err(PyValueError::new_err(format!(
+                "Invalid alignment method: {}, allowed values are: {}",
+                method,
+                ALIGNMENT_METHODS.join(", ")
+            )));
+        }
+
+        let Some((reference, run_anchors)) = self.run_anchors(&psms)? else {
+            return Ok(psms);
+        };
+
+        let mut models: HashMap<usize, AlignmentModel> = HashMap::new();
+        for (file_id, anchors) in run_anchors {
+            if anchors.len() < self.polynomial_degree + 2 {
+                return Err(PyValueError::new_err(format!(
+                    "run {} shares only {} identified peptides with reference run {}",
+                    file_id,
+                    anchors.len(),
+                    reference
+                )));
+            }
+            let model = AlignmentModel::fit(anchors, &method, self.bandwidth, self.polynomial_degree)?;
+            models.insert(file_id, model);
+        }
+
+        for psm in psms.iter_mut() {
+            psm.inner.aligned_rt = match models.get(&psm.inner.file_id) {
+                Some(model) => model.predict(psm.inner.rt as f64) as f32,
+                None => psm.inner.rt,
+            };
+        }
+        Ok(psms)
+    }
+
+    /// Like `align`, robust to misidentified anchors: with `method` "ransac" each run is mapped by
+    /// a RANSAC line (see `ransac_align_rts`). Returns the PSMs and the model of each aligned run
+    pub fn align_robust(
+        &self,
+        psms: Vec<PyFeature>,
+        method: &str,
+        max_iterations: u32,
+        inlier_threshold_min: f32,
+        min_inliers: usize,
+    ) -> PyResult<(Vec<PyFeature>, HashMap<usize, PyLinearModel>)> {
+        let mut psms = psms;
+        if method.to_lowercase() != "ransac" {
+            return Err(PyValueError::new_err(format!(
+                "Invalid robust alignment method: {}, allowed values are: ransac",
+                method
+            )));
+        }
+        let Some((reference, run_anchors)) = self.run_anchors(&psms)? else {
+            return Ok((psms, HashMap::new()));
+        };
+
+        let mut models: HashMap<usize, PyLinearModel> = HashMap::new();
+        for (file_id, anchors) in run_anchors {
+            let (model, _) = ransac_fit(&anchors, max_iterations, inlier_threshold_min as f64, min_inliers)
+                .ok_or_else(|| {
+                    PyValueError::new_err(format!(
+                        "RANSAC found no alignment of run {} onto reference run {} supported by {} anchors",
+                        file_id, reference, min_inliers
+                    ))
+                })?;
+            models.insert(file_id, model);
+        }
+
+        for psm in psms.iter_mut() {
+            psm.inner.aligned_rt = match models.get(&psm.inner.file_id) {
+                Some(model) => model.predict(psm.inner.rt),
+                None => psm.inner.rt,
+            };
+        }
+        Ok((psms, models))
+    }
+}
+
+/// Biognosys iRT kit peptides and their iRT values
+const BIOGNOSYS_IRT_PEPTIDES: [(&str, f32); 11] = [
+    ("LGGNEQVTR", -24.92),
+    ("GAGSSEPVTGLDAK", 0.00),
+    ("VEATFGVDESNAK", 12.39),
+    ("YILAGVENSK", 19.79),
+    ("TPVISGGPYEYR", 28.71),
+    ("TPVITGAPYEYR", 33.38),
+    ("DGLDAASYYAPVR", 42.26),
+    ("ADVTPADFSEWSK", 54.62),
+    ("GTFIIDPGGVIR", 70.52),
+    ("GTFIIDPAAVIR", 87.23),
+    ("LFLQFGAQGSPFLK", 100.00),
+];
+
+/// Linear map of observed retention times onto the iRT scale
+#[pyclass]
+#[derive(Clone)]
+pub struct PyIrtModel {
+    #[pyo3(get)]
+    pub slope: f32,
+    #[pyo3(get)]
+    pub intercept: f32,
+}
+
+#[pymethods]
+impl PyIrtModel {
+    #[new]
+    pub fn new(slope: f32, intercept: f32) -> Self {
+        PyIrtModel { slope, intercept }
+    }
+
+    pub fn predict_irt(&self, rt: f32) -> f32 {
+        self.slope * rt + self.intercept
+    }
+}
+
+/// Least squares fit of iRT values on the observed retention times of spike-in peptides,
+/// `observed_rts[i]` pairs with `irt_values[i]`
+#[pyfunction]
+pub fn compute_irt_regression(observed_rts: Vec<f32>, irt_values: Vec<f32>) -> PyResult<PyIrtModel> {
+    if observed_rts.len() != irt_values.len() {
+        return Err(PyValueError::new_err(format!(
+            "observed_rts and irt_values must have the same length, got {} and {}",
+            observed_rts.len(),
+            irt_values.len()
+        )));
+    }
+    let anchors: Vec<(f64, f64)> = observed_rts
+        .iter()
+        .zip(irt_values.iter())
+        .map(|(rt, irt)| (*rt as f64, *irt as f64))
+        .collect();
+    if anchors.len() < 2 {
+        return Err(PyValueError::new_err("iRT regression requires at least 2 peptides"));
+    }
+
+    let weights = vec![1.0; anchors.len()];
+    let coefficients = weighted_polyfit(&anchors, &weights, 1)
+        .ok_or_else(|| PyValueError::new_err("iRT regression failed, observed retention times are degenerate"))?;
+    Ok(PyIrtModel {
+        slope: coefficients[1] as f32,
+        intercept: coefficients[0] as f32,
+    })
+}
+
+/// Set `aligned_rt` of all PSMs to their retention time on the iRT scale, the observed `rt` is kept
+#[pyfunction]
+pub fn normalize_rts_to_irt(psms: Vec<PyFeature>, irt_model: PyIrtModel) -> Vec<PyFeature> {
+    let mut psms = psms;
+    for psm in psms.iter_mut() {
+        psm.inner.aligned_rt = irt_model.predict_irt(psm.inner.rt);
+    }
+    psms
+}
+
+#[pymodule]
+pub fn retention_alignment(_py: Python, m: &PyModule) -> PyResult<()> {
+    m.add_class::<PyRetentionAlignment>()?;
+    m.add_function(wrap_pyfunction!(loess_align, m)?)?;
+    m.add_class::<PyLinearModel>()?;
+    m.add_function(wrap_pyfunction!(ransac_align_rts, m)?)?;
+    m.add_class::<PyIrtModel>()?;
+    m.add_function(wrap_pyfunction!(compute_irt_regression, m)?)?;
+    m.add_function(wrap_pyfunction!(normalize_rts_to_irt, m)?)?;
+    m.add(
+        "BIOGNOSYS_IRT_PEPTIDES",
+        BIOGNOSYS_IRT_PEPTIDES
+            .iter()
+            .map(|(sequence, irt)| (sequence.to_string(), *irt))
+            .collect::<Vec<_>>(),
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loess_recovers_a_non_linear_retention_time_warp() {
+        let warp = |rt: f32| rt + 2.0 * (rt / 10.0).sin();
+        let mut query_rts: Vec<f32> = (0..=200).map(|i| i as f32 * 0.5).collect();
+        let mut reference_rts: Vec<f32> = query_rts.iter().map(|rt| warp(*rt)).collect();
+        // misidentified anchors far off the warp are down-weighted by the robustness passes
+        for rt in [20.25, 45.25, 70.25] {
+            query_rts.push(rt);
+            reference_rts.push(warp(rt) + 15.0);
+        }
+
+        let targets = vec![10.0, 33.3, 50.0, 66.6, 90.0];
+        let aligned = loess_align(reference_rts.clone(), query_rts.clone(), targets.clone(), 0.1, Some(2)).unwrap();
+        for (target, rt) in targets.iter().zip(aligned) {
+            assert!((rt - warp(*target)).abs() < 0.05, "{} aligned to {}", target, rt);
+        }
+
+        // a single global line cannot follow the warp
+        let weights = vec![1.0; query_rts.len()];
+        let anchors: Vec<(f64, f64)> = query_rts
+            .iter()
+            .zip(&reference_rts)
+            .map(|(q, r)| (*q as f64, *r as f64))
+            .collect();
+        let line = weighted_polyfit(&anchors, &weights, 1).unwrap();
+        assert!(targets.iter().any(|t| (polyval(&line, *t as f64) as f32 - warp(*t)).abs() > 0.5));
+    }
+
+    #[test]
+    fn loess_align_checks_its_parameters() {
+        let rts = vec![1.0, 2.0, 3.0, 4.0];
+        assert!(loess_align(rts.clone(), rts.clone(), rts.clone(), 0.0, None).is_err());
+        assert!(loess_align(rts.clone(), rts.clone(), rts.clone(), 0.5, Some(3)).is_err());
+        assert!(loess_align(rts.clone(), rts[..3].to_vec(), rts.clone(), 0.5, None).is_err());
+        assert!(loess_align(rts[..2].to_vec(), rts[..2].to_vec(), rts.clone(), 0.5, None).is_err());
+        assert_eq!(loess_align(rts.clone(), rts.clone(), vec![2.5], 1.0, None).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn irt_regression_maps_the_spike_in_peptides_onto_their_irt_values() {
+        // observed retention times (min) of the kit peptides on a 60 min gradient
+        let irt_values: Vec<f32> = BIOGNOSYS_IRT_PEPTIDES.iter().map(|(_, irt)| *irt).collect();
+        let observed_rts: Vec<f32> = irt_values.iter().map(|irt| 20.0 + 0.25 * irt).collect();
+
+        let model = compute_irt_regression(observed_rts.clone(), irt_values.clone()).unwrap();
+        assert!((model.slope - 4.0).abs() < 1e-3 && (model.intercept + 80.0).abs() < 1e-2);
+        for (rt, irt) in observed_rts.iter().zip(&irt_values) {
+            assert!((model.predict_irt(*rt) - irt).abs() < 1e-2);
+        }
+
+        let psm = PyFeature::from(sage_core::scoring::Feature {
+            rt: 45.0,
+            ..crate::py_io::default_feature()
+        });
+        let normalized = normalize_rts_to_irt(vec![psm], model);
+        assert_eq!(normalized[0].inner.rt, 45.0);
+        assert!((normalized[0].inner.aligned_rt - 100.0).abs() < 1e-2);
+
+        assert!(compute_irt_regression(vec![10.0], vec![0.0]).is_err());
+        assert!(compute_irt_regression(vec![10.0, 10.0], vec![0.0, 100.0]).is_err());
+        assert!(compute_irt_regression(vec![10.0, 20.0], vec![0.0]).is_err());
+    }
+
+    #[test]
+    fn ransac_ignores_misidentified_anchors() {
+        // run 1 elutes on a line onto run 0, three of its anchors are misidentified
+        let outliers = [3, 9, 15];
+        let query_rts: Vec<f32> = (0..20)
+            .map(|i| 10.0 + 3.0 * i as f32 + if outliers.contains(&i) { 20.0 } else { 0.0 })
+            .collect();
+        let reference_rts: Vec<f32> = (0..20).map(|i| 1.1 * (10.0 + 3.0 * i as f32) + 2.0).collect();
+
+        let (model, inliers) = ransac_align_rts(reference_rts.clone(), query_rts.clone(), 200, 0.5, 10).unwrap();
+        assert!((model.slope - 1.1).abs() < 1e-3 && (model.intercept - 2.0).abs() < 1e-2);
+        assert_eq!((model.num_inliers, model.num_anchors), (17, 20));
+        assert!((0..20).all(|i| inliers[i] != outliers.contains(&i)));
+        assert!(ransac_align_rts(reference_rts.clone(), query_rts.clone(), 200, 0.5, 18).is_err());
+
+        let psm = |file_id: usize, peptide: u32, rt: f32| {
+            PyFeature::from(sage_core::scoring::Feature {
+                file_id,
+                peptide_idx: PeptideIx(peptide),
+                rt,
+                spectrum_q: 0.001,
+                ..crate::py_io::default_feature()
+            })
+        };
+        let psms: Vec<PyFeature> = (0..20)
+            .flat_map(|i| [psm(0, i as u32, reference_rts[i]), psm(1, i as u32, query_rts[i])])
+            .collect();
+        let alignment = PyRetentionAlignment::new(Some(0), 0.3, 1, 0.01).unwrap();
+        let (aligned, models) = alignment.align_robust(psms.clone(), "ransac", 200, 0.5, 10).unwrap();
+        assert_eq!(models.keys().collect::<Vec<_>>(), vec![&1]);
+        for psm in aligned {
+            let i = psm.inner.peptide_idx.0 as usize;
+            match psm.inner.file_id {
+                0 => assert_eq!(psm.inner.aligned_rt, psm.inner.rt),
+                _ if outliers.contains(&i) => assert!((psm.inner.aligned_rt - reference_rts[i]).abs() > 20.0),
+                _ => assert!((psm.inner.aligned_rt - reference_rts[i]).abs() < 0.05),
+            }
+        }
+        assert!(alignment.align_robust(psms, "loess", 200, 0.5, 10).is_err());
+    }
+}
@@ -0,0 +1,480 @@
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+use std::collections::HashMap;
+
+use crate::py_database::PyIndexedDatabase;
+use crate::py_intensity::pearson;
+use crate::py_retention_alignment::splitmix64;
+use crate::py_scoring::{check_peptide_indices, solve_linear, PyFeature};
+use crate::py_utility::median;
+use sage_core::peptide::Peptide;
+
+const AMINO_ACIDS: &[u8; 20] = b"ACDEFGHIKLMNPQRSTVWY";
+
+/// Amino acid counts, peptide length, summed modification mass (per 100 Da), intercept
+const NUM_FEATURES: usize = AMINO_ACIDS.len() + 3;
+
+fn encode(sequence: &[u8], modification_mass: f32) -> [f64; NUM_FEATURES] {
+    let mut x = [0.0; NUM_FEATURES];
+    for residue in sequence {
+        if let Some(k) = AMINO_ACIDS.iter().position(|aa| aa == residue) {
+            x[k] += 1.0;
+        }
+    }
+    x[AMINO_ACIDS.len()] = sequence.len() as f64;
+    x[AMINO_ACIDS.len() + 1] = modification_mass as f64 / 100.0;
+    x[AMINO_ACIDS.len() + 2] = 1.0;
+    x
+}
+
+fn encode_peptide(peptide: &Peptide) -> [f64; NUM_FEATURES] {
+    let modification_mass = peptide.modifications.iter().sum::<f32>()
+        + peptide.nterm.unwrap_or(0.0)
+        + peptide.cterm.unwrap_or(0.0);
+    encode(&peptide.sequence, modification_mass)
+}
+
+fn encode_sequences(
+    sequences: &[String],
+    modifications: Option<&[HashMap<u32, f32>]>,
+) -> PyResult<Vec<[f64; NUM_FEATURES]>> {
+    match modifications {
+        Some(modifications) if modifications.len() != sequences.len() => Err(PyValueError::new_err(format!(
+            "sequences and modifications must have the same length, got {} and {}",
+            sequences.len(),
+            modifications.len()
+        ))),
+        Some(modifications) => Ok(sequences
+            .iter()
+            .zip(modifications)
+            .map(|(sequence, mods)| encode(sequence.as_bytes(), mods.values().sum()))
+            .collect()),
+        None => Ok(sequences.iter().map(|sequence| encode(sequence.as_bytes(), 0.0)).collect()),
+    }
+}
+
+/// Ridge regression, the intercept (last feature) is not penalized
+fn fit_ridge(rows: &[[f64; NUM_FEATURES]], targets: &[f64], ridge: f64) -> Option<Vec<f64>> {
+    let mut a = vec![vec![0.0; NUM_FEATURES]; NUM_FEATURES];
+    let mut b = vec![0.0; NUM_FEATURES];
+    for (x, y) in rows.iter().zip(targets) {
+        for i in 0..NUM_FEATURES {
+            b[i] += x[i] * y;
+            for j in 0..NUM_FEATURES {
+                a[i][j] += x[i] * x[j];
+            }
+        }
+    }
+    for (i, row) in a.iter_mut().enumerate().take(NUM_FEATURES - 1) {
+        row[i] += ridge;
+    }
+    solve_linear(a, b)
+}
+
+fn dot(coefficients: &[f64], x: &[f64; NUM_FEATURES]) -> f64 {
+    coefficients.iter().zip(x.iter()).map(|(c, v)| c * v).sum()
+}
+
+/// Linear retention time model on amino acid composition, length and modification mass. The first
+/// member is fitted on all training peptides, the others on bootstrap resamples; their spread is
+/// the uncertainty of a prediction
+#[pyclass]
+#[derive(Clone)]
+pub struct PyRetentionModel {
+    pub members: Vec<Vec<f64>>,
+}
+
+impl PyRetentionModel {
+    fn fit_rows(
+        rows: Vec<[f64; NUM_FEATURES]>,
+        targets: Vec<f64>,
+        ensemble_size: usize,
+        ridge: f64,
+        seed: u64,
+    ) -> PyResult<Self> {
+        if rows.len() < NUM_FEATURES {
+            return Err(PyValueError::new_err(format!(
+                "retention model requires at least {} training peptides, got {}",
+                NUM_FEATURES,
+                rows.len()
+            )));
+        }
+        let degenerate = || PyValueError::new_err("retention model fit failed, training peptides are degenerate");
+
+        let mut members = vec![fit_ridge(&rows, &targets, ridge).ok_or_else(degenerate)?];
+        let mut state = seed;
+        while members.len() < ensemble_size.max(1) {
+            let (sample_rows, sample_targets): (Vec<_>, Vec<_>) = (0..rows.len())
+                .map(|_| {
+                    let k = (splitmix64(&mut state) % rows.len() as u64) as usize;
+                    (rows[k], targets[k])
+                })
+                .unzip();
+            members.push(fit_ridge(&sample_rows, &sample_targets, ridge).ok_or_else(degenerate)?);
+        }
+        Ok(PyRetentionModel { members })
+    }
+
+    fn predict_row(&self, x: &[f64; NUM_FEATURES]) -> f32 {
+        dot(&self.members[0], x) as f32
+    }
+
+    fn predict_row_with_uncertainty(&self, x: &[f64; NUM_FEATURES]) -> (f32, f32) {
+        let predictions: Vec<f64> = self.members.iter().map(|m| dot(m, x)).collect();
+        let n = predictions.len() as f64;
+        let mean = predictions.iter().sum::<f64>() / n;
+        let std_dev = if predictions.len() > 1 {
+            (predictions.iter().map(|p| (p - mean).powi(2)).sum::<f64>() / (n - 1.0)).sqrt()
+        } else {
+            0.0
+        };
+        (self.predict_row(x), std_dev as f32)
+    }
+
+    fn predict_rows(&self, rows: &[[f64; NUM_FEATURES]], num_threads: usize) -> Vec<f32> {
+        thread_pool(num_threads).install(|| rows.par_iter().map(|x| self.predict_row(x)).collect())
+    }
+
+    fn predict_rows_with_uncertainty(&self, rows: &[[f64; NUM_FEATURES]], num_threads: usize) -> Vec<(f32, f32)> {
+        thread_pool(num_threads).install(|| rows.par_iter().map(|x| self.predict_row_with_uncertainty(x)).collect())
+    }
+}
+
+fn thread_pool(num_threads: usize) -> rayon::ThreadPool {
+    ThreadPoolBuilder::new().num_threads(num_threads).build().unwrap()
+}
+
+#[pymethods]
+impl PyRetentionModel {
+    /// Fit on peptide sequences and their (aligned) retention times, `modifications[i]` maps
+    /// residue positions of `sequences[i]` to their mass shifts
+    #[staticmethod]
+    pub fn fit(
+        sequences: Vec<String>,
+        retention_times: Vec<f32>,
+        modifications: Option<Vec<HashMap<u32, f32>>>,
+        ensemble_size: Option<usize>,
+        ridge: Option<f64>,
+        seed: Option<u64>,
+    ) -> PyResult<Self> {
+        if sequences.len() != retention_times.len() {
+            return Err(PyValueError::new_err(format!(
+                "sequences and retention_times must have the same length, got {} and {}",
+                sequences.len(),
+                retention_times.len()
+            )));
+        }
+        let rows = encode_sequences(&sequences, modifications.as_deref())?;
+        let targets = retention_times.iter().map(|rt| *rt as f64).collect();
+        Self::fit_rows(rows, targets, ensemble_size.unwrap_or(10), ridge.unwrap_or(1e-3), seed.unwrap_or(42))
+    }
+
+    /// Fit on the aligned retention times of target PSMs with `spectrum_q <= q_value_cutoff`
+    #[staticmethod]
+    pub fn fit_from_psms(
+        psms: Vec<PyFeature>,
+        db: &PyIndexedDatabase,
+        q_value_cutoff: Option<f32>,
+        ensemble_size: Option<usize>,
+        ridge: Option<f64>,
+        seed: Option<u64>,
+    ) -> PyResult<Self> {
+        check_peptide_indices(&db.inner, &psms)?;
+        let q_value_cutoff = q_value_cutoff.unwrap_or(0.01);
+        let (rows, targets) = psms
+            .iter()
+            .filter(|psm| psm.inner.label == 1 && psm.inner.spectrum_q <= q_value_cutoff)
+            .map(|psm| (encode_peptide(&db.inner[psm.inner.peptide_idx]), psm.inner.aligned_rt as f64))
+            .unzip();
+        Self::fit_rows(rows, targets, ensemble_size.unwrap_or(10), ridge.unwrap_or(1e-3), seed.unwrap_or(42))
+    }
+
+    #[getter]
+    pub fn ensemble_size(&self) -> usize {
+        self.members.len()
+    }
+
+    #[getter]
+    pub fn coefficients(&self) -> Vec<f64> {
+        self.members[0].clone()
+    }
+
+    pub fn predict(&self, sequence: &str, modifications: Option<HashMap<u32, f32>>) -> f32 {
+        let modification_mass = modifications.map(|mods| mods.values().sum()).unwrap_or(0.0);
+        self.predict_row(&encode(sequence.as_bytes(), modification_mass))
+    }
+
+    pub fn predict_batch(
+        &self,
+        py: Python,
+        sequences: Vec<String>,
+        modifications: Option<Vec<HashMap<u32, f32>>>,
+        num_threads: Option<usize>,
+    ) -> PyResult<Vec<f32>> {
+        let rows = encode_sequences(&sequences, modifications.as_deref())?;
+        Ok(py.allow_threads(|| self.predict_rows(&rows, num_threads.unwrap_or(4))))
+    }
+
+    /// (predicted retention time, standard deviation over the ensemble members) of each sequence
+    pub fn predict_with_uncertainty(
+        &self,
+        py: Python,
+        sequences: Vec<String>,
+        modifications: Option<Vec<HashMap<u32, f32>>>,
+        num_threads: Option<usize>,
+    ) -> PyResult<Vec<(f32, f32)>> {
+        let rows = encode_sequences(&sequences, modifications.as_deref())?;
+        Ok(py.allow_threads(|| self.predict_rows_with_uncertainty(&rows, num_threads.unwrap_or(4))))
+    }
+
+    /// Set `predicted_rt` and `delta_rt_model` (absolute difference to `aligned_rt`) of all PSMs
+    pub fn predict_from_psms(
+        &self,
+        py: Python,
+        psms: Vec<PyFeature>,
+        db: &PyIndexedDatabase,
+        num_threads: Option<usize>,
+    ) -> PyResult<Vec<PyFeature>> {
+        check_peptide_indices(&db.inner, &psms)?;
+        let mut psms = psms;
+        let pool = thread_pool(num_threads.unwrap_or(4));
+        py.allow_threads(|| {
+            pool.install(|| {
+                psms.par_iter_mut().for_each(|psm| {
+                    let predicted = self.predict_row(&encode_peptide(&db.inner[psm.inner.peptide_idx]));
+                    psm.inner.predicted_rt = predicted;
+                    psm.inner.delta_rt_model = (psm.inner.aligned_rt - predicted).abs();
+                })
+            })
+        });
+        Ok(psms)
+    }
+}
+
+/// Accuracy of a retention time model on held-out PSMs, errors in minutes
+#[pyclass]
+#[derive(Clone, Debug)]
+pub struct PyRtModelMetrics {
+    #[pyo3(get)]
+    pub mae_min: f32,
+    #[pyo3(get)]
+    pub median_absolute_error_min: f32,
+    #[pyo3(get)]
+    pub r_squared: f32,
+    #[pyo3(get)]
+    pub pearson_r: f32,
+    #[pyo3(get)]
+    pub n_psms: u32,
+}
+
+/// Predicted minus aligned retention time
+fn residual(psm: &PyFeature) -> f64 {
+    psm.inner.predicted_rt as f64 - psm.inner.aligned_rt as f64
+}
+
+/// Evaluate a retention time model on the aligned retention times of target PSMs with
+/// `spectrum_q <= fdr_threshold`, ideally PSMs it was not fitted on
+#[pyfunction]
+pub fn evaluate_rt_model(
+    model: &PyRetentionModel,
+    psms: Vec<PyFeature>,
+    db: &PyIndexedDatabase,
+    fdr_threshold: f32,
+) -> PyResult<PyRtModelMetrics> {
+    check_peptide_indices(&db.inner, &psms)?;
+    let (predicted, observed): (Vec<f64>, Vec<f64>) = psms
+        .iter()
+        .filter(|psm| psm.inner.label == 1 && psm.inner.spectrum_q <= fdr_threshold)
+        .map(|psm| {
+            let x = encode_peptide(&db.inner[psm.inner.peptide_idx]);
+            (model.predict_row(&x) as f64, psm.inner.aligned_rt as f64)
+        })
+        .unzip();
+    if predicted.is_empty() {
+        return Err(PyValueError::new_err(format!(
+            "no target PSMs with spectrum_q <= {}",
+            fdr_threshold
+        )));
+    }
+
+    let n = predicted.len() as f64;
+    let errors: Vec<f64> = predicted.iter().zip(&observed).map(|(p, o)| (p - o).abs()).collect();
+    let mean_observed = observed.iter().sum::<f64>() / n;
+    let ss_res: f64 = predicted.iter().zip(&observed).map(|(p, o)| (o - p).powi(2)).sum();
+    let ss_tot: f64 = observed.iter().map(|o| (o - mean_observed).powi(2)).sum();
+
+    Ok(PyRtModelMetrics {
+        mae_min: (errors.iter().sum::<f64>() / n) as f32,
+        median_absolute_error_min: median(errors.iter().copied()).unwrap_or(0.0) as f32,
+        r_squared: match ss_tot > 0.0 {
+            true => (1.0 - ss_res / ss_tot) as f32,
+            false => 0.0,
+        },
+        pearson_r: pearson(&predicted, &observed),
+        n_psms: predicted.len() as u32,
+    })
+}
+
+/// Predicted minus aligned retention time of each PSM, requires `predicted_rt` as set by
+/// `PyRetentionModel.predict_from_psms`
+#[pyfunction]
+pub fn rt_prediction_residuals(psms: Vec<PyFeature>) -> Vec<f32> {
+    psms.iter().map(|psm| residual(psm) as f32).collect()
+}
+
+/// Mean absolute retention time prediction error per peptide length
+#[pyfunction]
+pub fn rt_prediction_by_sequence_length(psms: Vec<PyFeature>) -> HashMap<usize, f32> {
+    let mut groups: HashMap<usize, (f64, usize)> = HashMap::new();
+    for psm in &psms {
+        let entry = groups.entry(psm.inner.peptide_len).or_default();
+        entry.0 += residual(psm).abs();
+        entry.1 += 1;
+    }
+    groups
+        .into_iter()
+        .map(|(len, (sum, count))| (len, (sum / count as f64) as f32))
+        .collect()
+}
+
+/// Flag PSMs whose retention time residual deviates from the median residual by more than
+/// `sigma_threshold` robust standard deviations (1.4826 times the median absolute deviation)
+#[pyfunction]
+pub fn detect_rt_outliers(psms: Vec<PyFeature>, sigma_threshold: f32) -> Vec<bool> {
+    let residuals: Vec<f64> = psms.iter().map(residual).collect();
+    let center = median(residuals.iter().copied()).unwrap_or(0.0);
+    let sigma = 1.4826 * median(residuals.iter().map(|r| (r - center).abs())).unwrap_or(0.0);
+    residuals
+        .iter()
+        .map(|r| sigma > 0.0 && (r - center).abs() > sigma_threshold as f64 * sigma)
+        .collect()
+}
+
+#[pymodule]
+pub fn retention_model(_py: Python, m: &PyModule) -> PyResult<()> {
+    m.add_class::<PyRetentionModel>()?;
+    m.add_class::<PyRtModelMetrics>()?;
+    m.add_function(wrap_pyfunction!(evaluate_rt_model, m)?)?;
+    m.add_function(wrap_pyfunction!(rt_prediction_residuals, m)?)?;
+    m.add_function(wrap_pyfunction!(rt_prediction_by_sequence_length, m)?)?;
+    m.add_function(wrap_pyfunction!(detect_rt_outliers, m)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sequences of 8 to 15 residues cycling through the amino acids, every fourth phosphorylated
+    /// at its first residue
+    fn training_peptide(i: usize) -> (String, HashMap<u32, f32>) {
+        let sequence = (0..8 + i % 8)
+            .map(|j| AMINO_ACIDS[(i * 7 + j * j * 3 + j) % 20] as char)
+            .collect();
+        let modifications = match i % 4 {
+            0 => HashMap::from([(0, 79.966)]),
+            _ => HashMap::new(),
+        };
+        (sequence, modifications)
+    }
+
+    /// Retention time linear in the residue index and the modification mass
+    fn true_rt(sequence: &str, modifications: &HashMap<u32, f32>) -> f32 {
+        let residues: f32 = sequence
+            .bytes()
+            .map(|r| 0.5 * AMINO_ACIDS.iter().position(|aa| *aa == r).unwrap() as f32)
+            .sum();
+        10.0 + residues + 0.05 * modifications.values().sum::<f32>()
+    }
+
+    #[test]
+    fn batch_predictions_match_single_predictions_of_a_recovered_linear_model() {
+        let (sequences, modifications): (Vec<String>, Vec<HashMap<u32, f32>>) = (0..60).map(training_peptide).unzip();
+        let retention_times = sequences.iter().zip(&modifications).map(|(s, m)| true_rt(s, m)).collect();
+        let model =
+            PyRetentionModel::fit(sequences, retention_times, Some(modifications), Some(5), None, None).unwrap();
+        assert_eq!(model.ensemble_size(), 5);
+
+        let (held_out, held_out_modifications): (Vec<String>, Vec<HashMap<u32, f32>>) =
+            (60..80).map(training_peptide).unzip();
+        let rows = encode_sequences(&held_out, Some(&held_out_modifications)).unwrap();
+        let batch = model.predict_rows(&rows, 4);
+        let with_uncertainty = model.predict_rows_with_uncertainty(&rows, 2);
+        assert_eq!(batch.len(), 20);
+        for (i, sequence) in held_out.iter().enumerate() {
+            let modifications = &held_out_modifications[i];
+            assert_eq!(batch[i], model.predict(sequence, Some(modifications.clone())));
+            assert!((batch[i] - true_rt(sequence, modifications)).abs() < 0.1, "{}: {}", sequence, batch[i]);
+            // noise-free training data leaves little spread between the bootstrap members
+            assert_eq!(with_uncertainty[i].0, batch[i]);
+            assert!(with_uncertainty[i].1 < 0.25);
+        }
+
+        assert!(encode_sequences(&held_out, Some(&held_out_modifications[..5])).is_err());
+        let few = vec!["PEPTIDEK".to_string(); 5];
+        assert!(PyRetentionModel::fit(few, vec![1.0; 5], None, None, None, None).is_err());
+    }
+
+    fn rt_psm(peptide_len: usize, predicted_rt: f32, aligned_rt: f32) -> PyFeature {
+        PyFeature::from(sage_core::scoring::Feature {
+            peptide_len,
+            predicted_rt,
+            aligned_rt,
+            ..crate::py_io::default_feature()
+        })
+    }
+
+    #[test]
+    fn model_is_evaluated_on_confident_targets_only() {
+        use crate::py_scoring::tests::{search_database, search_peptide};
+        use sage_core::database::PeptideIx;
+
+        let (sequences, modifications): (Vec<String>, Vec<HashMap<u32, f32>>) = (0..60).map(training_peptide).unzip();
+        let retention_times = sequences.iter().zip(&modifications).map(|(s, m)| true_rt(s, m)).collect();
+        let model = PyRetentionModel::fit(sequences, retention_times, Some(modifications), None, None, None).unwrap();
+
+        let held_out: Vec<String> = (60..80).filter(|i| i % 4 != 0).map(|i| training_peptide(i).0).collect();
+        let db = search_database(held_out.iter().map(|s| search_peptide(s, false)).collect());
+        let mut psms: Vec<PyFeature> = (0..db.inner.peptides.len())
+            .map(|idx| {
+                let sequence = std::str::from_utf8(&db.inner.peptides[idx].sequence).unwrap().to_string();
+                let mut psm = rt_psm(sequence.len(), 0.0, true_rt(&sequence, &HashMap::new()));
+                psm.inner.peptide_idx = PeptideIx(idx as u32);
+                psm.inner.spectrum_q = 0.001;
+                psm
+            })
+            .collect();
+        // a decoy and an unconfident PSM far off the prediction are left out
+        psms[0].inner.label = -1;
+        psms[0].inner.aligned_rt += 100.0;
+        psms[1].inner.spectrum_q = 0.5;
+        psms[1].inner.aligned_rt += 100.0;
+
+        let metrics = evaluate_rt_model(&model, psms.clone(), &db, 0.01).unwrap();
+        assert_eq!(metrics.n_psms as usize, held_out.len() - 2);
+        assert!(metrics.mae_min < 0.1 && metrics.median_absolute_error_min < 0.1);
+        assert!(metrics.r_squared > 0.99 && metrics.pearson_r > 0.99);
+
+        assert!(evaluate_rt_model(&model, psms, &db, 0.0001).is_err());
+    }
+
+    #[test]
+    fn residuals_are_summarised_by_length_and_screened_for_outliers() {
+        let psms = vec![rt_psm(8, 11.0, 10.0), rt_psm(8, 7.0, 10.0), rt_psm(10, 10.5, 10.0)];
+        assert_eq!(rt_prediction_residuals(psms.clone()), vec![1.0, -3.0, 0.5]);
+        assert_eq!(rt_prediction_by_sequence_length(psms), HashMap::from([(8, 2.0), (10, 0.5)]));
+
+        // one residual far outside the spread of the others
+        let residuals = [0.1, -0.1, 0.2, -0.2, 0.0, 0.1, -0.1, 0.0, 0.05, 5.0];
+        let psms: Vec<PyFeature> = residuals.iter().map(|r| rt_psm(8, 10.0 + r, 10.0)).collect();
+        let outliers = detect_rt_outliers(psms.clone(), 3.0);
+        assert_eq!(outliers.iter().filter(|o| **o).count(), 1);
+        assert!(outliers[9]);
+
+        // without any spread nothing is flagged
+        let constant = vec![rt_psm(8, 11.0, 10.0); 5];
+        assert!(detect_rt_outliers(constant, 3.0).iter().all(|o| !o));
+    }
+}
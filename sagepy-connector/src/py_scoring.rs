@@ -1,17 +1,47 @@
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
 use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyList};
 use rayon::prelude::*;
 use rayon::ThreadPoolBuilder;
 
-use crate::py_database::{PyIndexedDatabase, PyPeptideIx};
+use crate::py_database::{ParameterSettings, PyIndexedDatabase, PyParameters, PyPeptideIx};
+use crate::py_database::{DerivedDatabase, DerivedKey, ImsBands};
+use crate::py_fdr::{competition_q_values, competition_winners};
+use sage_core::database::{IndexedDatabase, PeptideIx};
+use sage_core::peptide::Peptide;
+use crate::py_intensity::{pearson, spectral_angle};
 use crate::py_mass::PyTolerance;
-use crate::py_spectrum::PyProcessedSpectrum;
+use crate::py_spectrum::{PyProcessedSpectrum, PyRawSpectrum};
+use crate::py_utility::{median, unimod_sequence};
+use sage_core::spectrum::{Peak, ProcessedSpectrum, RawSpectrum};
+use std::borrow::Cow;
+use sage_core::mass::{monoisotopic, Tolerance, NEUTRON, PROTON};
 use sage_core::scoring::{Feature, Scorer, Fragments};
 use crate::py_ion_series::PyKind;
+use sage_core::ion_series::{IonSeries, Kind};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::Cursor;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use arrow2::array::{Array, BooleanArray, PrimitiveArray, Utf8Array};
+use arrow2::chunk::Chunk;
+use arrow2::datatypes::{DataType, Field, Schema};
+use arrow2::io::ipc::read::{read_file_metadata, FileReader};
+use arrow2::io::ipc::write::{FileWriter, WriteOptions};
+use arrow2::io::parquet::read as parquet_read;
+use arrow2::io::parquet::write as parquet_write;
+use arrow2::types::NativeType;
 
 #[pyclass]
 #[derive(Clone)]
 pub struct PyFragments {
     pub inner: Fragments,
+    pub neutral_losses: Vec<f32>,
 }
 
 #[pymethods]
@@ -24,8 +54,11 @@ impl PyFragments {
         intensities: Vec<f32>,
         mz_calculated: Vec<f32>,
         mz_experimental: Vec<f32>,
+        neutral_losses: Option<Vec<f32>>,
     ) -> Self {
+        let neutral_losses = neutral_losses.unwrap_or_else(|| vec![0.0; charges.len()]);
         PyFragments {
+            neutral_losses,
             inner: Fragments {
                 charges,
                 kinds: kinds.into_iter().map(|k| k.inner).collect(),
@@ -66,12 +99,228 @@ impl PyFragments {
     pub fn mz_experimental(&self) -> Vec<f32> {
         self.inner.mz_experimental.clone()
     }
+
+    /// Neutral loss mass (Da) of each matched ion, 0 for regular fragment ions
+    #[getter]
+    pub fn neutral_losses(&self) -> Vec<f32> {
+        self.neutral_losses.clone()
+    }
 }
 
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct PyFeature {
     pub inner: Feature,
+    pub isotope_annotation_score: f32,
+    pub xcorr: Option<f32>,
+    pub delta_xcorr: Option<f32>,
+    pub localization_scores: Option<Vec<f32>>,
+    pub best_localization_site: Option<usize>,
+    pub neutral_losses: Vec<f32>,
+    pub neutral_loss_intensity_pct: f32,
+    pub spectral_entropy: f32,
+    pub delta_spectral_entropy: f32,
+    pub ms1_isotope_score: f32,
+    pub ms1_intensity_ratio: f32,
+    pub has_oxonium_evidence: bool,
+    pub oxonium_score: f32,
+    pub protein_q_razor: f32,
+    pub silac_pair_idx: Option<PeptideIx>,
+    pub unexplained_intensity_pct: f32,
+    pub coverage_stats: Option<PyFragmentCoverageStats>,
+    pub collision_energy_calibrated: Option<f32>,
+    pub precursor_purity: Option<f32>,
+    pub tag_match_score: Option<f32>,
+    pub intensity_similarity: Option<f32>,
+    pub chimera_score: Option<f64>,
+    pub peptide_sequence: Option<String>,
+    pub re_score: Option<f64>,
+    pub ims: Option<f32>,
+    pub inverse_ion_mobility_predicted: Option<f32>,
+    pub delta_ims_model: Option<f32>,
+    pub intensity_weighted_ppm: f32,
+    pub max_fragment_ppm: f32,
+}
+
+/// Peptide index of PSMs imported from other tools, which do not refer to a sagepy database
+/// and carry their peptide as `peptide_sequence` instead
+pub(crate) const UNKNOWN_PEPTIDE: PeptideIx = PeptideIx(u32::MAX);
+
+/// Raise if a PSM does not refer to a peptide of `db`, e.g. because it was imported from
+/// another tool, instead of panicking on the out of bounds index
+pub(crate) fn check_peptide_indices<'a>(
+    db: &IndexedDatabase,
+    psms: impl IntoIterator<Item = &'a PyFeature>,
+) -> PyResult<()> {
+    match psms
+        .into_iter()
+        .find(|psm| psm.inner.peptide_idx.0 as usize >= db.peptides.len())
+    {
+        Some(psm) => Err(PyValueError::new_err(format!(
+            "PSM {} does not refer to a peptide of the database ({}), imported PSMs carry their peptide as peptide_sequence only",
+            psm.inner.spec_id,
+            psm.peptide_sequence.as_deref().unwrap_or("unknown peptide")
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Percentage of the total ion current explained by matched fragments, neutral loss ions included
+pub(crate) fn explained_intensity_pct(psm: &PyFeature) -> f32 {
+    (psm.inner.matched_intensity_pct + psm.neutral_loss_intensity_pct).min(100.0)
+}
+
+/// Longest run of consecutive values in a sorted, deduplicated list of fragment ordinals
+fn longest_series(ordinals: &[i32]) -> u32 {
+    let mut longest = 0;
+    let mut current = 0;
+    for (i, ordinal) in ordinals.iter().enumerate() {
+        current = match i > 0 && ordinals[i - 1] + 1 == *ordinal {
+            true => current + 1,
+            false => 1,
+        };
+        longest = longest.max(current);
+    }
+    longest
+}
+
+/// Matched fraction of the b and y ion series of a PSM. Ions are counted once per ordinal,
+/// regardless of charge or neutral loss
+#[pyclass]
+#[derive(Clone, Debug)]
+pub struct PyFragmentCoverageStats {
+    pub b_coverage: f32,
+    pub y_coverage: f32,
+    pub b_total: u32,
+    pub y_total: u32,
+    pub b_matched: u32,
+    pub y_matched: u32,
+    pub longest_b_series: u32,
+    pub longest_y_series: u32,
+}
+
+impl PyFragmentCoverageStats {
+    /// Coverage of a scored feature. Matched ions are only known if matches were annotated, without
+    /// fragments nothing counts as matched and the series lengths are taken from the feature
+    pub(crate) fn from_feature(feature: &Feature) -> Self {
+        let ordinals = |kind: Kind| -> Vec<i32> {
+            let mut ordinals: Vec<i32> = feature
+                .fragments
+                .iter()
+                .flat_map(|f| f.kinds.iter().zip(f.fragment_ordinals.iter()))
+                .filter(|(k, _)| **k == kind)
+                .map(|(_, ordinal)| *ordinal)
+                .collect();
+            ordinals.sort_unstable();
+            ordinals.dedup();
+            ordinals
+        };
+        let (b, y) = (ordinals(Kind::B), ordinals(Kind::Y));
+        let total = feature.peptide_len.saturating_sub(1) as u32;
+        let coverage = |matched: usize| match total {
+            0 => 0.0,
+            _ => matched as f32 / total as f32,
+        };
+
+        let (longest_b_series, longest_y_series) = match feature.fragments {
+            Some(_) => (longest_series(&b), longest_series(&y)),
+            None => (feature.longest_b, feature.longest_y),
+        };
+        PyFragmentCoverageStats {
+            b_coverage: coverage(b.len()),
+            y_coverage: coverage(y.len()),
+            b_total: total,
+            y_total: total,
+            b_matched: b.len() as u32,
+            y_matched: y.len() as u32,
+            longest_b_series,
+            longest_y_series,
+        }
+    }
+}
+
+#[pymethods]
+impl PyFragmentCoverageStats {
+    /// Fraction (0-1) of the b ions matched
+    #[getter]
+    pub fn b_coverage(&self) -> f32 {
+        self.b_coverage
+    }
+
+    /// Fraction (0-1) of the y ions matched
+    #[getter]
+    pub fn y_coverage(&self) -> f32 {
+        self.y_coverage
+    }
+
+    #[getter]
+    pub fn b_total(&self) -> u32 {
+        self.b_total
+    }
+
+    #[getter]
+    pub fn y_total(&self) -> u32 {
+        self.y_total
+    }
+
+    #[getter]
+    pub fn b_matched(&self) -> u32 {
+        self.b_matched
+    }
+
+    #[getter]
+    pub fn y_matched(&self) -> u32 {
+        self.y_matched
+    }
+
+    #[getter]
+    pub fn longest_b_series(&self) -> u32 {
+        self.longest_b_series
+    }
+
+    #[getter]
+    pub fn longest_y_series(&self) -> u32 {
+        self.longest_y_series
+    }
+}
+
+impl From<Feature> for PyFeature {
+    fn from(inner: Feature) -> Self {
+        let unexplained_intensity_pct = 100.0 - inner.matched_intensity_pct.min(100.0);
+        let (intensity_weighted_ppm, max_fragment_ppm) = fragment_ppm_summary(inner.fragments.as_ref());
+        PyFeature {
+            inner,
+            isotope_annotation_score: f32::NAN,
+            xcorr: None,
+            delta_xcorr: None,
+            localization_scores: None,
+            best_localization_site: None,
+            neutral_losses: Vec::new(),
+            neutral_loss_intensity_pct: 0.0,
+            spectral_entropy: 0.0,
+            delta_spectral_entropy: 0.0,
+            ms1_isotope_score: 0.0,
+            ms1_intensity_ratio: 0.0,
+            has_oxonium_evidence: false,
+            oxonium_score: 0.0,
+            protein_q_razor: 1.0,
+            silac_pair_idx: None,
+            unexplained_intensity_pct,
+            coverage_stats: None,
+            collision_energy_calibrated: None,
+            precursor_purity: None,
+            tag_match_score: None,
+            intensity_similarity: None,
+            chimera_score: None,
+            peptide_sequence: None,
+            re_score: None,
+            ims: None,
+            inverse_ion_mobility_predicted: None,
+            delta_ims_model: None,
+            intensity_weighted_ppm,
+            max_fragment_ppm,
+        }
+    }
 }
 
 #[pymethods]
@@ -112,8 +361,41 @@ impl PyFeature {
         peptide_q: f32,
         protein_q: f32,
         ms2_intensity: f32,
-        fragments: Option<PyFragments>
+        fragments: Option<PyFragments>,
+        isotope_annotation_score: Option<f32>,
+        xcorr: Option<f32>,
+        delta_xcorr: Option<f32>,
+        localization_scores: Option<Vec<f32>>,
+        best_localization_site: Option<usize>,
+        neutral_loss_intensity_pct: Option<f32>,
+        spectral_entropy: Option<f32>,
+        delta_spectral_entropy: Option<f32>,
+        ms1_isotope_score: Option<f32>,
+        ms1_intensity_ratio: Option<f32>,
+        has_oxonium_evidence: Option<bool>,
+        oxonium_score: Option<f32>,
+        protein_q_razor: Option<f32>,
+        silac_pair_idx: Option<PyPeptideIx>,
+        unexplained_intensity_pct: Option<f32>,
+        coverage_stats: Option<PyFragmentCoverageStats>,
+        collision_energy_calibrated: Option<f32>,
+        precursor_purity: Option<f32>,
+        tag_match_score: Option<f32>,
+        intensity_similarity: Option<f32>,
+        chimera_score: Option<f64>,
+        peptide_sequence: Option<String>,
+        re_score: Option<f64>,
+        ims: Option<f32>,
+        inverse_ion_mobility_predicted: Option<f32>,
+        delta_ims_model: Option<f32>,
+        intensity_weighted_ppm: Option<f32>,
+        max_fragment_ppm: Option<f32>,
     ) -> Self {
+        let neutral_losses = fragments
+            .as_ref()
+            .map(|f| f.neutral_losses.clone())
+            .unwrap_or_default();
+        let fragment_ppm = fragment_ppm_summary(fragments.as_ref().map(|f| &f.inner));
         PyFeature {
             inner: Feature {
                 peptide_idx: peptide_idx.inner,
@@ -152,6 +434,37 @@ impl PyFeature {
                 ms2_intensity,
                 fragments: fragments.map(|f| f.inner),
             },
+            isotope_annotation_score: isotope_annotation_score.unwrap_or(f32::NAN),
+            xcorr,
+            delta_xcorr,
+            localization_scores,
+            best_localization_site,
+            neutral_losses,
+            neutral_loss_intensity_pct: neutral_loss_intensity_pct.unwrap_or_default(),
+            spectral_entropy: spectral_entropy.unwrap_or_default(),
+            delta_spectral_entropy: delta_spectral_entropy.unwrap_or_default(),
+            ms1_isotope_score: ms1_isotope_score.unwrap_or_default(),
+            ms1_intensity_ratio: ms1_intensity_ratio.unwrap_or_default(),
+            has_oxonium_evidence: has_oxonium_evidence.unwrap_or_default(),
+            oxonium_score: oxonium_score.unwrap_or_default(),
+            protein_q_razor: protein_q_razor.unwrap_or(1.0),
+            silac_pair_idx: silac_pair_idx.map(|p| p.inner),
+            unexplained_intensity_pct: unexplained_intensity_pct.unwrap_or_else(|| {
+                100.0 - (matched_intensity_pct + neutral_loss_intensity_pct.unwrap_or_default()).min(100.0)
+            }),
+            coverage_stats,
+            collision_energy_calibrated,
+            precursor_purity,
+            tag_match_score,
+            intensity_similarity,
+            chimera_score,
+            peptide_sequence,
+            re_score,
+            ims,
+            inverse_ion_mobility_predicted,
+            delta_ims_model,
+            intensity_weighted_ppm: intensity_weighted_ppm.unwrap_or(fragment_ppm.0),
+            max_fragment_ppm: max_fragment_ppm.unwrap_or(fragment_ppm.1),
         }
     }
 
@@ -232,6 +545,12 @@ impl PyFeature {
         self.inner.delta_mass
     }
 
+    /// Observed - calculated precursor mass in Da, the mass shift of an open search match
+    #[getter]
+    pub fn delta_mass_open(&self) -> f32 {
+        self.inner.expmass - self.inner.calcmass
+    }
+
     #[getter]
     pub fn isotope_error(&self) -> f32 {
         self.inner.isotope_error
@@ -329,263 +648,5914 @@ impl PyFeature {
 
     #[getter]
     pub fn fragments(&self) -> Option<PyFragments> {
-        self.inner.fragments.as_ref().map(|f| PyFragments { inner: f.clone() })
+        self.inner.fragments.as_ref().map(|f| {
+            let mut neutral_losses = self.neutral_losses.clone();
+            neutral_losses.resize(f.charges.len(), 0.0);
+            PyFragments {
+                inner: f.clone(),
+                neutral_losses,
+            }
+        })
     }
-}
 
-#[pyclass]
-pub struct PyScorer {
-    pub precursor_tolerance: PyTolerance,
-    pub fragment_tolerance: PyTolerance,
-    pub min_matched_peaks: u16,
-    pub min_isotope_err: i8,
-    pub max_isotope_err: i8,
-    pub min_precursor_charge: u8,
-    pub max_precursor_charge: u8,
-    pub max_fragment_charge: Option<u8>,
-    pub min_fragment_mass: f32,
-    pub max_fragment_mass: f32,
-    pub chimera: bool,
-    pub report_psms: usize,
-    pub wide_window: bool,
-    pub annotate_matches: bool,
-}
+    /// Mean isotope annotation score of the matched fragments. Scoring works on deisotoped peaks,
+    /// so the score is NaN until set from the raw spectrum by `annotate_isotope_annotation_scores`
+    #[getter]
+    pub fn isotope_annotation_score(&self) -> f32 {
+        self.isotope_annotation_score
+    }
 
-#[pymethods]
-impl PyScorer {
-    #[new]
-    pub fn new(
-        precursor_tolerance: PyTolerance,
-        fragment_tolerance: PyTolerance,
-        min_matched_peaks: u16,
-        min_isotope_err: i8,
-        max_isotope_err: i8,
-        min_precursor_charge: u8,
-        max_precursor_charge: u8,
-        min_fragment_mass: f32,
-        max_fragment_mass: f32,
-        chimera: bool,
-        report_psms: usize,
-        wide_window: bool,
-        annotate_matches: bool,
-        max_fragment_charge: Option<u8>,
-    ) -> Self {
-        PyScorer {
-            precursor_tolerance,
-            fragment_tolerance,
-            min_matched_peaks,
-            min_isotope_err,
-            max_isotope_err,
-            min_precursor_charge,
-            max_precursor_charge,
-            max_fragment_charge,
-            min_fragment_mass,
-            max_fragment_mass,
-            chimera,
-            report_psms,
-            wide_window,
-            annotate_matches,
-        }
+    /// Cross-correlation score, set if the PSM was scored with the xcorr score type
+    #[getter]
+    pub fn xcorr(&self) -> Option<f32> {
+        self.xcorr
     }
 
-    pub fn score(&self, db: &PyIndexedDatabase, spectrum: &PyProcessedSpectrum) -> Vec<PyFeature> {
-        let scorer = Scorer {
-            db: &db.inner,
-            precursor_tol: self.precursor_tolerance.inner.clone(),
-            fragment_tol: self.fragment_tolerance.inner.clone(),
-            min_matched_peaks: self.min_matched_peaks,
-            min_isotope_err: self.min_isotope_err,
-            max_isotope_err: self.max_isotope_err,
-            min_precursor_charge: self.min_precursor_charge,
-            max_precursor_charge: self.max_precursor_charge,
-            max_fragment_charge: self.max_fragment_charge,
-            min_fragment_mass: self.min_fragment_mass,
-            max_fragment_mass: self.max_fragment_mass,
-            chimera: self.chimera,
-            report_psms: self.report_psms,
-            wide_window: self.wide_window,
-            annotate_matches: self.annotate_matches,
-        };
-        let features = scorer.score(&spectrum.inner);
-        features
-            .into_iter()
-            .map(|f| PyFeature { inner: f })
-            .collect()
+    /// Normalized difference to the xcorr of the next best (rank 1) or best (other ranks) PSM
+    #[getter]
+    pub fn delta_xcorr(&self) -> Option<f32> {
+        self.delta_xcorr
     }
 
-    pub fn score_collection(
-        &self,
-        db: &PyIndexedDatabase,
-        spectra: Vec<PyProcessedSpectrum>,
-        num_threads: usize,
-    ) -> Vec<Vec<PyFeature>> {
-        let scorer = Scorer {
-            db: &db.inner,
-            precursor_tol: self.precursor_tolerance.inner.clone(),
-            fragment_tol: self.fragment_tolerance.inner.clone(),
-            min_matched_peaks: self.min_matched_peaks,
-            min_isotope_err: self.min_isotope_err,
-            max_isotope_err: self.max_isotope_err,
-            min_precursor_charge: self.min_precursor_charge,
-            max_precursor_charge: self.max_precursor_charge,
-            max_fragment_charge: self.max_fragment_charge,
-            min_fragment_mass: self.min_fragment_mass,
-            max_fragment_mass: self.max_fragment_mass,
-            chimera: self.chimera,
-            report_psms: self.report_psms,
-            wide_window: self.wide_window,
-            annotate_matches: self.annotate_matches,
-        };
-        // Configure the global thread pool to the desired number of threads
-        let pool = ThreadPoolBuilder::new()
-            .num_threads(num_threads)
-            .build()
-            .unwrap();
+    /// Site localization score per residue (0 for ineligible residues), set by `localize_modification`
+    #[getter]
+    pub fn localization_scores(&self) -> Option<Vec<f32>> {
+        self.localization_scores.clone()
+    }
 
-        let result = pool.install(|| {
-            spectra
-                .par_iter()
-                .map(|spectrum| {
-                    let features = scorer.score(&spectrum.inner);
-                    features
-                        .into_iter()
-                        .map(|f| PyFeature { inner: f })
-                        .collect()
-                })
-                .collect()
-        });
+    /// Residue position (0-based) with the highest localization score
+    #[getter]
+    pub fn best_localization_site(&self) -> Option<usize> {
+        self.best_localization_site
+    }
 
-        result
+    /// Percentage of the total ion current explained by neutral loss ions, computed during
+    /// scoring if `match_neutral_losses` is set, otherwise 0
+    #[getter]
+    pub fn neutral_loss_intensity_pct(&self) -> f32 {
+        self.neutral_loss_intensity_pct
     }
 
-    pub fn score_chimera_fast(
-        &self,
-        db: &PyIndexedDatabase,
-        query: &PyProcessedSpectrum,
-    ) -> Vec<PyFeature> {
-        let scorer = Scorer {
-            db: &db.inner,
-            precursor_tol: self.precursor_tolerance.inner.clone(),
-            fragment_tol: self.fragment_tolerance.inner.clone(),
-            min_matched_peaks: self.min_matched_peaks,
-            min_isotope_err: self.min_isotope_err,
-            max_isotope_err: self.max_isotope_err,
-            min_precursor_charge: self.min_precursor_charge,
-            max_precursor_charge: self.max_precursor_charge,
-            max_fragment_charge: self.max_fragment_charge,
-            min_fragment_mass: self.min_fragment_mass,
-            max_fragment_mass: self.max_fragment_mass,
-            chimera: self.chimera,
-            report_psms: self.report_psms,
-            wide_window: self.wide_window,
-            annotate_matches: self.annotate_matches,
-        };
-        let features = scorer.score_chimera_fast(&query.inner);
-        features
-            .into_iter()
-            .map(|f| PyFeature { inner: f })
-            .collect()
+    /// Normalised Shannon entropy of the spectrum's peak intensities, set when scoring with
+    /// a `spectral_entropy` mode other than off
+    #[getter]
+    pub fn spectral_entropy(&self) -> f32 {
+        self.spectral_entropy
     }
 
-    pub fn score_standard(
-        &self,
-        db: &PyIndexedDatabase,
-        query: &PyProcessedSpectrum,
-    ) -> Vec<PyFeature> {
-        let scorer = Scorer {
-            db: &db.inner,
-            precursor_tol: self.precursor_tolerance.inner.clone(),
-            fragment_tol: self.fragment_tolerance.inner.clone(),
-            min_matched_peaks: self.min_matched_peaks,
-            min_isotope_err: self.min_isotope_err,
-            max_isotope_err: self.max_isotope_err,
-            min_precursor_charge: self.min_precursor_charge,
-            max_precursor_charge: self.max_precursor_charge,
-            max_fragment_charge: self.max_fragment_charge,
-            min_fragment_mass: self.min_fragment_mass,
-            max_fragment_mass: self.max_fragment_mass,
-            chimera: self.chimera,
-            report_psms: self.report_psms,
-            wide_window: self.wide_window,
-            annotate_matches: self.annotate_matches,
-        };
-        let features = scorer.score_standard(&query.inner);
-        features
-            .into_iter()
-            .map(|f| PyFeature { inner: f })
-            .collect()
+    /// Normalised entropy of the peaks matched by the peptide's fragments minus that of the
+    /// unmatched peaks
+    #[getter]
+    pub fn delta_spectral_entropy(&self) -> f32 {
+        self.delta_spectral_entropy
     }
 
+    /// Cosine similarity of the observed M, M+1, M+2 precursor peaks to the averagine isotope
+    /// distribution, set by `score_with_ms1_isotope_evidence`
     #[getter]
-    pub fn precursor_tolerance(&self) -> PyTolerance {
-        self.precursor_tolerance.clone()
+    pub fn ms1_isotope_score(&self) -> f32 {
+        self.ms1_isotope_score
     }
 
+    /// Intensity of the monoisotopic precursor peak relative to the summed M to M+4 peaks
     #[getter]
-    pub fn fragment_tolerance(&self) -> PyTolerance {
-        self.fragment_tolerance.clone()
+    pub fn ms1_intensity_ratio(&self) -> f32 {
+        self.ms1_intensity_ratio
     }
 
+    /// Whether enough diagnostic oxonium ions were found in the spectrum (glycopeptide scoring mode)
     #[getter]
-    pub fn min_matched_peaks(&self) -> u16 {
-        self.min_matched_peaks
+    pub fn has_oxonium_evidence(&self) -> bool {
+        self.has_oxonium_evidence
     }
 
     #[getter]
-    pub fn min_isotope_err(&self) -> i8 {
-        self.min_isotope_err
+    pub fn oxonium_score(&self) -> f32 {
+        self.oxonium_score
     }
 
+    /// Protein q-value of the razor protein of the PSM's peptide (see `annotate_protein_q_razor`)
     #[getter]
-    pub fn max_isotope_err(&self) -> i8 {
-        self.max_isotope_err
+    pub fn protein_q_razor(&self) -> f32 {
+        self.protein_q_razor
     }
 
+    /// SILAC partner peptide of the PSM's peptide, if the partner is identified as well (see
+    /// `annotate_silac_pairs`)
     #[getter]
-    pub fn min_precursor_charge(&self) -> u8 {
-        self.min_precursor_charge
+    pub fn silac_pair_idx(&self) -> Option<PyPeptideIx> {
+        self.silac_pair_idx.map(|inner| PyPeptideIx { inner })
     }
 
+    /// Percentage of the total ion current not explained by matched fragments or neutral loss
+    /// ions, 100 - explained intensity
     #[getter]
-    pub fn max_precursor_charge(&self) -> u8 {
-        self.max_precursor_charge
+    pub fn unexplained_intensity_pct(&self) -> f32 {
+        self.unexplained_intensity_pct
     }
 
+    /// Per ion type fragment coverage, set when scoring with `with_coverage_stats`
     #[getter]
-    pub fn max_fragment_charge(&self) -> Option<u8> {
-        self.max_fragment_charge
+    pub fn coverage_stats(&self) -> Option<PyFragmentCoverageStats> {
+        self.coverage_stats.clone()
     }
 
+    /// Calibrated collision energy of intensity prediction, set by
+    /// `apply_collision_energy_calibration`
     #[getter]
-    pub fn min_fragment_mass(&self) -> f32 {
-        self.min_fragment_mass
+    pub fn collision_energy_calibrated(&self) -> Option<f32> {
+        self.collision_energy_calibrated
     }
 
+    /// Fraction of the MS1 isolation window intensity from the precursor, set by
+    /// `tag_precursor_purity`
     #[getter]
-    pub fn max_fragment_mass(&self) -> f32 {
-        self.max_fragment_mass
+    pub fn precursor_purity(&self) -> Option<f32> {
+        self.precursor_purity
     }
 
+    /// Summed score of the de novo sequence tags the peptide contains, set by
+    /// `score_with_sequence_tags`
     #[getter]
-    pub fn chimera(&self) -> bool {
-        self.chimera
+    pub fn tag_match_score(&self) -> Option<f32> {
+        self.tag_match_score
     }
 
+    /// Similarity of observed and predicted fragment intensities, set by
+    /// `rescore_collection_with_predictions`
     #[getter]
-    pub fn report_psms(&self) -> usize {
-        self.report_psms
+    pub fn intensity_similarity(&self) -> Option<f32> {
+        self.intensity_similarity
     }
 
+    /// Combined score of the co-isolated peptide pair the PSM is part of, set by
+    /// `score_chimera_with_mass_pairs`
     #[getter]
-    pub fn wide_window(&self) -> bool {
-        self.wide_window
+    pub fn chimera_score(&self) -> Option<f64> {
+        self.chimera_score
     }
-}
 
-#[pymodule]
-pub fn scoring(_py: Python, m: &PyModule) -> PyResult<()> {
-    m.add_class::<PyFragments>()?;
-    m.add_class::<PyFeature>()?;
-    m.add_class::<PyScorer>()?;
-    Ok(())
+    /// Modified peptide sequence in UNIMOD bracket notation of PSMs imported from other tools
+    /// (DIA-NN, PIN, mzTab, MSP), whose `peptide_idx` does not refer to a sagepy database
+    #[getter]
+    pub fn peptide_sequence(&self) -> Option<String> {
+        self.peptide_sequence.clone()
+    }
+
+    /// Score of a re-scoring step, set by `rescore_with_function` and `semi_supervised_fdr`
+    #[getter]
+    pub fn re_score(&self) -> Option<f64> {
+        self.re_score
+    }
+
+    /// Observed inverse ion mobility (1/K0) of the precursor, taken from `precursor_ims` of the
+    /// scored spectrum
+    #[getter]
+    pub fn ims(&self) -> Option<f32> {
+        self.ims
+    }
+
+    /// Inverse ion mobility predicted by `PyMobilityModel.predict_from_psms`
+    #[getter]
+    pub fn inverse_ion_mobility_predicted(&self) -> Option<f32> {
+        self.inverse_ion_mobility_predicted
+    }
+
+    /// Absolute difference of observed and predicted inverse ion mobility, set by
+    /// `PyMobilityModel.predict_from_psms` for PSMs with an observed ion mobility
+    #[getter]
+    pub fn delta_ims_model(&self) -> Option<f32> {
+        self.delta_ims_model
+    }
+
+    /// All fields keyed by name (the re-scoring feature names where applicable), unset optional
+    /// values are None, inverse of `from_dict`
+    pub fn to_dict(&self, py: Python) -> HashMap<String, PyObject> {
+        let f = &self.inner;
+        let fragments = f.fragments.as_ref().map(|fragments| {
+            let kinds: Vec<String> = fragments.kinds.iter().map(|k| format!("{:?}", k)).collect();
+            HashMap::from([
+                ("charges", fragments.charges.clone().into_py(py)),
+                ("kinds", kinds.into_py(py)),
+                ("fragment_ordinals", fragments.fragment_ordinals.clone().into_py(py)),
+                ("intensities", fragments.intensities.clone().into_py(py)),
+                ("mz_calculated", fragments.mz_calculated.clone().into_py(py)),
+                ("mz_experimental", fragments.mz_experimental.clone().into_py(py)),
+                ("neutral_losses", self.neutral_losses.clone().into_py(py)),
+            ])
+        });
+
+        let entries: Vec<(&str, PyObject)> = vec![
+            ("peptide_idx", f.peptide_idx.0.into_py(py)),
+            ("psm_id", f.psm_id.into_py(py)),
+            ("peptide_len", f.peptide_len.into_py(py)),
+            ("spec_id", f.spec_id.clone().into_py(py)),
+            ("file_id", f.file_id.into_py(py)),
+            ("rank", f.rank.into_py(py)),
+            ("label", f.label.into_py(py)),
+            ("expmass", f.expmass.into_py(py)),
+            ("calcmass", f.calcmass.into_py(py)),
+            ("charge", f.charge.into_py(py)),
+            ("rt", f.rt.into_py(py)),
+            ("aligned_rt", f.aligned_rt.into_py(py)),
+            ("predicted_rt", f.predicted_rt.into_py(py)),
+            ("delta_rt_model", f.delta_rt_model.into_py(py)),
+            ("delta_mass", f.delta_mass.into_py(py)),
+            ("isotope_error", f.isotope_error.into_py(py)),
+            ("average_ppm", f.average_ppm.into_py(py)),
+            ("hyperscore", f.hyperscore.into_py(py)),
+            ("delta_next", f.delta_next.into_py(py)),
+            ("delta_best", f.delta_best.into_py(py)),
+            ("matched_peaks", f.matched_peaks.into_py(py)),
+            ("longest_b", f.longest_b.into_py(py)),
+            ("longest_y", f.longest_y.into_py(py)),
+            ("longest_y_pct", f.longest_y_pct.into_py(py)),
+            ("missed_cleavages", f.missed_cleavages.into_py(py)),
+            ("matched_intensity_pct", f.matched_intensity_pct.into_py(py)),
+            ("scored_candidates", f.scored_candidates.into_py(py)),
+            ("poisson", f.poisson.into_py(py)),
+            ("discriminant_score", f.discriminant_score.into_py(py)),
+            ("posterior_error", f.posterior_error.into_py(py)),
+            ("spectrum_q", f.spectrum_q.into_py(py)),
+            ("peptide_q", f.peptide_q.into_py(py)),
+            ("protein_q", f.protein_q.into_py(py)),
+            ("ms2_intensity", f.ms2_intensity.into_py(py)),
+            ("fragments", fragments.into_py(py)),
+            ("isotope_annotation_score", self.isotope_annotation_score.into_py(py)),
+            ("xcorr", self.xcorr.into_py(py)),
+            ("delta_xcorr", self.delta_xcorr.into_py(py)),
+            ("localization_scores", self.localization_scores.clone().into_py(py)),
+            ("best_localization_site", self.best_localization_site.into_py(py)),
+            ("neutral_loss_intensity_pct", self.neutral_loss_intensity_pct.into_py(py)),
+            ("spectral_entropy", self.spectral_entropy.into_py(py)),
+            ("delta_spectral_entropy", self.delta_spectral_entropy.into_py(py)),
+            ("ms1_isotope_score", self.ms1_isotope_score.into_py(py)),
+            ("ms1_intensity_ratio", self.ms1_intensity_ratio.into_py(py)),
+            ("has_oxonium_evidence", self.has_oxonium_evidence.into_py(py)),
+            ("oxonium_score", self.oxonium_score.into_py(py)),
+            ("protein_q_razor", self.protein_q_razor.into_py(py)),
+            ("silac_pair_idx", self.silac_pair_idx.map(|p| p.0).into_py(py)),
+            ("unexplained_intensity_pct", self.unexplained_intensity_pct.into_py(py)),
+            ("coverage_stats", self.coverage_stats.clone().into_py(py)),
+            ("collision_energy_calibrated", self.collision_energy_calibrated.into_py(py)),
+            ("precursor_purity", self.precursor_purity.into_py(py)),
+            ("tag_match_score", self.tag_match_score.into_py(py)),
+            ("intensity_similarity", self.intensity_similarity.into_py(py)),
+            ("chimera_score", self.chimera_score.into_py(py)),
+            ("peptide_sequence", self.peptide_sequence.clone().into_py(py)),
+            ("re_score", self.re_score.into_py(py)),
+            ("ims", self.ims.into_py(py)),
+            ("inverse_ion_mobility_predicted", self.inverse_ion_mobility_predicted.into_py(py)),
+            ("delta_ims_model", self.delta_ims_model.into_py(py)),
+            ("intensity_weighted_ppm", self.intensity_weighted_ppm.into_py(py)),
+            ("max_fragment_ppm", self.max_fragment_ppm.into_py(py)),
+        ];
+        entries.into_iter().map(|(k, v)| (k.to_string(), v)).collect()
+    }
+
+    /// Rebuild a PSM from a dict created by `to_dict`, the keys of optional values may be missing
+    #[staticmethod]
+    pub fn from_dict(py: Python, d: HashMap<String, PyObject>) -> PyResult<PyFeature> {
+        let value = DictFields { py, d: &d };
+
+        let fragments = match value.optional::<HashMap<String, PyObject>>("fragments")? {
+            Some(fragments) => {
+                let fields = DictFields { py, d: &fragments };
+                let kinds = fields
+                    .required::<Vec<String>>("kinds")?
+                    .into_iter()
+                    .map(PyKind::new)
+                    .collect::<PyResult<Vec<_>>>()?;
+                Some(PyFragments::new(
+                    fields.required("charges")?,
+                    kinds,
+                    fields.required("fragment_ordinals")?,
+                    fields.required("intensities")?,
+                    fields.required("mz_calculated")?,
+                    fields.required("mz_experimental")?,
+                    fields.optional("neutral_losses")?,
+                ))
+            }
+            None => None,
+        };
+
+        Ok(PyFeature::new(
+            PyPeptideIx { inner: PeptideIx(value.required("peptide_idx")?) },
+            value.required("psm_id")?,
+            value.required("peptide_len")?,
+            value.required("spec_id")?,
+            value.required("file_id")?,
+            value.required("rank")?,
+            value.required("label")?,
+            value.required("expmass")?,
+            value.required("calcmass")?,
+            value.required("charge")?,
+            value.required("rt")?,
+            value.required("aligned_rt")?,
+            value.required("predicted_rt")?,
+            value.required("delta_rt_model")?,
+            value.required("delta_mass")?,
+            value.required("isotope_error")?,
+            value.required("average_ppm")?,
+            value.required("hyperscore")?,
+            value.required("delta_next")?,
+            value.required("delta_best")?,
+            value.required("matched_peaks")?,
+            value.required("longest_b")?,
+            value.required("longest_y")?,
+            value.required("longest_y_pct")?,
+            value.required("missed_cleavages")?,
+            value.required("matched_intensity_pct")?,
+            value.required("scored_candidates")?,
+            value.required("poisson")?,
+            value.required("discriminant_score")?,
+            value.required("posterior_error")?,
+            value.required("spectrum_q")?,
+            value.required("peptide_q")?,
+            value.required("protein_q")?,
+            value.required("ms2_intensity")?,
+            fragments,
+            value.optional("isotope_annotation_score")?,
+            value.optional("xcorr")?,
+            value.optional("delta_xcorr")?,
+            value.optional("localization_scores")?,
+            value.optional("best_localization_site")?,
+            value.optional("neutral_loss_intensity_pct")?,
+            value.optional("spectral_entropy")?,
+            value.optional("delta_spectral_entropy")?,
+            value.optional("ms1_isotope_score")?,
+            value.optional("ms1_intensity_ratio")?,
+            value.optional("has_oxonium_evidence")?,
+            value.optional("oxonium_score")?,
+            value.optional("protein_q_razor")?,
+            value
+                .optional::<u32>("silac_pair_idx")?
+                .map(|idx| PyPeptideIx { inner: PeptideIx(idx) }),
+            value.optional("unexplained_intensity_pct")?,
+            value.optional("coverage_stats")?,
+            value.optional("collision_energy_calibrated")?,
+            value.optional("precursor_purity")?,
+            value.optional("tag_match_score")?,
+            value.optional("intensity_similarity")?,
+            value.optional("chimera_score")?,
+            value.optional("peptide_sequence")?,
+            value.optional("re_score")?,
+            value.optional("ims")?,
+            value.optional("inverse_ion_mobility_predicted")?,
+            value.optional("delta_ims_model")?,
+            value.optional("intensity_weighted_ppm")?,
+            value.optional("max_fragment_ppm")?,
+        ))
+    }
+
+    #[staticmethod]
+    pub fn get_feature_names() -> Vec<String> {
+        FEATURE_NAMES.iter().map(|s| s.to_string()).collect()
+    }
+
+    /// Values of all re-scoring features, in the order given by `get_feature_names`
+    pub fn get_feature_values(&self) -> Vec<f64> {
+        feature_values(self)
+    }
+
+    #[staticmethod]
+    pub fn get_feature_names_vector() -> Vec<String> {
+        ML_FEATURE_NAMES.iter().map(|s| s.to_string()).collect()
+    }
+
+    /// Fixed-length feature vector for machine learning re-scoring, in the order given by
+    /// `get_feature_names_vector`
+    pub fn get_feature_vector(&self) -> Vec<f64> {
+        ml_feature_vector(self).to_vec()
+    }
+
+    /// Intensity-weighted mean of absolute fragment ppm errors, set during scoring with
+    /// `annotate_matches` and NaN for PSMs without annotated fragments
+    #[getter]
+    pub fn intensity_weighted_ppm(&self) -> f32 {
+        self.intensity_weighted_ppm
+    }
+
+    /// Largest absolute fragment ppm error, set during scoring with `annotate_matches` and NaN
+    /// for PSMs without annotated fragments
+    #[getter]
+    pub fn max_fragment_ppm(&self) -> f32 {
+        self.max_fragment_ppm
+    }
+}
+
+impl PyFeature {
+    /// Recompute `intensity_weighted_ppm` and `max_fragment_ppm` after fragments were added
+    pub(crate) fn update_fragment_ppm(&mut self) {
+        (self.intensity_weighted_ppm, self.max_fragment_ppm) = fragment_ppm_summary(self.inner.fragments.as_ref());
+    }
+}
+
+/// Typed access to the values of a dict passed in from Python
+pub(crate) struct DictFields<'a> {
+    pub py: Python<'a>,
+    pub d: &'a HashMap<String, PyObject>,
+}
+
+impl<'a> DictFields<'a> {
+    pub fn required<T: for<'p> FromPyObject<'p>>(&self, key: &str) -> PyResult<T> {
+        self.optional(key)?
+            .ok_or_else(|| PyValueError::new_err(format!("Missing value for key: {}", key)))
+    }
+
+    pub fn optional<T: for<'p> FromPyObject<'p>>(&self, key: &str) -> PyResult<Option<T>> {
+        match self.d.get(key) {
+            Some(value) => value.extract::<Option<T>>(self.py),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Names of the numeric PSM features available for re-scoring
+pub const FEATURE_NAMES: [&str; 25] = [
+    "hyperscore",
+    "delta_next",
+    "delta_best",
+    "matched_peaks",
+    "longest_b",
+    "longest_y",
+    "longest_y_pct",
+    "missed_cleavages",
+    "matched_intensity_pct",
+    "scored_candidates",
+    "poisson",
+    "average_ppm",
+    "delta_mass",
+    "isotope_error",
+    "ms2_intensity",
+    "rt",
+    "delta_rt_model",
+    "peptide_len",
+    "charge",
+    "posterior_error",
+    "spectrum_q",
+    "peptide_q",
+    "protein_q",
+    "ms1_isotope_score",
+    "ms1_intensity_ratio",
+];
+
+pub fn feature_values(psm: &PyFeature) -> Vec<f64> {
+    let feature = &psm.inner;
+    vec![
+        feature.hyperscore,
+        feature.delta_next,
+        feature.delta_best,
+        feature.matched_peaks as f64,
+        feature.longest_b as f64,
+        feature.longest_y as f64,
+        feature.longest_y_pct as f64,
+        feature.missed_cleavages as f64,
+        feature.matched_intensity_pct as f64,
+        feature.scored_candidates as f64,
+        feature.poisson,
+        feature.average_ppm as f64,
+        feature.delta_mass as f64,
+        feature.isotope_error as f64,
+        feature.ms2_intensity as f64,
+        feature.rt as f64,
+        feature.delta_rt_model as f64,
+        feature.peptide_len as f64,
+        feature.charge as f64,
+        feature.posterior_error as f64,
+        feature.spectrum_q as f64,
+        feature.peptide_q as f64,
+        feature.protein_q as f64,
+        psm.ms1_isotope_score as f64,
+        psm.ms1_intensity_ratio as f64,
+    ]
+}
+
+/// Names of the score features of `ml_feature_vector`. Unlike `FEATURE_NAMES`, no q-values or
+/// posterior error probabilities are included, as these are derived from the score itself
+pub const ML_FEATURE_NAMES: [&str; 23] = [
+    "hyperscore",
+    "delta_next",
+    "delta_best",
+    "matched_peaks",
+    "longest_b",
+    "longest_y",
+    "longest_y_pct",
+    "missed_cleavages",
+    "matched_intensity_pct",
+    "scored_candidates",
+    "poisson",
+    "average_ppm",
+    "delta_mass",
+    "ms2_intensity",
+    "rt",
+    "delta_rt_model",
+    "ims",
+    "delta_ims_model",
+    "peptide_len",
+    "charge",
+    "isotope_error",
+    "intensity_weighted_ppm",
+    "max_fragment_ppm",
+];
+
+const _: () = assert!(ML_FEATURE_NAMES.len() == 23);
+
+/// Feature vector of a PSM, the array type ties its length to `ML_FEATURE_NAMES` at compile time
+pub fn ml_feature_vector(psm: &PyFeature) -> [f64; ML_FEATURE_NAMES.len()] {
+    let feature = &psm.inner;
+    [
+        feature.hyperscore,
+        feature.delta_next,
+        feature.delta_best,
+        feature.matched_peaks as f64,
+        feature.longest_b as f64,
+        feature.longest_y as f64,
+        feature.longest_y_pct as f64,
+        feature.missed_cleavages as f64,
+        feature.matched_intensity_pct as f64,
+        feature.scored_candidates as f64,
+        feature.poisson,
+        feature.average_ppm as f64,
+        feature.delta_mass as f64,
+        feature.ms2_intensity as f64,
+        feature.rt as f64,
+        feature.delta_rt_model as f64,
+        // NaN marks PSMs without an observed or predicted ion mobility
+        psm.ims.map_or(f64::NAN, |ims| ims as f64),
+        psm.delta_ims_model.map_or(f64::NAN, |delta| delta as f64),
+        feature.peptide_len as f64,
+        feature.charge as f64,
+        feature.isotope_error as f64,
+        // NaN marks PSMs scored without annotated fragments
+        psm.intensity_weighted_ppm as f64,
+        psm.max_fragment_ppm as f64,
+    ]
+}
+
+/// Feature vectors of many PSMs in parallel, one row per PSM
+#[pyfunction]
+pub fn features_to_matrix(py: Python, psms: Vec<PyFeature>, num_threads: Option<usize>) -> Vec<Vec<f64>> {
+    let pool = ThreadPoolBuilder::new()
+        .num_threads(num_threads.unwrap_or(4))
+        .build()
+        .unwrap();
+    py.allow_threads(|| {
+        pool.install(|| {
+            psms.par_iter()
+                .map(|psm| ml_feature_vector(psm).to_vec())
+                .collect()
+        })
+    })
+}
+
+/// Set a re-scoring feature by its name in `FEATURE_NAMES`, returns false for unknown names
+pub fn set_feature_value(psm: &mut PyFeature, name: &str, value: f64) -> bool {
+    let feature = &mut psm.inner;
+    match name {
+        "hyperscore" => feature.hyperscore = value,
+        "delta_next" => feature.delta_next = value,
+        "delta_best" => feature.delta_best = value,
+        "matched_peaks" => feature.matched_peaks = value as u32,
+        "longest_b" => feature.longest_b = value as u32,
+        "longest_y" => feature.longest_y = value as u32,
+        "longest_y_pct" => feature.longest_y_pct = value as f32,
+        "missed_cleavages" => feature.missed_cleavages = value as u8,
+        "matched_intensity_pct" => feature.matched_intensity_pct = value as f32,
+        "scored_candidates" => feature.scored_candidates = value as u32,
+        "poisson" => feature.poisson = value,
+        "average_ppm" => feature.average_ppm = value as f32,
+        "delta_mass" => feature.delta_mass = value as f32,
+        "isotope_error" => feature.isotope_error = value as f32,
+        "ms2_intensity" => feature.ms2_intensity = value as f32,
+        "rt" => feature.rt = value as f32,
+        "delta_rt_model" => feature.delta_rt_model = value as f32,
+        "peptide_len" => feature.peptide_len = value as usize,
+        "charge" => feature.charge = value as u8,
+        "posterior_error" => feature.posterior_error = value as f32,
+        "spectrum_q" => feature.spectrum_q = value as f32,
+        "peptide_q" => feature.peptide_q = value as f32,
+        "protein_q" => feature.protein_q = value as f32,
+        "ms1_isotope_score" => psm.ms1_isotope_score = value as f32,
+        "ms1_intensity_ratio" => psm.ms1_intensity_ratio = value as f32,
+        _ => return false,
+    }
+    true
+}
+
+/// Signed ppm errors of all matched fragments
+fn fragment_ppm_errors(fragments: &Fragments) -> impl Iterator<Item = f32> + '_ {
+    fragments
+        .mz_experimental
+        .iter()
+        .zip(fragments.mz_calculated.iter())
+        .map(|(exp, calc)| (exp - calc) / calc * 1e6)
+}
+
+/// Intensity-weighted mean and maximum of the absolute fragment ppm errors, NaN without
+/// annotated fragments
+fn fragment_ppm_summary(fragments: Option<&Fragments>) -> (f32, f32) {
+    let Some(fragments) = fragments.filter(|f| !f.mz_calculated.is_empty()) else {
+        return (f32::NAN, f32::NAN);
+    };
+    let (weighted, total) = fragment_ppm_errors(fragments)
+        .zip(fragments.intensities.iter())
+        .fold((0.0f32, 0.0f32), |(w, t), (ppm, intensity)| {
+            (w + intensity * ppm.abs(), t + intensity)
+        });
+    let max = fragment_ppm_errors(fragments).map(f32::abs).fold(0.0, f32::max);
+    match total > 0.0 {
+        true => (weighted / total, max),
+        false => (f32::NAN, max),
+    }
+}
+
+/// Expected M+1 / M intensity ratio per Dalton of neutral mass, derived from the averagine
+/// composition and natural isotope abundances (dominated by 13C)
+const AVERAGINE_M1_RATIO_PER_DA: f32 = 0.000533;
+
+/// Most intense peak of a spectrum within the tolerance window around a neutral mass
+fn most_intense_match(spectrum: &ProcessedSpectrum, mass: f32, tolerance: Tolerance) -> Option<&Peak> {
+    most_intense_match_index(spectrum, mass, tolerance).map(|i| &spectrum.peaks[i])
+}
+
+/// Index of the most intense peak within the tolerance window around a mass
+fn most_intense_match_index(spectrum: &ProcessedSpectrum, mass: f32, tolerance: Tolerance) -> Option<usize> {
+    let (lo, hi) = tolerance.bounds(mass);
+    let start = spectrum.peaks.partition_point(|p| p.mass < lo);
+    spectrum.peaks[start..]
+        .iter()
+        .enumerate()
+        .take_while(|(_, p)| p.mass <= hi)
+        .max_by(|(_, a), (_, b)| a.intensity.total_cmp(&b.intensity))
+        .map(|(i, _)| start + i)
+}
+
+fn most_intense_peak(spectrum: &ProcessedSpectrum, mass: f32, tolerance: Tolerance) -> Option<f32> {
+    most_intense_match(spectrum, mass, tolerance).map(|p| p.intensity)
+}
+
+/// Intensity of the most intense raw peak within the tolerance window around an m/z
+fn most_intense_raw_peak(spectrum: &RawSpectrum, mz: f32, tolerance: Tolerance) -> Option<f32> {
+    let (lo, hi) = tolerance.bounds(mz);
+    spectrum
+        .mz
+        .iter()
+        .zip(spectrum.intensity.iter())
+        .filter(|(&peak_mz, _)| peak_mz >= lo && peak_mz <= hi)
+        .map(|(_, &intensity)| intensity)
+        .max_by(|a, b| a.total_cmp(b))
+}
+
+/// Agreement (0-1) between the observed and the averagine M+1 / M ratio of a fragment ion. The
+/// isotope peaks are looked up in the raw peaks, the M+1 peak is NEUTRON / charge above the fragment
+fn isotope_score(spectrum: &RawSpectrum, fragment_mz: f32, charge: i32, tolerance: Tolerance) -> f32 {
+    let charge = charge.max(1) as f32;
+    let mass = (fragment_mz - PROTON) * charge;
+
+    let monoisotopic = match most_intense_raw_peak(spectrum, fragment_mz, tolerance) {
+        Some(intensity) if intensity > 0.0 => intensity,
+        _ => return 0.0,
+    };
+    let m1 = most_intense_raw_peak(spectrum, fragment_mz + NEUTRON / charge, tolerance).unwrap_or(0.0);
+
+    let observed = m1 / monoisotopic;
+    let expected = mass * AVERAGINE_M1_RATIO_PER_DA;
+    if observed <= 0.0 || expected <= 0.0 {
+        return 0.0;
+    }
+    observed.min(expected) / observed.max(expected)
+}
+
+fn mean_isotope_annotation_score(spectrum: &RawSpectrum, fragments: &Fragments, tolerance: Tolerance) -> f32 {
+    if fragments.mz_calculated.is_empty() {
+        return 0.0;
+    }
+    let total: f32 = fragments
+        .mz_calculated
+        .iter()
+        .zip(fragments.charges.iter())
+        .map(|(&mz, &charge)| isotope_score(spectrum, mz, charge, tolerance))
+        .sum();
+    total / fragments.mz_calculated.len() as f32
+}
+
+/// Default m/z tolerance of precursor isotope peaks when scoring with MS1 spectra
+const MS1_ISOTOPE_WINDOW: f32 = 0.02;
+
+/// Averagine isotope distribution (M to M+n) of a neutral mass, approximated as Poisson with the
+/// averagine M+1 / M ratio as rate
+pub(crate) fn averagine_distribution(mass: f32, n: usize) -> Vec<f32> {
+    let lambda = mass * AVERAGINE_M1_RATIO_PER_DA;
+    let mut distribution = Vec::with_capacity(n + 1);
+    let mut p = (-lambda).exp();
+    for k in 0..=n {
+        distribution.push(p);
+        p *= lambda / (k + 1) as f32;
+    }
+    distribution
+}
+
+/// Annotate a PSM with the agreement of its precursor isotope envelope in an MS1 spectrum with the
+/// averagine model. MS1 peaks are expected as m/z - proton, isotopes are searched within
+/// +/- `isotope_window` of their expected position
+fn annotate_ms1_isotopes(feature: &mut PyFeature, ms1: &ProcessedSpectrum, isotope_window: f32) {
+    let charge = feature.inner.charge.max(1) as f32;
+    let mass = feature.inner.calcmass;
+    let tolerance = Tolerance::Da(-isotope_window, isotope_window);
+    let observed: Vec<f32> = (0..5)
+        .map(|k| {
+            let mz = (mass + k as f32 * NEUTRON) / charge;
+            most_intense_peak(ms1, mz, tolerance).unwrap_or(0.0)
+        })
+        .collect();
+
+    let total: f32 = observed.iter().sum();
+    feature.ms1_intensity_ratio = if total > 0.0 { observed[0] / total } else { 0.0 };
+
+    let expected = averagine_distribution(mass, 2);
+    let dot: f32 = observed.iter().zip(expected.iter()).map(|(o, e)| o * e).sum();
+    let norm = observed[..3].iter().map(|o| o * o).sum::<f32>().sqrt()
+        * expected.iter().map(|e| e * e).sum::<f32>().sqrt();
+    feature.ms1_isotope_score = if norm > 0.0 { dot / norm } else { 0.0 };
+}
+
+/// Most recent MS1 spectrum of the same file acquired before (or with) a spectrum
+fn preceding_ms1<'a>(ms1_spectra: &'a [PyProcessedSpectrum], spectrum: &ProcessedSpectrum) -> Option<&'a ProcessedSpectrum> {
+    ms1_spectra
+        .iter()
+        .map(|s| &s.inner)
+        .filter(|s| {
+            s.level == 1 && s.file_id == spectrum.file_id && s.scan_start_time <= spectrum.scan_start_time
+        })
+        .max_by(|a, b| a.scan_start_time.total_cmp(&b.scan_start_time))
+}
+
+/// Add MS1 isotope evidence to a PSM: the cosine similarity of the observed M, M+1 and M+2
+/// precursor peaks to the averagine distribution (`ms1_isotope_score`) and the monoisotopic
+/// fraction of the M to M+4 intensity (`ms1_intensity_ratio`)
+#[pyfunction]
+pub fn score_with_ms1_isotope_evidence(
+    psm: PyFeature,
+    ms1_spectrum: &PyProcessedSpectrum,
+    isotope_window: f32,
+) -> PyFeature {
+    let mut psm = psm;
+    annotate_ms1_isotopes(&mut psm, &ms1_spectrum.inner, isotope_window);
+    psm
+}
+
+pub(crate) const H2O: f32 = 18.010565;
+pub(crate) const NH3: f32 = 17.026549;
+const H3PO4: f32 = 97.976896;
+const PHOSPHO: f32 = 79.966331;
+
+/// Neutral loss ions matched in addition to the regular fragments as (kind, loss, phospho only)
+const NEUTRAL_LOSSES: [(Kind, f32, bool); 5] = [
+    (Kind::B, H2O, false),
+    (Kind::B, NH3, false),
+    (Kind::Y, H2O, false),
+    (Kind::Y, NH3, false),
+    (Kind::Y, H3PO4, true),
+];
+
+/// Match b/y neutral loss ions (singly charged) of a PSM's peptide, appending them to annotated
+/// fragments and to the matched peak count. Phosphoric acid losses are only considered for
+/// phosphopeptides. Every peak is counted once: peaks matching a regular ion of the database's
+/// ion kinds are skipped, and a peak matching several losses counts for the first only
+fn match_neutral_losses(
+    db: &IndexedDatabase,
+    spectrum: &ProcessedSpectrum,
+    feature: &mut PyFeature,
+    tolerance: Tolerance,
+    losses: &[(Kind, f32, bool)],
+) {
+    let peptide = &db[feature.inner.peptide_idx];
+    let phosphorylated = peptide.modifications.iter().any(|m| (m - PHOSPHO).abs() < 0.01);
+
+    let mut used: HashSet<usize> = db
+        .ion_kinds
+        .iter()
+        .flat_map(|kind| IonSeries::new(peptide, *kind))
+        .filter_map(|ion| most_intense_match_index(spectrum, ion.monoisotopic_mass, tolerance))
+        .collect();
+
+    let mut matched = 0u32;
+    let mut intensity = 0.0f32;
+    for &(kind, loss, phospho_only) in losses {
+        if phospho_only && !phosphorylated {
+            continue;
+        }
+        for (i, ion) in IonSeries::new(peptide, kind).enumerate() {
+            let mass = ion.monoisotopic_mass - loss;
+            let index = match most_intense_match_index(spectrum, mass, tolerance) {
+                Some(index) if used.insert(index) => index,
+                _ => continue,
+            };
+            let peak = &spectrum.peaks[index];
+            matched += 1;
+            intensity += peak.intensity;
+
+            if let Some(fragments) = feature.inner.fragments.as_mut() {
+                let ordinal = match kind {
+                    Kind::B => i + 1,
+                    _ => peptide.sequence.len() - i - 1,
+                };
+                feature.neutral_losses.resize(fragments.charges.len(), 0.0);
+                fragments.charges.push(1);
+                fragments.kinds.push(kind);
+                fragments.fragment_ordinals.push(ordinal as i32);
+                fragments.intensities.push(peak.intensity);
+                fragments.mz_calculated.push(mass + PROTON);
+                fragments.mz_experimental.push(peak.mass + PROTON);
+                feature.neutral_losses.push(loss);
+            }
+        }
+    }
+
+    feature.inner.matched_peaks += matched;
+    feature.neutral_loss_intensity_pct = if spectrum.total_ion_current > 0.0 {
+        100.0 * intensity / spectrum.total_ion_current
+    } else {
+        0.0
+    };
+    feature.unexplained_intensity_pct = 100.0 - explained_intensity_pct(feature);
+    feature.update_fragment_ppm();
+}
+
+/// Shannon entropy of an intensity distribution, normalised to [0, 1] by the maximal entropy
+/// ln(n) of n peaks
+fn normalized_entropy(intensities: &[f32]) -> f32 {
+    let total: f32 = intensities.iter().filter(|i| **i > 0.0).sum();
+    let n = intensities.iter().filter(|i| **i > 0.0).count();
+    if n < 2 || total <= 0.0 {
+        return 0.0;
+    }
+    let entropy: f32 = intensities
+        .iter()
+        .filter(|i| **i > 0.0)
+        .map(|i| {
+            let p = i / total;
+            -p * p.ln()
+        })
+        .sum();
+    entropy / (n as f32).ln()
+}
+
+/// Set the spectral entropy and the matched - unmatched peak entropy difference of a PSM, peaks
+/// are matched against the singly charged fragment ions of its peptide
+fn annotate_spectral_entropy(db: &IndexedDatabase, spectrum: &ProcessedSpectrum, feature: &mut PyFeature, tolerance: Tolerance) {
+    let peptide = &db[feature.inner.peptide_idx];
+    let mut matched = vec![false; spectrum.peaks.len()];
+    for ion in db.ion_kinds.iter().flat_map(|kind| IonSeries::new(peptide, *kind)) {
+        let (lo, hi) = tolerance.bounds(ion.monoisotopic_mass);
+        let start = spectrum.peaks.partition_point(|p| p.mass < lo);
+        for i in (start..spectrum.peaks.len()).take_while(|&i| spectrum.peaks[i].mass <= hi) {
+            matched[i] = true;
+        }
+    }
+
+    let (matched_intensities, unmatched_intensities): (Vec<(f32, bool)>, Vec<(f32, bool)>) = spectrum
+        .peaks
+        .iter()
+        .zip(matched.iter())
+        .map(|(p, m)| (p.intensity, *m))
+        .partition(|(_, m)| *m);
+    let entropy_of = |peaks: &[(f32, bool)]| {
+        normalized_entropy(&peaks.iter().map(|(i, _)| *i).collect::<Vec<_>>())
+    };
+
+    feature.spectral_entropy = compute_spectral_entropy_of(spectrum);
+    feature.delta_spectral_entropy = entropy_of(&matched_intensities) - entropy_of(&unmatched_intensities);
+}
+
+fn compute_spectral_entropy_of(spectrum: &ProcessedSpectrum) -> f32 {
+    normalized_entropy(&spectrum.peaks.iter().map(|p| p.intensity).collect::<Vec<_>>())
+}
+
+/// Normalised Shannon entropy [0, 1] of the peak intensities of a spectrum, low values indicate
+/// few dominant peaks
+#[pyfunction]
+pub fn compute_spectral_entropy(spectrum: &PyProcessedSpectrum) -> f32 {
+    compute_spectral_entropy_of(&spectrum.inner)
+}
+
+/// Score [0, 1] of how well the M+1 isotope peak of a fragment matches the averagine pattern,
+/// computed on the raw (not deisotoped) peaks of the spectrum
+#[pyfunction]
+pub fn isotope_annotation_score(
+    spectrum: &PyRawSpectrum,
+    fragment_mz: f32,
+    charge: i32,
+    tolerance: &PyTolerance,
+) -> f32 {
+    isotope_score(&spectrum.inner, fragment_mz, charge, tolerance.inner)
+}
+
+/// Mean isotope annotation score over all matched fragments
+#[pyfunction]
+pub fn total_isotope_annotation_score(
+    spectrum: &PyRawSpectrum,
+    fragments: &PyFragments,
+    tolerance: &PyTolerance,
+) -> f32 {
+    mean_isotope_annotation_score(&spectrum.inner, &fragments.inner, tolerance.inner)
+}
+
+/// Set the isotope annotation score of PSMs from the raw peaks of their spectrum, PSMs without
+/// annotated fragments (see `annotate_matches`) keep a score of NaN
+#[pyfunction]
+pub fn annotate_isotope_annotation_scores(
+    mut features: Vec<PyFeature>,
+    spectrum: &PyRawSpectrum,
+    tolerance: &PyTolerance,
+) -> Vec<PyFeature> {
+    for feature in features.iter_mut() {
+        if let Some(fragments) = &feature.inner.fragments {
+            feature.isotope_annotation_score =
+                mean_isotope_annotation_score(&spectrum.inner, fragments, tolerance.inner);
+        }
+    }
+    features
+}
+
+/// Singly charged m/z of the default diagnostic oxonium ions: HexNAc and its fragments, Hex,
+/// dHex, HexHexNAc, NeuAc (and water loss) and NeuGc
+const OXONIUM_IONS: [f32; 11] = [
+    126.0550, 138.0550, 144.0655, 147.0652, 163.0601, 168.0655, 186.0761, 204.0867, 274.0921,
+    292.1027, 308.0976,
+];
+
+/// Default number of oxonium ions required as evidence of a glycopeptide
+const MIN_OXONIUM_IONS: usize = 2;
+
+/// Monoisotopic residue masses of the monosaccharides of glycan compositions
+const MONOSACCHARIDES: [(&str, f32); 6] = [
+    ("HexNAc", 203.079373),
+    ("Hex", 162.052824),
+    ("Fuc", 146.057909),
+    ("dHex", 146.057909),
+    ("NeuAc", 291.095417),
+    ("NeuGc", 307.090331),
+];
+
+/// Number of matched oxonium ions and a hyperscore-like score ln(n!) + ln(1 + I / I_base) of
+/// their summed intensity relative to the base peak
+fn oxonium_evidence(spectrum: &ProcessedSpectrum, oxonium_mz: &[f32], tolerance: Tolerance) -> (usize, f32) {
+    let base_peak = spectrum.peaks.iter().map(|p| p.intensity).fold(0.0f32, f32::max);
+    if base_peak <= 0.0 {
+        return (0, 0.0);
+    }
+    let intensities: Vec<f32> = oxonium_mz
+        .iter()
+        .filter_map(|mz| most_intense_peak(spectrum, mz - PROTON, tolerance))
+        .collect();
+    let matched = intensities.len();
+    let log_factorial: f32 = (1..=matched).map(|k| (k as f32).ln()).sum();
+    let relative: f32 = intensities.iter().sum::<f32>() / base_peak;
+    (matched, log_factorial + relative.ln_1p())
+}
+
+/// Mass of a glycan composition such as `HexNAc(4)Hex(5)Fuc(1)NeuAc(2)`
+fn glycan_mass(composition: &str) -> PyResult<f32> {
+    let invalid = || PyValueError::new_err(format!("Invalid glycan composition: {}", composition));
+    let mut mass = 0.0;
+    let mut rest = composition;
+    while !rest.is_empty() {
+        let open = rest.find('(').ok_or_else(invalid)?;
+        let close = rest.find(')').ok_or_else(invalid)?;
+        if close < open {
+            return Err(invalid());
+        }
+        let residue = MONOSACCHARIDES
+            .iter()
+            .find(|(name, _)| *name == &rest[..open])
+            .map(|(_, mass)| *mass)
+            .ok_or_else(invalid)?;
+        let count: u32 = rest[open + 1..close].parse().map_err(|_| invalid())?;
+        mass += residue * count as f32;
+        rest = &rest[close + 1..];
+    }
+    Ok(mass)
+}
+
+/// Read a glycan database, one composition per line (anything after the first whitespace, comma
+/// or tab is ignored, lines starting with # are comments)
+fn read_glycan_database(path: &str) -> PyResult<Vec<(String, f32)>> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| PyValueError::new_err(format!("Could not read glycan database {}: {}", path, e)))?;
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let composition = line
+                .split(|c: char| c.is_whitespace() || c == ',')
+                .next()
+                .unwrap_or_default();
+            Ok((composition.to_string(), glycan_mass(composition)?))
+        })
+        .collect()
+}
+
+/// Whether a peptide carries an N-glycosylation sequon N-X-S/T (X not P)
+fn has_sequon(sequence: &[u8]) -> bool {
+    sequence
+        .windows(3)
+        .any(|w| w[0] == b'N' && w[1] != b'P' && (w[2] == b'S' || w[2] == b'T'))
+}
+
+/// Copies of a spectrum whose precursor m/z is lowered by a glycan mass, one per candidate charge
+/// if the precursor charge is unknown, so that only the peptide backbone is matched
+fn deglycosylated_spectra(spectrum: &ProcessedSpectrum, glycan_mass: f32, charges: RangeInclusive<u8>) -> Vec<ProcessedSpectrum> {
+    let Some(precursor) = spectrum.precursors.first() else {
+        return Vec::new();
+    };
+    let charges: Vec<u8> = match precursor.charge {
+        Some(charge) => vec![charge],
+        None => charges.collect(),
+    };
+    charges
+        .into_iter()
+        .map(|charge| {
+            let mut shifted = spectrum.clone();
+            shifted.precursors[0].charge = Some(charge);
+            shifted.precursors[0].mz -= glycan_mass / charge as f32;
+            shifted
+        })
+        .collect()
+}
+
+fn same_ion_kinds(a: &[Kind], b: &[Kind]) -> bool {
+    a.len() == b.len() && a.iter().all(|k| b.contains(k))
+}
+
+/// Ion kinds of the electron activated spectra among `spectra` (see
+/// `PyProcessedSpectrum::activation_ion_kinds`) that `db` was not built with
+fn activation_ion_kinds<'a>(
+    db: &IndexedDatabase,
+    spectra: impl IntoIterator<Item = &'a PyProcessedSpectrum>,
+) -> Vec<Vec<Kind>> {
+    let mut ion_kinds: Vec<Vec<Kind>> = Vec::new();
+    for kinds in spectra.into_iter().filter_map(|s| s.activation_ion_kinds()) {
+        if !same_ion_kinds(&kinds, &db.ion_kinds) && !ion_kinds.iter().any(|k| same_ion_kinds(k, &kinds)) {
+            ion_kinds.push(kinds);
+        }
+    }
+    ion_kinds
+}
+
+/// Default bin width (Th) of the xcorr score type
+const XCORR_DEFAULT_BIN_WIDTH: f32 = 0.02;
+/// Half width (Th) of the window the local background is averaged over in fast xcorr
+const XCORR_BACKGROUND_OFFSET: f32 = 75.0;
+/// Binned intensities are scaled to this maximum, and the xcorr sum by `XCORR_SCALE`, matching the
+/// magnitude of SEQUEST / Andromeda style cross-correlation scores
+const XCORR_MAX_INTENSITY: f32 = 50.0;
+const XCORR_SCALE: f32 = 0.005;
+
+/// Fast xcorr preprocessing: bin sqrt-transformed intensities and subtract the mean
+/// intensity of the surrounding background window from every bin
+fn xcorr_preprocess(spectrum: &ProcessedSpectrum, bin_width: f32) -> Vec<f32> {
+    let max_mass = spectrum.peaks.iter().map(|p| p.mass).fold(0.0f32, f32::max);
+    let num_bins = (max_mass / bin_width) as usize + 1;
+
+    let mut binned = vec![0.0f32; num_bins];
+    for peak in &spectrum.peaks {
+        let bin = (peak.mass / bin_width) as usize;
+        if bin < num_bins {
+            binned[bin] = binned[bin].max(peak.intensity.sqrt());
+        }
+    }
+
+    let max_intensity = binned.iter().copied().fold(0.0f32, f32::max);
+    if max_intensity > 0.0 {
+        binned.iter_mut().for_each(|b| *b *= XCORR_MAX_INTENSITY / max_intensity);
+    }
+
+    let mut prefix = vec![0.0f64; num_bins + 1];
+    for (i, b) in binned.iter().enumerate() {
+        prefix[i + 1] = prefix[i] + *b as f64;
+    }
+
+    let offset = ((XCORR_BACKGROUND_OFFSET / bin_width) as usize).max(1);
+    binned
+        .iter()
+        .enumerate()
+        .map(|(i, b)| {
+            let lo = i.saturating_sub(offset);
+            let hi = (i + offset + 1).min(num_bins);
+            let background = (prefix[hi] - prefix[lo]) as f32 - b;
+            b - background / (2 * offset) as f32
+        })
+        .collect()
+}
+
+/// Cross-correlation of a peptide's theoretical fragment ions with a preprocessed spectrum
+fn xcorr(processed: &[f32], peptide: &Peptide, ion_kinds: &[Kind], bin_width: f32) -> f32 {
+    let total: f32 = ion_kinds
+        .iter()
+        .flat_map(|kind| IonSeries::new(peptide, *kind))
+        .filter_map(|ion| processed.get((ion.monoisotopic_mass / bin_width) as usize))
+        .sum();
+    total * XCORR_SCALE
+}
+
+/// Re-rank the PSMs of a spectrum by xcorr and set xcorr and delta_xcorr
+fn rank_by_xcorr(db: &IndexedDatabase, spectrum: &ProcessedSpectrum, features: &mut [PyFeature], bin_width: f32) {
+    if features.is_empty() {
+        return;
+    }
+
+    let processed = xcorr_preprocess(spectrum, bin_width);
+    for feature in features.iter_mut() {
+        let peptide = &db[feature.inner.peptide_idx];
+        feature.xcorr = Some(xcorr(&processed, peptide, &db.ion_kinds, bin_width));
+    }
+
+    features.sort_by(|a, b| b.xcorr.unwrap_or_default().total_cmp(&a.xcorr.unwrap_or_default()));
+
+    let best = features[0].xcorr.unwrap_or_default();
+    let second = features.get(1).and_then(|f| f.xcorr).unwrap_or_default();
+    for (i, feature) in features.iter_mut().enumerate() {
+        let score = feature.xcorr.unwrap_or_default();
+        let delta = match (i, best > 0.0) {
+            (_, false) => 0.0,
+            (0, true) => (best - second) / best,
+            (_, true) => (best - score) / best,
+        };
+        feature.delta_xcorr = Some(delta);
+        feature.inner.rank = i as u32 + 1;
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScoreType {
+    #[default]
+    Standard,
+    Tdc,
+    XCorr,
+}
+
+#[pyclass]
+#[derive(Clone, Default)]
+pub struct PyScoreType {
+    pub inner: ScoreType,
+}
+
+#[pymethods]
+impl PyScoreType {
+    #[new]
+    pub fn new(score_type: &str) -> PyResult<Self> {
+        match score_type.to_lowercase().as_str() {
+            "standard" => Ok(PyScoreType {
+                inner: ScoreType::Standard,
+            }),
+            "tdc" => Ok(PyScoreType {
+                inner: ScoreType::Tdc,
+            }),
+            "xcorr" | "andromeda" => Ok(PyScoreType {
+                inner: ScoreType::XCorr,
+            }),
+            _ => Err(PyValueError::new_err(format!(
+                "Invalid score type: {}, allowed values are: standard, tdc, xcorr, andromeda",
+                score_type
+            ))),
+        }
+    }
+
+    #[getter]
+    pub fn score_type(&self) -> String {
+        match self.inner {
+            ScoreType::Standard => "standard".to_string(),
+            ScoreType::Tdc => "tdc".to_string(),
+            ScoreType::XCorr => "xcorr".to_string(),
+        }
+    }
+}
+
+/// Unit of the precursor ion mobility tolerance: absolute inverse ion mobility (1/K0, V s/cm2)
+/// or percent of the observed 1/K0
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImsUnit {
+    AbsoluteVm,
+    RelativePct,
+}
+
+#[pyclass]
+#[derive(Clone)]
+pub struct PyImsUnit {
+    pub inner: ImsUnit,
+}
+
+#[pymethods]
+impl PyImsUnit {
+    #[new]
+    pub fn new(unit: &str) -> PyResult<Self> {
+        match unit.to_lowercase().as_str() {
+            "absolute" | "absolute_vm" | "1/k0" => Ok(PyImsUnit {
+                inner: ImsUnit::AbsoluteVm,
+            }),
+            "relative" | "relative_pct" | "pct" => Ok(PyImsUnit {
+                inner: ImsUnit::RelativePct,
+            }),
+            _ => Err(PyValueError::new_err(format!(
+                "Invalid ion mobility unit: {}, allowed values are: absolute, relative",
+                unit
+            ))),
+        }
+    }
+
+    #[getter]
+    pub fn unit(&self) -> String {
+        match self.inner {
+            ImsUnit::AbsoluteVm => "absolute".to_string(),
+            ImsUnit::RelativePct => "relative".to_string(),
+        }
+    }
+}
+
+/// Spectral entropy of the scored PSMs: not computed, annotated as `spectral_entropy` and
+/// `delta_spectral_entropy`, or annotated and used to rescore (the discriminant score becomes the
+/// hyperscore weighted by 1 + delta_spectral_entropy)
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SpectralEntropyMode {
+    #[default]
+    Off,
+    Feature,
+    Rescore,
+}
+
+#[pyclass]
+#[derive(Clone, Default)]
+pub struct PySpectralEntropyMode {
+    pub inner: SpectralEntropyMode,
+}
+
+#[pymethods]
+impl PySpectralEntropyMode {
+    #[new]
+    pub fn new(mode: &str) -> PyResult<Self> {
+        match mode.to_lowercase().as_str() {
+            "off" => Ok(PySpectralEntropyMode {
+                inner: SpectralEntropyMode::Off,
+            }),
+            "feature" => Ok(PySpectralEntropyMode {
+                inner: SpectralEntropyMode::Feature,
+            }),
+            "rescore" => Ok(PySpectralEntropyMode {
+                inner: SpectralEntropyMode::Rescore,
+            }),
+            _ => Err(PyValueError::new_err(format!(
+                "Invalid spectral entropy mode: {}, allowed values are: off, feature, rescore",
+                mode
+            ))),
+        }
+    }
+
+    #[getter]
+    pub fn mode(&self) -> String {
+        match self.inner {
+            SpectralEntropyMode::Off => "off".to_string(),
+            SpectralEntropyMode::Feature => "feature".to_string(),
+            SpectralEntropyMode::Rescore => "rescore".to_string(),
+        }
+    }
+}
+
+/// Number of scored spectra between two calls of the progress callback
+const PROGRESS_INTERVAL: usize = 1000;
+
+/// Shared flag to interrupt a running `score_collection_with_progress`
+#[pyclass]
+#[derive(Clone, Default)]
+pub struct PyCancellationToken {
+    pub inner: Arc<AtomicBool>,
+}
+
+#[pymethods]
+impl PyCancellationToken {
+    #[new]
+    pub fn new() -> Self {
+        PyCancellationToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.inner.store(true, Ordering::SeqCst);
+    }
+
+    #[getter]
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.load(Ordering::SeqCst)
+    }
+}
+
+/// Serialisable settings of the glycopeptide scoring mode
+#[derive(Clone, Serialize, Deserialize)]
+pub struct GlycopeptideScoringMode {
+    pub oxonium_mz: Vec<f32>,
+    pub min_oxonium_ions: usize,
+}
+
+/// Glycopeptide scoring: the low m/z region of each spectrum is searched for diagnostic oxonium
+/// ions, whose score is added to the discriminant score of its PSMs
+#[pyclass]
+#[derive(Clone)]
+pub struct PyGlycopeptideScoringMode {
+    pub inner: GlycopeptideScoringMode,
+}
+
+#[pymethods]
+impl PyGlycopeptideScoringMode {
+    #[new]
+    pub fn new(oxonium_mz: Option<Vec<f32>>, min_oxonium_ions: Option<usize>) -> PyResult<Self> {
+        let oxonium_mz = oxonium_mz.unwrap_or_else(|| OXONIUM_IONS.to_vec());
+        if oxonium_mz.is_empty() {
+            return Err(PyValueError::new_err("oxonium_mz must not be empty"));
+        }
+        Ok(PyGlycopeptideScoringMode {
+            inner: GlycopeptideScoringMode {
+                oxonium_mz,
+                min_oxonium_ions: min_oxonium_ions.unwrap_or(MIN_OXONIUM_IONS),
+            },
+        })
+    }
+
+    #[getter]
+    pub fn oxonium_mz(&self) -> Vec<f32> {
+        self.inner.oxonium_mz.clone()
+    }
+
+    #[getter]
+    pub fn min_oxonium_ions(&self) -> usize {
+        self.inner.min_oxonium_ions
+    }
+}
+
+/// A PSM of a glycopeptide: the peptide backbone match and the glycan composition explaining the
+/// remaining precursor mass
+#[pyclass]
+#[derive(Clone)]
+pub struct PyGlycopeptideMatch {
+    pub feature: PyFeature,
+    pub glycan: String,
+    pub glycan_mass: f32,
+}
+
+#[pymethods]
+impl PyGlycopeptideMatch {
+    #[getter]
+    pub fn feature(&self) -> PyFeature {
+        self.feature.clone()
+    }
+
+    #[getter]
+    pub fn glycan(&self) -> String {
+        self.glycan.clone()
+    }
+
+    #[getter]
+    pub fn glycan_mass(&self) -> f32 {
+        self.glycan_mass
+    }
+}
+
+/// Drop fragment peaks below the absolute and/or relative (fraction of base peak) intensity
+/// thresholds, so that they can neither be matched nor contribute to the hyperscore. The intensity
+/// of the dropped peaks is removed from the total ion current, so that the matched intensity
+/// fraction refers to the peaks that could be matched
+fn filter_fragment_peaks(
+    spectrum: &ProcessedSpectrum,
+    min_intensity: Option<f32>,
+    min_intensity_relative: Option<f32>,
+) -> Cow<'_, ProcessedSpectrum> {
+    let base_peak = spectrum
+        .peaks
+        .iter()
+        .map(|p| p.intensity)
+        .fold(0.0f32, f32::max);
+
+    let threshold = match (min_intensity, min_intensity_relative) {
+        (None, None) => return Cow::Borrowed(spectrum),
+        (Some(absolute), None) => absolute,
+        (None, Some(relative)) => relative * base_peak,
+        (Some(absolute), Some(relative)) => absolute.max(relative * base_peak),
+    };
+
+    let mut filtered = spectrum.clone();
+    let dropped: f32 = filtered
+        .peaks
+        .iter()
+        .filter(|p| p.intensity < threshold)
+        .map(|p| p.intensity)
+        .sum();
+    filtered.peaks.retain(|p| p.intensity >= threshold);
+    filtered.total_ion_current = (filtered.total_ion_current - dropped).max(0.0);
+    Cow::Owned(filtered)
+}
+
+#[pyclass]
+#[derive(Clone)]
+pub struct PyScorer {
+    pub precursor_tolerance: PyTolerance,
+    pub fragment_tolerance: PyTolerance,
+    pub min_matched_peaks: u16,
+    pub min_isotope_err: i8,
+    pub max_isotope_err: i8,
+    pub min_precursor_charge: u8,
+    pub max_precursor_charge: u8,
+    pub max_fragment_charge: Option<u8>,
+    pub min_fragment_mass: f32,
+    pub max_fragment_mass: f32,
+    pub chimera: bool,
+    pub report_psms: usize,
+    pub wide_window: bool,
+    pub annotate_matches: bool,
+    pub score_type: PyScoreType,
+    pub min_fragment_intensity: Option<f32>,
+    pub min_fragment_intensity_relative: Option<f32>,
+    pub mc_prune_prior: Option<f32>,
+    pub xcorr_bin_width: Option<f32>,
+    pub match_neutral_losses: bool,
+    pub spectral_entropy: PySpectralEntropyMode,
+    pub glyco_mode: Option<PyGlycopeptideScoringMode>,
+    pub neutral_losses: Vec<f32>,
+    pub with_coverage_stats: bool,
+    pub mc_prune_max_allowed: Option<u8>,
+    pub ims_tolerance: Option<(f32, PyImsUnit)>,
+}
+
+/// Serialisable mirror of all `PyScorer` settings
+#[derive(Serialize, Deserialize)]
+struct ScorerSettings {
+    precursor_tolerance: Tolerance,
+    fragment_tolerance: Tolerance,
+    min_matched_peaks: u16,
+    min_isotope_err: i8,
+    max_isotope_err: i8,
+    min_precursor_charge: u8,
+    max_precursor_charge: u8,
+    max_fragment_charge: Option<u8>,
+    min_fragment_mass: f32,
+    max_fragment_mass: f32,
+    chimera: bool,
+    report_psms: usize,
+    wide_window: bool,
+    annotate_matches: bool,
+    #[serde(default)]
+    score_type: ScoreType,
+    #[serde(default)]
+    min_fragment_intensity: Option<f32>,
+    #[serde(default)]
+    min_fragment_intensity_relative: Option<f32>,
+    #[serde(default)]
+    mc_prune_prior: Option<f32>,
+    #[serde(default)]
+    xcorr_bin_width: Option<f32>,
+    #[serde(default)]
+    match_neutral_losses: bool,
+    #[serde(default)]
+    spectral_entropy: SpectralEntropyMode,
+    #[serde(default)]
+    glyco_mode: Option<GlycopeptideScoringMode>,
+    #[serde(default)]
+    neutral_losses: Vec<f32>,
+    #[serde(default)]
+    with_coverage_stats: bool,
+    #[serde(default)]
+    mc_prune_max_allowed: Option<u8>,
+    #[serde(default)]
+    ims_tolerance: Option<(f32, ImsUnit)>,
+}
+
+impl From<&PyScorer> for ScorerSettings {
+    fn from(scorer: &PyScorer) -> Self {
+        ScorerSettings {
+            precursor_tolerance: scorer.precursor_tolerance.inner.clone(),
+            fragment_tolerance: scorer.fragment_tolerance.inner.clone(),
+            min_matched_peaks: scorer.min_matched_peaks,
+            min_isotope_err: scorer.min_isotope_err,
+            max_isotope_err: scorer.max_isotope_err,
+            min_precursor_charge: scorer.min_precursor_charge,
+            max_precursor_charge: scorer.max_precursor_charge,
+            max_fragment_charge: scorer.max_fragment_charge,
+            min_fragment_mass: scorer.min_fragment_mass,
+            max_fragment_mass: scorer.max_fragment_mass,
+            chimera: scorer.chimera,
+            report_psms: scorer.report_psms,
+            wide_window: scorer.wide_window,
+            annotate_matches: scorer.annotate_matches,
+            score_type: scorer.score_type.inner,
+            min_fragment_intensity: scorer.min_fragment_intensity,
+            min_fragment_intensity_relative: scorer.min_fragment_intensity_relative,
+            mc_prune_prior: scorer.mc_prune_prior,
+            xcorr_bin_width: scorer.xcorr_bin_width,
+            match_neutral_losses: scorer.match_neutral_losses,
+            spectral_entropy: scorer.spectral_entropy.inner,
+            glyco_mode: scorer.glyco_mode.as_ref().map(|m| m.inner.clone()),
+            neutral_losses: scorer.neutral_losses.clone(),
+            with_coverage_stats: scorer.with_coverage_stats,
+            mc_prune_max_allowed: scorer.mc_prune_max_allowed,
+            ims_tolerance: scorer.ims_tolerance.as_ref().map(|(t, unit)| (*t, unit.inner)),
+        }
+    }
+}
+
+impl From<ScorerSettings> for PyScorer {
+    fn from(settings: ScorerSettings) -> Self {
+        PyScorer {
+            precursor_tolerance: PyTolerance {
+                inner: settings.precursor_tolerance,
+            },
+            fragment_tolerance: PyTolerance {
+                inner: settings.fragment_tolerance,
+            },
+            min_matched_peaks: settings.min_matched_peaks,
+            min_isotope_err: settings.min_isotope_err,
+            max_isotope_err: settings.max_isotope_err,
+            min_precursor_charge: settings.min_precursor_charge,
+            max_precursor_charge: settings.max_precursor_charge,
+            max_fragment_charge: settings.max_fragment_charge,
+            min_fragment_mass: settings.min_fragment_mass,
+            max_fragment_mass: settings.max_fragment_mass,
+            chimera: settings.chimera,
+            report_psms: settings.report_psms,
+            wide_window: settings.wide_window,
+            annotate_matches: settings.annotate_matches,
+            score_type: PyScoreType {
+                inner: settings.score_type,
+            },
+            min_fragment_intensity: settings.min_fragment_intensity,
+            min_fragment_intensity_relative: settings.min_fragment_intensity_relative,
+            mc_prune_prior: settings.mc_prune_prior,
+            xcorr_bin_width: settings.xcorr_bin_width,
+            match_neutral_losses: settings.match_neutral_losses,
+            spectral_entropy: PySpectralEntropyMode {
+                inner: settings.spectral_entropy,
+            },
+            glyco_mode: settings
+                .glyco_mode
+                .map(|inner| PyGlycopeptideScoringMode { inner }),
+            neutral_losses: settings.neutral_losses,
+            with_coverage_stats: settings.with_coverage_stats,
+            mc_prune_max_allowed: settings.mc_prune_max_allowed,
+            ims_tolerance: settings
+                .ims_tolerance
+                .map(|(t, inner)| (t, PyImsUnit { inner })),
+        }
+    }
+}
+
+#[pymethods]
+impl PyScorer {
+    #[new]
+    pub fn new(
+        precursor_tolerance: PyTolerance,
+        fragment_tolerance: PyTolerance,
+        min_matched_peaks: u16,
+        min_isotope_err: i8,
+        max_isotope_err: i8,
+        min_precursor_charge: u8,
+        max_precursor_charge: u8,
+        min_fragment_mass: f32,
+        max_fragment_mass: f32,
+        chimera: bool,
+        report_psms: usize,
+        wide_window: bool,
+        annotate_matches: bool,
+        max_fragment_charge: Option<u8>,
+        score_type: Option<PyScoreType>,
+        min_fragment_intensity: Option<f32>,
+        min_fragment_intensity_relative: Option<f32>,
+        mc_prune_prior: Option<f32>,
+        xcorr_bin_width: Option<f32>,
+        match_neutral_losses: Option<bool>,
+        spectral_entropy: Option<PySpectralEntropyMode>,
+        glyco_mode: Option<PyGlycopeptideScoringMode>,
+        neutral_losses: Option<Vec<f32>>,
+        with_coverage_stats: Option<bool>,
+        mc_prune_max_allowed: Option<u8>,
+        ims_tolerance: Option<(f32, PyImsUnit)>,
+    ) -> Self {
+        PyScorer {
+            precursor_tolerance,
+            fragment_tolerance,
+            min_matched_peaks,
+            min_isotope_err,
+            max_isotope_err,
+            min_precursor_charge,
+            max_precursor_charge,
+            max_fragment_charge,
+            min_fragment_mass,
+            max_fragment_mass,
+            chimera,
+            report_psms,
+            wide_window,
+            annotate_matches,
+            score_type: score_type.unwrap_or_default(),
+            min_fragment_intensity,
+            min_fragment_intensity_relative,
+            mc_prune_prior,
+            xcorr_bin_width,
+            match_neutral_losses: match_neutral_losses.unwrap_or(false),
+            spectral_entropy: spectral_entropy.unwrap_or_default(),
+            glyco_mode,
+            neutral_losses: neutral_losses.unwrap_or_default(),
+            with_coverage_stats: with_coverage_stats.unwrap_or(false),
+            mc_prune_max_allowed,
+            ims_tolerance,
+        }
+    }
+
+    pub fn to_json(&self) -> PyResult<String> {
+        serde_json::to_string_pretty(&ScorerSettings::from(self))
+            .map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    #[staticmethod]
+    pub fn from_json(json: &str) -> PyResult<PyScorer> {
+        let settings: ScorerSettings =
+            serde_json::from_str(json).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok(settings.into())
+    }
+
+    /// Score a spectrum, with `ms1_spectra` the PSMs are annotated with the isotope evidence of
+    /// their precursor in the preceding MS1 spectrum (see `score_with_ms1_isotope_evidence`)
+    #[pyo3(signature = (db, spectrum, ms1_spectra=None, ms1_isotope_window=None))]
+    pub fn score(
+        &self,
+        db: &PyIndexedDatabase,
+        spectrum: &PyProcessedSpectrum,
+        ms1_spectra: Option<Vec<PyProcessedSpectrum>>,
+        ms1_isotope_window: Option<f32>,
+    ) -> Vec<PyFeature> {
+        let ion_kinds = activation_ion_kinds(&db.inner, [spectrum]);
+        let databases = self.search_databases(db, &ion_kinds, None);
+        let mut features = self.score_searched(&databases, spectrum, &spectrum.inner);
+        if let Some(ms1) = ms1_spectra
+            .as_deref()
+            .and_then(|ms1| preceding_ms1(ms1, &spectrum.inner))
+        {
+            let window = ms1_isotope_window.unwrap_or(MS1_ISOTOPE_WINDOW);
+            for feature in features.iter_mut() {
+                annotate_ms1_isotopes(feature, ms1, window);
+            }
+        }
+        mark_silac_pairs(db, std::slice::from_mut(&mut features));
+        features
+    }
+
+    pub fn score_collection(
+        &self,
+        db: &PyIndexedDatabase,
+        spectra: Vec<PyProcessedSpectrum>,
+        num_threads: usize,
+    ) -> PyResult<Vec<Vec<PyFeature>>> {
+        let ion_kinds = activation_ion_kinds(&db.inner, &spectra);
+        let databases = self.search_databases(db, &ion_kinds, None);
+        // Configure the global thread pool to the desired number of threads
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .build()
+            .unwrap();
+
+        let mut result: Vec<Vec<PyFeature>> = pool.install(|| {
+            spectra
+                .par_iter()
+                .map(|spectrum| self.score_searched(&databases, spectrum, &spectrum.inner))
+                .collect()
+        });
+
+        mark_silac_pairs(db, &mut result);
+        Ok(result)
+    }
+
+    /// Score spectra in parallel, calling `progress_fn(completed, total)` every 1000 spectra and
+    /// once all spectra are scored. The search stops with an error once `cancellation_token` is
+    /// cancelled or the callback raises
+    pub fn score_collection_with_progress(
+        &self,
+        py: Python,
+        db: &PyIndexedDatabase,
+        spectra: Vec<PyProcessedSpectrum>,
+        num_threads: usize,
+        progress_fn: PyObject,
+        cancellation_token: Option<PyCancellationToken>,
+    ) -> PyResult<Vec<Vec<PyFeature>>> {
+        let cancelled = cancellation_token.map(|t| t.inner).unwrap_or_default();
+        let callback_error: Mutex<Option<PyErr>> = Mutex::new(None);
+        let total = spectra.len();
+
+        // workers only acquire the GIL to report progress
+        let (mut result, completed) = py.allow_threads(|| {
+            self.score_reporting_progress(db, &spectra, num_threads, &cancelled, |done, total| {
+                Python::with_gil(|py| {
+                    if let Err(e) = progress_fn.call1(py, (done, total)) {
+                        cancelled.store(true, Ordering::SeqCst);
+                        callback_error.lock().unwrap().get_or_insert(e);
+                    }
+                })
+            })
+        });
+
+        if let Some(e) = callback_error.into_inner().unwrap() {
+            return Err(e);
+        }
+        if cancelled.load(Ordering::SeqCst) {
+            return Err(PyRuntimeError::new_err(format!(
+                "search cancelled after {} of {} spectra",
+                completed, total
+            )));
+        }
+        mark_silac_pairs(db, &mut result);
+        Ok(result)
+    }
+
+    /// Open search: precursor filtering is replaced by a +/- `mass_window_da` window, so matches
+    /// may carry arbitrary mass shifts (see `PyFeature.delta_mass_open`)
+    pub fn score_open_search(
+        &self,
+        db: &PyIndexedDatabase,
+        spectrum: &PyProcessedSpectrum,
+        mass_window_da: f32,
+        num_threads: usize,
+    ) -> PyResult<Vec<PyFeature>> {
+        if mass_window_da <= 0.0 {
+            return Err(PyValueError::new_err("mass_window_da must be positive"));
+        }
+
+        let mut open = self.clone();
+        open.precursor_tolerance = PyTolerance {
+            inner: Tolerance::Da(-mass_window_da, mass_window_da),
+        };
+        // isotope errors and the isolation window are subsumed by the open window
+        open.min_isotope_err = 0;
+        open.max_isotope_err = 0;
+        open.wide_window = false;
+        let ion_kinds = activation_ion_kinds(&db.inner, [spectrum]);
+        let databases = open.search_databases(db, &ion_kinds, None);
+
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .build()
+            .unwrap();
+        let mut features = pool.install(|| open.score_searched(&databases, spectrum, &spectrum.inner));
+        features.retain(|f| f.inner.matched_peaks >= self.min_matched_peaks as u32);
+
+        Ok(features)
+    }
+
+    /// Score a spectrum once per candidate precursor charge and keep the PSMs of the best scoring
+    /// one (highest hyperscore). Candidates are the spectrum's `precursor_candidates` (see
+    /// `deconvolve_charge`) or, if there are none, every charge from min to max precursor charge
+    pub fn score_with_all_charge_states(
+        &self,
+        db: &PyIndexedDatabase,
+        spectrum: &PyProcessedSpectrum,
+    ) -> PyResult<Vec<PyFeature>> {
+        let Some(precursor) = spectrum.inner.precursors.first() else {
+            return Ok(Vec::new());
+        };
+        let candidates: Vec<(f32, u8)> = match spectrum.precursor_candidates.is_empty() {
+            false => spectrum
+                .precursor_candidates
+                .iter()
+                .map(|(mass, charge, _)| (mass / *charge as f32 + PROTON, *charge))
+                .collect(),
+            true => (self.min_precursor_charge..=self.max_precursor_charge)
+                .map(|charge| (precursor.mz, charge))
+                .collect(),
+        };
+
+        let ion_kinds = activation_ion_kinds(&db.inner, [spectrum]);
+        let databases = self.search_databases(db, &ion_kinds, None);
+        let best_hyperscore = |features: &[PyFeature]| {
+            features
+                .iter()
+                .map(|f| f.inner.hyperscore)
+                .fold(f64::NEG_INFINITY, f64::max)
+        };
+        Ok(candidates
+            .into_iter()
+            .map(|(mz, charge)| {
+                let mut charged = spectrum.inner.clone();
+                charged.precursors[0].mz = mz;
+                charged.precursors[0].charge = Some(charge);
+                self.score_searched(&databases, spectrum, &charged)
+            })
+            .max_by(|a, b| best_hyperscore(a).total_cmp(&best_hyperscore(b)))
+            .unwrap_or_default())
+    }
+
+    /// Score N-glycopeptides: for every glycan composition of `glycan_db_path` (one per line, e.g.
+    /// `HexNAc(4)Hex(5)Fuc(1)`) the precursor is lowered by the glycan mass and the peptide
+    /// backbone is matched, peptides without an N-X-S/T sequon are discarded. Matches are ranked by
+    /// discriminant score (hyperscore + oxonium score), the oxonium ions of `glyco_mode` or the
+    /// defaults are used. The isolation window is not shifted, so wide window search is unsupported
+    pub fn score_glycopeptides(
+        &self,
+        db: &PyIndexedDatabase,
+        spectra: Vec<PyProcessedSpectrum>,
+        glycan_db_path: &str,
+        num_threads: usize,
+    ) -> PyResult<Vec<Vec<PyGlycopeptideMatch>>> {
+        if self.wide_window {
+            return Err(PyValueError::new_err("glycopeptide scoring does not support wide_window"));
+        }
+        let glycans = read_glycan_database(glycan_db_path)?;
+
+        let mut glyco = self.clone();
+        if glyco.glyco_mode.is_none() {
+            glyco.glyco_mode = Some(PyGlycopeptideScoringMode::new(None, None)?);
+        }
+        let ion_kinds = activation_ion_kinds(&db.inner, &spectra);
+        let databases = glyco.search_databases(db, &ion_kinds, None);
+        let charges = self.min_precursor_charge..=self.max_precursor_charge;
+
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .build()
+            .unwrap();
+
+        let result = pool.install(|| {
+            spectra
+                .par_iter()
+                .map(|spectrum| {
+                    let mut matches: Vec<PyGlycopeptideMatch> = glycans
+                        .iter()
+                        .flat_map(|(glycan, mass)| {
+                            deglycosylated_spectra(&spectrum.inner, *mass, charges.clone())
+                                .into_iter()
+                                .flat_map(|shifted| glyco.score_searched(&databases, spectrum, &shifted))
+                                .filter(|f| has_sequon(&db.inner[f.inner.peptide_idx].sequence))
+                                .map(|feature| PyGlycopeptideMatch {
+                                    feature,
+                                    glycan: glycan.clone(),
+                                    glycan_mass: *mass,
+                                })
+                                .collect::<Vec<_>>()
+                        })
+                        .collect();
+                    matches.sort_by(|a, b| {
+                        b.feature
+                            .inner
+                            .discriminant_score
+                            .total_cmp(&a.feature.inner.discriminant_score)
+                    });
+                    matches.truncate(self.report_psms);
+                    for (rank, m) in matches.iter_mut().enumerate() {
+                        m.feature.inner.rank = rank as u32 + 1;
+                    }
+                    matches
+                })
+                .collect()
+        });
+
+        Ok(result)
+    }
+
+    pub fn score_chimera_fast(
+        &self,
+        db: &PyIndexedDatabase,
+        query: &PyProcessedSpectrum,
+    ) -> Vec<PyFeature> {
+        let ion_kinds = activation_ion_kinds(&db.inner, [query]);
+        let databases = self.search_databases(db, &ion_kinds, None);
+        let candidates = databases.for_spectrum(query);
+        let scorer = self.scorer(candidates.db());
+        let features = scorer.score_chimera_fast(&self.filter_fragment_peaks(&query.inner));
+        self.finalize(features, candidates.original_index())
+            .into_iter()
+            .map(|f| self.to_py_feature(f))
+            .collect()
+    }
+
+    pub fn score_standard(
+        &self,
+        db: &PyIndexedDatabase,
+        query: &PyProcessedSpectrum,
+    ) -> Vec<PyFeature> {
+        let ion_kinds = activation_ion_kinds(&db.inner, [query]);
+        let databases = self.search_databases(db, &ion_kinds, None);
+        let candidates = databases.for_spectrum(query);
+        let scorer = self.scorer(candidates.db());
+        let features = scorer.score_standard(&self.filter_fragment_peaks(&query.inner));
+        self.finalize(features, candidates.original_index())
+            .into_iter()
+            .map(|f| self.to_py_feature(f))
+            .collect()
+    }
+
+    #[getter]
+    pub fn precursor_tolerance(&self) -> PyTolerance {
+        self.precursor_tolerance.clone()
+    }
+
+    #[getter]
+    pub fn fragment_tolerance(&self) -> PyTolerance {
+        self.fragment_tolerance.clone()
+    }
+
+    #[getter]
+    pub fn min_matched_peaks(&self) -> u16 {
+        self.min_matched_peaks
+    }
+
+    #[getter]
+    pub fn min_isotope_err(&self) -> i8 {
+        self.min_isotope_err
+    }
+
+    #[getter]
+    pub fn max_isotope_err(&self) -> i8 {
+        self.max_isotope_err
+    }
+
+    #[getter]
+    pub fn min_precursor_charge(&self) -> u8 {
+        self.min_precursor_charge
+    }
+
+    #[getter]
+    pub fn max_precursor_charge(&self) -> u8 {
+        self.max_precursor_charge
+    }
+
+    #[getter]
+    pub fn max_fragment_charge(&self) -> Option<u8> {
+        self.max_fragment_charge
+    }
+
+    #[getter]
+    pub fn min_fragment_mass(&self) -> f32 {
+        self.min_fragment_mass
+    }
+
+    #[getter]
+    pub fn max_fragment_mass(&self) -> f32 {
+        self.max_fragment_mass
+    }
+
+    #[getter]
+    pub fn chimera(&self) -> bool {
+        self.chimera
+    }
+
+    #[getter]
+    pub fn report_psms(&self) -> usize {
+        self.report_psms
+    }
+
+    #[getter]
+    pub fn wide_window(&self) -> bool {
+        self.wide_window
+    }
+
+    #[getter]
+    pub fn annotate_matches(&self) -> bool {
+        self.annotate_matches
+    }
+
+    #[getter]
+    pub fn score_type(&self) -> PyScoreType {
+        self.score_type.clone()
+    }
+
+    #[getter]
+    pub fn min_fragment_intensity(&self) -> Option<f32> {
+        self.min_fragment_intensity
+    }
+
+    #[getter]
+    pub fn min_fragment_intensity_relative(&self) -> Option<f32> {
+        self.min_fragment_intensity_relative
+    }
+
+    #[getter]
+    pub fn mc_prune_prior(&self) -> Option<f32> {
+        self.mc_prune_prior
+    }
+
+    /// Maximum missed cleavages of peptides searched under missed cleavage pruning, unlimited if None
+    #[getter]
+    pub fn mc_prune_max_allowed(&self) -> Option<u8> {
+        self.mc_prune_max_allowed
+    }
+
+    /// Precursor ion mobility tolerance and its unit, candidates are restricted to peptides whose
+    /// predicted 1/K0 lies near the observed one (see `precompute_ims_predictions`)
+    #[getter]
+    pub fn ims_tolerance(&self) -> Option<(f32, PyImsUnit)> {
+        self.ims_tolerance.clone()
+    }
+
+    #[getter]
+    pub fn xcorr_bin_width(&self) -> f32 {
+        self.xcorr_bin_width.unwrap_or(XCORR_DEFAULT_BIN_WIDTH)
+    }
+
+    #[getter]
+    pub fn match_neutral_losses(&self) -> bool {
+        self.match_neutral_losses
+    }
+
+    /// Custom neutral losses (Da) of b and y ions, replacing the default losses
+    #[getter]
+    pub fn neutral_losses(&self) -> Vec<f32> {
+        self.neutral_losses.clone()
+    }
+
+    #[getter]
+    pub fn spectral_entropy(&self) -> PySpectralEntropyMode {
+        self.spectral_entropy.clone()
+    }
+
+    #[getter]
+    pub fn glyco_mode(&self) -> Option<PyGlycopeptideScoringMode> {
+        self.glyco_mode.clone()
+    }
+
+    #[getter]
+    pub fn with_coverage_stats(&self) -> bool {
+        self.with_coverage_stats
+    }
+}
+
+impl PyScorer {
+    fn scorer<'db>(&self, db: &'db IndexedDatabase) -> Scorer<'db> {
+        Scorer {
+            db,
+            precursor_tol: self.precursor_tolerance.inner.clone(),
+            fragment_tol: self.fragment_tolerance.inner.clone(),
+            min_matched_peaks: self.min_matched_peaks,
+            min_isotope_err: self.min_isotope_err,
+            max_isotope_err: self.max_isotope_err,
+            min_precursor_charge: self.min_precursor_charge,
+            max_precursor_charge: self.max_precursor_charge,
+            max_fragment_charge: self.max_fragment_charge,
+            min_fragment_mass: self.min_fragment_mass,
+            max_fragment_mass: self.max_fragment_mass,
+            chimera: self.chimera,
+            report_psms: self.report_psms,
+            wide_window: self.wide_window,
+            annotate_matches: self.annotate_matches,
+        }
+    }
+
+    /// Drop fragment peaks below the configured intensity thresholds, see `filter_fragment_peaks`
+    fn filter_fragment_peaks<'a>(&self, spectrum: &'a ProcessedSpectrum) -> Cow<'a, ProcessedSpectrum> {
+        filter_fragment_peaks(
+            spectrum,
+            self.min_fragment_intensity,
+            self.min_fragment_intensity_relative,
+        )
+    }
+
+    /// Neutral loss ions matched: the custom losses of b and y ions if given, the defaults otherwise
+    fn neutral_loss_table(&self) -> Vec<(Kind, f32, bool)> {
+        match self.neutral_losses.is_empty() {
+            true => NEUTRAL_LOSSES.to_vec(),
+            false => self
+                .neutral_losses
+                .iter()
+                .flat_map(|loss| [(Kind::B, *loss, false), (Kind::Y, *loss, false)])
+                .collect(),
+        }
+    }
+
+    /// Score a spectrum with the configured fragment filter and score type. If `scorer` searches a
+    /// candidate sub-database, `original_index` maps its peptide indices back to the full database
+    fn score_spectrum(
+        &self,
+        scorer: &Scorer,
+        original_index: Option<&[u32]>,
+        spectrum: &ProcessedSpectrum,
+    ) -> Vec<PyFeature> {
+        let filtered = self.filter_fragment_peaks(spectrum);
+        let features = scorer.score(&filtered);
+        let mut features: Vec<PyFeature> = self
+            .finalize(features, None)
+            .into_iter()
+            .map(|f| self.to_py_feature(f))
+            .collect();
+        let entropy_mode = self.spectral_entropy.inner;
+        if entropy_mode != SpectralEntropyMode::Off {
+            for feature in features.iter_mut() {
+                annotate_spectral_entropy(scorer.db, &filtered, feature, self.fragment_tolerance.inner);
+                if entropy_mode == SpectralEntropyMode::Rescore {
+                    // delta entropy is in [-1, 1], the hyperscore is at most doubled or zeroed
+                    let weight = 1.0 + feature.delta_spectral_entropy as f64;
+                    feature.inner.discriminant_score = (feature.inner.hyperscore * weight) as f32;
+                }
+            }
+        }
+        if self.match_neutral_losses || !self.neutral_losses.is_empty() {
+            let losses = self.neutral_loss_table();
+            for feature in features.iter_mut() {
+                match_neutral_losses(scorer.db, &filtered, feature, self.fragment_tolerance.inner, &losses);
+            }
+        }
+        if let Some(mode) = &self.glyco_mode {
+            // oxonium ions are looked up in the unfiltered spectrum, they are often of low intensity
+            let (matched, score) = oxonium_evidence(spectrum, &mode.inner.oxonium_mz, self.fragment_tolerance.inner);
+            for feature in features.iter_mut() {
+                let base = match entropy_mode {
+                    SpectralEntropyMode::Rescore => feature.inner.discriminant_score,
+                    _ => feature.inner.hyperscore as f32,
+                };
+                feature.has_oxonium_evidence = matched >= mode.inner.min_oxonium_ions;
+                feature.oxonium_score = score;
+                feature.inner.discriminant_score = base + score;
+            }
+        }
+        if self.score_type.inner == ScoreType::XCorr {
+            let bin_width = self.xcorr_bin_width.unwrap_or(XCORR_DEFAULT_BIN_WIDTH);
+            rank_by_xcorr(scorer.db, &filtered, &mut features, bin_width);
+        }
+        if let Some(original_index) = original_index {
+            for feature in features.iter_mut() {
+                restore_peptide_idx(&mut feature.inner, original_index);
+            }
+        }
+        features
+    }
+
+    /// Wrap a scored feature
+    fn to_py_feature(&self, feature: Feature) -> PyFeature {
+        let unexplained_intensity_pct = 100.0 - feature.matched_intensity_pct.min(100.0);
+        let coverage_stats = self
+            .with_coverage_stats
+            .then(|| PyFragmentCoverageStats::from_feature(&feature));
+        let (intensity_weighted_ppm, max_fragment_ppm) = fragment_ppm_summary(feature.fragments.as_ref());
+        PyFeature {
+            inner: feature,
+            isotope_annotation_score: f32::NAN,
+            xcorr: None,
+            delta_xcorr: None,
+            localization_scores: None,
+            best_localization_site: None,
+            neutral_losses: Vec::new(),
+            neutral_loss_intensity_pct: 0.0,
+            spectral_entropy: 0.0,
+            delta_spectral_entropy: 0.0,
+            ms1_isotope_score: 0.0,
+            ms1_intensity_ratio: 0.0,
+            has_oxonium_evidence: false,
+            oxonium_score: 0.0,
+            protein_q_razor: 1.0,
+            silac_pair_idx: None,
+            unexplained_intensity_pct,
+            coverage_stats,
+            collision_energy_calibrated: None,
+            precursor_purity: None,
+            tag_match_score: None,
+            intensity_similarity: None,
+            chimera_score: None,
+            peptide_sequence: None,
+            re_score: None,
+            ims: None,
+            inverse_ion_mobility_predicted: None,
+            delta_ims_model: None,
+            intensity_weighted_ppm,
+            max_fragment_ppm,
+        }
+    }
+
+    /// Apply the score type to the features of a spectrum. Under target-decoy competition, only
+    /// the best scoring peptide (target or decoy) is kept. With `original_index`, peptide indices
+    /// of a candidate sub-database are mapped back to the full database
+    fn finalize(&self, mut features: Vec<Feature>, original_index: Option<&[u32]>) -> Vec<Feature> {
+        if self.score_type.inner == ScoreType::Tdc {
+            features = competition_winners(features, |f| f);
+        }
+        if let Some(original_index) = original_index {
+            for feature in features.iter_mut() {
+                restore_peptide_idx(feature, original_index);
+            }
+        }
+        features
+    }
+
+    /// Describes the candidates a search runs against: the database itself or, with missed
+    /// cleavage pruning or a peptide length stratum, the sub-database of peptides passing them,
+    /// indexed with `ion_kinds` if given. Pruning before scoring keeps ranks and delta scores
+    /// consistent and lets the best allowed peptide take rank 1
+    fn candidate_key(&self, ion_kinds: Option<&[Kind]>, lengths: Option<&RangeInclusive<usize>>) -> DerivedKey {
+        DerivedKey {
+            ion_kinds: ion_kinds.map(<[Kind]>::to_vec),
+            mc_prune: self
+                .mc_prune_prior
+                .map(|prior| (self.mc_prune_max_allowed.unwrap_or(u8::MAX), prior)),
+            lengths: lengths.map(|l| (*l.start(), *l.end())),
+            ims: None,
+        }
+    }
+
+    /// Score spectra in parallel, calling `report(completed, total)` every `PROGRESS_INTERVAL`
+    /// spectra and once all spectra are scored. Spectra are skipped once `cancelled` is set, the
+    /// number of scored spectra is returned with the PSMs
+    fn score_reporting_progress(
+        &self,
+        db: &PyIndexedDatabase,
+        spectra: &[PyProcessedSpectrum],
+        num_threads: usize,
+        cancelled: &AtomicBool,
+        report: impl Fn(usize, usize) + Sync,
+    ) -> (Vec<Vec<PyFeature>>, usize) {
+        let completed = AtomicUsize::new(0);
+        let total = spectra.len();
+        let ion_kinds = activation_ion_kinds(&db.inner, spectra);
+        let databases = self.search_databases(db, &ion_kinds, None);
+
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .build()
+            .unwrap();
+        let result = pool.install(|| {
+            spectra
+                .par_iter()
+                .with_max_len(PROGRESS_INTERVAL / 10)
+                .map(|spectrum| {
+                    if cancelled.load(Ordering::Relaxed) {
+                        return Vec::new();
+                    }
+                    let features = self.score_searched(&databases, spectrum, &spectrum.inner);
+                    let done = completed.fetch_add(1, Ordering::SeqCst) + 1;
+                    if done % PROGRESS_INTERVAL == 0 || done == total {
+                        report(done, total);
+                    }
+                    features
+                })
+                .collect()
+        });
+        (result, completed.into_inner())
+    }
+
+    /// Candidates of `db` and of its copies indexed with the given ion kinds of electron
+    /// activated spectra (see `activation_ion_kinds`). Derived databases are built once by `db`
+    /// and reused by later searches
+    fn search_databases<'db>(
+        &self,
+        db: &'db PyIndexedDatabase,
+        ion_kinds: &[Vec<Kind>],
+        lengths: Option<&RangeInclusive<usize>>,
+    ) -> SearchDatabases<'db> {
+        SearchDatabases {
+            db,
+            default: self.candidate_key(None, lengths),
+            by_ion_kinds: ion_kinds
+                .iter()
+                .map(|kinds| (kinds.clone(), self.candidate_key(Some(kinds.as_slice()), lengths)))
+                .collect(),
+            ims_tolerance: self.ims_tolerance.as_ref().map(|(t, unit)| (*t, unit.inner)),
+        }
+    }
+
+    /// Score `query`, the spectrum itself or a copy of it (e.g. with another precursor charge),
+    /// against the candidates matching the activation of `spectrum`
+    fn score_searched(
+        &self,
+        databases: &SearchDatabases,
+        spectrum: &PyProcessedSpectrum,
+        query: &ProcessedSpectrum,
+    ) -> Vec<PyFeature> {
+        let candidates = databases.for_spectrum(spectrum);
+        let mut features = self.score_spectrum(&self.scorer(candidates.db()), candidates.original_index(), query);
+        for feature in features.iter_mut() {
+            feature.ims = spectrum.precursor_ims;
+        }
+        features
+    }
+}
+
+/// Width (1/K0) of the ion mobility bands candidate databases are derived for, the tolerance
+/// itself for an absolute tolerance and the tolerance at 1/K0 = 1 for a relative one
+fn ims_band_width(tolerance: f32, unit: ImsUnit) -> f32 {
+    match unit {
+        ImsUnit::AbsoluteVm => tolerance,
+        ImsUnit::RelativePct => tolerance / 100.0,
+    }
+}
+
+/// Ion mobility bands of the candidates of a spectrum: those covering its observed precursor 1/K0
+/// plus / minus the tolerance, at its precursor charge. None without an observed ion mobility, a
+/// precursor charge or a positive tolerance
+fn spectrum_ims_bands(spectrum: &PyProcessedSpectrum, tolerance: f32, unit: ImsUnit) -> Option<ImsBands> {
+    let observed = spectrum.precursor_ims?;
+    let charge = spectrum.inner.precursors.first()?.charge?;
+    let width = ims_band_width(tolerance, unit);
+    let tolerance = match unit {
+        ImsUnit::AbsoluteVm => tolerance,
+        ImsUnit::RelativePct => observed * tolerance / 100.0,
+    };
+    (width > 0.0).then(|| ImsBands::covering(charge, observed, tolerance, width))
+}
+
+/// Candidates of a search per fragmentation: those of the database for collisional activation
+/// and those of its copies indexed with the ion kinds of electron based activation. With an ion
+/// mobility tolerance and predictions cached by the database, the candidates of each spectrum
+/// are further restricted to the ion mobility bands around its precursor. One sub-database is
+/// built per band range and charge and kept by the database, trading memory for the smaller
+/// candidate sets (see `PyIndexedDatabase::clear_derived_cache`)
+struct SearchDatabases<'db> {
+    db: &'db PyIndexedDatabase,
+    default: DerivedKey,
+    by_ion_kinds: Vec<(Vec<Kind>, DerivedKey)>,
+    ims_tolerance: Option<(f32, ImsUnit)>,
+}
+
+impl<'db> SearchDatabases<'db> {
+    fn for_spectrum(&self, spectrum: &PyProcessedSpectrum) -> Candidates<'db> {
+        let key = spectrum
+            .activation_ion_kinds()
+            .and_then(|kinds| self.by_ion_kinds.iter().find(|(k, _)| same_ion_kinds(k, &kinds)))
+            .map_or(&self.default, |(_, key)| key);
+        let ims = match (self.ims_tolerance, &self.db.ims_predictions) {
+            (Some((tolerance, unit)), Some(_)) => spectrum_ims_bands(spectrum, tolerance, unit),
+            _ => None,
+        };
+        match (ims, key == &DerivedKey::default()) {
+            (None, true) => Candidates::Full(&self.db.inner),
+            (None, false) => Candidates::Derived(self.db.derived(key)),
+            (Some(ims), _) => Candidates::Derived(self.db.derived(&DerivedKey {
+                ims: Some(ims),
+                ..key.clone()
+            })),
+        }
+    }
+}
+
+/// Candidate peptides of a search: the full database or a database derived from it (see
+/// `PyIndexedDatabase::derived`), for subsets together with the full database index of each of
+/// their peptides
+enum Candidates<'db> {
+    Full(&'db IndexedDatabase),
+    Derived(Arc<DerivedDatabase>),
+}
+
+impl Candidates<'_> {
+    fn db(&self) -> &IndexedDatabase {
+        match self {
+            Candidates::Full(db) => db,
+            Candidates::Derived(derived) => &derived.db,
+        }
+    }
+
+    fn original_index(&self) -> Option<&[u32]> {
+        match self {
+            Candidates::Full(_) => None,
+            Candidates::Derived(derived) => derived.original_index.as_deref(),
+        }
+    }
+}
+
+fn restore_peptide_idx(feature: &mut Feature, original_index: &[u32]) {
+    feature.peptide_idx = PeptideIx(original_index[feature.peptide_idx.0 as usize]);
+}
+
+#[derive(Serialize, Deserialize)]
+struct SearchSettings {
+    scorer: ScorerSettings,
+    database: ParameterSettings,
+}
+
+/// Complete search configuration (scoring and database parameters) for reproducible searches
+#[pyclass]
+#[derive(Clone)]
+pub struct PySearchConfiguration {
+    pub scorer: PyScorer,
+    pub parameters: PyParameters,
+}
+
+#[pymethods]
+impl PySearchConfiguration {
+    #[new]
+    pub fn new(scorer: PyScorer, parameters: PyParameters) -> Self {
+        PySearchConfiguration { scorer, parameters }
+    }
+
+    #[getter]
+    pub fn scorer(&self) -> PyScorer {
+        self.scorer.clone()
+    }
+
+    #[getter]
+    pub fn parameters(&self) -> PyParameters {
+        self.parameters.clone()
+    }
+
+    pub fn to_json(&self) -> PyResult<String> {
+        serde_json::to_string_pretty(&self.settings())
+            .map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    #[staticmethod]
+    pub fn from_json(json: &str) -> PyResult<Self> {
+        let settings: SearchSettings =
+            serde_json::from_str(json).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok(PySearchConfiguration {
+            scorer: settings.scorer.into(),
+            parameters: settings.database.try_into()?,
+        })
+    }
+}
+
+impl PySearchConfiguration {
+    fn settings(&self) -> SearchSettings {
+        SearchSettings {
+            scorer: ScorerSettings::from(&self.scorer),
+            database: ParameterSettings::from(&self.parameters),
+        }
+    }
+}
+
+/// SHA-256 hex digest of the serialised search configuration
+#[pyfunction]
+pub fn search_hash(config: &PySearchConfiguration) -> PyResult<String> {
+    let json = serde_json::to_string(&config.settings())
+        .map_err(|e| PyValueError::new_err(e.to_string()))?;
+    let mut hasher = Sha256::new();
+    hasher.update(json.as_bytes());
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Histogram of precursor mass shifts (expmass - calcmass, in Da) of confident target PSMs,
+/// returned as (bin_centre, count) pairs. Without `fdr_cutoff` all target PSMs are counted,
+/// e.g. the unvalidated results of an open search
+#[pyfunction]
+pub fn delta_mass_histogram(
+    psms: Vec<PyFeature>,
+    fdr_cutoff: Option<f32>,
+    bin_width_da: f32,
+    range_da: (f32, f32),
+) -> PyResult<Vec<(f32, u32)>> {
+    let (lo, hi) = range_da;
+    if bin_width_da <= 0.0 || hi <= lo {
+        return Err(PyValueError::new_err(
+            "bin_width_da must be positive and range_da must be increasing",
+        ));
+    }
+
+    let num_bins = ((hi - lo) / bin_width_da).ceil() as usize;
+    let mut counts = vec![0u32; num_bins];
+
+    for psm in psms
+        .iter()
+        .filter(|p| p.inner.label == 1 && fdr_cutoff.map_or(true, |q| p.inner.spectrum_q <= q))
+    {
+        let shift = psm.inner.expmass - psm.inner.calcmass;
+        if shift < lo || shift >= hi {
+            continue;
+        }
+        let bin = (((shift - lo) / bin_width_da) as usize).min(num_bins - 1);
+        counts[bin] += 1;
+    }
+
+    Ok(counts
+        .into_iter()
+        .enumerate()
+        .map(|(i, c)| (lo + (i as f32 + 0.5) * bin_width_da, c))
+        .collect())
+}
+
+/// Pick local maxima of a delta mass histogram whose topographic prominence exceeds `min_prominence`
+#[pyfunction]
+pub fn delta_mass_peaks(histogram: Vec<(f32, u32)>, min_prominence: u32) -> Vec<f32> {
+    let counts: Vec<u32> = histogram.iter().map(|(_, c)| *c).collect();
+    let mut peaks = Vec::new();
+
+    for i in 0..counts.len() {
+        let c = counts[i];
+        let left = if i > 0 { counts[i - 1] } else { 0 };
+        let right = if i + 1 < counts.len() { counts[i + 1] } else { 0 };
+        // plateaus are reported once, at their left-most bin
+        if c == 0 || c <= left || c < right {
+            continue;
+        }
+
+        // lowest point on each side before reaching a higher bin (or the histogram edge)
+        let mut left_min = c;
+        for &v in counts[..i].iter().rev() {
+            if v > c {
+                break;
+            }
+            left_min = left_min.min(v);
+        }
+        let mut right_min = c;
+        for &v in counts[i + 1..].iter() {
+            if v > c {
+                break;
+            }
+            right_min = right_min.min(v);
+        }
+        if i == 0 {
+            left_min = 0;
+        }
+        if i + 1 == counts.len() {
+            right_min = 0;
+        }
+
+        let prominence = c - left_min.max(right_min);
+        if prominence >= min_prominence {
+            peaks.push(histogram[i].0);
+        }
+    }
+
+    peaks
+}
+
+/// Set the `re_score` of each PSM to `score_fn` of its feature values, `values[i]` being those of
+/// `psms[i]` in the order of `FEATURE_NAMES`
+fn apply_re_scores(
+    psms: &mut [PyFeature],
+    values: Vec<Vec<f64>>,
+    mut score_fn: impl FnMut(Vec<f64>) -> PyResult<f64>,
+) -> PyResult<()> {
+    for (psm, values) in psms.iter_mut().zip(values) {
+        psm.re_score = Some(score_fn(values)?);
+    }
+    Ok(())
+}
+
+/// Re-score PSMs with a custom Python function `score_fn(feature_names, feature_values) -> float`,
+/// the result is stored as `re_score` of each PSM
+#[pyfunction]
+pub fn rescore_with_function(
+    py: Python,
+    psms: Vec<PyFeature>,
+    score_fn: PyObject,
+) -> PyResult<Vec<PyFeature>> {
+    let mut psms = psms;
+
+    // feature extraction does not need the GIL, calling back into python does
+    let values: Vec<Vec<f64>> = py.allow_threads(|| {
+        psms.par_iter()
+            .map(feature_values)
+            .collect()
+    });
+
+    let names = PyList::new(py, FEATURE_NAMES);
+    apply_re_scores(&mut psms, values, |values| score_fn.call1(py, (names, values))?.extract(py))?;
+
+    Ok(psms)
+}
+
+/// Score spectra separately against length-stratified sub-databases, e.g. to keep short HLA
+/// ligands from sharing a score distribution with tryptic peptides. Strata are inclusive
+/// (min_len, max_len) ranges, for each spectrum the results of the stratum with the highest
+/// scoring top hit are returned, with peptide indices referring to the full database
+#[pyfunction]
+pub fn score_by_length_stratum(
+    scorer: &PyScorer,
+    db: &PyIndexedDatabase,
+    spectra: Vec<PyProcessedSpectrum>,
+    strata: Vec<(u8, u8)>,
+) -> Vec<Vec<PyFeature>> {
+    let ion_kinds = activation_ion_kinds(&db.inner, &spectra);
+    let sub_databases: Vec<SearchDatabases> = strata
+        .iter()
+        .map(|&(min_len, max_len)| {
+            scorer.search_databases(db, &ion_kinds, Some(&(min_len as usize..=max_len as usize)))
+        })
+        .collect();
+
+    spectra
+        .par_iter()
+        .map(|spectrum| {
+            sub_databases
+                .iter()
+                .map(|databases| scorer.score_searched(databases, spectrum, &spectrum.inner))
+                .max_by(|a, b| {
+                    let best = |f: &Vec<PyFeature>| f.first().map_or(f64::MIN, |f| f.inner.hyperscore);
+                    best(a).total_cmp(&best(b))
+                })
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+/// Fraction of anchors used for each local fit of the loess calibration
+const LOESS_SPAN: f64 = 0.3;
+/// Number of grid points the loess calibration is evaluated at, predictions interpolate between them
+const LOESS_GRID_POINTS: usize = 100;
+
+#[derive(Clone, Debug)]
+enum CalibrationModel {
+    Polynomial(Vec<f64>),
+    Loess(Vec<(f64, f64)>),
+}
+
+impl CalibrationModel {
+    fn predict(&self, x: f64) -> f64 {
+        match self {
+            CalibrationModel::Polynomial(coefficients) => {
+                coefficients.iter().rev().fold(0.0, |acc, c| acc * x + c)
+            }
+            CalibrationModel::Loess(grid) => {
+                let i = grid.partition_point(|(gx, _)| *gx < x);
+                if i == 0 {
+                    grid[0].1
+                } else if i == grid.len() {
+                    grid[grid.len() - 1].1
+                } else {
+                    let ((x0, y0), (x1, y1)) = (grid[i - 1], grid[i]);
+                    if x1 > x0 {
+                        y0 + (y1 - y0) * (x - x0) / (x1 - x0)
+                    } else {
+                        y0
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Solve a small linear system by Gaussian elimination with partial pivoting
+pub(crate) fn solve_linear(mut a: Vec<Vec<f64>>, mut b: Vec<f64>) -> Option<Vec<f64>> {
+    let n = b.len();
+    for col in 0..n {
+        let pivot = (col..n).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+        if a[pivot][col].abs() < 1e-12 {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+        for row in col + 1..n {
+            let factor = a[row][col] / a[col][col];
+            for k in col..n {
+                a[row][k] -= factor * a[col][k];
+            }
+            b[row] -= factor * b[col];
+        }
+    }
+    let mut x = vec![0.0; n];
+    for row in (0..n).rev() {
+        let sum: f64 = (row + 1..n).map(|k| a[row][k] * x[k]).sum();
+        x[row] = (b[row] - sum) / a[row][row];
+    }
+    Some(x)
+}
+
+/// Weighted least squares polynomial fit, coefficients are returned in increasing order of degree
+pub(crate) fn weighted_polyfit(points: &[(f64, f64)], weights: &[f64], degree: usize) -> Option<Vec<f64>> {
+    // fit on standardized x for numerical stability, then expand back to the original scale
+    let total: f64 = weights.iter().sum();
+    let mean = points.iter().zip(weights).map(|((x, _), w)| x * w).sum::<f64>() / total;
+    let scale = (points.iter().zip(weights).map(|((x, _), w)| w * (x - mean).powi(2)).sum::<f64>() / total)
+        .sqrt()
+        .max(1e-9);
+
+    let terms = degree + 1;
+    let mut a = vec![vec![0.0; terms]; terms];
+    let mut b = vec![0.0; terms];
+    for ((x, y), w) in points.iter().zip(weights) {
+        let t = (x - mean) / scale;
+        let powers: Vec<f64> = (0..terms).map(|k| t.powi(k as i32)).collect();
+        for i in 0..terms {
+            b[i] += w * powers[i] * y;
+            for j in 0..terms {
+                a[i][j] += w * powers[i] * powers[j];
+            }
+        }
+    }
+    let standardized = solve_linear(a, b)?;
+
+    // sum_k c_k ((x - mean) / scale)^k expanded in powers of x
+    let mut coefficients = vec![0.0; terms];
+    for (k, c) in standardized.iter().enumerate() {
+        let c = c / scale.powi(k as i32);
+        let mut binomial = 1.0;
+        for j in 0..=k {
+            coefficients[j] += c * binomial * (-mean).powi((k - j) as i32);
+            binomial = binomial * (k - j) as f64 / (j + 1) as f64;
+        }
+    }
+    Some(coefficients)
+}
+
+/// Local linear regression with tricube weights over the nearest `LOESS_SPAN` fraction of anchors,
+/// evaluated on an evenly spaced grid across the anchor range
+fn loess_grid(points: &[(f64, f64)]) -> Option<Vec<(f64, f64)>> {
+    let k = ((points.len() as f64 * LOESS_SPAN).ceil() as usize).clamp(3, points.len());
+    let lo = points.iter().map(|p| p.0).fold(f64::INFINITY, f64::min);
+    let hi = points.iter().map(|p| p.0).fold(f64::NEG_INFINITY, f64::max);
+
+    (0..LOESS_GRID_POINTS)
+        .map(|i| {
+            let x = lo + (hi - lo) * i as f64 / (LOESS_GRID_POINTS - 1) as f64;
+            let mut distances: Vec<f64> = points.iter().map(|p| (p.0 - x).abs()).collect();
+            distances.select_nth_unstable_by(k - 1, |a, b| a.total_cmp(b));
+            let max_distance = distances[k - 1].max(1e-9) * 1.0001;
+
+            let weights: Vec<f64> = points
+                .iter()
+                .map(|p| {
+                    let d = (p.0 - x).abs() / max_distance;
+                    if d < 1.0 {
+                        (1.0 - d.powi(3)).powi(3)
+                    } else {
+                        0.0
+                    }
+                })
+                .collect();
+
+            let local = weighted_polyfit(points, &weights, 1)
+                .or_else(|| weighted_polyfit(points, &weights, 0))?;
+            Some((x, CalibrationModel::Polynomial(local).predict(x)))
+        })
+        .collect()
+}
+
+/// Signed precursor mass error in ppm, corrected for the selected isotope
+fn signed_ppm_error(feature: &Feature) -> f64 {
+    let calcmass = feature.calcmass as f64;
+    (feature.expmass as f64 - feature.isotope_error as f64 - calcmass) / calcmass * 1e6
+}
+
+#[pyclass]
+#[derive(Clone, Debug)]
+pub struct PyMassCalibration {
+    model_name: String,
+    model: CalibrationModel,
+    residual_rms: f64,
+    num_anchors: usize,
+}
+
+#[pymethods]
+impl PyMassCalibration {
+    #[getter]
+    pub fn model(&self) -> String {
+        self.model_name.clone()
+    }
+
+    /// Polynomial coefficients (increasing degree) of the ppm error as function of calcmass,
+    /// empty for the loess model
+    #[getter]
+    pub fn coefficients(&self) -> Vec<f64> {
+        match &self.model {
+            CalibrationModel::Polynomial(coefficients) => coefficients.clone(),
+            CalibrationModel::Loess(_) => Vec::new(),
+        }
+    }
+
+    /// Root mean square of the ppm error residuals of the anchor PSMs after calibration
+    #[getter]
+    pub fn residual_rms(&self) -> f64 {
+        self.residual_rms
+    }
+
+    #[getter]
+    pub fn num_anchors(&self) -> usize {
+        self.num_anchors
+    }
+
+    /// Predicted systematic precursor mass error (ppm) at a calculated mass
+    pub fn predict(&self, calcmass: f32) -> f32 {
+        self.model.predict(calcmass as f64) as f32
+    }
+}
+
+/// Recalibrate precursor masses using high-confidence identifications (target PSMs with
+/// spectrum_q < fdr_cutoff) as anchors. The systematic ppm error is modelled as function of
+/// calcmass ("linear", "polynomial_2" or "loess"), and expmass and delta_mass (absolute ppm)
+/// of all PSMs are corrected in place
+#[pyfunction]
+pub fn recalibrate_masses(
+    mut psms: Vec<PyRefMut<PyFeature>>,
+    fdr_cutoff: f32,
+    recalib_model: &str,
+) -> PyResult<PyMassCalibration> {
+    recalibrate_features(psms.iter_mut().map(|p| &mut p.inner).collect(), fdr_cutoff, recalib_model)
+}
+
+fn recalibrate_features(
+    mut features: Vec<&mut Feature>,
+    fdr_cutoff: f32,
+    recalib_model: &str,
+) -> PyResult<PyMassCalibration> {
+    let anchors: Vec<(f64, f64)> = features
+        .iter()
+        .filter(|f| f.label == 1 && f.spectrum_q < fdr_cutoff && f.calcmass > 0.0)
+        .map(|f| (f.calcmass as f64, signed_ppm_error(f)))
+        .collect();
+
+    let min_anchors = match recalib_model {
+        "linear" => 2,
+        "polynomial_2" | "loess" => 3,
+        _ => {
+            return Err(PyValueError::new_err(format!(
+                "Invalid recalibration model {}, allowed values are: linear, polynomial_2, loess",
+                recalib_model
+            )))
+        }
+    };
+    if anchors.len() < min_anchors {
+        return Err(PyValueError::new_err(format!(
+            "Recalibration requires at least {} PSMs below the FDR cutoff, found {}",
+            min_anchors,
+            anchors.len()
+        )));
+    }
+
+    let unit_weights = vec![1.0; anchors.len()];
+    let model = match recalib_model {
+        "linear" => weighted_polyfit(&anchors, &unit_weights, 1).map(CalibrationModel::Polynomial),
+        "polynomial_2" => weighted_polyfit(&anchors, &unit_weights, 2).map(CalibrationModel::Polynomial),
+        _ => loess_grid(&anchors).map(CalibrationModel::Loess),
+    }
+    .ok_or_else(|| PyValueError::new_err("Recalibration model could not be fitted, anchor masses are degenerate"))?;
+
+    let residual_rms = (anchors
+        .iter()
+        .map(|(x, y)| (y - model.predict(*x)).powi(2))
+        .sum::<f64>()
+        / anchors.len() as f64)
+        .sqrt();
+
+    for feature in features.iter_mut() {
+        if feature.calcmass <= 0.0 {
+            continue;
+        }
+        let shift = model.predict(feature.calcmass as f64) * 1e-6 * feature.calcmass as f64;
+        feature.expmass = (feature.expmass as f64 - shift) as f32;
+        feature.delta_mass = signed_ppm_error(feature).abs() as f32;
+    }
+
+    Ok(PyMassCalibration {
+        model_name: recalib_model.to_string(),
+        model,
+        residual_rms,
+        num_anchors: anchors.len(),
+    })
+}
+
+/// z-value of the 95% confidence interval of a mass error offset
+const OFFSET_CI_Z: f64 = 1.96;
+
+#[pyclass]
+#[derive(Clone, Debug)]
+pub struct PyMassErrorOffset {
+    #[pyo3(get)]
+    pub offset_ppm: f32,
+    #[pyo3(get)]
+    pub ci_lower: f32,
+    #[pyo3(get)]
+    pub ci_upper: f32,
+    /// Number of mass errors remaining after outlier removal
+    #[pyo3(get)]
+    pub num_observations: usize,
+}
+
+/// Linearly interpolated quantile of sorted values
+fn sorted_quantile(sorted: &[f64], quantile: f64) -> f64 {
+    let position = quantile * (sorted.len() - 1) as f64;
+    let (lo, hi) = (position.floor() as usize, position.ceil() as usize);
+    sorted[lo] + (sorted[hi] - sorted[lo]) * (position - lo as f64)
+}
+
+/// Quantile of signed ppm errors after removing outliers outside 1.5 IQR of the quartiles, the
+/// distribution-free confidence interval is given by the binomial order statistics around it
+fn mass_error_offset(errors: Vec<f64>, quantile: f32) -> PyResult<PyMassErrorOffset> {
+    if !(0.0..=1.0).contains(&quantile) {
+        return Err(PyValueError::new_err(format!(
+            "quantile must be within [0, 1], got {}",
+            quantile
+        )));
+    }
+    let mut errors: Vec<f64> = errors.into_iter().filter(|e| e.is_finite()).collect();
+    if errors.is_empty() {
+        return Err(PyValueError::new_err("no mass errors available for calibration"));
+    }
+    errors.sort_by(|a, b| a.total_cmp(b));
+
+    let (q1, q3) = (sorted_quantile(&errors, 0.25), sorted_quantile(&errors, 0.75));
+    let fence = 1.5 * (q3 - q1);
+    errors.retain(|e| *e >= q1 - fence && *e <= q3 + fence);
+
+    let n = errors.len() as f64;
+    let q = quantile as f64;
+    let spread = OFFSET_CI_Z * (n * q * (1.0 - q)).sqrt();
+    let rank = |r: f64| (r.clamp(1.0, n) - 1.0) as usize;
+
+    Ok(PyMassErrorOffset {
+        offset_ppm: sorted_quantile(&errors, q) as f32,
+        ci_lower: errors[rank((n * q - spread).floor())] as f32,
+        ci_upper: errors[rank((n * q + spread).ceil())] as f32,
+        num_observations: errors.len(),
+    })
+}
+
+/// Estimate the systematic precursor mass error (ppm) as `quantile` of the signed precursor
+/// errors of target PSMs with spectrum_q <= fdr_cutoff, after IQR outlier removal
+#[pyfunction]
+pub fn calibrate_mass_error(psms: Vec<PyFeature>, quantile: f32, fdr_cutoff: Option<f32>) -> PyResult<PyMassErrorOffset> {
+    let fdr_cutoff = fdr_cutoff.unwrap_or(0.01);
+    let errors = psms
+        .iter()
+        .filter(|p| p.inner.label == 1 && p.inner.spectrum_q <= fdr_cutoff && p.inner.calcmass > 0.0)
+        .map(|p| signed_ppm_error(&p.inner))
+        .collect();
+    mass_error_offset(errors, quantile)
+}
+
+/// Estimate the systematic fragment mass error (ppm) as `quantile` of the signed errors of all
+/// matched fragments of target PSMs with spectrum_q <= fdr_cutoff, after IQR outlier removal.
+/// Requires PSMs scored with fragment annotation
+#[pyfunction]
+pub fn calibrate_fragment_error(psms: Vec<PyFeature>, quantile: f32, fdr_cutoff: Option<f32>) -> PyResult<PyMassErrorOffset> {
+    let fdr_cutoff = fdr_cutoff.unwrap_or(0.01);
+    let errors = psms
+        .iter()
+        .filter(|p| p.inner.label == 1 && p.inner.spectrum_q <= fdr_cutoff)
+        .filter_map(|p| p.inner.fragments.as_ref())
+        .flat_map(|f| f.mz_calculated.iter().zip(f.mz_experimental.iter()))
+        .filter(|(calc, _)| **calc > 0.0)
+        .map(|(calc, exp)| (*exp as f64 - *calc as f64) / *calc as f64 * 1e6)
+        .collect();
+    mass_error_offset(errors, quantile)
+}
+
+/// Shift an m/z by a systematic error of `offset_ppm`, so that the corrected value is what the
+/// instrument would have measured without the offset
+fn correct_mz(mz: f32, offset_ppm: f32) -> f32 {
+    (mz as f64 / (1.0 + offset_ppm as f64 * 1e-6)) as f32
+}
+
+/// Remove a systematic precursor m/z offset from all precursors of the spectra
+#[pyfunction]
+pub fn apply_mass_calibration(spectra: Vec<PyProcessedSpectrum>, offset_ppm: f32) -> Vec<PyProcessedSpectrum> {
+    let mut spectra = spectra;
+    for spectrum in spectra.iter_mut() {
+        for precursor in spectrum.inner.precursors.iter_mut() {
+            precursor.mz = correct_mz(precursor.mz, offset_ppm);
+        }
+    }
+    spectra
+}
+
+/// Remove a systematic fragment m/z offset from all peaks of the spectra
+#[pyfunction]
+pub fn apply_fragment_calibration(spectra: Vec<PyProcessedSpectrum>, offset_ppm: f32) -> Vec<PyProcessedSpectrum> {
+    let mut spectra = spectra;
+    for spectrum in spectra.iter_mut() {
+        // peaks store the singly charged neutral mass, m/z - proton
+        for peak in spectrum.inner.peaks.iter_mut() {
+            peak.mass = correct_mz(peak.mass + PROTON, offset_ppm) - PROTON;
+        }
+    }
+    spectra
+}
+
+/// Systematic fragment mass error (ppm) of the PSMs, the offset of `calibrate_fragment_error`
+#[pyfunction]
+pub fn calibrate_fragment_masses(psms: Vec<PyFeature>, quantile: f32, fdr_cutoff: Option<f32>) -> PyResult<f32> {
+    Ok(calibrate_fragment_error(psms, quantile, fdr_cutoff)?.offset_ppm)
+}
+
+/// Rescore the spectra of the PSMs with a systematic fragment error of `offset_ppm` removed. The
+/// peaks are corrected as by `apply_fragment_calibration`, which centers the fragment tolerance
+/// on the offset and reports the calibrated fragment errors. PSMs of the given spectra are
+/// replaced by their rescored PSMs (q-values need to be recomputed), PSMs of spectra not given
+/// are kept unchanged
+#[pyfunction]
+pub fn rescore_with_fragment_calibration(
+    psms: Vec<PyFeature>,
+    offset_ppm: f32,
+    db: &PyIndexedDatabase,
+    spectra: Vec<PyProcessedSpectrum>,
+    scorer: &PyScorer,
+    num_threads: Option<usize>,
+) -> PyResult<Vec<PyFeature>> {
+    check_peptide_indices(&db.inner, &psms)?;
+    let psm_spectra: HashSet<(usize, &str)> = psms
+        .iter()
+        .map(|psm| (psm.inner.file_id, psm.inner.spec_id.as_str()))
+        .collect();
+    let affected: Vec<PyProcessedSpectrum> = spectra
+        .into_iter()
+        .filter(|s| psm_spectra.contains(&(s.inner.file_id, s.inner.id.as_str())))
+        .collect();
+    let rescored_spectra: HashSet<(usize, String)> = affected
+        .iter()
+        .map(|s| (s.inner.file_id, s.inner.id.clone()))
+        .collect();
+
+    let calibrated = apply_fragment_calibration(affected, offset_ppm);
+    let rescored = scorer.score_collection(db, calibrated, num_threads.unwrap_or(4))?;
+
+    Ok(psms
+        .into_iter()
+        .filter(|psm| !rescored_spectra.contains(&(psm.inner.file_id, psm.inner.spec_id.clone())))
+        .chain(rescored.into_iter().flatten())
+        .collect())
+}
+
+/// Precursor mass accuracy of confident PSMs for quality control reports
+#[pyclass]
+#[derive(Clone, Debug)]
+pub struct PyMassAccuracyStats {
+    #[pyo3(get)]
+    pub mean_ppm: f32,
+    #[pyo3(get)]
+    pub median_ppm: f32,
+    #[pyo3(get)]
+    pub std_ppm: f32,
+    #[pyo3(get)]
+    pub percentile_5: f32,
+    #[pyo3(get)]
+    pub percentile_95: f32,
+    #[pyo3(get)]
+    pub num_psms: u32,
+}
+
+/// Signed precursor mass errors (ppm) of target PSMs with spectrum_q <= fdr_threshold, `delta_mass`
+/// holds their absolute value
+fn confident_mass_errors(psms: &[PyFeature], fdr_threshold: f32) -> impl Iterator<Item = (&Feature, f64)> {
+    psms.iter()
+        .map(|p| &p.inner)
+        .filter(move |f| f.label == 1 && f.spectrum_q <= fdr_threshold && f.calcmass > 0.0)
+        .map(|f| (f, signed_ppm_error(f)))
+        .filter(|(_, error)| error.is_finite())
+}
+
+/// Mean, median, standard deviation and 5th/95th percentile of the signed precursor mass errors
+/// (ppm) of target PSMs with spectrum_q <= fdr_threshold
+#[pyfunction]
+pub fn compute_mass_accuracy_stats(psms: Vec<PyFeature>, fdr_threshold: f32) -> PyResult<PyMassAccuracyStats> {
+    let mut errors: Vec<f64> = confident_mass_errors(&psms, fdr_threshold).map(|(_, e)| e).collect();
+    if errors.is_empty() {
+        return Err(PyValueError::new_err(format!(
+            "no target PSMs with spectrum_q <= {}",
+            fdr_threshold
+        )));
+    }
+    errors.sort_by(|a, b| a.total_cmp(b));
+
+    let n = errors.len() as f64;
+    let mean = errors.iter().sum::<f64>() / n;
+    let std = match errors.len() {
+        1 => 0.0,
+        _ => (errors.iter().map(|e| (e - mean).powi(2)).sum::<f64>() / (n - 1.0)).sqrt(),
+    };
+    Ok(PyMassAccuracyStats {
+        mean_ppm: mean as f32,
+        median_ppm: sorted_quantile(&errors, 0.5) as f32,
+        std_ppm: std as f32,
+        percentile_5: sorted_quantile(&errors, 0.05) as f32,
+        percentile_95: sorted_quantile(&errors, 0.95) as f32,
+        num_psms: errors.len() as u32,
+    })
+}
+
+/// (retention time, signed precursor mass error in ppm) of target PSMs with spectrum_q <=
+/// fdr_threshold, sorted by retention time
+#[pyfunction]
+pub fn mass_accuracy_over_time(psms: Vec<PyFeature>, fdr_threshold: Option<f32>) -> Vec<(f32, f32)> {
+    let mut points: Vec<(f32, f32)> = confident_mass_errors(&psms, fdr_threshold.unwrap_or(0.01))
+        .map(|(f, e)| (f.rt, e as f32))
+        .collect();
+    points.sort_by(|a, b| a.0.total_cmp(&b.0));
+    points
+}
+
+/// Rolling mean of the mass errors of `mass_accuracy_over_time`, over a window of `window_size`
+/// PSMs centered on each PSM (truncated at the ends of the run). A trend in the result indicates
+/// a drift of the mass calibration
+#[pyfunction]
+pub fn detect_mass_calibration_drift(
+    psms: Vec<PyFeature>,
+    window_size: usize,
+    fdr_threshold: Option<f32>,
+) -> PyResult<Vec<f32>> {
+    if window_size == 0 {
+        return Err(PyValueError::new_err("window_size must be at least 1"));
+    }
+    let errors: Vec<f64> = mass_accuracy_over_time(psms, fdr_threshold)
+        .into_iter()
+        .map(|(_, e)| e as f64)
+        .collect();
+
+    let mut prefix = vec![0.0; errors.len() + 1];
+    for (i, e) in errors.iter().enumerate() {
+        prefix[i + 1] = prefix[i] + e;
+    }
+    let (before, after) = ((window_size - 1) / 2, window_size / 2);
+    Ok((0..errors.len())
+        .map(|i| {
+            let (lo, hi) = (i.saturating_sub(before), (i + after + 1).min(errors.len()));
+            ((prefix[hi] - prefix[lo]) / (hi - lo) as f64) as f32
+        })
+        .collect())
+}
+
+/// Mass and eligible residues of the Unimod modifications supported by site localization
+fn unimod_site_modification(unimod_id: u32) -> Option<(f32, &'static [u8])> {
+    match unimod_id {
+        1 => Some((42.010565, b"K")),
+        7 => Some((0.984016, b"NQ")),
+        21 => Some((79.966331, b"STY")),
+        35 => Some((15.994915, b"M")),
+        _ => None,
+    }
+}
+
+/// Upper bound on the number of site permutations scored per PSM
+const MAX_LOCALIZATION_PERMUTATIONS: usize = 1024;
+/// Masses closer than this (Da) are considered the same fragment ion across site permutations
+const SHARED_ION_TOLERANCE: f32 = 0.001;
+
+/// All k-subsets of 0..n in lexicographic order, at most `limit` of them
+fn combinations(n: usize, k: usize, limit: usize) -> Vec<Vec<usize>> {
+    fn extend(start: usize, n: usize, k: usize, limit: usize, current: &mut Vec<usize>, out: &mut Vec<Vec<usize>>) {
+        if out.len() >= limit {
+            return;
+        }
+        if current.len() == k {
+            out.push(current.clone());
+            return;
+        }
+        for i in start..n {
+            current.push(i);
+            extend(i + 1, n, k, limit, current, out);
+            current.pop();
+        }
+    }
+    let mut out = Vec::new();
+    extend(0, n, k, limit, &mut Vec::with_capacity(k), &mut out);
+    out
+}
+
+fn contains_mass(sorted: &[f32], mass: f32) -> bool {
+    let i = sorted.partition_point(|m| *m < mass - SHARED_ION_TOLERANCE);
+    sorted.get(i).map_or(false, |m| (*m - mass).abs() <= SHARED_ION_TOLERANCE)
+}
+
+/// -10 log10 of the probability to match at least `matched` of `n` ions by chance
+fn binomial_score(n: usize, matched: usize, p: f64) -> f32 {
+    let mut coefficient = 1.0f64;
+    let mut tail = 0.0f64;
+    for k in 0..=n {
+        if k > 0 {
+            coefficient *= (n - k + 1) as f64 / k as f64;
+        }
+        if k >= matched {
+            tail += coefficient * p.powi(k as i32) * (1.0 - p).powi((n - k) as i32);
+        }
+    }
+    (-10.0 * tail.max(f64::MIN_POSITIVE).log10()) as f32
+}
+
+/// Ascore-like site localization of a variable modification: all placements of the modification
+/// on eligible residues are scored with their site-determining fragment ions, and the score of a
+/// site is the best score of placements using it minus the best score of placements that do not
+fn localization_scores(
+    db: &IndexedDatabase,
+    feature: &Feature,
+    spectrum: &ProcessedSpectrum,
+    mod_mass: f32,
+    residues: &[u8],
+    tolerance: Tolerance,
+) -> Vec<(usize, f32)> {
+    let peptide = &db[feature.peptide_idx];
+    let is_modified = |i: usize| {
+        peptide.modifications.get(i).map_or(false, |m| (m - mod_mass).abs() < 0.01)
+    };
+
+    let sites: Vec<usize> = (0..peptide.sequence.len())
+        .filter(|&i| residues.contains(&peptide.sequence[i]))
+        .collect();
+    let num_modified = sites.iter().filter(|&&i| is_modified(i)).count();
+    if num_modified == 0 || sites.is_empty() {
+        return Vec::new();
+    }
+
+    let placements = combinations(sites.len(), num_modified, MAX_LOCALIZATION_PERMUTATIONS);
+    let ions: Vec<Vec<f32>> = placements
+        .iter()
+        .map(|placement| {
+            let mut modforms = peptide.clone();
+            for &site in &sites {
+                if is_modified(site) {
+                    modforms.modifications[site] -= mod_mass;
+                }
+            }
+            for &p in placement {
+                modforms.modifications[sites[p]] += mod_mass;
+            }
+            let mut masses: Vec<f32> = db
+                .ion_kinds
+                .iter()
+                .flat_map(|kind| IonSeries::new(&modforms, *kind))
+                .map(|ion| ion.monoisotopic_mass)
+                .collect();
+            masses.sort_by(|a, b| a.total_cmp(b));
+            masses
+        })
+        .collect();
+
+    // chance of a random fragment mass to hit a peak: peak density times tolerance window width
+    let mass_range = spectrum.peaks.iter().map(|p| p.mass).fold(0.0f32, f32::max).max(1.0);
+    let (lo, hi) = tolerance.bounds(1000.0);
+    let p = (spectrum.peaks.len() as f64 * (hi - lo) as f64 / mass_range as f64).clamp(1e-3, 0.5);
+
+    let scores: Vec<f32> = ions
+        .iter()
+        .map(|masses| {
+            let diagnostic: Vec<f32> = masses
+                .iter()
+                .copied()
+                .filter(|&m| !ions.iter().all(|other| contains_mass(other, m)))
+                .collect();
+            let matched = diagnostic
+                .iter()
+                .filter(|&&m| most_intense_peak(spectrum, m, tolerance).is_some())
+                .count();
+            binomial_score(diagnostic.len(), matched, p)
+        })
+        .collect();
+
+    let mut result: Vec<(usize, f32)> = sites
+        .iter()
+        .enumerate()
+        .map(|(site_index, &position)| {
+            let (with, without): (Vec<_>, Vec<_>) = placements
+                .iter()
+                .zip(scores.iter())
+                .partition(|(placement, _)| placement.contains(&site_index));
+            let best = |v: &[(&Vec<usize>, &f32)]| v.iter().map(|(_, s)| **s).fold(0.0f32, f32::max);
+            (position, best(&with) - best(&without))
+        })
+        .collect();
+
+    result.sort_by(|a, b| b.1.total_cmp(&a.1));
+    result
+}
+
+/// Localize a variable modification (Unimod accession, supported: 1, 7, 21, 35) on the peptide of
+/// a PSM. Returns (position, localization score) of all eligible residues sorted by decreasing
+/// score, and stores per-residue scores and the best site on the PSM
+#[pyfunction]
+pub fn localize_modification(
+    mut psm: PyRefMut<PyFeature>,
+    db: &PyIndexedDatabase,
+    spectrum: &PyProcessedSpectrum,
+    mod_unimod_id: u32,
+    fragment_tolerance: &PyTolerance,
+) -> PyResult<Vec<(usize, f32)>> {
+    check_peptide_indices(&db.inner, [&*psm])?;
+    let (mod_mass, residues) = unimod_site_modification(mod_unimod_id).ok_or_else(|| {
+        PyValueError::new_err(format!(
+            "Unsupported Unimod accession {} for localization, supported are: 1, 7, 21, 35",
+            mod_unimod_id
+        ))
+    })?;
+
+    let sites = localization_scores(
+        &db.inner,
+        &psm.inner,
+        &spectrum.inner,
+        mod_mass,
+        residues,
+        fragment_tolerance.inner,
+    );
+
+    if sites.is_empty() {
+        psm.localization_scores = None;
+        psm.best_localization_site = None;
+    } else {
+        let mut per_residue = vec![0.0f32; db.inner[psm.inner.peptide_idx].sequence.len()];
+        for &(position, score) in &sites {
+            per_residue[position] = score;
+        }
+        psm.localization_scores = Some(per_residue);
+        psm.best_localization_site = Some(sites[0].0);
+    }
+
+    Ok(sites)
+}
+
+/// Arrow schema of PSMs exchanged via IPC and Parquet: (column, type, nullable). Matched
+/// fragments (including their neutral losses) and per-residue localization scores are not
+/// stored and are empty after reading. Columns from best_localization_site on were added after
+/// the first release of the schema and are optional on read
+pub const PSM_ARROW_SCHEMA: &[(&str, DataType, bool)] = &[
+    ("peptide_idx", DataType::UInt32, false),
+    ("psm_id", DataType::UInt64, false),
+    ("peptide_len", DataType::UInt64, false),
+    ("spec_id", DataType::Utf8, false),
+    ("file_id", DataType::UInt64, false),
+    ("rank", DataType::UInt32, false),
+    ("label", DataType::Int32, false),
+    ("expmass", DataType::Float32, false),
+    ("calcmass", DataType::Float32, false),
+    ("charge", DataType::UInt8, false),
+    ("rt", DataType::Float32, false),
+    ("aligned_rt", DataType::Float32, false),
+    ("predicted_rt", DataType::Float32, false),
+    ("delta_rt_model", DataType::Float32, false),
+    ("delta_mass", DataType::Float32, false),
+    ("isotope_error", DataType::Float32, false),
+    ("average_ppm", DataType::Float32, false),
+    ("hyperscore", DataType::Float64, false),
+    ("delta_next", DataType::Float64, false),
+    ("delta_best", DataType::Float64, false),
+    ("matched_peaks", DataType::UInt32, false),
+    ("longest_b", DataType::UInt32, false),
+    ("longest_y", DataType::UInt32, false),
+    ("longest_y_pct", DataType::Float32, false),
+    ("missed_cleavages", DataType::UInt8, false),
+    ("matched_intensity_pct", DataType::Float32, false),
+    ("scored_candidates", DataType::UInt32, false),
+    ("poisson", DataType::Float64, false),
+    ("discriminant_score", DataType::Float32, false),
+    ("posterior_error", DataType::Float32, false),
+    ("spectrum_q", DataType::Float32, false),
+    ("peptide_q", DataType::Float32, false),
+    ("protein_q", DataType::Float32, false),
+    ("ms2_intensity", DataType::Float32, false),
+    ("isotope_annotation_score", DataType::Float32, false),
+    ("xcorr", DataType::Float32, true),
+    ("delta_xcorr", DataType::Float32, true),
+    ("best_localization_site", DataType::UInt64, true),
+    ("neutral_loss_intensity_pct", DataType::Float32, false),
+    ("spectral_entropy", DataType::Float32, false),
+    ("delta_spectral_entropy", DataType::Float32, false),
+    ("ms1_isotope_score", DataType::Float32, false),
+    ("ms1_intensity_ratio", DataType::Float32, false),
+    ("has_oxonium_evidence", DataType::Boolean, false),
+    ("oxonium_score", DataType::Float32, false),
+    ("protein_q_razor", DataType::Float32, false),
+    ("silac_pair_idx", DataType::UInt32, true),
+    ("unexplained_intensity_pct", DataType::Float32, false),
+    ("collision_energy_calibrated", DataType::Float32, true),
+    ("precursor_purity", DataType::Float32, true),
+    ("tag_match_score", DataType::Float32, true),
+    ("intensity_similarity", DataType::Float32, true),
+    ("chimera_score", DataType::Float64, true),
+    ("peptide_sequence", DataType::Utf8, true),
+    ("re_score", DataType::Float64, true),
+    ("ims", DataType::Float32, true),
+    ("inverse_ion_mobility_predicted", DataType::Float32, true),
+    ("delta_ims_model", DataType::Float32, true),
+    ("intensity_weighted_ppm", DataType::Float32, false),
+    ("max_fragment_ppm", DataType::Float32, false),
+];
+
+fn psm_arrow_schema() -> Schema {
+    Schema::from(
+        PSM_ARROW_SCHEMA
+            .iter()
+            .map(|(name, data_type, nullable)| Field::new(*name, data_type.clone(), *nullable))
+            .collect::<Vec<_>>(),
+    )
+}
+
+fn arrow_error(e: arrow2::error::Error) -> PyErr {
+    PyValueError::new_err(format!("Arrow error: {}", e))
+}
+
+fn primitive_column<T: NativeType>(psms: &[PyFeature], value: impl Fn(&PyFeature) -> T) -> Box<dyn Array> {
+    PrimitiveArray::<T>::from_vec(psms.iter().map(value).collect()).boxed()
+}
+
+fn nullable_column<T: NativeType>(psms: &[PyFeature], value: impl Fn(&PyFeature) -> Option<T>) -> Box<dyn Array> {
+    PrimitiveArray::<T>::from(psms.iter().map(value).collect::<Vec<_>>()).boxed()
+}
+
+/// One record batch holding all PSMs, columns in the order of `PSM_ARROW_SCHEMA`
+fn psms_to_chunk(psms: &[PyFeature]) -> PyResult<Chunk<Box<dyn Array>>> {
+    let spec_ids: Vec<&str> = psms.iter().map(|p| p.inner.spec_id.as_str()).collect();
+
+    let columns: Vec<Box<dyn Array>> = vec![
+        primitive_column(&psms, |p| p.inner.peptide_idx.0),
+        primitive_column(&psms, |p| p.inner.psm_id as u64),
+        primitive_column(&psms, |p| p.inner.peptide_len as u64),
+        Utf8Array::<i32>::from_slice(&spec_ids).boxed(),
+        primitive_column(&psms, |p| p.inner.file_id as u64),
+        primitive_column(&psms, |p| p.inner.rank),
+        primitive_column(&psms, |p| p.inner.label),
+        primitive_column(&psms, |p| p.inner.expmass),
+        primitive_column(&psms, |p| p.inner.calcmass),
+        primitive_column(&psms, |p| p.inner.charge),
+        primitive_column(&psms, |p| p.inner.rt),
+        primitive_column(&psms, |p| p.inner.aligned_rt),
+        primitive_column(&psms, |p| p.inner.predicted_rt),
+        primitive_column(&psms, |p| p.inner.delta_rt_model),
+        primitive_column(&psms, |p| p.inner.delta_mass),
+        primitive_column(&psms, |p| p.inner.isotope_error),
+        primitive_column(&psms, |p| p.inner.average_ppm),
+        primitive_column(&psms, |p| p.inner.hyperscore),
+        primitive_column(&psms, |p| p.inner.delta_next),
+        primitive_column(&psms, |p| p.inner.delta_best),
+        primitive_column(&psms, |p| p.inner.matched_peaks),
+        primitive_column(&psms, |p| p.inner.longest_b),
+        primitive_column(&psms, |p| p.inner.longest_y),
+        primitive_column(&psms, |p| p.inner.longest_y_pct),
+        primitive_column(&psms, |p| p.inner.missed_cleavages),
+        primitive_column(&psms, |p| p.inner.matched_intensity_pct),
+        primitive_column(&psms, |p| p.inner.scored_candidates),
+        primitive_column(&psms, |p| p.inner.poisson),
+        primitive_column(&psms, |p| p.inner.discriminant_score),
+        primitive_column(&psms, |p| p.inner.posterior_error),
+        primitive_column(&psms, |p| p.inner.spectrum_q),
+        primitive_column(&psms, |p| p.inner.peptide_q),
+        primitive_column(&psms, |p| p.inner.protein_q),
+        primitive_column(&psms, |p| p.inner.ms2_intensity),
+        primitive_column(&psms, |p| p.isotope_annotation_score),
+        nullable_column(&psms, |p| p.xcorr),
+        nullable_column(&psms, |p| p.delta_xcorr),
+        nullable_column(&psms, |p| p.best_localization_site.map(|s| s as u64)),
+        primitive_column(&psms, |p| p.neutral_loss_intensity_pct),
+        primitive_column(&psms, |p| p.spectral_entropy),
+        primitive_column(&psms, |p| p.delta_spectral_entropy),
+        primitive_column(&psms, |p| p.ms1_isotope_score),
+        primitive_column(&psms, |p| p.ms1_intensity_ratio),
+        BooleanArray::from_slice(psms.iter().map(|p| p.has_oxonium_evidence).collect::<Vec<_>>()).boxed(),
+        primitive_column(&psms, |p| p.oxonium_score),
+        primitive_column(&psms, |p| p.protein_q_razor),
+        nullable_column(&psms, |p| p.silac_pair_idx.map(|idx| idx.0)),
+        primitive_column(&psms, |p| p.unexplained_intensity_pct),
+        nullable_column(&psms, |p| p.collision_energy_calibrated),
+        nullable_column(&psms, |p| p.precursor_purity),
+        nullable_column(&psms, |p| p.tag_match_score),
+        nullable_column(&psms, |p| p.intensity_similarity),
+        nullable_column(&psms, |p| p.chimera_score),
+        Utf8Array::<i32>::from(psms.iter().map(|p| p.peptide_sequence.as_deref()).collect::<Vec<_>>()).boxed(),
+        nullable_column(&psms, |p| p.re_score),
+        nullable_column(&psms, |p| p.ims),
+        nullable_column(&psms, |p| p.inverse_ion_mobility_predicted),
+        nullable_column(&psms, |p| p.delta_ims_model),
+        primitive_column(&psms, |p| p.intensity_weighted_ppm),
+        primitive_column(&psms, |p| p.max_fragment_ppm),
+    ];
+
+    Chunk::try_new(columns).map_err(arrow_error)
+}
+
+/// Encode PSMs as a single Arrow record batch in IPC file format (see `PSM_ARROW_SCHEMA`), the
+/// result can be opened with `pyarrow.ipc.open_file`
+#[pyfunction]
+pub fn psms_to_arrow_ipc(py: Python, psms: Vec<PyFeature>) -> PyResult<Py<PyBytes>> {
+    let chunk = psms_to_chunk(&psms)?;
+    let mut writer = FileWriter::try_new(
+        Vec::new(),
+        psm_arrow_schema(),
+        None,
+        WriteOptions { compression: None },
+    )
+    .map_err(arrow_error)?;
+    writer.write(&chunk, None).map_err(arrow_error)?;
+    writer.finish().map_err(arrow_error)?;
+
+    Ok(PyBytes::new(py, &writer.into_inner()).into())
+}
+
+/// Columns of a record batch read from IPC or Parquet, looked up by name
+struct ArrowColumns<'a> {
+    schema: &'a Schema,
+    chunk: &'a Chunk<Box<dyn Array>>,
+}
+
+impl<'a> ArrowColumns<'a> {
+    fn array(&self, name: &str) -> PyResult<&'a dyn Array> {
+        let index = self
+            .schema
+            .fields
+            .iter()
+            .position(|f| f.name == name)
+            .ok_or_else(|| PyValueError::new_err(format!("Arrow data is missing column {}", name)))?;
+        Ok(self.chunk.arrays()[index].as_ref())
+    }
+
+    fn primitive<T: NativeType>(&self, name: &str) -> PyResult<&'a PrimitiveArray<T>> {
+        self.array(name)?
+            .as_any()
+            .downcast_ref::<PrimitiveArray<T>>()
+            .ok_or_else(|| PyValueError::new_err(format!("Arrow column {} has an unexpected type", name)))
+    }
+
+    fn boolean(&self, name: &str) -> PyResult<&'a BooleanArray> {
+        self.array(name)?
+            .as_any()
+            .downcast_ref::<BooleanArray>()
+            .ok_or_else(|| PyValueError::new_err(format!("Arrow column {} has an unexpected type", name)))
+    }
+
+    fn utf8(&self, name: &str) -> PyResult<&'a Utf8Array<i32>> {
+        self.array(name)?
+            .as_any()
+            .downcast_ref::<Utf8Array<i32>>()
+            .ok_or_else(|| PyValueError::new_err(format!("Arrow column {} has an unexpected type", name)))
+    }
+
+    fn has(&self, name: &str) -> bool {
+        self.schema.fields.iter().any(|f| f.name == name)
+    }
+
+    fn optional_primitive<T: NativeType>(&self, name: &str) -> PyResult<Option<&'a PrimitiveArray<T>>> {
+        self.has(name).then(|| self.primitive(name)).transpose()
+    }
+
+    fn optional_boolean(&self, name: &str) -> PyResult<Option<&'a BooleanArray>> {
+        self.has(name).then(|| self.boolean(name)).transpose()
+    }
+
+    fn optional_utf8(&self, name: &str) -> PyResult<Option<&'a Utf8Array<i32>>> {
+        self.has(name).then(|| self.utf8(name)).transpose()
+    }
+}
+
+fn optional_value<T: NativeType>(array: &PrimitiveArray<T>, i: usize) -> Option<T> {
+    array.is_valid(i).then(|| array.value(i))
+}
+
+/// Value of an optional non-nullable column, `default` if the column is absent
+fn value_or<T: NativeType>(array: Option<&PrimitiveArray<T>>, i: usize, default: T) -> T {
+    array.map_or(default, |array| array.value(i))
+}
+
+/// Value of an optional nullable column, None if the column is absent
+fn optional_column_value<T: NativeType>(array: Option<&PrimitiveArray<T>>, i: usize) -> Option<T> {
+    array.and_then(|array| optional_value(array, i))
+}
+
+/// Decode the PSMs of one record batch laid out as `PSM_ARROW_SCHEMA`, columns are looked up by
+/// name so their order does not matter
+fn psms_from_chunk(schema: &Schema, chunk: &Chunk<Box<dyn Array>>, psms: &mut Vec<PyFeature>) -> PyResult<()> {
+    let columns = ArrowColumns { schema, chunk };
+
+    let peptide_idx = columns.primitive::<u32>("peptide_idx")?;
+    let psm_id = columns.primitive::<u64>("psm_id")?;
+    let peptide_len = columns.primitive::<u64>("peptide_len")?;
+    let spec_id = columns.utf8("spec_id")?;
+    let file_id = columns.primitive::<u64>("file_id")?;
+    let rank = columns.primitive::<u32>("rank")?;
+    let label = columns.primitive::<i32>("label")?;
+    let expmass = columns.primitive::<f32>("expmass")?;
+    let calcmass = columns.primitive::<f32>("calcmass")?;
+    let charge = columns.primitive::<u8>("charge")?;
+    let rt = columns.primitive::<f32>("rt")?;
+    let aligned_rt = columns.primitive::<f32>("aligned_rt")?;
+    let predicted_rt = columns.primitive::<f32>("predicted_rt")?;
+    let delta_rt_model = columns.primitive::<f32>("delta_rt_model")?;
+    let delta_mass = columns.primitive::<f32>("delta_mass")?;
+    let isotope_error = columns.primitive::<f32>("isotope_error")?;
+    let average_ppm = columns.primitive::<f32>("average_ppm")?;
+    let hyperscore = columns.primitive::<f64>("hyperscore")?;
+    let delta_next = columns.primitive::<f64>("delta_next")?;
+    let delta_best = columns.primitive::<f64>("delta_best")?;
+    let matched_peaks = columns.primitive::<u32>("matched_peaks")?;
+    let longest_b = columns.primitive::<u32>("longest_b")?;
+    let longest_y = columns.primitive::<u32>("longest_y")?;
+    let longest_y_pct = columns.primitive::<f32>("longest_y_pct")?;
+    let missed_cleavages = columns.primitive::<u8>("missed_cleavages")?;
+    let matched_intensity_pct = columns.primitive::<f32>("matched_intensity_pct")?;
+    let scored_candidates = columns.primitive::<u32>("scored_candidates")?;
+    let poisson = columns.primitive::<f64>("poisson")?;
+    let discriminant_score = columns.primitive::<f32>("discriminant_score")?;
+    let posterior_error = columns.primitive::<f32>("posterior_error")?;
+    let spectrum_q = columns.primitive::<f32>("spectrum_q")?;
+    let peptide_q = columns.primitive::<f32>("peptide_q")?;
+    let protein_q = columns.primitive::<f32>("protein_q")?;
+    let ms2_intensity = columns.primitive::<f32>("ms2_intensity")?;
+    let isotope_annotation_score = columns.primitive::<f32>("isotope_annotation_score")?;
+    let xcorr = columns.primitive::<f32>("xcorr")?;
+    let delta_xcorr = columns.primitive::<f32>("delta_xcorr")?;
+    // columns added after the first release of the schema are optional, files written before
+    // they existed are read with their default values
+    let best_localization_site = columns.optional_primitive::<u64>("best_localization_site")?;
+    let neutral_loss_intensity_pct = columns.optional_primitive::<f32>("neutral_loss_intensity_pct")?;
+    let spectral_entropy = columns.optional_primitive::<f32>("spectral_entropy")?;
+    let delta_spectral_entropy = columns.optional_primitive::<f32>("delta_spectral_entropy")?;
+    let ms1_isotope_score = columns.optional_primitive::<f32>("ms1_isotope_score")?;
+    let ms1_intensity_ratio = columns.optional_primitive::<f32>("ms1_intensity_ratio")?;
+    let has_oxonium_evidence = columns.optional_boolean("has_oxonium_evidence")?;
+    let oxonium_score = columns.optional_primitive::<f32>("oxonium_score")?;
+    let protein_q_razor = columns.optional_primitive::<f32>("protein_q_razor")?;
+    let silac_pair_idx = columns.optional_primitive::<u32>("silac_pair_idx")?;
+    let unexplained_intensity_pct = columns.optional_primitive::<f32>("unexplained_intensity_pct")?;
+    let collision_energy_calibrated = columns.optional_primitive::<f32>("collision_energy_calibrated")?;
+    let precursor_purity = columns.optional_primitive::<f32>("precursor_purity")?;
+    let tag_match_score = columns.optional_primitive::<f32>("tag_match_score")?;
+    let intensity_similarity = columns.optional_primitive::<f32>("intensity_similarity")?;
+    let chimera_score = columns.optional_primitive::<f64>("chimera_score")?;
+    let peptide_sequence = columns.optional_utf8("peptide_sequence")?;
+    let re_score = columns.optional_primitive::<f64>("re_score")?;
+    let ims = columns.optional_primitive::<f32>("ims")?;
+    let inverse_ion_mobility_predicted = columns.optional_primitive::<f32>("inverse_ion_mobility_predicted")?;
+    let delta_ims_model = columns.optional_primitive::<f32>("delta_ims_model")?;
+    let intensity_weighted_ppm = columns.optional_primitive::<f32>("intensity_weighted_ppm")?;
+    let max_fragment_ppm = columns.optional_primitive::<f32>("max_fragment_ppm")?;
+
+    for i in 0..chunk.len() {
+        psms.push(PyFeature {
+            inner: Feature {
+                peptide_idx: PeptideIx(peptide_idx.value(i)),
+                psm_id: psm_id.value(i) as usize,
+                peptide_len: peptide_len.value(i) as usize,
+                spec_id: spec_id.value(i).to_string(),
+                file_id: file_id.value(i) as usize,
+                rank: rank.value(i),
+                label: label.value(i),
+                expmass: expmass.value(i),
+                calcmass: calcmass.value(i),
+                charge: charge.value(i),
+                rt: rt.value(i),
+                aligned_rt: aligned_rt.value(i),
+                predicted_rt: predicted_rt.value(i),
+                delta_rt_model: delta_rt_model.value(i),
+                delta_mass: delta_mass.value(i),
+                isotope_error: isotope_error.value(i),
+                average_ppm: average_ppm.value(i),
+                hyperscore: hyperscore.value(i),
+                delta_next: delta_next.value(i),
+                delta_best: delta_best.value(i),
+                matched_peaks: matched_peaks.value(i),
+                longest_b: longest_b.value(i),
+                longest_y: longest_y.value(i),
+                longest_y_pct: longest_y_pct.value(i),
+                missed_cleavages: missed_cleavages.value(i),
+                matched_intensity_pct: matched_intensity_pct.value(i),
+                scored_candidates: scored_candidates.value(i),
+                poisson: poisson.value(i),
+                discriminant_score: discriminant_score.value(i),
+                posterior_error: posterior_error.value(i),
+                spectrum_q: spectrum_q.value(i),
+                peptide_q: peptide_q.value(i),
+                protein_q: protein_q.value(i),
+                ms2_intensity: ms2_intensity.value(i),
+                fragments: None,
+            },
+            isotope_annotation_score: isotope_annotation_score.value(i),
+            xcorr: optional_value(xcorr, i),
+            delta_xcorr: optional_value(delta_xcorr, i),
+            localization_scores: None,
+            best_localization_site: optional_column_value(best_localization_site, i).map(|s| s as usize),
+            neutral_losses: Vec::new(),
+            neutral_loss_intensity_pct: value_or(neutral_loss_intensity_pct, i, 0.0),
+            spectral_entropy: value_or(spectral_entropy, i, 0.0),
+            delta_spectral_entropy: value_or(delta_spectral_entropy, i, 0.0),
+            ms1_isotope_score: value_or(ms1_isotope_score, i, 0.0),
+            ms1_intensity_ratio: value_or(ms1_intensity_ratio, i, 0.0),
+            has_oxonium_evidence: has_oxonium_evidence.map_or(false, |a| a.value(i)),
+            oxonium_score: value_or(oxonium_score, i, 0.0),
+            protein_q_razor: value_or(protein_q_razor, i, 1.0),
+            silac_pair_idx: optional_column_value(silac_pair_idx, i).map(PeptideIx),
+            unexplained_intensity_pct: value_or(
+                unexplained_intensity_pct,
+                i,
+                100.0 - matched_intensity_pct.value(i).min(100.0),
+            ),
+            coverage_stats: None,
+            collision_energy_calibrated: optional_column_value(collision_energy_calibrated, i),
+            precursor_purity: optional_column_value(precursor_purity, i),
+            tag_match_score: optional_column_value(tag_match_score, i),
+            intensity_similarity: optional_column_value(intensity_similarity, i),
+            chimera_score: optional_column_value(chimera_score, i),
+            peptide_sequence: peptide_sequence.and_then(|s| s.get(i)).map(str::to_string),
+            re_score: optional_column_value(re_score, i),
+            ims: optional_column_value(ims, i),
+            inverse_ion_mobility_predicted: optional_column_value(inverse_ion_mobility_predicted, i),
+            delta_ims_model: optional_column_value(delta_ims_model, i),
+            intensity_weighted_ppm: value_or(intensity_weighted_ppm, i, f32::NAN),
+            max_fragment_ppm: value_or(max_fragment_ppm, i, f32::NAN),
+        });
+    }
+
+    Ok(())
+}
+
+/// Decode PSMs encoded with `psms_to_arrow_ipc`
+#[pyfunction]
+pub fn psms_from_arrow_ipc(bytes: &[u8]) -> PyResult<Vec<PyFeature>> {
+    let mut cursor = Cursor::new(bytes);
+    let metadata = read_file_metadata(&mut cursor).map_err(arrow_error)?;
+    let schema = metadata.schema.clone();
+    let reader = FileReader::new(cursor, metadata, None, None);
+
+    let mut psms = Vec::new();
+    for chunk in reader {
+        let chunk = chunk.map_err(arrow_error)?;
+        psms_from_chunk(&schema, &chunk, &mut psms)?;
+    }
+
+    Ok(psms)
+}
+
+/// Columns a Parquet export can be partitioned by
+const PARQUET_PARTITION_COLUMNS: [&str; 2] = ["file_id", "charge"];
+
+fn parquet_compression(compression: &str) -> PyResult<parquet_write::CompressionOptions> {
+    match compression.to_lowercase().as_str() {
+        "snappy" => Ok(parquet_write::CompressionOptions::Snappy),
+        "zstd" => Ok(parquet_write::CompressionOptions::Zstd(None)),
+        "none" | "uncompressed" => Ok(parquet_write::CompressionOptions::Uncompressed),
+        _ => Err(PyValueError::new_err(format!(
+            "Invalid compression: {}, allowed values are: snappy, zstd, none",
+            compression
+        ))),
+    }
+}
+
+fn write_parquet_file(
+    path: &Path,
+    schema: Schema,
+    chunk: Chunk<Box<dyn Array>>,
+    compression: parquet_write::CompressionOptions,
+) -> PyResult<()> {
+    let options = parquet_write::WriteOptions {
+        write_statistics: true,
+        compression,
+        version: parquet_write::Version::V2,
+        data_pagesize_limit: None,
+    };
+    let encodings = schema
+        .fields
+        .iter()
+        .map(|f| parquet_write::transverse(&f.data_type, |_| parquet_write::Encoding::Plain))
+        .collect();
+    let row_groups =
+        parquet_write::RowGroupIterator::try_new(vec![Ok(chunk)].into_iter(), &schema, options, encodings)
+            .map_err(arrow_error)?;
+
+    let file = File::create(path)
+        .map_err(|e| PyValueError::new_err(format!("Could not create {}: {}", path.display(), e)))?;
+    let mut writer = parquet_write::FileWriter::try_new(file, schema, options).map_err(arrow_error)?;
+    for group in row_groups {
+        writer.write(group.map_err(arrow_error)?).map_err(arrow_error)?;
+    }
+    writer.end(None).map_err(arrow_error)?;
+    Ok(())
+}
+
+/// Write PSMs to Parquet with the columns of `PSM_ARROW_SCHEMA` (fragment annotations are not
+/// stored). With `partition_by` ("file_id" or "charge") `path` is a directory holding one
+/// hive-style `<column>=<value>/part-0.parquet` file per value, the partition column is encoded in
+/// the directory name only, like pyarrow and pandas expect
+#[pyfunction]
+pub fn psms_to_parquet(
+    psms: Vec<PyFeature>,
+    path: &str,
+    compression: &str,
+    partition_by: Option<&str>,
+) -> PyResult<()> {
+    let compression = parquet_compression(compression)?;
+
+    let partition_by = match partition_by {
+        None => return write_parquet_file(Path::new(path), psm_arrow_schema(), psms_to_chunk(&psms)?, compression),
+        Some(column) if PARQUET_PARTITION_COLUMNS.contains(&column) => column,
+        Some(column) => {
+            return Err(PyValueError::new_err(format!(
+                "Invalid partition column: {}, allowed values are: {}",
+                column,
+                PARQUET_PARTITION_COLUMNS.join(", ")
+            )))
+        }
+    };
+
+    let mut partitions: BTreeMap<u64, Vec<PyFeature>> = BTreeMap::new();
+    for psm in psms {
+        let key = match partition_by {
+            "file_id" => psm.inner.file_id as u64,
+            _ => psm.inner.charge as u64,
+        };
+        partitions.entry(key).or_default().push(psm);
+    }
+
+    let full_schema = psm_arrow_schema();
+    let keep: Vec<usize> = (0..full_schema.fields.len())
+        .filter(|&i| full_schema.fields[i].name != partition_by)
+        .collect();
+    let schema = Schema::from(keep.iter().map(|&i| full_schema.fields[i].clone()).collect::<Vec<_>>());
+
+    for (key, psms) in partitions {
+        let directory = Path::new(path).join(format!("{}={}", partition_by, key));
+        fs::create_dir_all(&directory)
+            .map_err(|e| PyValueError::new_err(format!("Could not create {}: {}", directory.display(), e)))?;
+        let arrays = psms_to_chunk(&psms)?.into_arrays();
+        let chunk = Chunk::try_new(keep.iter().map(|&i| arrays[i].clone()).collect()).map_err(arrow_error)?;
+        write_parquet_file(&directory.join("part-0.parquet"), schema.clone(), chunk, compression)?;
+    }
+
+    Ok(())
+}
+
+fn collect_parquet_files(path: &Path, files: &mut Vec<PathBuf>) -> PyResult<()> {
+    if path.is_file() {
+        files.push(path.to_path_buf());
+        return Ok(());
+    }
+    let mut entries = fs::read_dir(path)
+        .map_err(|e| PyValueError::new_err(format!("Could not read {}: {}", path.display(), e)))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .collect::<Vec<_>>();
+    entries.sort();
+    for entry in entries {
+        if entry.is_dir() {
+            collect_parquet_files(&entry, files)?;
+        } else if entry.extension().map_or(false, |e| e == "parquet") {
+            files.push(entry);
+        }
+    }
+    Ok(())
+}
+
+/// Restore a partition column missing from a Parquet file from its `<column>=<value>` directory
+fn restore_partition_column(
+    file: &Path,
+    schema: &mut Schema,
+    chunk: Chunk<Box<dyn Array>>,
+) -> PyResult<Chunk<Box<dyn Array>>> {
+    let mut arrays = chunk.into_arrays();
+    for column in PARQUET_PARTITION_COLUMNS {
+        if schema.fields.iter().any(|f| f.name == column) {
+            continue;
+        }
+        let value = file
+            .ancestors()
+            .filter_map(|a| a.file_name()?.to_str()?.strip_prefix(column)?.strip_prefix('='))
+            .next()
+            .and_then(|v| v.parse::<u64>().ok());
+        let Some(value) = value else { continue };
+
+        let len = arrays.first().map_or(0, |a| a.len());
+        let array = match column {
+            "file_id" => PrimitiveArray::<u64>::from_vec(vec![value; len]).boxed(),
+            _ => PrimitiveArray::<u8>::from_vec(vec![value as u8; len]).boxed(),
+        };
+        schema.fields.push(Field::new(column, array.data_type().clone(), false));
+        arrays.push(array);
+    }
+    Chunk::try_new(arrays).map_err(arrow_error)
+}
+
+/// Read PSMs written with `psms_to_parquet`, `path` is either a single file or a partitioned
+/// directory
+#[pyfunction]
+pub fn psms_from_parquet(path: &str) -> PyResult<Vec<PyFeature>> {
+    let mut files = Vec::new();
+    collect_parquet_files(Path::new(path), &mut files)?;
+
+    let mut psms = Vec::new();
+    for file in files {
+        let mut reader = File::open(&file)
+            .map_err(|e| PyValueError::new_err(format!("Could not open {}: {}", file.display(), e)))?;
+        let metadata = parquet_read::read_metadata(&mut reader).map_err(arrow_error)?;
+        let schema = parquet_read::infer_schema(&metadata).map_err(arrow_error)?;
+        let chunks = parquet_read::FileReader::new(reader, metadata.row_groups, schema.clone(), None, None, None);
+        for chunk in chunks {
+            let mut schema = schema.clone();
+            let chunk = restore_partition_column(&file, &mut schema, chunk.map_err(arrow_error)?)?;
+            psms_from_chunk(&schema, &chunk, &mut psms)?;
+        }
+    }
+
+    Ok(psms)
+}
+
+/// Spectrum-level q-values under target-decoy competition: every spectrum (file_id, spec_id)
+/// contributes its single best PSM, and the FDR at a score threshold is estimated as decoy winners
+/// / all winners. Only the winners are returned, by descending hyperscore
+#[pyfunction]
+pub fn tdc_q_values(psms: Vec<PyFeature>) -> Vec<PyFeature> {
+    let mut winners = competition_winners(psms, |p| &p.inner);
+    let scores: Vec<f64> = winners.iter().map(|p| p.inner.hyperscore).collect();
+    let is_decoy: Vec<bool> = winners.iter().map(|p| p.inner.label == -1).collect();
+    for (psm, q) in winners.iter_mut().zip(competition_q_values(&scores, &is_decoy)) {
+        psm.inner.spectrum_q = q as f32;
+    }
+    winners
+}
+
+/// Set `silac_pair_idx` of all PSMs whose SILAC partner peptide is identified among the results
+fn mark_silac_pairs(db: &PyIndexedDatabase, results: &mut [Vec<PyFeature>]) {
+    if db.silac_partners.is_empty() {
+        return;
+    }
+    let identified: HashSet<u32> = results
+        .iter()
+        .flatten()
+        .map(|f| f.inner.peptide_idx.0)
+        .collect();
+    for feature in results.iter_mut().flatten() {
+        feature.silac_pair_idx = db
+            .silac_partners
+            .get(&feature.inner.peptide_idx.0)
+            .filter(|partner| identified.contains(partner))
+            .map(|partner| PeptideIx(*partner));
+    }
+}
+
+/// Detect SILAC pairs among PSMs collected from several searches, e.g. of all runs, see
+/// `PyFeature.silac_pair_idx`
+#[pyfunction]
+pub fn annotate_silac_pairs(psms: Vec<PyFeature>, db: &PyIndexedDatabase) -> PyResult<Vec<PyFeature>> {
+    check_peptide_indices(&db.inner, &psms)?;
+    let mut results = vec![psms];
+    mark_silac_pairs(db, &mut results);
+    Ok(results.pop().unwrap_or_default())
+}
+
+/// Convert PSMs to a list of dicts (see `PyFeature.to_dict`), e.g. to build a DataFrame
+#[pyfunction]
+pub fn to_records(py: Python, psms: Vec<PyFeature>) -> Vec<HashMap<String, PyObject>> {
+    psms.iter().map(|psm| psm.to_dict(py)).collect()
+}
+
+/// Order of the PSMs of one spectrum: targets before decoys, then descending hyperscore, then
+/// ascending peptide index
+fn spectrum_psm_order(a: &PyFeature, b: &PyFeature) -> std::cmp::Ordering {
+    (a.inner.label == -1)
+        .cmp(&(b.inner.label == -1))
+        .then(b.inner.hyperscore.total_cmp(&a.inner.hyperscore))
+        .then(a.inner.peptide_idx.0.cmp(&b.inner.peptide_idx.0))
+}
+
+/// Group PSMs by spec_id, the PSMs of each spectrum are ordered targets first, then by descending
+/// hyperscore and ascending peptide index
+#[pyfunction]
+pub fn group_by_spectrum(psms: Vec<PyFeature>) -> BTreeMap<String, Vec<PyFeature>> {
+    let mut results: BTreeMap<String, Vec<PyFeature>> = BTreeMap::new();
+    for psm in psms {
+        results.entry(psm.inner.spec_id.clone()).or_default().push(psm);
+    }
+    for spectrum_psms in results.values_mut() {
+        spectrum_psms.sort_by(spectrum_psm_order);
+    }
+    results
+}
+
+/// Flatten PSMs grouped by spectrum into one vector sorted by spec_id, then rank
+#[pyfunction]
+pub fn as_flat_vec(results: BTreeMap<String, Vec<PyFeature>>) -> Vec<PyFeature> {
+    let mut psms: Vec<PyFeature> = results.into_values().flatten().collect();
+    psms.sort_by(|a, b| {
+        a.inner
+            .spec_id
+            .cmp(&b.inner.spec_id)
+            .then(a.inner.rank.cmp(&b.inner.rank))
+    });
+    psms
+}
+
+/// The rank 1 PSMs of PSMs grouped by spectrum, sorted by spec_id
+#[pyfunction]
+pub fn top_psm_per_spectrum(results: BTreeMap<String, Vec<PyFeature>>) -> Vec<PyFeature> {
+    results
+        .into_values()
+        .flat_map(|psms| psms.into_iter().filter(|p| p.inner.rank == 1))
+        .collect()
+}
+
+/// Fill the delta scores of PSMs grouped by spec_id and ordered by descending hyperscore:
+/// `delta_next` = (hyperscore - next hyperscore) / hyperscore (1.0 for the last PSM of a spectrum)
+/// and `delta_best` = rank 1 hyperscore - median hyperscore of the spectrum. The PSMs are returned
+/// sorted by spec_id, then descending hyperscore
+#[pyfunction]
+pub fn compute_delta_scores(features: Vec<PyFeature>) -> Vec<PyFeature> {
+    let mut results: BTreeMap<String, Vec<PyFeature>> = BTreeMap::new();
+    for feature in features {
+        results.entry(feature.inner.spec_id.clone()).or_default().push(feature);
+    }
+
+    let mut features = Vec::new();
+    for mut spectrum_psms in results.into_values() {
+        spectrum_psms.sort_by(|a, b| b.inner.hyperscore.total_cmp(&a.inner.hyperscore));
+        let scores: Vec<f64> = spectrum_psms.iter().map(|p| p.inner.hyperscore).collect();
+        let median = median(scores.iter().copied()).unwrap_or(0.0);
+        for (i, psm) in spectrum_psms.iter_mut().enumerate() {
+            let score = scores[i];
+            let next = scores.get(i + 1).copied().unwrap_or_default();
+            psm.inner.delta_next = if score > 0.0 { (score - next) / score } else { 0.0 };
+            psm.inner.delta_best = scores[0] - median;
+        }
+        features.extend(spectrum_psms);
+    }
+    features
+}
+
+/// The best PSM of every spectrum after `compute_delta_scores`
+fn top_delta_psms(psms: Vec<PyFeature>) -> Vec<PyFeature> {
+    let mut last_spec_id: Option<String> = None;
+    compute_delta_scores(psms)
+        .into_iter()
+        .filter(|p| {
+            let first = last_spec_id.as_ref() != Some(&p.inner.spec_id);
+            last_spec_id = Some(p.inner.spec_id.clone());
+            first
+        })
+        .collect()
+}
+
+/// Relative delta scores (see `compute_delta_scores`) of the best PSM of every spectrum, e.g. for
+/// a histogram, optionally of target PSMs only
+#[pyfunction]
+pub fn delta_score_distribution(psms: Vec<PyFeature>, target_only: bool) -> Vec<f32> {
+    top_delta_psms(psms)
+        .into_iter()
+        .filter(|p| !target_only || p.inner.label != -1)
+        .map(|p| p.inner.delta_next as f32)
+        .collect()
+}
+
+/// Hyperscore cutoff at a FDR estimated with the relative delta score as discriminant: the best
+/// PSMs of all spectra are ranked by delta score, target-decoy q-values are computed, and the lowest
+/// hyperscore of a target PSM with q-value <= fdr is returned
+#[pyfunction]
+pub fn score_threshold_from_delta(psms: Vec<PyFeature>, fdr: f32) -> PyResult<f32> {
+    let mut top = top_delta_psms(psms);
+    top.sort_by(|a, b| b.inner.delta_next.total_cmp(&a.inner.delta_next));
+
+    let (mut decoys, mut targets) = (0usize, 0usize);
+    let fdrs: Vec<f32> = top
+        .iter()
+        .map(|psm| {
+            if psm.inner.label == -1 {
+                decoys += 1;
+            } else {
+                targets += 1;
+            }
+            decoys as f32 / targets.max(1) as f32
+        })
+        .collect();
+
+    // accepted are all PSMs down to the last one whose q-value, the minimal FDR below it, passes
+    let Some(last) = fdrs.iter().rposition(|f| *f <= fdr) else {
+        return Err(PyValueError::new_err(format!("no PSM passes a FDR of {}", fdr)));
+    };
+    top[..=last]
+        .iter()
+        .filter(|p| p.inner.label != -1)
+        .map(|p| p.inner.hyperscore as f32)
+        .min_by(|a, b| a.total_cmp(b))
+        .ok_or_else(|| PyValueError::new_err(format!("no target PSM passes a FDR of {}", fdr)))
+}
+
+/// Fraction (0-1) of the total ion current explained by the matched fragments of a PSM, neutral
+/// loss ions included if they were matched
+#[pyfunction]
+pub fn compute_explained_intensity(psm: &PyFeature) -> f32 {
+    explained_intensity_pct(psm) / 100.0
+}
+
+/// PSMs whose matched fragments explain at least `min_pct` percent of the total ion current
+#[pyfunction]
+pub fn filter_by_explained_intensity(psms: Vec<PyFeature>, min_pct: f32) -> Vec<PyFeature> {
+    psms.into_iter()
+        .filter(|psm| explained_intensity_pct(psm) >= min_pct)
+        .collect()
+}
+
+/// Median spectral angle of an intensity predictor per collision energy, see
+/// `calibrate_collision_energy`
+#[pyclass]
+#[derive(Clone, Debug)]
+pub struct PyCollisionEnergyCalibration {
+    pub calibrated_ce: f32,
+    pub curve: Vec<(f32, f32)>,
+}
+
+#[pymethods]
+impl PyCollisionEnergyCalibration {
+    /// Collision energy with the highest median spectral angle
+    #[getter]
+    pub fn calibrated_ce(&self) -> f32 {
+        self.calibrated_ce
+    }
+
+    /// (collision energy, median spectral angle) of all tested collision energies
+    #[getter]
+    pub fn curve(&self) -> Vec<(f32, f32)> {
+        self.curve.clone()
+    }
+}
+
+/// Calibrate the collision energy of a fragment intensity predictor (e.g. Prosit) on confident
+/// target PSMs (spectrum q-value <= `min_q_value`) with annotated fragments. For each collision
+/// energy of `ce_range` (inclusive, in steps of `step`), `predict_fn(psms, ce)` returns the
+/// predicted intensities of the annotated fragments of each PSM, which are compared to the
+/// observed ones by spectral angle. The collision energy of the highest median angle is returned
+#[pyfunction]
+pub fn calibrate_collision_energy(
+    py: Python,
+    psms: Vec<PyFeature>,
+    predict_fn: PyObject,
+    min_q_value: f32,
+    ce_range: (f32, f32),
+    step: f32,
+    num_threads: usize,
+) -> PyResult<PyCollisionEnergyCalibration> {
+    let (ce_min, ce_max) = ce_range;
+    if step <= 0.0 || ce_max < ce_min {
+        return Err(PyValueError::new_err(format!(
+            "Invalid collision energy range {:?} with step {}",
+            ce_range, step
+        )));
+    }
+    let anchors: Vec<PyFeature> = psms
+        .into_iter()
+        .filter(|p| p.inner.label == 1 && p.inner.spectrum_q <= min_q_value && p.inner.fragments.is_some())
+        .collect();
+    if anchors.is_empty() {
+        return Err(PyValueError::new_err(
+            "collision energy calibration requires confident target PSMs with annotated fragments",
+        ));
+    }
+    let observed: Vec<&[f32]> = anchors
+        .iter()
+        .map(|p| p.inner.fragments.as_ref().map_or(&[][..], |f| &f.intensities[..]))
+        .collect();
+
+    let pool = ThreadPoolBuilder::new()
+        .num_threads(num_threads)
+        .build()
+        .unwrap();
+
+    let steps = ((ce_max - ce_min) / step).floor() as usize;
+    let mut curve = Vec::with_capacity(steps + 1);
+    for k in 0..=steps {
+        let ce = ce_min + k as f32 * step;
+        let predicted: Vec<Vec<f32>> = predict_fn.call1(py, (anchors.clone(), ce))?.extract(py)?;
+        if predicted.len() != anchors.len() {
+            return Err(PyValueError::new_err(format!(
+                "predict_fn returned {} predictions for {} PSMs",
+                predicted.len(),
+                anchors.len()
+            )));
+        }
+
+        let angles: Vec<f32> = py.allow_threads(|| {
+            pool.install(|| {
+                observed
+                    .par_iter()
+                    .zip(predicted.par_iter())
+                    .filter(|(o, p)| o.len() == p.len())
+                    .map(|(o, p)| spectral_angle(o, p, 1e-7, true))
+                    .collect()
+            })
+        });
+        curve.push((ce, median(angles).unwrap_or(0.0) as f32));
+    }
+
+    let calibrated_ce = curve
+        .iter()
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map_or(ce_min, |(ce, _)| *ce);
+    Ok(PyCollisionEnergyCalibration { calibrated_ce, curve })
+}
+
+/// Set the calibrated collision energy of all PSMs
+#[pyfunction]
+pub fn apply_collision_energy_calibration(psms: Vec<PyFeature>, calibrated_ce: f32) -> Vec<PyFeature> {
+    let mut psms = psms;
+    for psm in psms.iter_mut() {
+        psm.collision_energy_calibrated = Some(calibrated_ce);
+    }
+    psms
+}
+
+/// Similarity measure of observed and predicted fragment intensities
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IntensityScoreType {
+    SpectralAngle,
+    Pearson,
+    DotProduct,
+}
+
+#[pyclass]
+#[derive(Clone)]
+pub struct PyIntensityScoreType {
+    pub inner: IntensityScoreType,
+}
+
+#[pymethods]
+impl PyIntensityScoreType {
+    #[new]
+    pub fn new(score_type: &str) -> PyResult<Self> {
+        let inner = match score_type.to_lowercase().as_str() {
+            "spectral_angle" => IntensityScoreType::SpectralAngle,
+            "pearson" => IntensityScoreType::Pearson,
+            "dot_product" => IntensityScoreType::DotProduct,
+            _ => {
+                return Err(PyValueError::new_err(format!(
+                    "Invalid intensity score type: {}, allowed values are: spectral_angle, pearson, dot_product",
+                    score_type
+                )))
+            }
+        };
+        Ok(PyIntensityScoreType { inner })
+    }
+
+    #[getter]
+    pub fn score_type(&self) -> String {
+        match self.inner {
+            IntensityScoreType::SpectralAngle => "spectral_angle".to_string(),
+            IntensityScoreType::Pearson => "pearson".to_string(),
+            IntensityScoreType::DotProduct => "dot_product".to_string(),
+        }
+    }
+}
+
+/// Fragment charges per ion type of the Prosit intensity layout
+const PREDICTED_FRAGMENT_CHARGES: usize = 3;
+
+/// Observed intensities of a PSM in the Prosit layout of `predicted`: for fragment ordinal 1, 2,
+/// ... the entries y+, y++, y+++, b+, b++, b+++. The most intense peak within the tolerance of
+/// each theoretical ion is taken, 0 if there is none. Entries predicted as negative (impossible
+/// ions) or beyond the peptide length are left out of both vectors
+fn observed_and_predicted(
+    spectrum: &ProcessedSpectrum,
+    peptide: &Peptide,
+    predicted: &[f32],
+    tolerance: Tolerance,
+) -> (Vec<f32>, Vec<f32>) {
+    let mut ions: HashMap<usize, f32> = HashMap::new();
+    let block = 2 * PREDICTED_FRAGMENT_CHARGES;
+    for kind in [Kind::B, Kind::Y] {
+        for (i, ion) in IonSeries::new(peptide, kind).enumerate() {
+            let (ordinal, offset) = match kind {
+                Kind::B => (i + 1, PREDICTED_FRAGMENT_CHARGES),
+                _ => (peptide.sequence.len() - i - 1, 0),
+            };
+            for charge in 1..=PREDICTED_FRAGMENT_CHARGES {
+                // peaks store m/z - proton, which is mass / charge for a fragment of `charge`
+                let mass = ion.monoisotopic_mass / charge as f32;
+                let intensity = most_intense_peak(spectrum, mass, tolerance).unwrap_or(0.0);
+                ions.insert((ordinal - 1) * block + offset + charge - 1, intensity);
+            }
+        }
+    }
+    predicted
+        .iter()
+        .enumerate()
+        .filter(|(_, p)| **p >= 0.0)
+        .filter_map(|(k, p)| ions.get(&k).map(|o| (*o, *p)))
+        .unzip()
+}
+
+fn intensity_similarity(observed: &[f32], predicted: &[f32], score_type: IntensityScoreType) -> f32 {
+    match score_type {
+        IntensityScoreType::SpectralAngle => spectral_angle(observed, predicted, 1e-7, true),
+        IntensityScoreType::Pearson => {
+            let observed: Vec<f64> = observed.iter().map(|x| *x as f64).collect();
+            let predicted: Vec<f64> = predicted.iter().map(|x| *x as f64).collect();
+            pearson(&observed, &predicted)
+        }
+        IntensityScoreType::DotProduct => {
+            let norm = |v: &[f32]| v.iter().map(|x| (*x as f64).powi(2)).sum::<f64>().sqrt();
+            let (norm_observed, norm_predicted) = (norm(observed), norm(predicted));
+            if norm_observed == 0.0 || norm_predicted == 0.0 {
+                return 0.0;
+            }
+            let dot: f64 = observed.iter().zip(predicted).map(|(o, p)| *o as f64 * *p as f64).sum();
+            (dot / (norm_observed * norm_predicted)) as f32
+        }
+    }
+}
+
+/// Set `intensity_similarity` of the PSMs to the similarity of their observed and predicted
+/// fragment intensities (e.g. of Prosit or AlphaPeptDeep). `spectra` maps the spec_id of a PSM
+/// to its spectrum, `predicted_intensities` maps `<modified sequence>/<charge>` (UNIMOD
+/// annotated, e.g. `PEPC[UNIMOD:4]TIDE/2`) to the predicted intensities in the Prosit layout
+/// (see `observed_and_predicted`). PSMs without spectrum or prediction are left unchanged
+#[pyfunction]
+pub fn rescore_collection_with_predictions(
+    py: Python,
+    psms: Vec<PyFeature>,
+    db: &PyIndexedDatabase,
+    spectra: HashMap<String, PyProcessedSpectrum>,
+    predicted_intensities: HashMap<String, Vec<f32>>,
+    score_type: &PyIntensityScoreType,
+    fragment_tol: Option<PyTolerance>,
+    num_threads: usize,
+) -> PyResult<Vec<PyFeature>> {
+    check_peptide_indices(&db.inner, &psms)?;
+    let tolerance = fragment_tol.map_or(Tolerance::Ppm(-20.0, 20.0), |t| t.inner);
+    let score_type = score_type.inner;
+    let mut psms = psms;
+
+    let pool = ThreadPoolBuilder::new()
+        .num_threads(num_threads)
+        .build()
+        .unwrap();
+
+    py.allow_threads(|| {
+        pool.install(|| {
+            psms.par_iter_mut().for_each(|psm| {
+                let spectrum = match spectra.get(&psm.inner.spec_id) {
+                    Some(spectrum) => &spectrum.inner,
+                    None => return,
+                };
+                let peptide = &db.inner[psm.inner.peptide_idx];
+                let key = format!("{}/{}", unimod_sequence(peptide), psm.inner.charge);
+                if let Some(predicted) = predicted_intensities.get(&key) {
+                    let (observed, predicted) = observed_and_predicted(spectrum, peptide, predicted, tolerance);
+                    psm.intensity_similarity = Some(intensity_similarity(&observed, &predicted, score_type));
+                }
+            })
+        })
+    });
+    Ok(psms)
+}
+
+/// Per ion type fragment coverage of a PSM, requires PSMs scored with `annotate_matches`
+#[pyfunction]
+pub fn compute_coverage_stats(psm: &PyFeature) -> PyFragmentCoverageStats {
+    PyFragmentCoverageStats::from_feature(&psm.inner)
+}
+
+/// `compute_coverage_stats` of many PSMs, computed in parallel
+#[pyfunction]
+pub fn compute_coverage_stats_batch(psms: Vec<PyFeature>, num_threads: usize) -> Vec<PyFragmentCoverageStats> {
+    let pool = ThreadPoolBuilder::new()
+        .num_threads(num_threads)
+        .build()
+        .unwrap();
+
+    pool.install(|| {
+        psms.par_iter()
+            .map(|psm| PyFragmentCoverageStats::from_feature(&psm.inner))
+            .collect()
+    })
+}
+
+/// PSMs with at least `min_b_coverage` of their b ions and `min_y_coverage` of their y ions matched
+#[pyfunction]
+pub fn filter_psms_by_coverage(psms: Vec<PyFeature>, min_b_coverage: f32, min_y_coverage: f32) -> Vec<PyFeature> {
+    psms.into_iter()
+        .filter(|psm| {
+            let stats = PyFragmentCoverageStats::from_feature(&psm.inner);
+            stats.b_coverage >= min_b_coverage && stats.y_coverage >= min_y_coverage
+        })
+        .collect()
+}
+
+/// Residues of de novo sequence tags, Ile is reported as the isobaric Leu
+const TAG_RESIDUES: &[u8; 19] = b"ACDEFGHKLMNPQRSTVWY";
+
+/// De novo sequence tag: residues read between consecutive fragment peaks, starting at the
+/// fragment m/z `start_mz`. The reading direction (b or y ladder) is unknown
+#[pyclass]
+#[derive(Clone, Debug)]
+pub struct PyDeNovoTag {
+    #[pyo3(get)]
+    pub sequence: String,
+    #[pyo3(get)]
+    pub start_mz: f32,
+    #[pyo3(get)]
+    pub length: u8,
+    #[pyo3(get)]
+    pub score: f32,
+}
+
+#[pymethods]
+impl PyDeNovoTag {
+    #[new]
+    pub fn new(sequence: String, start_mz: f32, score: f32) -> Self {
+        let length = sequence.len().min(u8::MAX as usize) as u8;
+        PyDeNovoTag {
+            sequence,
+            start_mz,
+            length,
+            score,
+        }
+    }
+
+    pub fn __repr__(&self) -> String {
+        format!(
+            "DeNovoTag(sequence: {}, start_mz: {:.4}, score: {:.3})",
+            self.sequence, self.start_mz, self.score
+        )
+    }
+}
+
+/// Sequence tags of a spectrum: peaks (singly charged) differing by a residue mass are linked,
+/// the highest scoring ladder ending at each peak is found by dynamic programming, its score is
+/// the summed relative intensity of its peaks. Ladders of at least `min_length` residues whose
+/// peaks are not all part of a better tag are reported, best first
+#[pyfunction]
+pub fn generate_sequence_tags(
+    spectrum: &PyProcessedSpectrum,
+    min_length: u8,
+    fragment_tol: &PyTolerance,
+    max_tags: Option<usize>,
+) -> Vec<PyDeNovoTag> {
+    let peaks = &spectrum.inner.peaks;
+    let max_intensity = peaks.iter().map(|p| p.intensity).fold(0.0f32, f32::max);
+    if peaks.is_empty() || max_intensity <= 0.0 {
+        return Vec::new();
+    }
+    let weight = |i: usize| peaks[i].intensity / max_intensity;
+    let residues: Vec<(u8, f32)> = TAG_RESIDUES.iter().map(|r| (*r, monoisotopic(*r))).collect();
+    let max_residue = residues.iter().map(|(_, m)| *m).fold(0.0f32, f32::max);
+
+    // best (score, length, predecessor, residue) of a ladder ending at each peak
+    let mut best: Vec<(f32, usize, Option<(usize, u8)>)> = (0..peaks.len()).map(|i| (weight(i), 0, None)).collect();
+    for j in 0..peaks.len() {
+        for i in (0..j).rev() {
+            let delta = peaks[j].mass - peaks[i].mass;
+            if delta > max_residue * 1.01 {
+                break;
+            }
+            let Some((residue, _)) = residues.iter().find(|(_, mass)| {
+                let (lo, hi) = fragment_tol.inner.bounds(peaks[i].mass + mass);
+                peaks[j].mass >= lo && peaks[j].mass <= hi
+            }) else {
+                continue;
+            };
+            let score = best[i].0 + weight(j);
+            if score > best[j].0 {
+                best[j] = (score, best[i].1 + 1, Some((i, *residue)));
+            }
+        }
+    }
+
+    let mut ends: Vec<usize> = (0..peaks.len()).filter(|j| best[*j].1 >= min_length.max(1) as usize).collect();
+    ends.sort_by(|a, b| best[*b].0.total_cmp(&best[*a].0));
+
+    let mut used = vec![false; peaks.len()];
+    let mut tags = Vec::new();
+    for end in ends {
+        let mut path = vec![end];
+        let mut sequence = Vec::new();
+        while let Some((previous, residue)) = best[*path.last().unwrap()].2 {
+            path.push(previous);
+            sequence.push(residue);
+        }
+        if path.iter().all(|i| used[*i]) {
+            continue;
+        }
+        path.iter().for_each(|i| used[*i] = true);
+        sequence.reverse();
+        tags.push(PyDeNovoTag {
+            sequence: String::from_utf8_lossy(&sequence).to_string(),
+            start_mz: peaks[*path.last().unwrap()].mass + PROTON,
+            length: sequence.len().min(u8::MAX as usize) as u8,
+            score: best[end].0,
+        });
+        if tags.len() >= max_tags.unwrap_or(20) {
+            break;
+        }
+    }
+    tags
+}
+
+/// Matched singly charged fragments of a peptide in a spectrum, as (kind, ordinal, fragment
+/// mass, matched peak)
+fn match_fragments<'a>(
+    spectrum: &'a ProcessedSpectrum,
+    peptide: &Peptide,
+    ion_kinds: &[Kind],
+    tolerance: Tolerance,
+) -> Vec<(Kind, usize, f32, &'a Peak)> {
+    ion_kinds
+        .iter()
+        .flat_map(|kind| IonSeries::new(peptide, *kind).enumerate().map(move |(i, ion)| (*kind, i, ion)))
+        .filter_map(|(kind, i, ion)| {
+            let ordinal = match kind {
+                Kind::B | Kind::A | Kind::C => i + 1,
+                _ => peptide.sequence.len() - i - 1,
+            };
+            most_intense_match(spectrum, ion.monoisotopic_mass, tolerance)
+                .map(|peak| (kind, ordinal, ion.monoisotopic_mass, peak))
+        })
+        .collect()
+}
+
+/// Longest run of consecutive ordinals
+fn longest_run(mut ordinals: Vec<usize>) -> u32 {
+    ordinals.sort_unstable();
+    ordinals.dedup();
+    let (mut longest, mut current) = (0, 0);
+    for (k, ordinal) in ordinals.iter().enumerate() {
+        current = if k > 0 && *ordinal == ordinals[k - 1] + 1 { current + 1 } else { 1 };
+        longest = longest.max(current);
+    }
+    longest
+}
+
+fn ln_factorial(n: usize) -> f64 {
+    (2..=n).map(|k| (k as f64).ln()).sum()
+}
+
+/// Hyperscore of fragment matches: log factorials of the matched b and y ions plus the log of
+/// their summed intensity
+fn match_hyperscore(matches: &[(Kind, usize, f32, &Peak)]) -> f64 {
+    let count = |kind: Kind| matches.iter().filter(|m| m.0 == kind).count();
+    let intensity: f32 = matches.iter().map(|m| m.3.intensity).sum();
+    ln_factorial(count(Kind::B)) + ln_factorial(count(Kind::Y)) + (intensity.max(1.0) as f64).ln()
+}
+
+/// Feature of a peptide scored by its fragment matches (see `match_fragments`) in a spectrum,
+/// with the matches as annotated fragments. Ranks and deltas are set by `rank_features`
+fn matched_feature(
+    spectrum: &ProcessedSpectrum,
+    db: &PyIndexedDatabase,
+    peptide_idx: PeptideIx,
+    charge: u8,
+    expmass: f32,
+    matches: &[(Kind, usize, f32, &Peak)],
+    scored_candidates: usize,
+) -> PyFeature {
+    let peptide = &db.inner[peptide_idx];
+    let ordinals = |kind: Kind| -> Vec<usize> { matches.iter().filter(|m| m.0 == kind).map(|m| m.1).collect() };
+    let total_intensity: f32 = spectrum.peaks.iter().map(|p| p.intensity).sum();
+    let intensity: f32 = matches.iter().map(|m| m.3.intensity).sum();
+    let average_ppm = match matches.len() {
+        0 => 0.0,
+        n => matches.iter().map(|m| ((m.3.mass - m.2) / m.2 * 1e6).abs()).sum::<f32>() / n as f32,
+    };
+    let longest_y = longest_run(ordinals(Kind::Y));
+
+    let mut feature = PyFeature::from(Feature {
+        peptide_idx,
+        psm_id: 0,
+        peptide_len: peptide.sequence.len(),
+        spec_id: spectrum.id.clone(),
+        file_id: spectrum.file_id,
+        rank: 0,
+        label: if peptide.decoy { -1 } else { 1 },
+        expmass,
+        calcmass: peptide.monoisotopic,
+        charge,
+        rt: spectrum.scan_start_time,
+        aligned_rt: spectrum.scan_start_time,
+        predicted_rt: 0.0,
+        delta_rt_model: 0.0,
+        delta_mass: match expmass > 0.0 {
+            true => (expmass - peptide.monoisotopic).abs() / peptide.monoisotopic * 1e6,
+            false => 0.0,
+        },
+        isotope_error: 0.0,
+        average_ppm,
+        hyperscore: match_hyperscore(matches),
+        delta_next: 0.0,
+        delta_best: 0.0,
+        matched_peaks: matches.len() as u32,
+        longest_b: longest_run(ordinals(Kind::B)),
+        longest_y,
+        longest_y_pct: longest_y as f32 / peptide.sequence.len() as f32,
+        missed_cleavages: peptide.missed_cleavages,
+        matched_intensity_pct: match total_intensity > 0.0 {
+            true => 100.0 * intensity / total_intensity,
+            false => 0.0,
+        },
+        scored_candidates: scored_candidates as u32,
+        poisson: 0.0,
+        discriminant_score: 0.0,
+        posterior_error: 1.0,
+        spectrum_q: 1.0,
+        peptide_q: 1.0,
+        protein_q: 1.0,
+        ms2_intensity: intensity,
+        fragments: Some(Fragments {
+            charges: vec![1; matches.len()],
+            kinds: matches.iter().map(|m| m.0).collect(),
+            fragment_ordinals: matches.iter().map(|m| m.1 as i32).collect(),
+            intensities: matches.iter().map(|m| m.3.intensity).collect(),
+            mz_calculated: matches.iter().map(|m| m.2 + PROTON).collect(),
+            mz_experimental: matches.iter().map(|m| m.3.mass + PROTON).collect(),
+        }),
+    });
+    feature.neutral_losses = vec![0.0; matches.len()];
+    feature
+}
+
+/// Sort features by decreasing hyperscore and set rank, delta_best and delta_next
+fn rank_features(features: &mut [PyFeature]) {
+    features.sort_by(|a, b| b.inner.hyperscore.total_cmp(&a.inner.hyperscore));
+    let best = features.first().map_or(0.0, |f| f.inner.hyperscore);
+    let scores: Vec<f64> = features.iter().map(|f| f.inner.hyperscore).collect();
+    for (i, feature) in features.iter_mut().enumerate() {
+        feature.inner.rank = i as u32 + 1;
+        feature.inner.delta_best = best - feature.inner.hyperscore;
+        feature.inner.delta_next = feature.inner.hyperscore - scores.get(i + 1).copied().unwrap_or(0.0);
+    }
+}
+
+/// Score the database peptides containing at least one of the tags (read in either direction,
+/// Ile as Leu) against a spectrum: hyperscore of the matched singly charged fragments of the
+/// database ion kinds, features are ranked by hyperscore. With a precursor tolerance, only
+/// peptides matching the precursor mass (at the precursor charge) are candidates.
+/// `tag_match_score` is the summed score of the tags a peptide contains
+#[pyfunction]
+pub fn score_with_sequence_tags(
+    spectrum: &PyProcessedSpectrum,
+    tags: Vec<PyDeNovoTag>,
+    db: &PyIndexedDatabase,
+    fragment_tol: &PyTolerance,
+    precursor_tol: Option<PyTolerance>,
+) -> Vec<PyFeature> {
+    let spectrum = &spectrum.inner;
+    let normalize = |s: &[u8]| -> Vec<u8> { s.iter().map(|r| if *r == b'I' { b'L' } else { *r }).collect() };
+    let patterns: Vec<(Vec<u8>, Vec<u8>, f32)> = tags
+        .iter()
+        .filter(|t| !t.sequence.is_empty())
+        .map(|t| {
+            let forward = normalize(t.sequence.as_bytes());
+            let reverse = forward.iter().rev().copied().collect();
+            (forward, reverse, t.score)
+        })
+        .collect();
+    let contains = |haystack: &[u8], needle: &[u8]| haystack.windows(needle.len()).any(|w| w == needle);
+
+    let precursor = spectrum.precursors.first();
+    let charge = precursor.and_then(|p| p.charge).unwrap_or(0);
+    let expmass = match (precursor, charge) {
+        (Some(p), c) if c > 0 => (p.mz - PROTON) * c as f32,
+        _ => 0.0,
+    };
+
+    let candidates: Vec<(usize, f32)> = db
+        .inner
+        .peptides
+        .par_iter()
+        .enumerate()
+        .filter(|(_, peptide)| match (&precursor_tol, expmass > 0.0) {
+            (Some(tolerance), true) => {
+                let (lo, hi) = tolerance.inner.bounds(peptide.monoisotopic);
+                expmass >= lo && expmass <= hi
+            }
+            _ => true,
+        })
+        .filter_map(|(idx, peptide)| {
+            let sequence = normalize(&peptide.sequence);
+            let matched: Vec<f32> = patterns
+                .iter()
+                .filter(|(forward, reverse, _)| contains(&sequence, forward) || contains(&sequence, reverse))
+                .map(|(_, _, score)| *score)
+                .collect();
+            (!matched.is_empty()).then(|| (idx, matched.iter().sum()))
+        })
+        .collect();
+
+    let mut features: Vec<PyFeature> = candidates
+        .par_iter()
+        .map(|(idx, tag_score)| {
+            let peptide_idx = PeptideIx(*idx as u32);
+            let matches = match_fragments(spectrum, &db.inner[peptide_idx], &db.inner.ion_kinds, fragment_tol.inner);
+            let mut feature = matched_feature(spectrum, db, peptide_idx, charge, expmass, &matches, candidates.len());
+            feature.tag_match_score = Some(*tag_score);
+            feature
+        })
+        .collect();
+
+    rank_features(&mut features);
+    features
+}
+
+/// Precursor charges of co-isolated peptide candidates
+const CHIMERA_CHARGES: RangeInclusive<u8> = 2..=4;
+
+/// Half width (Th) of the isolation window of spectra that do not report one
+const CHIMERA_ISOLATION_HALF_WIDTH: f32 = 1.0;
+
+/// Number of best individually scoring candidates combined into pairs
+const CHIMERA_CANDIDATES: usize = 50;
+
+/// Two peptides co-isolated in a chimeric spectrum, `feature_a` scores better on its own
+#[pyclass]
+#[derive(Clone)]
+pub struct PyChimeraPair {
+    #[pyo3(get)]
+    pub feature_a: PyFeature,
+    #[pyo3(get)]
+    pub feature_b: PyFeature,
+    /// Hyperscore of a plus the hyperscore of b on the peaks a leaves unexplained (or vice versa,
+    /// whichever is higher), so that shared peaks count once
+    #[pyo3(get)]
+    pub combined_score: f64,
+    /// Precursor m/z of b relative to a, in ppm
+    #[pyo3(get)]
+    pub mass_pair_ppm: f32,
+}
+
+/// Score a spectrum for pairs of co-isolated peptides: all peptides whose precursor m/z (at
+/// charges 2 to 4) falls into the isolation window, widened by `mass_tolerance`, are scored on
+/// their own, the best `CHIMERA_CANDIDATES` are combined into pairs. Returns the `max_pairs`
+/// best pairs by combined score, their features carry it as `chimera_score`
+#[pyfunction]
+pub fn score_chimera_with_mass_pairs(
+    py: Python,
+    db: &PyIndexedDatabase,
+    spectrum: &PyProcessedSpectrum,
+    mass_tolerance: &PyTolerance,
+    max_pairs: usize,
+    fragment_tolerance: Option<PyTolerance>,
+) -> Vec<PyChimeraPair> {
+    let fragment_tolerance = fragment_tolerance.map_or(Tolerance::Ppm(-20.0, 20.0), |t| t.inner);
+    py.allow_threads(|| chimera_pairs(db, &spectrum.inner, mass_tolerance.inner, max_pairs, fragment_tolerance))
+}
+
+fn chimera_pairs(
+    db: &PyIndexedDatabase,
+    spectrum: &ProcessedSpectrum,
+    mass_tolerance: Tolerance,
+    max_pairs: usize,
+    fragment_tolerance: Tolerance,
+) -> Vec<PyChimeraPair> {
+    let Some(precursor) = spectrum.precursors.first() else {
+        return Vec::new();
+    };
+    let window = precursor
+        .isolation_window
+        .unwrap_or(Tolerance::Da(-CHIMERA_ISOLATION_HALF_WIDTH, CHIMERA_ISOLATION_HALF_WIDTH));
+    let (lo_mz, hi_mz) = window.bounds(precursor.mz);
+    let (lo_mz, hi_mz) = (mass_tolerance.bounds(lo_mz).0, mass_tolerance.bounds(hi_mz).1);
+
+    let peptides = &db.inner.peptides;
+    let candidates: Vec<(PeptideIx, u8)> = CHIMERA_CHARGES
+        .flat_map(|charge| {
+            let (lo, hi) = ((lo_mz - PROTON) * charge as f32, (hi_mz - PROTON) * charge as f32);
+            let start = peptides.partition_point(|p| p.monoisotopic < lo);
+            let end = peptides.partition_point(|p| p.monoisotopic <= hi);
+            (start..end).map(move |idx| (PeptideIx(idx as u32), charge))
+        })
+        .collect();
+
+    let mut scored: Vec<(PeptideIx, u8, Vec<(Kind, usize, f32, &Peak)>, f64)> = candidates
+        .par_iter()
+        .map(|&(peptide_idx, charge)| {
+            let matches = match_fragments(spectrum, &db.inner[peptide_idx], &db.inner.ion_kinds, fragment_tolerance);
+            let score = match_hyperscore(&matches);
+            (peptide_idx, charge, matches, score)
+        })
+        .collect();
+    scored.sort_by(|a, b| b.3.total_cmp(&a.3));
+    scored.truncate(CHIMERA_CANDIDATES);
+
+    // hyperscore of `matches` on the peaks not explained by `explained`
+    let residual = |matches: &[(Kind, usize, f32, &Peak)], explained: &[(Kind, usize, f32, &Peak)]| {
+        let used: HashSet<*const Peak> = explained.iter().map(|m| m.3 as *const Peak).collect();
+        let remaining: Vec<_> = matches
+            .iter()
+            .copied()
+            .filter(|m| !used.contains(&(m.3 as *const Peak)))
+            .collect();
+        match_hyperscore(&remaining)
+    };
+
+    let mut pairs: Vec<(usize, usize, f64)> = (0..scored.len())
+        .flat_map(|a| (a + 1..scored.len()).map(move |b| (a, b)))
+        .filter(|&(a, b)| scored[a].0 != scored[b].0)
+        .map(|(a, b)| {
+            let (a_first, b_first) = (
+                scored[a].3 + residual(&scored[b].2, &scored[a].2),
+                scored[b].3 + residual(&scored[a].2, &scored[b].2),
+            );
+            (a, b, a_first.max(b_first))
+        })
+        .collect();
+    pairs.sort_by(|x, y| y.2.total_cmp(&x.2));
+    pairs.truncate(max_pairs);
+
+    let mz = |i: usize| {
+        let (idx, charge) = (scored[i].0, scored[i].1);
+        db.inner[idx].monoisotopic / charge as f32 + PROTON
+    };
+    let feature = |i: usize, combined_score: f64| {
+        let (peptide_idx, charge, matches, _) = &scored[i];
+        let expmass = (precursor.mz - PROTON) * *charge as f32;
+        let mut feature =
+            matched_feature(spectrum, db, *peptide_idx, *charge, expmass, matches, candidates.len());
+        feature.chimera_score = Some(combined_score);
+        feature
+    };
+    pairs
+        .into_iter()
+        .map(|(a, b, combined_score)| PyChimeraPair {
+            feature_a: feature(a, combined_score),
+            feature_b: feature(b, combined_score),
+            combined_score,
+            mass_pair_ppm: (mz(b) - mz(a)) / mz(a) * 1e6,
+        })
+        .collect()
+}
+
+#[pymodule]
+pub fn scoring(_py: Python, m: &PyModule) -> PyResult<()> {
+    m.add_class::<PyFragments>()?;
+    m.add_class::<PyFeature>()?;
+    m.add_class::<PyScoreType>()?;
+    m.add_class::<PyImsUnit>()?;
+    m.add_class::<PySpectralEntropyMode>()?;
+    m.add_class::<PyScorer>()?;
+    m.add_class::<PyCancellationToken>()?;
+    m.add_class::<PyGlycopeptideScoringMode>()?;
+    m.add_class::<PyGlycopeptideMatch>()?;
+    m.add_class::<PySearchConfiguration>()?;
+    m.add_class::<PyMassCalibration>()?;
+    m.add_function(wrap_pyfunction!(search_hash, m)?)?;
+    m.add_function(wrap_pyfunction!(tdc_q_values, m)?)?;
+    m.add_function(wrap_pyfunction!(score_by_length_stratum, m)?)?;
+    m.add_function(wrap_pyfunction!(recalibrate_masses, m)?)?;
+    m.add_class::<PyMassErrorOffset>()?;
+    m.add_function(wrap_pyfunction!(calibrate_mass_error, m)?)?;
+    m.add_function(wrap_pyfunction!(calibrate_fragment_error, m)?)?;
+    m.add_function(wrap_pyfunction!(apply_mass_calibration, m)?)?;
+    m.add_function(wrap_pyfunction!(apply_fragment_calibration, m)?)?;
+    m.add_function(wrap_pyfunction!(calibrate_fragment_masses, m)?)?;
+    m.add_function(wrap_pyfunction!(rescore_with_fragment_calibration, m)?)?;
+    m.add_class::<PyMassAccuracyStats>()?;
+    m.add_function(wrap_pyfunction!(compute_mass_accuracy_stats, m)?)?;
+    m.add_function(wrap_pyfunction!(mass_accuracy_over_time, m)?)?;
+    m.add_function(wrap_pyfunction!(detect_mass_calibration_drift, m)?)?;
+    m.add_class::<PyDeNovoTag>()?;
+    m.add_function(wrap_pyfunction!(generate_sequence_tags, m)?)?;
+    m.add_function(wrap_pyfunction!(score_with_sequence_tags, m)?)?;
+    m.add_class::<PyChimeraPair>()?;
+    m.add_function(wrap_pyfunction!(score_chimera_with_mass_pairs, m)?)?;
+    m.add_function(wrap_pyfunction!(psms_to_arrow_ipc, m)?)?;
+    m.add_function(wrap_pyfunction!(psms_from_arrow_ipc, m)?)?;
+    m.add_function(wrap_pyfunction!(psms_to_parquet, m)?)?;
+    m.add_function(wrap_pyfunction!(psms_from_parquet, m)?)?;
+    m.add_function(wrap_pyfunction!(to_records, m)?)?;
+    m.add_function(wrap_pyfunction!(group_by_spectrum, m)?)?;
+    m.add_function(wrap_pyfunction!(as_flat_vec, m)?)?;
+    m.add_function(wrap_pyfunction!(top_psm_per_spectrum, m)?)?;
+    m.add_function(wrap_pyfunction!(compute_delta_scores, m)?)?;
+    m.add_function(wrap_pyfunction!(delta_score_distribution, m)?)?;
+    m.add_function(wrap_pyfunction!(score_threshold_from_delta, m)?)?;
+    m.add_function(wrap_pyfunction!(compute_explained_intensity, m)?)?;
+    m.add_function(wrap_pyfunction!(filter_by_explained_intensity, m)?)?;
+    m.add_class::<PyFragmentCoverageStats>()?;
+    m.add_function(wrap_pyfunction!(compute_coverage_stats, m)?)?;
+    m.add_function(wrap_pyfunction!(compute_coverage_stats_batch, m)?)?;
+    m.add_class::<PyCollisionEnergyCalibration>()?;
+    m.add_function(wrap_pyfunction!(calibrate_collision_energy, m)?)?;
+    m.add_function(wrap_pyfunction!(apply_collision_energy_calibration, m)?)?;
+    m.add_class::<PyIntensityScoreType>()?;
+    m.add_function(wrap_pyfunction!(rescore_collection_with_predictions, m)?)?;
+    m.add_function(wrap_pyfunction!(filter_psms_by_coverage, m)?)?;
+    m.add_function(wrap_pyfunction!(annotate_silac_pairs, m)?)?;
+    m.add_function(wrap_pyfunction!(localize_modification, m)?)?;
+    m.add_function(wrap_pyfunction!(rescore_with_function, m)?)?;
+    m.add_function(wrap_pyfunction!(features_to_matrix, m)?)?;
+    m.add_function(wrap_pyfunction!(delta_mass_histogram, m)?)?;
+    m.add_function(wrap_pyfunction!(delta_mass_peaks, m)?)?;
+    m.add_function(wrap_pyfunction!(isotope_annotation_score, m)?)?;
+    m.add_function(wrap_pyfunction!(compute_spectral_entropy, m)?)?;
+    m.add_function(wrap_pyfunction!(score_with_ms1_isotope_evidence, m)?)?;
+    m.add_function(wrap_pyfunction!(total_isotope_annotation_score, m)?)?;
+    m.add_function(wrap_pyfunction!(annotate_isotope_annotation_scores, m)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::py_database::{index_peptides, theoretical_fragments};
+    use sage_core::enzyme::Position;
+    use sage_core::spectrum::Representation;
+
+    /// Unmodified, fully enzymatic peptide of a protein named after its sequence
+    pub(crate) fn search_peptide(sequence: &str, decoy: bool) -> Peptide {
+        Peptide {
+            decoy,
+            sequence: Arc::from(sequence.as_bytes().to_vec().into_boxed_slice()),
+            modifications: vec![0.0; sequence.len()],
+            nterm: None,
+            cterm: None,
+            monoisotopic: sequence.bytes().map(monoisotopic).sum::<f32>() + H2O,
+            missed_cleavages: 0,
+            position: Position::Full,
+            proteins: vec![Arc::new(format!("{}sp|P1|{}", if decoy { "rev_" } else { "" }, sequence))],
+            semi_enzymatic: false,
+        }
+    }
+
+    pub(crate) fn search_database(peptides: Vec<Peptide>) -> PyIndexedDatabase {
+        let ion_kinds = vec![Kind::B, Kind::Y];
+        let fragments = theoretical_fragments(&peptides, &ion_kinds, 2, 150.0, 2000.0);
+        PyIndexedDatabase::from_index(index_peptides(
+            peptides,
+            fragments,
+            ion_kinds,
+            Vec::new(),
+            8192,
+            false,
+            "rev_".to_string(),
+        ))
+    }
+
+    /// Doubly charged precursor of `precursor` with the singly charged b and y ions of `fragments`
+    pub(crate) fn search_spectrum(
+        spec_id: &str,
+        precursor: &Peptide,
+        fragments: &[(&Peptide, usize)],
+    ) -> PyProcessedSpectrum {
+        let mut mz: Vec<f32> = fragments
+            .iter()
+            .flat_map(|(peptide, count)| {
+                [Kind::B, Kind::Y]
+                    .into_iter()
+                    .flat_map(|kind| IonSeries::new(peptide, kind).skip(1).take(*count))
+                    .map(|ion| ion.monoisotopic_mass + PROTON)
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        mz.sort_by(|a, b| a.total_cmp(b));
+        let intensity = vec![100.0; mz.len()];
+        let precursor_mz = (precursor.monoisotopic + 2.0 * PROTON) / 2.0;
+        PyProcessedSpectrum::from_arrays(spec_id.to_string(), precursor_mz, 2, mz, intensity, 0.0, None).unwrap()
+    }
+
+    pub(crate) fn search_scorer(score_type: ScoreType) -> PyScorer {
+        let tolerance = || PyTolerance { inner: Tolerance::Ppm(-10.0, 10.0) };
+        PyScorer::new(
+            tolerance(),
+            tolerance(),
+            2,
+            0,
+            0,
+            2,
+            2,
+            150.0,
+            2000.0,
+            false,
+            1,
+            false,
+            false,
+            None,
+            Some(PyScoreType { inner: score_type }),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+    }
+
+    fn raw_spectrum(mz: Vec<f32>, intensity: Vec<f32>) -> RawSpectrum {
+        RawSpectrum {
+            file_id: 0,
+            ms_level: 2,
+            id: "scan=1".to_string(),
+            precursors: Vec::new(),
+            representation: Representation::Centroid,
+            scan_start_time: 0.0,
+            ion_injection_time: 0.0,
+            total_ion_current: intensity.iter().sum(),
+            mz,
+            intensity,
+        }
+    }
+
+    #[test]
+    fn isotope_score_uses_charge_dependent_spacing() {
+        let tolerance = Tolerance::Ppm(-10.0, 10.0);
+        let mass = (500.0 - PROTON) * 2.0;
+        let m1 = 100.0 * mass * AVERAGINE_M1_RATIO_PER_DA;
+        // a peak at the singly charged M+1 spacing must not be taken as the isotope peak
+        let spectrum = raw_spectrum(
+            vec![500.0, 500.0 + NEUTRON / 2.0, 500.0 + NEUTRON],
+            vec![100.0, m1, 10.0],
+        );
+
+        assert!((isotope_score(&spectrum, 500.0, 2, tolerance) - 1.0).abs() < 1e-3);
+        assert!(isotope_score(&spectrum, 500.0, 1, tolerance) < 0.5);
+    }
+
+    #[test]
+    fn isotope_score_without_isotope_peak_is_zero() {
+        let tolerance = Tolerance::Ppm(-10.0, 10.0);
+        let spectrum = raw_spectrum(vec![500.0, 620.0], vec![100.0, 50.0]);
+
+        assert_eq!(isotope_score(&spectrum, 500.0, 2, tolerance), 0.0);
+        assert_eq!(isotope_score(&spectrum, 400.0, 2, tolerance), 0.0);
+    }
+
+    #[test]
+    fn isotope_annotation_score_is_nan_until_annotated() {
+        let mut psm = PyFeature::from(crate::py_io::default_feature());
+        let unannotated = psm.clone();
+        assert!(psm.isotope_annotation_score.is_nan());
+
+        let mass = (500.0 - PROTON) * 2.0;
+        let m1 = 100.0 * mass * AVERAGINE_M1_RATIO_PER_DA;
+        let spectrum = PyRawSpectrum { inner: raw_spectrum(vec![500.0, 500.0 + NEUTRON / 2.0], vec![100.0, m1]) };
+        psm.inner.fragments = Some(Fragments {
+            charges: vec![2],
+            kinds: vec![Kind::Y],
+            fragment_ordinals: vec![5],
+            intensities: vec![100.0],
+            mz_calculated: vec![500.0],
+            mz_experimental: vec![500.0],
+        });
+        let tolerance = PyTolerance { inner: Tolerance::Ppm(-10.0, 10.0) };
+
+        let annotated = annotate_isotope_annotation_scores(vec![psm, unannotated], &spectrum, &tolerance);
+        assert!((annotated[0].isotope_annotation_score - 1.0).abs() < 1e-3);
+        assert!(annotated[1].isotope_annotation_score.is_nan());
+    }
+
+    #[test]
+    fn relative_fragment_intensity_threshold_drops_peaks_and_their_ion_current() {
+        let peak = |mass: f32, intensity: f32| Peak { mass, intensity };
+        let spectrum = ProcessedSpectrum {
+            level: 2,
+            id: "scan=1".to_string(),
+            file_id: 0,
+            scan_start_time: 0.0,
+            ion_injection_time: 0.0,
+            precursors: Vec::new(),
+            peaks: vec![peak(200.0, 1000.0), peak(300.0, 10.0), peak(400.0, 9.9), peak(500.0, 0.5)],
+            total_ion_current: 1020.4,
+        };
+
+        // 1% of the base peak: 10.0 is kept, 9.9 and 0.5 are dropped
+        let filtered = filter_fragment_peaks(&spectrum, None, Some(0.01));
+        let kept: Vec<f32> = filtered.peaks.iter().map(|p| p.intensity).collect();
+        assert_eq!(kept, vec![1000.0, 10.0]);
+        assert!((filtered.total_ion_current - 1010.0).abs() < 1e-3);
+
+        // the stricter of both thresholds applies
+        let filtered = filter_fragment_peaks(&spectrum, Some(100.0), Some(0.01));
+        assert_eq!(filtered.peaks.len(), 1);
+        assert!(matches!(filter_fragment_peaks(&spectrum, None, None), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn ims_bands_follow_the_precursor_of_a_spectrum() {
+        let spectrum = PyProcessedSpectrum::from_arrays(
+            "scan=1".to_string(),
+            600.0,
+            2,
+            vec![200.0],
+            vec![100.0],
+            10.0,
+            None,
+        )
+        .unwrap();
+        assert_eq!(spectrum_ims_bands(&spectrum, 0.05, ImsUnit::AbsoluteVm), None);
+
+        let spectrum = spectrum.with_precursor_ims(0.93);
+        let bands = spectrum_ims_bands(&spectrum, 0.05, ImsUnit::AbsoluteVm).unwrap();
+        assert_eq!((bands.charge, bands.first, bands.last), (2, 17, 19));
+        // 5% of 0.93 in bands of 0.05
+        let bands = spectrum_ims_bands(&spectrum, 5.0, ImsUnit::RelativePct).unwrap();
+        assert_eq!((bands.first, bands.last), (17, 19));
+    }
+
+    #[test]
+    fn xcorr_ranks_the_matching_peptide_first() {
+        // isobaric peptides sharing part of their b or y ions with the target
+        let target = search_peptide("PEPTIDEK", false);
+        let peptides = vec![
+            target.clone(),
+            search_peptide("PEPTIKDE", false),
+            search_peptide("TPEPIDEK", false),
+        ];
+        let db = search_database(peptides);
+        let spectrum = search_spectrum("1", &target, &[(&target, 5)]);
+        let mut scorer = search_scorer(ScoreType::XCorr);
+        scorer.report_psms = 3;
+
+        for bin_width in [None, Some(0.5)] {
+            scorer.xcorr_bin_width = bin_width;
+            let psms = scorer.score(&db, &spectrum, None, None);
+            assert!(psms.len() > 1, "{} PSMs at bin width {:?}", psms.len(), bin_width);
+            assert_eq!(&*db.inner[psms[0].inner.peptide_idx].sequence, b"PEPTIDEK");
+            assert_eq!(psms[0].inner.rank, 1);
+
+            let xcorr: Vec<f32> = psms.iter().map(|psm| psm.xcorr.unwrap()).collect();
+            assert!(xcorr[0] > xcorr[1], "xcorr {:?} at bin width {:?}", xcorr, bin_width);
+            assert!(xcorr.windows(2).all(|w| w[0] >= w[1]));
+            assert!((psms[0].delta_xcorr.unwrap() - (xcorr[0] - xcorr[1]) / xcorr[0]).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn ims_tolerance_narrows_candidates_but_keeps_identifications() {
+        // isobaric peptides, told apart by their predicted 1/K0 only
+        let sequences = ["PEPTIDEK", "PEPTIKDE", "PEPKTIDE", "KPEPTIDE"];
+        let peptides: Vec<Peptide> = sequences.iter().map(|s| search_peptide(s, false)).collect();
+        let mut db = search_database(peptides.clone());
+        let predicted_ims = |p: &Peptide| 0.6 + 0.05 * p.sequence.iter().position(|r| *r == b'K').unwrap() as f32;
+        db.set_ims_predictions(|p| [predicted_ims(p); crate::py_database::IMS_CHARGES]);
+
+        let spectra: Vec<PyProcessedSpectrum> = [0, 2]
+            .iter()
+            .map(|&i| {
+                let spectrum = search_spectrum(sequences[i], &peptides[i], &[(&peptides[i], 3)]);
+                spectrum.with_precursor_ims(predicted_ims(&peptides[i]))
+            })
+            .collect();
+        let mut scorer = search_scorer(ScoreType::Standard);
+        let unfiltered: Vec<Vec<PyFeature>> = spectra.iter().map(|s| scorer.score(&db, s, None, None)).collect();
+        scorer.ims_tolerance = Some((0.03, PyImsUnit { inner: ImsUnit::AbsoluteVm }));
+        let filtered: Vec<Vec<PyFeature>> = spectra.iter().map(|s| scorer.score(&db, s, None, None)).collect();
+
+        for (unfiltered, filtered) in unfiltered.iter().zip(filtered.iter()) {
+            assert!(
+                filtered[0].inner.scored_candidates < unfiltered[0].inner.scored_candidates,
+                "{} candidates with and {} without the ion mobility tolerance",
+                filtered[0].inner.scored_candidates,
+                unfiltered[0].inner.scored_candidates
+            );
+            // peptide indices of the candidate subset are mapped back to the full database
+            assert_eq!(filtered[0].inner.peptide_idx, unfiltered[0].inner.peptide_idx);
+            assert_eq!(filtered[0].inner.hyperscore, unfiltered[0].inner.hyperscore);
+        }
+        let identified = |psms: &[PyFeature]| db.inner[psms[0].inner.peptide_idx].sequence.clone();
+        assert_eq!(&*identified(&filtered[0]), b"PEPTIDEK");
+        assert_eq!(&*identified(&filtered[1]), b"PEPKTIDE");
+    }
+
+    fn arrow_test_psm() -> PyFeature {
+        let mut psm = PyFeature::from(Feature {
+            peptide_idx: PeptideIx(3),
+            spec_id: "scan=7".to_string(),
+            matched_intensity_pct: 40.0,
+            hyperscore: 25.0,
+            ..crate::py_io::default_feature()
+        });
+        psm.protein_q_razor = 0.01;
+        psm.chimera_score = Some(31.5);
+        psm.peptide_sequence = Some("PEPTIDEK".to_string());
+        psm.ims = Some(0.95);
+        psm.intensity_weighted_ppm = 1.5;
+        psm.max_fragment_ppm = 4.0;
+        psm
+    }
+
+    #[test]
+    fn ml_feature_vector_follows_the_feature_names() {
+        let feature = Feature {
+            hyperscore: 25.0,
+            delta_rt_model: 0.5,
+            peptide_len: 9,
+            charge: 2,
+            isotope_error: 1.0,
+            ..crate::py_io::default_feature()
+        };
+        let mut psm = PyFeature::from(feature);
+        let position = |name: &str| ML_FEATURE_NAMES.iter().position(|n| *n == name).unwrap();
+
+        let vector = ml_feature_vector(&psm);
+        assert_eq!(vector.len(), 23);
+        assert_eq!(vector[position("hyperscore")], 25.0);
+        assert_eq!(vector[position("delta_rt_model")], 0.5);
+        assert!(vector[position("ims")].is_nan() && vector[position("delta_ims_model")].is_nan());
+        assert!(vector[position("intensity_weighted_ppm")].is_nan());
+        assert_eq!(vector[position("peptide_len")], 9.0);
+        assert_eq!(vector[position("charge")], 2.0);
+        assert_eq!(vector[position("isotope_error")], 1.0);
+
+        psm.ims = Some(0.9);
+        psm.delta_ims_model = Some(0.25);
+        let vector = ml_feature_vector(&psm);
+        assert!((vector[position("ims")] - 0.9).abs() < 1e-6);
+        assert_eq!(vector[position("delta_ims_model")], 0.25);
+    }
+
+    #[test]
+    fn rescoring_function_sets_re_score() {
+        let psm = |hyperscore: f64, peptide_q: f32| {
+            PyFeature::from(Feature {
+                hyperscore,
+                peptide_q,
+                ..crate::py_io::default_feature()
+            })
+        };
+        let mut psms = vec![psm(30.0, 0.001), psm(12.0, 0.1)];
+        let values = psms.iter().map(feature_values).collect();
+        let position = |name: &str| FEATURE_NAMES.iter().position(|n| *n == name).unwrap();
+        let (hyperscore, peptide_q) = (position("hyperscore"), position("peptide_q"));
+
+        apply_re_scores(&mut psms, values, |v| Ok(v[hyperscore] * -v[peptide_q].log10())).unwrap();
+
+        assert!((psms[0].re_score.unwrap() - 90.0).abs() < 1e-3);
+        assert!((psms[1].re_score.unwrap() - 12.0).abs() < 1e-3);
+        assert_eq!(psms[0].inner.discriminant_score, 0.0);
+    }
+
+    #[test]
+    fn arrow_chunk_round_trip() {
+        let chunk = psms_to_chunk(&[arrow_test_psm()]).unwrap();
+        let mut psms = Vec::new();
+        psms_from_chunk(&psm_arrow_schema(), &chunk, &mut psms).unwrap();
+
+        assert_eq!(psms.len(), 1);
+        assert_eq!(psms[0].inner.peptide_idx, PeptideIx(3));
+        assert_eq!(psms[0].inner.spec_id, "scan=7");
+        assert_eq!(psms[0].protein_q_razor, 0.01);
+        assert_eq!(psms[0].chimera_score, Some(31.5));
+        assert_eq!(psms[0].peptide_sequence.as_deref(), Some("PEPTIDEK"));
+        assert_eq!(psms[0].ims, Some(0.95));
+        assert_eq!(psms[0].delta_ims_model, None);
+        assert_eq!((psms[0].intensity_weighted_ppm, psms[0].max_fragment_ppm), (1.5, 4.0));
+    }
+
+    /// Keys of `to_dict` that have no Arrow column
+    #[cfg(not(feature = "extension-module"))]
+    const DICT_ONLY_KEYS: [&str; 3] = ["fragments", "localization_scores", "coverage_stats"];
+
+    // needs an embedded interpreter, run with `cargo test --no-default-features`
+    #[cfg(not(feature = "extension-module"))]
+    #[test]
+    fn dict_round_trip_matches_the_arrow_schema() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let mut psm = arrow_test_psm();
+            psm.inverse_ion_mobility_predicted = Some(0.92);
+            psm.delta_ims_model = Some(0.03);
+            let dict = psm.to_dict(py);
+
+            let mut keys: Vec<&str> = dict
+                .keys()
+                .map(String::as_str)
+                .filter(|key| !DICT_ONLY_KEYS.contains(key))
+                .collect();
+            let mut columns: Vec<&str> = PSM_ARROW_SCHEMA.iter().map(|(name, _, _)| *name).collect();
+            keys.sort_unstable();
+            columns.sort_unstable();
+            assert_eq!(keys, columns);
+
+            let read = PyFeature::from_dict(py, dict).unwrap();
+            assert_eq!(read.ims, Some(0.95));
+            assert_eq!(read.inverse_ion_mobility_predicted, Some(0.92));
+            assert_eq!(read.delta_ims_model, Some(0.03));
+            assert_eq!(read.peptide_sequence.as_deref(), Some("PEPTIDEK"));
+        });
+    }
+
+    /// PSM with random scores, every optional value set or unset at random
+    #[cfg(not(feature = "extension-module"))]
+    fn random_psm(state: &mut u64) -> PyFeature {
+        use crate::py_retention_alignment::splitmix64;
+        let uniform = |state: &mut u64| (splitmix64(state) % 1_000_000) as f32 / 1_000.0;
+        let maybe = |state: &mut u64| splitmix64(state) % 2 == 0;
+
+        let fragments = maybe(state).then(|| {
+            let n = 1 + splitmix64(state) as usize % 6;
+            Fragments {
+                charges: (0..n).map(|_| (1 + splitmix64(state) % 3) as i32).collect(),
+                kinds: (0..n).map(|i| if i % 2 == 0 { Kind::B } else { Kind::Y }).collect(),
+                fragment_ordinals: (0..n as i32).collect(),
+                intensities: (0..n).map(|_| uniform(state)).collect(),
+                mz_calculated: (0..n).map(|_| uniform(state)).collect(),
+                mz_experimental: (0..n).map(|_| uniform(state)).collect(),
+            }
+        });
+        let mut psm = PyFeature::from(Feature {
+            psm_id: splitmix64(state) as usize % 10_000,
+            spec_id: format!("scan={}", splitmix64(state) % 10_000),
+            label: if maybe(state) { 1 } else { -1 },
+            charge: 1 + (splitmix64(state) % 4) as u8,
+            rt: uniform(state),
+            hyperscore: uniform(state) as f64,
+            spectrum_q: uniform(state) / 1_000.0,
+            fragments,
+            ..crate::py_io::default_feature()
+        });
+        let num_fragments = psm.inner.fragments.as_ref().map_or(0, |f| f.charges.len());
+        psm.neutral_losses = (0..num_fragments).map(|_| uniform(state)).collect();
+        if maybe(state) {
+            psm.isotope_annotation_score = uniform(state) / 1_000.0;
+        }
+        psm.xcorr = maybe(state).then(|| uniform(state));
+        psm.delta_xcorr = maybe(state).then(|| uniform(state));
+        psm.localization_scores = maybe(state).then(|| vec![uniform(state), uniform(state)]);
+        psm.best_localization_site = maybe(state).then(|| splitmix64(state) as usize % 30);
+        psm.spectral_entropy = uniform(state) / 1_000.0;
+        psm.has_oxonium_evidence = maybe(state);
+        psm.silac_pair_idx = maybe(state).then(|| PeptideIx(splitmix64(state) as u32));
+        psm.coverage_stats = maybe(state).then(|| PyFragmentCoverageStats::from_feature(&psm.inner));
+        psm.collision_energy_calibrated = maybe(state).then(|| uniform(state));
+        psm.precursor_purity = maybe(state).then(|| uniform(state) / 1_000.0);
+        psm.tag_match_score = maybe(state).then(|| uniform(state));
+        psm.intensity_similarity = maybe(state).then(|| uniform(state) / 1_000.0);
+        psm.chimera_score = maybe(state).then(|| uniform(state) as f64);
+        psm.peptide_sequence = maybe(state).then(|| "PEPTIDEK".to_string());
+        psm.re_score = maybe(state).then(|| uniform(state) as f64);
+        psm.ims = maybe(state).then(|| uniform(state) / 1_000.0);
+        psm.inverse_ion_mobility_predicted = maybe(state).then(|| uniform(state) / 1_000.0);
+        psm.delta_ims_model = maybe(state).then(|| uniform(state) / 1_000.0);
+        psm
+    }
+
+    // needs an embedded interpreter, run with `cargo test --no-default-features`
+    #[cfg(not(feature = "extension-module"))]
+    #[test]
+    fn dict_round_trip_of_random_psms() {
+        pyo3::prepare_freethreaded_python();
+        let mut state = 1281;
+        Python::with_gil(|py| {
+            for _ in 0..200 {
+                let psm = random_psm(&mut state);
+                let dict = psm.to_dict(py);
+                assert_eq!(dict.len(), PSM_ARROW_SCHEMA.len() + DICT_ONLY_KEYS.len());
+
+                // Debug output compares NaN (unset scores) as equal
+                let read = PyFeature::from_dict(py, dict).unwrap();
+                assert_eq!(format!("{:?}", read), format!("{:?}", psm));
+            }
+        });
+    }
+
+    #[test]
+    fn arrow_chunk_without_later_columns_reads_defaults() {
+        let first_optional = PSM_ARROW_SCHEMA
+            .iter()
+            .position(|(name, _, _)| *name == "best_localization_site")
+            .unwrap();
+        let schema = Schema::from(
+            PSM_ARROW_SCHEMA[..first_optional]
+                .iter()
+                .map(|(name, data_type, nullable)| Field::new(*name, data_type.clone(), *nullable))
+                .collect::<Vec<_>>(),
+        );
+        let chunk = psms_to_chunk(&[arrow_test_psm()]).unwrap();
+        let chunk = Chunk::new(chunk.arrays()[..first_optional].to_vec());
+
+        let mut psms = Vec::new();
+        psms_from_chunk(&schema, &chunk, &mut psms).unwrap();
+
+        assert_eq!(psms[0].inner.hyperscore, 25.0);
+        assert_eq!(psms[0].protein_q_razor, 1.0);
+        assert_eq!(psms[0].unexplained_intensity_pct, 60.0);
+        assert_eq!(psms[0].chimera_score, None);
+        assert_eq!(psms[0].peptide_sequence, None);
+        assert!(psms[0].intensity_weighted_ppm.is_nan());
+    }
+
+    #[test]
+    fn short_ligand_scores_in_its_length_stratum() {
+        // an HLA class I 9-mer, its reversed decoy and a tryptic peptide
+        let ligand = search_peptide("GILGFVFTL", false);
+        let decoy = search_peptide("TFVFGLIGL", true);
+        let tryptic = search_peptide("TPEVDDEALEKFDK", false);
+        let db = search_database(vec![ligand.clone(), decoy, tryptic.clone()]);
+        let spectra = vec![
+            search_spectrum("ligand", &ligand, &[(&ligand, 6)]),
+            search_spectrum("tryptic", &tryptic, &[(&tryptic, 8)]),
+        ];
+
+        let results = score_by_length_stratum(
+            &search_scorer(ScoreType::Standard),
+            &db,
+            spectra,
+            vec![(8, 11), (12, 30)],
+        );
+        assert_eq!(results.len(), 2);
+
+        let hit = &results[0][0];
+        assert_eq!(hit.inner.label, 1);
+        assert_eq!(&*db.inner[hit.inner.peptide_idx].sequence, b"GILGFVFTL");
+        // ln(b! y!) of six matched b and y ions alone is 13.2, the summed intensities add to it
+        assert!(hit.inner.hyperscore > 15.0, "hyperscore {}", hit.inner.hyperscore);
+        assert!(hit.inner.matched_peaks >= 10);
+
+        let hit = &results[1][0];
+        assert_eq!(&*db.inner[hit.inner.peptide_idx].sequence, b"TPEVDDEALEKFDK");
+    }
+
+    #[test]
+    fn oxidation_shows_up_as_a_delta_mass_peak() {
+        let psm = |shift: f32, label: i32, spectrum_q: f32| {
+            PyFeature::from(Feature {
+                label,
+                calcmass: 1000.0,
+                expmass: 1000.0 + shift,
+                spectrum_q,
+                ..crate::py_io::default_feature()
+            })
+        };
+        // 90 unmodified and 10 oxidised confident targets, a few millidaltons of mass error each
+        let mut psms: Vec<PyFeature> = (0..100)
+            .map(|i| {
+                let error = ((i % 5) as f32 - 2.0) * 0.002;
+                psm(if i % 10 == 0 { 15.9949 + error } else { error }, 1, 0.001)
+            })
+            .collect();
+        // neither decoys nor targets above the cutoff are counted
+        psms.extend((0..20).map(|_| psm(79.9663, -1, 0.001)));
+        psms.extend((0..20).map(|_| psm(-17.0265, 1, 0.5)));
+
+        let histogram = delta_mass_histogram(psms, Some(0.01), 0.02, (-20.01, 99.99)).unwrap();
+        assert_eq!(histogram.iter().map(|(_, c)| c).sum::<u32>(), 100);
+
+        let peaks = delta_mass_peaks(histogram, 5);
+        assert_eq!(peaks.len(), 2, "peaks {:?}", peaks);
+        assert!(peaks[0].abs() < 0.01);
+        assert!((peaks[1] - 15.995).abs() < 0.01, "peak {}", peaks[1]);
+    }
+
+    #[test]
+    fn intensity_weighted_ppm_favours_intense_fragments() {
+        let mut psm = PyFeature::from(crate::py_io::default_feature());
+        assert!(psm.intensity_weighted_ppm.is_nan() && psm.max_fragment_ppm.is_nan());
+
+        // +1.953 ppm at three times the intensity of a -3.906 ppm match
+        psm.inner.fragments = Some(Fragments {
+            charges: vec![1, 1],
+            kinds: vec![Kind::B, Kind::Y],
+            fragment_ordinals: vec![3, 7],
+            intensities: vec![3.0, 1.0],
+            mz_calculated: vec![500.0, 1000.0],
+            mz_experimental: vec![500.0 + 1.0 / 1024.0, 1000.0 - 1.0 / 256.0],
+        });
+        psm.update_fragment_ppm();
+
+        assert!((psm.intensity_weighted_ppm - 2.441).abs() < 1e-3, "weighted ppm {}", psm.intensity_weighted_ppm);
+        assert!((psm.max_fragment_ppm - 3.906).abs() < 1e-3);
+    }
+
+    #[test]
+    fn fragment_ppm_is_stored_when_scoring_with_annotated_matches() {
+        let target = search_peptide("PEPTIDEK", false);
+        let db = search_database(vec![target.clone()]);
+        let spectrum = search_spectrum("1", &target, &[(&target, 3)]);
+        let mut scorer = search_scorer(ScoreType::Standard);
+
+        let psms = scorer.score(&db, &spectrum, None, None);
+        assert!(psms[0].intensity_weighted_ppm.is_nan());
+
+        scorer.annotate_matches = true;
+        let psms = scorer.score(&db, &spectrum, None, None);
+        assert_eq!(psms.len(), 1);
+        // all fragments match within the 10 ppm tolerance, up to f32 rounding of the peak masses
+        let psm = &psms[0];
+        assert!(psm.intensity_weighted_ppm < 5.0, "weighted ppm {}", psm.intensity_weighted_ppm);
+        assert!(psm.max_fragment_ppm < 5.0, "max ppm {}", psm.max_fragment_ppm);
+        assert!(psm.intensity_weighted_ppm <= psm.max_fragment_ppm);
+        assert_eq!(ml_feature_vector(psm)[ML_FEATURE_NAMES.len() - 1], psm.max_fragment_ppm as f64);
+    }
+
+    #[test]
+    fn recalibration_removes_a_mass_dependent_ppm_error() {
+        let systematic_ppm = |calcmass: f32| 3.0 + 0.002 * calcmass;
+        let mut features: Vec<Feature> = (0..40)
+            .map(|i| {
+                let calcmass = 800.0 + 50.0 * i as f32;
+                Feature {
+                    label: if i % 4 == 3 { -1 } else { 1 },
+                    calcmass,
+                    expmass: calcmass + calcmass * systematic_ppm(calcmass) * 1e-6,
+                    spectrum_q: 0.001,
+                    ..crate::py_io::default_feature()
+                }
+            })
+            .collect();
+
+        let calibration = recalibrate_features(features.iter_mut().collect(), 0.01, "linear").unwrap();
+
+        assert_eq!(calibration.num_anchors, 30);
+        let coefficients = calibration.coefficients();
+        assert!((coefficients[0] - 3.0).abs() < 0.1 && (coefficients[1] - 0.002).abs() < 1e-4);
+        assert!(calibration.residual_rms < 0.1);
+        // decoys are corrected too, without being anchors
+        for feature in &features {
+            assert!(feature.delta_mass < 0.2, "{} ppm left at {}", feature.delta_mass, feature.calcmass);
+        }
+    }
+
+    #[test]
+    fn recalibration_needs_enough_anchors_and_a_known_model() {
+        let mut feature = Feature {
+            calcmass: 1000.0,
+            expmass: 1000.005,
+            spectrum_q: 0.001,
+            ..crate::py_io::default_feature()
+        };
+        assert!(recalibrate_features(vec![&mut feature], 0.01, "linear").is_err());
+        assert!(recalibrate_features(vec![&mut feature], 0.01, "spline").is_err());
+    }
+
+    #[test]
+    fn phosphosite_is_localized_by_its_site_determining_ions() {
+        let mut peptide = search_peptide("LSAPEDTAK", false);
+        peptide.modifications[6] = PHOSPHO;
+        peptide.monoisotopic += PHOSPHO;
+        let db = search_database(vec![peptide.clone()]);
+        let spectrum = search_spectrum("phospho", &peptide, &[(&peptide, 8)]);
+        let feature = Feature {
+            peptide_idx: PeptideIx(0),
+            ..crate::py_io::default_feature()
+        };
+        let (mod_mass, residues) = unimod_site_modification(21).unwrap();
+
+        let sites = localization_scores(
+            &db.inner,
+            &feature,
+            &spectrum.inner,
+            mod_mass,
+            residues,
+            Tolerance::Ppm(-10.0, 10.0),
+        );
+
+        // b2-b5 and y3-y7 tell the threonine from the serine placement
+        assert_eq!(sites.len(), 2);
+        assert_eq!(sites[0].0, 6);
+        assert!(sites[0].1 > 20.0, "score {}", sites[0].1);
+        assert_eq!(sites[1].0, 1);
+        assert!(sites[1].1 < 0.0);
+    }
+
+    #[test]
+    fn open_search_matches_a_peptide_with_an_unknown_mass_shift() {
+        let peptide = search_peptide("ELVISLIVESK", false);
+        let db = search_database(vec![peptide.clone(), search_peptide("SEVILSIVLEK", true)]);
+        // the precursor carries an oxidation that is absent from the database
+        let oxidised = Peptide { monoisotopic: peptide.monoisotopic + 15.9949, ..peptide.clone() };
+        let spectrum = search_spectrum("open", &oxidised, &[(&peptide, 8)]);
+        let scorer = search_scorer(ScoreType::Standard);
+
+        assert!(scorer.score(&db, &spectrum, None, None).is_empty());
+        assert!(scorer.score_open_search(&db, &spectrum, 0.0, 1).is_err());
+
+        let features = scorer.score_open_search(&db, &spectrum, 100.0, 1).unwrap();
+        let best = &features[0];
+        assert_eq!(&*db.inner[best.inner.peptide_idx].sequence, b"ELVISLIVESK");
+        assert!((best.delta_mass_open() - 15.9949).abs() < 0.01, "shift {}", best.delta_mass_open());
+        assert!(best.inner.matched_peaks >= 10);
+    }
+
+    #[test]
+    fn ms1_isotope_evidence_follows_the_averagine_envelope() {
+        let psm = PyFeature::from(Feature {
+            calcmass: 2000.0,
+            charge: 2,
+            ..crate::py_io::default_feature()
+        });
+        let expected = averagine_distribution(2000.0, 4);
+        let ms1 = |isotopes: std::ops::Range<usize>| {
+            let mz = isotopes.clone().map(|k| (2000.0 + k as f32 * NEUTRON) / 2.0 + PROTON).collect();
+            let intensity = isotopes.map(|k| 1e6 * expected[k]).collect();
+            PyProcessedSpectrum::from_arrays("ms1".to_string(), 0.0, 0, mz, intensity, 0.0, None).unwrap()
+        };
+
+        let full = score_with_ms1_isotope_evidence(psm.clone(), &ms1(0..5), 0.02);
+        assert!(full.ms1_isotope_score > 0.999, "score {}", full.ms1_isotope_score);
+        let monoisotopic_fraction = expected[0] / expected.iter().sum::<f32>();
+        assert!((full.ms1_intensity_ratio - monoisotopic_fraction).abs() < 1e-4);
+
+        // without the monoisotopic peak the precursor was likely assigned to the wrong isotope
+        let shifted = score_with_ms1_isotope_evidence(psm, &ms1(1..5), 0.02);
+        assert!(shifted.ms1_isotope_score < 0.9, "score {}", shifted.ms1_isotope_score);
+        assert_eq!(shifted.ms1_intensity_ratio, 0.0);
+    }
+
+    #[test]
+    fn progress_is_reported_once_all_spectra_are_scored() {
+        let peptide = search_peptide("ELVISLIVESK", false);
+        let db = search_database(vec![peptide.clone(), search_peptide("SEVILSIVLEK", true)]);
+        let spectra: Vec<PyProcessedSpectrum> = (0..3)
+            .map(|i| search_spectrum(&format!("scan={}", i), &peptide, &[(&peptide, 8)]))
+            .collect();
+        let scorer = search_scorer(ScoreType::Standard);
+
+        let reports = Mutex::new(Vec::new());
+        let report = |done, total| reports.lock().unwrap().push((done, total));
+        let (result, completed) = scorer.score_reporting_progress(&db, &spectra, 2, &AtomicBool::new(false), report);
+        assert_eq!(completed, 3);
+        assert_eq!(reports.into_inner().unwrap(), vec![(3, 3)]);
+        assert!(result.iter().all(|psms| &*db.inner[psms[0].inner.peptide_idx].sequence == b"ELVISLIVESK"));
+
+        // a cancelled search skips the remaining spectra
+        let (result, completed) = scorer.score_reporting_progress(&db, &spectra, 2, &AtomicBool::new(true), |_, _| {});
+        assert_eq!(completed, 0);
+        assert!(result.iter().all(Vec::is_empty));
+    }
+
+    #[test]
+    fn parquet_round_trip_restores_partition_columns() {
+        let directory = std::env::temp_dir().join(format!("sagepy_parquet_{}", std::process::id()));
+        let file = directory.join("psms.parquet");
+        let partitioned = directory.join("by_charge");
+        fs::create_dir_all(&directory).unwrap();
+        let psms: Vec<PyFeature> = [3u8, 2, 3]
+            .into_iter()
+            .enumerate()
+            .map(|(i, charge)| {
+                let mut psm = arrow_test_psm();
+                psm.inner.charge = charge;
+                psm.inner.psm_id = i;
+                psm
+            })
+            .collect();
+
+        psms_to_parquet(psms.clone(), file.to_str().unwrap(), "zstd", None).unwrap();
+        psms_to_parquet(psms, partitioned.to_str().unwrap(), "snappy", Some("charge")).unwrap();
+        let from_file = psms_from_parquet(file.to_str().unwrap());
+        let from_partitions = psms_from_parquet(partitioned.to_str().unwrap());
+        let partition_files =
+            [2, 3].map(|z| partitioned.join(format!("charge={}", z)).join("part-0.parquet").is_file());
+        fs::remove_dir_all(&directory).unwrap();
+
+        let from_file = from_file.unwrap();
+        assert_eq!(from_file.iter().map(|p| p.inner.charge).collect::<Vec<_>>(), [3, 2, 3]);
+        assert_eq!(from_file[0].chimera_score, Some(31.5));
+        assert_eq!(from_file[0].peptide_sequence.as_deref(), Some("PEPTIDEK"));
+
+        assert_eq!(partition_files, [true, true]);
+        let from_partitions = from_partitions.unwrap();
+        let charges: Vec<(usize, u8)> = from_partitions.iter().map(|p| (p.inner.psm_id, p.inner.charge)).collect();
+        assert_eq!(charges, [(1, 2), (0, 3), (2, 3)]);
+
+        assert!(psms_to_parquet(Vec::new(), file.to_str().unwrap(), "lz4", None).is_err());
+        assert!(psms_to_parquet(Vec::new(), file.to_str().unwrap(), "zstd", Some("rank")).is_err());
+    }
+
+    #[test]
+    fn glycopeptide_is_matched_with_its_glycan_and_oxonium_ions() {
+        let path = std::env::temp_dir().join(format!("sagepy_glycans_{}.txt", std::process::id()));
+        fs::write(&path, "# N-glycans\nHexNAc(2)Hex(5)\tMan5\nHexNAc(4)Hex(5)Fuc(1)\n").unwrap();
+        let man5 = glycan_mass("HexNAc(2)Hex(5)").unwrap();
+        assert!((man5 - 1216.4229).abs() < 1e-3);
+
+        // the IgG1 Fc glycopeptide, its reversed decoy lacks the N-X-S/T sequon
+        let peptide = search_peptide("EEQYNSTYR", false);
+        let db = search_database(vec![peptide.clone(), search_peptide("YTSNYQEER", true)]);
+        let glycopeptide = Peptide { monoisotopic: peptide.monoisotopic + man5, ..peptide.clone() };
+        let with_oxonium = |oxonium: bool| {
+            let mut spectrum = search_spectrum("glyco", &glycopeptide, &[(&peptide, 8)]);
+            if oxonium {
+                for mz in [138.0550, 163.0601, 204.0867, 366.1395] {
+                    spectrum.inner.peaks.push(Peak { mass: mz - PROTON, intensity: 100.0 });
+                }
+                spectrum.inner.peaks.sort_by(|a, b| a.mass.total_cmp(&b.mass));
+            }
+            spectrum
+        };
+
+        let scorer = search_scorer(ScoreType::Standard);
+        let spectra = vec![with_oxonium(true), with_oxonium(false)];
+        let result = scorer.score_glycopeptides(&db, spectra, path.to_str().unwrap(), 1);
+        fs::remove_file(&path).unwrap();
+        let result = result.unwrap();
+
+        let best = &result[0][0];
+        assert_eq!(best.glycan, "HexNAc(2)Hex(5)");
+        assert_eq!(&*db.inner[best.feature.inner.peptide_idx].sequence, b"EEQYNSTYR");
+        assert!(best.feature.has_oxonium_evidence);
+        assert!(best.feature.inner.discriminant_score > best.feature.inner.hyperscore as f32);
+
+        let without = &result[1][0];
+        assert_eq!(without.glycan, "HexNAc(2)Hex(5)");
+        assert!(!without.feature.has_oxonium_evidence);
+        assert_eq!(without.feature.oxonium_score, 0.0);
+    }
+
+    #[test]
+    fn mass_error_offset_is_the_quantile_of_confident_target_errors() {
+        let psm = |label: i32, ppm: f32, spectrum_q: f32| {
+            PyFeature::from(Feature {
+                label,
+                calcmass: 500.0,
+                expmass: 500.0 + 500.0 * ppm * 1e-6,
+                spectrum_q,
+                ..crate::py_io::default_feature()
+            })
+        };
+        // a +5 ppm offset with +-1 ppm spread, two misidentified outliers, a decoy and a target
+        // above the FDR cutoff
+        let mut psms: Vec<PyFeature> = (0..21).map(|i| psm(1, 4.0 + 0.1 * i as f32, 0.001)).collect();
+        psms.extend([psm(1, 200.0, 0.001), psm(1, 180.0, 0.001), psm(-1, -50.0, 0.001), psm(1, -50.0, 0.5)]);
+
+        let offset = calibrate_mass_error(psms.clone(), 0.5, None).unwrap();
+        assert_eq!(offset.num_observations, 21);
+        assert!((offset.offset_ppm - 5.0).abs() < 0.1, "{}", offset.offset_ppm);
+        assert!(offset.ci_lower <= offset.offset_ppm && offset.offset_ppm <= offset.ci_upper);
+        assert!(offset.ci_lower > 3.9 && offset.ci_upper < 6.1);
+        assert!(calibrate_mass_error(psms.clone(), 1.5, None).is_err());
+        assert!(calibrate_mass_error(psms[21..].to_vec(), 0.5, Some(0.0)).is_err());
+
+        let spectrum = PyProcessedSpectrum::from_arrays(
+            "1".to_string(),
+            500.0 * (1.0 + 5e-6),
+            2,
+            vec![200.0],
+            vec![1.0],
+            0.0,
+            None,
+        )
+        .unwrap();
+        let calibrated = apply_mass_calibration(vec![spectrum], offset.offset_ppm);
+        assert!((calibrated[0].inner.precursors[0].mz - 500.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn explained_intensity_is_the_matched_share_of_the_ion_current() {
+        let target = search_peptide("PEPTIDEK", false);
+        let db = search_database(vec![target.clone()]);
+        // six matched fragments and six equally intense peaks no fragment explains
+        let matched = search_spectrum("1", &target, &[(&target, 3)]);
+        let mut mz: Vec<f32> = matched.inner.peaks.iter().map(|p| p.mass + PROTON).collect();
+        mz.extend((0..6).map(|i| 1700.0 + 10.0 * i as f32));
+        let precursor_mz = matched.inner.precursors[0].mz;
+        let spectrum =
+            PyProcessedSpectrum::from_arrays("1".to_string(), precursor_mz, 2, mz, vec![100.0; 12], 0.0, None)
+                .unwrap();
+
+        let psms = search_scorer(ScoreType::Standard).score(&db, &spectrum, None, None);
+        assert_eq!(psms.len(), 1);
+        assert_eq!(psms[0].inner.matched_peaks, 6);
+        assert!((compute_explained_intensity(&psms[0]) - 0.5).abs() < 1e-4);
+        assert!((psms[0].unexplained_intensity_pct - 50.0).abs() < 1e-2);
+
+        assert_eq!(filter_by_explained_intensity(psms.clone(), 40.0).len(), 1);
+        assert!(filter_by_explained_intensity(psms, 60.0).is_empty());
+    }
+
+    #[test]
+    fn fragment_calibration_recovers_matches_beyond_the_fragment_tolerance() {
+        let target = search_peptide("PEPTIDEK", false);
+        let db = search_database(vec![target.clone()]);
+        // fragment peaks measured `ppm` too high, the precursor is unaffected
+        let shifted = |spec_id: &str, ppm: f32| {
+            let spectrum = search_spectrum(spec_id, &target, &[(&target, 3)]);
+            apply_fragment_calibration(vec![spectrum], -ppm).remove(0)
+        };
+        let mut scorer = search_scorer(ScoreType::Standard);
+        scorer.annotate_matches = true;
+
+        let mut psms = scorer.score(&db, &shifted("1", 5.0), None, None);
+        assert_eq!(psms.len(), 1);
+        psms[0].inner.spectrum_q = 0.0;
+        let offset = calibrate_fragment_masses(psms, 0.5, None).unwrap();
+        assert!((offset - 5.0).abs() < 0.25, "offset {}", offset);
+
+        // at +20 ppm no fragment is within the 10 ppm tolerance until the offset is removed
+        let spectrum = shifted("2", 20.0);
+        assert!(scorer.score(&db, &spectrum, None, None).is_empty());
+        let psm = |spec_id: &str| {
+            PyFeature::from(Feature { spec_id: spec_id.to_string(), ..crate::py_io::default_feature() })
+        };
+        let rescored =
+            rescore_with_fragment_calibration(vec![psm("2"), psm("3")], 20.0, &db, vec![spectrum], &scorer, Some(1))
+                .unwrap();
+        assert_eq!(rescored.len(), 2);
+        // PSMs of spectra that are not given are kept as they are
+        assert_eq!(rescored[0].inner.spec_id, "3");
+        assert_eq!(rescored[0].inner.matched_peaks, 0);
+        assert_eq!(rescored[1].inner.spec_id, "2");
+        assert_eq!(rescored[1].inner.matched_peaks, 6);
+    }
+
+    #[test]
+    fn sequence_tags_read_the_fragment_ladders_and_select_their_peptide() {
+        let target = search_peptide("PEPTIDEK", false);
+        let other = search_peptide("ELVISLIVESK", false);
+        let db = search_database(vec![target.clone(), other]);
+        // b2..b7 and y1..y6, each ladder reads PTIDE in one direction, Ile as Leu
+        let spectrum = search_spectrum("1", &target, &[(&target, 6)]);
+        let tolerance = PyTolerance { inner: Tolerance::Ppm(-10.0, 10.0) };
+
+        let tags = generate_sequence_tags(&spectrum, 3, &tolerance, None);
+        let mut sequences: Vec<&str> = tags.iter().map(|t| t.sequence.as_str()).collect();
+        sequences.sort_unstable();
+        assert_eq!(sequences, vec!["EDLTP", "PTLDE"]);
+        assert!(tags.iter().all(|t| t.length == 5 && t.score == 6.0));
+        assert!(generate_sequence_tags(&spectrum, 6, &tolerance, None).is_empty());
+
+        // a tag of the other peptide makes it a candidate, but it explains no fragment
+        let mut candidates = tags.clone();
+        candidates.push(PyDeNovoTag::new("VLSL".to_string(), 0.0, 1.0));
+        let features = score_with_sequence_tags(&spectrum, candidates.clone(), &db, &tolerance, None);
+        assert_eq!(features.len(), 2);
+        assert_eq!(&*db.inner[features[0].inner.peptide_idx].sequence, b"PEPTIDEK");
+        assert_eq!(features[0].inner.rank, 1);
+        assert_eq!(features[0].inner.matched_peaks, 12);
+        assert_eq!(features[0].tag_match_score, Some(12.0));
+        assert_eq!(features[1].tag_match_score, Some(1.0));
+
+        // with a precursor tolerance only the peptide of the precursor mass remains
+        let filtered = score_with_sequence_tags(&spectrum, candidates, &db, &tolerance, Some(tolerance.clone()));
+        assert_eq!(filtered.len(), 1);
+        assert_eq!(&*db.inner[filtered[0].inner.peptide_idx].sequence, b"PEPTIDEK");
+    }
+
+    #[test]
+    fn predicted_intensities_are_compared_in_the_prosit_layout() {
+        let target = search_peptide("PEPTIDEK", false);
+        // singly charged b and y ions with intensities growing with their ordinal, the prediction
+        // in the Prosit layout (y+, y++, y+++, b+, b++, b+++ per ordinal, -1 beyond the peptide)
+        let mut peaks: Vec<(f32, f32)> = Vec::new();
+        let mut predicted = vec![-1.0; 29 * 6];
+        predicted[..7 * 6].fill(0.0);
+        for kind in [Kind::B, Kind::Y] {
+            for (i, ion) in IonSeries::new(&target, kind).enumerate() {
+                let (ordinal, offset, scale) = match kind {
+                    Kind::B => (i + 1, 3, 10.0),
+                    _ => (target.sequence.len() - i - 1, 0, 20.0),
+                };
+                let intensity = scale * ordinal as f32;
+                peaks.push((ion.monoisotopic_mass + PROTON, intensity));
+                predicted[(ordinal - 1) * 6 + offset] = intensity / 280.0;
+            }
+        }
+        peaks.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let (mz, intensity): (Vec<f32>, Vec<f32>) = peaks.into_iter().unzip();
+        let spectrum = PyProcessedSpectrum::from_arrays("1".to_string(), 500.0, 2, mz, intensity, 0.0, None).unwrap();
+        let tolerance = Tolerance::Ppm(-20.0, 20.0);
+
+        let (observed, expected) = observed_and_predicted(&spectrum.inner, &target, &predicted, tolerance);
+        assert_eq!(observed.len(), 7 * 6);
+        assert_eq!(observed.iter().filter(|o| **o > 0.0).count(), 14);
+        for score_type in ["spectral_angle", "pearson", "dot_product"] {
+            let score_type = PyIntensityScoreType::new(score_type).unwrap().inner;
+            let similarity = intensity_similarity(&observed, &expected, score_type);
+            assert!((similarity - 1.0).abs() < 1e-3, "{:?}: {}", score_type, similarity);
+        }
+
+        // a prediction with the b and y intensities swapped matches worse
+        let mut swapped = predicted.clone();
+        for ordinal in 0..7 {
+            swapped.swap(ordinal * 6, ordinal * 6 + 3);
+        }
+        let (observed, expected) = observed_and_predicted(&spectrum.inner, &target, &swapped, tolerance);
+        let score_types = [
+            IntensityScoreType::SpectralAngle,
+            IntensityScoreType::Pearson,
+            IntensityScoreType::DotProduct,
+        ];
+        for score_type in score_types {
+            assert!(intensity_similarity(&observed, &expected, score_type) < 0.99);
+        }
+        assert!(PyIntensityScoreType::new("cosine").is_err());
+    }
+
+    #[test]
+    fn chimera_pairs_combine_two_co_isolated_peptides() {
+        let major = search_peptide("PEPTIDEK", false);
+        // same composition, so both precursors fall into the isolation window at charge 2
+        let minor = search_peptide("TIDEPEPK", false);
+        let db = search_database(vec![major.clone(), minor.clone(), search_peptide("ELVISLIVESK", false)]);
+        let spectrum = search_spectrum("chimera", &major, &[(&major, 6), (&minor, 3)]);
+        let mass_tolerance = Tolerance::Ppm(-10.0, 10.0);
+        let fragment_tolerance = Tolerance::Ppm(-10.0, 10.0);
+
+        let pairs = chimera_pairs(&db, &spectrum.inner, mass_tolerance, 5, fragment_tolerance);
+        assert_eq!(pairs.len(), 1);
+        let pair = &pairs[0];
+        assert_eq!(&*db.inner[pair.feature_a.inner.peptide_idx].sequence, b"PEPTIDEK");
+        assert_eq!(&*db.inner[pair.feature_b.inner.peptide_idx].sequence, b"TIDEPEPK");
+        assert!(pair.feature_a.inner.hyperscore > pair.feature_b.inner.hyperscore);
+        // the minor peptide adds the peaks the major one leaves unexplained
+        assert!(pair.combined_score > pair.feature_a.inner.hyperscore);
+        assert_eq!(pair.feature_a.inner.charge, 2);
+        assert_eq!(pair.feature_a.chimera_score, Some(pair.combined_score));
+        assert_eq!(pair.feature_b.chimera_score, Some(pair.combined_score));
+        assert!(pair.mass_pair_ppm.abs() < 1.0);
+
+        assert!(chimera_pairs(&db, &spectrum.inner, mass_tolerance, 0, fragment_tolerance).is_empty());
+    }
+
+    #[test]
+    fn spectral_entropy_mode_annotates_or_rescores_psms() {
+        let target = search_peptide("PEPTIDEK", false);
+        let db = search_database(vec![target.clone()]);
+        // six equally intense fragments and six unexplained peaks of unequal intensity
+        let matched = search_spectrum("1", &target, &[(&target, 3)]);
+        let mut mz: Vec<f32> = matched.inner.peaks.iter().map(|p| p.mass + PROTON).collect();
+        mz.extend((0..6).map(|i| 1700.0 + 10.0 * i as f32));
+        let mut intensity = vec![100.0; 6];
+        intensity.extend((1..=6).map(|i| 10.0 * (i * i) as f32));
+        let precursor_mz = matched.inner.precursors[0].mz;
+        let spectrum =
+            PyProcessedSpectrum::from_arrays("1".to_string(), precursor_mz, 2, mz, intensity, 0.0, None).unwrap();
+
+        let score = |mode: SpectralEntropyMode| {
+            let mut scorer = search_scorer(ScoreType::Standard);
+            scorer.spectral_entropy = PySpectralEntropyMode { inner: mode };
+            let psms = scorer.score(&db, &spectrum, None, None);
+            assert_eq!(psms.len(), 1);
+            psms[0].clone()
+        };
+        let off = score(SpectralEntropyMode::Off);
+        assert_eq!(off.spectral_entropy, 0.0);
+
+        let feature = score(SpectralEntropyMode::Feature);
+        assert!(feature.spectral_entropy > 0.0);
+        assert!(feature.delta_spectral_entropy > 0.0);
+        assert_eq!(feature.inner.discriminant_score, off.inner.discriminant_score);
+
+        let rescored = score(SpectralEntropyMode::Rescore);
+        assert_eq!(rescored.delta_spectral_entropy, feature.delta_spectral_entropy);
+        let weighted = rescored.inner.hyperscore * (1.0 + rescored.delta_spectral_entropy as f64);
+        assert_eq!(rescored.inner.discriminant_score, weighted as f32);
+
+        // the mode is kept in the scorer settings, settings without it leave entropy off
+        let mut scorer = search_scorer(ScoreType::Standard);
+        scorer.spectral_entropy = PySpectralEntropyMode::new("rescore").unwrap();
+        let json = scorer.to_json().unwrap();
+        assert!(json.contains("\"spectral_entropy\": \"rescore\""));
+        assert_eq!(PyScorer::from_json(&json).unwrap().spectral_entropy.inner, SpectralEntropyMode::Rescore);
+        let mut settings: serde_json::Value = serde_json::from_str(&json).unwrap();
+        settings.as_object_mut().unwrap().remove("spectral_entropy");
+        let legacy = PyScorer::from_json(&settings.to_string()).unwrap();
+        assert_eq!(legacy.spectral_entropy.inner, SpectralEntropyMode::Off);
+        assert!(PySpectralEntropyMode::new("weighted").is_err());
+    }
 }
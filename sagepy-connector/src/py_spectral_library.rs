@@ -0,0 +1,263 @@
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use std::collections::BTreeMap;
+
+use crate::py_database::PyIndexedDatabase;
+use crate::py_intensity::spectral_angle;
+use crate::py_io::default_feature;
+use crate::py_mass::PyTolerance;
+use crate::py_scoring::{check_peptide_indices, PyFeature, PyFragments};
+use crate::py_spectrum::PyProcessedSpectrum;
+use crate::py_utility::unimod_sequence;
+use sage_core::database::PeptideIx;
+use sage_core::mass::{Tolerance, PROTON};
+use sage_core::scoring::Fragments;
+use sage_core::spectrum::ProcessedSpectrum;
+
+/// Resolution (Th) of the quantized precursor m/z keys of a spectral library
+const LIBRARY_MZ_QUANTUM: f32 = 1e-4;
+
+/// Fragment tolerance of library matching if none is given, in ppm
+const LIBRARY_FRAGMENT_TOLERANCE_PPM: f32 = 20.0;
+
+pub(crate) fn quantize_mz(mz: f32) -> i64 {
+    (mz / LIBRARY_MZ_QUANTUM).round() as i64
+}
+
+/// Consensus spectrum of a peptide precursor, taken from its best scoring PSM
+#[derive(Clone)]
+pub struct LibraryEntry {
+    pub sequence_modified: String,
+    pub peptide_idx: PeptideIx,
+    pub peptide_len: usize,
+    pub precursor_mz: f32,
+    pub decoy: bool,
+    pub fragments: Fragments,
+}
+
+#[pyclass]
+#[derive(Clone, Default)]
+pub struct PySpectralLibrary {
+    /// Entries keyed by quantized precursor m/z and charge
+    pub entries: BTreeMap<(i64, u8), LibraryEntry>,
+}
+
+#[pymethods]
+impl PySpectralLibrary {
+    #[new]
+    pub fn new() -> Self {
+        PySpectralLibrary::default()
+    }
+
+    pub fn __len__(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Fragments of a precursor by its modified sequence (UNIMOD annotated) and charge
+    pub fn get(&self, sequence_modified: &str, charge: u8) -> Option<PyFragments> {
+        self.entries
+            .iter()
+            .find(|((_, c), entry)| *c == charge && entry.sequence_modified == sequence_modified)
+            .map(|(_, entry)| PyFragments {
+                inner: entry.fragments.clone(),
+                neutral_losses: Vec::new(),
+            })
+    }
+
+    /// (modified sequence, charge) of all entries, ordered by precursor m/z
+    #[getter]
+    pub fn precursors(&self) -> Vec<(String, u8)> {
+        self.entries
+            .iter()
+            .map(|((_, charge), entry)| (entry.sequence_modified.clone(), *charge))
+            .collect()
+    }
+}
+
+/// Most intense peak matching each library fragment, 0.0 where none is within tolerance
+fn matched_intensities(spectrum: &ProcessedSpectrum, fragments: &Fragments, tolerance: Tolerance) -> Vec<f32> {
+    fragments
+        .mz_calculated
+        .iter()
+        .map(|mz| {
+            // peaks store m/z - proton, matching the fragment ion mass over its charge
+            let (lo, hi) = tolerance.bounds(mz - PROTON);
+            spectrum
+                .peaks
+                .iter()
+                .filter(|p| p.mass >= lo && p.mass <= hi)
+                .map(|p| p.intensity)
+                .fold(0.0, f32::max)
+        })
+        .collect()
+}
+
+/// Build a spectral library from the annotated fragments of PSMs with spectrum_q <= min_q_value,
+/// keeping the best scoring PSM of each (modified sequence, charge). Requires PSMs scored with
+/// fragment annotation
+#[pyfunction]
+pub fn build_spectral_library(
+    psms: Vec<PyFeature>,
+    db: &PyIndexedDatabase,
+    min_q_value: f32,
+) -> PyResult<PySpectralLibrary> {
+    check_peptide_indices(&db.inner, &psms)?;
+    let mut best: BTreeMap<(String, u8), &PyFeature> = BTreeMap::new();
+    for psm in &psms {
+        if psm.inner.spectrum_q > min_q_value || psm.inner.fragments.is_none() || psm.inner.charge == 0 {
+            continue;
+        }
+        let sequence = unimod_sequence(&db.inner[psm.inner.peptide_idx]);
+        let entry = best.entry((sequence, psm.inner.charge)).or_insert(psm);
+        if psm.inner.hyperscore > entry.inner.hyperscore {
+            *entry = psm;
+        }
+    }
+    if best.is_empty() {
+        return Err(PyValueError::new_err(
+            "no PSM with fragment annotation passes min_q_value, score with annotate_matches enabled",
+        ));
+    }
+
+    let entries = best
+        .into_iter()
+        .map(|((sequence_modified, charge), psm)| {
+            let precursor_mz = (psm.inner.calcmass + charge as f32 * PROTON) / charge as f32;
+            let entry = LibraryEntry {
+                sequence_modified,
+                peptide_idx: psm.inner.peptide_idx,
+                peptide_len: psm.inner.peptide_len,
+                precursor_mz,
+                decoy: psm.inner.label == -1,
+                fragments: psm.inner.fragments.clone().unwrap(),
+            };
+            ((quantize_mz(precursor_mz), charge), entry)
+        })
+        .collect();
+
+    Ok(PySpectralLibrary { entries })
+}
+
+/// Match a spectrum against the library entries whose precursor m/z (and charge, if known) fit
+/// its precursor, PSMs with a spectral angle of at least `spectral_angle_threshold` are returned
+/// ranked by spectral angle, which is stored as hyperscore and discriminant score
+#[pyfunction]
+pub fn score_against_library(
+    library: &PySpectralLibrary,
+    spectrum: &PyProcessedSpectrum,
+    precursor_tolerance: PyTolerance,
+    spectral_angle_threshold: f32,
+    fragment_tolerance: Option<PyTolerance>,
+) -> Vec<PyFeature> {
+    let Some(precursor) = spectrum.inner.precursors.first() else {
+        return Vec::new();
+    };
+    let fragment_tolerance = fragment_tolerance.map(|t| t.inner).unwrap_or(Tolerance::Ppm(
+        -LIBRARY_FRAGMENT_TOLERANCE_PPM,
+        LIBRARY_FRAGMENT_TOLERANCE_PPM,
+    ));
+    let (lo, hi) = precursor_tolerance.inner.bounds(precursor.mz);
+
+    let mut matches: Vec<(f32, &LibraryEntry, u8, usize)> = library
+        .entries
+        .range((quantize_mz(lo), 0)..=(quantize_mz(hi), u8::MAX))
+        .filter(|((_, charge), _)| precursor.charge.unwrap_or(*charge) == *charge)
+        .filter_map(|((_, charge), entry)| {
+            let observed = matched_intensities(&spectrum.inner, &entry.fragments, fragment_tolerance);
+            let angle = spectral_angle(&observed, &entry.fragments.intensities, 0.0, true);
+            let matched = observed.iter().filter(|i| **i > 0.0).count();
+            (angle >= spectral_angle_threshold).then_some((angle, entry, *charge, matched))
+        })
+        .collect();
+    matches.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    let best = matches.first().map(|m| m.0).unwrap_or_default();
+    let next = matches.get(1).map(|m| m.0).unwrap_or_default();
+    matches
+        .into_iter()
+        .enumerate()
+        .map(|(rank, (angle, entry, charge, matched))| {
+            let mut feature = default_feature();
+            let calcmass = (entry.precursor_mz - PROTON) * charge as f32;
+            let expmass = (precursor.mz - PROTON) * charge as f32;
+            feature.peptide_idx = entry.peptide_idx;
+            feature.peptide_len = entry.peptide_len;
+            feature.spec_id = spectrum.inner.id.clone();
+            feature.file_id = spectrum.inner.file_id;
+            feature.rank = rank as u32 + 1;
+            feature.label = if entry.decoy { -1 } else { 1 };
+            feature.expmass = expmass;
+            feature.calcmass = calcmass;
+            feature.charge = charge;
+            feature.rt = spectrum.inner.scan_start_time;
+            feature.delta_mass = ((expmass - calcmass) / calcmass * 1e6).abs();
+            feature.hyperscore = angle as f64;
+            feature.discriminant_score = angle;
+            feature.delta_best = (best - angle) as f64;
+            feature.delta_next = if rank == 0 { (angle - next) as f64 } else { 0.0 };
+            feature.matched_peaks = matched as u32;
+            feature.fragments = Some(entry.fragments.clone());
+            PyFeature::from(feature)
+        })
+        .collect()
+}
+
+#[pymodule]
+pub fn spectral_library(_py: Python, m: &PyModule) -> PyResult<()> {
+    m.add_class::<PySpectralLibrary>()?;
+    m.add_function(wrap_pyfunction!(build_spectral_library, m)?)?;
+    m.add_function(wrap_pyfunction!(score_against_library, m)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::py_scoring::tests::{search_database, search_peptide, search_scorer, search_spectrum};
+    use crate::py_scoring::ScoreType;
+
+    #[test]
+    fn library_search_matches_the_consensus_spectrum_of_a_peptide() {
+        let target = search_peptide("PEPTIDEK", false);
+        let other = search_peptide("ELVISLIVESK", false);
+        let db = search_database(vec![target.clone(), other.clone()]);
+        let mut scorer = search_scorer(ScoreType::Standard);
+        scorer.annotate_matches = true;
+
+        let psms: Vec<PyFeature> = [
+            search_spectrum("1", &target, &[(&target, 6)]),
+            search_spectrum("2", &other, &[(&other, 6)]),
+        ]
+        .iter()
+        .flat_map(|spectrum| scorer.score(&db, spectrum, None, None))
+        .map(|mut psm| {
+            psm.inner.spectrum_q = 0.001;
+            psm
+        })
+        .collect();
+        assert!(build_spectral_library(psms.clone(), &db, 0.0001).is_err());
+
+        let library = build_spectral_library(psms, &db, 0.01).unwrap();
+        assert_eq!(library.__len__(), 2);
+        assert!(library.get("PEPTIDEK", 2).is_some());
+        assert!(library.get("PEPTIDEK", 3).is_none());
+
+        let tolerance = PyTolerance {
+            inner: Tolerance::Ppm(-10.0, 10.0),
+        };
+        let full = search_spectrum("3", &target, &[(&target, 6)]);
+        let hits = score_against_library(&library, &full, tolerance.clone(), 0.5, None);
+        // only the entry of the precursor m/z is compared
+        assert_eq!(hits.len(), 1);
+        let hit = &hits[0].inner;
+        assert_eq!((hit.label, hit.charge, hit.rank), (1, 2, 1));
+        assert_eq!(&*db.inner[hit.peptide_idx].sequence, target.sequence.as_ref());
+        assert!(hit.discriminant_score > 0.99);
+
+        // fewer matched fragments give a lower spectral angle
+        let partial = search_spectrum("4", &target, &[(&target, 2)]);
+        let hits = score_against_library(&library, &partial, tolerance, 0.0, None);
+        assert!(hits[0].inner.discriminant_score < hit.discriminant_score);
+        assert_eq!(hits[0].inner.matched_peaks, 4);
+    }
+}
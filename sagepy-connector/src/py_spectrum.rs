@@ -1,7 +1,24 @@
-use numpy::{IntoPyArray, PyArray1};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use flate2::read::{MultiGzDecoder, ZlibDecoder};
+use numpy::{IntoPyArray, PyArray1, PyArray2};
+use pyo3::exceptions::PyValueError;
 use pyo3::prelude::*;
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
 
 use crate::py_mass::PyTolerance;
+use crate::py_scoring::{averagine_distribution, DictFields, PyFeature, PyFragments, H2O, NH3};
+use crate::py_spectral_library::{quantize_mz, LibraryEntry, PySpectralLibrary};
+use sage_core::database::PeptideIx;
+use sage_core::ion_series::Kind;
+use sage_core::mass::{Tolerance, NEUTRON, PROTON};
+use sage_core::scoring::Fragments;
 use sage_core::spectrum::{
     Deisotoped, Peak, Precursor, ProcessedSpectrum, RawSpectrum, Representation, SpectrumProcessor,
 };
@@ -43,6 +60,11 @@ impl PyRepresentation {
 #[derive(Clone)]
 pub struct PyProcessedSpectrum {
     pub inner: ProcessedSpectrum,
+    pub activation_type: Option<String>,
+    pub is_centroided: bool,
+    pub precursor_candidates: Vec<(f32, u8, f32)>,
+    pub ims_values: Option<Vec<f32>>,
+    pub precursor_ims: Option<f32>,
 }
 
 #[pymethods]
@@ -57,6 +79,9 @@ impl PyProcessedSpectrum {
         precursors: Vec<PyPrecursor>,
         peaks: Vec<PyPeak>,
         total_ion_current: f32,
+        activation_type: Option<String>,
+        is_centroided: Option<bool>,
+        precursor_candidates: Option<Vec<(f32, u8, f32)>>,
     ) -> Self {
         PyProcessedSpectrum {
             inner: ProcessedSpectrum {
@@ -69,6 +94,11 @@ impl PyProcessedSpectrum {
                 peaks: peaks.into_iter().map(|p| p.inner).collect(),
                 total_ion_current,
             },
+            activation_type,
+            is_centroided: is_centroided.unwrap_or(true),
+            precursor_candidates: precursor_candidates.unwrap_or_default(),
+            ims_values: None,
+            precursor_ims: None,
         }
     }
 
@@ -122,6 +152,69 @@ impl PyProcessedSpectrum {
         self.inner.total_ion_current
     }
 
+    /// Fragmentation method of the spectrum, e.g. "HCD", "CID", "ETD", "ECD" or "EThcD"
+    #[getter]
+    pub fn activation_type(&self) -> Option<String> {
+        self.activation_type.clone()
+    }
+
+    /// Whether the peaks are centroids, false for profile-mode spectra
+    #[getter]
+    pub fn is_centroided(&self) -> bool {
+        self.is_centroided
+    }
+
+    /// Candidate (neutral mass, charge, confidence) of the precursor, see `deconvolve_charge`
+    #[getter]
+    pub fn precursor_candidates(&self) -> Vec<(f32, u8, f32)> {
+        self.precursor_candidates.clone()
+    }
+
+    /// Copy of the spectrum carrying the given precursor candidates
+    pub fn with_precursor_candidates(&self, precursor_candidates: Vec<(f32, u8, f32)>) -> PyProcessedSpectrum {
+        PyProcessedSpectrum {
+            precursor_candidates,
+            ..self.clone()
+        }
+    }
+
+    /// Inverse ion mobility (1/K0) of each peak, as read from PASEF frames. None for spectra
+    /// without ion mobility
+    #[getter]
+    pub fn ims_values(&self) -> Option<Vec<f32>> {
+        self.ims_values.clone()
+    }
+
+    /// Copy of the spectrum carrying the inverse ion mobility of each of its peaks
+    pub fn with_ims_values(&self, ims_values: Vec<f32>) -> PyResult<PyProcessedSpectrum> {
+        if ims_values.len() != self.inner.peaks.len() {
+            return Err(PyValueError::new_err(format!(
+                "Spectrum {}: expected one ion mobility value per peak, got {} for {} peaks",
+                self.inner.id,
+                ims_values.len(),
+                self.inner.peaks.len()
+            )));
+        }
+        Ok(PyProcessedSpectrum {
+            ims_values: Some(ims_values),
+            ..self.clone()
+        })
+    }
+
+    /// Observed inverse ion mobility (1/K0) of the precursor, e.g. of a timsTOF PASEF precursor
+    #[getter]
+    pub fn precursor_ims(&self) -> Option<f32> {
+        self.precursor_ims
+    }
+
+    /// Copy of the spectrum carrying the observed inverse ion mobility of its precursor
+    pub fn with_precursor_ims(&self, precursor_ims: f32) -> PyProcessedSpectrum {
+        PyProcessedSpectrum {
+            precursor_ims: Some(precursor_ims),
+            ..self.clone()
+        }
+    }
+
     pub fn extract_ms1_precursor(&self) -> Option<(f32, u8)> {
         self.inner.extract_ms1_precursor()
     }
@@ -129,6 +222,127 @@ impl PyProcessedSpectrum {
     pub fn in_isolation_window(&self, mz: f32) -> Option<bool> {
         self.inner.in_isolation_window(mz)
     }
+
+    /// Centroided MS2 spectrum of m/z and intensity arrays (e.g. numpy arrays), mz must be
+    /// ascending. A precursor charge of 0 is treated as unknown
+    #[staticmethod]
+    pub fn from_arrays(
+        spec_id: String,
+        precursor_mz: f32,
+        precursor_charge: u8,
+        mz: Vec<f32>,
+        intensity: Vec<f32>,
+        rt: f32,
+        precursor_intensity: Option<f32>,
+    ) -> PyResult<PyProcessedSpectrum> {
+        if mz.len() != intensity.len() {
+            return Err(PyValueError::new_err(format!(
+                "Spectrum {}: mz and intensity must have the same length, got {} and {}",
+                spec_id,
+                mz.len(),
+                intensity.len()
+            )));
+        }
+        if let Some(i) = mz.windows(2).position(|w| w[1] < w[0]) {
+            return Err(PyValueError::new_err(format!(
+                "Spectrum {}: mz must be ascending, {} follows {}",
+                spec_id,
+                mz[i + 1],
+                mz[i]
+            )));
+        }
+
+        let precursor = Precursor {
+            mz: precursor_mz,
+            intensity: precursor_intensity,
+            charge: (precursor_charge > 0).then_some(precursor_charge),
+            spectrum_ref: None,
+            isolation_window: None,
+        };
+        // processed peaks store the m/z minus a proton
+        let peaks = mz
+            .iter()
+            .zip(intensity.iter())
+            .map(|(mz, intensity)| Peak {
+                mass: mz - PROTON,
+                intensity: *intensity,
+            })
+            .collect();
+        Ok(PyProcessedSpectrum {
+            inner: ProcessedSpectrum {
+                level: 2,
+                id: spec_id,
+                file_id: 0,
+                scan_start_time: rt,
+                ion_injection_time: 0.0,
+                precursors: vec![precursor],
+                peaks,
+                total_ion_current: intensity.iter().sum(),
+            },
+            activation_type: None,
+            is_centroided: true,
+            precursor_candidates: Vec::new(),
+            ims_values: None,
+            precursor_ims: None,
+        })
+    }
+
+    /// Spectra of a dict of equally long columns (e.g. of a DataFrame): spec_id, precursor_mz,
+    /// precursor_charge, rt and per-spectrum mz and intensity arrays, optionally
+    /// precursor_intensity. The spectra are built in parallel, see `from_arrays`
+    #[staticmethod]
+    pub fn from_dataframe(py: Python, df: HashMap<String, PyObject>) -> PyResult<Vec<PyProcessedSpectrum>> {
+        let columns = DictFields { py, d: &df };
+        let spec_ids: Vec<String> = columns.required("spec_id")?;
+        let precursor_mzs: Vec<f32> = columns.required("precursor_mz")?;
+        let precursor_charges: Vec<u8> = columns.required("precursor_charge")?;
+        let mzs: Vec<Vec<f32>> = columns.required("mz")?;
+        let intensities: Vec<Vec<f32>> = columns.required("intensity")?;
+        let rts: Vec<f32> = columns.required("rt")?;
+        let precursor_intensities: Option<Vec<Option<f32>>> = columns.optional("precursor_intensity")?;
+
+        let n = spec_ids.len();
+        let lengths = [
+            precursor_mzs.len(),
+            precursor_charges.len(),
+            mzs.len(),
+            intensities.len(),
+            rts.len(),
+            precursor_intensities.as_ref().map_or(n, Vec::len),
+        ];
+        if lengths.iter().any(|l| *l != n) {
+            return Err(PyValueError::new_err("All columns must have the same length"));
+        }
+
+        py.allow_threads(|| {
+            (0..n)
+                .into_par_iter()
+                .map(|i| {
+                    PyProcessedSpectrum::from_arrays(
+                        spec_ids[i].clone(),
+                        precursor_mzs[i],
+                        precursor_charges[i],
+                        mzs[i].clone(),
+                        intensities[i].clone(),
+                        rts[i],
+                        precursor_intensities.as_ref().and_then(|p| p[i]),
+                    )
+                })
+                .collect()
+        })
+    }
+}
+
+impl PyProcessedSpectrum {
+    /// Fragment ion kinds of electron-based fragmentation: c and z-dot ions for ETD and ECD, b,
+    /// y, c and z-dot ions for EThcD. None for collisional activation
+    pub fn activation_ion_kinds(&self) -> Option<Vec<Kind>> {
+        match self.activation_type.as_deref().map(str::to_uppercase).as_deref() {
+            Some("ETD") | Some("ECD") => Some(vec![Kind::C, Kind::Z]),
+            Some("ETHCD") => Some(vec![Kind::B, Kind::Y, Kind::C, Kind::Z]),
+            _ => None,
+        }
+    }
 }
 
 #[pyclass]
@@ -300,9 +514,15 @@ impl PySpectrumProcessor {
         self.inner.deisotope
     }
 
-    pub fn process(&self, spectrum: &PyRawSpectrum) -> PyProcessedSpectrum {
+    #[pyo3(signature = (spectrum, activation_type=None))]
+    pub fn process(&self, spectrum: &PyRawSpectrum, activation_type: Option<String>) -> PyProcessedSpectrum {
         PyProcessedSpectrum {
             inner: self.inner.process(spectrum.inner.clone()),
+            activation_type,
+            is_centroided: matches!(spectrum.inner.representation, Representation::Centroid),
+            precursor_candidates: Vec::new(),
+            ims_values: None,
+            precursor_ims: None,
         }
     }
 }
@@ -404,6 +624,1225 @@ impl PyPrecursor {
     }
 }
 
+/// Index range of the points within `ppm` of the point `i`, `mz` is ascending
+fn ppm_window(mz: &[f32], i: usize, ppm: f32) -> std::ops::Range<usize> {
+    let delta = mz[i] * ppm / 1e6;
+    mz.partition_point(|m| *m < mz[i] - delta)..mz.partition_point(|m| *m <= mz[i] + delta)
+}
+
+/// Gaussian smoothing of intensities over the points within `ppm` in m/z, sigma is half the window
+fn gaussian_smooth(mz: &[f32], intensities: &[f32], ppm: f32) -> Vec<f32> {
+    (0..mz.len())
+        .map(|i| {
+            let sigma = (mz[i] * ppm / 1e6 / 2.0).max(f32::EPSILON);
+            let (sum, weight) = ppm_window(mz, i, ppm).fold((0.0, 0.0), |(sum, weight), j| {
+                let d = (mz[j] - mz[i]) / sigma;
+                let w = (-0.5 * d * d).exp();
+                (sum + w * intensities[j], weight + w)
+            });
+            sum / weight
+        })
+        .collect()
+}
+
+/// Centroid a profile-mode raw spectrum: intensities are smoothed with a Gaussian kernel, local
+/// maxima within `ppm` in m/z above `snr_threshold` times the median intensity (the noise level)
+/// are peak apexes, their m/z is refined by a quadratic fit to the apex and its neighbours and
+/// their intensity is the sum of the raw intensities within `ppm` of the apex
+fn centroid(spectrum: &RawSpectrum, snr_threshold: f32, ppm: f32) -> RawSpectrum {
+    let (mz, raw) = (&spectrum.mz, &spectrum.intensity);
+    let smoothed = gaussian_smooth(mz, raw, ppm);
+
+    let mut non_zero: Vec<f32> = smoothed.iter().copied().filter(|i| *i > 0.0).collect();
+    non_zero.sort_by(|a, b| a.total_cmp(b));
+    let noise = non_zero.get(non_zero.len() / 2).copied().unwrap_or(0.0);
+    let threshold = snr_threshold * noise;
+
+    let (mut centroid_mz, mut centroid_intensity) = (Vec::new(), Vec::new());
+    for i in 0..smoothed.len() {
+        let window = ppm_window(mz, i, ppm);
+        // strict on the left so that a flat apex yields a single centroid
+        let is_apex = smoothed[i] > threshold
+            && (window.start..i).all(|j| smoothed[j] < smoothed[i])
+            && (i + 1..window.end).all(|j| smoothed[j] <= smoothed[i]);
+        if !is_apex {
+            continue;
+        }
+
+        let mut apex = mz[i];
+        if i > 0 && i + 1 < mz.len() && window.contains(&(i - 1)) && window.contains(&(i + 1)) {
+            // vertex of the parabola through the apex and its neighbours
+            let (x0, x1, x2) = (mz[i - 1] - mz[i], 0.0, mz[i + 1] - mz[i]);
+            let (y0, y1, y2) = (smoothed[i - 1], smoothed[i], smoothed[i + 1]);
+            let numerator = (x1 * x1 - x2 * x2) * y0 + (x2 * x2 - x0 * x0) * y1 + (x0 * x0 - x1 * x1) * y2;
+            let denominator = (x1 - x2) * y0 + (x2 - x0) * y1 + (x0 - x1) * y2;
+            if denominator.abs() > f32::EPSILON {
+                apex += (0.5 * numerator / denominator).clamp(x0, x2);
+            }
+        }
+        centroid_mz.push(apex);
+        centroid_intensity.push(raw[window].iter().sum());
+    }
+
+    RawSpectrum {
+        representation: Representation::Centroid,
+        total_ion_current: centroid_intensity.iter().sum(),
+        mz: centroid_mz,
+        intensity: centroid_intensity,
+        ..spectrum.clone()
+    }
+}
+
+/// Number of isotope peaks above the monoisotopic peak compared to the averagine envelope
+const DECONVOLUTION_ISOTOPES: usize = 4;
+
+/// Isotope peaks besides the monoisotopic one the precursor may have been selected on
+const MAX_SELECTED_ISOTOPE: usize = 2;
+
+/// Candidate (neutral mass, charge, confidence) of a precursor, best first. For each charge the
+/// envelope around the selected precursor m/z is matched (the precursor may be any of the first
+/// isotopes) and compared to the averagine model by cosine similarity, a peak one isotope below the
+/// assumed monoisotopic peak counts against the fit. MS1 peaks are expected as m/z - proton
+fn precursor_candidates(
+    precursor: &Precursor,
+    ms1: &ProcessedSpectrum,
+    min_charge: u8,
+    max_charge: u8,
+    mass_tolerance_ppm: f32,
+) -> Vec<(f32, u8, f32)> {
+    let tolerance = Tolerance::Ppm(-mass_tolerance_ppm, mass_tolerance_ppm);
+    let intensity_at = |mz: f32| {
+        let (lo, hi) = tolerance.bounds(mz - PROTON);
+        let start = ms1.peaks.partition_point(|p| p.mass < lo);
+        ms1.peaks[start..]
+            .iter()
+            .take_while(|p| p.mass <= hi)
+            .map(|p| p.intensity)
+            .fold(0.0f32, f32::max)
+    };
+
+    let mut candidates: Vec<(f32, u8, f32)> = (min_charge.max(1)..=max_charge)
+        .filter_map(|charge| {
+            let spacing = NEUTRON / charge as f32;
+            (0..=MAX_SELECTED_ISOTOPE)
+                .filter_map(|selected| {
+                    let mono_mz = precursor.mz - selected as f32 * spacing;
+                    // intensities of M-1, M, ..., M+n
+                    let observed: Vec<f32> = (-1..=DECONVOLUTION_ISOTOPES as i32)
+                        .map(|k| intensity_at(mono_mz + k as f32 * spacing))
+                        .collect();
+                    let matched = observed[1..].iter().filter(|i| **i > 0.0).count();
+                    if observed[1] <= 0.0 || observed[selected + 1] <= 0.0 || matched < 2 {
+                        return None;
+                    }
+
+                    let mass = (mono_mz - PROTON) * charge as f32;
+                    let expected: Vec<f32> = std::iter::once(0.0)
+                        .chain(averagine_distribution(mass, DECONVOLUTION_ISOTOPES))
+                        .collect();
+                    let dot: f32 = observed.iter().zip(&expected).map(|(o, e)| o * e).sum();
+                    let norm = observed.iter().map(|o| o * o).sum::<f32>().sqrt()
+                        * expected.iter().map(|e| e * e).sum::<f32>().sqrt();
+                    (norm > 0.0).then(|| (mass, charge, dot / norm))
+                })
+                .max_by(|a, b| a.2.total_cmp(&b.2))
+        })
+        .collect();
+    candidates.sort_by(|a, b| b.2.total_cmp(&a.2));
+    candidates
+}
+
+/// Rank the charge states of the first precursor of a spectrum by the fit of its isotope envelope
+/// in `ms1_spectrum` (the spectrum itself if not given) to the averagine model, returns
+/// (neutral mass, charge, confidence) tuples, best first
+#[pyfunction]
+pub fn deconvolve_charge(
+    spectrum: &PyProcessedSpectrum,
+    min_charge: u8,
+    max_charge: u8,
+    mass_tolerance_ppm: f32,
+    ms1_spectrum: Option<&PyProcessedSpectrum>,
+) -> PyResult<Vec<(f32, u8, f32)>> {
+    let precursor = spectrum
+        .inner
+        .precursors
+        .first()
+        .ok_or_else(|| PyValueError::new_err(format!("Spectrum {} has no precursor", spectrum.inner.id)))?;
+    let ms1 = ms1_spectrum.map_or(&spectrum.inner, |s| &s.inner);
+    Ok(precursor_candidates(precursor, ms1, min_charge, max_charge, mass_tolerance_ppm))
+}
+
+/// Whether each fragment peak lies within `ims_window` (full width, 1/K0) of `precursor_ims`,
+/// requires the ion mobility of the peaks
+fn ims_gate(spectrum: &PyProcessedSpectrum, precursor_ims: f32, ims_window: f32) -> PyResult<Vec<bool>> {
+    let ims_values = spectrum.ims_values.as_ref().ok_or_else(|| {
+        PyValueError::new_err(format!("Spectrum {} has no ion mobility values", spectrum.inner.id))
+    })?;
+    let half_width = ims_window / 2.0;
+    Ok(ims_values.iter().map(|ims| (ims - precursor_ims).abs() <= half_width).collect())
+}
+
+/// Simulate the TIMS-DDA isolation of a precursor in m/z and ion mobility: fragment peaks
+/// outside `ims_window` (full width, 1/K0) around `precursor_ims` are dropped as fragments of
+/// co-isolated ions of another mobility, and only the precursors within `mz_window` (full width,
+/// Th) around `precursor_mz` are kept, carrying that isolation window. The intensity of the
+/// dropped peaks is removed from the total ion current
+#[pyfunction]
+pub fn simulate_tims_isolation(
+    spectrum: &PyProcessedSpectrum,
+    precursor_mz: f32,
+    precursor_ims: f32,
+    mz_window: f32,
+    ims_window: f32,
+) -> PyResult<PyProcessedSpectrum> {
+    let keep = ims_gate(spectrum, precursor_ims, ims_window)?;
+    let ims_values = spectrum.ims_values.as_deref().unwrap_or_default();
+    let half_width = mz_window / 2.0;
+
+    let mut isolated = spectrum.clone();
+    let (mut peaks, mut ims, mut dropped) = (Vec::new(), Vec::new(), 0.0f32);
+    for ((peak, value), keep) in spectrum.inner.peaks.iter().zip(ims_values).zip(keep) {
+        match keep {
+            true => {
+                peaks.push(*peak);
+                ims.push(*value);
+            }
+            false => dropped += peak.intensity,
+        }
+    }
+    isolated.inner.peaks = peaks;
+    isolated.ims_values = Some(ims);
+    isolated.inner.total_ion_current = (spectrum.inner.total_ion_current - dropped).max(0.0);
+    isolated.inner.precursors = spectrum
+        .inner
+        .precursors
+        .iter()
+        .filter(|p| (p.mz - precursor_mz).abs() <= half_width)
+        .map(|p| Precursor {
+            isolation_window: Some(Tolerance::Da(-half_width, half_width)),
+            ..p.clone()
+        })
+        .collect();
+    Ok(isolated)
+}
+
+/// Fraction of the fragment intensity outside `ims_window` (full width, 1/K0) around
+/// `precursor_ims`, an estimate of the intensity of co-isolated interfering ions. 0 for a
+/// spectrum without intensity
+#[pyfunction]
+pub fn tims_chimera_fraction(spectrum: &PyProcessedSpectrum, precursor_ims: f32, ims_window: f32) -> PyResult<f32> {
+    let keep = ims_gate(spectrum, precursor_ims, ims_window)?;
+    let (mut total, mut interfering) = (0.0f32, 0.0f32);
+    for (peak, keep) in spectrum.inner.peaks.iter().zip(keep) {
+        total += peak.intensity;
+        if !keep {
+            interfering += peak.intensity;
+        }
+    }
+    Ok(match total > 0.0 {
+        true => interfering / total,
+        false => 0.0,
+    })
+}
+
+/// Isotope peaks above the monoisotopic one counted as precursor intensity by `precursor_purity`
+const PURITY_ISOTOPES: usize = 3;
+
+/// Intensity of the precursor peak (and with a charge its isotope envelope) relative to all MS1
+/// peaks in the isolation window centered on `precursor_mz`, 0 for an empty window. MS1 peaks are
+/// expected as m/z - proton
+pub(crate) fn precursor_purity(
+    ms1: &ProcessedSpectrum,
+    precursor_mz: f32,
+    isolation_window_da: f32,
+    charge: Option<u8>,
+    tolerance: Tolerance,
+) -> f32 {
+    let half_width = isolation_window_da / 2.0;
+    let start = ms1.peaks.partition_point(|p| p.mass + PROTON < precursor_mz - half_width);
+    let window: Vec<&Peak> = ms1.peaks[start..]
+        .iter()
+        .take_while(|p| p.mass + PROTON <= precursor_mz + half_width)
+        .collect();
+    let total: f32 = window.iter().map(|p| p.intensity).sum();
+    if total <= 0.0 {
+        return 0.0;
+    }
+
+    let isotopes = match charge {
+        Some(_) => PURITY_ISOTOPES,
+        None => 0,
+    };
+    let spacing = NEUTRON / charge.unwrap_or(1).max(1) as f32;
+    let precursor: f32 = window
+        .iter()
+        .filter(|p| {
+            (0..=isotopes).any(|k| {
+                let (lo, hi) = tolerance.bounds(precursor_mz + k as f32 * spacing - PROTON);
+                p.mass >= lo && p.mass <= hi
+            })
+        })
+        .map(|p| p.intensity)
+        .sum();
+    precursor / total
+}
+
+/// Precursor purity of an isolation window, see `precursor_purity`. With a charge the isotope
+/// peaks of the precursor count as precursor intensity
+#[pyfunction]
+pub fn compute_precursor_purity(
+    ms1_spectrum: &PyProcessedSpectrum,
+    precursor_mz: f32,
+    isolation_window_da: f32,
+    charge: Option<u8>,
+    tolerance_ppm: Option<f32>,
+) -> f32 {
+    let tolerance_ppm = tolerance_ppm.unwrap_or(10.0);
+    precursor_purity(
+        &ms1_spectrum.inner,
+        precursor_mz,
+        isolation_window_da,
+        charge,
+        Tolerance::Ppm(-tolerance_ppm, tolerance_ppm),
+    )
+}
+
+/// Set `precursor_purity` of all PSMs, `ms1_spectra` maps the spec_id of a PSM to the MS1
+/// spectrum its precursor was isolated from. The window is centered on the experimental
+/// precursor m/z, PSMs without an MS1 spectrum are left untagged
+#[pyfunction]
+pub fn tag_precursor_purity(
+    psms: Vec<PyFeature>,
+    ms1_spectra: HashMap<String, PyProcessedSpectrum>,
+    isolation_window_da: f32,
+    tolerance_ppm: Option<f32>,
+) -> Vec<PyFeature> {
+    let tolerance_ppm = tolerance_ppm.unwrap_or(10.0);
+    let tolerance = Tolerance::Ppm(-tolerance_ppm, tolerance_ppm);
+    let mut psms = psms;
+    psms.par_iter_mut().for_each(|psm| {
+        if let Some(ms1) = ms1_spectra.get(&psm.inner.spec_id) {
+            let charge = psm.inner.charge.max(1);
+            let precursor_mz = psm.inner.expmass / charge as f32 + PROTON;
+            psm.precursor_purity = Some(precursor_purity(
+                &ms1.inner,
+                precursor_mz,
+                isolation_window_da,
+                Some(charge),
+                tolerance,
+            ));
+        }
+    });
+    psms
+}
+
+/// PSMs with a precursor purity of at least `min_purity`, untagged PSMs are dropped
+#[pyfunction]
+pub fn filter_by_precursor_purity(psms: Vec<PyFeature>, min_purity: f32) -> Vec<PyFeature> {
+    psms.into_iter()
+        .filter(|psm| psm.precursor_purity.map_or(false, |purity| purity >= min_purity))
+        .collect()
+}
+
+/// Summed intensity of the peaks within `tolerance` of `mz` in each MS1 spectrum, as (retention
+/// time, intensity) pairs sorted by retention time. `num_isotopes` isotope traces (spaced by
+/// NEUTRON / charge) starting at `mz` are summed. MS1 peaks are expected as m/z - proton
+fn xic(
+    ms1_spectra: &[(f32, PyProcessedSpectrum)],
+    mz: f32,
+    charge: u8,
+    num_isotopes: usize,
+    tolerance: Tolerance,
+) -> Vec<(f32, f32)> {
+    let spacing = NEUTRON / charge.max(1) as f32;
+    let mut xic: Vec<(f32, f32)> = ms1_spectra
+        .iter()
+        .map(|(rt, spectrum)| {
+            let peaks = &spectrum.inner.peaks;
+            let intensity = (0..num_isotopes.max(1))
+                .map(|k| {
+                    let (lo, hi) = tolerance.bounds(mz + k as f32 * spacing - PROTON);
+                    let start = peaks.partition_point(|p| p.mass < lo);
+                    peaks[start..]
+                        .iter()
+                        .take_while(|p| p.mass <= hi)
+                        .map(|p| p.intensity)
+                        .sum::<f32>()
+                })
+                .sum();
+            (*rt, intensity)
+        })
+        .collect();
+    xic.sort_by(|a, b| a.0.total_cmp(&b.0));
+    xic
+}
+
+/// Extracted ion chromatogram of a precursor m/z over (retention time, MS1 spectrum) pairs, see
+/// `xic`. By default only the monoisotopic trace is extracted
+#[pyfunction]
+pub fn extract_xic(
+    ms1_spectra: Vec<(f32, PyProcessedSpectrum)>,
+    target_mz: f32,
+    charge: u8,
+    tolerance: &PyTolerance,
+    num_isotopes: Option<usize>,
+) -> Vec<(f32, f32)> {
+    xic(&ms1_spectra, target_mz, charge, num_isotopes.unwrap_or(1), tolerance.inner)
+}
+
+/// Area (trapezoidal rule) of the XIC points within `rt_window` (full width) centered on
+/// `apex_rt`
+#[pyfunction]
+pub fn integrate_xic(xic: Vec<(f32, f32)>, apex_rt: f32, rt_window: f32) -> f32 {
+    let half_width = rt_window / 2.0;
+    let mut points: Vec<(f32, f32)> = xic
+        .into_iter()
+        .filter(|(rt, _)| (rt - apex_rt).abs() <= half_width)
+        .collect();
+    points.sort_by(|a, b| a.0.total_cmp(&b.0));
+    points
+        .windows(2)
+        .map(|w| (w[1].0 - w[0].0) * (w[0].1 + w[1].1) / 2.0)
+        .sum()
+}
+
+/// Retention time of the most intense XIC point
+#[pyfunction]
+pub fn find_xic_apex(xic: Vec<(f32, f32)>) -> PyResult<f32> {
+    xic.iter()
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(rt, _)| *rt)
+        .ok_or_else(|| PyValueError::new_err("Cannot find the apex of an empty XIC"))
+}
+
+/// Monoisotopic XIC of the theoretical precursor m/z of a PSM at its charge
+#[pyfunction]
+pub fn xic_for_peptide(
+    psm: &PyFeature,
+    ms1_spectra: Vec<(f32, PyProcessedSpectrum)>,
+    tolerance_ppm: f32,
+) -> Vec<(f32, f32)> {
+    let charge = psm.inner.charge.max(1);
+    let mz = psm.inner.calcmass / charge as f32 + PROTON;
+    xic(&ms1_spectra, mz, charge, 1, Tolerance::Ppm(-tolerance_ppm, tolerance_ppm))
+}
+
+/// Centroid a profile-mode raw spectrum before processing, see `centroid_collection` for many
+/// spectra
+#[pyfunction]
+pub fn centroid_spectrum(spectrum: &PyRawSpectrum, snr_threshold: f32, ppm: f32) -> PyRawSpectrum {
+    PyRawSpectrum {
+        inner: centroid(&spectrum.inner, snr_threshold, ppm),
+    }
+}
+
+#[pyfunction]
+pub fn centroid_collection(
+    spectra: Vec<PyRawSpectrum>,
+    snr_threshold: f32,
+    ppm: f32,
+    num_threads: usize,
+) -> Vec<PyRawSpectrum> {
+    let pool = ThreadPoolBuilder::new()
+        .num_threads(num_threads)
+        .build()
+        .unwrap();
+
+    pool.install(|| {
+        spectra
+            .par_iter()
+            .map(|s| PyRawSpectrum {
+                inner: centroid(&s.inner, snr_threshold, ppm),
+            })
+            .collect()
+    })
+}
+
+fn check_bins(min_mz: f32, max_mz: f32, bin_width: f32) -> PyResult<usize> {
+    if !(bin_width > 0.0 && max_mz > min_mz) {
+        return Err(PyValueError::new_err(format!(
+            "Invalid binning, requires min_mz < max_mz and a positive bin_width, got {}, {} and {}",
+            min_mz, max_mz, bin_width
+        )));
+    }
+    Ok(((max_mz - min_mz) / bin_width).ceil() as usize)
+}
+
+/// Intensities of the peaks in [min_mz, max_mz) summed into bins of `bin_width`, optionally
+/// scaled to unit L2 norm
+fn binned_intensities(spectrum: &ProcessedSpectrum, min_mz: f32, bin_width: f32, num_bins: usize, normalize: bool) -> Vec<f32> {
+    let mut bins = vec![0.0f32; num_bins];
+    for peak in &spectrum.peaks {
+        // peaks store m/z - proton
+        let offset = (peak.mass + PROTON - min_mz) / bin_width;
+        if offset >= 0.0 && (offset as usize) < num_bins {
+            bins[offset as usize] += peak.intensity;
+        }
+    }
+    if normalize {
+        let norm = bins.iter().map(|i| i * i).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            bins.iter_mut().for_each(|i| *i /= norm);
+        }
+    }
+    bins
+}
+
+/// Fixed-width m/z bin representation of a spectrum, e.g. as input of ML scoring models
+#[pyfunction]
+pub fn bin_spectrum(
+    py: Python,
+    spectrum: &PyProcessedSpectrum,
+    min_mz: f32,
+    max_mz: f32,
+    bin_width: f32,
+    normalize: bool,
+) -> PyResult<Py<PyArray1<f32>>> {
+    let num_bins = check_bins(min_mz, max_mz, bin_width)?;
+    let bins = binned_intensities(&spectrum.inner, min_mz, bin_width, num_bins, normalize);
+    Ok(bins.into_pyarray(py).to_owned())
+}
+
+/// Bin spectra in parallel (see `bin_spectrum`), one row per spectrum
+#[pyfunction]
+pub fn bin_spectra_parallel(
+    py: Python,
+    spectra: Vec<PyProcessedSpectrum>,
+    min_mz: f32,
+    max_mz: f32,
+    bin_width: f32,
+    normalize: bool,
+    num_threads: usize,
+) -> PyResult<Py<PyArray2<f32>>> {
+    let num_bins = check_bins(min_mz, max_mz, bin_width)?;
+    let pool = ThreadPoolBuilder::new()
+        .num_threads(num_threads)
+        .build()
+        .unwrap();
+
+    let bins: Vec<f32> = pool.install(|| {
+        spectra
+            .par_iter()
+            .flat_map_iter(|s| binned_intensities(&s.inner, min_mz, bin_width, num_bins, normalize))
+            .collect()
+    });
+    Ok(bins.into_pyarray(py).reshape([spectra.len(), num_bins])?.to_owned())
+}
+
+fn check_bin_lengths(binned_a: &[f32], binned_b: &[f32]) -> PyResult<()> {
+    if binned_a.len() != binned_b.len() {
+        return Err(PyValueError::new_err(format!(
+            "binned spectra must have the same number of bins, got {} and {}",
+            binned_a.len(),
+            binned_b.len()
+        )));
+    }
+    Ok(())
+}
+
+/// Dot product of two binned spectra
+#[pyfunction]
+pub fn dot_product_score(binned_a: Vec<f32>, binned_b: Vec<f32>) -> PyResult<f32> {
+    check_bin_lengths(&binned_a, &binned_b)?;
+    Ok(binned_a.iter().zip(binned_b.iter()).map(|(a, b)| a * b).sum())
+}
+
+/// Cosine similarity of two binned spectra, 0.0 if either is empty
+#[pyfunction]
+pub fn cosine_similarity(binned_a: Vec<f32>, binned_b: Vec<f32>) -> PyResult<f32> {
+    check_bin_lengths(&binned_a, &binned_b)?;
+    let dot: f32 = binned_a.iter().zip(binned_b.iter()).map(|(a, b)| a * b).sum();
+    let norm_a = binned_a.iter().map(|a| a * a).sum::<f32>().sqrt();
+    let norm_b = binned_b.iter().map(|b| b * b).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return Ok(0.0);
+    }
+    Ok(dot / (norm_a * norm_b))
+}
+
+/// Leading bytes of a gzip compressed file
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Signal-to-noise threshold and ppm window of the centroiding of profile spectra read from mzML
+const MZML_CENTROID_SNR: f32 = 3.0;
+const MZML_CENTROID_PPM: f32 = 10.0;
+
+fn mzml_error(path: &str) -> impl Fn(quick_xml::Error) -> PyErr + '_ {
+    move |e| PyValueError::new_err(format!("Could not parse mzML file {}: {}", path, e))
+}
+
+fn attribute(element: &BytesStart, name: &str) -> PyResult<Option<String>> {
+    let read_error = |e: quick_xml::Error| PyValueError::new_err(format!("Invalid mzML attribute {}: {}", name, e));
+    match element.try_get_attribute(name).map_err(|e| read_error(e.into()))? {
+        Some(a) => Ok(Some(a.unescape_value().map_err(read_error)?.into_owned())),
+        None => Ok(None),
+    }
+}
+
+fn float_attribute(element: &BytesStart, name: &str) -> PyResult<Option<f32>> {
+    match attribute(element, name)? {
+        Some(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|_| PyValueError::new_err(format!("Invalid mzML value of {}: {}", name, value))),
+        None => Ok(None),
+    }
+}
+
+/// Activation method of an mzML dissociation method accession, electron transfer combined with
+/// a supplemental collisional activation is reported as EThcD
+fn combine_activation(current: Option<String>, accession: &str) -> Option<String> {
+    let method = match accession {
+        "MS:1000133" => "CID",
+        "MS:1000422" | "MS:1002678" => "HCD",
+        "MS:1000598" => "ETD",
+        "MS:1000250" => "ECD",
+        "MS:1002631" => "EThcD",
+        _ => return current,
+    };
+    match (current.as_deref(), method) {
+        (Some("ETD"), "HCD") | (Some("HCD"), "ETD") => Some("EThcD".to_string()),
+        (Some(_), _) if method != "EThcD" => current,
+        _ => Some(method.to_string()),
+    }
+}
+
+/// Decode a base64 encoded, optionally zlib compressed mzML binary data array
+fn decode_binary(text: &str, is_64_bit: bool, is_zlib: bool) -> PyResult<Vec<f32>> {
+    let decode_error = |e: String| PyValueError::new_err(format!("Invalid mzML binary data array: {}", e));
+    let mut bytes = BASE64.decode(text.trim()).map_err(|e| decode_error(e.to_string()))?;
+    if is_zlib {
+        let mut decompressed = Vec::new();
+        ZlibDecoder::new(&bytes[..])
+            .read_to_end(&mut decompressed)
+            .map_err(|e| decode_error(e.to_string()))?;
+        bytes = decompressed;
+    }
+    Ok(match is_64_bit {
+        true => bytes
+            .chunks_exact(8)
+            .map(|b| f64::from_le_bytes(b.try_into().unwrap()) as f32)
+            .collect(),
+        false => bytes
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+            .collect(),
+    })
+}
+
+/// Streaming reader of the spectra of a (possibly gzip compressed) mzML file
+struct MzMLReader {
+    reader: Reader<Box<dyn BufRead + Send>>,
+    path: String,
+    file_id: usize,
+    buf: Vec<u8>,
+}
+
+impl MzMLReader {
+    fn open(path: &str, file_id: usize) -> PyResult<Self> {
+        let io_error = |e: std::io::Error| PyValueError::new_err(format!("Could not read mzML file {}: {}", path, e));
+        let mut magic = [0u8; 2];
+        let is_gzip = File::open(path)
+            .map_err(io_error)?
+            .read(&mut magic)
+            .map_err(io_error)?
+            == 2
+            && magic == GZIP_MAGIC;
+
+        let file = File::open(path).map_err(io_error)?;
+        let source: Box<dyn BufRead + Send> = if is_gzip {
+            Box::new(BufReader::new(MultiGzDecoder::new(BufReader::new(file))))
+        } else {
+            Box::new(BufReader::new(file))
+        };
+        let mut reader = Reader::from_reader(source);
+        reader.trim_text(true);
+
+        Ok(MzMLReader {
+            reader,
+            path: path.to_string(),
+            file_id,
+            buf: Vec::new(),
+        })
+    }
+
+    /// The next spectrum of the file and its activation method, None at the end of the spectrum list
+    fn next_spectrum(&mut self) -> PyResult<Option<(RawSpectrum, Option<String>)>> {
+        let MzMLReader {
+            reader,
+            path,
+            file_id,
+            buf,
+        } = self;
+        let read_error = mzml_error(path);
+
+        let mut spectrum: Option<RawSpectrum> = None;
+        let mut activation_type: Option<String> = None;
+
+        // state of the current precursor
+        let mut precursor: Option<Precursor> = None;
+        let mut selected_mz: Option<f32> = None;
+        let mut target_mz: Option<f32> = None;
+        let mut window: (Option<f32>, Option<f32>) = (None, None);
+
+        // state of the current binary data array
+        let mut array: Option<&str> = None;
+        let mut is_64_bit = false;
+        let mut is_zlib = false;
+        let mut in_binary = false;
+        let mut binary = String::new();
+
+        loop {
+            buf.clear();
+            match reader.read_event_into(buf).map_err(&read_error)? {
+                Event::Start(e) | Event::Empty(e) => match e.local_name().as_ref() {
+                    b"spectrum" => {
+                        spectrum = Some(RawSpectrum {
+                            file_id: *file_id,
+                            ms_level: 0,
+                            id: attribute(&e, "id")?.unwrap_or_default(),
+                            precursors: Vec::new(),
+                            representation: Representation::Centroid,
+                            scan_start_time: 0.0,
+                            ion_injection_time: 0.0,
+                            total_ion_current: 0.0,
+                            mz: Vec::new(),
+                            intensity: Vec::new(),
+                        });
+                        activation_type = None;
+                    }
+                    b"precursor" if spectrum.is_some() => {
+                        precursor = Some(Precursor {
+                            spectrum_ref: attribute(&e, "spectrumRef")?,
+                            ..Precursor::default()
+                        });
+                        selected_mz = None;
+                        target_mz = None;
+                        window = (None, None);
+                    }
+                    b"binaryDataArray" => {
+                        array = None;
+                        is_64_bit = false;
+                        is_zlib = false;
+                        binary.clear();
+                    }
+                    b"binary" => in_binary = true,
+                    b"cvParam" => {
+                        let Some(s) = spectrum.as_mut() else { continue };
+                        let accession = attribute(&e, "accession")?.unwrap_or_default();
+                        match accession.as_str() {
+                            "MS:1000511" => s.ms_level = float_attribute(&e, "value")?.unwrap_or(0.0) as u8,
+                            "MS:1000127" => s.representation = Representation::Centroid,
+                            "MS:1000128" => s.representation = Representation::Profile,
+                            "MS:1000285" => s.total_ion_current = float_attribute(&e, "value")?.unwrap_or(0.0),
+                            "MS:1000016" => {
+                                let time = float_attribute(&e, "value")?.unwrap_or(0.0);
+                                let unit = attribute(&e, "unitAccession")?;
+                                // sage keeps retention times in minutes
+                                s.scan_start_time = match unit.as_deref() {
+                                    Some("UO:0000010") => time / 60.0,
+                                    _ => time,
+                                };
+                            }
+                            "MS:1000927" => s.ion_injection_time = float_attribute(&e, "value")?.unwrap_or(0.0),
+                            "MS:1000827" => target_mz = float_attribute(&e, "value")?,
+                            "MS:1000828" => window.0 = float_attribute(&e, "value")?,
+                            "MS:1000829" => window.1 = float_attribute(&e, "value")?,
+                            "MS:1000744" => selected_mz = float_attribute(&e, "value")?,
+                            "MS:1000041" => {
+                                if let Some(p) = precursor.as_mut() {
+                                    p.charge = float_attribute(&e, "value")?.map(|z| z as u8);
+                                }
+                            }
+                            "MS:1000042" => {
+                                if let Some(p) = precursor.as_mut() {
+                                    p.intensity = float_attribute(&e, "value")?;
+                                }
+                            }
+                            "MS:1000514" => array = Some("mz"),
+                            "MS:1000515" => array = Some("intensity"),
+                            "MS:1000521" => is_64_bit = false,
+                            "MS:1000523" => is_64_bit = true,
+                            "MS:1000574" => is_zlib = true,
+                            "MS:1000576" => is_zlib = false,
+                            other => activation_type = combine_activation(activation_type.take(), other),
+                        }
+                    }
+                    _ => {}
+                },
+                Event::Text(text) if in_binary => {
+                    binary.push_str(&text.unescape().map_err(&read_error)?);
+                }
+                Event::End(e) => match e.local_name().as_ref() {
+                    b"binary" => in_binary = false,
+                    b"binaryDataArray" => {
+                        if let (Some(s), Some(kind)) = (spectrum.as_mut(), array) {
+                            let values = decode_binary(&binary, is_64_bit, is_zlib)?;
+                            match kind {
+                                "mz" => s.mz = values,
+                                _ => s.intensity = values,
+                            }
+                        }
+                    }
+                    b"precursor" => {
+                        if let (Some(s), Some(mut p)) = (spectrum.as_mut(), precursor.take()) {
+                            p.mz = selected_mz.or(target_mz).unwrap_or(0.0);
+                            if let (Some(lo), Some(hi)) = window {
+                                p.isolation_window = Some(Tolerance::Da(-lo, hi));
+                            }
+                            s.precursors.push(p);
+                        }
+                    }
+                    b"spectrum" => {
+                        if let Some(s) = spectrum.take() {
+                            return Ok(Some((s, activation_type)));
+                        }
+                    }
+                    // chromatograms follow the spectra
+                    b"spectrumList" => return Ok(None),
+                    _ => {}
+                },
+                Event::Eof => return Ok(None),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Wrap a spectrum read from mzML keeping all peaks, as neutral (singly charged) masses. Profile
+/// spectra are centroided if requested
+fn mzml_spectrum(raw: RawSpectrum, activation_type: Option<String>, centroid_profile: bool) -> PyProcessedSpectrum {
+    let raw = match matches!(raw.representation, Representation::Profile) && centroid_profile {
+        true => centroid(&raw, MZML_CENTROID_SNR, MZML_CENTROID_PPM),
+        false => raw,
+    };
+    let is_centroided = matches!(raw.representation, Representation::Centroid);
+    let total_ion_current = match raw.total_ion_current > 0.0 {
+        true => raw.total_ion_current,
+        false => raw.intensity.iter().sum(),
+    };
+    PyProcessedSpectrum {
+        inner: ProcessedSpectrum {
+            level: raw.ms_level,
+            id: raw.id,
+            file_id: raw.file_id,
+            scan_start_time: raw.scan_start_time,
+            ion_injection_time: raw.ion_injection_time,
+            precursors: raw.precursors,
+            peaks: raw
+                .mz
+                .iter()
+                .zip(raw.intensity.iter())
+                .map(|(mz, intensity)| Peak {
+                    mass: mz - PROTON,
+                    intensity: *intensity,
+                })
+                .collect(),
+            total_ion_current,
+        },
+        activation_type,
+        is_centroided,
+        precursor_candidates: Vec::new(),
+        ims_values: None,
+        precursor_ims: None,
+    }
+}
+
+/// Read the spectra of an mzML file (optionally gzip compressed) with the given MS level. Scan
+/// ids, retention times (in minutes), precursors with their isolation windows, activation
+/// methods and all peaks are kept, see `iter_mzml` to stream large files
+#[pyfunction]
+pub fn read_mzml(
+    path: &str,
+    ms_level: u8,
+    file_id: Option<usize>,
+    centroid: Option<bool>,
+) -> PyResult<Vec<PyProcessedSpectrum>> {
+    let mut reader = MzMLReader::open(path, file_id.unwrap_or_default())?;
+    let mut spectra = Vec::new();
+    while let Some((raw, activation_type)) = reader.next_spectrum()? {
+        if raw.ms_level == ms_level {
+            spectra.push(mzml_spectrum(raw, activation_type, centroid.unwrap_or(false)));
+        }
+    }
+    Ok(spectra)
+}
+
+/// Iterator over the spectra of an mzML file, reading one spectrum at a time
+#[pyclass]
+pub struct PyMzMLIterator {
+    reader: MzMLReader,
+    ms_level: Option<u8>,
+    centroid: bool,
+}
+
+#[pymethods]
+impl PyMzMLIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self) -> PyResult<Option<PyProcessedSpectrum>> {
+        while let Some((raw, activation_type)) = self.reader.next_spectrum()? {
+            if self.ms_level.map_or(true, |level| level == raw.ms_level) {
+                return Ok(Some(mzml_spectrum(raw, activation_type, self.centroid)));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Stream the spectra of an mzML file, all MS levels unless `ms_level` is given (see `read_mzml`)
+#[pyfunction]
+pub fn iter_mzml(
+    path: &str,
+    ms_level: Option<u8>,
+    file_id: Option<usize>,
+    centroid: Option<bool>,
+) -> PyResult<PyMzMLIterator> {
+    Ok(PyMzMLIterator {
+        reader: MzMLReader::open(path, file_id.unwrap_or_default())?,
+        ms_level,
+        centroid: centroid.unwrap_or(false),
+    })
+}
+
+/// An entry of an MSP (NIST) spectral library. Peaks without a parseable ion annotation are
+/// stored as y ions with ordinal 0, metadata holds all header fields but Name, MW and Num peaks
+#[pyclass]
+#[derive(Clone)]
+pub struct PySpectralLibraryEntry {
+    #[pyo3(get, set)]
+    pub name: String,
+    #[pyo3(get, set)]
+    pub mw: f32,
+    #[pyo3(get, set)]
+    pub charge: u8,
+    #[pyo3(get, set)]
+    pub fragments: PyFragments,
+    #[pyo3(get, set)]
+    pub metadata: HashMap<String, String>,
+}
+
+#[pymethods]
+impl PySpectralLibraryEntry {
+    #[new]
+    pub fn new(
+        name: String,
+        mw: f32,
+        charge: u8,
+        fragments: PyFragments,
+        metadata: Option<HashMap<String, String>>,
+    ) -> Self {
+        PySpectralLibraryEntry {
+            name,
+            mw,
+            charge,
+            fragments,
+            metadata: metadata.unwrap_or_default(),
+        }
+    }
+}
+
+/// Value of a metadata field, MSP field names are matched case-insensitively
+fn msp_field<'a>(metadata: &'a HashMap<String, String>, key: &str) -> Option<&'a str> {
+    metadata
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(key))
+        .map(|(_, v)| v.trim())
+}
+
+/// Leading number of a string, e.g. 2 of "2_1(4,C,CAM)"
+fn leading_number(s: &str) -> &str {
+    let end = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(s.len());
+    &s[..end]
+}
+
+/// Kind, ordinal, charge and neutral loss of the first interpretation of an MSP peak annotation,
+/// e.g. "y7/0.01", "b3^2/-0.02" or "y5-18^2 2/2 0.8"; None for unknown ("?") or other ions
+fn parse_msp_annotation(annotation: &str) -> Option<(Kind, i32, i32, f32)> {
+    let first = annotation.trim_matches('"').split([',', '/', ' ']).next()?;
+    let kind = match first.chars().next()? {
+        'a' => Kind::A,
+        'b' => Kind::B,
+        'c' => Kind::C,
+        'x' => Kind::X,
+        'y' => Kind::Y,
+        'z' => Kind::Z,
+        _ => return None,
+    };
+    let ordinal_str = leading_number(&first[1..]);
+    let ordinal = ordinal_str.parse().ok()?;
+
+    let mut charge = 1;
+    let mut loss = 0.0;
+    let mut rest = &first[1 + ordinal_str.len()..];
+    while let Some(c) = rest.chars().next() {
+        let tail = &rest[1..];
+        let value = leading_number(tail);
+        match c {
+            '^' => charge = value.parse().ok()?,
+            '-' | '+' => {
+                let (mass, len) = match value.parse::<f32>() {
+                    Ok(mass) => (mass, value.len()),
+                    Err(_) if tail.starts_with("H2O") => (H2O, 3),
+                    Err(_) if tail.starts_with("NH3") => (NH3, 3),
+                    Err(_) => return None,
+                };
+                loss += if c == '-' { mass } else { -mass };
+                rest = &tail[len..];
+                continue;
+            }
+            // isotope peaks, mass deviations and other suffixes
+            _ => break,
+        }
+        rest = &tail[value.len()..];
+    }
+    Some((kind, ordinal, charge, loss))
+}
+
+fn msp_annotation(kind: Kind, ordinal: i32, charge: i32, loss: f32) -> String {
+    if ordinal == 0 {
+        return "?".to_string();
+    }
+    let mut annotation = format!("{:?}{}", kind, ordinal).to_lowercase();
+    if loss != 0.0 {
+        annotation.push_str(&format!("{:+}", -loss));
+    }
+    if charge > 1 {
+        annotation.push_str(&format!("^{}", charge));
+    }
+    annotation
+}
+
+/// Builds the entries of an MSP file while its lines are read
+struct MspEntryBuilder {
+    name: String,
+    mw: Option<f32>,
+    metadata: HashMap<String, String>,
+    in_peaks: bool,
+    fragments: Fragments,
+    neutral_losses: Vec<f32>,
+}
+
+impl MspEntryBuilder {
+    fn new(name: &str) -> Self {
+        MspEntryBuilder {
+            name: name.to_string(),
+            mw: None,
+            metadata: HashMap::new(),
+            in_peaks: false,
+            fragments: Fragments {
+                charges: Vec::new(),
+                kinds: Vec::new(),
+                fragment_ordinals: Vec::new(),
+                intensities: Vec::new(),
+                mz_calculated: Vec::new(),
+                mz_experimental: Vec::new(),
+            },
+            neutral_losses: Vec::new(),
+        }
+    }
+
+    /// Add the peaks of a peak line, which may hold several "m/z [intensity] [annotation]"
+    /// groups separated by ';'. Peaks without intensity get intensity 1.0
+    fn add_peaks(&mut self, line: &str) -> Result<(), String> {
+        for group in line.split(';').map(str::trim).filter(|g| !g.is_empty()) {
+            let mut tokens = group.split_whitespace();
+            let mz: f32 = tokens
+                .next()
+                .and_then(|t| t.parse().ok())
+                .ok_or_else(|| format!("invalid peak \"{}\"", group))?;
+            let mut rest: Vec<&str> = tokens.collect();
+            let intensity = match rest.first().and_then(|t| t.parse::<f32>().ok()) {
+                Some(intensity) => {
+                    rest.remove(0);
+                    intensity
+                }
+                None => 1.0,
+            };
+            let (kind, ordinal, charge, loss) =
+                parse_msp_annotation(&rest.join(" ")).unwrap_or((Kind::Y, 0, 1, 0.0));
+            self.fragments.kinds.push(kind);
+            self.fragments.fragment_ordinals.push(ordinal);
+            self.fragments.charges.push(charge);
+            self.fragments.intensities.push(intensity);
+            self.fragments.mz_calculated.push(mz);
+            self.fragments.mz_experimental.push(mz);
+            self.neutral_losses.push(loss);
+        }
+        Ok(())
+    }
+
+    /// Charge from the Name suffix (PEPTIDEK/2) or a Charge field, 0 if neither is given. The
+    /// MW defaults to the neutral mass of a PrecursorMZ field
+    fn build(self) -> PySpectralLibraryEntry {
+        let charge = self
+            .name
+            .rsplit_once('/')
+            .and_then(|(_, suffix)| leading_number(suffix).parse().ok())
+            .or_else(|| msp_field(&self.metadata, "Charge").and_then(|c| c.trim_end_matches('+').parse().ok()))
+            .unwrap_or(0);
+        let mw = self.mw.unwrap_or_else(|| {
+            msp_field(&self.metadata, "PrecursorMZ")
+                .and_then(|mz| mz.parse::<f32>().ok())
+                .map_or(0.0, |mz| (mz - PROTON) * charge as f32)
+        });
+        PySpectralLibraryEntry {
+            name: self.name,
+            mw,
+            charge,
+            fragments: PyFragments {
+                inner: self.fragments,
+                neutral_losses: self.neutral_losses,
+            },
+            metadata: self.metadata,
+        }
+    }
+}
+
+/// Read the entries of an MSP (NIST) spectral library. Header fields other than Name, MW and
+/// Num peaks are kept as metadata, peak lines may hold m/z only or m/z, intensity and annotation
+#[pyfunction]
+pub fn read_msp(path: &str) -> PyResult<Vec<PySpectralLibraryEntry>> {
+    let file = File::open(path)
+        .map_err(|e| PyValueError::new_err(format!("Could not read MSP file {}: {}", path, e)))?;
+
+    let mut entries = Vec::new();
+    let mut current: Option<MspEntryBuilder> = None;
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|e| PyValueError::new_err(format!("Could not read MSP file {}: {}", path, e)))?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let msp_error =
+            |e: String| PyValueError::new_err(format!("Invalid MSP file {} line {}: {}", path, number + 1, e));
+
+        if let Some(entry) = current.as_mut().filter(|e| e.in_peaks) {
+            if line.starts_with(|c: char| c.is_ascii_digit() || c == '.') {
+                entry.add_peaks(line).map_err(msp_error)?;
+                continue;
+            }
+        }
+        let Some((key, value)) = line.split_once(':') else {
+            return Err(msp_error(format!("expected a \"field: value\" line, got \"{}\"", line)));
+        };
+        let value = value.trim();
+        match key.trim().to_lowercase().as_str() {
+            "name" => {
+                entries.extend(current.take().map(MspEntryBuilder::build));
+                current = Some(MspEntryBuilder::new(value));
+            }
+            _ if current.is_none() => return Err(msp_error("field before the first Name".to_string())),
+            "mw" => {
+                let mw = value.parse().map_err(|_| msp_error(format!("invalid MW \"{}\"", value)))?;
+                current.as_mut().unwrap().mw = Some(mw);
+            }
+            "num peaks" | "numpeaks" | "num_peaks" => current.as_mut().unwrap().in_peaks = true,
+            _ => {
+                current
+                    .as_mut()
+                    .unwrap()
+                    .metadata
+                    .insert(key.trim().to_string(), value.to_string());
+            }
+        }
+    }
+    entries.extend(current.map(MspEntryBuilder::build));
+    Ok(entries)
+}
+
+/// Write entries as an MSP (NIST) spectral library, peaks as tab separated m/z, intensity and
+/// ion annotation ("?" for peaks with ordinal 0)
+#[pyfunction]
+pub fn write_msp(entries: Vec<PySpectralLibraryEntry>, path: &str) -> PyResult<()> {
+    let io_error = |e: std::io::Error| PyValueError::new_err(format!("Could not write MSP file {}: {}", path, e));
+    let mut writer = BufWriter::new(File::create(path).map_err(io_error)?);
+
+    for entry in &entries {
+        writeln!(writer, "Name: {}", entry.name).map_err(io_error)?;
+        writeln!(writer, "MW: {}", entry.mw).map_err(io_error)?;
+        let has_charge = entry.name.contains('/') || msp_field(&entry.metadata, "Charge").is_some();
+        if !has_charge && entry.charge > 0 {
+            writeln!(writer, "Charge: {}", entry.charge).map_err(io_error)?;
+        }
+        let mut metadata: Vec<_> = entry.metadata.iter().collect();
+        metadata.sort();
+        for (key, value) in metadata {
+            writeln!(writer, "{}: {}", key, value).map_err(io_error)?;
+        }
+
+        let fragments = &entry.fragments.inner;
+        writeln!(writer, "Num peaks: {}", fragments.mz_calculated.len()).map_err(io_error)?;
+        for i in 0..fragments.mz_calculated.len() {
+            let annotation = msp_annotation(
+                fragments.kinds[i],
+                fragments.fragment_ordinals[i],
+                fragments.charges[i],
+                entry.fragments.neutral_losses.get(i).copied().unwrap_or_default(),
+            );
+            writeln!(
+                writer,
+                "{}\t{}\t\"{}\"",
+                fragments.mz_calculated[i], fragments.intensities[i], annotation
+            )
+            .map_err(io_error)?;
+        }
+        writeln!(writer).map_err(io_error)?;
+    }
+    writer.flush().map_err(io_error)
+}
+
+/// Residues of a modified sequence, ignoring bracketed modifications such as [UNIMOD:4] or (O)
+fn residue_count(sequence: &str) -> usize {
+    let mut depth = 0;
+    sequence
+        .chars()
+        .filter(|c| {
+            match c {
+                '[' | '(' => depth += 1,
+                ']' | ')' => depth -= 1,
+                _ => return depth == 0 && c.is_ascii_uppercase(),
+            }
+            false
+        })
+        .count()
+}
+
+/// Convert MSP entries to a spectral library, keyed by the Name without charge suffix. The
+/// precursor m/z is taken from a PrecursorMZ field or computed from MW, entries with a Decoy
+/// field of true or 1 are decoys. Entries of the same precursor m/z and charge replace earlier ones
+#[pyfunction]
+pub fn msp_to_spectral_library(entries: Vec<PySpectralLibraryEntry>) -> PyResult<PySpectralLibrary> {
+    let mut library = PySpectralLibrary::default();
+    for entry in entries {
+        if entry.charge == 0 {
+            return Err(PyValueError::new_err(format!("MSP entry {} has no charge", entry.name)));
+        }
+        let precursor_mz = msp_field(&entry.metadata, "PrecursorMZ")
+            .and_then(|mz| mz.parse().ok())
+            .unwrap_or(entry.mw / entry.charge as f32 + PROTON);
+        let sequence_modified = entry.name.split('/').next().unwrap_or_default().to_string();
+        let decoy = msp_field(&entry.metadata, "Decoy")
+            .is_some_and(|d| d.eq_ignore_ascii_case("true") || d == "1");
+        let library_entry = LibraryEntry {
+            peptide_len: residue_count(&sequence_modified),
+            sequence_modified,
+            peptide_idx: PeptideIx(u32::MAX),
+            precursor_mz,
+            decoy,
+            fragments: entry.fragments.inner,
+        };
+        library
+            .entries
+            .insert((quantize_mz(precursor_mz), entry.charge), library_entry);
+    }
+    Ok(library)
+}
+
 #[pymodule]
 pub fn spectrum(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_class::<PyPeak>()?;
@@ -413,5 +1852,345 @@ pub fn spectrum(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_class::<PyRepresentation>()?;
     m.add_class::<PyRawSpectrum>()?;
     m.add_class::<PyProcessedSpectrum>()?;
+    m.add_function(wrap_pyfunction!(centroid_spectrum, m)?)?;
+    m.add_function(wrap_pyfunction!(centroid_collection, m)?)?;
+    m.add_function(wrap_pyfunction!(deconvolve_charge, m)?)?;
+    m.add_function(wrap_pyfunction!(simulate_tims_isolation, m)?)?;
+    m.add_function(wrap_pyfunction!(tims_chimera_fraction, m)?)?;
+    m.add_function(wrap_pyfunction!(compute_precursor_purity, m)?)?;
+    m.add_function(wrap_pyfunction!(tag_precursor_purity, m)?)?;
+    m.add_function(wrap_pyfunction!(filter_by_precursor_purity, m)?)?;
+    m.add_function(wrap_pyfunction!(extract_xic, m)?)?;
+    m.add_function(wrap_pyfunction!(integrate_xic, m)?)?;
+    m.add_function(wrap_pyfunction!(find_xic_apex, m)?)?;
+    m.add_function(wrap_pyfunction!(xic_for_peptide, m)?)?;
+    m.add_function(wrap_pyfunction!(bin_spectrum, m)?)?;
+    m.add_function(wrap_pyfunction!(bin_spectra_parallel, m)?)?;
+    m.add_function(wrap_pyfunction!(dot_product_score, m)?)?;
+    m.add_function(wrap_pyfunction!(cosine_similarity, m)?)?;
+    m.add_class::<PyMzMLIterator>()?;
+    m.add_function(wrap_pyfunction!(read_mzml, m)?)?;
+    m.add_function(wrap_pyfunction!(iter_mzml, m)?)?;
+    m.add_class::<PySpectralLibraryEntry>()?;
+    m.add_function(wrap_pyfunction!(read_msp, m)?)?;
+    m.add_function(wrap_pyfunction!(write_msp, m)?)?;
+    m.add_function(wrap_pyfunction!(msp_to_spectral_library, m)?)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile_spectrum(apexes: &[(f32, f32)]) -> RawSpectrum {
+        // profile points every 2 mTh with Gaussian peaks of 4 mTh sigma
+        let mz: Vec<f32> = (0..200).map(|k| 499.8 + k as f32 * 0.002).collect();
+        let intensity = mz
+            .iter()
+            .map(|m| {
+                let signal: f32 = apexes
+                    .iter()
+                    .map(|(apex, height)| height * (-0.5 * ((m - apex) / 0.004).powi(2)).exp())
+                    .sum();
+                signal + 1.0
+            })
+            .collect();
+        RawSpectrum {
+            file_id: 0,
+            ms_level: 1,
+            id: "scan=1".to_string(),
+            precursors: Vec::new(),
+            representation: Representation::Profile,
+            scan_start_time: 0.0,
+            ion_injection_time: 0.0,
+            total_ion_current: 0.0,
+            mz,
+            intensity,
+        }
+    }
+
+    #[test]
+    fn centroid_separates_peaks_by_ppm() {
+        // 60 ppm apart, only a few profile points between the apexes
+        let spectrum = profile_spectrum(&[(500.0, 1000.0), (500.03, 500.0)]);
+        let centroided = centroid(&spectrum, 3.0, 20.0);
+
+        assert!(matches!(centroided.representation, Representation::Centroid));
+        assert_eq!(centroided.mz.len(), 2);
+        assert!((centroided.mz[0] - 500.0).abs() < 0.001);
+        assert!((centroided.mz[1] - 500.03).abs() < 0.001);
+        assert!(centroided.intensity[0] > centroided.intensity[1]);
+    }
+
+    fn envelope_spectrum(mass: f32, charge: u8, selected_isotope: usize) -> PyProcessedSpectrum {
+        let spacing = NEUTRON / charge as f32;
+        let mono_mz = mass / charge as f32 + PROTON;
+        let (mut mz, mut intensity) = (vec![mono_mz - 5.0], vec![50.0]);
+        for (k, abundance) in averagine_distribution(mass, DECONVOLUTION_ISOTOPES).iter().enumerate() {
+            mz.push(mono_mz + k as f32 * spacing);
+            intensity.push(1000.0 * abundance);
+        }
+        let precursor_mz = mono_mz + selected_isotope as f32 * spacing;
+        PyProcessedSpectrum::from_arrays("scan=1".to_string(), precursor_mz, 0, mz, intensity, 10.0, None).unwrap()
+    }
+
+    #[test]
+    fn deconvolution_ranks_the_charge_of_the_isotope_envelope_first() {
+        for selected_isotope in 0..=1 {
+            let spectrum = envelope_spectrum(2000.0, 3, selected_isotope);
+            let candidates = deconvolve_charge(&spectrum, 1, 4, 10.0, None).unwrap();
+
+            let (mass, charge, confidence) = candidates[0];
+            assert_eq!(charge, 3);
+            assert!((mass - 2000.0).abs() < 0.01, "{}", mass);
+            assert!(confidence > 0.99);
+            // other charges only match the isotopes that coincide with their spacing
+            assert!(candidates[1..].iter().all(|c| c.1 != 3 && c.2 < confidence));
+        }
+    }
+
+    fn binary_data_array(values: &[f64], kind: &str, is_64_bit: bool, is_zlib: bool) -> String {
+        let mut bytes: Vec<u8> = match is_64_bit {
+            true => values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+            false => values.iter().flat_map(|v| (*v as f32).to_le_bytes()).collect(),
+        };
+        if is_zlib {
+            let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(&bytes).unwrap();
+            bytes = encoder.finish().unwrap();
+        }
+        format!(
+            r#"<binaryDataArray><cvParam accession="{}"/><cvParam accession="{}"/><cvParam accession="{}"/><binary>{}</binary></binaryDataArray>"#,
+            if is_64_bit { "MS:1000523" } else { "MS:1000521" },
+            if is_zlib { "MS:1000574" } else { "MS:1000576" },
+            if kind == "mz" { "MS:1000514" } else { "MS:1000515" },
+            BASE64.encode(bytes)
+        )
+    }
+
+    #[test]
+    fn mzml_spectra_keep_their_retention_time_precursor_and_peaks() {
+        let ms1 = format!(
+            r#"<spectrum id="scan=1"><cvParam accession="MS:1000511" value="1"/><cvParam accession="MS:1000127"/>
+            <scanList><scan><cvParam accession="MS:1000016" value="60.0" unitAccession="UO:0000010"/></scan></scanList>
+            <binaryDataArrayList>{}{}</binaryDataArrayList></spectrum>"#,
+            binary_data_array(&[445.12, 500.25], "mz", true, false),
+            binary_data_array(&[1000.0, 5000.0], "intensity", false, false),
+        );
+        let ms2 = format!(
+            r#"<spectrum id="scan=2"><cvParam accession="MS:1000511" value="2"/><cvParam accession="MS:1000127"/>
+            <scanList><scan><cvParam accession="MS:1000016" value="1.5" unitAccession="UO:0000031"/></scan></scanList>
+            <precursorList><precursor spectrumRef="scan=1">
+            <isolationWindow><cvParam accession="MS:1000827" value="500.0"/><cvParam accession="MS:1000828" value="0.7"/><cvParam accession="MS:1000829" value="0.7"/></isolationWindow>
+            <selectedIonList><selectedIon><cvParam accession="MS:1000744" value="500.25"/><cvParam accession="MS:1000041" value="2"/></selectedIon></selectedIonList>
+            <activation><cvParam accession="MS:1000422"/></activation></precursor></precursorList>
+            <binaryDataArrayList>{}{}</binaryDataArrayList></spectrum>"#,
+            binary_data_array(&[200.1, 300.2, 400.3], "mz", true, true),
+            binary_data_array(&[10.0, 20.0, 30.0], "intensity", false, true),
+        );
+        let mzml = format!(
+            r#"<?xml version="1.0" encoding="utf-8"?><mzML><run><spectrumList count="2">{}{}</spectrumList></run></mzML>"#,
+            ms1, ms2
+        );
+        let path = std::env::temp_dir().join(format!("sagepy_mzml_{}.mzML", std::process::id()));
+        std::fs::write(&path, mzml).unwrap();
+        let path = path.to_str().unwrap();
+
+        let spectra = read_mzml(path, 2, Some(3), None).unwrap();
+        assert_eq!(spectra.len(), 1);
+        let spectrum = &spectra[0];
+        assert_eq!(spectrum.inner.id, "scan=2");
+        assert_eq!(spectrum.inner.file_id, 3);
+        assert_eq!(spectrum.inner.scan_start_time, 1.5);
+        assert_eq!(spectrum.activation_type.as_deref(), Some("HCD"));
+        assert!(spectrum.is_centroided);
+
+        let precursor = &spectrum.inner.precursors[0];
+        assert_eq!(precursor.mz, 500.25);
+        assert_eq!(precursor.charge, Some(2));
+        assert_eq!(precursor.spectrum_ref.as_deref(), Some("scan=1"));
+        assert_eq!(spectrum.inner.in_isolation_window(500.6), Some(true));
+        assert_eq!(spectrum.inner.in_isolation_window(501.0), Some(false));
+
+        let mz: Vec<f32> = spectrum.inner.peaks.iter().map(|p| p.mass + PROTON).collect();
+        assert!(mz.iter().zip([200.1, 300.2, 400.3]).all(|(a, b)| (a - b).abs() < 1e-4));
+        assert_eq!(spectrum.inner.total_ion_current, 60.0);
+
+        // the iterator streams all MS levels, retention times in seconds are converted to minutes
+        let mut iterator = iter_mzml(path, None, None, None).unwrap();
+        let ms1 = iterator.__next__().unwrap().unwrap();
+        assert_eq!((ms1.inner.level, ms1.inner.scan_start_time), (1, 1.0));
+        assert!(ms1.inner.precursors.is_empty());
+        assert_eq!(iterator.__next__().unwrap().unwrap().inner.id, "scan=2");
+        assert!(iterator.__next__().unwrap().is_none());
+
+        let mut iterator = iter_mzml(path, Some(1), None, None).unwrap();
+        assert_eq!(iterator.__next__().unwrap().unwrap().inner.id, "scan=1");
+        assert!(iterator.__next__().unwrap().is_none());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn msp_entries_survive_a_write_and_read_round_trip() {
+        let msp = [
+            "Name: PEPTIDEK/2",
+            "MW: 927.45",
+            "Comment: Mods=0",
+            "Num peaks: 4",
+            "260.2\t1000\t\"y2/0.01\"",
+            "244.6\t200\t\"b4^2\"",
+            "359.2\t300\t\"y3-H2O\"",
+            "400\t50\t\"?\"",
+            "",
+            "NAME: ELVISK",
+            "Charge: 3+",
+            "PrecursorMZ: 230.4",
+            "Num peaks: 3",
+            "100.0; 200.0 ; 300.0",
+        ]
+        .join("\n");
+        let path = std::env::temp_dir().join(format!("sagepy_msp_{}.msp", std::process::id()));
+        std::fs::write(&path, msp).unwrap();
+        let path = path.to_str().unwrap();
+
+        let entries = read_msp(path).unwrap();
+        assert_eq!(entries.len(), 2);
+        let (first, second) = (&entries[0], &entries[1]);
+        assert_eq!((first.charge, first.mw), (2, 927.45));
+        assert_eq!(first.metadata, HashMap::from([("Comment".to_string(), "Mods=0".to_string())]));
+        let fragments = &first.fragments.inner;
+        assert_eq!(fragments.kinds, vec![Kind::Y, Kind::B, Kind::Y, Kind::Y]);
+        assert_eq!(fragments.fragment_ordinals, vec![2, 4, 3, 0]);
+        assert_eq!(fragments.charges, vec![1, 2, 1, 1]);
+        assert_eq!(first.fragments.neutral_losses, vec![0.0, 0.0, H2O, 0.0]);
+
+        // peaks without intensity, charge and MW from the Charge and PrecursorMZ fields
+        assert_eq!(second.charge, 3);
+        assert!((second.mw - (230.4 - PROTON) * 3.0).abs() < 1e-3);
+        assert_eq!(second.fragments.inner.mz_calculated, vec![100.0, 200.0, 300.0]);
+        assert_eq!(second.fragments.inner.intensities, vec![1.0; 3]);
+
+        write_msp(entries.clone(), path).unwrap();
+        let reread = read_msp(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        for (entry, reread) in entries.iter().zip(&reread) {
+            assert_eq!((&entry.name, entry.mw, entry.charge), (&reread.name, reread.mw, reread.charge));
+            assert_eq!(entry.metadata, reread.metadata);
+            let (a, b) = (&entry.fragments.inner, &reread.fragments.inner);
+            assert_eq!((&a.kinds, &a.fragment_ordinals, &a.charges), (&b.kinds, &b.fragment_ordinals, &b.charges));
+            assert_eq!((&a.mz_calculated, &a.intensities), (&b.mz_calculated, &b.intensities));
+            assert_eq!(entry.fragments.neutral_losses, reread.fragments.neutral_losses);
+        }
+
+        let library = msp_to_spectral_library(entries).unwrap();
+        assert_eq!(library.precursors(), vec![("ELVISK".to_string(), 3), ("PEPTIDEK".to_string(), 2)]);
+        let uncharged = PySpectralLibraryEntry::new("X".to_string(), 100.0, 0, reread[1].fragments.clone(), None);
+        assert!(msp_to_spectral_library(vec![uncharged]).is_err());
+    }
+
+    fn tims_spectrum() -> PyProcessedSpectrum {
+        PyProcessedSpectrum::from_arrays(
+            "scan=1".to_string(),
+            600.0,
+            2,
+            vec![200.0, 300.0, 400.0, 500.0],
+            vec![100.0, 50.0, 30.0, 20.0],
+            10.0,
+            None,
+        )
+        .unwrap()
+        .with_ims_values(vec![0.90, 0.91, 1.10, 0.89])
+        .unwrap()
+    }
+
+    #[test]
+    fn tims_isolation_drops_fragments_of_other_mobilities() {
+        let spectrum = tims_spectrum();
+        let isolated = simulate_tims_isolation(&spectrum, 600.0, 0.90, 2.0, 0.05).unwrap();
+
+        let intensities: Vec<f32> = isolated.inner.peaks.iter().map(|p| p.intensity).collect();
+        assert_eq!(intensities, vec![100.0, 50.0, 20.0]);
+        assert_eq!(isolated.ims_values, Some(vec![0.90, 0.91, 0.89]));
+        assert_eq!(isolated.inner.total_ion_current, 170.0);
+        assert_eq!(isolated.inner.precursors.len(), 1);
+        assert_eq!(isolated.inner.in_isolation_window(600.9), Some(true));
+        assert_eq!(isolated.inner.in_isolation_window(601.1), Some(false));
+
+        // a precursor outside the m/z window is not isolated
+        let isolated = simulate_tims_isolation(&spectrum, 610.0, 0.90, 2.0, 0.05).unwrap();
+        assert!(isolated.inner.precursors.is_empty());
+    }
+
+    #[test]
+    fn tims_chimera_fraction_is_the_intensity_outside_the_mobility_window() {
+        let spectrum = tims_spectrum();
+        assert!((tims_chimera_fraction(&spectrum, 0.90, 0.05).unwrap() - 0.15).abs() < 1e-6);
+        assert_eq!(tims_chimera_fraction(&spectrum, 0.90, 1.0).unwrap(), 0.0);
+        assert!(spectrum.with_ims_values(vec![1.0]).is_err());
+    }
+
+    #[test]
+    fn precursor_purity_counts_the_isotope_envelope_against_co_isolated_peaks() {
+        // a 2+ precursor at 500 with two isotopes, a co-isolated peak and a peak outside the window
+        let mz = vec![499.3, 500.0, 500.0 + NEUTRON / 2.0, 500.0 + NEUTRON, 520.0];
+        let intensity = vec![30.0, 100.0, 50.0, 20.0, 1000.0];
+        let ms1 = PyProcessedSpectrum::from_arrays("ms1".to_string(), 0.0, 0, mz, intensity, 1.0, None).unwrap();
+
+        let purity = compute_precursor_purity(&ms1, 500.0, 3.0, Some(2), None);
+        assert!((purity - 170.0 / 200.0).abs() < 1e-5, "purity {}", purity);
+        // without a charge only the monoisotopic peak is the precursor
+        let monoisotopic = compute_precursor_purity(&ms1, 500.0, 3.0, None, None);
+        assert!((monoisotopic - 0.5).abs() < 1e-5);
+        assert_eq!(compute_precursor_purity(&ms1, 600.0, 3.0, Some(2), None), 0.0);
+
+        let psm = |spec_id: &str| {
+            PyFeature::from(sage_core::scoring::Feature {
+                spec_id: spec_id.to_string(),
+                charge: 2,
+                expmass: 2.0 * (500.0 - PROTON),
+                ..crate::py_io::default_feature()
+            })
+        };
+        let ms1_spectra = HashMap::from([("1".to_string(), ms1)]);
+        let tagged = tag_precursor_purity(vec![psm("1"), psm("2")], ms1_spectra, 3.0, None);
+        assert!((tagged[0].precursor_purity.unwrap() - 0.85).abs() < 1e-5);
+        assert_eq!(tagged[1].precursor_purity, None);
+
+        let kept = filter_by_precursor_purity(tagged, 0.8);
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0].inner.spec_id, "1");
+    }
+
+    #[test]
+    fn xic_traces_the_precursor_across_ms1_spectra() {
+        // a 2+ precursor at 500 eluting over five MS1 spectra, next to an intense unrelated peak
+        let heights = [10.0, 30.0, 50.0, 30.0, 10.0];
+        let ms1 = |rt: f32, height: f32| {
+            let mz = vec![500.0, 500.1, 500.0 + NEUTRON / 2.0];
+            let intensity = vec![height, 1000.0, height / 2.0];
+            (rt, PyProcessedSpectrum::from_arrays(format!("ms1_{}", rt), 0.0, 0, mz, intensity, rt, None).unwrap())
+        };
+        // spectra in any order, the XIC is sorted by retention time
+        let spectra: Vec<(f32, PyProcessedSpectrum)> =
+            [4, 0, 2, 1, 3].into_iter().map(|k| ms1(k as f32 + 1.0, heights[k])).collect();
+        let tolerance = PyTolerance { inner: Tolerance::Ppm(-10.0, 10.0) };
+
+        let trace = extract_xic(spectra.clone(), 500.0, 2, &tolerance, None);
+        let expected: Vec<(f32, f32)> = heights.iter().enumerate().map(|(k, h)| (k as f32 + 1.0, *h)).collect();
+        assert_eq!(trace, expected);
+        let with_isotope = extract_xic(spectra.clone(), 500.0, 2, &tolerance, Some(2));
+        assert!(with_isotope.iter().zip(&expected).all(|(a, b)| a.1 == 1.5 * b.1));
+
+        assert_eq!(find_xic_apex(trace.clone()).unwrap(), 3.0);
+        assert!(find_xic_apex(vec![]).is_err());
+        assert_eq!(integrate_xic(trace.clone(), 3.0, 2.0), 80.0);
+        assert_eq!(integrate_xic(trace.clone(), 3.0, 10.0), 120.0);
+
+        let psm = PyFeature::from(sage_core::scoring::Feature {
+            charge: 2,
+            calcmass: 2.0 * (500.0 - PROTON),
+            ..crate::py_io::default_feature()
+        });
+        assert_eq!(xic_for_peptide(&psm, spectra, 10.0), trace);
+    }
+}
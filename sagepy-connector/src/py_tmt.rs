@@ -1,7 +1,29 @@
+use pyo3::exceptions::PyValueError;
 use pyo3::prelude::*;
 use sage_core::tmt::{Isobaric, Purity, TmtQuant};
-use crate::py_scoring::PyFeature;
-use crate::py_spectrum::{PyPeak, PyProcessedSpectrum};
+use crate::py_database::PyIndexedDatabase;
+use crate::py_mass::PyTolerance;
+use crate::py_scoring::{check_peptide_indices, solve_linear, PyFeature, PyScorer};
+use crate::py_spectrum::{PyPeak, PyProcessedSpectrum, PyRawSpectrum};
+use crate::py_utility::median;
+use sage_core::database::IndexedDatabase;
+use sage_core::ion_series::{IonSeries, Kind};
+use sage_core::mass::{Tolerance, PROTON};
+use sage_core::peptide::Peptide;
+use sage_core::spectrum::ProcessedSpectrum;
+use std::collections::HashMap;
+
+const TMT6_CHANNELS: [&str; 6] = ["126", "127", "128", "129", "130", "131"];
+const TMT10_CHANNELS: [&str; 10] = [
+    "126", "127N", "127C", "128N", "128C", "129N", "129C", "130N", "130C", "131",
+];
+const TMT11_CHANNELS: [&str; 11] = [
+    "126", "127N", "127C", "128N", "128C", "129N", "129C", "130N", "130C", "131N", "131C",
+];
+const TMTPRO_CHANNELS: [&str; 18] = [
+    "126", "127N", "127C", "128N", "128C", "129N", "129C", "130N", "130C", "131N", "131C", "132N",
+    "132C", "133N", "133C", "134N", "134C", "135N",
+];
 
 #[pyclass]
 pub struct PyIsobaric {
@@ -20,7 +42,7 @@ impl PyIsobaric {
                 "tmt10" => Isobaric::Tmt10,
                 "tmt11" => Isobaric::Tmt11,
                 "tmt16" => Isobaric::Tmt16,
-                "tmt18" => Isobaric::Tmt18,
+                "tmt18" | "tmtpro18" => Isobaric::Tmt18,
                 _ => panic!("Invalid isobaric type"),
             },
         }
@@ -40,6 +62,25 @@ impl PyIsobaric {
     pub fn modification_mass(&self) -> Option<f32> {
         self.inner.modification_mass()
     }
+
+    /// m/z of the reporter ions, in channel order
+    #[getter]
+    pub fn reporter_masses(&self) -> Vec<f32> {
+        self.inner.reporter_masses().to_vec()
+    }
+
+    #[getter]
+    pub fn channel_names(&self) -> Vec<String> {
+        let names: &[&str] = match self.inner {
+            Isobaric::Tmt6 => &TMT6_CHANNELS,
+            Isobaric::Tmt10 => &TMT10_CHANNELS,
+            Isobaric::Tmt11 => &TMT11_CHANNELS,
+            Isobaric::Tmt16 => &TMTPRO_CHANNELS[..16],
+            Isobaric::Tmt18 => &TMTPRO_CHANNELS,
+            _ => &[],
+        };
+        names.iter().map(|n| n.to_string()).collect()
+    }
 }
 
 #[pyclass]
@@ -187,11 +228,744 @@ impl PyTmtQuant {
 }
 
 
+/// Reporter ion intensities of a (centroided) MS2/MS3 spectrum, the most intense peak within the
+/// tolerance of each reporter m/z or 0 if there is none
+#[pyfunction]
+pub fn extract_tmt_intensities(
+    spectrum: &PyRawSpectrum,
+    isobaric: &PyIsobaric,
+    tolerance: &PyTolerance,
+) -> Vec<f32> {
+    let mz = &spectrum.inner.mz;
+    let intensity = &spectrum.inner.intensity;
+    isobaric
+        .inner
+        .reporter_masses()
+        .iter()
+        .map(|&reporter| {
+            let (lo, hi) = tolerance.inner.bounds(reporter);
+            let start = mz.partition_point(|m| *m < lo);
+            (start..mz.len())
+                .take_while(|&i| mz[i] <= hi)
+                .map(|i| intensity[i])
+                .fold(0.0, f32::max)
+        })
+        .collect()
+}
+
+/// Iterations and fraction of rows kept (least trimmed squares) of the VSN calibration
+const VSN_ITERATIONS: usize = 20;
+const VSN_TRIM_FRACTION: f64 = 0.75;
+
+/// Variance stabilizing transformation of each channel, h(y) = asinh((y - mu) / sigma)
+#[pyclass]
+#[derive(Clone)]
+pub struct PyVsnParams {
+    #[pyo3(get)]
+    pub mu: Vec<f32>,
+    #[pyo3(get)]
+    pub sigma: Vec<f32>,
+}
+
+#[pymethods]
+impl PyVsnParams {
+    #[new]
+    pub fn new(mu: Vec<f32>, sigma: Vec<f32>) -> PyResult<Self> {
+        if mu.len() != sigma.len() || sigma.iter().any(|s| *s <= 0.0) {
+            return Err(PyValueError::new_err("mu and sigma must have one value per channel, sigma positive"));
+        }
+        Ok(PyVsnParams { mu, sigma })
+    }
+}
+
+fn check_rows(intensities: &[Vec<f32>]) -> PyResult<usize> {
+    let channels = intensities.first().map_or(0, |r| r.len());
+    if intensities.iter().any(|r| r.len() != channels) {
+        return Err(PyValueError::new_err("all rows must have the same number of channels"));
+    }
+    Ok(channels)
+}
+
+/// Fit VSN (Huber et al. 2002) by iterative calibration: the channels are transformed with
+/// asinh(a + b * y), each row's mean transformed value is its consensus, and (a, b) of each
+/// channel are refitted by weighted least squares against the consensus (linearized through sinh)
+/// on the best fitting rows. Only rows without missing (zero) values are used
+pub(crate) fn fit_vsn_params(intensities: &[Vec<f32>]) -> PyResult<PyVsnParams> {
+    let channels = check_rows(intensities)?;
+    let rows: Vec<Vec<f64>> = intensities
+        .iter()
+        .filter(|r| r.iter().all(|v| *v > 0.0))
+        .map(|r| r.iter().map(|v| *v as f64).collect())
+        .collect();
+    if rows.len() < 3 || channels == 0 {
+        return Err(PyValueError::new_err("VSN requires at least 3 rows without missing values"));
+    }
+
+    // start in the log regime above the lowest decile of each channel
+    let mut a = vec![0.0f64; channels];
+    let mut b: Vec<f64> = (0..channels)
+        .map(|c| {
+            let mut values: Vec<f64> = rows.iter().map(|r| r[c]).collect();
+            values.sort_by(|x, y| x.total_cmp(y));
+            1.0 / values[values.len() / 10]
+        })
+        .collect();
+
+    let keep = ((rows.len() as f64 * VSN_TRIM_FRACTION).ceil() as usize).max(3);
+    for _ in 0..VSN_ITERATIONS {
+        let h: Vec<Vec<f64>> = rows
+            .iter()
+            .map(|r| (0..channels).map(|c| (a[c] + b[c] * r[c]).asinh()).collect())
+            .collect();
+        let consensus: Vec<f64> = h.iter().map(|r| r.iter().sum::<f64>() / channels as f64).collect();
+
+        let mut order: Vec<usize> = (0..rows.len()).collect();
+        let residual = |k: usize| h[k].iter().map(|v| (v - consensus[k]).powi(2)).sum::<f64>();
+        order.sort_by(|&i, &j| residual(i).total_cmp(&residual(j)));
+        order.truncate(keep);
+
+        let mut change = 0.0f64;
+        for c in 0..channels {
+            let (mut sw, mut swy, mut swyy, mut swt, mut swyt) = (0.0, 0.0, 0.0, 0.0, 0.0);
+            for &k in &order {
+                let w = 1.0 / consensus[k].cosh().powi(2);
+                let (y, t) = (rows[k][c], consensus[k].sinh());
+                sw += w;
+                swy += w * y;
+                swyy += w * y * y;
+                swt += w * t;
+                swyt += w * y * t;
+            }
+            let Some(fit) = solve_linear(vec![vec![sw, swy], vec![swy, swyy]], vec![swt, swyt]) else {
+                continue;
+            };
+            if fit[1] <= 0.0 {
+                continue;
+            }
+            change = change.max(((fit[1] - b[c]) / b[c]).abs());
+            a[c] = fit[0];
+            b[c] = fit[1];
+        }
+        if change < 1e-4 {
+            break;
+        }
+    }
+
+    Ok(PyVsnParams {
+        mu: a.iter().zip(&b).map(|(a, b)| (-a / b) as f32).collect(),
+        sigma: b.iter().map(|b| (1.0 / b) as f32).collect(),
+    })
+}
+
+/// Transform intensities with fitted VSN parameters, missing (zero) values are kept
+pub(crate) fn apply_vsn_params(intensities: Vec<Vec<f32>>, params: &PyVsnParams) -> PyResult<Vec<Vec<f32>>> {
+    let channels = check_rows(&intensities)?;
+    if !intensities.is_empty() && channels != params.mu.len() {
+        return Err(PyValueError::new_err(format!(
+            "VSN parameters of {} channels cannot be applied to {} channels",
+            params.mu.len(),
+            channels
+        )));
+    }
+    let mut intensities = intensities;
+    for row in intensities.iter_mut() {
+        for (value, (mu, sigma)) in row.iter_mut().zip(params.mu.iter().zip(params.sigma.iter())) {
+            if *value > 0.0 {
+                *value = ((*value - mu) / sigma).asinh();
+            }
+        }
+    }
+    Ok(intensities)
+}
+
+/// Fit VSN parameters to intensities (one row of channels per spectrum or feature), see
+/// `vsn_normalize`
+#[pyfunction]
+pub fn fit_vsn(intensities: Vec<Vec<f32>>) -> PyResult<PyVsnParams> {
+    fit_vsn_params(&intensities)
+}
+
+/// Apply fitted VSN parameters, e.g. to new data of the same channels
+#[pyfunction]
+pub fn apply_vsn(intensities: Vec<Vec<f32>>, params: PyVsnParams) -> PyResult<Vec<Vec<f32>>> {
+    apply_vsn_params(intensities, &params)
+}
+
+/// Variance stabilizing normalization: fit VSN to the intensities and transform them
+#[pyfunction]
+pub fn vsn_normalize(intensities: Vec<Vec<f32>>) -> PyResult<Vec<Vec<f32>>> {
+    let params = fit_vsn_params(&intensities)?;
+    apply_vsn_params(intensities, &params)
+}
+
+/// Normalise reporter intensities (one row of channels per spectrum). "sum" scales each row to
+/// sum to 1, "median" scales each channel to the mean of the channel medians (of non-zero values),
+/// "sample_loading" scales each channel total to the mean channel total and "vsn" applies the
+/// variance stabilizing transformation (see `vsn_normalize`)
+#[pyfunction]
+pub fn normalize_tmt_intensities(intensities: Vec<Vec<f32>>, method: &str) -> PyResult<Vec<Vec<f32>>> {
+    let mut intensities = intensities;
+    let channels = check_rows(&intensities)?;
+
+    let scale_channels = |intensities: &mut Vec<Vec<f32>>, channel_values: Vec<f32>| {
+        let valid: Vec<f32> = channel_values.iter().copied().filter(|v| *v > 0.0).collect();
+        let target = valid.iter().sum::<f32>() / valid.len().max(1) as f32;
+        for row in intensities.iter_mut() {
+            for (value, reference) in row.iter_mut().zip(channel_values.iter()) {
+                if *reference > 0.0 {
+                    *value *= target / reference;
+                }
+            }
+        }
+    };
+
+    match method.to_lowercase().as_str() {
+        "sum" => {
+            for row in intensities.iter_mut() {
+                let total: f32 = row.iter().sum();
+                if total > 0.0 {
+                    row.iter_mut().for_each(|v| *v /= total);
+                }
+            }
+        }
+        "median" => {
+            let medians = (0..channels)
+                .map(|c| median(intensities.iter().map(|r| r[c]).filter(|v| *v > 0.0)).unwrap_or(0.0) as f32)
+                .collect();
+            scale_channels(&mut intensities, medians);
+        }
+        "sample_loading" => {
+            let totals = (0..channels)
+                .map(|c| intensities.iter().map(|r| r[c]).sum())
+                .collect();
+            scale_channels(&mut intensities, totals);
+        }
+        "vsn" => return vsn_normalize(intensities),
+        _ => {
+            return Err(PyValueError::new_err(format!(
+                "Invalid normalization method: {}, allowed values are: sum, median, sample_loading, vsn",
+                method
+            )))
+        }
+    }
+    Ok(intensities)
+}
+
+/// Correct reporter intensities for isotopic impurities. `impurity_matrix[i][j]` is the fraction
+/// of channel j's signal observed in channel i (from the kit's certificate of analysis), the true
+/// intensities solve impurity_matrix * x = observed and are clamped to be non-negative
+#[pyfunction]
+pub fn correct_isotope_impurities(intensities: Vec<f32>, impurity_matrix: Vec<Vec<f32>>) -> PyResult<Vec<f32>> {
+    let n = intensities.len();
+    if impurity_matrix.len() != n || impurity_matrix.iter().any(|r| r.len() != n) {
+        return Err(PyValueError::new_err(format!(
+            "impurity_matrix must be {} x {} to match the number of channels",
+            n, n
+        )));
+    }
+
+    let a = impurity_matrix
+        .iter()
+        .map(|r| r.iter().map(|v| *v as f64).collect())
+        .collect();
+    let b = intensities.iter().map(|v| *v as f64).collect();
+    let corrected = solve_linear(a, b)
+        .ok_or_else(|| PyValueError::new_err("impurity_matrix is singular"))?;
+    Ok(corrected.into_iter().map(|v| v.max(0.0) as f32).collect())
+}
+
+/// Representative isotope impurities (% of the reagent at -2, -1, +1, +2 Da) of a TMT6plex lot,
+/// replace them with the certificate of analysis of the lot used
+const TMT6_ISOTOPE_IMPURITIES: [[f32; 4]; 6] = [
+    [0.0, 0.0, 6.1, 0.0],
+    [0.0, 0.5, 6.7, 0.0],
+    [0.0, 1.1, 4.2, 0.0],
+    [0.0, 1.7, 4.1, 0.0],
+    [0.0, 1.6, 2.1, 0.0],
+    [0.2, 3.2, 2.8, 0.0],
+];
+
+/// Representative isotope impurities (% of the reagent at -2, -1, +1, +2 Da) of a TMTpro 18plex
+/// lot, replace them with the certificate of analysis of the lot used
+const TMTPRO_ISOTOPE_IMPURITIES: [[f32; 4]; 18] = [
+    [0.0, 0.0, 8.3, 0.4],
+    [0.0, 0.8, 7.7, 0.3],
+    [0.0, 0.8, 7.1, 0.2],
+    [0.0, 0.8, 6.5, 0.2],
+    [0.0, 1.0, 6.4, 0.1],
+    [0.0, 1.2, 5.8, 0.1],
+    [0.0, 1.5, 5.6, 0.0],
+    [0.0, 1.6, 5.0, 0.0],
+    [0.0, 1.8, 4.6, 0.0],
+    [0.1, 2.2, 4.3, 0.0],
+    [0.1, 2.3, 4.0, 0.0],
+    [0.1, 2.5, 3.6, 0.0],
+    [0.1, 2.8, 3.3, 0.0],
+    [0.1, 3.0, 3.0, 0.0],
+    [0.2, 3.3, 2.6, 0.0],
+    [0.2, 3.6, 2.2, 0.0],
+    [0.2, 3.9, 1.9, 0.0],
+    [0.3, 4.3, 1.6, 0.0],
+];
+
+/// Impurity matrix from per-reagent isotope impurities (%), `step` is the distance in channels of a
+/// 1 Da shift (1 for TMT6plex, 2 for the N/C interleaved TMTpro channels)
+fn matrix_from_isotope_impurities(impurities: &[[f32; 4]], step: usize) -> Vec<Vec<f32>> {
+    let n = impurities.len();
+    let mut matrix = vec![vec![0.0; n]; n];
+    for (j, fractions) in impurities.iter().enumerate() {
+        matrix[j][j] = 1.0 - fractions.iter().sum::<f32>() / 100.0;
+        for (fraction, shift) in fractions.iter().zip([-2i64, -1, 1, 2]) {
+            let i = j as i64 + shift * step as i64;
+            if (0..n as i64).contains(&i) {
+                matrix[i as usize][j] = fraction / 100.0;
+            }
+        }
+    }
+    matrix
+}
+
+/// Isotope impurity correction matrix of a TMT lot, `matrix[i][j]` is the fraction of channel j's
+/// signal observed in channel i
+#[pyclass]
+#[derive(Clone)]
+pub struct PyImpurityMatrix {
+    #[pyo3(get)]
+    pub channels: Vec<String>,
+    #[pyo3(get)]
+    pub matrix: Vec<Vec<f32>>,
+}
+
+#[pymethods]
+impl PyImpurityMatrix {
+    #[new]
+    pub fn new(channels: Vec<String>, matrix: Vec<Vec<f32>>) -> PyResult<Self> {
+        if matrix.len() != channels.len() {
+            return Err(PyValueError::new_err(format!(
+                "impurity matrix has {} rows for {} channels",
+                matrix.len(),
+                channels.len()
+            )));
+        }
+        Ok(PyImpurityMatrix { channels, matrix })
+    }
+
+    /// Example matrix of a TMT6plex lot
+    #[staticmethod]
+    pub fn tmt6() -> Self {
+        PyImpurityMatrix {
+            channels: TMT6_CHANNELS.iter().map(|c| c.to_string()).collect(),
+            matrix: matrix_from_isotope_impurities(&TMT6_ISOTOPE_IMPURITIES, 1),
+        }
+    }
+
+    /// Example matrix of a TMTpro 18plex lot
+    #[staticmethod]
+    pub fn tmtpro() -> Self {
+        PyImpurityMatrix {
+            channels: TMTPRO_CHANNELS.iter().map(|c| c.to_string()).collect(),
+            matrix: matrix_from_isotope_impurities(&TMTPRO_ISOTOPE_IMPURITIES, 2),
+        }
+    }
+}
+
+/// Check that an impurity matrix is square, column-wise diagonally dominant (each reagent mostly
+/// reports in its own channel) and invertible
+#[pyfunction]
+pub fn validate_impurity_matrix(matrix: PyImpurityMatrix) -> bool {
+    let m = &matrix.matrix;
+    let n = m.len();
+    if n == 0 || m.iter().any(|r| r.len() != n) {
+        return false;
+    }
+
+    let dominant = (0..n).all(|j| {
+        let off_diagonal: f32 = (0..n).filter(|&i| i != j).map(|i| m[i][j].abs()).sum();
+        m[j][j].abs() > off_diagonal
+    });
+
+    let a = m
+        .iter()
+        .map(|r| r.iter().map(|v| *v as f64).collect())
+        .collect();
+    dominant && solve_linear(a, vec![1.0; n]).is_some()
+}
+
+/// Correct reporter intensities with an impurity matrix, solving matrix * x = observed by LU
+/// decomposition with partial pivoting (see `correct_isotope_impurities`)
+#[pyfunction]
+pub fn apply_impurity_correction(intensities: Vec<f32>, matrix: PyImpurityMatrix) -> PyResult<Vec<f32>> {
+    correct_isotope_impurities(intensities, matrix.matrix)
+}
+
+/// A residue modification at least this close to the label mass (Da) carries the isobaric label,
+/// e.g. an N-terminal label combined with a side chain modification
+const LABEL_MASS_TOLERANCE: f32 = 0.01;
+
+/// Reporter ion tolerance of SPS-MS3 quantification, unless given
+const SPS_REPORTER_TOLERANCE_PPM: f32 = 20.0;
+
+/// MS3 quantification of an identified MS2 spectrum
+#[pyclass]
+#[derive(Clone)]
+pub struct PySpsQuantResult {
+    pub psm: PyFeature,
+    pub ms3_spec_id: String,
+    pub intensities: Vec<f32>,
+    pub sps_match_fraction: f32,
+}
+
+#[pymethods]
+impl PySpsQuantResult {
+    #[getter]
+    pub fn psm(&self) -> PyFeature {
+        self.psm.clone()
+    }
+
+    #[getter]
+    pub fn ms3_spec_id(&self) -> String {
+        self.ms3_spec_id.clone()
+    }
+
+    /// Reporter intensities in channel order, weighted by `sps_match_fraction`
+    #[getter]
+    pub fn intensities(&self) -> Vec<f32> {
+        self.intensities.clone()
+    }
+
+    /// Fraction of the SPS precursors of the MS3 scan matching a labelled fragment of the peptide
+    #[getter]
+    pub fn sps_match_fraction(&self) -> f32 {
+        self.sps_match_fraction
+    }
+}
+
+/// m/z of the b and y ions of a peptide carrying the isobaric label, for charges up to `max_charge`
+fn labelled_fragment_mzs(peptide: &Peptide, label_mass: Option<f32>, max_charge: u8) -> Vec<f32> {
+    let labelled: Vec<bool> = peptide
+        .modifications
+        .iter()
+        .map(|m| label_mass.map_or(true, |label| *m >= label - LABEL_MASS_TOLERANCE))
+        .collect();
+
+    let mut mzs = Vec::new();
+    for kind in [Kind::B, Kind::Y] {
+        for (i, ion) in IonSeries::new(peptide, kind).enumerate() {
+            let residues = match kind {
+                Kind::B => &labelled[..=i],
+                _ => &labelled[i + 1..],
+            };
+            if !residues.iter().any(|l| *l) {
+                continue;
+            }
+            for z in 1..=max_charge.max(1) {
+                mzs.push((ion.monoisotopic_mass + z as f32 * PROTON) / z as f32);
+            }
+        }
+    }
+    mzs
+}
+
+/// Reporter intensities of an SPS-MS3 spectrum weighted by the fraction of its SPS precursors that
+/// match a labelled fragment of the MS2 identification, and that fraction
+fn sps_ms3_quant(
+    ms3: &ProcessedSpectrum,
+    psm: &PyFeature,
+    db: &IndexedDatabase,
+    isobaric: &Isobaric,
+    isolation_window: f32,
+    tolerance: Tolerance,
+) -> (Vec<f32>, f32) {
+    let fragments = labelled_fragment_mzs(
+        &db[psm.inner.peptide_idx],
+        isobaric.modification_mass(),
+        psm.inner.charge,
+    );
+    let half_window = isolation_window / 2.0;
+    let ms2_precursor_mz = (psm.inner.expmass + psm.inner.charge.max(1) as f32 * PROTON) / psm.inner.charge.max(1) as f32;
+
+    // the MS2 precursor may be listed along with the SPS ions
+    let sps: Vec<f32> = ms3
+        .precursors
+        .iter()
+        .map(|p| p.mz)
+        .filter(|mz| (mz - ms2_precursor_mz).abs() > half_window)
+        .collect();
+    let matched = sps
+        .iter()
+        .filter(|mz| fragments.iter().any(|f| (*f - **mz).abs() <= half_window))
+        .count();
+    let fraction = matched as f32 / sps.len().max(1) as f32;
+
+    let intensities = isobaric
+        .reporter_masses()
+        .iter()
+        .map(|&reporter| {
+            // peaks hold singly charged neutral masses
+            let (lo, hi) = tolerance.bounds(reporter);
+            let start = ms3.peaks.partition_point(|p| p.mass + PROTON < lo);
+            let intensity = ms3.peaks[start..]
+                .iter()
+                .take_while(|p| p.mass + PROTON <= hi)
+                .map(|p| p.intensity)
+                .fold(0.0, f32::max);
+            intensity * fraction
+        })
+        .collect();
+    (intensities, fraction)
+}
+
+/// Reporter intensities of an SPS-MS3 spectrum for the PSM of its MS2 scan. The SPS precursors are
+/// checked against the labelled b and y ions of the peptide within half the `isolation_window`
+/// (Th), the intensities are weighted by the fraction matched, so they are 0 if none matches. The
+/// spectrum needs to keep its reporter region (e.g. read with `read_mzml`)
+#[pyfunction]
+pub fn extract_sps_ms3_intensities(
+    ms3_spectrum: &PyProcessedSpectrum,
+    ms2_psm: &PyFeature,
+    db: &PyIndexedDatabase,
+    isobaric: &PyIsobaric,
+    isolation_window: f32,
+    reporter_tolerance: Option<PyTolerance>,
+) -> PyResult<Vec<f32>> {
+    check_peptide_indices(&db.inner, [ms2_psm])?;
+    if ms3_spectrum.inner.precursors.is_empty() {
+        return Err(PyValueError::new_err(format!(
+            "MS3 spectrum {} has no SPS precursors",
+            ms3_spectrum.inner.id
+        )));
+    }
+    let tolerance = reporter_tolerance
+        .map(|t| t.inner)
+        .unwrap_or(Tolerance::Ppm(-SPS_REPORTER_TOLERANCE_PPM, SPS_REPORTER_TOLERANCE_PPM));
+    let (intensities, _) = sps_ms3_quant(
+        &ms3_spectrum.inner,
+        ms2_psm,
+        &db.inner,
+        &isobaric.inner,
+        isolation_window,
+        tolerance,
+    );
+    Ok(intensities)
+}
+
+/// Score the MS2 spectra and quantify the best PSM of each with the MS3 scan referring to the MS2
+/// scan through the spectrum reference of its precursors. MS2 spectra without identification or
+/// MS3 scan are skipped
+#[pyfunction]
+pub fn score_collection_with_sps(
+    ms2_spectra: Vec<PyProcessedSpectrum>,
+    ms3_spectra: Vec<PyProcessedSpectrum>,
+    db: &PyIndexedDatabase,
+    scorer: &PyScorer,
+    isobaric: &PyIsobaric,
+    isolation_window: f32,
+    num_threads: usize,
+    reporter_tolerance: Option<PyTolerance>,
+) -> PyResult<Vec<PySpsQuantResult>> {
+    let tolerance = reporter_tolerance
+        .map(|t| t.inner)
+        .unwrap_or(Tolerance::Ppm(-SPS_REPORTER_TOLERANCE_PPM, SPS_REPORTER_TOLERANCE_PPM));
+
+    let mut ms3_by_ms2: HashMap<&str, &ProcessedSpectrum> = HashMap::new();
+    for ms3 in ms3_spectra.iter() {
+        if let Some(reference) = ms3.inner.precursors.iter().find_map(|p| p.spectrum_ref.as_deref()) {
+            ms3_by_ms2.entry(reference).or_insert(&ms3.inner);
+        }
+    }
+
+    let psms = scorer.score_collection(db, ms2_spectra.clone(), num_threads)?;
+    let mut results = Vec::new();
+    for (spectrum, psms) in ms2_spectra.iter().zip(psms.iter()) {
+        let (Some(psm), Some(ms3)) = (
+            psms.iter().find(|p| p.inner.rank == 1),
+            ms3_by_ms2.get(spectrum.inner.id.as_str()),
+        ) else {
+            continue;
+        };
+        let (intensities, sps_match_fraction) =
+            sps_ms3_quant(ms3, psm, &db.inner, &isobaric.inner, isolation_window, tolerance);
+        results.push(PySpsQuantResult {
+            psm: psm.clone(),
+            ms3_spec_id: ms3.id.clone(),
+            intensities,
+            sps_match_fraction,
+        });
+    }
+    Ok(results)
+}
+
 #[pymodule]
 pub fn tmt(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_class::<PyIsobaric>()?;
     m.add_class::<PyPurity>()?;
     m.add_class::<PyQuant>()?;
     m.add_class::<PyTmtQuant>()?;
+    m.add_function(wrap_pyfunction!(extract_tmt_intensities, m)?)?;
+    m.add_function(wrap_pyfunction!(normalize_tmt_intensities, m)?)?;
+    m.add_function(wrap_pyfunction!(correct_isotope_impurities, m)?)?;
+    m.add_class::<PyVsnParams>()?;
+    m.add_function(wrap_pyfunction!(fit_vsn, m)?)?;
+    m.add_function(wrap_pyfunction!(apply_vsn, m)?)?;
+    m.add_function(wrap_pyfunction!(vsn_normalize, m)?)?;
+    m.add_class::<PyImpurityMatrix>()?;
+    m.add_function(wrap_pyfunction!(validate_impurity_matrix, m)?)?;
+    m.add_function(wrap_pyfunction!(apply_impurity_correction, m)?)?;
+    m.add_class::<PySpsQuantResult>()?;
+    m.add_function(wrap_pyfunction!(extract_sps_ms3_intensities, m)?)?;
+    m.add_function(wrap_pyfunction!(score_collection_with_sps, m)?)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sage_core::spectrum::{RawSpectrum, Representation};
+
+    fn raw_spectrum(mz: Vec<f32>, intensity: Vec<f32>) -> PyRawSpectrum {
+        PyRawSpectrum {
+            inner: RawSpectrum {
+                file_id: 0,
+                ms_level: 2,
+                id: "scan=1".to_string(),
+                precursors: Vec::new(),
+                representation: Representation::Centroid,
+                scan_start_time: 0.0,
+                ion_injection_time: 0.0,
+                total_ion_current: intensity.iter().sum(),
+                mz,
+                intensity,
+            },
+        }
+    }
+
+    #[test]
+    fn extracts_all_tmtpro18_channels() {
+        let isobaric = PyIsobaric { inner: Isobaric::Tmt18 };
+        let reporters = isobaric.reporter_masses();
+        assert_eq!(reporters.len(), 18);
+        assert_eq!(isobaric.channel_names().len(), 18);
+
+        // reporters shifted by 2 ppm, with an interfering peak between each pair of channels
+        let mut peaks: Vec<(f32, f32)> = reporters
+            .iter()
+            .enumerate()
+            .map(|(i, mz)| (mz * (1.0 + 2e-6), 100.0 * (i + 1) as f32))
+            .collect();
+        peaks.extend(reporters.windows(2).map(|w| ((w[0] + w[1]) / 2.0, 1e6)));
+        peaks.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let spectrum = raw_spectrum(peaks.iter().map(|p| p.0).collect(), peaks.iter().map(|p| p.1).collect());
+
+        let tolerance = PyTolerance { inner: Tolerance::Ppm(-10.0, 10.0) };
+        let intensities = extract_tmt_intensities(&spectrum, &isobaric, &tolerance);
+        let expected: Vec<f32> = (1..=18).map(|i| 100.0 * i as f32).collect();
+        assert_eq!(intensities, expected);
+    }
+
+    #[test]
+    fn missing_channels_are_zero() {
+        let isobaric = PyIsobaric { inner: Isobaric::Tmt18 };
+        let first = isobaric.reporter_masses()[0];
+        let spectrum = raw_spectrum(vec![first], vec![50.0]);
+        let tolerance = PyTolerance { inner: Tolerance::Ppm(-10.0, 10.0) };
+        let intensities = extract_tmt_intensities(&spectrum, &isobaric, &tolerance);
+        assert_eq!(intensities[0], 50.0);
+        assert!(intensities[1..].iter().all(|v| *v == 0.0));
+    }
+
+    #[test]
+    fn normalization_methods() {
+        let intensities = vec![vec![1.0, 2.0, 4.0], vec![3.0, 6.0, 12.0]];
+
+        let sum = normalize_tmt_intensities(intensities.clone(), "sum").unwrap();
+        for row in &sum {
+            assert!((row.iter().sum::<f32>() - 1.0).abs() < 1e-6);
+        }
+
+        // channels scaled 1:2:4 are equalised by both channel-wise methods
+        for method in ["median", "sample_loading"] {
+            let normalized = normalize_tmt_intensities(intensities.clone(), method).unwrap();
+            for row in &normalized {
+                assert!((row[0] - row[1]).abs() < 1e-5 && (row[1] - row[2]).abs() < 1e-5);
+            }
+        }
+        assert!(normalize_tmt_intensities(intensities, "total").is_err());
+    }
+
+    #[test]
+    fn impurity_correction_recovers_true_intensities() {
+        let truth = [100.0f32, 50.0, 25.0];
+        let matrix = vec![vec![0.9, 0.05, 0.0], vec![0.1, 0.9, 0.05], vec![0.0, 0.05, 0.95]];
+        let observed: Vec<f32> = matrix
+            .iter()
+            .map(|row: &Vec<f32>| row.iter().zip(truth.iter()).map(|(a, t)| a * t).sum())
+            .collect();
+
+        let corrected = correct_isotope_impurities(observed, matrix).unwrap();
+        for (c, t) in corrected.iter().zip(truth.iter()) {
+            assert!((c - t).abs() < 1e-3);
+        }
+        assert!(correct_isotope_impurities(vec![1.0, 2.0], vec![vec![1.0]]).is_err());
+    }
+
+    #[test]
+    fn example_impurity_matrices_undo_cross_talk_into_heavier_channels() {
+        for matrix in [PyImpurityMatrix::tmt6(), PyImpurityMatrix::tmtpro()] {
+            assert!(validate_impurity_matrix(matrix.clone()));
+            assert_eq!(matrix.channels.len(), matrix.matrix.len());
+            // each reagent reports mostly in its own channel, impurities beyond the outer channels
+            // are lost
+            let n = matrix.matrix.len();
+            for j in 0..n {
+                let total: f32 = (0..n).map(|i| matrix.matrix[i][j]).sum();
+                assert!(total <= 1.0 + 1e-5 && matrix.matrix[j][j] > 0.9);
+            }
+        }
+
+        // the +1 Da impurity of 126 lands in 127C, two channels up in the interleaved TMTpro order
+        let tmtpro = PyImpurityMatrix::tmtpro();
+        let observed: Vec<f32> = tmtpro.matrix.iter().map(|row| 1000.0 * row[0]).collect();
+        assert!((observed[2] - 83.0).abs() < 1e-3 && observed[1] == 0.0);
+        let corrected = apply_impurity_correction(observed, tmtpro).unwrap();
+        assert!((corrected[0] - 1000.0).abs() < 1e-2);
+        assert!(corrected[1..].iter().all(|c| c.abs() < 1e-2));
+
+        let channels = vec!["a".to_string(), "b".to_string()];
+        let not_dominant = PyImpurityMatrix::new(channels.clone(), vec![vec![0.4, 0.5], vec![0.6, 0.5]]).unwrap();
+        assert!(!validate_impurity_matrix(not_dominant));
+        let not_square = PyImpurityMatrix::new(channels.clone(), vec![vec![1.0], vec![0.0, 1.0]]).unwrap();
+        assert!(!validate_impurity_matrix(not_square));
+        assert!(PyImpurityMatrix::new(channels, vec![vec![1.0]]).is_err());
+    }
+
+    #[test]
+    fn vsn_removes_channel_scaling_on_a_log_like_scale() {
+        // channel 1 is loaded twice and channel 2 half as much as channel 0
+        let mut intensities: Vec<Vec<f32>> = (0..50)
+            .map(|i| {
+                let level = 10f32.powf(2.0 + 4.0 * i as f32 / 50.0);
+                vec![level, 2.0 * level, 0.5 * level]
+            })
+            .collect();
+        intensities.push(vec![1000.0, 0.0, 500.0]);
+
+        let params = fit_vsn(intensities.clone()).unwrap();
+        assert!((params.sigma[1] / params.sigma[0] - 2.0).abs() < 1e-3);
+        assert!((params.sigma[2] / params.sigma[0] - 0.5).abs() < 1e-3);
+
+        let normalized = vsn_normalize(intensities.clone()).unwrap();
+        for row in &normalized[..50] {
+            assert!((row[1] - row[0]).abs() < 1e-3 && (row[2] - row[0]).abs() < 1e-3);
+        }
+        // missing values stay missing
+        assert_eq!(normalized[50][1], 0.0);
+        // intense rows are as far apart as their log intensities
+        let step = normalized[49][0] - normalized[49 - 12][0];
+        let expected = (intensities[49][0] / intensities[49 - 12][0]).ln();
+        assert!((step - expected).abs() < 0.01, "{} {}", step, expected);
+
+        assert_eq!(apply_vsn(intensities[..2].to_vec(), params.clone()).unwrap(), normalized[..2].to_vec());
+        assert!(apply_vsn(vec![vec![1.0, 2.0]], params).is_err());
+        assert!(fit_vsn(intensities[..2].to_vec()).is_err());
+    }
+}
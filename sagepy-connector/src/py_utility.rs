@@ -0,0 +1,500 @@
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use rayon::prelude::*;
+use regex::Regex;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::py_database::PyIndexedDatabase;
+use crate::py_enzyme::{terminal_specificity, PyEnzyme};
+use crate::py_modification::{unimod_id_by_name, PyTerminalModification, Terminus};
+use crate::py_scoring::{check_peptide_indices, feature_values, PyFeature, FEATURE_NAMES};
+use sage_core::peptide::Peptide;
+
+/// Unimod accessions of the modifications known to sagepy by their nominal mass, mirrors
+/// `sagepy.utility.mass_to_mod`
+const UNIMOD_BY_NOMINAL_MASS: [(i32, u32); 5] = [(42, 1), (57, 4), (80, 21), (16, 35), (119, 312)];
+
+/// Sequence of a peptide with UNIMOD bracket annotations, e.g. `PEPC[UNIMOD:4]TIDE`. Acetylation
+/// of the first residue and N-terminal modifications precede it, C-terminal modifications follow
+/// as `-[UNIMOD:2]`, masses without known accession are written as `[+mass]`
+pub(crate) fn unimod_sequence(peptide: &Peptide) -> String {
+    let mut sequence = String::with_capacity(peptide.sequence.len() * 2);
+    if let Some(mass) = peptide.nterm.filter(|m| *m != 0.0) {
+        sequence.push_str(&PyTerminalModification::from_mass(Terminus::N, mass).unimod_annotation());
+    }
+    for (i, (residue, mass)) in peptide
+        .sequence
+        .iter()
+        .zip(peptide.modifications.iter())
+        .enumerate()
+    {
+        if *mass == 0.0 {
+            sequence.push(*residue as char);
+            continue;
+        }
+        let annotation = match residue_unimod_id(*mass) {
+            Some(id) => format!("[UNIMOD:{}]", id),
+            None => format!("[{:+.4}]", mass),
+        };
+        if i == 0 && annotation == "[UNIMOD:1]" {
+            sequence.push_str(&annotation);
+            sequence.push(*residue as char);
+        } else {
+            sequence.push(*residue as char);
+            sequence.push_str(&annotation);
+        }
+    }
+    if let Some(mass) = peptide.cterm.filter(|m| *m != 0.0) {
+        sequence.push('-');
+        sequence.push_str(&PyTerminalModification::from_mass(Terminus::C, mass).unimod_annotation());
+    }
+    sequence
+}
+
+fn residue_unimod_id(mass: f32) -> Option<u32> {
+    UNIMOD_BY_NOMINAL_MASS
+        .iter()
+        .find(|(nominal, _)| *nominal == mass.round() as i32)
+        .map(|(_, id)| *id)
+}
+
+/// ProForma 2.0 sequence of a peptide, e.g. `[UNIMOD:1]-PEPTM[UNIMOD:35]IDE`. With
+/// `use_mass_notation` or without known Unimod accession, modifications are written as mass
+/// shifts, e.g. `PEPTM[+15.9949]IDE`. Like `unimod_sequence`, acetylation of the first residue is
+/// written as N-terminal modification
+pub(crate) fn proforma_sequence(peptide: &Peptide, use_mass_notation: bool) -> String {
+    let annotation = |mass: f32, unimod_id: Option<u32>| match unimod_id {
+        Some(id) if !use_mass_notation => format!("[UNIMOD:{}]", id),
+        _ => format!("[{:+.4}]", mass),
+    };
+
+    let mut nterm: Vec<String> = Vec::new();
+    if let Some(mass) = peptide.nterm.filter(|m| *m != 0.0) {
+        nterm.push(annotation(mass, PyTerminalModification::from_mass(Terminus::N, mass).unimod_id));
+    }
+    let mut body = String::with_capacity(peptide.sequence.len() * 2);
+    for (i, (residue, mass)) in peptide
+        .sequence
+        .iter()
+        .zip(peptide.modifications.iter())
+        .enumerate()
+    {
+        if *mass == 0.0 {
+            body.push(*residue as char);
+        } else if i == 0 && residue_unimod_id(*mass) == Some(1) {
+            nterm.push(annotation(*mass, Some(1)));
+            body.push(*residue as char);
+        } else {
+            body.push(*residue as char);
+            body.push_str(&annotation(*mass, residue_unimod_id(*mass)));
+        }
+    }
+
+    let mut sequence = nterm.concat();
+    if !sequence.is_empty() {
+        sequence.push('-');
+    }
+    sequence.push_str(&body);
+    if let Some(mass) = peptide.cterm.filter(|m| *m != 0.0) {
+        sequence.push('-');
+        sequence.push_str(&annotation(mass, PyTerminalModification::from_mass(Terminus::C, mass).unimod_id));
+    }
+    sequence
+}
+
+/// UNIMOD bracket annotation of a ProForma modification: `UNIMOD:35` / `U:35`, a mass shift
+/// (mapped to a known accession by nominal mass, kept as `[+mass]` otherwise) or a modification
+/// name. `terminus` selects the terminal modification accessions for mass shifts
+fn proforma_to_unimod_annotation(modification: &str, terminus: Option<Terminus>) -> PyResult<String> {
+    let modification = modification.trim();
+    let accession = modification
+        .split_once(':')
+        .filter(|(prefix, _)| prefix.eq_ignore_ascii_case("unimod") || prefix.eq_ignore_ascii_case("u"))
+        .map(|(_, id)| id);
+    if let Some(id) = accession {
+        let id: u32 = id
+            .parse()
+            .map_err(|_| PyValueError::new_err(format!("Invalid Unimod accession: {}", modification)))?;
+        return Ok(format!("[UNIMOD:{}]", id));
+    }
+    if let Ok(mass) = modification.parse::<f32>() {
+        return Ok(match terminus {
+            Some(terminus) => PyTerminalModification::from_mass(terminus, mass).unimod_annotation(),
+            None => match residue_unimod_id(mass) {
+                Some(id) => format!("[UNIMOD:{}]", id),
+                None => format!("[{:+.4}]", mass),
+            },
+        });
+    }
+    unimod_id_by_name(modification)
+        .map(|id| format!("[UNIMOD:{}]", id))
+        .ok_or_else(|| PyValueError::new_err(format!("Unknown ProForma modification: {}", modification)))
+}
+
+/// ProForma 2.0 sequence of the peptide of a PSM, see `proforma_to_unimod_sequence` for the
+/// reverse
+#[pyfunction]
+pub fn sequence_to_proforma(psm: &PyFeature, db: &PyIndexedDatabase, use_mass_notation: Option<bool>) -> PyResult<String> {
+    check_peptide_indices(&db.inner, [psm])?;
+    Ok(proforma_sequence(&db.inner[psm.inner.peptide_idx], use_mass_notation.unwrap_or(false)))
+}
+
+/// Convert a ProForma 2.0 sequence, e.g. `[Acetyl]-PEPTM[+15.9949]IDE`, to the UNIMOD-annotated
+/// notation of `unimod_sequence`, e.g. `[UNIMOD:1]PEPTM[UNIMOD:35]IDE`
+#[pyfunction]
+pub fn proforma_to_unimod_sequence(proforma: &str) -> PyResult<String> {
+    let mut rest = proforma.trim();
+    let mut sequence = String::with_capacity(rest.len());
+
+    if rest.starts_with('[') {
+        if let Some(end) = rest.find("]-") {
+            for modification in rest[1..end].split("][") {
+                sequence.push_str(&proforma_to_unimod_annotation(modification, Some(Terminus::N))?);
+            }
+            rest = &rest[end + 2..];
+        }
+    }
+
+    let mut cterm = String::new();
+    if let Some(start) = rest.rfind("-[").filter(|_| rest.ends_with(']')) {
+        for modification in rest[start + 2..rest.len() - 1].split("][") {
+            cterm.push_str(&proforma_to_unimod_annotation(modification, Some(Terminus::C))?);
+        }
+        rest = &rest[..start];
+    }
+
+    let re = Regex::new(r"\[([^\]]+)\]").unwrap();
+    let mut last = 0;
+    for capture in re.captures_iter(rest) {
+        let annotation = capture.get(0).unwrap();
+        sequence.push_str(&rest[last..annotation.start()]);
+        sequence.push_str(&proforma_to_unimod_annotation(&capture[1], None)?);
+        last = annotation.end();
+    }
+    sequence.push_str(&rest[last..]);
+
+    if !cterm.is_empty() {
+        sequence.push('-');
+        sequence.push_str(&cterm);
+    }
+    Ok(sequence)
+}
+
+/// (0-based residue position, Unimod accession) of the annotations of a UNIMOD-annotated
+/// sequence, annotations preceding the first residue (N-terminal) are assigned to position 0
+pub(crate) fn unimod_annotations(sequence: &str) -> Vec<(usize, u32)> {
+    let re = Regex::new(r"\[UNIMOD:(\d+)\]").unwrap();
+    let mut annotations = Vec::new();
+    let mut residues = 0;
+    let mut last = 0;
+    for capture in re.captures_iter(sequence) {
+        let annotation = capture.get(0).unwrap();
+        residues += sequence[last..annotation.start()]
+            .chars()
+            .filter(|c| c.is_ascii_uppercase())
+            .count();
+        last = annotation.end();
+        if let Ok(id) = capture[1].parse() {
+            annotations.push((residues.saturating_sub(1), id));
+        }
+    }
+    annotations
+}
+
+fn modification_positions(peptide: &Peptide, unimod_id: u32) -> Vec<usize> {
+    unimod_annotations(&unimod_sequence(peptide))
+        .into_iter()
+        .filter(|(_, id)| *id == unimod_id)
+        .map(|(position, _)| position)
+        .collect()
+}
+
+/// PSMs whose peptide carries the modification with the given Unimod accession, e.g. 21 for
+/// phosphorylation
+#[pyfunction]
+pub fn filter_by_modification(psms: Vec<PyFeature>, db: &PyIndexedDatabase, unimod_id: u32) -> PyResult<Vec<PyFeature>> {
+    check_peptide_indices(&db.inner, &psms)?;
+    Ok(psms
+        .into_iter()
+        .filter(|psm| !modification_positions(&db.inner[psm.inner.peptide_idx], unimod_id).is_empty())
+        .collect())
+}
+
+/// 0-based residue positions of a modification in the peptide of a PSM
+#[pyfunction]
+pub fn get_modification_positions(psm: &PyFeature, db: &PyIndexedDatabase, unimod_id: u32) -> PyResult<Vec<usize>> {
+    check_peptide_indices(&db.inner, [psm])?;
+    Ok(modification_positions(&db.inner[psm.inner.peptide_idx], unimod_id))
+}
+
+/// Number of occurrences of each Unimod accession in the peptides of the PSMs
+#[pyfunction]
+pub fn count_modifications(psms: Vec<PyFeature>, db: &PyIndexedDatabase) -> PyResult<HashMap<u32, usize>> {
+    check_peptide_indices(&db.inner, &psms)?;
+    let mut counts = HashMap::new();
+    for psm in psms.iter() {
+        let sequence = unimod_sequence(&db.inner[psm.inner.peptide_idx]);
+        for (_, id) in unimod_annotations(&sequence) {
+            *counts.entry(id).or_insert(0) += 1;
+        }
+    }
+    Ok(counts)
+}
+
+/// Score fields for which lower values are better
+const ASCENDING_SCORE_FIELDS: [&str; 4] = ["posterior_error", "spectrum_q", "peptide_q", "protein_q"];
+
+/// Value of a score field of a PSM, "discriminant_score" or one of `FEATURE_NAMES`
+fn score_field_value(name: &str) -> PyResult<impl Fn(&PyFeature) -> f64 + Sync> {
+    let index = match name {
+        "discriminant_score" => None,
+        _ => Some(FEATURE_NAMES.iter().position(|n| *n == name).ok_or_else(|| {
+            PyValueError::new_err(format!("Unknown score field {}, see get_feature_names", name))
+        })?),
+    };
+    Ok(move |psm: &PyFeature| match index {
+        Some(i) => feature_values(psm)[i],
+        None => psm.inner.discriminant_score as f64,
+    })
+}
+
+/// Keep the `keep_top_n` best PSMs of each (modified sequence, charge) by `score_field`, higher is
+/// better except for q-values and posterior error. The result is ordered by modified sequence and
+/// charge
+#[pyfunction]
+pub fn dedup_psms_per_peptide(
+    psms: Vec<PyFeature>,
+    db: &PyIndexedDatabase,
+    score_field: &str,
+    keep_top_n: usize,
+) -> PyResult<Vec<PyFeature>> {
+    check_peptide_indices(&db.inner, &psms)?;
+    let value = score_field_value(score_field)?;
+    let ascending = ASCENDING_SCORE_FIELDS.contains(&score_field);
+
+    let keyed: Vec<((String, u8), PyFeature)> = psms
+        .into_par_iter()
+        .map(|psm| {
+            let sequence = unimod_sequence(&db.inner[psm.inner.peptide_idx]);
+            ((sequence, psm.inner.charge), psm)
+        })
+        .collect();
+    let mut groups: BTreeMap<(String, u8), Vec<PyFeature>> = BTreeMap::new();
+    for (key, psm) in keyed {
+        groups.entry(key).or_default().push(psm);
+    }
+
+    Ok(groups
+        .into_values()
+        .collect::<Vec<_>>()
+        .into_par_iter()
+        .flat_map_iter(|mut group| {
+            group.sort_by(|a, b| match ascending {
+                true => value(a).total_cmp(&value(b)),
+                false => value(b).total_cmp(&value(a)),
+            });
+            group.truncate(keep_top_n);
+            group
+        })
+        .collect())
+}
+
+/// Keep the top ranked PSM of each spectrum, identified by file_id and spec_id
+#[pyfunction]
+pub fn dedup_psms_per_spectrum(psms: Vec<PyFeature>) -> Vec<PyFeature> {
+    let mut groups: BTreeMap<(usize, String), Vec<PyFeature>> = BTreeMap::new();
+    for psm in psms {
+        groups
+            .entry((psm.inner.file_id, psm.inner.spec_id.clone()))
+            .or_default()
+            .push(psm);
+    }
+
+    groups
+        .into_values()
+        .collect::<Vec<_>>()
+        .into_par_iter()
+        .filter_map(|group| group.into_iter().min_by_key(|psm| psm.inner.rank))
+        .collect()
+}
+
+/// Number of PSMs per missed cleavage count
+#[pyfunction]
+pub fn missed_cleavage_distribution(psms: Vec<PyFeature>) -> HashMap<u8, u32> {
+    let mut counts = HashMap::new();
+    for psm in psms.iter() {
+        *counts.entry(psm.inner.missed_cleavages).or_insert(0) += 1;
+    }
+    counts
+}
+
+/// Fraction of PSMs without missed cleavages, 0 for no PSMs
+#[pyfunction]
+pub fn compute_cleavage_efficiency(psms: Vec<PyFeature>) -> f32 {
+    if psms.is_empty() {
+        return 0.0;
+    }
+    psms.iter().filter(|psm| psm.inner.missed_cleavages == 0).count() as f32 / psms.len() as f32
+}
+
+/// Whether the (N-terminus, C-terminus) of the peptide of a PSM agree with the cleavage rule of
+/// an enzyme, see `terminal_specificity`
+#[pyfunction]
+pub fn check_enzyme_specificity(psm: &PyFeature, db: &PyIndexedDatabase, enzyme: &PyEnzyme) -> PyResult<(bool, bool)> {
+    check_peptide_indices(&db.inner, [psm])?;
+    Ok(terminal_specificity(&enzyme.inner, &db.inner[psm.inner.peptide_idx]))
+}
+
+/// PSMs with at least `min_specificity` enzyme specific termini: 0 (non-specific), 1 (semi) or
+/// 2 (fully specific)
+#[pyfunction]
+pub fn filter_by_specificity(
+    psms: Vec<PyFeature>,
+    db: &PyIndexedDatabase,
+    enzyme: &PyEnzyme,
+    min_specificity: u8,
+) -> PyResult<Vec<PyFeature>> {
+    check_peptide_indices(&db.inner, &psms)?;
+    if min_specificity > 2 {
+        return Err(PyValueError::new_err(format!(
+            "min_specificity must be 0, 1 or 2, got {}",
+            min_specificity
+        )));
+    }
+    Ok(psms
+        .into_iter()
+        .filter(|psm| {
+            let (nterm, cterm) = terminal_specificity(&enzyme.inner, &db.inner[psm.inner.peptide_idx]);
+            nterm as u8 + cterm as u8 >= min_specificity
+        })
+        .collect())
+}
+
+/// Median of the values, None if there are none
+pub(crate) fn median<T: Into<f64>>(values: impl IntoIterator<Item = T>) -> Option<f64> {
+    let mut values: Vec<f64> = values.into_iter().map(Into::into).collect();
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(|a, b| a.total_cmp(b));
+    let mid = values.len() / 2;
+    Some(if values.len() % 2 == 0 {
+        (values[mid - 1] + values[mid]) / 2.0
+    } else {
+        values[mid]
+    })
+}
+
+#[pymodule]
+pub fn utility(_py: Python, m: &PyModule) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(filter_by_modification, m)?)?;
+    m.add_function(wrap_pyfunction!(get_modification_positions, m)?)?;
+    m.add_function(wrap_pyfunction!(count_modifications, m)?)?;
+    m.add_function(wrap_pyfunction!(sequence_to_proforma, m)?)?;
+    m.add_function(wrap_pyfunction!(proforma_to_unimod_sequence, m)?)?;
+    m.add_function(wrap_pyfunction!(dedup_psms_per_peptide, m)?)?;
+    m.add_function(wrap_pyfunction!(dedup_psms_per_spectrum, m)?)?;
+    m.add_function(wrap_pyfunction!(missed_cleavage_distribution, m)?)?;
+    m.add_function(wrap_pyfunction!(compute_cleavage_efficiency, m)?)?;
+    m.add_function(wrap_pyfunction!(check_enzyme_specificity, m)?)?;
+    m.add_function(wrap_pyfunction!(filter_by_specificity, m)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::py_scoring::tests::{search_database, search_peptide};
+    use sage_core::database::PeptideIx;
+    use sage_core::scoring::Feature;
+
+    #[test]
+    fn dedup_keeps_the_best_psms_of_each_modified_sequence_and_charge() {
+        let mut phospho = search_peptide("PEPTIDEK", false);
+        phospho.modifications[3] = 79.9663;
+        phospho.monoisotopic += 79.9663;
+        let db = search_database(vec![search_peptide("PEPTIDEK", false), phospho]);
+        let peptide_idx = |modified: bool| {
+            let idx = db.inner.peptides.iter().position(|p| (p.modifications[3] != 0.0) == modified).unwrap();
+            PeptideIx(idx as u32)
+        };
+        let psm = |spec_id: &str, modified: bool, charge: u8, hyperscore: f64, spectrum_q: f32| {
+            PyFeature::from(Feature {
+                spec_id: spec_id.to_string(),
+                peptide_idx: peptide_idx(modified),
+                charge,
+                hyperscore,
+                spectrum_q,
+                ..crate::py_io::default_feature()
+            })
+        };
+        let psms = vec![
+            psm("1", false, 2, 10.0, 0.003),
+            psm("2", false, 2, 30.0, 0.002),
+            psm("3", false, 2, 20.0, 0.001),
+            psm("4", false, 3, 5.0, 0.004),
+            psm("5", true, 2, 15.0, 0.005),
+        ];
+        let spec_ids = |psms: Vec<PyFeature>| psms.into_iter().map(|p| p.inner.spec_id).collect::<Vec<_>>();
+
+        // ordered by modified sequence, PEPTIDEK sorts before PEPT[UNIMOD:21]IDEK
+        let best = dedup_psms_per_peptide(psms.clone(), &db, "hyperscore", 1).unwrap();
+        assert_eq!(spec_ids(best), vec!["2", "4", "5"]);
+        let top_two = dedup_psms_per_peptide(psms.clone(), &db, "hyperscore", 2).unwrap();
+        assert_eq!(spec_ids(top_two), vec!["2", "3", "4", "5"]);
+        // lower q-values are better
+        let best = dedup_psms_per_peptide(psms.clone(), &db, "spectrum_q", 1).unwrap();
+        assert_eq!(spec_ids(best), vec!["3", "4", "5"]);
+        assert!(dedup_psms_per_peptide(psms, &db, "score", 1).is_err());
+    }
+
+    #[test]
+    fn dedup_per_spectrum_keeps_the_top_ranked_psm() {
+        let psm = |file_id: usize, spec_id: &str, rank: u32| {
+            PyFeature::from(Feature {
+                file_id,
+                spec_id: spec_id.to_string(),
+                rank,
+                ..crate::py_io::default_feature()
+            })
+        };
+        let psms = vec![psm(0, "1", 2), psm(0, "1", 1), psm(1, "1", 3), psm(0, "2", 1)];
+        let kept: Vec<(usize, String, u32)> = dedup_psms_per_spectrum(psms)
+            .into_iter()
+            .map(|p| (p.inner.file_id, p.inner.spec_id, p.inner.rank))
+            .collect();
+        assert_eq!(
+            kept,
+            vec![(0, "1".to_string(), 1), (0, "2".to_string(), 1), (1, "1".to_string(), 3)]
+        );
+    }
+
+    #[test]
+    fn proforma_writes_terminal_and_residue_modifications_and_reads_them_back() {
+        let mut peptide = search_peptide("PEPTMIDEK", false);
+        peptide.nterm = Some(42.010565);
+        peptide.modifications[4] = 15.9949;
+        peptide.cterm = Some(-0.984016);
+
+        let proforma = proforma_sequence(&peptide, false);
+        assert_eq!(proforma, "[UNIMOD:1]-PEPTM[UNIMOD:35]IDEK-[UNIMOD:2]");
+        assert_eq!(proforma_sequence(&peptide, true), "[+42.0106]-PEPTM[+15.9949]IDEK-[-0.9840]");
+
+        let unimod = unimod_sequence(&peptide);
+        assert_eq!(unimod, "[UNIMOD:1]PEPTM[UNIMOD:35]IDEK-[UNIMOD:2]");
+        assert_eq!(proforma_to_unimod_sequence(&proforma).unwrap(), unimod);
+        assert_eq!(proforma_to_unimod_sequence(&proforma_sequence(&peptide, true)).unwrap(), unimod);
+        assert_eq!(
+            proforma_to_unimod_sequence("[Acetyl]-PEPTM[Oxidation]IDEK-[U:2]").unwrap(),
+            unimod
+        );
+
+        // acetylation of the first residue is written as N-terminal modification
+        let mut acetyl = search_peptide("KPEPTIDE", false);
+        acetyl.modifications[0] = 42.010565;
+        assert_eq!(proforma_sequence(&acetyl, false), "[UNIMOD:1]-KPEPTIDE");
+
+        assert!(proforma_to_unimod_sequence("PEPT[NotAModification]IDE").is_err());
+        assert!(proforma_to_unimod_sequence("PEPT[UNIMOD:x]IDE").is_err());
+    }
+}
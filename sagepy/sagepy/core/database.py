@@ -1,6 +1,6 @@
 import numpy as np
 
-from typing import List, Dict, Tuple, Union
+from typing import List, Dict, Tuple, Union, Optional
 
 import pandas as pd
 
@@ -8,6 +8,7 @@ from sagepy.core.mass import Tolerance
 from sagepy.core.peptide import Peptide
 
 from sagepy.core.enzyme import EnzymeParameters
+from sagepy.core.fasta import Fasta
 import sagepy_connector
 
 from sagepy.core.ion_series import IonType
@@ -73,9 +74,46 @@ class Theoretical:
         return self.__theoretical_ptr
 
 
+class SilacLabel:
+    def __init__(self, amino_acid: str, mass_shift: float, is_heavy: bool = True):
+        """SilacLabel class, a SILAC amino acid label
+
+        Args:
+            amino_acid (str): The labelled residue, e.g. 'K'
+            mass_shift (float): The mass shift of the label, e.g. 8.014199 for Lys8
+            is_heavy (bool, optional): Whether the label belongs to the heavy channel. Defaults to True.
+        """
+        self.__silac_label_ptr = psc.PySilacLabel(amino_acid, mass_shift, is_heavy)
+
+    @classmethod
+    def from_py_silac_label(cls, label: psc.PySilacLabel) -> 'SilacLabel':
+        instance = cls.__new__(cls)
+        instance.__silac_label_ptr = label
+        return instance
+
+    @property
+    def amino_acid(self) -> str:
+        return self.__silac_label_ptr.amino_acid
+
+    @property
+    def mass_shift(self) -> float:
+        return self.__silac_label_ptr.mass_shift
+
+    @property
+    def is_heavy(self) -> bool:
+        return self.__silac_label_ptr.is_heavy
+
+    def __repr__(self) -> str:
+        return f"SilacLabel(amino_acid: {self.amino_acid}, mass_shift: {self.mass_shift}, is_heavy: {self.is_heavy})"
+
+    def get_py_ptr(self):
+        return self.__silac_label_ptr
+
+
 class EnzymeBuilder:
     def __init__(self, missed_cleavages: int = None, min_len: int = None, max_len: int = None, cleave_at: str = None,
-                 restrict: str = None, c_terminal: bool = None, semi_enzymatic: bool = None):
+                 restrict: str = None, c_terminal: bool = None, semi_enzymatic: bool = None,
+                 specificity: str = None):
         """EnzymeBuilder class
 
         Args:
@@ -86,14 +124,24 @@ class EnzymeBuilder:
             restrict (str, optional): Restriction pattern. Defaults to None.
             c_terminal (bool, optional): Cleavage at the C-terminal. Defaults to None.
             semi_enzymatic (bool, optional): Allow semi-enzymatic peptides. Defaults to None.
+            specificity (str, optional): The cleavage specificity, 'full', 'semi' or 'none' (all subsequences
+                within the length bounds), overrides semi_enzymatic and, for 'none', the cleavage rule.
+                Defaults to None.
         """
         self.__enzyme_builder_ptr = psc.PyEnzymeBuilder(missed_cleavages, min_len, max_len, cleave_at, restrict,
-                                                        c_terminal, semi_enzymatic)
+                                                        c_terminal, semi_enzymatic, specificity)
 
     @staticmethod
     def default_trypsin() -> 'EnzymeBuilder':
         return EnzymeBuilder.from_py_enzyme_builder(psc.PyEnzymeBuilder.from_default_trypsin())
 
+    @staticmethod
+    def from_name(name: str, missed_cleavages: int = None, min_len: int = None, max_len: int = None,
+                  semi_enzymatic: bool = None) -> 'EnzymeBuilder':
+        """EnzymeBuilder with a predefined cleavage rule, see Enzyme.from_name for the available enzymes"""
+        return EnzymeBuilder.from_py_enzyme_builder(
+            psc.PyEnzymeBuilder.from_name(name, missed_cleavages, min_len, max_len, semi_enzymatic))
+
     @classmethod
     def from_py_enzyme_builder(cls, enzyme_builder: psc.PyEnzymeBuilder) -> 'EnzymeBuilder':
         instance = cls.__new__(cls)
@@ -134,6 +182,10 @@ class EnzymeBuilder:
     def semi_enzymatic(self) -> bool:
         return self.__enzyme_builder_ptr.semi_enzymatic
 
+    @property
+    def specificity(self) -> str:
+        return self.__enzyme_builder_ptr.specificity
+
     def __repr__(self):
         return f"EnzymeBuilder(missed_cleavages: {self.missed_cleavages}, min_len: {self.min_len}, " \
                f"max_len: {self.max_len}, cleave_at: {self.cleave_at}, restrict: {self.restrict}, " \
@@ -156,6 +208,8 @@ class SageSearchConfiguration:
                  max_variable_mods: int = 2,
                  decoy_tag: str = 'rev_',
                  generate_decoys: bool = True,
+                 silac_labels: Optional[List[SilacLabel]] = None,
+                 max_variable_mod_sites: Optional[int] = None,
                  ):
         """SageSearchConfiguration class
 
@@ -171,9 +225,14 @@ class SageSearchConfiguration:
             min_ion_index (int, optional): The minimum ion index. Defaults to 2.
             static_mods (Dict[ModificationSpecificity, float], optional): The static modifications. Defaults to None.
             variable_mods (Dict[ModificationSpecificity, List[float]], optional): The variable modifications. Defaults to None.
-            max_variable_mods (int, optional): The maximum number of variable modifications. Defaults to 2.
+            max_variable_mods (int, optional): The maximum number of variable modifications per peptide, at most 4.
+                Defaults to 2.
             decoy_tag (str, optional): The decoy tag. Defaults to 'rev_'.
             generate_decoys (bool, optional): Whether to generate decoys. Defaults to True.
+            silac_labels (Optional[List[SilacLabel]], optional): SILAC labels, a light and a heavy variant of each
+                peptide holding a labelled residue is indexed. Defaults to None.
+            max_variable_mod_sites (Optional[int], optional): Peptides with more residues (and termini) a variable
+                modification can be placed on are only indexed without variable modifications. Defaults to None.
         """
         self.__py_parameter_ptr = psc.PyParameters(
             find_next_power_of_2(bucket_size),
@@ -190,6 +249,8 @@ class SageSearchConfiguration:
             generate_decoys,
             fasta,
             ion_kinds,
+            [l.get_py_ptr() for l in silac_labels] if silac_labels is not None else None,
+            max_variable_mod_sites,
         )
 
     @classmethod
@@ -198,6 +259,13 @@ class SageSearchConfiguration:
         instance.__py_parameter_ptr = parameters
         return instance
 
+    def to_json(self) -> str:
+        return self.__py_parameter_ptr.to_json()
+
+    @classmethod
+    def from_json(cls, json: str) -> 'SageSearchConfiguration':
+        return cls.from_py_parameters(psc.PyParameters.from_json(json))
+
     def get_py_ptr(self):
         return self.__py_parameter_ptr
 
@@ -212,6 +280,19 @@ class SageSearchConfiguration:
         """
         return IndexedDatabase.from_py_indexed_database(self.__py_parameter_ptr.build_indexed_database())
 
+    def generate_indexed_database_cached(self, path: str) -> 'IndexedDatabase':
+        """Load the indexed database from a cache file (e.g. database.idx) if it was built with the same
+        parameters and fasta, otherwise generate it and write the cache file
+
+        Args:
+            path (str): The path of the cache file
+
+        Returns:
+            IndexedDatabase: The indexed database
+        """
+        return IndexedDatabase.from_py_indexed_database(
+            self.__py_parameter_ptr.build_indexed_database_cached(path))
+
     @property
     def bucket_size(self):
         return self.__py_parameter_ptr.bucket_size
@@ -268,6 +349,14 @@ class SageSearchConfiguration:
     def fasta(self):
         return self.__py_parameter_ptr.fasta
 
+    @property
+    def silac_labels(self) -> List[SilacLabel]:
+        return [SilacLabel.from_py_silac_label(l) for l in self.__py_parameter_ptr.silac_labels]
+
+    @property
+    def max_variable_mod_sites(self) -> Optional[int]:
+        return self.__py_parameter_ptr.max_variable_mod_sites
+
     def __repr__(self):
         return f"SageSearchConfiguration(bucket_size: {self.bucket_size}, enzyme_builder: {self.enzyme_builder}, " \
                f"fragment_min_mz: {self.fragment_min_mz}, fragment_max_mz: {self.fragment_max_mz}, " \
@@ -323,6 +412,46 @@ class IndexedDatabase:
         else:
             raise ValueError(f"Invalid item type: {type(item)}")
 
+    def clear_derived_cache(self):
+        """Drop the databases derived for searches (re-indexed for other ion kinds or restricted to candidate
+        subsets, e.g. one per ion mobility band range and charge with an ion mobility tolerance). The cache is
+        never evicted otherwise, its entries are rebuilt on next use
+        """
+        self.__indexed_database_ptr.clear_derived_cache()
+
+    @property
+    def num_derived_databases(self) -> int:
+        """Number of databases derived for searches and cached, see clear_derived_cache
+
+        Returns:
+            int: The number of cached databases
+        """
+        return self.__indexed_database_ptr.num_derived_databases
+
+    def get_silac_partner(self, peptide_idx: PeptideIx) -> Optional[PeptideIx]:
+        """The heavy variant of a light peptide and vice versa, for databases built with SILAC labels
+
+        Args:
+            peptide_idx (PeptideIx): The peptide
+
+        Returns:
+            Optional[PeptideIx]: The partner peptide, None if the peptide holds no labelled residue
+        """
+        partner = self.__indexed_database_ptr.get_silac_partner(peptide_idx.get_py_ptr())
+        return PeptideIx.from_py_peptide_ix(partner) if partner is not None else None
+
+    def get_protein_sources(self, peptide_idx: PeptideIx) -> List[Tuple[str, int]]:
+        """The protein accessions of a peptide, each with the index (source_db_id) of the database it came from in
+        merge_databases, 0 for databases that were not merged. Sources are not written by to_file
+
+        Args:
+            peptide_idx (PeptideIx): The peptide
+
+        Returns:
+            List[Tuple[str, int]]: The protein accessions and their source_db_id
+        """
+        return self.__indexed_database_ptr.get_protein_sources(peptide_idx.get_py_ptr())
+
     def query(self, precursor_mass: float, precursor_tolerance: Tolerance, fragment_tolerance: Tolerance):
         return IndexedQuery.from_py_indexed_query(self.__indexed_database_ptr.query(precursor_mass,
                                                                                     precursor_tolerance.get_py_ptr(),
@@ -423,6 +552,52 @@ class IndexedDatabase:
     def decoy_tag(self):
         return self.__indexed_database_ptr.decoy_tag
 
+    @property
+    def fasta_hash(self) -> str:
+        """SHA-256 digest of the FASTA content the database was built from
+
+        Returns:
+            str: The hex digest
+        """
+        return self.__indexed_database_ptr.fasta_hash
+
+    @property
+    def built(self) -> int:
+        return self.__indexed_database_ptr.built
+
+    @property
+    def version_string(self) -> str:
+        return self.__indexed_database_ptr.version_string
+
+    def checksum(self) -> str:
+        """SHA-256 digest of the FASTA content and the database contents (peptides, fragment index, ion kind,
+        modification and decoy settings)
+
+        Returns:
+            str: The hex digest
+        """
+        return self.__indexed_database_ptr.checksum()
+
+    def to_file(self, path: str):
+        """Serialize the database to a file
+
+        Args:
+            path (str): The path of the file
+        """
+        self.__indexed_database_ptr.to_file(path)
+
+    @staticmethod
+    def from_file(path: str) -> 'IndexedDatabase':
+        """Load a database written by to_file, files of a different sagepy version are rejected
+
+        Args:
+            path (str): The path of the file
+
+        Returns:
+            IndexedDatabase: The database
+        """
+        return IndexedDatabase.from_py_indexed_database(psc.PyIndexedDatabase.from_file(path))
+
     def __repr__(self):
         return f"IndexedDatabase(peptides: {self.num_peptides}, fragments: {self.num_fragments}, ion_kinds: {self.ion_kinds}, " \
                f"num_buckets: {len(self.min_value)}, " \
@@ -491,3 +666,326 @@ def find_next_power_of_2(n):
     int: The smallest power of two greater than or equal to n.
     """
     return 1 << (n-1).bit_length()
+
+
+def database_hash(db: IndexedDatabase) -> str:
+    """SHA-256 digest of the FASTA content a database was built from, allows to verify that
+    results of different searches were produced against the same protein database
+
+    Args:
+        db (IndexedDatabase): The database
+
+    Returns:
+        str: The hex digest
+    """
+    return psc.database_hash(db.get_py_ptr())
+
+
+def merge_databases(databases: List[IndexedDatabase]) -> IndexedDatabase:
+    """Merge several (e.g. organism-specific) databases into one combined index,
+    peptides shared between databases are stored once with all protein accessions. The position
+    of each database in the list is kept as source_db_id of its proteins (see get_protein_sources)
+
+    Args:
+        databases (List[IndexedDatabase]): The databases to merge
+
+    Returns:
+        IndexedDatabase: The merged database
+    """
+    return IndexedDatabase.from_py_indexed_database(psc.merge_databases([db.get_py_ptr() for db in databases]))
+
+
+def split_results_by_organism(features: List['Feature'], db: IndexedDatabase,
+                              organism_assignments: Dict[str, str]) -> Dict[str, List['Feature']]:
+    """Split PSMs into per-organism lists
+
+    Args:
+        features (List[Feature]): The PSMs to split
+        db (IndexedDatabase): The (merged) database the PSMs were scored against
+        organism_assignments (Dict[str, str]): A map from protein accession to organism
+
+    Returns:
+        Dict[str, List[Feature]]: The PSMs per organism, unassigned PSMs are stored under 'unassigned'
+    """
+    from sagepy.core.scoring import Feature
+    result = psc.split_results_by_organism([f.get_py_ptr() for f in features], db.get_py_ptr(),
+                                           organism_assignments)
+    return {k: [Feature.from_py_feature(f) for f in v] for k, v in result.items()}
+
+
+def prune_by_missed_cleavage_probability(candidates: List[PeptideIx], db: IndexedDatabase, max_allowed: int,
+                                         abundance_prior: float) -> List[PeptideIx]:
+    """Prune candidate peptides using a missed cleavage prior P(k) ~ exp(-abundance_prior * k),
+    candidates with more than max_allowed missed cleavages or a prior below 0.05 are removed
+
+    Args:
+        candidates (List[PeptideIx]): The candidate peptides
+        db (IndexedDatabase): The database the candidates belong to
+        max_allowed (int): The maximum number of missed cleavages
+        abundance_prior (float): The decay rate of the missed cleavage prior
+
+    Returns:
+        List[PeptideIx]: The remaining candidates, ordered by decreasing prior
+    """
+    result = psc.prune_by_missed_cleavage_probability([c.get_py_ptr() for c in candidates], db.get_py_ptr(),
+                                                      max_allowed, abundance_prior)
+    return [PeptideIx.from_py_peptide_ix(ix) for ix in result]
+
+
+def filter_database_by_detectability(db: IndexedDatabase, min_detectability: float) -> IndexedDatabase:
+    """Create a sub-database containing only peptides with a predicted detectability of at least min_detectability
+
+    Args:
+        db (IndexedDatabase): The database
+        min_detectability (float): The minimum predicted detectability (0-1)
+
+    Returns:
+        IndexedDatabase: The filtered database
+    """
+    return IndexedDatabase.from_py_indexed_database(psc.filter_database_by_detectability(db.get_py_ptr(),
+                                                                                         min_detectability))
+
+
+class ProteinCoverage:
+    def __init__(self):
+        raise NotImplementedError("ProteinCoverage is created by compute_sequence_coverage")
+
+    @classmethod
+    def from_py_protein_coverage(cls, coverage: psc.PyProteinCoverage) -> 'ProteinCoverage':
+        instance = cls.__new__(cls)
+        instance.__protein_coverage_ptr = coverage
+        return instance
+
+    @property
+    def coverage_pct(self) -> float:
+        return self.__protein_coverage_ptr.coverage_pct
+
+    @property
+    def covered_positions(self) -> List[bool]:
+        return self.__protein_coverage_ptr.covered_positions
+
+    @property
+    def peptide_spans(self) -> List[Tuple[int, int]]:
+        """0-based, end-exclusive (start, end) spans of all peptide occurrences"""
+        return self.__protein_coverage_ptr.peptide_spans
+
+    def uncovered_regions(self, min_gap: int = 1) -> List[Tuple[int, int]]:
+        return self.__protein_coverage_ptr.uncovered_regions(min_gap)
+
+    def __repr__(self) -> str:
+        return f"ProteinCoverage(coverage_pct: {self.coverage_pct:.2f}, peptides: {len(self.peptide_spans)})"
+
+    def get_py_ptr(self):
+        return self.__protein_coverage_ptr
+
+
+def compute_sequence_coverage(protein_sequence: str, identified_peptides: List[str]) -> ProteinCoverage:
+    """Compute the sequence coverage of a protein by identified peptides
+
+    Args:
+        protein_sequence (str): The protein sequence
+        identified_peptides (List[str]): The identified (unmodified) peptide sequences
+
+    Returns:
+        ProteinCoverage: The coverage, every occurrence of a peptide counts
+    """
+    return ProteinCoverage.from_py_protein_coverage(psc.compute_sequence_coverage(protein_sequence,
+                                                                                  identified_peptides))
+
+
+def get_uncovered_regions(coverage: ProteinCoverage, min_gap: int = 1) -> List[Tuple[int, int]]:
+    """Get the runs of uncovered residues of a protein
+
+    Args:
+        coverage (ProteinCoverage): The coverage
+        min_gap (int, optional): The minimum length of a reported region. Defaults to 1.
+
+    Returns:
+        List[Tuple[int, int]]: 0-based, end-exclusive (start, end) spans of the uncovered regions
+    """
+    return psc.get_uncovered_regions(coverage.get_py_ptr(), min_gap)
+
+
+def compute_coverage_for_all_proteins(features: List['Feature'], db: IndexedDatabase,
+                                      fasta: Fasta) -> Dict[str, ProteinCoverage]:
+    """Compute the sequence coverage of every target protein identified by target PSMs
+
+    Args:
+        features (List[Feature]): The PSMs
+        db (IndexedDatabase): The database the PSMs were scored against
+        fasta (Fasta): The FASTA the database was built from, providing the protein sequences
+
+    Returns:
+        Dict[str, ProteinCoverage]: The coverage per protein accession
+    """
+    result = psc.compute_coverage_for_all_proteins([f.get_py_ptr() for f in features], db.get_py_ptr(),
+                                                   fasta.get_py_ptr())
+    return {k: ProteinCoverage.from_py_protein_coverage(v) for k, v in result.items()}
+
+
+class PeptideRegion:
+    def __init__(self):
+        raise NotImplementedError("PeptideRegion is created by build_coverage_maps")
+
+    @classmethod
+    def from_py_peptide_region(cls, region: psc.PyPeptideRegion) -> 'PeptideRegion':
+        instance = cls.__new__(cls)
+        instance.__peptide_region_ptr = region
+        return instance
+
+    @property
+    def start(self) -> int:
+        """0-based start position in the protein"""
+        return self.__peptide_region_ptr.start
+
+    @property
+    def end(self) -> int:
+        """0-based, exclusive end position in the protein"""
+        return self.__peptide_region_ptr.end
+
+    @property
+    def sequence(self) -> str:
+        return self.__peptide_region_ptr.sequence
+
+    @property
+    def q_value(self) -> float:
+        """The best spectrum q-value of the PSMs of the peptide"""
+        return self.__peptide_region_ptr.q_value
+
+    @property
+    def count(self) -> int:
+        """The number of PSMs of the peptide"""
+        return self.__peptide_region_ptr.count
+
+    @property
+    def unique(self) -> bool:
+        """Whether the peptide maps to this protein only"""
+        return self.__peptide_region_ptr.unique
+
+    def __repr__(self) -> str:
+        return f"PeptideRegion(sequence: {self.sequence}, start: {self.start}, end: {self.end}, " \
+               f"q_value: {self.q_value}, count: {self.count}, unique: {self.unique})"
+
+    def get_py_ptr(self):
+        return self.__peptide_region_ptr
+
+
+class ProteinCoverageMap:
+    def __init__(self):
+        raise NotImplementedError("ProteinCoverageMap is created by build_coverage_maps")
+
+    @classmethod
+    def from_py_protein_coverage_map(cls, coverage_map: psc.PyProteinCoverageMap) -> 'ProteinCoverageMap':
+        instance = cls.__new__(cls)
+        instance.__coverage_map_ptr = coverage_map
+        return instance
+
+    @property
+    def protein_id(self) -> str:
+        return self.__coverage_map_ptr.protein_id
+
+    @property
+    def sequence(self) -> str:
+        return self.__coverage_map_ptr.sequence
+
+    @property
+    def covered(self) -> List[bool]:
+        return self.__coverage_map_ptr.covered
+
+    @property
+    def peptide_regions(self) -> List[PeptideRegion]:
+        return [PeptideRegion.from_py_peptide_region(r) for r in self.__coverage_map_ptr.peptide_regions]
+
+    @property
+    def coverage_pct(self) -> float:
+        return self.__coverage_map_ptr.coverage_pct
+
+    def to_svg(self, residues_per_row: int = 50) -> str:
+        """SVG of the coverage map, covered residues are shaded and peptides drawn as bars below their residues,
+        blue if unique to the protein and orange if shared"""
+        return self.__coverage_map_ptr.to_svg(residues_per_row)
+
+    def __repr__(self) -> str:
+        return f"ProteinCoverageMap(protein_id: {self.protein_id}, coverage_pct: {self.coverage_pct:.2f}, " \
+               f"peptides: {len(self.peptide_regions)})"
+
+    def get_py_ptr(self):
+        return self.__coverage_map_ptr
+
+
+def build_coverage_maps(features: List['Feature'], db: IndexedDatabase, fasta: Fasta,
+                        fdr_threshold: float = 0.01) -> List[ProteinCoverageMap]:
+    """Build residue level coverage maps with peptide annotations of every target protein identified by confident
+    target PSMs
+
+    Args:
+        features (List[Feature]): The PSMs
+        db (IndexedDatabase): The database the PSMs were scored against
+        fasta (Fasta): The FASTA the database was built from, providing the protein sequences
+        fdr_threshold (float, optional): The maximum spectrum q-value of the PSMs used. Defaults to 0.01.
+
+    Returns:
+        List[ProteinCoverageMap]: The coverage maps, sorted by protein accession
+    """
+    result = psc.build_coverage_maps([f.get_py_ptr() for f in features], db.get_py_ptr(), fasta.get_py_ptr(),
+                                     fdr_threshold)
+    return [ProteinCoverageMap.from_py_protein_coverage_map(m) for m in result]
+
+
+def coverage_to_svg(coverage_map: ProteinCoverageMap, residues_per_row: int = 50) -> str:
+    """SVG representation of a coverage map, see ProteinCoverageMap.to_svg
+
+    Args:
+        coverage_map (ProteinCoverageMap): The coverage map
+        residues_per_row (int, optional): The number of residues per row. Defaults to 50.
+
+    Returns:
+        str: The SVG document
+    """
+    return psc.coverage_to_svg(coverage_map.get_py_ptr(), residues_per_row)
+
+
+def merge_coverage_maps(coverage_maps: List[ProteinCoverageMap]) -> ProteinCoverageMap:
+    """Merge coverage maps of the same protein, e.g. of replicates: coverage is the union, peptide regions at the
+    same position keep the best q-value and sum their PSM counts
+
+    Args:
+        coverage_maps (List[ProteinCoverageMap]): The coverage maps of one protein
+
+    Returns:
+        ProteinCoverageMap: The merged coverage map
+    """
+    return ProteinCoverageMap.from_py_protein_coverage_map(
+        psc.merge_coverage_maps([m.get_py_ptr() for m in coverage_maps]))
+
+
+def count_modified_peptides(db: IndexedDatabase, configuration: SageSearchConfiguration) -> Dict[int, int]:
+    """Count the indexed peptides per number of variable modifications, e.g. to judge the database size
+    added by max_variable_mods and max_variable_mod_sites
+
+    Args:
+        db (IndexedDatabase): The database
+        configuration (SageSearchConfiguration): The configuration the database was built with, separating
+            static from variable modifications
+
+    Returns:
+        Dict[int, int]: The number of peptides per number of variable modifications
+    """
+    return psc.count_modified_peptides(db.get_py_ptr(), configuration.get_py_ptr())
+
+
+def report_variable_mod_coverage(db: IndexedDatabase, configuration: SageSearchConfiguration,
+                                 features: List['Feature'], fdr_threshold: float = 0.01) -> Dict[int, float]:
+    """Fraction of confident target PSMs per number of variable modifications of their peptide
+
+    Args:
+        db (IndexedDatabase): The database the PSMs were scored against
+        configuration (SageSearchConfiguration): The configuration the database was built with
+        features (List[Feature]): The PSMs
+        fdr_threshold (float, optional): The maximum spectrum q-value of the PSMs used. Defaults to 0.01.
+
+    Returns:
+        Dict[int, float]: The fraction of PSMs per number of variable modifications
+    """
+    return psc.report_variable_mod_coverage(db.get_py_ptr(), configuration.get_py_ptr(),
+                                            [f.get_py_ptr() for f in features], fdr_threshold)
@@ -1,4 +1,4 @@
-from typing import Union
+from typing import Optional, Union, List, Tuple
 
 import numpy as np
 import sagepy_connector
@@ -38,7 +38,8 @@ class Position:
 
 
 class Enzyme:
-    def __init__(self, cleave_pattern: str = 'KR', c_terminal: bool = True, skip_suffix: str = 'P', semi_enzymatic: bool = False):
+    def __init__(self, cleave_pattern: str = 'KR', c_terminal: bool = True, skip_suffix: str = 'P', semi_enzymatic: bool = False,
+                 specificity: Optional[str] = None):
         """Enzyme class, default enzyme is Trypsin
 
         Args:
@@ -46,8 +47,25 @@ class Enzyme:
             c_terminal (bool, optional): Cleave from the C-terminal. Defaults to True.
             skip_suffix (str, optional): The skip suffix of the enzyme. Defaults to 'P'.
             semi_enzymatic (bool, optional): Is the enzyme semi enzymatic. Defaults to False.
+            specificity (Optional[str], optional): The cleavage specificity, 'full', 'semi' (one terminus follows
+                the cleavage rule) or 'none' (all subsequences), overrides semi_enzymatic. Defaults to None.
         """
-        self.__enzyme_ptr = psc.PyEnzyme(cleave_pattern, c_terminal, skip_suffix, semi_enzymatic)
+        self.__enzyme_ptr = psc.PyEnzyme(cleave_pattern, c_terminal, semi_enzymatic, skip_suffix, specificity)
+
+    @classmethod
+    def from_name(cls, name: str, semi_enzymatic: bool = False) -> 'Enzyme':
+        """Enzyme with a predefined cleavage rule
+
+        Args:
+            name (str): The enzyme, one of trypsin, trypsin_p, lysc, lysn (before K), chymotrypsin,
+                gluc (after D/E, phosphate buffer), gluc_bicarb (after E, ammonium bicarbonate), aspn (before D)
+                or aspn_cys (before D/C)
+            semi_enzymatic (bool, optional): Is the enzyme semi enzymatic. Defaults to False.
+
+        Returns:
+            Enzyme: The enzyme
+        """
+        return cls.from_py_enzyme(psc.PyEnzyme.from_name(name, semi_enzymatic))
 
     @classmethod
     def from_py_enzyme(cls, enzyme: psc.PyEnzyme):
@@ -67,11 +85,19 @@ class Enzyme:
             return None
         return self.__enzyme_ptr.skip_suffix
 
+    @property
+    def specificity(self) -> str:
+        return self.__enzyme_ptr.specificity
+
     def cleavage_sites(self, sequence: str):
         if self.__enzyme_ptr is None:
             return None
         return self.__enzyme_ptr.cleavage_sites(sequence)
 
+    def cleavage_positions(self, sequence: str) -> List[int]:
+        """The positions the enzyme cuts a sequence, the 0-based index of the first residue after each cut"""
+        return self.__enzyme_ptr.cleavage_positions(sequence)
+
     def cleave(self, sequence: str, min_length: int = 1, max_length: int = np.inf):
         if self.__enzyme_ptr is None:
             raise ValueError("Enzyme is not defined")
@@ -194,4 +220,132 @@ class EnzymeParameters:
         return [Digest.from_py_digest(s) for s in self.__enzyme_parameters_ptr.digest(sequence, protein)]
 
     def get_py_ptr(self):
-        return self.__enzyme_parameters_ptr
\ No newline at end of file
+        return self.__enzyme_parameters_ptr
+
+
+def cleavage_rules(enzyme: Enzyme) -> Tuple[str, bool]:
+    """The cleavage rule of an enzyme
+
+    Args:
+        enzyme (Enzyme): The enzyme
+
+    Returns:
+        Tuple[str, bool]: The cleavage regex and whether the enzyme cleaves before proline
+    """
+    return psc.cleavage_rules(enzyme.get_py_ptr())
+
+
+def combined_trypsin_lysc_digest(sequence: str, protein: str, missed_cleavages: int = 0, min_len: int = 5,
+                                 max_len: int = 50) -> List[Digest]:
+    """Double digest with trypsin and Lys-C, cleaving after every K and after R not followed by P
+
+    Args:
+        sequence (str): The protein sequence
+        protein (str): The protein accession
+        missed_cleavages (int, optional): The maximum number of missed cleavages. Defaults to 0.
+        min_len (int, optional): The minimum peptide length. Defaults to 5.
+        max_len (int, optional): The maximum peptide length. Defaults to 50.
+
+    Returns:
+        List[Digest]: The digests
+    """
+    return [Digest.from_py_digest(d) for d in
+            psc.combined_trypsin_lysc_digest(sequence, protein, missed_cleavages, min_len, max_len)]
+
+
+class DigestedPeptide:
+    def __init__(self):
+        raise NotImplementedError("DigestedPeptide is created by digest_sequence")
+
+    @classmethod
+    def from_py_digested_peptide(cls, peptide: psc.PyDigestedPeptide) -> 'DigestedPeptide':
+        instance = cls.__new__(cls)
+        instance.__digested_peptide_ptr = peptide
+        return instance
+
+    @property
+    def sequence(self) -> str:
+        return self.__digested_peptide_ptr.sequence
+
+    @property
+    def start(self) -> int:
+        return self.__digested_peptide_ptr.start
+
+    @property
+    def end(self) -> int:
+        return self.__digested_peptide_ptr.end
+
+    @property
+    def missed_cleavages(self) -> int:
+        return self.__digested_peptide_ptr.missed_cleavages
+
+    @property
+    def mono_mass(self) -> float:
+        return self.__digested_peptide_ptr.mono_mass
+
+    @property
+    def protein_id(self) -> str:
+        return self.__digested_peptide_ptr.protein_id
+
+    def __repr__(self):
+        return f"DigestedPeptide(sequence: {self.sequence}, start: {self.start}, end: {self.end}, " \
+               f"missed_cleavages: {self.missed_cleavages}, mono_mass: {self.mono_mass}, protein_id: {self.protein_id})"
+
+    def get_py_ptr(self):
+        return self.__digested_peptide_ptr
+
+
+def digest_sequence(sequence: str, enzyme: Enzyme, missed_cleavages: int = 0, min_len: int = 5,
+                    max_len: int = 50) -> List[DigestedPeptide]:
+    """Digest a single sequence without building a database
+
+    Args:
+        sequence (str): The protein sequence
+        enzyme (Enzyme): The enzyme, peptides follow its cleavage rule on both termini
+        missed_cleavages (int, optional): The maximum number of missed cleavages. Defaults to 0.
+        min_len (int, optional): The minimum peptide length. Defaults to 5.
+        max_len (int, optional): The maximum peptide length. Defaults to 50.
+
+    Returns:
+        List[DigestedPeptide]: The peptides with their 0-based, end-exclusive span in the sequence
+    """
+    return [DigestedPeptide.from_py_digested_peptide(p) for p in
+            psc.digest_sequence(sequence, enzyme.get_py_ptr(), missed_cleavages, min_len, max_len)]
+
+
+def digest_protein(fasta_entry: Tuple[str, str], enzyme: Enzyme, missed_cleavages: int = 0, min_len: int = 5,
+                   max_len: int = 50) -> List[DigestedPeptide]:
+    """Digest a FASTA entry without building a database
+
+    Args:
+        fasta_entry (Tuple[str, str]): The (accession, sequence) entry, e.g. of Fasta.targets
+        enzyme (Enzyme): The enzyme
+        missed_cleavages (int, optional): The maximum number of missed cleavages. Defaults to 0.
+        min_len (int, optional): The minimum peptide length. Defaults to 5.
+        max_len (int, optional): The maximum peptide length. Defaults to 50.
+
+    Returns:
+        List[DigestedPeptide]: The peptides, with the accession as protein_id
+    """
+    return [DigestedPeptide.from_py_digested_peptide(p) for p in
+            psc.digest_protein(fasta_entry, enzyme.get_py_ptr(), missed_cleavages, min_len, max_len)]
+
+
+def digest_collection_parallel(fasta_entries: List[Tuple[str, str]], enzyme: Enzyme, missed_cleavages: int = 0,
+                               min_len: int = 5, max_len: int = 50, num_threads: int = 4) -> List[DigestedPeptide]:
+    """Digest many FASTA entries in parallel without building a database
+
+    Args:
+        fasta_entries (List[Tuple[str, str]]): The (accession, sequence) entries, e.g. Fasta.targets
+        enzyme (Enzyme): The enzyme
+        missed_cleavages (int, optional): The maximum number of missed cleavages. Defaults to 0.
+        min_len (int, optional): The minimum peptide length. Defaults to 5.
+        max_len (int, optional): The maximum peptide length. Defaults to 50.
+        num_threads (int, optional): The number of threads. Defaults to 4.
+
+    Returns:
+        List[DigestedPeptide]: The peptides of all entries, in the order of the entries
+    """
+    return [DigestedPeptide.from_py_digested_peptide(p) for p in
+            psc.digest_collection_parallel(fasta_entries, enzyme.get_py_ptr(), missed_cleavages, min_len, max_len,
+                                           num_threads)]
@@ -0,0 +1,65 @@
+from typing import List, Optional
+
+import sagepy_connector
+from sagepy.core.database import IndexedDatabase
+from sagepy.core.scoring import Feature
+
+psc = sagepy_connector.py_export
+
+
+def write_mzidentml(features: List[Feature], db: IndexedDatabase, database_path: str, output_path: str,
+                    software_version: str) -> None:
+    """Write PSMs as mzIdentML 1.2, peptides and proteins are resolved from the database. Matched peaks and
+    the PSM q-value are reported with PSI-MS terms, all re-scoring features with SAGEPY terms
+
+    Args:
+        features (List[Feature]): The PSMs
+        db (IndexedDatabase): The database the PSMs were identified with
+        database_path (str): The path of the searched FASTA file, stored as SearchDatabase location
+        output_path (str): The path of the mzIdentML file
+        software_version (str): The sagepy version stored as AnalysisSoftware version
+    """
+    psc.write_mzidentml([f.get_py_ptr() for f in features], db.get_py_ptr(), database_path, output_path,
+                        software_version)
+
+
+def read_mzidentml(path: str) -> List[Feature]:
+    """Read PSMs from an mzIdentML file, recovering charge, masses, rank, retention time, decoy status and
+    all SAGEPY scores. Files are numbered in order of appearance as file_id, the PSMs refer to no database
+    peptide and carry their peptide sequence as peptide_sequence
+
+    Args:
+        path (str): The path of the mzIdentML file
+
+    Returns:
+        List[Feature]: The PSMs
+    """
+    return [Feature.from_py_feature(f) for f in psc.read_mzidentml(path)]
+
+
+def write_mztab(features: List[Feature], db: IndexedDatabase, output_path: str,
+                study_variables: Optional[List[str]] = None) -> None:
+    """Write PSMs as mzTab 1.0 (Summary, Identification), one PSM row per PSM and protein accession with the
+    hyperscore as search engine score, the decoy flag, q-value and peptide index as optional columns
+
+    Args:
+        features (List[Feature]): The PSMs
+        db (IndexedDatabase): The database the PSMs were identified with
+        output_path (str): The path of the mzTab file
+        study_variables (Optional[List[str]], optional): The study variable descriptions. Defaults to None.
+    """
+    psc.write_mztab([f.get_py_ptr() for f in features], db.get_py_ptr(), output_path,
+                    study_variables if study_variables is not None else [])
+
+
+def read_mztab(path: str) -> List[Feature]:
+    """Read PSMs from the PSM section of an mzTab file, recovering charge, masses, retention time, spectrum
+    reference and hyperscore (decoy status, q-value and peptide index for files written by write_mztab)
+
+    Args:
+        path (str): The path of the mzTab file
+
+    Returns:
+        List[Feature]: The PSMs
+    """
+    return [Feature.from_py_feature(f) for f in psc.read_mztab(path)]
@@ -1,3 +1,4 @@
+from typing import List, Tuple
 from .enzyme import EnzymeParameters, Digest
 import sagepy_connector
 psc = sagepy_connector.py_fasta
@@ -20,5 +21,85 @@ class Fasta:
         instance.__fasta_ptr = fasta
         return instance
 
+    @property
+    def targets(self) -> List[Tuple[str, str]]:
+        return self.__fasta_ptr.targets
+
+    @property
+    def fasta_hash(self) -> str:
+        return self.__fasta_ptr.fasta_hash
+
+    def get_py_ptr(self):
+        return self.__fasta_ptr
+
     def _digest(self, enzyme_parameters: EnzymeParameters):
         return [Digest.from_py_digest(s) for s in self.__fasta_ptr.digest(enzyme_parameters.get_py_ptr())]
+
+
+def read_fasta(path: str, decoy_tag: str = 'decoy_', generate_decoys: bool = False) -> Fasta:
+    """Read a fasta file, gzip compressed files (e.g. .fa.gz) are decompressed transparently. Records are streamed
+    and parsed in batches, the file contents are never held in memory as a whole
+
+    Args:
+        path (str): The path of the fasta file
+        decoy_tag (str, optional): The decoy tag. Defaults to 'decoy_'.
+        generate_decoys (bool, optional): Should decoys be generated. Defaults to False.
+
+    Returns:
+        Fasta: The parsed fasta
+    """
+    return Fasta.from_py_fasta(psc.PyFasta.read(path, decoy_tag, generate_decoys))
+
+
+def read_fasta_parallel(path: str, decoy_tag: str = 'decoy_', generate_decoys: bool = False,
+                        num_threads: int = 4) -> Fasta:
+    """Read a fasta file like read_fasta, parsing the streamed batches of records on multiple threads
+
+    Args:
+        path (str): The path of the fasta file
+        decoy_tag (str, optional): The decoy tag. Defaults to 'decoy_'.
+        generate_decoys (bool, optional): Should decoys be generated. Defaults to False.
+        num_threads (int, optional): The number of threads. Defaults to 4.
+
+    Returns:
+        Fasta: The parsed fasta
+    """
+    return Fasta.from_py_fasta(psc.PyFasta.read_parallel(path, decoy_tag, generate_decoys, num_threads))
+
+
+def cluster_sequences_by_identity(proteins: List[Tuple[str, str]],
+                                  identity_threshold: float = 0.9) -> List[List[Tuple[str, str]]]:
+    """Greedily cluster proteins at a sequence identity (CD-HIT-like), estimating identity from shared 5-mers
+
+    Args:
+        proteins (List[Tuple[str, str]]): The (accession, sequence) proteins, e.g. Fasta.targets
+        identity_threshold (float, optional): The minimum identity (0-1) to a cluster representative.
+            Defaults to 0.9.
+
+    Returns:
+        List[List[Tuple[str, str]]]: The clusters, each starting with its representative
+    """
+    return psc.cluster_sequences_by_identity(proteins, identity_threshold)
+
+
+def select_representatives(clusters: List[List[Tuple[str, str]]]) -> List[Tuple[str, str]]:
+    """Pick the longest protein of each cluster
+
+    Args:
+        clusters (List[List[Tuple[str, str]]]): The clusters of (accession, sequence) proteins
+
+    Returns:
+        List[Tuple[str, str]]: One representative per cluster
+    """
+    return psc.select_representatives(clusters)
+
+
+def write_nonredundant_fasta(proteins: List[Tuple[str, str]], path: str, identity: float = 0.9) -> None:
+    """Cluster proteins at a sequence identity and write the cluster representatives as fasta
+
+    Args:
+        proteins (List[Tuple[str, str]]): The (accession, sequence) proteins, e.g. Fasta.targets
+        path (str): The path of the fasta file to write
+        identity (float, optional): The minimum identity (0-1) to a cluster representative. Defaults to 0.9.
+    """
+    psc.write_nonredundant_fasta(proteins, path, identity)
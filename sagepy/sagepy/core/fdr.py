@@ -1,9 +1,45 @@
-from typing import Optional
-from sagepy.core.database import PeptideIx
+from typing import Optional, List, Dict, Tuple
+from sagepy.core.database import PeptideIx, IndexedDatabase
+from sagepy.core.scoring import Feature
 import sagepy_connector
 psc = sagepy_connector.py_fdr
 
 
+class GroupingStrategy:
+    """GroupingStrategy class, the grouping of PSMs for group-wise FDR control
+
+    Args:
+        strategy (str): The grouping strategy, allowed values are: charge, length, modification
+        bin_width (Optional[int], optional): The peptide length bin width of the length strategy. Defaults to None (5).
+    """
+    def __init__(self, strategy: str, bin_width: Optional[int] = None):
+        strategies = ["charge", "length", "modification"]
+        if strategy in strategies:
+            self.__grouping_strategy_ptr = psc.PyGroupingStrategy(strategy, bin_width)
+        else:
+            raise ValueError(f"Invalid grouping strategy, allowed values are: {strategies}")
+
+    @classmethod
+    def from_py_grouping_strategy(cls, grouping_strategy: psc.PyGroupingStrategy):
+        instance = cls.__new__(cls)
+        instance.__grouping_strategy_ptr = grouping_strategy
+        return instance
+
+    @property
+    def strategy(self) -> str:
+        return self.__grouping_strategy_ptr.strategy
+
+    @property
+    def bin_width(self) -> Optional[int]:
+        return self.__grouping_strategy_ptr.bin_width
+
+    def __repr__(self):
+        return f"GroupingStrategy({self.strategy}, bin_width={self.bin_width})"
+
+    def get_py_ptr(self):
+        return self.__grouping_strategy_ptr
+
+
 class CompetitionPeptideIx:
     def __init__(self, forward: float, reverse: float,
                  forward_ix: Optional[PeptideIx] = None, reverse_ix: Optional[PeptideIx] = None):
@@ -45,3 +81,455 @@ class CompetitionPeptideIx:
     def __repr__(self):
         return (f"CompetitionPeptideIx(forward={self.forward}, reverse={self.reverse}, "
                 f"forward_ix={self.forward_ix}, reverse_ix={self.reverse_ix})")
+
+
+def storey_qvalues(scores: List[float], is_decoy: List[bool], lambda_range: Optional[List[float]] = None) -> List[float]:
+    """Storey-Tibshirani q-values, target p-values are estimated from the decoy score distribution and
+    the proportion of true nulls (pi0) from these p-values over the lambda grid, smoothed by a cubic smoothing
+    spline as in the R qvalue package
+
+    Args:
+        scores (List[float]): The scores, higher is better
+        is_decoy (List[bool]): Whether the score belongs to a decoy
+        lambda_range (Optional[List[float]], optional): The lambda grid of the pi0 estimation.
+            Defaults to None (0.05, 0.10, ..., 0.95).
+
+    Returns:
+        List[float]: The q-values, decoys are assigned 1.0
+    """
+    return psc.storey_qvalues(scores, is_decoy, lambda_range if lambda_range is not None else [])
+
+
+def calculate_fdr(scores: List[float], is_decoy: List[bool], method: str = 'tda') -> List[float]:
+    """Calculate q-values of scored target and decoy PSMs
+
+    Args:
+        scores (List[float]): The scores, higher is better
+        is_decoy (List[bool]): Whether the score belongs to a decoy
+        method (str, optional): The method, 'tda' (target-decoy) or 'storey' (Storey-Tibshirani). Defaults to 'tda'.
+
+    Returns:
+        List[float]: The q-values
+    """
+    return psc.calculate_fdr(scores, is_decoy, method)
+
+
+def estimate_pi0(scores: List[float], is_decoy: List[bool], lambdas: Optional[List[float]] = None,
+                 method: str = 'spline') -> Tuple[float, List[float]]:
+    """Estimate pi0, the proportion of incorrect target PSMs, from target p-values against the decoy scores
+
+    Args:
+        scores (List[float]): The scores, higher is better
+        is_decoy (List[bool]): Whether the score belongs to a decoy
+        lambdas (Optional[List[float]], optional): The lambda grid in [0, 1). Defaults to None (0.05, 0.1, ..., 0.95).
+        method (str, optional): 'spline' (cubic smoothing spline with 3 degrees of freedom through the pi0(lambda)
+            curve, evaluated at the largest lambda, as the R qvalue package) or 'bootstrap' (lambda with the
+            smallest bootstrap mean squared error). Defaults to 'spline'.
+
+    Returns:
+        Tuple[float, List[float]]: pi0 and the raw pi0 at each lambda
+    """
+    return psc.estimate_pi0(scores, is_decoy, lambdas if lambdas is not None else [], method)
+
+
+def pi0_confidence_interval(scores: List[float], is_decoy: List[bool], lambda_: float = 0.5,
+                            n_bootstrap: int = 1000) -> Tuple[float, float, float]:
+    """Bootstrap 95% confidence interval of pi0 at a lambda
+
+    Args:
+        scores (List[float]): The scores, higher is better
+        is_decoy (List[bool]): Whether the score belongs to a decoy
+        lambda_ (float, optional): The lambda in [0, 1). Defaults to 0.5.
+        n_bootstrap (int, optional): The number of bootstrap samples. Defaults to 1000.
+
+    Returns:
+        Tuple[float, float, float]: The lower bound, the estimate and the upper bound
+    """
+    return psc.pi0_confidence_interval(scores, is_decoy, lambda_, n_bootstrap)
+
+
+def calculate_q_values_with_pi0(psms: List[Feature], pi0: float) -> List[Feature]:
+    """Set the spectrum q-values of PSMs by target-decoy competition on the discriminant score, with the FDR
+    estimate scaled by pi0
+
+    Args:
+        psms (List[Feature]): The target and decoy PSMs
+        pi0 (float): The proportion of incorrect target PSMs, e.g. of estimate_pi0
+
+    Returns:
+        List[Feature]: The PSMs with updated spectrum q-values
+    """
+    return [Feature.from_py_feature(f) for f in
+            psc.calculate_q_values_with_pi0([p.get_py_ptr() for p in psms], pi0)]
+
+
+def semi_supervised_fdr(psms: List[Feature], num_iterations: int = 10,
+                        num_threads: int = 4) -> List[Feature]:
+    """Percolator-like semi-supervised re-scoring with 3-fold cross-validation, per fold a linear SVM is
+    iteratively trained on the other folds to separate confident target PSMs (q <= 0.01) from decoys and
+    re-scores the held-out fold. PSMs of the same spectrum share a fold
+
+    Args:
+        psms (List[Feature]): The target and decoy PSMs
+        num_iterations (int, optional): The number of training iterations. Defaults to 10.
+        num_threads (int, optional): The number of threads. Defaults to 4.
+
+    Returns:
+        List[Feature]: The PSMs with their re_score and updated spectrum q-value and posterior error (the
+            discriminant score is unchanged)
+    """
+    return [Feature.from_py_feature(p) for p in
+            psc.semi_supervised_fdr([p.get_py_ptr() for p in psms], num_iterations, num_threads)]
+
+
+def protein_fdr(psms: List[Feature], db: IndexedDatabase, peptide_q_cutoff: float = 0.01) -> Dict[str, float]:
+    """Protein-level FDR with razor protein assignment, a shared peptide counts for the protein with the most
+    unique identified peptides and proteins are scored by the best discriminant score of their razor peptides
+
+    Args:
+        psms (List[Feature]): The target and decoy PSMs, with peptide q-values
+        db (IndexedDatabase): The database the PSMs were scored against
+        peptide_q_cutoff (float, optional): The peptide q-value a peptide needs to count as evidence. Defaults to 0.01.
+
+    Returns:
+        Dict[str, float]: The q-value of each protein, decoy proteins carry the decoy tag
+    """
+    return psc.protein_fdr([p.get_py_ptr() for p in psms], db.get_py_ptr(), peptide_q_cutoff)
+
+
+def razor_protein_assignment(psms: List[Feature], db: IndexedDatabase,
+                             protein_peptide_map: Dict[str, List[str]]) -> Dict[str, str]:
+    """Assign each identified peptide to its razor protein, a shared peptide goes to the protein with the most
+    unique identified peptides, ties are broken by the most identified peptides, then alphabetically by accession
+
+    Args:
+        psms (List[Feature]): The PSMs identifying the peptides
+        db (IndexedDatabase): The database the PSMs were scored against
+        protein_peptide_map (Dict[str, List[str]]): The (stripped) peptide sequences of each protein
+
+    Returns:
+        Dict[str, str]: The razor protein of each identified peptide
+    """
+    return psc.razor_protein_assignment([p.get_py_ptr() for p in psms], db.get_py_ptr(), protein_peptide_map)
+
+
+def parsimony_protein_set(psms: List[Feature], db: IndexedDatabase) -> List[str]:
+    """The minimal set of proteins explaining the peptides of all target PSMs, found greedily
+
+    Args:
+        psms (List[Feature]): The PSMs, decoys are ignored
+        db (IndexedDatabase): The database the PSMs were scored against
+
+    Returns:
+        List[str]: The protein accessions, sorted
+    """
+    return psc.parsimony_protein_set([p.get_py_ptr() for p in psms], db.get_py_ptr())
+
+
+def annotate_protein_q_razor(psms: List[Feature], db: IndexedDatabase,
+                             peptide_q_cutoff: float = 0.01) -> List[Feature]:
+    """Set Feature.protein_q_razor to the q-value of the razor protein of each PSM (see protein_fdr)
+
+    Args:
+        psms (List[Feature]): The target and decoy PSMs, with peptide q-values
+        db (IndexedDatabase): The database the PSMs were scored against
+        peptide_q_cutoff (float, optional): The peptide q-value a peptide needs to count as evidence. Defaults to 0.01.
+
+    Returns:
+        List[Feature]: The PSMs, those of peptides failing the cutoff get a protein_q_razor of 1.0
+    """
+    result = psc.annotate_protein_q_razor([p.get_py_ptr() for p in psms], db.get_py_ptr(), peptide_q_cutoff)
+    return [Feature.from_py_feature(p) for p in result]
+
+
+def group_fdr(psms: List[Feature], strategy: GroupingStrategy, fdr_threshold: float = 0.01,
+              db: Optional[IndexedDatabase] = None) -> List[Feature]:
+    """Group-wise FDR control, target-decoy q-values are computed independently within each group
+    of PSMs (charge, peptide length bin or modified vs unmodified) and the passing PSMs are merged
+
+    Args:
+        psms (List[Feature]): The target and decoy PSMs, with discriminant scores
+        strategy (GroupingStrategy): The grouping of the PSMs
+        fdr_threshold (float, optional): The q-value threshold within each group. Defaults to 0.01.
+        db (Optional[IndexedDatabase], optional): The database the PSMs were scored against, required by
+            the modification strategy. Defaults to None.
+
+    Returns:
+        List[Feature]: The PSMs passing the threshold, with their group-wise spectrum q-values
+    """
+    result = psc.group_fdr([p.get_py_ptr() for p in psms], strategy.get_py_ptr(), fdr_threshold,
+                           db.get_py_ptr() if db is not None else None)
+    return [Feature.from_py_feature(p) for p in result]
+
+
+def randomize_modification_positions(localization_scores: List[List[Tuple[int, float]]],
+                                     seed: Optional[int] = None) -> List[Optional[Tuple[int, float]]]:
+    """Draw a decoy site for each PSM from its candidate sites other than the localized (best) one
+
+    Args:
+        localization_scores (List[List[Tuple[int, float]]]): The (position, score) candidate sites of each PSM,
+            e.g. of localize_modification
+        seed (Optional[int], optional): The seed of the draw. Defaults to None (fixed seed).
+
+    Returns:
+        List[Optional[Tuple[int, float]]]: The decoy site of each PSM, None for PSMs with a single candidate site
+    """
+    return psc.randomize_modification_positions(localization_scores, seed)
+
+
+def compute_ptm_site_fdr(localization_scores: List[List[Tuple[int, float]]], site_fdr: float = 0.01,
+                         seed: Optional[int] = None) -> List[List[Tuple[int, float, bool]]]:
+    """Site-level false localization rate, the best site of each PSM is a target and a randomly drawn other
+    candidate site a decoy
+
+    Args:
+        localization_scores (List[List[Tuple[int, float]]]): The (position, score) candidate sites of each PSM,
+            e.g. of localize_modification
+        site_fdr (float, optional): The maximum site q-value of localized sites. Defaults to 0.01.
+        seed (Optional[int], optional): The seed of the decoy site draw. Defaults to None (fixed seed).
+
+    Returns:
+        List[List[Tuple[int, float, bool]]]: The position, site q-value and whether it is localized of each site
+    """
+    return psc.compute_ptm_site_fdr(localization_scores, site_fdr, seed)
+
+
+def filter_localized_psms(psms: List[Feature], site_fdr: float = 0.01, seed: Optional[int] = None) -> List[Feature]:
+    """Keep PSMs whose best localization site passes the site-level false localization rate
+
+    Args:
+        psms (List[Feature]): The PSMs, localized by localize_modification
+        site_fdr (float, optional): The maximum site q-value. Defaults to 0.01.
+        seed (Optional[int], optional): The seed of the decoy site draw. Defaults to None (fixed seed).
+
+    Returns:
+        List[Feature]: The PSMs with a confidently localized site
+    """
+    result = psc.filter_localized_psms([p.get_py_ptr() for p in psms], site_fdr, seed)
+    return [Feature.from_py_feature(p) for p in result]
+
+
+class IdentificationStats:
+    def __init__(self):
+        raise NotImplementedError("IdentificationStats is created by compute_identification_rate")
+
+    @classmethod
+    def from_py_identification_stats(cls, stats: psc.PyIdentificationStats):
+        instance = cls.__new__(cls)
+        instance.__identification_stats_ptr = stats
+        return instance
+
+    @property
+    def total_spectra(self) -> int:
+        return self.__identification_stats_ptr.total_spectra
+
+    @property
+    def identified_spectra(self) -> int:
+        return self.__identification_stats_ptr.identified_spectra
+
+    @property
+    def identification_rate(self) -> float:
+        return self.__identification_stats_ptr.identification_rate
+
+    @property
+    def unique_peptides(self) -> int:
+        return self.__identification_stats_ptr.unique_peptides
+
+    @property
+    def unique_proteins(self) -> int:
+        return self.__identification_stats_ptr.unique_proteins
+
+    @property
+    def median_score(self) -> float:
+        return self.__identification_stats_ptr.median_score
+
+    def __repr__(self):
+        return f"IdentificationStats(total_spectra: {self.total_spectra}, " \
+               f"identified_spectra: {self.identified_spectra}, identification_rate: {self.identification_rate}, " \
+               f"unique_peptides: {self.unique_peptides}, unique_proteins: {self.unique_proteins}, " \
+               f"median_score: {self.median_score})"
+
+    def get_py_ptr(self):
+        return self.__identification_stats_ptr
+
+
+class IdentificationComparison:
+    def __init__(self):
+        raise NotImplementedError("IdentificationComparison is created by compare_runs")
+
+    @classmethod
+    def from_py_identification_comparison(cls, comparison: psc.PyIdentificationComparison):
+        instance = cls.__new__(cls)
+        instance.__identification_comparison_ptr = comparison
+        return instance
+
+    @property
+    def delta_identified_spectra(self) -> int:
+        return self.__identification_comparison_ptr.delta_identified_spectra
+
+    @property
+    def delta_identification_rate(self) -> float:
+        return self.__identification_comparison_ptr.delta_identification_rate
+
+    @property
+    def delta_unique_peptides(self) -> int:
+        return self.__identification_comparison_ptr.delta_unique_peptides
+
+    @property
+    def delta_unique_proteins(self) -> int:
+        return self.__identification_comparison_ptr.delta_unique_proteins
+
+    @property
+    def delta_median_score(self) -> float:
+        return self.__identification_comparison_ptr.delta_median_score
+
+    @property
+    def peptide_ratio(self) -> float:
+        return self.__identification_comparison_ptr.peptide_ratio
+
+    def __repr__(self):
+        return f"IdentificationComparison(delta_identified_spectra: {self.delta_identified_spectra}, " \
+               f"delta_identification_rate: {self.delta_identification_rate}, " \
+               f"delta_unique_peptides: {self.delta_unique_peptides}, " \
+               f"delta_unique_proteins: {self.delta_unique_proteins}, " \
+               f"delta_median_score: {self.delta_median_score}, peptide_ratio: {self.peptide_ratio})"
+
+    def get_py_ptr(self):
+        return self.__identification_comparison_ptr
+
+
+def compute_identification_rate(total_spectra: int, psms: List[Feature], db: IndexedDatabase,
+                                fdr_threshold: float = 0.01) -> IdentificationStats:
+    """Fraction of the acquired spectra identified by a target PSM passing the FDR threshold, with the unique
+    peptides and proteins and the median hyperscore of these PSMs
+
+    Args:
+        total_spectra (int): The number of acquired MS2 spectra
+        psms (List[Feature]): The PSMs
+        db (IndexedDatabase): The database the PSMs were scored against
+        fdr_threshold (float, optional): The maximum spectrum q-value. Defaults to 0.01.
+
+    Returns:
+        IdentificationStats: The identification statistics
+    """
+    return IdentificationStats.from_py_identification_stats(
+        psc.compute_identification_rate(total_spectra, [p.get_py_ptr() for p in psms], fdr_threshold,
+                                        db.get_py_ptr()))
+
+
+def identification_rate_over_rt(psms: List[Feature], n_bins: int = 20,
+                                fdr_threshold: float = 0.01) -> List[Tuple[float, float]]:
+    """Identification rate in retention time bins, spectra without any PSM are not counted
+
+    Args:
+        psms (List[Feature]): The PSMs
+        n_bins (int, optional): The number of equal width retention time bins. Defaults to 20.
+        fdr_threshold (float, optional): The maximum spectrum q-value. Defaults to 0.01.
+
+    Returns:
+        List[Tuple[float, float]]: The bin center and the fraction of its spectra identified
+    """
+    return psc.identification_rate_over_rt([p.get_py_ptr() for p in psms], n_bins, fdr_threshold)
+
+
+def compare_runs(stats_a: IdentificationStats, stats_b: IdentificationStats) -> IdentificationComparison:
+    """Compare the identification statistics of two runs
+
+    Args:
+        stats_a (IdentificationStats): The statistics of the first run
+        stats_b (IdentificationStats): The statistics of the second run
+
+    Returns:
+        IdentificationComparison: The differences of the second to the first run
+    """
+    return IdentificationComparison.from_py_identification_comparison(
+        psc.compare_runs(stats_a.get_py_ptr(), stats_b.get_py_ptr()))
+
+
+class ProteinGroup:
+    def __init__(self):
+        raise NotImplementedError("ProteinGroup is created by infer_protein_groups")
+
+    @classmethod
+    def from_py_protein_group(cls, group: psc.PyProteinGroup):
+        instance = cls.__new__(cls)
+        instance.__protein_group_ptr = group
+        return instance
+
+    @property
+    def representative(self) -> str:
+        return self.__protein_group_ptr.representative
+
+    @property
+    def members(self) -> List[str]:
+        return self.__protein_group_ptr.members
+
+    @property
+    def shared_peptides(self) -> List[str]:
+        return self.__protein_group_ptr.shared_peptides
+
+    @property
+    def unique_peptides(self) -> List[str]:
+        return self.__protein_group_ptr.unique_peptides
+
+    @property
+    def group_q_value(self) -> float:
+        return self.__protein_group_ptr.group_q_value
+
+    @property
+    def score(self) -> float:
+        return self.__protein_group_ptr.score
+
+    @property
+    def decoy(self) -> bool:
+        return self.__protein_group_ptr.decoy
+
+    def __repr__(self):
+        return f"ProteinGroup(representative: {self.representative}, members: {self.members}, " \
+               f"shared_peptides: {len(self.shared_peptides)}, unique_peptides: {len(self.unique_peptides)}, " \
+               f"group_q_value: {self.group_q_value}, score: {self.score}, decoy: {self.decoy})"
+
+    def get_py_ptr(self):
+        return self.__protein_group_ptr
+
+
+def infer_protein_groups(psms: List[Feature], db: IndexedDatabase, fdr_threshold: float = 0.01,
+                         share_threshold: float = 0.5) -> List[ProteinGroup]:
+    """Group proteins sharing most of their identified peptides, e.g. isoforms of a protein family
+
+    Args:
+        psms (List[Feature]): The PSMs
+        db (IndexedDatabase): The database the PSMs were scored against
+        fdr_threshold (float, optional): The maximum spectrum q-value of a PSM. Defaults to 0.01.
+        share_threshold (float, optional): Two proteins are grouped if their shared peptides are more than
+            this fraction of the peptides of the smaller one. Defaults to 0.5.
+
+    Returns:
+        List[ProteinGroup]: The protein groups by descending score, without q-values (see protein_group_fdr)
+    """
+    return [ProteinGroup.from_py_protein_group(g) for g in
+            psc.infer_protein_groups([p.get_py_ptr() for p in psms], db.get_py_ptr(), fdr_threshold,
+                                     share_threshold)]
+
+
+def protein_group_fdr(groups: List[ProteinGroup]) -> List[ProteinGroup]:
+    """Group-level q-values by target-decoy competition on the group scores
+
+    Args:
+        groups (List[ProteinGroup]): The protein groups, targets and decoys
+
+    Returns:
+        List[ProteinGroup]: The protein groups with their group_q_value set
+    """
+    return [ProteinGroup.from_py_protein_group(g) for g in
+            psc.protein_group_fdr([g.get_py_ptr() for g in groups])]
+
+
+def export_protein_groups_tsv(groups: List[ProteinGroup], path: str) -> None:
+    """Write protein groups as a tab separated table, list columns are separated by ';'
+
+    Args:
+        groups (List[ProteinGroup]): The protein groups
+        path (str): The output path
+    """
+    psc.export_protein_groups_tsv([g.get_py_ptr() for g in groups], path)
@@ -0,0 +1,65 @@
+from typing import List
+
+import sagepy_connector
+
+psc = sagepy_connector.py_intensity
+
+
+def spectral_angle_similarity(observed: List[float], predicted: List[float], epsilon: float = 1e-7,
+                              normalize: bool = True) -> float:
+    """Normalized spectral contrast angle of observed and predicted fragment intensities, 1.0 for identical
+    and 0.0 for orthogonal intensity vectors
+
+    Args:
+        observed (List[float]): The observed intensities
+        predicted (List[float]): The predicted intensities of the same fragments
+        epsilon (float, optional): Added to the vector norms to guard against empty vectors. Defaults to 1e-7.
+        normalize (bool, optional): Whether to scale both vectors to unit L2 norm, otherwise they are taken
+            to be normalized already. Defaults to True.
+
+    Returns:
+        float: The spectral angle similarity
+    """
+    return psc.spectral_angle_similarity(observed, predicted, epsilon, normalize)
+
+
+def batch_spectral_angle(observed_list: List[List[float]], predicted_list: List[List[float]],
+                         epsilon: float = 1e-7, num_threads: int = 4) -> List[float]:
+    """Spectral angle similarity of many pairs of intensity vectors in parallel, see spectral_angle_similarity
+
+    Args:
+        observed_list (List[List[float]]): The observed intensities
+        predicted_list (List[List[float]]): The predicted intensities, pairing with observed_list
+        epsilon (float, optional): Added to the vector norms to guard against empty vectors. Defaults to 1e-7.
+        num_threads (int, optional): The number of threads. Defaults to 4.
+
+    Returns:
+        List[float]: The spectral angle similarity of each pair
+    """
+    return psc.batch_spectral_angle(observed_list, predicted_list, epsilon, num_threads)
+
+
+def pearson_correlation(a: List[float], b: List[float]) -> float:
+    """Pearson correlation of two intensity vectors
+
+    Args:
+        a (List[float]): The first intensities
+        b (List[float]): The second intensities
+
+    Returns:
+        float: The correlation, 0.0 if either vector is constant
+    """
+    return psc.pearson_correlation(a, b)
+
+
+def spearman_correlation(a: List[float], b: List[float]) -> float:
+    """Spearman rank correlation of two intensity vectors, ties share their mean rank
+
+    Args:
+        a (List[float]): The first intensities
+        b (List[float]): The second intensities
+
+    Returns:
+        float: The correlation, 0.0 if either vector is constant
+    """
+    return psc.spearman_correlation(a, b)
@@ -0,0 +1,64 @@
+from typing import List, Optional
+
+import sagepy_connector
+from sagepy.core.database import IndexedDatabase
+from sagepy.core.scoring import Feature
+
+psc = sagepy_connector.py_io
+
+
+def read_diann_report(path: str) -> List[Feature]:
+    """Read a DIA-NN report.tsv into PSMs, Q.Value is mapped to spectrum_q, Global.Q.Value to peptide_q,
+    PG.Q.Value to protein_q, CScore to discriminant_score and Precursor.Quantity to ms2_intensity,
+    the precursor id (modified sequence and charge) is stored as spec_id. The PSMs do not refer to a database
+    peptide, Modified.Sequence is kept in UNIMOD bracket notation as peptide_sequence
+
+    Args:
+        path (str): The path to the DIA-NN report
+
+    Returns:
+        List[Feature]: The PSMs
+    """
+    return [Feature.from_py_feature(f) for f in psc.read_diann_report(path)]
+
+
+def diann_to_unimod_sequence(modified_sequence: str) -> str:
+    """Convert a DIA-NN modified sequence, e.g. PEPC(UniMod:4)TIDE, to UNIMOD bracket notation, e.g. PEPC[UNIMOD:4]TIDE
+
+    Args:
+        modified_sequence (str): The DIA-NN modified sequence
+
+    Returns:
+        str: The sequence in UNIMOD bracket notation
+    """
+    return psc.diann_to_unimod_sequence(modified_sequence)
+
+
+def write_pin_file(features: List[Feature], path: str, feature_names: Optional[List[str]] = None,
+                   db: Optional[IndexedDatabase] = None) -> None:
+    """Write PSMs as Percolator input (PIN) file with columns SpecId, Label, ScanNr, features, Peptide, Proteins
+
+    Args:
+        features (List[Feature]): The PSMs
+        path (str): The path of the PIN file
+        feature_names (Optional[List[str]], optional): The re-scoring features to write, see
+            Feature.get_feature_names. Defaults to None (all features).
+        db (Optional[IndexedDatabase], optional): The database to resolve peptides and proteins, every PSM must
+            refer to one of its peptides. Without it, the imported peptide sequence or else the peptide index is
+            written in place of the peptide. Defaults to None.
+    """
+    psc.write_pin_file([f.get_py_ptr() for f in features], path, feature_names if feature_names is not None else [],
+                       db.get_py_ptr() if db is not None else None)
+
+
+def read_pin_file(path: str) -> List[Feature]:
+    """Read PSMs from a Percolator input (PIN) file, recovering SpecId, Label, ScanNr and all re-scoring features.
+    A Peptide column holding sequences is kept as peptide_sequence, the PSMs then refer to no database peptide
+
+    Args:
+        path (str): The path of the PIN file
+
+    Returns:
+        List[Feature]: The PSMs
+    """
+    return [Feature.from_py_feature(f) for f in psc.read_pin_file(path)]
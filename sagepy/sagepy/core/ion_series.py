@@ -1,3 +1,5 @@
+from typing import List
+
 import sagepy_connector
 
 from sagepy.core.peptide import Peptide
@@ -10,12 +12,12 @@ class IonType:
         """IonType class
 
         Args:
-            ion_type (str): The ion type, allowed values are: a, b, c, x, y, z
+            ion_type (str): The ion type, allowed values are: a, b, c, x, y, z (z-dot)
         """
         try:
             self.__ion_type_ptr = psc.PyKind(ion_type)
         except ValueError:
-            raise ValueError("Invalid ion type, allowed values are: a, b, c, x, y, z")
+            raise ValueError("Invalid ion type, allowed values are: a, b, c, x, y, z, z-dot")
 
     @classmethod
     def from_py_kind(cls, kind: psc.PyKind):
@@ -31,6 +33,14 @@ class IonType:
     def b(cls):
         return cls.from_py_kind(psc.PyKind('b'))
 
+    @classmethod
+    def c(cls):
+        return cls.from_py_kind(psc.PyKind('c'))
+
+    @classmethod
+    def z_dot(cls):
+        return cls.from_py_kind(psc.PyKind('z-dot'))
+
     def __repr__(self):
         return f"IonType({self.__ion_type_ptr.kind_as_string()})"
 
@@ -44,9 +54,10 @@ class Ion:
     Args:
         ion_type (IonType): The ion type, e.g. b, y
         mass (float): The mass of the ion
+        neutral_loss (float, optional): The mass lost from the regular ion. Defaults to 0.0.
     """
-    def __init__(self, ion_type: IonType, mass: float):
-        self.__ion_ptr = psc.PyIon(ion_type.get_py_ptr(), mass)
+    def __init__(self, ion_type: IonType, mass: float, neutral_loss: float = 0.0):
+        self.__ion_ptr = psc.PyIon(ion_type.get_py_ptr(), mass, neutral_loss)
 
     @classmethod
     def from_py_ion(cls, ion: psc.PyIon):
@@ -62,8 +73,15 @@ class Ion:
     def mono_isotopic_mass(self):
         return self.__ion_ptr.monoisotopic_mass
 
+    @property
+    def neutral_loss(self):
+        return self.__ion_ptr.neutral_loss
+
+    def get_py_ptr(self):
+        return self.__ion_ptr
+
     def __repr__(self):
-        return f"Ion({self.ion_type}, {self.mono_isotopic_mass})"
+        return f"Ion({self.ion_type}, {self.mono_isotopic_mass}, neutral_loss={self.neutral_loss})"
 
 
 class IonSeries:
@@ -101,4 +119,34 @@ class IonSeries:
         return self.__ion_series_ptr
 
     def get_ion_series(self):
-        return [Ion.from_py_ion(i) for i in self.__ion_series_ptr.get_ion_series()]
\ No newline at end of file
+        return [Ion.from_py_ion(i) for i in self.__ion_series_ptr.get_ion_series()]
+
+    def with_neutral_losses(self, b_loss_water: bool = True, b_loss_nh3: bool = True, y_loss_water: bool = True,
+                            y_loss_nh3: bool = True) -> List[Ion]:
+        """Get the ions of the series followed by their water and ammonia loss ions
+
+        Args:
+            b_loss_water (bool, optional): Generate water losses if this is a b series. Defaults to True.
+            b_loss_nh3 (bool, optional): Generate ammonia losses if this is a b series. Defaults to True.
+            y_loss_water (bool, optional): Generate water losses if this is a y series. Defaults to True.
+            y_loss_nh3 (bool, optional): Generate ammonia losses if this is a y series. Defaults to True.
+
+        Returns:
+            List[Ion]: The ions, loss ions carry their loss in Ion.neutral_loss
+        """
+        return [Ion.from_py_ion(i) for i in
+                self.__ion_series_ptr.with_neutral_losses(b_loss_water, b_loss_nh3, y_loss_water, y_loss_nh3)]
+
+
+def generate_neutral_loss_ions(base_ions: List[Ion], losses: List[float]) -> List[Ion]:
+    """Generate the neutral loss variants of ions, e.g. water (18.0106 Da) and ammonia (17.0265 Da) losses
+
+    Args:
+        base_ions (List[Ion]): The ions
+        losses (List[float]): The losses in Da
+
+    Returns:
+        List[Ion]: One ion per ion and loss, ordered by loss
+    """
+    return [Ion.from_py_ion(i) for i in
+            psc.generate_neutral_loss_ions([i.get_py_ptr() for i in base_ions], losses)]
\ No newline at end of file
@@ -1,5 +1,9 @@
-from typing import Optional, List
-from sagepy.core.database import PeptideIx
+from typing import Optional, List, Dict
+from sagepy.core.database import PeptideIx, IndexedDatabase
+from sagepy.core.enzyme import Enzyme
+from sagepy.core.fasta import Fasta
+from sagepy.core.scoring import Feature
+from sagepy.core.spectrum import ProcessedSpectrum
 import sagepy_connector
 psc = sagepy_connector.py_lfq
 
@@ -258,3 +262,396 @@ class Query:
     def __repr__(self):
         return f"Query(num_ranges: {self.get_num_ranges()}, page_lo: {self.page_lo}, page_hi: {self.page_hi}, " \
                 f"bin_size: {self.bin_size}, min_rt: {self.min_rt}, max_rt: {self.max_rt})"
+
+
+class FoldChangeResult:
+    @classmethod
+    def from_py_fold_change_result(cls, result: psc.PyFoldChangeResult):
+        instance = cls.__new__(cls)
+        instance.__fold_change_result_ptr = result
+        return instance
+
+    def get_py_ptr(self):
+        return self.__fold_change_result_ptr
+
+    @property
+    def log2_fc(self) -> float:
+        return self.__fold_change_result_ptr.log2_fc
+
+    @property
+    def t_statistic(self) -> float:
+        return self.__fold_change_result_ptr.t_statistic
+
+    @property
+    def p_value(self) -> float:
+        return self.__fold_change_result_ptr.p_value
+
+    @property
+    def adjusted_p_value(self) -> float:
+        return self.__fold_change_result_ptr.adjusted_p_value
+
+    def __repr__(self):
+        return f"FoldChangeResult(log2_fc: {self.log2_fc}, t_statistic: {self.t_statistic}, " \
+               f"p_value: {self.p_value}, adjusted_p_value: {self.adjusted_p_value})"
+
+
+def fold_change_analysis(group_a_intensities: List[Optional[float]], group_b_intensities: List[Optional[float]],
+                         min_valid_values: int = 2) -> FoldChangeResult:
+    """Test a protein for differential abundance between two groups using Welch's t-test on log2 intensities
+
+    Args:
+        group_a_intensities (List[Optional[float]]): The intensities of group A, None for missing values
+        group_b_intensities (List[Optional[float]]): The intensities of group B, None for missing values
+        min_valid_values (int, optional): The minimum number of valid values per group. Defaults to 2.
+
+    Returns:
+        FoldChangeResult: The result, log2_fc is log2(B / A)
+    """
+    return FoldChangeResult.from_py_fold_change_result(
+        psc.fold_change_analysis(group_a_intensities, group_b_intensities, min_valid_values))
+
+
+def batch_fold_change(protein_intensities: Dict[str, List[Optional[float]]], group_a_indices: List[int],
+                      group_b_indices: List[int]) -> Dict[str, FoldChangeResult]:
+    """Test all proteins for differential abundance, p-values are adjusted using Benjamini-Hochberg FDR
+
+    Args:
+        protein_intensities (Dict[str, List[Optional[float]]]): The intensities per protein, one value per sample
+        group_a_indices (List[int]): The sample indices of group A
+        group_b_indices (List[int]): The sample indices of group B
+
+    Returns:
+        Dict[str, FoldChangeResult]: The result per protein
+    """
+    result = psc.batch_fold_change(protein_intensities, group_a_indices, group_b_indices)
+    return {k: FoldChangeResult.from_py_fold_change_result(v) for k, v in result.items()}
+
+
+def compute_ibaq(protein_intensities: Dict[str, float], fasta: Fasta, enzyme: Enzyme,
+                 min_len: int = 6, max_len: int = 30, normalize: bool = False) -> Dict[str, float]:
+    """Intensity-based absolute quantification, the protein intensity divided by its number of theoretically
+    observable peptides (distinct peptides of an in-silico digest without missed cleavages within the length bounds)
+
+    Args:
+        protein_intensities (Dict[str, float]): The summed peptide intensity per protein
+        fasta (Fasta): The FASTA the proteins were identified with, its target proteins are digested
+        enzyme (Enzyme): The enzyme defining full cleavage
+        min_len (int, optional): The minimum length of an observable peptide. Defaults to 6.
+        max_len (int, optional): The maximum length of an observable peptide. Defaults to 30.
+        normalize (bool, optional): Scale the values to sum to 1e9 (riBAQ). Defaults to False.
+
+    Returns:
+        Dict[str, float]: The iBAQ value per protein, proteins without observable peptides or missing from the
+            FASTA are omitted
+    """
+    return psc.compute_ibaq(protein_intensities, fasta.get_py_ptr(), enzyme.get_py_ptr(), min_len, max_len,
+                            normalize)
+
+
+def maxlfq(peptide_intensities: Dict[str, List[Optional[float]]], peptide_proteins: Dict[str, str],
+           run_names: List[str], min_peptides_per_protein: int = 2) -> Dict[str, List[Optional[float]]]:
+    """MaxLFQ protein quantification (Cox et al., 2014): pairwise run ratios are the median log-ratio of shared
+    peptides and protein intensities across runs are their least-squares solution, scaled to the summed peptide
+    intensity
+
+    Args:
+        peptide_intensities (Dict[str, List[Optional[float]]]): The intensity per run of each peptide, None for missing
+        peptide_proteins (Dict[str, str]): The protein of each peptide, peptides without protein are ignored
+        run_names (List[str]): The names of the runs, in the order of the intensities
+        min_peptides_per_protein (int, optional): The minimum number of peptides of a protein, also the minimum
+            number of shared peptides of a pairwise ratio. Defaults to 2.
+
+    Returns:
+        Dict[str, List[Optional[float]]]: The intensity per run of each protein, None for runs without data
+    """
+    return psc.maxlfq(peptide_intensities, peptide_proteins, run_names, min_peptides_per_protein)
+
+
+def direct_lfq(ion_intensities: Dict[str, List[Optional[float]]], ion_proteins: Dict[str, str],
+               run_names: List[str], min_ions_per_protein: int = 1) -> Dict[str, List[Optional[float]]]:
+    """directLFQ protein quantification (Ammar et al., 2023): the log intensity trace of each ion is shifted onto
+    the median trace of the already normalized ions of its protein, starting with the most complete ion, and the
+    protein intensity of a run is the median of the shifted ions. Avoids the pairwise run ratios of MaxLFQ
+
+    Args:
+        ion_intensities (Dict[str, List[Optional[float]]]): The intensity per run of each ion, None for missing
+        ion_proteins (Dict[str, str]): The protein of each ion, ions without protein are ignored
+        run_names (List[str]): The names of the runs, in the order of the intensities
+        min_ions_per_protein (int, optional): The minimum number of ions of a protein. Defaults to 1.
+
+    Returns:
+        Dict[str, List[Optional[float]]]: The intensity per run of each protein, None for runs without data
+    """
+    return psc.direct_lfq(ion_intensities, ion_proteins, run_names, min_ions_per_protein)
+
+
+def quantify_direct_lfq(features: List[Feature], database: IndexedDatabase,
+                        min_ions_per_protein: int = 1) -> Dict[str, Dict[int, float]]:
+    """directLFQ on PSMs, ions are the (peptide, charge) precursors of target PSMs quantified by their highest
+    ms2_intensity per run (file_id), shared peptides count for their razor protein
+
+    Args:
+        features (List[Feature]): The PSMs of all runs
+        database (IndexedDatabase): The database the PSMs were identified with
+        min_ions_per_protein (int, optional): The minimum number of ions of a protein. Defaults to 1.
+
+    Returns:
+        Dict[str, Dict[int, float]]: The intensity per protein and run
+    """
+    return psc.quantify_direct_lfq([f.get_py_ptr() for f in features], database.get_py_ptr(), min_ions_per_protein)
+
+
+def top_n_quantification(features: List[Feature], database: IndexedDatabase, n: int = 3,
+                         use_unique_peptides: bool = True, aggregation: str = 'mean',
+                         normalize: bool = False) -> Dict[str, float]:
+    """Top-N protein quantification, the n most intense peptides of each protein are aggregated. Peptide
+    intensities are the highest ms2_intensity of their target PSMs
+
+    Args:
+        features (List[Feature]): The PSMs
+        database (IndexedDatabase): The database the PSMs were identified with
+        n (int, optional): The number of peptides per protein. Defaults to 3.
+        use_unique_peptides (bool, optional): Count shared peptides only for their razor protein (the protein
+            with the most identified peptides). Defaults to True.
+        aggregation (str, optional): The aggregation, 'sum', 'mean' or 'median'. Defaults to 'mean'.
+        normalize (bool, optional): Divide by the median protein intensity. Defaults to False.
+
+    Returns:
+        Dict[str, float]: The intensity per protein
+    """
+    return psc.top_n_quantification([f.get_py_ptr() for f in features], database.get_py_ptr(), n,
+                                    use_unique_peptides, aggregation, normalize)
+
+
+def top_n_quantification_per_run(features: List[Feature], database: IndexedDatabase, n: int = 3,
+                                 use_unique_peptides: bool = True, aggregation: str = 'mean',
+                                 normalize: bool = False) -> Dict[str, Dict[int, float]]:
+    """Top-N protein quantification per run (file_id), see top_n_quantification
+
+    Args:
+        features (List[Feature]): The PSMs
+        database (IndexedDatabase): The database the PSMs were identified with
+        n (int, optional): The number of peptides per protein. Defaults to 3.
+        use_unique_peptides (bool, optional): Count shared peptides only for their razor protein. Defaults to True.
+        aggregation (str, optional): The aggregation, 'sum', 'mean' or 'median'. Defaults to 'mean'.
+        normalize (bool, optional): Scale the runs to a common median protein intensity. Defaults to False.
+
+    Returns:
+        Dict[str, Dict[int, float]]: The intensity per protein and run
+    """
+    return psc.top_n_quantification_per_run([f.get_py_ptr() for f in features], database.get_py_ptr(), n,
+                                            use_unique_peptides, aggregation, normalize)
+
+
+def top3_intensity(features: List[Feature], database: IndexedDatabase) -> Dict[str, float]:
+    """Top3 quantification, the mean intensity of the three most intense razor peptides of each protein
+
+    Args:
+        features (List[Feature]): The PSMs
+        database (IndexedDatabase): The database the PSMs were identified with
+
+    Returns:
+        Dict[str, float]: The intensity per protein
+    """
+    return psc.top3_intensity([f.get_py_ptr() for f in features], database.get_py_ptr())
+
+
+def normalize_intensities(intensities: List[List[float]], normalization_method: str = 'median') -> List[List[float]]:
+    """Normalize a run intensity matrix
+
+    Args:
+        intensities (List[List[float]]): The intensities, one row per peptide or protein and one column per run,
+            0.0 for missing values
+        normalization_method (str, optional): 'none', 'median' (equal run medians), 'quantile' (equal run
+            intensity distributions) or 'vsn' (variance stabilizing transformation). Defaults to 'median'.
+
+    Returns:
+        List[List[float]]: The normalized intensities, missing values stay 0.0
+    """
+    return psc.normalize_intensities(intensities, normalization_method)
+
+
+def median_centering_normalization(intensities: List[List[Optional[float]]]) -> List[List[Optional[float]]]:
+    """Divide the intensities of each run by the median of its observed values
+
+    Args:
+        intensities (List[List[Optional[float]]]): The intensities, one row per peptide or protein and one column
+            per run, None for missing values
+
+    Returns:
+        List[List[Optional[float]]]: The normalized intensities, missing values stay None
+    """
+    return psc.median_centering_normalization(intensities)
+
+
+def quantile_normalization(intensities: List[List[Optional[float]]]) -> List[List[Optional[float]]]:
+    """Quantile normalize the observed intensities of the runs to a common distribution
+
+    Args:
+        intensities (List[List[Optional[float]]]): The intensities, one row per peptide or protein and one column
+            per run, None for missing values
+
+    Returns:
+        List[List[Optional[float]]]: The normalized intensities, missing values stay None
+    """
+    return psc.quantile_normalization(intensities)
+
+
+def loess_normalization(intensities: List[List[Optional[float]]], reference_run: int = 0,
+                        bandwidth: float = 0.4) -> List[List[Optional[float]]]:
+    """Intensity dependent normalization of each run to a reference run, by a LOESS fit of the log2 ratio on the
+    mean log2 intensity (MA plot) of the rows observed in both runs
+
+    Args:
+        intensities (List[List[Optional[float]]]): The intensities, one row per peptide or protein and one column
+            per run, None for missing values
+        reference_run (int, optional): The column of the reference run. Defaults to 0.
+        bandwidth (float, optional): The LOESS bandwidth, the fraction of rows in each local fit. Defaults to 0.4.
+
+    Returns:
+        List[List[Optional[float]]]: The normalized intensities, missing values stay None
+    """
+    return psc.loess_normalization(intensities, reference_run, bandwidth)
+
+
+def normalize_lfq_intensities(intensities: List[List[Optional[float]]], method: str = 'median',
+                              reference_run: int = 0, bandwidth: float = 0.4) -> List[List[Optional[float]]]:
+    """Normalize intensities of multiple LFQ runs
+
+    Args:
+        intensities (List[List[Optional[float]]]): The intensities, one row per peptide or protein and one column
+            per run, None for missing values
+        method (str, optional): 'median' (median_centering_normalization), 'quantile' (quantile_normalization)
+            or 'loess' (loess_normalization). Defaults to 'median'.
+        reference_run (int, optional): The reference run of 'loess'. Defaults to 0.
+        bandwidth (float, optional): The LOESS bandwidth of 'loess'. Defaults to 0.4.
+
+    Returns:
+        List[List[Optional[float]]]: The normalized intensities, missing values stay None
+    """
+    return psc.normalize_lfq_intensities(intensities, method, reference_run, bandwidth)
+
+
+class SilacConfig:
+    """SilacConfig class
+
+    Args:
+        heavy_lys (float, optional): The mass offset of heavy lysine. Defaults to 8.014199 (13C6 15N2).
+        heavy_arg (float, optional): The mass offset of heavy arginine. Defaults to 10.008269 (13C6 15N4).
+        medium_lys (Optional[float], optional): The mass offset of medium lysine, e.g. 4.025107 (D4). Defaults to None.
+        medium_arg (Optional[float], optional): The mass offset of medium arginine, e.g. 6.020129 (13C6).
+            Defaults to None.
+        ppm_tolerance (float, optional): The MS1 extraction tolerance in ppm. Defaults to 10.0.
+        rt_window (float, optional): The retention time window around the PSM to integrate. Defaults to 0.5.
+        fdr_cutoff (float, optional): The spectrum q-value cutoff of quantified PSMs. Defaults to 0.01.
+        normalize (bool, optional): Divide ratios by the global median ratio. Defaults to True.
+        impute_missing (bool, optional): Interpolate scans without signal from neighbouring scans. Defaults to True.
+    """
+    def __init__(self, heavy_lys: float = 8.014199, heavy_arg: float = 10.008269,
+                 medium_lys: Optional[float] = None, medium_arg: Optional[float] = None,
+                 ppm_tolerance: float = 10.0, rt_window: float = 0.5, fdr_cutoff: float = 0.01,
+                 normalize: bool = True, impute_missing: bool = True):
+        self.__silac_config_ptr = psc.PySilacConfig(heavy_lys, heavy_arg, medium_lys, medium_arg, ppm_tolerance,
+                                                    rt_window, fdr_cutoff, normalize, impute_missing)
+
+    @classmethod
+    def from_py_silac_config(cls, silac_config: psc.PySilacConfig):
+        instance = cls.__new__(cls)
+        instance.__silac_config_ptr = silac_config
+        return instance
+
+    @property
+    def heavy_lys(self) -> float:
+        return self.__silac_config_ptr.heavy_lys
+
+    @property
+    def heavy_arg(self) -> float:
+        return self.__silac_config_ptr.heavy_arg
+
+    @property
+    def medium_lys(self) -> Optional[float]:
+        return self.__silac_config_ptr.medium_lys
+
+    @property
+    def medium_arg(self) -> Optional[float]:
+        return self.__silac_config_ptr.medium_arg
+
+    @property
+    def ppm_tolerance(self) -> float:
+        return self.__silac_config_ptr.ppm_tolerance
+
+    @property
+    def rt_window(self) -> float:
+        return self.__silac_config_ptr.rt_window
+
+    @property
+    def fdr_cutoff(self) -> float:
+        return self.__silac_config_ptr.fdr_cutoff
+
+    @property
+    def normalize(self) -> bool:
+        return self.__silac_config_ptr.normalize
+
+    @property
+    def impute_missing(self) -> bool:
+        return self.__silac_config_ptr.impute_missing
+
+    def __repr__(self):
+        return f"SilacConfig(heavy_lys: {self.heavy_lys}, heavy_arg: {self.heavy_arg}, " \
+               f"medium_lys: {self.medium_lys}, medium_arg: {self.medium_arg}, " \
+               f"ppm_tolerance: {self.ppm_tolerance}, rt_window: {self.rt_window}, " \
+               f"fdr_cutoff: {self.fdr_cutoff}, normalize: {self.normalize}, impute_missing: {self.impute_missing})"
+
+    def get_py_ptr(self):
+        return self.__silac_config_ptr
+
+
+class SilacRatio:
+    @classmethod
+    def from_py_silac_ratio(cls, silac_ratio: psc.PySilacRatio):
+        instance = cls.__new__(cls)
+        instance.__silac_ratio_ptr = silac_ratio
+        return instance
+
+    def get_py_ptr(self):
+        return self.__silac_ratio_ptr
+
+    @property
+    def protein(self) -> str:
+        return self.__silac_ratio_ptr.protein
+
+    @property
+    def ratio_h_l(self) -> float:
+        return self.__silac_ratio_ptr.ratio_h_l
+
+    @property
+    def ratio_m_l(self) -> Optional[float]:
+        return self.__silac_ratio_ptr.ratio_m_l
+
+    @property
+    def count(self) -> int:
+        return self.__silac_ratio_ptr.count
+
+    def __repr__(self):
+        return f"SilacRatio(protein: {self.protein}, ratio_h_l: {self.ratio_h_l}, " \
+               f"ratio_m_l: {self.ratio_m_l}, count: {self.count})"
+
+
+def quantify_silac(psms: List[Feature], database: IndexedDatabase, config: SilacConfig,
+                   spectra: List[ProcessedSpectrum]) -> Dict[str, SilacRatio]:
+    """Quantify SILAC heavy/light (and medium/light) protein ratios from MS1 extracted ion chromatograms. PSMs of
+    the label states of a peptide are paired and quantified once per run and charge, protein ratios are the median
+    peptide ratios
+
+    Args:
+        psms (List[Feature]): The scored PSMs, the label state is inferred from their K/R modifications
+        database (IndexedDatabase): The database the PSMs were identified with
+        config (SilacConfig): The label offsets and extraction settings
+        spectra (List[ProcessedSpectrum]): The processed spectra, MS1 scans are used for extraction
+
+    Returns:
+        Dict[str, SilacRatio]: The ratios per protein (shared peptides are keyed by ';'-joined proteins)
+    """
+    result = psc.quantify_silac([psm.get_py_ptr() for psm in psms], database.get_py_ptr(), config.get_py_ptr(),
+                                [spectrum.get_py_ptr() for spectrum in spectra])
+    return {k: SilacRatio.from_py_silac_ratio(v) for k, v in result.items()}
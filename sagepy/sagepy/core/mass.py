@@ -1,4 +1,4 @@
-from typing import List
+from typing import Dict, List, Tuple
 
 import sagepy_connector
 psc = sagepy_connector.py_mass
@@ -122,3 +122,44 @@ class Tolerance:
 
         else:
             raise ValueError("Tolerance can only be multiplied by a float or an int")
+
+
+def averagine_isotope_pattern(mass: float, num_isotopes: int = 5) -> List[Tuple[float, float]]:
+    """Compute the isotope pattern of a neutral mass following the averagine model
+
+    Args:
+        mass (float): The neutral monoisotopic mass
+        num_isotopes (int, optional): The number of isotope peaks. Defaults to 5.
+
+    Returns:
+        List[Tuple[float, float]]: (mass offset, intensity relative to the most intense peak) pairs
+    """
+    return psc.averagine_isotope_pattern(mass, num_isotopes)
+
+
+def exact_isotope_pattern(formula: Dict[str, int], charge: int = 1,
+                          num_isotopes: int = 5) -> List[Tuple[float, float]]:
+    """Compute the isotope pattern of an elemental formula by convolving the element isotope distributions
+
+    Args:
+        formula (Dict[str, int]): The atom count per element, e.g. {'C': 50, 'H': 80, 'N': 14, 'O': 15}
+        charge (int, optional): The charge, used to scale the m/z offsets. Defaults to 1.
+        num_isotopes (int, optional): The number of isotope peaks. Defaults to 5.
+
+    Returns:
+        List[Tuple[float, float]]: (m/z offset, intensity relative to the most intense peak) pairs
+    """
+    return psc.exact_isotope_pattern(formula, charge, num_isotopes)
+
+
+def compare_isotope_patterns(observed: List[float], theoretical: List[float]) -> float:
+    """Compute the cosine similarity of an observed and a theoretical isotope pattern
+
+    Args:
+        observed (List[float]): The observed isotope intensities
+        theoretical (List[float]): The theoretical isotope intensities
+
+    Returns:
+        float: The similarity, between 0 and 1
+    """
+    return psc.compare_isotope_patterns(observed, theoretical)
@@ -0,0 +1,139 @@
+from typing import Optional, List, Dict, Tuple
+
+import sagepy_connector
+from sagepy.core.database import IndexedDatabase
+from sagepy.core.scoring import Feature
+
+psc = sagepy_connector.py_mobility_model
+
+
+class MobilityModel:
+    """Linear ion mobility (1/K0) model on amino acid composition, length, modification mass and charge"""
+    def __init__(self):
+        raise NotImplementedError("MobilityModel is created by MobilityModel.fit")
+
+    @classmethod
+    def from_py_mobility_model(cls, mobility_model: psc.PyMobilityModel):
+        instance = cls.__new__(cls)
+        instance.__mobility_model_ptr = mobility_model
+        return instance
+
+    @classmethod
+    def fit(cls, sequences: List[str], charges: List[int], inverse_ion_mobilities: List[float],
+            modifications: Optional[List[Dict[int, float]]] = None, ridge: float = 1e-3) -> 'MobilityModel':
+        """Fit an ion mobility model
+
+        Args:
+            sequences (List[str]): The peptide sequences
+            charges (List[int]): The precursor charge of each sequence
+            inverse_ion_mobilities (List[float]): The observed inverse ion mobility (1/K0) of each sequence
+            modifications (Optional[List[Dict[int, float]]], optional): Residue position to mass shift of each
+                sequence. Defaults to None.
+            ridge (float, optional): The ridge penalty. Defaults to 1e-3.
+
+        Returns:
+            MobilityModel: The fitted model
+        """
+        return cls.from_py_mobility_model(
+            psc.PyMobilityModel.fit(sequences, charges, inverse_ion_mobilities, modifications, ridge))
+
+    @property
+    def coefficients(self) -> List[float]:
+        """Coefficients of the amino acids ACDEFGHIKLMNPQRSTVWY, length, modification mass (per 100 Da), inverse
+        charge, (mass / 100 Da)^(2/3) / charge, intercept"""
+        return self.__mobility_model_ptr.coefficients
+
+    @property
+    def training_charge(self) -> int:
+        """Most frequent precursor charge of the training peptides, used by retrain without charges"""
+        return self.__mobility_model_ptr.training_charge
+
+    def predict(self, sequence: str, charge: int, modifications: Optional[Dict[int, float]] = None) -> float:
+        return self.__mobility_model_ptr.predict(sequence, charge, modifications)
+
+    def predict_batch(self, sequences: List[str], modifications: Optional[List[Dict[int, float]]], charges: List[int],
+                      num_threads: int = 4) -> List[float]:
+        """Predict the inverse ion mobility of many sequences in parallel
+
+        Args:
+            sequences (List[str]): The peptide sequences
+            modifications (Optional[List[Dict[int, float]]]): Residue position to mass shift of each sequence
+            charges (List[int]): The precursor charge of each sequence
+            num_threads (int, optional): The number of threads. Defaults to 4.
+
+        Returns:
+            List[float]: The predicted inverse ion mobility of each sequence
+        """
+        return self.__mobility_model_ptr.predict_batch(sequences, modifications, charges, num_threads)
+
+    def predict_from_psms(self, psms: List[Feature], db: IndexedDatabase, num_threads: int = 4) -> List[Feature]:
+        """Set inverse_ion_mobility_predicted of all PSMs at their precursor charge, and delta_ims_model of those
+        with an observed ion mobility
+
+        Args:
+            psms (List[Feature]): The PSMs
+            db (IndexedDatabase): The database the PSMs were scored against, the peptide (sequence and
+                modifications) of each PSM is looked up by its peptide_idx. PSMs imported from other tools, which
+                do not refer to a sagepy database, raise a ValueError
+            num_threads (int, optional): The number of threads. Defaults to 4.
+
+        Returns:
+            List[Feature]: The PSMs with their ion mobility predictions
+        """
+        result = self.__mobility_model_ptr.predict_from_psms(
+            [psm.get_py_ptr() for psm in psms], db.get_py_ptr(), num_threads)
+        return [Feature.from_py_feature(psm) for psm in result]
+
+    def evaluate_model(self, psms: List[Feature], db: IndexedDatabase) -> Tuple[float, float]:
+        """Accuracy of the model on the PSMs with an observed ion mobility, ideally PSMs it was not fitted on
+
+        Args:
+            psms (List[Feature]): The PSMs
+            db (IndexedDatabase): The database the PSMs were scored against, the peptide (sequence and
+                modifications) of each PSM is looked up by its peptide_idx. PSMs imported from other tools, which
+                do not refer to a sagepy database, raise a ValueError
+
+        Returns:
+            Tuple[float, float]: The median absolute error (1/K0) and R²
+        """
+        return self.__mobility_model_ptr.evaluate_model([psm.get_py_ptr() for psm in psms], db.get_py_ptr())
+
+    def retrain(self, sequences: List[str], observed_ims: List[float], num_epochs: int,
+                charges: Optional[List[int]] = None, modifications: Optional[List[Dict[int, float]]] = None,
+                learning_rate: float = 0.1) -> None:
+        """Adapt the model to new observations (e.g. another instrument calibration) by online gradient updates,
+        starting from the current fit
+
+        Args:
+            sequences (List[str]): The peptide sequences
+            observed_ims (List[float]): The observed inverse ion mobility of each sequence
+            num_epochs (int): The number of passes over the observations
+            charges (Optional[List[int]], optional): The precursor charge of each sequence. Defaults to None,
+                all sequences are taken at the training_charge of the model.
+            modifications (Optional[List[Dict[int, float]]], optional): Residue position to mass shift of each
+                sequence. Defaults to None.
+            learning_rate (float, optional): The step size, in (0, 2). Defaults to 0.1.
+        """
+        self.__mobility_model_ptr.retrain(sequences, observed_ims, num_epochs, charges, modifications, learning_rate)
+
+    def __repr__(self):
+        return f"MobilityModel(num_coefficients: {len(self.coefficients)})"
+
+    def get_py_ptr(self):
+        return self.__mobility_model_ptr
+
+
+def precompute_ims_predictions(db: IndexedDatabase, mobility_model: MobilityModel) -> IndexedDatabase:
+    """Cache the predicted ion mobility of every peptide at charges 1 to 6 in a database, used by the ion mobility
+    filter of the Scorer (see its ims_tolerance). The database is updated in place rather than copied, the
+    predictions are not written by to_file
+
+    Args:
+        db (IndexedDatabase): The database
+        mobility_model (MobilityModel): The ion mobility model
+
+    Returns:
+        IndexedDatabase: The same database, now holding the ion mobility predictions
+    """
+    psc.precompute_ims_predictions(db.get_py_ptr(), mobility_model.get_py_ptr())
+    return db
@@ -1,4 +1,4 @@
-from typing import Dict, List
+from typing import Dict, List, Optional
 
 import sagepy_connector
 
@@ -43,6 +43,59 @@ class SAGE_KNOWN_MODS:
         return "]", 111.0
 
 
+class TerminalModification:
+    def __init__(self, terminus: str, mass_shift: float, unimod_id: Optional[int] = None):
+        """A modification of the peptide N- or C-terminus
+
+        Args:
+            terminus (str): The terminus, N or C
+            mass_shift (float): The mass shift in Da
+            unimod_id (Optional[int], optional): The Unimod accession. Defaults to None.
+        """
+        self.__terminal_modification_ptr = psc.PyTerminalModification(terminus, mass_shift, unimod_id)
+
+    @classmethod
+    def from_py_terminal_modification(cls, modification: psc.PyTerminalModification):
+        instance = cls.__new__(cls)
+        instance.__terminal_modification_ptr = modification
+        return instance
+
+    @classmethod
+    def acetyl(cls) -> 'TerminalModification':
+        return cls.from_py_terminal_modification(psc.PyTerminalModification.acetyl())
+
+    @classmethod
+    def carbamyl(cls) -> 'TerminalModification':
+        return cls.from_py_terminal_modification(psc.PyTerminalModification.carbamyl())
+
+    @classmethod
+    def amidated(cls) -> 'TerminalModification':
+        return cls.from_py_terminal_modification(psc.PyTerminalModification.amidated())
+
+    @property
+    def terminus(self) -> str:
+        return self.__terminal_modification_ptr.terminus
+
+    @property
+    def mass_shift(self) -> float:
+        return self.__terminal_modification_ptr.mass_shift
+
+    @property
+    def unimod_id(self) -> Optional[int]:
+        return self.__terminal_modification_ptr.unimod_id
+
+    @property
+    def unimod_annotation(self) -> str:
+        return self.__terminal_modification_ptr.unimod_annotation
+
+    def __repr__(self):
+        return f"TerminalModification(terminus: {self.terminus}, mass_shift: {self.mass_shift}, " \
+               f"unimod_id: {self.unimod_id})"
+
+    def get_py_ptr(self):
+        return self.__terminal_modification_ptr
+
+
 # TODO: need to re-implement based on constant modification list
 class ModificationSpecificity:
     def __init__(self, s: str):
@@ -75,6 +128,79 @@ def validate_var_mods(mods: Dict[str, List[float]]) -> Dict[ModificationSpecific
     return {ModificationSpecificity.from_py_modification_specificity(k): v for k, v in py_validate_dict.items()}
 
 
+
+class ModificationCandidate:
+    def __init__(self):
+        raise NotImplementedError("ModificationCandidate is created by get_modification_candidates")
+
+    @classmethod
+    def from_py_modification_candidate(cls, candidate: psc.PyModificationCandidate) -> 'ModificationCandidate':
+        instance = cls.__new__(cls)
+        instance.__modification_candidate_ptr = candidate
+        return instance
+
+    @property
+    def unimod_id(self) -> int:
+        return self.__modification_candidate_ptr.unimod_id
+
+    @property
+    def name(self) -> str:
+        return self.__modification_candidate_ptr.name
+
+    @property
+    def theoretical_mass(self) -> float:
+        return self.__modification_candidate_ptr.theoretical_mass
+
+    @property
+    def mass_error_ppm(self) -> float:
+        return self.__modification_candidate_ptr.mass_error_ppm
+
+    @property
+    def residues(self) -> str:
+        return self.__modification_candidate_ptr.residues
+
+    @property
+    def unimod_annotation(self) -> str:
+        return self.__modification_candidate_ptr.unimod_annotation
+
+    def __repr__(self):
+        return f"ModificationCandidate(unimod_id: {self.unimod_id}, name: {self.name}, " \
+               f"theoretical_mass: {self.theoretical_mass}, mass_error_ppm: {self.mass_error_ppm})"
+
+    def get_py_ptr(self):
+        return self.__modification_candidate_ptr
+
+
+def get_modification_candidates(delta_mass: float, tolerance_da: float = 0.01) -> List[ModificationCandidate]:
+    """Find the Unimod modifications explaining a mass shift, e.g. of an open search PSM
+
+    Args:
+        delta_mass (float): The observed mass shift in Da
+        tolerance_da (float, optional): The mass tolerance in Da. Defaults to 0.01.
+
+    Returns:
+        List[ModificationCandidate]: The candidates, by increasing absolute mass error
+    """
+    return [ModificationCandidate.from_py_modification_candidate(c)
+            for c in psc.get_modification_candidates(delta_mass, tolerance_da)]
+
+
+def get_likely_modification(delta_mass: float, observed_amino_acid: str,
+                            tolerance_da: float = 0.01) -> Optional[ModificationCandidate]:
+    """Find the Unimod modification with the smallest mass error that can occur on a residue
+
+    Args:
+        delta_mass (float): The observed mass shift in Da
+        observed_amino_acid (str): The modified residue, ^ or $ for the peptide N- or C-terminus
+        tolerance_da (float, optional): The mass tolerance in Da. Defaults to 0.01.
+
+    Returns:
+        Optional[ModificationCandidate]: The candidate, None if no modification matches
+    """
+    candidate = psc.get_likely_modification(delta_mass, observed_amino_acid, tolerance_da)
+    return ModificationCandidate.from_py_modification_candidate(candidate) if candidate is not None else None
+
+
 if __name__ == "__main__":
     static_mods = {k: v for k, v in [SAGE_KNOWN_MODS.cysteine_static()]}
     variable_mods = {k: v for k, v in [SAGE_KNOWN_MODS.methionine_variable()]}
@@ -3,6 +3,7 @@ from typing import List, Optional
 import sagepy_connector
 
 from sagepy.core.enzyme import Position, Digest
+from sagepy.core.modification import TerminalModification
 from sagepy.utility import mass_to_mod
 
 psc = sagepy_connector.py_peptide
@@ -86,10 +87,31 @@ class Peptide:
     def c_term(self):
         return self.__peptide_ptr.c_term
 
+    @property
+    def n_term_mod(self) -> Optional[TerminalModification]:
+        maybe_mod = self.__peptide_ptr.n_term_mod
+        return TerminalModification.from_py_terminal_modification(maybe_mod) if maybe_mod is not None else None
+
+    @property
+    def c_term_mod(self) -> Optional[TerminalModification]:
+        maybe_mod = self.__peptide_ptr.c_term_mod
+        return TerminalModification.from_py_terminal_modification(maybe_mod) if maybe_mod is not None else None
+
     @property
     def semi_enzymatic(self):
         return self.__peptide_ptr.semi_enzymatic
 
+    def with_terminal_modification(self, modification: TerminalModification) -> 'Peptide':
+        """Get the peptide with a terminal modification, replacing the previous one of that terminus
+
+        Args:
+            modification (TerminalModification): The terminal modification
+
+        Returns:
+            Peptide: The modified peptide, with the monoisotopic mass updated
+        """
+        return Peptide.from_py_peptide(self.__peptide_ptr.with_terminal_modification(modification.get_py_ptr()))
+
     def get_py_ptr(self):
         return self.__peptide_ptr
 
@@ -110,7 +132,8 @@ class Peptide:
         mods = self.modifications
         sequence = self.sequence
 
-        seq = ''
+        n_term_mod, c_term_mod = self.n_term_mod, self.c_term_mod
+        seq = n_term_mod.unimod_annotation if n_term_mod is not None and n_term_mod.mass_shift != 0 else ''
 
         for i, (s, m) in enumerate(zip(sequence, mods)):
             if m != 0:
@@ -125,4 +148,20 @@ class Peptide:
             else:
                 seq += s
 
+        if c_term_mod is not None and c_term_mod.mass_shift != 0:
+            seq += f'-{c_term_mod.unimod_annotation}'
+
         return seq
+
+
+def predict_detectability(sequence: str) -> float:
+    """Predict the LC-MS detectability of a tryptic peptide from its length, Kyte-Doolittle hydrophobicity,
+    missed cleavages, Cys/Met/Trp content and N-terminal proline
+
+    Args:
+        sequence (str): The peptide sequence
+
+    Returns:
+        float: The detectability score between 0 and 1
+    """
+    return psc.predict_detectability(sequence)
@@ -0,0 +1,232 @@
+from typing import Optional, List, Tuple, Dict
+
+import sagepy_connector
+from sagepy.core.scoring import Feature
+
+psc = sagepy_connector.py_retention_alignment
+
+# Biognosys iRT kit peptides and their iRT values
+BIOGNOSYS_IRT_PEPTIDES: List[Tuple[str, float]] = psc.BIOGNOSYS_IRT_PEPTIDES
+
+
+class LinearModel:
+    """Linear map of query onto reference retention times fitted by RANSAC"""
+    @classmethod
+    def from_py_linear_model(cls, linear_model: psc.PyLinearModel):
+        instance = cls.__new__(cls)
+        instance.__linear_model_ptr = linear_model
+        return instance
+
+    @property
+    def slope(self) -> float:
+        return self.__linear_model_ptr.slope
+
+    @property
+    def intercept(self) -> float:
+        return self.__linear_model_ptr.intercept
+
+    @property
+    def num_inliers(self) -> int:
+        return self.__linear_model_ptr.num_inliers
+
+    @property
+    def num_anchors(self) -> int:
+        return self.__linear_model_ptr.num_anchors
+
+    def predict(self, rt: float) -> float:
+        return self.__linear_model_ptr.predict(rt)
+
+    def inlier_fraction(self) -> float:
+        """Fraction of the anchors within the inlier threshold of the model"""
+        return self.__linear_model_ptr.inlier_fraction()
+
+    def __repr__(self):
+        return (f"LinearModel(slope: {self.slope}, intercept: {self.intercept}, "
+                f"inliers: {self.num_inliers}/{self.num_anchors})")
+
+    def get_py_ptr(self):
+        return self.__linear_model_ptr
+
+
+class RetentionAlignment:
+    def __init__(self, reference_file_id: Optional[int] = None, bandwidth: float = 0.3, polynomial_degree: int = 1,
+                 fdr_cutoff: float = 0.01):
+        """Retention time alignment of runs onto a reference run, anchored on shared identified peptides
+
+        Args:
+            reference_file_id (Optional[int], optional): The reference run. Defaults to None (the run with
+                most identified peptides).
+            bandwidth (float, optional): The fraction of anchors used for each local LOESS fit, in (0, 1].
+                Defaults to 0.3.
+            polynomial_degree (int, optional): The degree of the local LOESS fits, 1 or 2. Defaults to 1.
+            fdr_cutoff (float, optional): The spectrum q-value of PSMs used as anchors. Defaults to 0.01.
+        """
+        self.__alignment_ptr = psc.PyRetentionAlignment(reference_file_id, bandwidth, polynomial_degree, fdr_cutoff)
+
+    @classmethod
+    def from_py_retention_alignment(cls, alignment: psc.PyRetentionAlignment):
+        instance = cls.__new__(cls)
+        instance.__alignment_ptr = alignment
+        return instance
+
+    @property
+    def reference_file_id(self) -> Optional[int]:
+        return self.__alignment_ptr.reference_file_id
+
+    @property
+    def bandwidth(self) -> float:
+        return self.__alignment_ptr.bandwidth
+
+    @property
+    def polynomial_degree(self) -> int:
+        return self.__alignment_ptr.polynomial_degree
+
+    @property
+    def fdr_cutoff(self) -> float:
+        return self.__alignment_ptr.fdr_cutoff
+
+    def align(self, psms: List[Feature], method: str = 'loess') -> List[Feature]:
+        """Align the retention times of all runs onto the reference run
+
+        Args:
+            psms (List[Feature]): The PSMs of all runs
+            method (str, optional): The alignment, 'linear', 'piecewise' or 'loess'. Defaults to 'loess'.
+
+        Returns:
+            List[Feature]: The PSMs with aligned_rt set to the retention time of the reference run
+        """
+        result = self.__alignment_ptr.align([p.get_py_ptr() for p in psms], method)
+        return [Feature.from_py_feature(p) for p in result]
+
+    def align_robust(self, psms: List[Feature], method: str = 'ransac', max_iterations: int = 1000,
+                     inlier_threshold_min: float = 1.0,
+                     min_inliers: int = 10) -> Tuple[List[Feature], Dict[int, LinearModel]]:
+        """Align the retention times of all runs onto the reference run, robust to misidentified anchor PSMs
+
+        Args:
+            psms (List[Feature]): The PSMs of all runs
+            method (str, optional): The robust alignment, 'ransac'. Defaults to 'ransac'.
+            max_iterations (int, optional): The number of RANSAC samples. Defaults to 1000.
+            inlier_threshold_min (float, optional): The maximal residual of an inlier anchor. Defaults to 1.0.
+            min_inliers (int, optional): The minimal number of inlier anchors of a run. Defaults to 10.
+
+        Returns:
+            Tuple[List[Feature], Dict[int, LinearModel]]: The PSMs with aligned_rt set, and the model of each
+                aligned run by file_id
+        """
+        result, models = self.__alignment_ptr.align_robust([p.get_py_ptr() for p in psms], method, max_iterations,
+                                                           inlier_threshold_min, min_inliers)
+        return ([Feature.from_py_feature(p) for p in result],
+                {file_id: LinearModel.from_py_linear_model(m) for file_id, m in models.items()})
+
+    def __repr__(self):
+        return (f"RetentionAlignment(reference_file_id: {self.reference_file_id}, bandwidth: {self.bandwidth}, "
+                f"polynomial_degree: {self.polynomial_degree}, fdr_cutoff: {self.fdr_cutoff})")
+
+    def get_py_ptr(self):
+        return self.__alignment_ptr
+
+
+def loess_align(reference_rts: List[float], query_rts: List[float], target_rts: List[float],
+                bandwidth: float = 0.3, polynomial_degree: int = 1) -> List[float]:
+    """Map retention times of a query run onto a reference run with robust LOESS
+
+    Args:
+        reference_rts (List[float]): The anchor retention times in the reference run
+        query_rts (List[float]): The retention times of the same anchors in the query run
+        target_rts (List[float]): The query run retention times to map
+        bandwidth (float, optional): The fraction of anchors used for each local fit, in (0, 1]. Defaults to 0.3.
+        polynomial_degree (int, optional): The degree of the local fits, 1 or 2. Defaults to 1.
+
+    Returns:
+        List[float]: The retention times in the reference run
+    """
+    return psc.loess_align(reference_rts, query_rts, target_rts, bandwidth, polynomial_degree)
+
+
+def ransac_align_rts(reference_rts: List[float], query_rts: List[float], max_iterations: int = 1000,
+                     inlier_threshold_min: float = 1.0, min_inliers: int = 10) -> Tuple[LinearModel, List[bool]]:
+    """Fit a linear map of query onto reference retention times by RANSAC, robust to misidentified anchors
+
+    Args:
+        reference_rts (List[float]): The anchor retention times in the reference run
+        query_rts (List[float]): The retention times of the same anchors in the query run
+        max_iterations (int, optional): The number of RANSAC samples. Defaults to 1000.
+        inlier_threshold_min (float, optional): The maximal residual of an inlier anchor. Defaults to 1.0.
+        min_inliers (int, optional): The minimal number of inlier anchors. Defaults to 10.
+
+    Returns:
+        Tuple[LinearModel, List[bool]]: The model and whether each anchor is an inlier
+    """
+    model, mask = psc.ransac_align_rts(reference_rts, query_rts, max_iterations, inlier_threshold_min, min_inliers)
+    return LinearModel.from_py_linear_model(model), mask
+
+
+class IrtModel:
+    def __init__(self, slope: float, intercept: float):
+        """Linear map of observed retention times onto the iRT scale
+
+        Args:
+            slope (float): The slope
+            intercept (float): The intercept
+        """
+        self.__irt_model_ptr = psc.PyIrtModel(slope, intercept)
+
+    @classmethod
+    def from_py_irt_model(cls, irt_model: psc.PyIrtModel):
+        instance = cls.__new__(cls)
+        instance.__irt_model_ptr = irt_model
+        return instance
+
+    @property
+    def slope(self) -> float:
+        return self.__irt_model_ptr.slope
+
+    @property
+    def intercept(self) -> float:
+        return self.__irt_model_ptr.intercept
+
+    def predict_irt(self, rt: float) -> float:
+        """Map an observed retention time onto the iRT scale
+
+        Args:
+            rt (float): The observed retention time
+
+        Returns:
+            float: The iRT
+        """
+        return self.__irt_model_ptr.predict_irt(rt)
+
+    def __repr__(self):
+        return f"IrtModel(slope: {self.slope}, intercept: {self.intercept})"
+
+    def get_py_ptr(self):
+        return self.__irt_model_ptr
+
+
+def compute_irt_regression(observed_rts: List[float], irt_values: List[float]) -> IrtModel:
+    """Fit a linear regression from observed retention times of spike-in peptides to their iRT values,
+    e.g. those of BIOGNOSYS_IRT_PEPTIDES
+
+    Args:
+        observed_rts (List[float]): The observed retention times of the iRT peptides
+        irt_values (List[float]): The iRT values of the same peptides
+
+    Returns:
+        IrtModel: The fitted model
+    """
+    return IrtModel.from_py_irt_model(psc.compute_irt_regression(observed_rts, irt_values))
+
+
+def normalize_rts_to_irt(psms: List[Feature], irt_model: IrtModel) -> List[Feature]:
+    """Map the retention times of PSMs onto the iRT scale
+
+    Args:
+        psms (List[Feature]): The PSMs
+        irt_model (IrtModel): The iRT model of their run
+
+    Returns:
+        List[Feature]: The PSMs with aligned_rt set to the iRT, rt keeps the observed retention time
+    """
+    result = psc.normalize_rts_to_irt([p.get_py_ptr() for p in psms], irt_model.get_py_ptr())
+    return [Feature.from_py_feature(p) for p in result]
@@ -0,0 +1,218 @@
+from typing import Optional, List, Tuple, Dict
+
+import sagepy_connector
+from sagepy.core.database import IndexedDatabase
+from sagepy.core.scoring import Feature
+
+psc = sagepy_connector.py_retention_model
+
+
+class RetentionModel:
+    """Linear retention time model on amino acid composition, length and modification mass, with an
+    ensemble of bootstrap fits for the uncertainty of predictions"""
+    def __init__(self):
+        raise NotImplementedError("RetentionModel is created by RetentionModel.fit or RetentionModel.fit_from_psms")
+
+    @classmethod
+    def from_py_retention_model(cls, retention_model: psc.PyRetentionModel):
+        instance = cls.__new__(cls)
+        instance.__retention_model_ptr = retention_model
+        return instance
+
+    @classmethod
+    def fit(cls, sequences: List[str], retention_times: List[float],
+            modifications: Optional[List[Dict[int, float]]] = None, ensemble_size: int = 10,
+            ridge: float = 1e-3, seed: int = 42) -> 'RetentionModel':
+        """Fit a retention time model
+
+        Args:
+            sequences (List[str]): The peptide sequences
+            retention_times (List[float]): The (aligned) retention time of each sequence
+            modifications (Optional[List[Dict[int, float]]], optional): Residue position to mass shift of each
+                sequence. Defaults to None.
+            ensemble_size (int, optional): The number of ensemble members, the first is fitted on all peptides,
+                the others on bootstrap resamples. Defaults to 10.
+            ridge (float, optional): The ridge penalty. Defaults to 1e-3.
+            seed (int, optional): The seed of the bootstrap resampling. Defaults to 42.
+
+        Returns:
+            RetentionModel: The fitted model
+        """
+        return cls.from_py_retention_model(
+            psc.PyRetentionModel.fit(sequences, retention_times, modifications, ensemble_size, ridge, seed))
+
+    @classmethod
+    def fit_from_psms(cls, psms: List[Feature], db: IndexedDatabase, q_value_cutoff: float = 0.01,
+                      ensemble_size: int = 10, ridge: float = 1e-3, seed: int = 42) -> 'RetentionModel':
+        """Fit a retention time model on the aligned retention times of confident target PSMs
+
+        Args:
+            psms (List[Feature]): The PSMs
+            db (IndexedDatabase): The database the PSMs were scored against
+            q_value_cutoff (float, optional): The maximum spectrum q-value of training PSMs. Defaults to 0.01.
+            ensemble_size (int, optional): The number of ensemble members. Defaults to 10.
+            ridge (float, optional): The ridge penalty. Defaults to 1e-3.
+            seed (int, optional): The seed of the bootstrap resampling. Defaults to 42.
+
+        Returns:
+            RetentionModel: The fitted model
+        """
+        return cls.from_py_retention_model(psc.PyRetentionModel.fit_from_psms(
+            [p.get_py_ptr() for p in psms], db.get_py_ptr(), q_value_cutoff, ensemble_size, ridge, seed))
+
+    @property
+    def ensemble_size(self) -> int:
+        return self.__retention_model_ptr.ensemble_size
+
+    @property
+    def coefficients(self) -> List[float]:
+        """Coefficients of the amino acids ACDEFGHIKLMNPQRSTVWY, length, modification mass (per 100 Da), intercept"""
+        return self.__retention_model_ptr.coefficients
+
+    def predict(self, sequence: str, modifications: Optional[Dict[int, float]] = None) -> float:
+        return self.__retention_model_ptr.predict(sequence, modifications)
+
+    def predict_batch(self, sequences: List[str], modifications: Optional[List[Dict[int, float]]] = None,
+                      num_threads: int = 4) -> List[float]:
+        """Predict the retention times of many sequences in parallel
+
+        Args:
+            sequences (List[str]): The peptide sequences
+            modifications (Optional[List[Dict[int, float]]], optional): Residue position to mass shift of each
+                sequence. Defaults to None.
+            num_threads (int, optional): The number of threads. Defaults to 4.
+
+        Returns:
+            List[float]: The predicted retention times
+        """
+        return self.__retention_model_ptr.predict_batch(sequences, modifications, num_threads)
+
+    def predict_with_uncertainty(self, sequences: List[str], modifications: Optional[List[Dict[int, float]]] = None,
+                                 num_threads: int = 4) -> List[Tuple[float, float]]:
+        """Predict retention times with the standard deviation over the ensemble members
+
+        Args:
+            sequences (List[str]): The peptide sequences
+            modifications (Optional[List[Dict[int, float]]], optional): Residue position to mass shift of each
+                sequence. Defaults to None.
+            num_threads (int, optional): The number of threads. Defaults to 4.
+
+        Returns:
+            List[Tuple[float, float]]: The predicted retention time and its standard deviation
+        """
+        return self.__retention_model_ptr.predict_with_uncertainty(sequences, modifications, num_threads)
+
+    def predict_from_psms(self, psms: List[Feature], db: IndexedDatabase, num_threads: int = 4) -> List[Feature]:
+        """Predict the retention times of PSMs
+
+        Args:
+            psms (List[Feature]): The PSMs
+            db (IndexedDatabase): The database the PSMs were scored against
+            num_threads (int, optional): The number of threads. Defaults to 4.
+
+        Returns:
+            List[Feature]: The PSMs with predicted_rt and delta_rt_model (distance to aligned_rt) set
+        """
+        result = self.__retention_model_ptr.predict_from_psms([p.get_py_ptr() for p in psms], db.get_py_ptr(),
+                                                              num_threads)
+        return [Feature.from_py_feature(p) for p in result]
+
+    def __repr__(self):
+        return f"RetentionModel(ensemble_size: {self.ensemble_size})"
+
+    def get_py_ptr(self):
+        return self.__retention_model_ptr
+
+
+class RtModelMetrics:
+    def __init__(self):
+        raise NotImplementedError("RtModelMetrics is created by evaluate_rt_model")
+
+    @classmethod
+    def from_py_rt_model_metrics(cls, metrics: psc.PyRtModelMetrics):
+        instance = cls.__new__(cls)
+        instance.__rt_model_metrics_ptr = metrics
+        return instance
+
+    @property
+    def mae_min(self) -> float:
+        return self.__rt_model_metrics_ptr.mae_min
+
+    @property
+    def median_absolute_error_min(self) -> float:
+        return self.__rt_model_metrics_ptr.median_absolute_error_min
+
+    @property
+    def r_squared(self) -> float:
+        return self.__rt_model_metrics_ptr.r_squared
+
+    @property
+    def pearson_r(self) -> float:
+        return self.__rt_model_metrics_ptr.pearson_r
+
+    @property
+    def n_psms(self) -> int:
+        return self.__rt_model_metrics_ptr.n_psms
+
+    def __repr__(self):
+        return f"RtModelMetrics(mae_min: {self.mae_min}, median_absolute_error_min: " \
+               f"{self.median_absolute_error_min}, r_squared: {self.r_squared}, pearson_r: {self.pearson_r}, " \
+               f"n_psms: {self.n_psms})"
+
+    def get_py_ptr(self):
+        return self.__rt_model_metrics_ptr
+
+
+def evaluate_rt_model(model: RetentionModel, psms: List[Feature], db: IndexedDatabase,
+                      fdr_threshold: float = 0.01) -> RtModelMetrics:
+    """Evaluate a retention time model on the aligned retention times of confident target PSMs
+
+    Args:
+        model (RetentionModel): The model
+        psms (List[Feature]): The PSMs, ideally not used to fit the model
+        db (IndexedDatabase): The database the PSMs were scored against
+        fdr_threshold (float, optional): The maximum spectrum q-value of PSMs used. Defaults to 0.01.
+
+    Returns:
+        RtModelMetrics: The mean and median absolute error in minutes, R squared and Pearson correlation
+    """
+    return RtModelMetrics.from_py_rt_model_metrics(
+        psc.evaluate_rt_model(model.get_py_ptr(), [p.get_py_ptr() for p in psms], db.get_py_ptr(), fdr_threshold))
+
+
+def rt_prediction_residuals(psms: List[Feature]) -> List[float]:
+    """Predicted minus aligned retention time of PSMs, see RetentionModel.predict_from_psms
+
+    Args:
+        psms (List[Feature]): The PSMs with predicted_rt set
+
+    Returns:
+        List[float]: The residual of each PSM
+    """
+    return psc.rt_prediction_residuals([p.get_py_ptr() for p in psms])
+
+
+def rt_prediction_by_sequence_length(psms: List[Feature]) -> Dict[int, float]:
+    """Mean absolute retention time prediction error per peptide length
+
+    Args:
+        psms (List[Feature]): The PSMs with predicted_rt set
+
+    Returns:
+        Dict[int, float]: The mean absolute error of each peptide length
+    """
+    return psc.rt_prediction_by_sequence_length([p.get_py_ptr() for p in psms])
+
+
+def detect_rt_outliers(psms: List[Feature], sigma_threshold: float = 3.0) -> List[bool]:
+    """Flag PSMs with extreme retention time residuals, measured in robust standard deviations (1.4826 MAD)
+    from the median residual
+
+    Args:
+        psms (List[Feature]): The PSMs with predicted_rt set
+        sigma_threshold (float, optional): The number of robust standard deviations. Defaults to 3.0.
+
+    Returns:
+        List[bool]: Whether each PSM is an outlier
+    """
+    return psc.detect_rt_outliers([p.get_py_ptr() for p in psms], sigma_threshold)
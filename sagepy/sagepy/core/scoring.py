@@ -1,23 +1,25 @@
-from typing import Union, Optional, List
+from typing import Union, Optional, List, Tuple, Callable, Dict
 
 import numpy as np
+from numpy.typing import NDArray
 import sagepy_connector
 
-from .spectrum import ProcessedSpectrum
+from .spectrum import ProcessedSpectrum, RawSpectrum
 
 psc = sagepy_connector.py_scoring
 from .ion_series import IonType
 from .mass import Tolerance
-from .database import PeptideIx, IndexedDatabase
+from .database import PeptideIx, IndexedDatabase, SageSearchConfiguration
 
 
 class Fragments:
-    def __int__(self, charges: List[int], ion_types: List[IonType], fragment_ordinals: List[int],
-                intensities: List[float], mz_calculated: List[float], mz_experimental: List[float]):
+    def __init__(self, charges: List[int], ion_types: List[IonType], fragment_ordinals: List[int],
+                 intensities: List[float], mz_calculated: List[float], mz_experimental: List[float],
+                 neutral_losses: Optional[List[float]] = None):
         kinds = [x.get_py_ptr() for x in ion_types]
 
         self.__fragments_ptr = psc.PyFragments(charges, kinds, fragment_ordinals,
-                                               intensities, mz_calculated, mz_experimental)
+                                               intensities, mz_calculated, mz_experimental, neutral_losses)
 
     @classmethod
     def from_py_fragments(cls, fragments: psc.PyFragments):
@@ -25,6 +27,9 @@ class Fragments:
         instance.__fragments_ptr = fragments
         return instance
 
+    def get_py_ptr(self):
+        return self.__fragments_ptr
+
     @property
     def charges(self) -> List[int]:
         return self.__fragments_ptr.charges
@@ -49,6 +54,10 @@ class Fragments:
     def mz_experimental(self) -> List[float]:
         return self.__fragments_ptr.mz_experimental
 
+    @property
+    def neutral_losses(self) -> List[float]:
+        return self.__fragments_ptr.neutral_losses
+
     def __repr__(self):
         return (f"Fragments(charges: {self.charges}, "
                 f"ion_types: {self.ion_types}, "
@@ -61,6 +70,92 @@ class Fragments:
         return self.__fragments_ptr
 
 
+class CancellationToken:
+    """Token to interrupt a running Scorer.score_collection_with_progress, e.g. from another thread"""
+    def __init__(self):
+        self.__token_ptr = psc.PyCancellationToken()
+
+    @classmethod
+    def from_py_cancellation_token(cls, token: psc.PyCancellationToken):
+        instance = cls.__new__(cls)
+        instance.__token_ptr = token
+        return instance
+
+    def cancel(self) -> None:
+        self.__token_ptr.cancel()
+
+    @property
+    def is_cancelled(self) -> bool:
+        return self.__token_ptr.is_cancelled
+
+    def __repr__(self):
+        return f"CancellationToken(is_cancelled: {self.is_cancelled})"
+
+    def get_py_ptr(self):
+        return self.__token_ptr
+
+
+class GlycopeptideScoringMode:
+    def __init__(self, oxonium_mz: Optional[List[float]] = None, min_oxonium_ions: int = 2):
+        """Glycopeptide scoring mode, the score of diagnostic oxonium ions found in a spectrum is added
+        to the discriminant score of its PSMs
+
+        Args:
+            oxonium_mz (Optional[List[float]], optional): The m/z of the diagnostic oxonium ions. Defaults to None
+                (HexNAc 204.087 and its fragments, Hex 163.060, dHex, HexHexNAc, NeuAc and NeuGc).
+            min_oxonium_ions (int, optional): The number of oxonium ions required as glycopeptide evidence.
+                Defaults to 2.
+        """
+        self.__mode_ptr = psc.PyGlycopeptideScoringMode(oxonium_mz, min_oxonium_ions)
+
+    @classmethod
+    def from_py_glycopeptide_scoring_mode(cls, mode: psc.PyGlycopeptideScoringMode):
+        instance = cls.__new__(cls)
+        instance.__mode_ptr = mode
+        return instance
+
+    @property
+    def oxonium_mz(self) -> List[float]:
+        return self.__mode_ptr.oxonium_mz
+
+    @property
+    def min_oxonium_ions(self) -> int:
+        return self.__mode_ptr.min_oxonium_ions
+
+    def __repr__(self):
+        return f"GlycopeptideScoringMode(oxonium_mz: {self.oxonium_mz}, min_oxonium_ions: {self.min_oxonium_ions})"
+
+    def get_py_ptr(self):
+        return self.__mode_ptr
+
+
+class GlycopeptideMatch:
+    """A glycopeptide PSM, the peptide backbone match and the glycan composition of the remaining precursor mass"""
+    @classmethod
+    def from_py_glycopeptide_match(cls, match: psc.PyGlycopeptideMatch):
+        instance = cls.__new__(cls)
+        instance.__match_ptr = match
+        return instance
+
+    @property
+    def feature(self) -> 'Feature':
+        return Feature.from_py_feature(self.__match_ptr.feature)
+
+    @property
+    def glycan(self) -> str:
+        return self.__match_ptr.glycan
+
+    @property
+    def glycan_mass(self) -> float:
+        return self.__match_ptr.glycan_mass
+
+    def __repr__(self):
+        return f"GlycopeptideMatch(feature: {self.feature}, glycan: {self.glycan}, glycan_mass: {self.glycan_mass})"
+
+    def get_py_ptr(self):
+        return self.__match_ptr
+
+
 class Scorer:
 
     def __init__(
@@ -78,7 +173,19 @@ class Scorer:
             report_psms: int = 1,
             wide_window: bool = False,
             annotate_matches: bool = False,
-            max_fragment_charge: Optional[int] = 1):
+            max_fragment_charge: Optional[int] = 1,
+            score_type: str = 'standard',
+            min_fragment_intensity: Optional[float] = None,
+            min_fragment_intensity_relative: Optional[float] = None,
+            mc_prune_prior: Optional[float] = None,
+            xcorr_bin_width: Optional[float] = None,
+            match_neutral_losses: bool = False,
+            spectral_entropy: str = 'off',
+            glyco_mode: Optional[GlycopeptideScoringMode] = None,
+            neutral_losses: Optional[List[float]] = None,
+            with_coverage_stats: bool = False,
+            mc_prune_max_allowed: Optional[int] = None,
+            ims_tolerance: Optional[Tuple[float, str]] = None):
         """Scorer class
 
         Args:
@@ -95,13 +202,50 @@ class Scorer:
             report_psms (int, optional): The number of PSMs to report. Defaults to 1.
             wide_window (bool, optional): Should wide window be used. Defaults to False.
             max_fragment_charge (Optional[int], optional): The maximum fragment charge. Defaults to 1.
+            score_type (str, optional): The score type, 'standard', 'tdc' (target-decoy competition,
+                only the best target or decoy PSM per spectrum is reported) or 'xcorr' / 'andromeda'
+                (reported PSMs are re-ranked by cross-correlation). Defaults to 'standard'.
+            min_fragment_intensity (Optional[float], optional): The minimum absolute intensity of a fragment peak
+                to be matched. Defaults to None.
+            min_fragment_intensity_relative (Optional[float], optional): The minimum intensity of a fragment peak
+                to be matched, as fraction of the base peak intensity. Defaults to None.
+            mc_prune_prior (Optional[float], optional): If set, peptides whose missed cleavage prior
+                exp(-mc_prune_prior * missed_cleavages) falls below 0.05 are not searched. Defaults to None.
+            xcorr_bin_width (Optional[float], optional): The bin width in Th of the xcorr score type.
+                Defaults to None (0.02 Th).
+            match_neutral_losses (bool, optional): Also match b/y ions with H2O and NH3 losses (and y ions with
+                H3PO4 loss for phosphopeptides), they are counted as matched peaks. Defaults to False.
+            spectral_entropy (str, optional): 'off', 'feature' (annotate each PSM with its spectral_entropy and
+                delta_spectral_entropy) or 'rescore' (annotate and set the discriminant score of each PSM to its
+                hyperscore weighted by (1 + delta_spectral_entropy)). Defaults to 'off'.
+            glyco_mode (Optional[GlycopeptideScoringMode], optional): If set, diagnostic oxonium ions are matched
+                and their score is added to the discriminant score. Defaults to None.
+            neutral_losses (Optional[List[float]], optional): Custom neutral losses in Da matched for b and y ions,
+                replacing the default losses of match_neutral_losses, matched ions are annotated with their loss in
+                Fragments.neutral_losses. Defaults to None.
+            with_coverage_stats (bool, optional): Annotate each PSM with its b and y ion coverage statistics,
+                requires annotate_matches. Defaults to False.
+            mc_prune_max_allowed (Optional[int], optional): With mc_prune_prior, peptides with more missed
+                cleavages are not searched either. Defaults to None (no limit).
+            ims_tolerance (Optional[Tuple[float, str]], optional): Precursor ion mobility tolerance and its unit,
+                'absolute' (1/K0) or 'relative' (percent). Spectra carrying a precursor_ims are only searched against
+                peptides whose predicted 1/K0 lies within the tolerance (widened to bands of the tolerance width),
+                requires a database with precompute_ims_predictions. Defaults to None.
         """
         self.__scorer_ptr = psc.PyScorer(precursor_tolerance.get_py_ptr(),
                                          fragment_tolerance.get_py_ptr(),
                                          min_matched_peaks,
                                          min_isotope_err, max_isotope_err, min_precursor_charge,
                                          max_precursor_charge, min_fragment_mass, max_fragment_mass,
-                                         chimera, report_psms, wide_window, annotate_matches, max_fragment_charge)
+                                         chimera, report_psms, wide_window, annotate_matches, max_fragment_charge,
+                                         psc.PyScoreType(score_type), min_fragment_intensity,
+                                         min_fragment_intensity_relative, mc_prune_prior, xcorr_bin_width,
+                                         match_neutral_losses, psc.PySpectralEntropyMode(spectral_entropy),
+                                         glyco_mode.get_py_ptr() if glyco_mode is not None else None,
+                                         neutral_losses, with_coverage_stats,
+                                         mc_prune_max_allowed,
+                                         (ims_tolerance[0], psc.PyImsUnit(ims_tolerance[1]))
+                                         if ims_tolerance is not None else None)
 
     @classmethod
     def from_py_scorer(cls, scorer: psc.PyScorer):
@@ -109,6 +253,16 @@ class Scorer:
         instance.__scorer_ptr = scorer
         return instance
 
+    def get_py_ptr(self):
+        return self.__scorer_ptr
+
+    def to_json(self) -> str:
+        return self.__scorer_ptr.to_json()
+
+    @classmethod
+    def from_json(cls, json: str) -> 'Scorer':
+        return cls.from_py_scorer(psc.PyScorer.from_json(json))
+
     @property
     def precursor_tolerance(self) -> Tolerance:
         return Tolerance.from_py_tolerance(self.__scorer_ptr.precursor_tolerance)
@@ -164,14 +318,88 @@ class Scorer:
         else:
             return self.__scorer_ptr.max_fragment_charge
 
+    @property
+    def annotate_matches(self) -> bool:
+        return self.__scorer_ptr.annotate_matches
+
+    @property
+    def score_type(self) -> str:
+        return self.__scorer_ptr.score_type.score_type
+
+    @property
+    def min_fragment_intensity(self) -> Optional[float]:
+        return self.__scorer_ptr.min_fragment_intensity
+
+    @property
+    def min_fragment_intensity_relative(self) -> Optional[float]:
+        return self.__scorer_ptr.min_fragment_intensity_relative
+
+    @property
+    def mc_prune_prior(self) -> Optional[float]:
+        return self.__scorer_ptr.mc_prune_prior
+
+    @property
+    def mc_prune_max_allowed(self) -> Optional[int]:
+        return self.__scorer_ptr.mc_prune_max_allowed
+
+    @property
+    def ims_tolerance(self) -> Optional[Tuple[float, str]]:
+        tolerance = self.__scorer_ptr.ims_tolerance
+        return (tolerance[0], tolerance[1].unit) if tolerance is not None else None
+
+    @property
+    def xcorr_bin_width(self) -> float:
+        return self.__scorer_ptr.xcorr_bin_width
+
+    @property
+    def match_neutral_losses(self) -> bool:
+        return self.__scorer_ptr.match_neutral_losses
+
+    @property
+    def neutral_losses(self) -> List[float]:
+        return self.__scorer_ptr.neutral_losses
+
+    @property
+    def spectral_entropy(self) -> str:
+        return self.__scorer_ptr.spectral_entropy.mode
+
+    @property
+    def glyco_mode(self) -> Optional[GlycopeptideScoringMode]:
+        mode = self.__scorer_ptr.glyco_mode
+        if mode is None:
+            return None
+        return GlycopeptideScoringMode.from_py_glycopeptide_scoring_mode(mode)
+
+    @property
+    def with_coverage_stats(self) -> bool:
+        return self.__scorer_ptr.with_coverage_stats
+
     def __repr__(self):
         return (f"Scorer({self.precursor_tolerance}, {self.fragment_tolerance}, {self.min_matched_peaks}, "
                 f"{self.min_isotope_err}, {self.max_isotope_err}, {self.min_precursor_charge}, "
                 f"{self.max_precursor_charge}, {self.min_fragment_mass}, {self.max_fragment_mass}, "
                 f"{self.chimera}, {self.report_psms}, {self.wide_window}, {self.max_fragment_charge})")
 
-    def score(self, db: IndexedDatabase, spectrum: ProcessedSpectrum) -> List['Feature']:
-        return [Feature.from_py_feature(f) for f in self.__scorer_ptr.score(db.get_py_ptr(), spectrum.get_py_ptr())]
+    def score(self, db: IndexedDatabase, spectrum: ProcessedSpectrum,
+              ms1_spectra: Optional[List[ProcessedSpectrum]] = None,
+              ms1_isotope_window: Optional[float] = None) -> List['Feature']:
+        """Score a spectrum against the database
+
+        Args:
+            db (IndexedDatabase): The database
+            spectrum (ProcessedSpectrum): The spectrum
+            ms1_spectra (Optional[List[ProcessedSpectrum]], optional): MS1 spectra, if given the PSMs are annotated
+                with the isotope evidence of their precursor in the preceding MS1 spectrum of the same file.
+                Defaults to None.
+            ms1_isotope_window (Optional[float], optional): The m/z tolerance of the precursor isotope peaks.
+                Defaults to None (0.02).
+
+        Returns:
+            List[Feature]: The PSMs
+        """
+        ms1 = [s.get_py_ptr() for s in ms1_spectra] if ms1_spectra is not None else None
+        return [Feature.from_py_feature(f) for f in
+                self.__scorer_ptr.score(db.get_py_ptr(), spectrum.get_py_ptr(), ms1, ms1_isotope_window)]
 
     def score_collection_top_n(self, db: IndexedDatabase,
                                spectrum_collection: List[ProcessedSpectrum], num_threads: int = 4) -> List[
@@ -180,6 +408,28 @@ class Scorer:
                                                     [spec.get_py_ptr() for spec in spectrum_collection], num_threads)
         return [[Feature.from_py_feature(f) for f in score] for score in scores]
 
+    def score_collection_with_progress(self, db: IndexedDatabase, spectrum_collection: List[ProcessedSpectrum],
+                                       progress_fn: Callable[[int, int], None], num_threads: int = 4,
+                                       cancellation_token: Optional[CancellationToken] = None) -> List[
+        List['Feature']]:
+        """Score spectra in parallel, reporting progress
+
+        Args:
+            db (IndexedDatabase): The database
+            spectrum_collection (List[ProcessedSpectrum]): The spectra
+            progress_fn (Callable[[int, int], None]): Called with (completed, total) every 1000 spectra and at the end
+            num_threads (int, optional): The number of threads. Defaults to 4.
+            cancellation_token (Optional[CancellationToken], optional): Cancelling it stops the search with a
+                RuntimeError. Defaults to None.
+
+        Returns:
+            List[List[Feature]]: The PSMs of each spectrum
+        """
+        scores = self.__scorer_ptr.score_collection_with_progress(
+            db.get_py_ptr(), [spec.get_py_ptr() for spec in spectrum_collection], num_threads, progress_fn,
+            cancellation_token.get_py_ptr() if cancellation_token is not None else None)
+        return [[Feature.from_py_feature(f) for f in score] for score in scores]
+
     def score_collection(self, db: IndexedDatabase, spectrum_collection: List[Optional[ProcessedSpectrum]],
                          num_threads: int = 4) -> List['Feature']:
         scores = self.score_collection_top_n(db, spectrum_collection, num_threads)
@@ -194,6 +444,57 @@ class Scorer:
 
         return result
 
+    def score_open_search(self, db: IndexedDatabase, spectrum: ProcessedSpectrum, mass_window_da: float = 500.0,
+                          num_threads: int = 4) -> List['Feature']:
+        """Open search, candidates are matched within +/- mass_window_da of the precursor mass, the mass shift
+        of each match is available as Feature.delta_mass_open
+
+        Args:
+            db (IndexedDatabase): The database
+            spectrum (ProcessedSpectrum): The spectrum
+            mass_window_da (float, optional): The precursor mass window in Da. Defaults to 500.0.
+            num_threads (int, optional): The number of threads. Defaults to 4.
+
+        Returns:
+            List[Feature]: The features with at least min_matched_peaks matched peaks
+        """
+        return [Feature.from_py_feature(f) for f in
+                self.__scorer_ptr.score_open_search(db.get_py_ptr(), spectrum.get_py_ptr(), mass_window_da,
+                                                    num_threads)]
+
+    def score_with_all_charge_states(self, db: IndexedDatabase, spectrum: ProcessedSpectrum) -> List['Feature']:
+        """Score a spectrum for each candidate precursor charge and keep the PSMs of the best scoring charge
+
+        Args:
+            db (IndexedDatabase): The database
+            spectrum (ProcessedSpectrum): The spectrum, its precursor_candidates (see deconvolve_charge) are used,
+                or every charge from min_precursor_charge to max_precursor_charge if there are none
+
+        Returns:
+            List[Feature]: The PSMs of the charge with the highest hyperscore
+        """
+        return [Feature.from_py_feature(f) for f in
+                self.__scorer_ptr.score_with_all_charge_states(db.get_py_ptr(), spectrum.get_py_ptr())]
+
+    def score_glycopeptides(self, db: IndexedDatabase, spectrum_collection: List[ProcessedSpectrum],
+                            glycan_db_path: str, num_threads: int = 4) -> List[List[GlycopeptideMatch]]:
+        """Score N-glycopeptides, the peptide backbone is matched for every glycan composition of the glycan
+        database (the precursor mass is lowered by the glycan mass), peptides need an N-X-S/T sequon
+
+        Args:
+            db (IndexedDatabase): The database
+            spectrum_collection (List[ProcessedSpectrum]): The spectra
+            glycan_db_path (str): A text file with one glycan composition per line, e.g. HexNAc(4)Hex(5)Fuc(1)
+            num_threads (int, optional): The number of threads. Defaults to 4.
+
+        Returns:
+            List[List[GlycopeptideMatch]]: The best report_psms matches of each spectrum
+        """
+        matches = self.__scorer_ptr.score_glycopeptides(db.get_py_ptr(),
+                                                        [spec.get_py_ptr() for spec in spectrum_collection],
+                                                        glycan_db_path, num_threads)
+        return [[GlycopeptideMatch.from_py_glycopeptide_match(m) for m in match] for match in matches]
+
     def _score_chimera_fast(self, db: IndexedDatabase, spectrum: ProcessedSpectrum) -> List['Feature']:
         return [Feature.from_py_feature(f) for f in
                 self.__scorer_ptr.score_chimera_fast(db.get_py_ptr(), spectrum.get_py_ptr())]
@@ -203,6 +504,56 @@ class Scorer:
                 self.__scorer_ptr.score_standard(db.get_py_ptr(), spectrum.get_py_ptr())]
 
 
+class FragmentCoverageStats:
+    def __init__(self):
+        raise NotImplementedError("FragmentCoverageStats is created by compute_coverage_stats")
+
+    @classmethod
+    def from_py_fragment_coverage_stats(cls, stats: psc.PyFragmentCoverageStats) -> 'FragmentCoverageStats':
+        instance = cls.__new__(cls)
+        instance.__stats_ptr = stats
+        return instance
+
+    @property
+    def b_coverage(self) -> float:
+        return self.__stats_ptr.b_coverage
+
+    @property
+    def y_coverage(self) -> float:
+        return self.__stats_ptr.y_coverage
+
+    @property
+    def b_total(self) -> int:
+        return self.__stats_ptr.b_total
+
+    @property
+    def y_total(self) -> int:
+        return self.__stats_ptr.y_total
+
+    @property
+    def b_matched(self) -> int:
+        return self.__stats_ptr.b_matched
+
+    @property
+    def y_matched(self) -> int:
+        return self.__stats_ptr.y_matched
+
+    @property
+    def longest_b_series(self) -> int:
+        return self.__stats_ptr.longest_b_series
+
+    @property
+    def longest_y_series(self) -> int:
+        return self.__stats_ptr.longest_y_series
+
+    def __repr__(self) -> str:
+        return (f"FragmentCoverageStats(b: {self.b_matched}/{self.b_total}, y: {self.y_matched}/{self.y_total}, "
+                f"longest_b_series: {self.longest_b_series}, longest_y_series: {self.longest_y_series})")
+
+    def get_py_ptr(self):
+        return self.__stats_ptr
+
+
 class Feature:
     def __init__(self, peptide_idx: PeptideIx, psm_id: int, peptide_len: int, spec_id: str, file_id: int,
                  rank: int, label: int, expmass: float, calcmass: float, charge: int, rt: float,
@@ -212,7 +563,22 @@ class Feature:
                  longest_y_pct: float, missed_cleavages: int, matched_intensity_pct: float,
                  scored_candidates: int, poisson: float, discriminant_score: float,
                  posterior_error: float, spectrum_q: float, peptide_q: float, protein_q: float,
-                 ms2_intensity: float, fragments: Optional[Fragments] = None):
+                 ms2_intensity: float, fragments: Optional[Fragments] = None,
+                 isotope_annotation_score: Optional[float] = None, xcorr: Optional[float] = None,
+                 delta_xcorr: Optional[float] = None, localization_scores: Optional[List[float]] = None,
+                 best_localization_site: Optional[int] = None, neutral_loss_intensity_pct: float = 0.0,
+                 spectral_entropy: float = 0.0, delta_spectral_entropy: float = 0.0,
+                 ms1_isotope_score: float = 0.0, ms1_intensity_ratio: float = 0.0,
+                 has_oxonium_evidence: bool = False, oxonium_score: float = 0.0, protein_q_razor: float = 1.0,
+                 silac_pair_idx: Optional[PeptideIx] = None, unexplained_intensity_pct: Optional[float] = None,
+                 coverage_stats: Optional[FragmentCoverageStats] = None,
+                 collision_energy_calibrated: Optional[float] = None,
+                 precursor_purity: Optional[float] = None, tag_match_score: Optional[float] = None,
+                 intensity_similarity: Optional[float] = None, chimera_score: Optional[float] = None,
+                 peptide_sequence: Optional[str] = None, re_score: Optional[float] = None,
+                 ims: Optional[float] = None, inverse_ion_mobility_predicted: Optional[float] = None,
+                 delta_ims_model: Optional[float] = None, intensity_weighted_ppm: Optional[float] = None,
+                 max_fragment_ppm: Optional[float] = None):
         """Feature class
 
         Args:
@@ -250,6 +616,55 @@ class Feature:
             peptide_q (float): The peptide q
             protein_q (float): The protein q
             ms2_intensity (float): The MS2 intensity
+            fragments (Optional[Fragments], optional): The matched fragments. Defaults to None.
+            isotope_annotation_score (Optional[float], optional): The mean isotope annotation score of the
+                matched fragments. Defaults to None, stored as NaN until set by annotate_isotope_annotation_scores.
+            xcorr (Optional[float], optional): The cross-correlation score. Defaults to None.
+            delta_xcorr (Optional[float], optional): The normalized xcorr difference to the next best
+                (rank 1) or best PSM. Defaults to None.
+            localization_scores (Optional[List[float]], optional): The site localization score per residue.
+                Defaults to None.
+            best_localization_site (Optional[int], optional): The residue position with the highest
+                localization score. Defaults to None.
+            neutral_loss_intensity_pct (float, optional): The percentage of the total ion current explained by
+                neutral loss ions. Defaults to 0.0.
+            spectral_entropy (float, optional): The normalized entropy of the spectrum peaks. Defaults to 0.0.
+            delta_spectral_entropy (float, optional): The normalized entropy of the matched minus the unmatched
+                peaks. Defaults to 0.0.
+            ms1_isotope_score (float, optional): The similarity of the precursor isotope envelope to the averagine
+                model. Defaults to 0.0.
+            ms1_intensity_ratio (float, optional): The monoisotopic fraction of the M to M+4 precursor intensity.
+                Defaults to 0.0.
+            has_oxonium_evidence (bool, optional): Whether diagnostic oxonium ions were found. Defaults to False.
+            oxonium_score (float, optional): The score of the matched oxonium ions. Defaults to 0.0.
+            protein_q_razor (float, optional): The q-value of the razor protein. Defaults to 1.0.
+            silac_pair_idx (Optional[PeptideIx], optional): The identified SILAC partner peptide. Defaults to None.
+            unexplained_intensity_pct (Optional[float], optional): The percentage of the total ion current not
+                explained by matched fragments. Defaults to None (100 - matched and neutral loss intensity).
+            coverage_stats (Optional[FragmentCoverageStats], optional): The b and y ion coverage. Defaults to None.
+            collision_energy_calibrated (Optional[float], optional): The calibrated collision energy of intensity
+                prediction. Defaults to None.
+            precursor_purity (Optional[float], optional): The fraction of the MS1 isolation window intensity from
+                the precursor. Defaults to None.
+            tag_match_score (Optional[float], optional): The summed score of the de novo sequence tags contained in
+                the peptide. Defaults to None.
+            intensity_similarity (Optional[float], optional): The similarity of observed and predicted fragment
+                intensities. Defaults to None.
+            chimera_score (Optional[float], optional): The combined score of the co-isolated peptide pair the PSM is
+                part of. Defaults to None.
+            peptide_sequence (Optional[str], optional): The modified peptide sequence in UNIMOD bracket notation of a
+                PSM imported from another tool, whose peptide_idx does not refer to a sagepy database. Defaults to None.
+            re_score (Optional[float], optional): The score of a re-scoring step. Defaults to None.
+            ims (Optional[float], optional): The observed inverse ion mobility (1/K0) of the precursor. Defaults to
+                None.
+            inverse_ion_mobility_predicted (Optional[float], optional): The inverse ion mobility predicted by a
+                MobilityModel. Defaults to None.
+            delta_ims_model (Optional[float], optional): The absolute difference of observed and predicted inverse
+                ion mobility. Defaults to None.
+            intensity_weighted_ppm (Optional[float], optional): The intensity-weighted mean of absolute fragment ppm
+                errors. Defaults to None, computed from the fragments (NaN without fragments).
+            max_fragment_ppm (Optional[float], optional): The largest absolute fragment ppm error. Defaults to None,
+                computed from the fragments (NaN without fragments).
         """
 
         self.__feature_ptr = psc.PyFeature(peptide_idx, psm_id, peptide_len, spec_id, file_id, rank, label,
@@ -260,7 +675,20 @@ class Feature:
                                            matched_intensity_pct, scored_candidates, poisson,
                                            discriminant_score, posterior_error, spectrum_q,
                                            peptide_q, protein_q, ms2_intensity,
-                                           fragments.get_py_ptr() if fragments is not None else None)
+                                           fragments.get_py_ptr() if fragments is not None else None,
+                                           isotope_annotation_score, xcorr, delta_xcorr,
+                                           localization_scores, best_localization_site,
+                                           neutral_loss_intensity_pct, spectral_entropy, delta_spectral_entropy,
+                                           ms1_isotope_score, ms1_intensity_ratio, has_oxonium_evidence,
+                                           oxonium_score, protein_q_razor,
+                                           silac_pair_idx.get_py_ptr() if silac_pair_idx is not None else None,
+                                           unexplained_intensity_pct,
+                                           coverage_stats.get_py_ptr() if coverage_stats is not None else None,
+                                           collision_energy_calibrated,
+                                           precursor_purity, tag_match_score, intensity_similarity,
+                                           chimera_score, peptide_sequence, re_score, ims,
+                                           inverse_ion_mobility_predicted, delta_ims_model,
+                                           intensity_weighted_ppm, max_fragment_ppm)
 
     @classmethod
     def from_py_feature(cls, feature: psc.PyFeature):
@@ -328,6 +756,10 @@ class Feature:
     def delta_mass(self) -> float:
         return self.__feature_ptr.delta_mass
 
+    @property
+    def delta_mass_open(self) -> float:
+        return self.__feature_ptr.delta_mass_open
+
     @property
     def isotope_error(self) -> float:
         return self.__feature_ptr.isotope_error
@@ -411,6 +843,159 @@ class Feature:
         else:
             return Fragments.from_py_fragments(self.__feature_ptr.fragments)
 
+    @staticmethod
+    def get_feature_names() -> List[str]:
+        return psc.PyFeature.get_feature_names()
+
+    def get_feature_values(self) -> List[float]:
+        return self.__feature_ptr.get_feature_values()
+
+    @staticmethod
+    def get_feature_names_vector() -> List[str]:
+        return psc.PyFeature.get_feature_names_vector()
+
+    def get_feature_vector(self) -> List[float]:
+        """Fixed-length score feature vector for machine learning re-scoring (e.g. mokapot or Percolator), without
+        q-values, in the order given by get_feature_names_vector. ims and delta_ims_model are NaN for PSMs
+        without an observed or predicted ion mobility, intensity_weighted_ppm and max_fragment_ppm for PSMs
+        scored without annotate_matches
+        """
+        return self.__feature_ptr.get_feature_vector()
+
+    def to_dict(self) -> Dict[str, object]:
+        """All fields keyed by name (the re-scoring feature names where applicable), unset optional values are None
+
+        Returns:
+            Dict[str, object]: The PSM, fragments as a nested dict with kinds as strings
+        """
+        return self.__feature_ptr.to_dict()
+
+    @classmethod
+    def from_dict(cls, d: Dict[str, object]) -> 'Feature':
+        """Rebuild a PSM from a dict created by to_dict, the keys of optional values may be missing
+
+        Args:
+            d (Dict[str, object]): The PSM fields
+
+        Returns:
+            Feature: The PSM
+        """
+        return cls.from_py_feature(psc.PyFeature.from_dict(d))
+
+    @property
+    def intensity_weighted_ppm(self) -> float:
+        return self.__feature_ptr.intensity_weighted_ppm
+
+    @property
+    def max_fragment_ppm(self) -> float:
+        return self.__feature_ptr.max_fragment_ppm
+
+    @property
+    def isotope_annotation_score(self) -> float:
+        return self.__feature_ptr.isotope_annotation_score
+
+    @property
+    def xcorr(self) -> Optional[float]:
+        return self.__feature_ptr.xcorr
+
+    @property
+    def delta_xcorr(self) -> Optional[float]:
+        return self.__feature_ptr.delta_xcorr
+
+    @property
+    def localization_scores(self) -> Optional[List[float]]:
+        return self.__feature_ptr.localization_scores
+
+    @property
+    def best_localization_site(self) -> Optional[int]:
+        return self.__feature_ptr.best_localization_site
+
+    @property
+    def neutral_loss_intensity_pct(self) -> float:
+        return self.__feature_ptr.neutral_loss_intensity_pct
+
+    @property
+    def spectral_entropy(self) -> float:
+        return self.__feature_ptr.spectral_entropy
+
+    @property
+    def delta_spectral_entropy(self) -> float:
+        return self.__feature_ptr.delta_spectral_entropy
+
+    @property
+    def ms1_isotope_score(self) -> float:
+        return self.__feature_ptr.ms1_isotope_score
+
+    @property
+    def ms1_intensity_ratio(self) -> float:
+        return self.__feature_ptr.ms1_intensity_ratio
+
+    @property
+    def has_oxonium_evidence(self) -> bool:
+        return self.__feature_ptr.has_oxonium_evidence
+
+    @property
+    def oxonium_score(self) -> float:
+        return self.__feature_ptr.oxonium_score
+
+    @property
+    def protein_q_razor(self) -> float:
+        return self.__feature_ptr.protein_q_razor
+
+    @property
+    def silac_pair_idx(self) -> Optional[PeptideIx]:
+        idx = self.__feature_ptr.silac_pair_idx
+        return PeptideIx.from_py_peptide_ix(idx) if idx is not None else None
+
+    @property
+    def unexplained_intensity_pct(self) -> float:
+        return self.__feature_ptr.unexplained_intensity_pct
+
+    @property
+    def coverage_stats(self) -> Optional[FragmentCoverageStats]:
+        stats = self.__feature_ptr.coverage_stats
+        return FragmentCoverageStats.from_py_fragment_coverage_stats(stats) if stats is not None else None
+
+    @property
+    def collision_energy_calibrated(self) -> Optional[float]:
+        return self.__feature_ptr.collision_energy_calibrated
+
+    @property
+    def precursor_purity(self) -> Optional[float]:
+        return self.__feature_ptr.precursor_purity
+
+    @property
+    def tag_match_score(self) -> Optional[float]:
+        return self.__feature_ptr.tag_match_score
+
+    @property
+    def intensity_similarity(self) -> Optional[float]:
+        return self.__feature_ptr.intensity_similarity
+
+    @property
+    def chimera_score(self) -> Optional[float]:
+        return self.__feature_ptr.chimera_score
+
+    @property
+    def peptide_sequence(self) -> Optional[str]:
+        return self.__feature_ptr.peptide_sequence
+
+    @property
+    def re_score(self) -> Optional[float]:
+        return self.__feature_ptr.re_score
+
+    @property
+    def ims(self) -> Optional[float]:
+        return self.__feature_ptr.ims
+
+    @property
+    def inverse_ion_mobility_predicted(self) -> Optional[float]:
+        return self.__feature_ptr.inverse_ion_mobility_predicted
+
+    @property
+    def delta_ims_model(self) -> Optional[float]:
+        return self.__feature_ptr.delta_ims_model
+
     def __repr__(self):
         return (f"Feature("
                 f"idx: {self.peptide_idx}, "
@@ -451,3 +1036,1164 @@ class Feature:
 
     def get_py_ptr(self):
         return self.__feature_ptr
+
+
+class SearchConfiguration:
+    def __init__(self, scorer: Scorer, parameters: SageSearchConfiguration):
+        """SearchConfiguration class, bundles scoring and database parameters of a search
+
+        Args:
+            scorer (Scorer): The scorer
+            parameters (SageSearchConfiguration): The database parameters
+        """
+        self.__search_configuration_ptr = psc.PySearchConfiguration(scorer.get_py_ptr(), parameters.get_py_ptr())
+
+    @classmethod
+    def from_py_search_configuration(cls, search_configuration: psc.PySearchConfiguration):
+        instance = cls.__new__(cls)
+        instance.__search_configuration_ptr = search_configuration
+        return instance
+
+    def get_py_ptr(self):
+        return self.__search_configuration_ptr
+
+    @property
+    def scorer(self) -> Scorer:
+        return Scorer.from_py_scorer(self.__search_configuration_ptr.scorer)
+
+    @property
+    def parameters(self) -> SageSearchConfiguration:
+        return SageSearchConfiguration.from_py_parameters(self.__search_configuration_ptr.parameters)
+
+    def to_json(self) -> str:
+        return self.__search_configuration_ptr.to_json()
+
+    @classmethod
+    def from_json(cls, json: str) -> 'SearchConfiguration':
+        return cls.from_py_search_configuration(psc.PySearchConfiguration.from_json(json))
+
+    def __repr__(self):
+        return f"SearchConfiguration(scorer: {self.scorer}, parameters: {self.parameters})"
+
+
+def rescore_with_function(features: List[Feature],
+                          score_fn: Callable[[List[str], List[float]], float]) -> List[Feature]:
+    """Re-score PSMs with a custom score combination function, the result is stored as re_score
+
+    Args:
+        features (List[Feature]): The PSMs to re-score
+        score_fn (Callable[[List[str], List[float]], float]): Called with the feature names and values of each PSM
+
+    Returns:
+        List[Feature]: The re-scored PSMs
+    """
+    return [Feature.from_py_feature(f) for f in
+            psc.rescore_with_function([f.get_py_ptr() for f in features], score_fn)]
+
+
+def features_to_matrix(features: List[Feature], num_threads: int = 4) -> NDArray:
+    """Feature vectors of PSMs as a matrix, see Feature.get_feature_vector
+
+    Args:
+        features (List[Feature]): The PSMs
+        num_threads (int, optional): The number of threads. Defaults to 4.
+
+    Returns:
+        NDArray: One row per PSM, the columns are named by Feature.get_feature_names_vector
+    """
+    return np.array(psc.features_to_matrix([f.get_py_ptr() for f in features], num_threads))
+
+
+def tdc_q_values(features: List[Feature]) -> List[Feature]:
+    """Calculate spectrum q-values under target-decoy competition: only the best PSM of each spectrum
+    (file_id, spec_id) is kept, FDR is estimated as the number of decoy winners divided by the number of winners
+
+    Args:
+        features (List[Feature]): The PSMs
+
+    Returns:
+        List[Feature]: The winning PSMs sorted by hyperscore with spectrum_q set
+    """
+    return [Feature.from_py_feature(f) for f in psc.tdc_q_values([f.get_py_ptr() for f in features])]
+
+
+def search_hash(config: SearchConfiguration) -> str:
+    """SHA-256 hex digest of a search configuration, allows quick comparison of search settings
+
+    Args:
+        config (SearchConfiguration): The search configuration
+
+    Returns:
+        str: The hex digest
+    """
+    return psc.search_hash(config.get_py_ptr())
+
+
+def compute_spectral_entropy(spectrum: ProcessedSpectrum) -> float:
+    """Normalized Shannon entropy of the peak intensities of a spectrum
+
+    Args:
+        spectrum (ProcessedSpectrum): The spectrum
+
+    Returns:
+        float: The entropy in [0, 1], low values indicate few dominant peaks
+    """
+    return psc.compute_spectral_entropy(spectrum.get_py_ptr())
+
+
+def compute_theoretical_spectrum(sequence: str, modifications: Optional[Dict[int, float]] = None, charge: int = 2,
+                                 fragment_charges: Optional[List[int]] = None,
+                                 ion_kinds: Optional[List[IonType]] = None, min_ordinal: int = 1) -> Fragments:
+    """Theoretical fragments of a peptide without a database search, e.g. for spectral libraries or annotation
+
+    Args:
+        sequence (str): The peptide sequence
+        modifications (Optional[Dict[int, float]], optional): The mass delta per 0-based residue position.
+            Defaults to None.
+        charge (int, optional): The precursor charge. Defaults to 2.
+        fragment_charges (Optional[List[int]], optional): The fragment charges. Defaults to None (1 up to charge - 1).
+        ion_kinds (Optional[List[IonType]], optional): The ion types. Defaults to None (b and y).
+        min_ordinal (int, optional): The minimum fragment ordinal. Defaults to 1.
+
+    Returns:
+        Fragments: The fragments, with mz_calculated set and mz_experimental and intensities zeroed
+    """
+    if ion_kinds is None:
+        ion_kinds = [IonType.b(), IonType.y()]
+    return Fragments.from_py_fragments(sagepy_connector.py_ion_series.compute_theoretical_spectrum(
+        sequence, modifications if modifications is not None else {}, charge,
+        fragment_charges if fragment_charges is not None else [], [k.get_py_ptr() for k in ion_kinds],
+        min_ordinal))
+
+
+class FragmentAnnotation:
+    """A theoretical ion and the observed peak it matches, or an observed peak no ion explains (empty ion_type)"""
+    @classmethod
+    def from_py_fragment_annotation(cls, annotation: sagepy_connector.py_ion_series.PyFragmentAnnotation):
+        instance = cls.__new__(cls)
+        instance.__annotation_ptr = annotation
+        return instance
+
+    @property
+    def ion_type(self) -> str:
+        return self.__annotation_ptr.ion_type
+
+    @property
+    def ordinal(self) -> int:
+        return self.__annotation_ptr.ordinal
+
+    @property
+    def charge(self) -> int:
+        return self.__annotation_ptr.charge
+
+    @property
+    def theoretical_mz(self) -> float:
+        return self.__annotation_ptr.theoretical_mz
+
+    @property
+    def observed_mz(self) -> float:
+        return self.__annotation_ptr.observed_mz
+
+    @property
+    def observed_intensity(self) -> float:
+        return self.__annotation_ptr.observed_intensity
+
+    @property
+    def ppm_error(self) -> float:
+        return self.__annotation_ptr.ppm_error
+
+    @property
+    def matched(self) -> bool:
+        return self.__annotation_ptr.matched
+
+    def __repr__(self):
+        return f"FragmentAnnotation(ion_type: {self.ion_type}, ordinal: {self.ordinal}, charge: {self.charge}, " \
+               f"theoretical_mz: {self.theoretical_mz}, observed_mz: {self.observed_mz}, " \
+               f"observed_intensity: {self.observed_intensity}, ppm_error: {self.ppm_error}, matched: {self.matched})"
+
+    def get_py_ptr(self):
+        return self.__annotation_ptr
+
+
+def annotate_spectrum(sequence: str, observed_mz: List[float], observed_intensity: List[float],
+                      fragment_tolerance: Tolerance, modifications: Optional[Dict[int, float]] = None,
+                      charge: int = 2, ion_kinds: Optional[List[IonType]] = None) -> List[FragmentAnnotation]:
+    """Annotate an observed spectrum with the theoretical ions of a peptide, each ion is matched to the most intense
+    peak within the tolerance, observed peaks matching no ion are reported with an empty ion_type
+
+    Args:
+        sequence (str): The peptide sequence
+        observed_mz (List[float]): The observed m/z values
+        observed_intensity (List[float]): The observed intensities
+        fragment_tolerance (Tolerance): The fragment tolerance
+        modifications (Optional[Dict[int, float]], optional): The mass delta per 0-based residue position.
+            Defaults to None.
+        charge (int, optional): The precursor charge. Defaults to 2.
+        ion_kinds (Optional[List[IonType]], optional): The ion types. Defaults to None (b and y).
+
+    Returns:
+        List[FragmentAnnotation]: The annotation, sorted by theoretical m/z
+    """
+    if ion_kinds is None:
+        ion_kinds = [IonType.b(), IonType.y()]
+    return [FragmentAnnotation.from_py_fragment_annotation(a) for a in
+            sagepy_connector.py_ion_series.annotate_spectrum(
+                sequence, modifications if modifications is not None else {}, charge, observed_mz,
+                observed_intensity, fragment_tolerance.get_py_ptr(), [k.get_py_ptr() for k in ion_kinds])]
+
+
+def unannotated_peaks(annotation: List[FragmentAnnotation]) -> List[Tuple[float, float]]:
+    """The observed peaks no theoretical ion explains
+
+    Args:
+        annotation (List[FragmentAnnotation]): The annotation of a spectrum
+
+    Returns:
+        List[Tuple[float, float]]: The (m/z, intensity) of the unannotated peaks
+    """
+    return sagepy_connector.py_ion_series.unannotated_peaks([a.get_py_ptr() for a in annotation])
+
+
+def score_with_ms1_isotope_evidence(feature: Feature, ms1_spectrum: ProcessedSpectrum,
+                                    isotope_window: float = 0.02) -> Feature:
+    """Annotate a PSM with the isotope evidence of its precursor: ms1_isotope_score is the cosine similarity of the
+    observed M, M+1 and M+2 peaks to the averagine distribution, ms1_intensity_ratio is I(M) / sum(I(M..M+4))
+
+    Args:
+        feature (Feature): The PSM
+        ms1_spectrum (ProcessedSpectrum): The MS1 spectrum containing the precursor
+        isotope_window (float, optional): The m/z tolerance of the isotope peaks. Defaults to 0.02.
+
+    Returns:
+        Feature: The annotated PSM
+    """
+    return Feature.from_py_feature(
+        psc.score_with_ms1_isotope_evidence(feature.get_py_ptr(), ms1_spectrum.get_py_ptr(), isotope_window))
+
+
+def tag_precursor_purity(psms: List[Feature], ms1_spectra: Dict[str, ProcessedSpectrum], isolation_window_da: float,
+                         tolerance_ppm: float = 10.0) -> List[Feature]:
+    """Tag PSMs with the purity of their precursor isolation window, the intensity of the precursor isotope envelope
+    relative to all MS1 peaks in the window centered on the experimental precursor m/z
+
+    Args:
+        psms (List[Feature]): The PSMs
+        ms1_spectra (Dict[str, ProcessedSpectrum]): The MS1 spectrum the precursor of each spec_id was isolated from
+        isolation_window_da (float): The full width of the isolation window in Da
+        tolerance_ppm (float, optional): The tolerance of the precursor peaks in ppm. Defaults to 10.0.
+
+    Returns:
+        List[Feature]: The PSMs with precursor_purity set, PSMs without an MS1 spectrum stay untagged
+    """
+    result = sagepy_connector.py_spectrum.tag_precursor_purity(
+        [p.get_py_ptr() for p in psms], {k: v.get_py_ptr() for k, v in ms1_spectra.items()}, isolation_window_da,
+        tolerance_ppm)
+    return [Feature.from_py_feature(p) for p in result]
+
+
+def filter_by_precursor_purity(psms: List[Feature], min_purity: float) -> List[Feature]:
+    """Select the PSMs with a precursor purity of at least min_purity, untagged PSMs are dropped
+
+    Args:
+        psms (List[Feature]): The PSMs, tagged by tag_precursor_purity
+        min_purity (float): The minimal precursor purity, e.g. 0.95
+
+    Returns:
+        List[Feature]: The selected PSMs
+    """
+    result = sagepy_connector.py_spectrum.filter_by_precursor_purity([p.get_py_ptr() for p in psms], min_purity)
+    return [Feature.from_py_feature(p) for p in result]
+
+
+def xic_for_peptide(feature: Feature, ms1_spectra: List[Tuple[float, ProcessedSpectrum]],
+                    tolerance_ppm: float = 10.0) -> List[Tuple[float, float]]:
+    """Extract the monoisotopic ion chromatogram of the theoretical precursor m/z of a PSM
+
+    Args:
+        feature (Feature): The PSM
+        ms1_spectra (List[Tuple[float, ProcessedSpectrum]]): The (retention time, MS1 spectrum) pairs
+        tolerance_ppm (float, optional): The m/z tolerance in ppm. Defaults to 10.0.
+
+    Returns:
+        List[Tuple[float, float]]: The (retention time, intensity) pairs sorted by retention time
+    """
+    return sagepy_connector.py_spectrum.xic_for_peptide(
+        feature.get_py_ptr(), [(rt, s.get_py_ptr()) for rt, s in ms1_spectra], tolerance_ppm)
+
+
+def delta_mass_histogram(features: List[Feature], fdr_cutoff: Optional[float] = 0.01, bin_width_da: float = 0.01,
+                         range_da: Tuple[float, float] = (-250.0, 250.0)) -> List[Tuple[float, int]]:
+    """Histogram of precursor mass shifts (experimental - calculated mass, in Da) of confident target PSMs
+
+    Args:
+        features (List[Feature]): The scored features
+        fdr_cutoff (Optional[float], optional): The maximum spectrum q-value of a PSM to be counted, None counts
+            all target PSMs, e.g. of an open search. Defaults to 0.01.
+        bin_width_da (float, optional): The bin width in Da. Defaults to 0.01.
+        range_da (Tuple[float, float], optional): The mass shift range in Da. Defaults to (-250.0, 250.0).
+
+    Returns:
+        List[Tuple[float, int]]: The (bin centre, count) pairs
+    """
+    return psc.delta_mass_histogram([f.get_py_ptr() for f in features], fdr_cutoff, bin_width_da, range_da)
+
+
+def delta_mass_peaks(histogram: List[Tuple[float, int]], min_prominence: int = 10) -> List[float]:
+    """Find significant peaks in a delta mass histogram, e.g. uncharacterised modifications
+
+    Args:
+        histogram (List[Tuple[float, int]]): The (bin centre, count) pairs from delta_mass_histogram
+        min_prominence (int, optional): The minimum prominence of a peak. Defaults to 10.
+
+    Returns:
+        List[float]: The bin centres of the detected peaks
+    """
+    return psc.delta_mass_peaks(histogram, min_prominence)
+
+
+def isotope_annotation_score(spectrum: RawSpectrum, fragment_mz: float, charge: int,
+                             tolerance: Tolerance) -> float:
+    """Score how well the M+1 isotope peak of a fragment matches the expected (averagine) 13C abundance,
+    the M+1 peak is looked up NEUTRON / charge above the fragment m/z
+
+    Args:
+        spectrum (RawSpectrum): The raw (not deisotoped) spectrum
+        fragment_mz (float): The m/z of the fragment
+        charge (int): The charge of the fragment
+        tolerance (Tolerance): The fragment tolerance
+
+    Returns:
+        float: The score between 0 and 1, 1 meaning a perfect match of the isotope envelope
+    """
+    return psc.isotope_annotation_score(spectrum.get_py_ptr(), fragment_mz, charge, tolerance.get_py_ptr())
+
+
+def total_isotope_annotation_score(spectrum: RawSpectrum, fragments: Fragments, tolerance: Tolerance) -> float:
+    """Mean isotope annotation score over all matched fragments
+
+    Args:
+        spectrum (RawSpectrum): The raw (not deisotoped) spectrum
+        fragments (Fragments): The matched fragments
+        tolerance (Tolerance): The fragment tolerance
+
+    Returns:
+        float: The mean score between 0 and 1
+    """
+    return psc.total_isotope_annotation_score(spectrum.get_py_ptr(), fragments.get_py_ptr(),
+                                              tolerance.get_py_ptr())
+
+
+def annotate_isotope_annotation_scores(features: List['Feature'], spectrum: RawSpectrum,
+                                       tolerance: Tolerance) -> List['Feature']:
+    """Set the isotope annotation score of PSMs from the raw peaks of their spectrum. Scoring works on
+    deisotoped peaks and leaves the score NaN, this extra step is needed to fill it in. PSMs without
+    annotated fragments (see annotate_matches) keep a score of NaN
+
+    Args:
+        features (List[Feature]): The PSMs of the spectrum
+        spectrum (RawSpectrum): The raw (not deisotoped) spectrum
+        tolerance (Tolerance): The fragment tolerance
+
+    Returns:
+        List[Feature]: The annotated PSMs
+    """
+    return [Feature.from_py_feature(f) for f in psc.annotate_isotope_annotation_scores(
+        [f.get_py_ptr() for f in features], spectrum.get_py_ptr(), tolerance.get_py_ptr())]
+
+
+def score_by_length_stratum(scorer: Scorer, db: IndexedDatabase, spectra: List[ProcessedSpectrum],
+                            strata: List[Tuple[int, int]]) -> List[List[Feature]]:
+    """Score spectra separately against length-stratified sub-databases, e.g. to keep short HLA ligands
+    from sharing a score distribution with tryptic peptides
+
+    Args:
+        scorer (Scorer): The scorer
+        db (IndexedDatabase): The database
+        spectra (List[ProcessedSpectrum]): The spectra to score
+        strata (List[Tuple[int, int]]): The inclusive (min_len, max_len) peptide length ranges
+
+    Returns:
+        List[List[Feature]]: Per spectrum, the PSMs of the stratum with the highest scoring top hit,
+            peptide indices refer to the full database
+    """
+    result = psc.score_by_length_stratum(scorer.get_py_ptr(), db.get_py_ptr(),
+                                         [s.get_py_ptr() for s in spectra], strata)
+    return [[Feature.from_py_feature(f) for f in features] for features in result]
+
+
+class MassCalibration:
+    @classmethod
+    def from_py_mass_calibration(cls, calibration: psc.PyMassCalibration):
+        instance = cls.__new__(cls)
+        instance.__mass_calibration_ptr = calibration
+        return instance
+
+    def get_py_ptr(self):
+        return self.__mass_calibration_ptr
+
+    @property
+    def model(self) -> str:
+        return self.__mass_calibration_ptr.model
+
+    @property
+    def coefficients(self) -> List[float]:
+        return self.__mass_calibration_ptr.coefficients
+
+    @property
+    def residual_rms(self) -> float:
+        return self.__mass_calibration_ptr.residual_rms
+
+    @property
+    def num_anchors(self) -> int:
+        return self.__mass_calibration_ptr.num_anchors
+
+    def predict(self, calcmass: float) -> float:
+        """Predicted systematic precursor mass error in ppm at a calculated mass
+
+        Args:
+            calcmass (float): The calculated (theoretical) precursor mass
+
+        Returns:
+            float: The predicted mass error in ppm
+        """
+        return self.__mass_calibration_ptr.predict(calcmass)
+
+    def __repr__(self):
+        return f"MassCalibration(model: {self.model}, coefficients: {self.coefficients}, " \
+               f"residual_rms: {self.residual_rms}, num_anchors: {self.num_anchors})"
+
+
+def recalibrate_masses(features: List[Feature], fdr_cutoff: float = 0.01,
+                       recalib_model: str = 'linear') -> MassCalibration:
+    """Recalibrate precursor masses using high-confidence target PSMs (spectrum_q < fdr_cutoff) as anchors,
+    expmass and delta_mass of all PSMs are corrected in place
+
+    Args:
+        features (List[Feature]): The PSMs
+        fdr_cutoff (float, optional): The spectrum q-value cutoff for anchor PSMs. Defaults to 0.01.
+        recalib_model (str, optional): The model of the ppm error as function of calcmass,
+            'linear', 'polynomial_2' or 'loess'. Defaults to 'linear'.
+
+    Returns:
+        MassCalibration: The fitted calibration
+    """
+    return MassCalibration.from_py_mass_calibration(
+        psc.recalibrate_masses([f.get_py_ptr() for f in features], fdr_cutoff, recalib_model))
+
+
+class MassErrorOffset:
+    @classmethod
+    def from_py_mass_error_offset(cls, offset: psc.PyMassErrorOffset):
+        instance = cls.__new__(cls)
+        instance.__mass_error_offset_ptr = offset
+        return instance
+
+    def get_py_ptr(self):
+        return self.__mass_error_offset_ptr
+
+    @property
+    def offset_ppm(self) -> float:
+        return self.__mass_error_offset_ptr.offset_ppm
+
+    @property
+    def ci_lower(self) -> float:
+        return self.__mass_error_offset_ptr.ci_lower
+
+    @property
+    def ci_upper(self) -> float:
+        return self.__mass_error_offset_ptr.ci_upper
+
+    @property
+    def num_observations(self) -> int:
+        return self.__mass_error_offset_ptr.num_observations
+
+    def __repr__(self):
+        return f"MassErrorOffset(offset_ppm: {self.offset_ppm}, ci: ({self.ci_lower}, {self.ci_upper}), " \
+               f"num_observations: {self.num_observations})"
+
+
+def calibrate_mass_error(features: List[Feature], quantile: float = 0.5,
+                         fdr_cutoff: float = 0.01) -> MassErrorOffset:
+    """Estimate the systematic precursor mass error as a quantile of the signed ppm errors of
+    high-confidence target PSMs, outliers beyond 1.5 IQR are removed first
+
+    Args:
+        features (List[Feature]): The PSMs
+        quantile (float, optional): The quantile of the mass errors taken as offset. Defaults to 0.5.
+        fdr_cutoff (float, optional): The spectrum q-value cutoff of the PSMs used. Defaults to 0.01.
+
+    Returns:
+        MassErrorOffset: The offset in ppm with its 95% confidence interval
+    """
+    return MassErrorOffset.from_py_mass_error_offset(
+        psc.calibrate_mass_error([f.get_py_ptr() for f in features], quantile, fdr_cutoff))
+
+
+def calibrate_fragment_error(features: List[Feature], quantile: float = 0.5,
+                             fdr_cutoff: float = 0.01) -> MassErrorOffset:
+    """Estimate the systematic fragment mass error as a quantile of the signed ppm errors of the
+    matched fragments of high-confidence target PSMs, requires PSMs scored with fragment annotation
+
+    Args:
+        features (List[Feature]): The PSMs
+        quantile (float, optional): The quantile of the mass errors taken as offset. Defaults to 0.5.
+        fdr_cutoff (float, optional): The spectrum q-value cutoff of the PSMs used. Defaults to 0.01.
+
+    Returns:
+        MassErrorOffset: The offset in ppm with its 95% confidence interval
+    """
+    return MassErrorOffset.from_py_mass_error_offset(
+        psc.calibrate_fragment_error([f.get_py_ptr() for f in features], quantile, fdr_cutoff))
+
+
+def apply_mass_calibration(spectra: List[ProcessedSpectrum], offset_ppm: float) -> List[ProcessedSpectrum]:
+    """Remove a systematic offset from the precursor m/z values of the spectra
+
+    Args:
+        spectra (List[ProcessedSpectrum]): The spectra
+        offset_ppm (float): The precursor mass error offset in ppm
+
+    Returns:
+        List[ProcessedSpectrum]: The calibrated spectra
+    """
+    result = psc.apply_mass_calibration([s.get_py_ptr() for s in spectra], offset_ppm)
+    return [ProcessedSpectrum.from_py_processed_spectrum(s) for s in result]
+
+
+def apply_fragment_calibration(spectra: List[ProcessedSpectrum], offset_ppm: float) -> List[ProcessedSpectrum]:
+    """Remove a systematic offset from the fragment peaks of the spectra
+
+    Args:
+        spectra (List[ProcessedSpectrum]): The spectra
+        offset_ppm (float): The fragment mass error offset in ppm
+
+    Returns:
+        List[ProcessedSpectrum]: The calibrated spectra
+    """
+    result = psc.apply_fragment_calibration([s.get_py_ptr() for s in spectra], offset_ppm)
+    return [ProcessedSpectrum.from_py_processed_spectrum(s) for s in result]
+
+
+def calibrate_spectra(spectra: List[ProcessedSpectrum], features: List[Feature], quantile: float = 0.5,
+                      fdr_cutoff: float = 0.01) -> Tuple[List[ProcessedSpectrum], MassErrorOffset, MassErrorOffset]:
+    """Preprocessing step removing systematic precursor and fragment mass errors, estimated from a first
+    search of the spectra, the calibrated spectra are meant to be searched again
+
+    Args:
+        spectra (List[ProcessedSpectrum]): The spectra
+        features (List[Feature]): The PSMs of a first search, scored with fragment annotation
+        quantile (float, optional): The quantile of the mass errors taken as offset. Defaults to 0.5.
+        fdr_cutoff (float, optional): The spectrum q-value cutoff of the PSMs used. Defaults to 0.01.
+
+    Returns:
+        Tuple[List[ProcessedSpectrum], MassErrorOffset, MassErrorOffset]: The calibrated spectra, the
+            precursor and the fragment offset
+    """
+    precursor_offset = calibrate_mass_error(features, quantile, fdr_cutoff)
+    fragment_offset = calibrate_fragment_error(features, quantile, fdr_cutoff)
+    spectra = apply_mass_calibration(spectra, precursor_offset.offset_ppm)
+    spectra = apply_fragment_calibration(spectra, fragment_offset.offset_ppm)
+    return spectra, precursor_offset, fragment_offset
+
+
+def calibrate_fragment_masses(features: List[Feature], quantile: float = 0.5, fdr_cutoff: float = 0.01) -> float:
+    """Estimate the systematic fragment mass error in ppm, the offset of calibrate_fragment_error
+
+    Args:
+        features (List[Feature]): The PSMs, scored with fragment annotation
+        quantile (float, optional): The quantile of the mass errors taken as offset. Defaults to 0.5.
+        fdr_cutoff (float, optional): The spectrum q-value cutoff of the PSMs used. Defaults to 0.01.
+
+    Returns:
+        float: The fragment mass error offset in ppm
+    """
+    return psc.calibrate_fragment_masses([f.get_py_ptr() for f in features], quantile, fdr_cutoff)
+
+
+def rescore_with_fragment_calibration(features: List[Feature], offset_ppm: float, db: IndexedDatabase,
+                                      spectra: List[ProcessedSpectrum], scorer: Scorer,
+                                      num_threads: int = 4) -> List[Feature]:
+    """Rescore the spectra of PSMs with a systematic fragment mass error removed from their peaks, which centers
+    the fragment tolerance on the offset
+
+    Args:
+        features (List[Feature]): The PSMs
+        offset_ppm (float): The fragment mass error offset in ppm, e.g. of calibrate_fragment_masses
+        db (IndexedDatabase): The database
+        spectra (List[ProcessedSpectrum]): The spectra, those of the PSMs are rescored
+        scorer (Scorer): The scorer
+        num_threads (int, optional): The number of threads. Defaults to 4.
+
+    Returns:
+        List[Feature]: The rescored PSMs, PSMs of spectra not given are kept unchanged, q-values need to be
+            recomputed
+    """
+    result = psc.rescore_with_fragment_calibration([f.get_py_ptr() for f in features], offset_ppm, db.get_py_ptr(),
+                                                   [s.get_py_ptr() for s in spectra], scorer.get_py_ptr(),
+                                                   num_threads)
+    return [Feature.from_py_feature(f) for f in result]
+
+
+class MassAccuracyStats:
+    def __init__(self):
+        raise NotImplementedError("MassAccuracyStats is created by compute_mass_accuracy_stats")
+
+    @classmethod
+    def from_py_mass_accuracy_stats(cls, stats: psc.PyMassAccuracyStats):
+        instance = cls.__new__(cls)
+        instance.__mass_accuracy_stats_ptr = stats
+        return instance
+
+    def get_py_ptr(self):
+        return self.__mass_accuracy_stats_ptr
+
+    @property
+    def mean_ppm(self) -> float:
+        return self.__mass_accuracy_stats_ptr.mean_ppm
+
+    @property
+    def median_ppm(self) -> float:
+        return self.__mass_accuracy_stats_ptr.median_ppm
+
+    @property
+    def std_ppm(self) -> float:
+        return self.__mass_accuracy_stats_ptr.std_ppm
+
+    @property
+    def percentile_5(self) -> float:
+        return self.__mass_accuracy_stats_ptr.percentile_5
+
+    @property
+    def percentile_95(self) -> float:
+        return self.__mass_accuracy_stats_ptr.percentile_95
+
+    @property
+    def num_psms(self) -> int:
+        return self.__mass_accuracy_stats_ptr.num_psms
+
+    def __repr__(self):
+        return f"MassAccuracyStats(mean_ppm: {self.mean_ppm}, median_ppm: {self.median_ppm}, " \
+               f"std_ppm: {self.std_ppm}, percentile_5: {self.percentile_5}, " \
+               f"percentile_95: {self.percentile_95}, num_psms: {self.num_psms})"
+
+
+def compute_mass_accuracy_stats(features: List[Feature], fdr_threshold: float = 0.01) -> MassAccuracyStats:
+    """Summarize the signed precursor mass errors of confident target PSMs for quality control
+
+    Args:
+        features (List[Feature]): The PSMs
+        fdr_threshold (float, optional): The maximum spectrum q-value of PSMs included. Defaults to 0.01.
+
+    Returns:
+        MassAccuracyStats: The mean, median, standard deviation and 5th/95th percentile of the errors in ppm
+    """
+    return MassAccuracyStats.from_py_mass_accuracy_stats(
+        psc.compute_mass_accuracy_stats([f.get_py_ptr() for f in features], fdr_threshold))
+
+
+def mass_accuracy_over_time(features: List[Feature], fdr_threshold: float = 0.01) -> List[Tuple[float, float]]:
+    """Signed precursor mass errors of confident target PSMs along the run
+
+    Args:
+        features (List[Feature]): The PSMs
+        fdr_threshold (float, optional): The maximum spectrum q-value of PSMs included. Defaults to 0.01.
+
+    Returns:
+        List[Tuple[float, float]]: The retention time and mass error in ppm of each PSM, sorted by retention time
+    """
+    return psc.mass_accuracy_over_time([f.get_py_ptr() for f in features], fdr_threshold)
+
+
+def detect_mass_calibration_drift(features: List[Feature], window_size: int = 100,
+                                  fdr_threshold: float = 0.01) -> List[float]:
+    """Rolling mean of the mass errors of mass_accuracy_over_time, a trend indicates a drifting mass calibration
+
+    Args:
+        features (List[Feature]): The PSMs
+        window_size (int, optional): The number of PSMs of the window centered on each PSM. Defaults to 100.
+        fdr_threshold (float, optional): The maximum spectrum q-value of PSMs included. Defaults to 0.01.
+
+    Returns:
+        List[float]: The rolling mean mass error in ppm, in the order of mass_accuracy_over_time
+    """
+    return psc.detect_mass_calibration_drift([f.get_py_ptr() for f in features], window_size, fdr_threshold)
+
+
+class DeNovoTag:
+    def __init__(self, sequence: str, start_mz: float, score: float):
+        """De novo sequence tag, residues read between consecutive fragment peaks
+
+        Args:
+            sequence (str): The tag residues, Ile is reported as Leu
+            start_mz (float): The fragment m/z the tag starts at, the reading direction (b or y ladder) is unknown
+            score (float): The tag score
+        """
+        self.__de_novo_tag_ptr = psc.PyDeNovoTag(sequence, start_mz, score)
+
+    @classmethod
+    def from_py_de_novo_tag(cls, tag: psc.PyDeNovoTag):
+        instance = cls.__new__(cls)
+        instance.__de_novo_tag_ptr = tag
+        return instance
+
+    @property
+    def sequence(self) -> str:
+        return self.__de_novo_tag_ptr.sequence
+
+    @property
+    def start_mz(self) -> float:
+        return self.__de_novo_tag_ptr.start_mz
+
+    @property
+    def length(self) -> int:
+        return self.__de_novo_tag_ptr.length
+
+    @property
+    def score(self) -> float:
+        return self.__de_novo_tag_ptr.score
+
+    def __repr__(self):
+        return self.__de_novo_tag_ptr.__repr__()
+
+    def get_py_ptr(self):
+        return self.__de_novo_tag_ptr
+
+
+def generate_sequence_tags(spectrum: ProcessedSpectrum, min_length: int = 3,
+                           fragment_tolerance: Tolerance = Tolerance(ppm=(-10, 10)),
+                           max_tags: Optional[int] = 20) -> List[DeNovoTag]:
+    """Read de novo sequence tags from ladders of fragment peaks differing by residue masses
+
+    Args:
+        spectrum (ProcessedSpectrum): The spectrum
+        min_length (int, optional): The minimum number of residues of a tag. Defaults to 3.
+        fragment_tolerance (Tolerance, optional): The fragment tolerance. Defaults to Tolerance(ppm=(-10, 10)).
+        max_tags (Optional[int], optional): The maximum number of tags, None for all. Defaults to 20.
+
+    Returns:
+        List[DeNovoTag]: The tags, best first
+    """
+    result = psc.generate_sequence_tags(spectrum.get_py_ptr(), min_length, fragment_tolerance.get_py_ptr(), max_tags)
+    return [DeNovoTag.from_py_de_novo_tag(t) for t in result]
+
+
+def score_with_sequence_tags(spectrum: ProcessedSpectrum, tags: List[DeNovoTag], db: IndexedDatabase,
+                             fragment_tolerance: Tolerance = Tolerance(ppm=(-10, 10)),
+                             precursor_tolerance: Optional[Tolerance] = None) -> List[Feature]:
+    """Score the database peptides containing at least one of the tags (in either direction) against a spectrum
+
+    Args:
+        spectrum (ProcessedSpectrum): The spectrum
+        tags (List[DeNovoTag]): The tags, e.g. of generate_sequence_tags
+        db (IndexedDatabase): The database
+        fragment_tolerance (Tolerance, optional): The fragment tolerance. Defaults to Tolerance(ppm=(-10, 10)).
+        precursor_tolerance (Optional[Tolerance], optional): If given, only peptides matching the precursor mass
+            are scored. Defaults to None.
+
+    Returns:
+        List[Feature]: The PSMs ranked by hyperscore, with tag_match_score set
+    """
+    result = psc.score_with_sequence_tags(
+        spectrum.get_py_ptr(), [t.get_py_ptr() for t in tags], db.get_py_ptr(), fragment_tolerance.get_py_ptr(),
+        precursor_tolerance.get_py_ptr() if precursor_tolerance is not None else None)
+    return [Feature.from_py_feature(f) for f in result]
+
+
+class ChimeraPair:
+    def __init__(self):
+        raise NotImplementedError("ChimeraPair is created by score_chimera_with_mass_pairs")
+
+    @classmethod
+    def from_py_chimera_pair(cls, pair: psc.PyChimeraPair):
+        instance = cls.__new__(cls)
+        instance.__chimera_pair_ptr = pair
+        return instance
+
+    @property
+    def feature_a(self) -> Feature:
+        return Feature.from_py_feature(self.__chimera_pair_ptr.feature_a)
+
+    @property
+    def feature_b(self) -> Feature:
+        return Feature.from_py_feature(self.__chimera_pair_ptr.feature_b)
+
+    @property
+    def combined_score(self) -> float:
+        return self.__chimera_pair_ptr.combined_score
+
+    @property
+    def mass_pair_ppm(self) -> float:
+        return self.__chimera_pair_ptr.mass_pair_ppm
+
+    def __repr__(self):
+        return f"ChimeraPair(feature_a: {self.feature_a}, feature_b: {self.feature_b}, " \
+               f"combined_score: {self.combined_score}, mass_pair_ppm: {self.mass_pair_ppm})"
+
+    def get_py_ptr(self):
+        return self.__chimera_pair_ptr
+
+
+def score_chimera_with_mass_pairs(db: IndexedDatabase, spectrum: ProcessedSpectrum,
+                                  mass_tolerance: Tolerance = Tolerance(ppm=(-10, 10)), max_pairs: int = 10,
+                                  fragment_tolerance: Optional[Tolerance] = None) -> List[ChimeraPair]:
+    """Score a spectrum for pairs of co-isolated peptides, candidates are all peptides with a precursor m/z (at
+    charge 2 to 4) within the isolation window of the spectrum
+
+    Args:
+        db (IndexedDatabase): The database
+        spectrum (ProcessedSpectrum): The spectrum
+        mass_tolerance (Tolerance, optional): The tolerance widening the isolation window, which is +-1 Th for
+            spectra without one. Defaults to Tolerance(ppm=(-10, 10)).
+        max_pairs (int, optional): The maximum number of pairs returned. Defaults to 10.
+        fragment_tolerance (Optional[Tolerance], optional): The fragment tolerance. Defaults to None (20 ppm).
+
+    Returns:
+        List[ChimeraPair]: The pairs sorted by decreasing combined score
+    """
+    result = psc.score_chimera_with_mass_pairs(
+        db.get_py_ptr(), spectrum.get_py_ptr(), mass_tolerance.get_py_ptr(), max_pairs,
+        fragment_tolerance.get_py_ptr() if fragment_tolerance is not None else None)
+    return [ChimeraPair.from_py_chimera_pair(p) for p in result]
+
+
+def psms_to_arrow_ipc(features: List[Feature]) -> bytes:
+    """Encode PSMs as an Arrow record batch in IPC file format, the result can be read with
+    pyarrow.ipc.open_file(io.BytesIO(data)). Fragment annotations (including neutral losses) and per-residue
+    localization scores are not stored
+
+    Args:
+        features (List[Feature]): The PSMs
+
+    Returns:
+        bytes: The Arrow IPC file content
+    """
+    return psc.psms_to_arrow_ipc([f.get_py_ptr() for f in features])
+
+
+def psms_from_arrow_ipc(data: bytes) -> List[Feature]:
+    """Decode PSMs encoded with psms_to_arrow_ipc, columns missing in data written by older versions
+    are set to their defaults
+
+    Args:
+        data (bytes): The Arrow IPC file content
+
+    Returns:
+        List[Feature]: The PSMs
+    """
+    return [Feature.from_py_feature(f) for f in psc.psms_from_arrow_ipc(data)]
+
+
+def psms_to_parquet(features: List[Feature], path: str, compression: str = 'snappy',
+                    partition_by: Optional[str] = None) -> None:
+    """Write PSMs to Parquet, the result can be read with pandas.read_parquet. Fragment annotations (including
+    neutral losses) and per-residue localization scores are not stored
+
+    Args:
+        features (List[Feature]): The PSMs
+        path (str): The output file, or the output directory if partition_by is set
+        compression (str, optional): The compression, 'snappy', 'zstd' or 'none'. Defaults to 'snappy'.
+        partition_by (Optional[str], optional): Write one hive-style partition per 'file_id' or 'charge'.
+            Defaults to None.
+    """
+    psc.psms_to_parquet([f.get_py_ptr() for f in features], path, compression, partition_by)
+
+
+def annotate_silac_pairs(features: List[Feature], db: IndexedDatabase) -> List[Feature]:
+    """Detect SILAC pairs among PSMs collected from several searches, e.g. of all runs
+
+    Args:
+        features (List[Feature]): The PSMs
+        db (IndexedDatabase): The database built with SILAC labels
+
+    Returns:
+        List[Feature]: The PSMs, silac_pair_idx is set where the partner peptide is identified as well
+    """
+    result = psc.annotate_silac_pairs([f.get_py_ptr() for f in features], db.get_py_ptr())
+    return [Feature.from_py_feature(f) for f in result]
+
+
+def to_records(features: List[Feature]) -> List[Dict[str, object]]:
+    """Convert PSMs to a list of dicts (see Feature.to_dict), e.g. for pandas.DataFrame.from_records
+
+    Args:
+        features (List[Feature]): The PSMs
+
+    Returns:
+        List[Dict[str, object]]: One dict per PSM
+    """
+    return psc.to_records([f.get_py_ptr() for f in features])
+
+
+def group_by_spectrum(features: List[Feature]) -> Dict[str, List[Feature]]:
+    """Group PSMs by spec_id, the PSMs of each spectrum are ordered targets first, then by descending
+    hyperscore and ascending peptide index
+
+    Args:
+        features (List[Feature]): The PSMs
+
+    Returns:
+        Dict[str, List[Feature]]: The PSMs of each spectrum, keyed by spec_id in ascending order
+    """
+    result = psc.group_by_spectrum([f.get_py_ptr() for f in features])
+    return {spec_id: [Feature.from_py_feature(f) for f in psms] for spec_id, psms in sorted(result.items())}
+
+
+def as_flat_vec(results: Dict[str, List[Feature]]) -> List[Feature]:
+    """Flatten PSMs grouped by spectrum into a single list sorted by spec_id, then rank
+
+    Args:
+        results (Dict[str, List[Feature]]): The PSMs of each spectrum, e.g. from group_by_spectrum
+
+    Returns:
+        List[Feature]: The PSMs
+    """
+    result = psc.as_flat_vec({spec_id: [f.get_py_ptr() for f in psms] for spec_id, psms in results.items()})
+    return [Feature.from_py_feature(f) for f in result]
+
+
+def top_psm_per_spectrum(results: Dict[str, List[Feature]]) -> List[Feature]:
+    """Keep the rank 1 PSMs of PSMs grouped by spectrum
+
+    Args:
+        results (Dict[str, List[Feature]]): The PSMs of each spectrum, e.g. from group_by_spectrum
+
+    Returns:
+        List[Feature]: The rank 1 PSMs, sorted by spec_id
+    """
+    result = psc.top_psm_per_spectrum({spec_id: [f.get_py_ptr() for f in psms] for spec_id, psms in results.items()})
+    return [Feature.from_py_feature(f) for f in result]
+
+
+def compute_explained_intensity(psm: Feature) -> float:
+    """The fraction of the total ion current explained by the matched fragments of a PSM, neutral loss ions included
+
+    Args:
+        psm (Feature): The PSM
+
+    Returns:
+        float: The explained intensity fraction between 0 and 1
+    """
+    return psc.compute_explained_intensity(psm.get_py_ptr())
+
+
+def filter_by_explained_intensity(psms: List[Feature], min_pct: float) -> List[Feature]:
+    """Keep the PSMs whose matched fragments explain at least min_pct percent of the total ion current
+
+    Args:
+        psms (List[Feature]): The PSMs
+        min_pct (float): The minimum explained intensity in percent
+
+    Returns:
+        List[Feature]: The passing PSMs
+    """
+    return [Feature.from_py_feature(f) for f in
+            psc.filter_by_explained_intensity([p.get_py_ptr() for p in psms], min_pct)]
+
+
+class CollisionEnergyCalibration:
+    def __init__(self):
+        raise NotImplementedError("CollisionEnergyCalibration is created by calibrate_collision_energy")
+
+    @classmethod
+    def from_py_collision_energy_calibration(cls, calibration: psc.PyCollisionEnergyCalibration):
+        instance = cls.__new__(cls)
+        instance.__calibration_ptr = calibration
+        return instance
+
+    @property
+    def calibrated_ce(self) -> float:
+        return self.__calibration_ptr.calibrated_ce
+
+    @property
+    def curve(self) -> List[Tuple[float, float]]:
+        """(collision energy, median spectral angle) of all tested collision energies"""
+        return self.__calibration_ptr.curve
+
+    def __repr__(self):
+        return f"CollisionEnergyCalibration(calibrated_ce: {self.calibrated_ce}, steps: {len(self.curve)})"
+
+    def get_py_ptr(self):
+        return self.__calibration_ptr
+
+
+def calibrate_collision_energy(psms: List[Feature], predict_fn: Callable[[List[Feature], float], List[List[float]]],
+                               min_q_value: float = 0.01, ce_range: Tuple[float, float] = (20.0, 40.0),
+                               step: float = 1.0, num_threads: int = 4) -> CollisionEnergyCalibration:
+    """Calibrate the collision energy of a fragment intensity predictor (e.g. Prosit) on confident target PSMs
+    with annotated fragments (scored with annotate_matches)
+
+    Args:
+        psms (List[Feature]): The PSMs
+        predict_fn (Callable[[List[Feature], float], List[List[float]]]): Predicts the intensities of the annotated
+            fragments of each PSM at a collision energy
+        min_q_value (float, optional): The maximum spectrum q-value of the PSMs used. Defaults to 0.01.
+        ce_range (Tuple[float, float], optional): The inclusive collision energy range. Defaults to (20.0, 40.0).
+        step (float, optional): The collision energy step. Defaults to 1.0.
+        num_threads (int, optional): The number of threads of the spectral angle computation. Defaults to 4.
+
+    Returns:
+        CollisionEnergyCalibration: The collision energy with the highest median spectral angle and the curve
+    """
+    def predict(py_psms, ce):
+        return predict_fn([Feature.from_py_feature(p) for p in py_psms], ce)
+
+    return CollisionEnergyCalibration.from_py_collision_energy_calibration(
+        psc.calibrate_collision_energy([p.get_py_ptr() for p in psms], predict, min_q_value, ce_range, step,
+                                       num_threads))
+
+
+def apply_collision_energy_calibration(psms: List[Feature], calibrated_ce: float) -> List[Feature]:
+    """Set the calibrated collision energy of PSMs
+
+    Args:
+        psms (List[Feature]): The PSMs
+        calibrated_ce (float): The calibrated collision energy, e.g. of calibrate_collision_energy
+
+    Returns:
+        List[Feature]: The PSMs with collision_energy_calibrated set
+    """
+    return [Feature.from_py_feature(f) for f in
+            psc.apply_collision_energy_calibration([p.get_py_ptr() for p in psms], calibrated_ce)]
+
+
+def rescore_collection_with_predictions(psms: List[Feature], db: IndexedDatabase,
+                                        spectra: Dict[str, ProcessedSpectrum],
+                                        predicted_intensities: Dict[str, List[float]],
+                                        score_type: str = 'spectral_angle',
+                                        fragment_tolerance: Optional[Tolerance] = None,
+                                        num_threads: int = 4) -> List[Feature]:
+    """Compare the observed fragment intensities of PSMs to predicted ones (e.g. of Prosit or AlphaPeptDeep)
+
+    Args:
+        psms (List[Feature]): The PSMs
+        db (IndexedDatabase): The database the PSMs were scored against
+        spectra (Dict[str, ProcessedSpectrum]): The spectrum of each spec_id
+        predicted_intensities (Dict[str, List[float]]): The predicted intensities keyed by UNIMOD annotated
+            sequence and charge, e.g. 'PEPC[UNIMOD:4]TIDE/2', in the Prosit layout: y+, y++, y+++, b+, b++, b+++
+            for fragment ordinal 1, 2, ..., negative values mark impossible ions
+        score_type (str, optional): The similarity, 'spectral_angle', 'pearson' or 'dot_product'.
+            Defaults to 'spectral_angle'.
+        fragment_tolerance (Optional[Tolerance], optional): The fragment tolerance. Defaults to None (20 ppm).
+        num_threads (int, optional): The number of threads. Defaults to 4.
+
+    Returns:
+        List[Feature]: The PSMs with intensity_similarity set, PSMs without spectrum or prediction are unchanged
+    """
+    result = psc.rescore_collection_with_predictions(
+        [p.get_py_ptr() for p in psms], db.get_py_ptr(), {k: v.get_py_ptr() for k, v in spectra.items()},
+        predicted_intensities, psc.PyIntensityScoreType(score_type),
+        fragment_tolerance.get_py_ptr() if fragment_tolerance is not None else None, num_threads)
+    return [Feature.from_py_feature(f) for f in result]
+
+
+def compute_coverage_stats(psm: Feature) -> FragmentCoverageStats:
+    """Compute the matched fraction of the b and y ion series of a PSM, requires PSMs scored with annotate_matches
+
+    Args:
+        psm (Feature): The PSM
+
+    Returns:
+        FragmentCoverageStats: The coverage statistics
+    """
+    return FragmentCoverageStats.from_py_fragment_coverage_stats(psc.compute_coverage_stats(psm.get_py_ptr()))
+
+
+def compute_coverage_stats_batch(psms: List[Feature], num_threads: int = 4) -> List[FragmentCoverageStats]:
+    """Compute the coverage statistics of many PSMs in parallel
+
+    Args:
+        psms (List[Feature]): The PSMs
+        num_threads (int, optional): The number of threads. Defaults to 4.
+
+    Returns:
+        List[FragmentCoverageStats]: The coverage statistics, in the order of the PSMs
+    """
+    return [FragmentCoverageStats.from_py_fragment_coverage_stats(s) for s in
+            psc.compute_coverage_stats_batch([p.get_py_ptr() for p in psms], num_threads)]
+
+
+def filter_psms_by_coverage(psms: List[Feature], min_b_coverage: float, min_y_coverage: float) -> List[Feature]:
+    """Keep the PSMs with sufficient b and y ion coverage
+
+    Args:
+        psms (List[Feature]): The PSMs
+        min_b_coverage (float): The minimum fraction (0-1) of matched b ions
+        min_y_coverage (float): The minimum fraction (0-1) of matched y ions
+
+    Returns:
+        List[Feature]: The passing PSMs
+    """
+    return [Feature.from_py_feature(f) for f in
+            psc.filter_psms_by_coverage([p.get_py_ptr() for p in psms], min_b_coverage, min_y_coverage)]
+
+
+def compute_delta_scores(features: List[Feature]) -> List[Feature]:
+    """Fill the delta scores of PSMs per spectrum: delta_next is the hyperscore difference to the next PSM relative
+    to the hyperscore, delta_best the rank 1 hyperscore minus the median hyperscore of the spectrum
+
+    Args:
+        features (List[Feature]): The PSMs
+
+    Returns:
+        List[Feature]: The PSMs, sorted by spec_id, then descending hyperscore
+    """
+    return [Feature.from_py_feature(f) for f in psc.compute_delta_scores([f.get_py_ptr() for f in features])]
+
+
+def delta_score_distribution(psms: List[Feature], target_only: bool = False) -> List[float]:
+    """The relative delta scores (see compute_delta_scores) of the best PSM of every spectrum
+
+    Args:
+        psms (List[Feature]): The PSMs
+        target_only (bool, optional): Whether to only include target PSMs. Defaults to False.
+
+    Returns:
+        List[float]: The delta scores
+    """
+    return psc.delta_score_distribution([p.get_py_ptr() for p in psms], target_only)
+
+
+def score_threshold_from_delta(psms: List[Feature], fdr: float = 0.01) -> float:
+    """The hyperscore cutoff at a FDR estimated with the relative delta score as discriminant
+
+    Args:
+        psms (List[Feature]): The PSMs, targets and decoys
+        fdr (float, optional): The FDR. Defaults to 0.01.
+
+    Returns:
+        float: The lowest hyperscore of a target PSM accepted at the FDR
+    """
+    return psc.score_threshold_from_delta([p.get_py_ptr() for p in psms], fdr)
+
+
+def psms_from_parquet(path: str) -> List[Feature]:
+    """Read PSMs written with psms_to_parquet, columns missing in files written by older versions are set to
+    their defaults
+
+    Args:
+        path (str): The Parquet file or partitioned directory
+
+    Returns:
+        List[Feature]: The PSMs
+    """
+    return [Feature.from_py_feature(f) for f in psc.psms_from_parquet(path)]
+
+
+def localize_modification(feature: Feature, db: IndexedDatabase, spectrum: ProcessedSpectrum, mod_unimod_id: int,
+                          fragment_tolerance: Tolerance) -> List[Tuple[int, float]]:
+    """Ascore-like localization of a variable modification, all placements on eligible residues are scored
+    using their site-determining fragment ions. The per-residue scores and the best site are stored on the PSM
+
+    Args:
+        feature (Feature): The PSM
+        db (IndexedDatabase): The database the PSM was scored against
+        spectrum (ProcessedSpectrum): The spectrum of the PSM
+        mod_unimod_id (int): The Unimod accession of the modification, supported are 1 (acetyl),
+            7 (deamidation), 21 (phospho) and 35 (oxidation)
+        fragment_tolerance (Tolerance): The fragment tolerance
+
+    Returns:
+        List[Tuple[int, float]]: The (position, localization score) of all eligible residues, best first
+    """
+    return psc.localize_modification(feature.get_py_ptr(), db.get_py_ptr(), spectrum.get_py_ptr(),
+                                     mod_unimod_id, fragment_tolerance.get_py_ptr())
@@ -0,0 +1,174 @@
+from typing import Dict, List, Optional, Tuple
+
+import sagepy_connector
+from sagepy.core.database import IndexedDatabase
+from sagepy.core.mass import Tolerance
+from sagepy.core.scoring import Feature, Fragments
+from sagepy.core.spectrum import ProcessedSpectrum
+
+psc = sagepy_connector.py_spectral_library
+
+
+class SpectralLibrary:
+    """In-memory spectral library, the fragments of each (modified sequence, charge) precursor keyed by
+    quantized precursor m/z. Build it from search results with build_spectral_library
+    """
+    def __init__(self):
+        self.__spectral_library_ptr = psc.PySpectralLibrary()
+
+    @classmethod
+    def from_py_spectral_library(cls, spectral_library: psc.PySpectralLibrary):
+        instance = cls.__new__(cls)
+        instance.__spectral_library_ptr = spectral_library
+        return instance
+
+    def get_py_ptr(self):
+        return self.__spectral_library_ptr
+
+    @property
+    def precursors(self) -> List[Tuple[str, int]]:
+        return self.__spectral_library_ptr.precursors
+
+    def get(self, sequence_modified: str, charge: int) -> Optional[Fragments]:
+        """Get the library fragments of a precursor
+
+        Args:
+            sequence_modified (str): The UNIMOD annotated sequence, e.g. PEPC[UNIMOD:4]TIDE
+            charge (int): The precursor charge
+
+        Returns:
+            Optional[Fragments]: The fragments, None if the precursor is not in the library
+        """
+        maybe_fragments = self.__spectral_library_ptr.get(sequence_modified, charge)
+        if maybe_fragments is None:
+            return None
+        return Fragments.from_py_fragments(maybe_fragments)
+
+    def __len__(self):
+        return len(self.__spectral_library_ptr)
+
+    def __repr__(self):
+        return f"SpectralLibrary(num_entries: {len(self)})"
+
+
+def build_spectral_library(psms: List[Feature], db: IndexedDatabase, min_q_value: float = 0.01) -> SpectralLibrary:
+    """Build a spectral library from confident PSMs, the best scoring PSM of each (modified sequence, charge)
+    provides the library spectrum
+
+    Args:
+        psms (List[Feature]): The PSMs, scored with fragment annotation
+        db (IndexedDatabase): The database searched
+        min_q_value (float, optional): The spectrum q-value a PSM needs to enter the library. Defaults to 0.01.
+
+    Returns:
+        SpectralLibrary: The library
+    """
+    return SpectralLibrary.from_py_spectral_library(
+        psc.build_spectral_library([p.get_py_ptr() for p in psms], db.get_py_ptr(), min_q_value))
+
+
+def score_against_library(library: SpectralLibrary, spectrum: ProcessedSpectrum, precursor_tolerance: Tolerance,
+                          spectral_angle_threshold: float = 0.5,
+                          fragment_tolerance: Optional[Tolerance] = None) -> List[Feature]:
+    """Search a spectrum against a spectral library, candidates are selected by precursor m/z and ranked by
+    the normalized spectral contrast angle of their fragments
+
+    Args:
+        library (SpectralLibrary): The library
+        spectrum (ProcessedSpectrum): The spectrum
+        precursor_tolerance (Tolerance): The precursor m/z tolerance
+        spectral_angle_threshold (float, optional): The minimum spectral angle of a match. Defaults to 0.5.
+        fragment_tolerance (Optional[Tolerance], optional): The fragment tolerance. Defaults to None (20 ppm).
+
+    Returns:
+        List[Feature]: The matches, with the spectral angle as hyperscore and discriminant score
+    """
+    result = psc.score_against_library(library.get_py_ptr(), spectrum.get_py_ptr(), precursor_tolerance.get_py_ptr(),
+                                       spectral_angle_threshold,
+                                       fragment_tolerance.get_py_ptr() if fragment_tolerance is not None else None)
+    return [Feature.from_py_feature(p) for p in result]
+
+
+class SpectralLibraryEntry:
+    def __init__(self, name: str, mw: float, charge: int, fragments: Fragments,
+                 metadata: Optional[Dict[str, str]] = None):
+        """An entry of an MSP (NIST) spectral library
+
+        Args:
+            name (str): The name, by convention the modified sequence and charge, e.g. PEPTIDEK/2
+            mw (float): The neutral precursor mass
+            charge (int): The precursor charge
+            fragments (Fragments): The peaks, unannotated peaks are y ions with ordinal 0
+            metadata (Optional[Dict[str, str]], optional): The other header fields. Defaults to None.
+        """
+        self.__entry_ptr = sagepy_connector.py_spectrum.PySpectralLibraryEntry(
+            name, mw, charge, fragments.get_py_ptr(), metadata)
+
+    @classmethod
+    def from_py_spectral_library_entry(cls, entry: sagepy_connector.py_spectrum.PySpectralLibraryEntry):
+        instance = cls.__new__(cls)
+        instance.__entry_ptr = entry
+        return instance
+
+    @property
+    def name(self) -> str:
+        return self.__entry_ptr.name
+
+    @property
+    def mw(self) -> float:
+        return self.__entry_ptr.mw
+
+    @property
+    def charge(self) -> int:
+        return self.__entry_ptr.charge
+
+    @property
+    def fragments(self) -> Fragments:
+        return Fragments.from_py_fragments(self.__entry_ptr.fragments)
+
+    @property
+    def metadata(self) -> Dict[str, str]:
+        return self.__entry_ptr.metadata
+
+    def get_py_ptr(self):
+        return self.__entry_ptr
+
+    def __repr__(self):
+        return f"SpectralLibraryEntry(name: {self.name}, mw: {self.mw}, charge: {self.charge}, " \
+               f"num_peaks: {len(self.fragments.mz_calculated)})"
+
+
+def read_msp(path: str) -> List[SpectralLibraryEntry]:
+    """Read an MSP (NIST) spectral library
+
+    Args:
+        path (str): The path to the MSP file
+
+    Returns:
+        List[SpectralLibraryEntry]: The entries
+    """
+    return [SpectralLibraryEntry.from_py_spectral_library_entry(e)
+            for e in sagepy_connector.py_spectrum.read_msp(path)]
+
+
+def write_msp(entries: List[SpectralLibraryEntry], path: str):
+    """Write entries as an MSP (NIST) spectral library
+
+    Args:
+        entries (List[SpectralLibraryEntry]): The entries
+        path (str): The path to the MSP file
+    """
+    sagepy_connector.py_spectrum.write_msp([e.get_py_ptr() for e in entries], path)
+
+
+def msp_to_spectral_library(entries: List[SpectralLibraryEntry]) -> SpectralLibrary:
+    """Convert MSP entries to a spectral library that can be searched with score_against_library
+
+    Args:
+        entries (List[SpectralLibraryEntry]): The entries, each with a known charge
+
+    Returns:
+        SpectralLibrary: The library
+    """
+    return SpectralLibrary.from_py_spectral_library(
+        sagepy_connector.py_spectrum.msp_to_spectral_library([e.get_py_ptr() for e in entries]))
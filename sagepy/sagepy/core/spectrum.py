@@ -1,6 +1,6 @@
 import numpy as np
 
-from typing import List
+from typing import Dict, List, Optional, Sequence, Tuple, Iterator
 
 import sagepy_connector
 from numpy.typing import NDArray
@@ -173,7 +173,10 @@ class ProcessedSpectrum:
                  ion_injection_time: float,
                  precursors: List[Precursor],
                  peaks: List[Peak],
-                 total_ion_current: float):
+                 total_ion_current: float,
+                 activation_type: Optional[str] = None,
+                 is_centroided: bool = True,
+                 precursor_candidates: Optional[List[Tuple[float, int, float]]] = None):
         """ProcessedSpectrum class
 
         Args:
@@ -185,11 +188,18 @@ class ProcessedSpectrum:
             precursors (List[Precursor]): The precursors of the spectrum
             peaks (List[Peak]): The peaks of the spectrum
             total_ion_current (float): The total ion current of the spectrum
+            activation_type (Optional[str], optional): The fragmentation method, e.g. 'HCD', 'ETD' or 'ECD',
+                ETD / ECD spectra are scored with c and z ions, EThcD spectra with b, y, c and z ions. Defaults to None.
+            is_centroided (bool, optional): Whether the peaks are centroids, False for profile-mode spectra
+                (centroid their raw spectra with centroid_spectrum before processing). Defaults to True.
+            precursor_candidates (Optional[List[Tuple[float, int, float]]], optional): Candidate (neutral mass,
+                charge, confidence) of the precursor, see deconvolve_charge. Defaults to None.
         """
         self.__processed_spectrum_ptr = psc.PyProcessedSpectrum(
             level, id, file_id, scan_start_time,
             ion_injection_time, [p.get_py_ptr() for p in precursors],
-            [p.get_py_ptr() for p in peaks], total_ion_current)
+            [p.get_py_ptr() for p in peaks], total_ion_current, activation_type, is_centroided,
+            precursor_candidates)
 
     @classmethod
     def from_py_processed_spectrum(cls, processed_spectrum: psc.PyProcessedSpectrum):
@@ -229,6 +239,74 @@ class ProcessedSpectrum:
     def total_ion_current(self):
         return self.__processed_spectrum_ptr.total_ion_current
 
+    @property
+    def activation_type(self) -> Optional[str]:
+        return self.__processed_spectrum_ptr.activation_type
+
+    @property
+    def is_centroided(self) -> bool:
+        return self.__processed_spectrum_ptr.is_centroided
+
+    @property
+    def precursor_candidates(self) -> List[Tuple[float, int, float]]:
+        return self.__processed_spectrum_ptr.precursor_candidates
+
+    @property
+    def ims_values(self) -> Optional[List[float]]:
+        return self.__processed_spectrum_ptr.ims_values
+
+    @property
+    def precursor_ims(self) -> Optional[float]:
+        return self.__processed_spectrum_ptr.precursor_ims
+
+    @classmethod
+    def from_arrays(cls, spec_id: str, precursor_mz: float, precursor_charge: int, mz: NDArray, intensity: NDArray,
+                    rt: float, precursor_intensity: Optional[float] = None) -> 'ProcessedSpectrum':
+        """Create a centroided MS2 spectrum from m/z and intensity arrays
+
+        Args:
+            spec_id (str): The id of the spectrum
+            precursor_mz (float): The precursor m/z
+            precursor_charge (int): The precursor charge, 0 if unknown
+            mz (NDArray): The ascending peak m/z values
+            intensity (NDArray): The peak intensities
+            rt (float): The retention time
+            precursor_intensity (Optional[float], optional): The precursor intensity. Defaults to None.
+
+        Returns:
+            ProcessedSpectrum: The spectrum
+        """
+        return cls.from_py_processed_spectrum(psc.PyProcessedSpectrum.from_arrays(
+            spec_id, precursor_mz, precursor_charge, np.asarray(mz, dtype=np.float32),
+            np.asarray(intensity, dtype=np.float32), rt, precursor_intensity))
+
+    @classmethod
+    def from_dataframe(cls, df: Dict[str, Sequence]) -> List['ProcessedSpectrum']:
+        """Create spectra in parallel from columns, e.g. of a pandas DataFrame converted with to_dict('list')
+
+        Args:
+            df (Dict[str, Sequence]): The columns spec_id, precursor_mz, precursor_charge, rt, mz and intensity
+                (one array per spectrum) and optionally precursor_intensity
+
+        Returns:
+            List[ProcessedSpectrum]: The spectra
+        """
+        return [cls.from_py_processed_spectrum(s) for s in psc.PyProcessedSpectrum.from_dataframe(dict(df))]
+
+    def with_precursor_candidates(self, precursor_candidates: List[Tuple[float, int, float]]) -> 'ProcessedSpectrum':
+        """Copy of the spectrum carrying the given (neutral mass, charge, confidence) precursor candidates"""
+        return ProcessedSpectrum.from_py_processed_spectrum(
+            self.__processed_spectrum_ptr.with_precursor_candidates(precursor_candidates))
+
+    def with_ims_values(self, ims_values: List[float]) -> 'ProcessedSpectrum':
+        """Copy of the spectrum carrying the inverse ion mobility (1/K0) of each of its peaks, e.g. of a PASEF frame"""
+        return ProcessedSpectrum.from_py_processed_spectrum(self.__processed_spectrum_ptr.with_ims_values(ims_values))
+
+    def with_precursor_ims(self, precursor_ims: float) -> 'ProcessedSpectrum':
+        """Copy of the spectrum carrying the observed inverse ion mobility (1/K0) of its precursor, used by the ion
+        mobility filter of the Scorer (see ims_tolerance)"""
+        return ProcessedSpectrum.from_py_processed_spectrum(self.__processed_spectrum_ptr.with_precursor_ims(precursor_ims))
+
     def get_py_ptr(self):
         return self.__processed_spectrum_ptr
 
@@ -376,5 +454,255 @@ class SpectrumProcessor:
         return f"SpectrumProcessor(take_top_n: {self.take_top_n}, max_fragment_mz: {self.max_fragment_mz}, " \
                f"min_fragment_mz: {self.min_fragment_mz}, deisotope: {self.deisotope})"
 
-    def process(self, raw_spectrum: RawSpectrum) -> ProcessedSpectrum:
-        return ProcessedSpectrum.from_py_processed_spectrum(self.__spectrum_processor_ptr.process(raw_spectrum.get_py_ptr()))
+    def process(self, raw_spectrum: RawSpectrum, activation_type: Optional[str] = None) -> ProcessedSpectrum:
+        return ProcessedSpectrum.from_py_processed_spectrum(
+            self.__spectrum_processor_ptr.process(raw_spectrum.get_py_ptr(), activation_type))
+
+
+def centroid_spectrum(spectrum: RawSpectrum, snr_threshold: float = 3.0, ppm: float = 10.0) -> RawSpectrum:
+    """Centroid a profile-mode raw spectrum before processing: intensities are smoothed with a Gaussian kernel,
+    local maxima within ppm above the noise level (median intensity) times snr_threshold are refined by a quadratic
+    apex fit and their intensity is summed within ppm of the apex
+
+    Args:
+        spectrum (RawSpectrum): The profile-mode spectrum
+        snr_threshold (float, optional): The minimum signal-to-noise ratio of a peak. Defaults to 3.0.
+        ppm (float, optional): The m/z window around an apex in ppm. Defaults to 10.0.
+
+    Returns:
+        RawSpectrum: The centroided spectrum
+    """
+    return RawSpectrum.from_py_raw_spectrum(psc.centroid_spectrum(spectrum.get_py_ptr(), snr_threshold, ppm))
+
+
+def centroid_collection(spectra: List[RawSpectrum], snr_threshold: float = 3.0, ppm: float = 10.0,
+                        num_threads: int = 4) -> List[RawSpectrum]:
+    """Centroid profile-mode raw spectra in parallel, see centroid_spectrum
+
+    Args:
+        spectra (List[RawSpectrum]): The profile-mode spectra
+        snr_threshold (float, optional): The minimum signal-to-noise ratio of a peak. Defaults to 3.0.
+        ppm (float, optional): The m/z window around an apex in ppm. Defaults to 10.0.
+        num_threads (int, optional): The number of threads. Defaults to 4.
+
+    Returns:
+        List[RawSpectrum]: The centroided spectra
+    """
+    return [RawSpectrum.from_py_raw_spectrum(s) for s in
+            psc.centroid_collection([s.get_py_ptr() for s in spectra], snr_threshold, ppm, num_threads)]
+
+
+def bin_spectrum(spectrum: ProcessedSpectrum, min_mz: float = 100.0, max_mz: float = 2000.0, bin_width: float = 1.0005,
+                 normalize: bool = True) -> NDArray:
+    """Sum the peak intensities of a spectrum into fixed-width m/z bins, e.g. as input of ML scoring models
+
+    Args:
+        spectrum (ProcessedSpectrum): The spectrum
+        min_mz (float, optional): The lower bound of the first bin. Defaults to 100.0.
+        max_mz (float, optional): The upper bound of the last bin. Defaults to 2000.0.
+        bin_width (float, optional): The bin width in Th. Defaults to 1.0005.
+        normalize (bool, optional): Whether to scale the bins to unit L2 norm. Defaults to True.
+
+    Returns:
+        NDArray: The binned intensities
+    """
+    return psc.bin_spectrum(spectrum.get_py_ptr(), min_mz, max_mz, bin_width, normalize)
+
+
+def bin_spectra_parallel(spectra: List[ProcessedSpectrum], min_mz: float = 100.0, max_mz: float = 2000.0,
+                         bin_width: float = 1.0005, normalize: bool = True, num_threads: int = 4) -> NDArray:
+    """Bin spectra in parallel, see bin_spectrum
+
+    Args:
+        spectra (List[ProcessedSpectrum]): The spectra
+        min_mz (float, optional): The lower bound of the first bin. Defaults to 100.0.
+        max_mz (float, optional): The upper bound of the last bin. Defaults to 2000.0.
+        bin_width (float, optional): The bin width in Th. Defaults to 1.0005.
+        normalize (bool, optional): Whether to scale the bins to unit L2 norm. Defaults to True.
+        num_threads (int, optional): The number of threads. Defaults to 4.
+
+    Returns:
+        NDArray: The binned intensities, one row per spectrum
+    """
+    return psc.bin_spectra_parallel([s.get_py_ptr() for s in spectra], min_mz, max_mz, bin_width, normalize,
+                                    num_threads)
+
+
+def dot_product_score(binned_a: NDArray, binned_b: NDArray) -> float:
+    """Dot product of two binned spectra
+
+    Args:
+        binned_a (NDArray): The first binned spectrum
+        binned_b (NDArray): The second binned spectrum, with the same bins
+
+    Returns:
+        float: The dot product
+    """
+    return psc.dot_product_score(list(binned_a), list(binned_b))
+
+
+def cosine_similarity(binned_a: NDArray, binned_b: NDArray) -> float:
+    """Cosine similarity of two binned spectra
+
+    Args:
+        binned_a (NDArray): The first binned spectrum
+        binned_b (NDArray): The second binned spectrum, with the same bins
+
+    Returns:
+        float: The cosine similarity, 0.0 if either spectrum is empty
+    """
+    return psc.cosine_similarity(list(binned_a), list(binned_b))
+
+
+def deconvolve_charge(spectrum: ProcessedSpectrum, min_charge: int = 2, max_charge: int = 4,
+                      mass_tolerance_ppm: float = 10.0,
+                      ms1_spectrum: Optional[ProcessedSpectrum] = None) -> List[Tuple[float, int, float]]:
+    """Rank the charge states of the precursor of a spectrum by the fit of its isotope envelope to the averagine
+    model, the precursor may have been selected on one of the first isotope peaks
+
+    Args:
+        spectrum (ProcessedSpectrum): The spectrum, its first precursor is deconvolved
+        min_charge (int, optional): The minimum charge. Defaults to 2.
+        max_charge (int, optional): The maximum charge. Defaults to 4.
+        mass_tolerance_ppm (float, optional): The tolerance of the isotope peaks in ppm. Defaults to 10.0.
+        ms1_spectrum (Optional[ProcessedSpectrum], optional): The MS1 spectrum holding the envelope. Defaults
+            to None (the spectrum itself).
+
+    Returns:
+        List[Tuple[float, int, float]]: The (neutral mass, charge, confidence) candidates, best first
+    """
+    return psc.deconvolve_charge(spectrum.get_py_ptr(), min_charge, max_charge, mass_tolerance_ppm,
+                                 ms1_spectrum.get_py_ptr() if ms1_spectrum is not None else None)
+
+
+def simulate_tims_isolation(spectrum: ProcessedSpectrum, precursor_mz: float, precursor_ims: float,
+                            mz_window: float, ims_window: float) -> ProcessedSpectrum:
+    """Simulate the TIMS-DDA isolation of a precursor in m/z and ion mobility, fragment peaks of co-isolated ions
+    with another ion mobility are dropped
+
+    Args:
+        spectrum (ProcessedSpectrum): The spectrum, carrying the ion mobility of its peaks (see with_ims_values)
+        precursor_mz (float): The precursor m/z the isolation window is centered on
+        precursor_ims (float): The precursor inverse ion mobility (1/K0)
+        mz_window (float): The full width of the m/z isolation window in Th
+        ims_window (float): The full width of the ion mobility window in 1/K0
+
+    Returns:
+        ProcessedSpectrum: The spectrum with the peaks inside the ion mobility window and the precursors inside the
+            m/z window
+    """
+    return ProcessedSpectrum.from_py_processed_spectrum(
+        psc.simulate_tims_isolation(spectrum.get_py_ptr(), precursor_mz, precursor_ims, mz_window, ims_window))
+
+
+def tims_chimera_fraction(spectrum: ProcessedSpectrum, precursor_ims: float, ims_window: float) -> float:
+    """Fraction of the fragment intensity outside the ion mobility window of the precursor, an estimate of the
+    intensity of interfering ions
+
+    Args:
+        spectrum (ProcessedSpectrum): The spectrum, carrying the ion mobility of its peaks (see with_ims_values)
+        precursor_ims (float): The precursor inverse ion mobility (1/K0)
+        ims_window (float): The full width of the ion mobility window in 1/K0
+
+    Returns:
+        float: The interfering intensity fraction, 0 for a spectrum without intensity
+    """
+    return psc.tims_chimera_fraction(spectrum.get_py_ptr(), precursor_ims, ims_window)
+
+
+def compute_precursor_purity(ms1_spectrum: ProcessedSpectrum, precursor_mz: float, isolation_window_da: float,
+                             charge: Optional[int] = None, tolerance_ppm: float = 10.0) -> float:
+    """Purity of a precursor isolation window, the intensity of the precursor peak relative to all MS1 peaks in the
+    window, see tag_precursor_purity in sagepy.core.scoring to tag PSMs
+
+    Args:
+        ms1_spectrum (ProcessedSpectrum): The MS1 spectrum
+        precursor_mz (float): The precursor m/z the window is centered on
+        isolation_window_da (float): The full width of the isolation window in Da
+        charge (Optional[int], optional): The precursor charge, if given its isotope peaks count as precursor
+            intensity. Defaults to None.
+        tolerance_ppm (float, optional): The tolerance of the precursor peaks in ppm. Defaults to 10.0.
+
+    Returns:
+        float: The precursor purity, 0 for an empty window
+    """
+    return psc.compute_precursor_purity(ms1_spectrum.get_py_ptr(), precursor_mz, isolation_window_da, charge,
+                                        tolerance_ppm)
+
+
+def extract_xic(ms1_spectra: List[Tuple[float, ProcessedSpectrum]], target_mz: float, charge: int,
+                tolerance: Tolerance, num_isotopes: int = 1) -> List[Tuple[float, float]]:
+    """Extract the ion chromatogram of a precursor m/z, the summed intensity of the peaks within the tolerance in
+    each MS1 spectrum
+
+    Args:
+        ms1_spectra (List[Tuple[float, ProcessedSpectrum]]): The (retention time, MS1 spectrum) pairs
+        target_mz (float): The precursor m/z
+        charge (int): The precursor charge, setting the isotope spacing
+        tolerance (Tolerance): The m/z tolerance
+        num_isotopes (int, optional): The number of isotope traces summed, starting at target_mz. Defaults to 1.
+
+    Returns:
+        List[Tuple[float, float]]: The (retention time, intensity) pairs sorted by retention time
+    """
+    return psc.extract_xic([(rt, s.get_py_ptr()) for rt, s in ms1_spectra], target_mz, charge,
+                           tolerance.get_py_ptr(), num_isotopes)
+
+
+def integrate_xic(xic: List[Tuple[float, float]], apex_rt: float, rt_window: float) -> float:
+    """Integrate an XIC by the trapezoidal rule in a retention time window around its apex
+
+    Args:
+        xic (List[Tuple[float, float]]): The (retention time, intensity) pairs
+        apex_rt (float): The retention time the window is centered on, e.g. of find_xic_apex
+        rt_window (float): The full width of the window
+
+    Returns:
+        float: The peak area
+    """
+    return psc.integrate_xic(xic, apex_rt, rt_window)
+
+
+def find_xic_apex(xic: List[Tuple[float, float]]) -> float:
+    """Find the retention time of the most intense point of an XIC
+
+    Args:
+        xic (List[Tuple[float, float]]): The (retention time, intensity) pairs
+
+    Returns:
+        float: The apex retention time
+    """
+    return psc.find_xic_apex(xic)
+
+
+def read_mzml(path: str, ms_level: int = 2, file_id: int = 0, centroid: bool = False) -> List[ProcessedSpectrum]:
+    """Read the spectra of an mzML file (optionally gzip compressed) with the given MS level, all peaks are kept
+
+    Args:
+        path (str): The mzML file
+        ms_level (int, optional): The MS level of the spectra to read. Defaults to 2.
+        file_id (int, optional): The file id assigned to the spectra. Defaults to 0.
+        centroid (bool, optional): Whether to centroid profile-mode spectra. Defaults to False.
+
+    Returns:
+        List[ProcessedSpectrum]: The spectra, retention times in minutes
+    """
+    return [ProcessedSpectrum.from_py_processed_spectrum(s) for s in
+            psc.read_mzml(path, ms_level, file_id, centroid)]
+
+
+def iter_mzml(path: str, ms_level: Optional[int] = None, file_id: int = 0,
+              centroid: bool = False) -> Iterator[ProcessedSpectrum]:
+    """Stream the spectra of an mzML file one at a time, see read_mzml
+
+    Args:
+        path (str): The mzML file
+        ms_level (Optional[int], optional): The MS level of the spectra to read. Defaults to None (all levels).
+        file_id (int, optional): The file id assigned to the spectra. Defaults to 0.
+        centroid (bool, optional): Whether to centroid profile-mode spectra. Defaults to False.
+
+    Yields:
+        ProcessedSpectrum: The spectra in file order
+    """
+    for spectrum in psc.iter_mzml(path, ms_level, file_id, centroid):
+        yield ProcessedSpectrum.from_py_processed_spectrum(spectrum)
@@ -3,15 +3,17 @@ from typing import Optional, List
 import sagepy_connector
 
 from sagepy.core import ProcessedSpectrum
-from sagepy.core.scoring import Feature
-from sagepy.core.spectrum import Peak
+from sagepy.core.mass import Tolerance
+from sagepy.core.database import IndexedDatabase
+from sagepy.core.scoring import Feature, Scorer
+from sagepy.core.spectrum import Peak, RawSpectrum
 
 psc = sagepy_connector.py_tmt
 
 
 class Isobaric:
     def __init__(self, type_name: str):
-        types = ["tmt6", "tmt10", "tmt11", "tmt16", "tmt18"]
+        types = ["tmt6", "tmt10", "tmt11", "tmt16", "tmt18", "tmtpro18"]
         if type_name in types:
             self.__isobaric_ptr = psc.PyIsobaric(type_name)
         else:
@@ -27,6 +29,14 @@ class Isobaric:
     def type_name(self):
         return self.__isobaric_ptr.type_name
 
+    @property
+    def reporter_masses(self) -> List[float]:
+        return self.__isobaric_ptr.reporter_masses
+
+    @property
+    def channel_names(self) -> List[str]:
+        return self.__isobaric_ptr.channel_names
+
     def __repr__(self):
         return f"Isobaric({self.__isobaric_ptr.type_name})"
 
@@ -132,3 +142,264 @@ class Quant:
 
     def get_py_ptr(self):
         return self.__quant_ptr
+
+
+class ImpurityMatrix:
+    """Isotope impurity correction matrix of a TMT lot
+
+    Args:
+        channels (List[str]): The channel names
+        matrix (List[List[float]]): The fraction of channel j's signal observed in channel i, as
+            provided by the kit manufacturer
+    """
+    def __init__(self, channels: List[str], matrix: List[List[float]]):
+        self.__impurity_matrix_ptr = psc.PyImpurityMatrix(channels, matrix)
+
+    @classmethod
+    def from_py_impurity_matrix(cls, impurity_matrix: psc.PyImpurityMatrix):
+        instance = cls.__new__(cls)
+        instance.__impurity_matrix_ptr = impurity_matrix
+        return instance
+
+    @classmethod
+    def tmt6(cls) -> 'ImpurityMatrix':
+        """Example matrix of a TMT6plex lot, replace it with the certificate of the lot used"""
+        return cls.from_py_impurity_matrix(psc.PyImpurityMatrix.tmt6())
+
+    @classmethod
+    def tmtpro(cls) -> 'ImpurityMatrix':
+        """Example matrix of a TMTpro 18plex lot, replace it with the certificate of the lot used"""
+        return cls.from_py_impurity_matrix(psc.PyImpurityMatrix.tmtpro())
+
+    @property
+    def channels(self) -> List[str]:
+        return self.__impurity_matrix_ptr.channels
+
+    @property
+    def matrix(self) -> List[List[float]]:
+        return self.__impurity_matrix_ptr.matrix
+
+    def __repr__(self):
+        return f"ImpurityMatrix(channels={self.channels})"
+
+    def get_py_ptr(self):
+        return self.__impurity_matrix_ptr
+
+
+class SpsQuantResult:
+    """SpsQuantResult class, the MS3 quantification of an identified MS2 spectrum"""
+
+    @classmethod
+    def from_py_sps_quant_result(cls, result: psc.PySpsQuantResult):
+        instance = cls.__new__(cls)
+        instance.__result_ptr = result
+        return instance
+
+    @property
+    def psm(self) -> Feature:
+        return Feature.from_py_feature(self.__result_ptr.psm)
+
+    @property
+    def ms3_spec_id(self) -> str:
+        return self.__result_ptr.ms3_spec_id
+
+    @property
+    def intensities(self) -> List[float]:
+        return self.__result_ptr.intensities
+
+    @property
+    def sps_match_fraction(self) -> float:
+        return self.__result_ptr.sps_match_fraction
+
+    def __repr__(self):
+        return (f"SpsQuantResult(psm={self.psm.spec_id}, ms3_spec_id={self.ms3_spec_id}, "
+                f"sps_match_fraction={self.sps_match_fraction}, intensities={self.intensities})")
+
+    def get_py_ptr(self):
+        return self.__result_ptr
+
+
+def extract_tmt_intensities(spectrum: RawSpectrum, isobaric: Isobaric,
+                            tolerance: Tolerance = Tolerance(ppm=(-20.0, 20.0))) -> List[float]:
+    """Extract the reporter ion intensities of a spectrum
+
+    Args:
+        spectrum (RawSpectrum): The (centroided) MS2 or MS3 spectrum
+        isobaric (Isobaric): The isobaric label, e.g. Isobaric('tmtpro18')
+        tolerance (Tolerance, optional): The reporter ion tolerance. Defaults to Tolerance(ppm=(-20.0, 20.0)).
+
+    Returns:
+        List[float]: The intensity per channel (see Isobaric.channel_names), 0.0 if no peak was found
+    """
+    return psc.extract_tmt_intensities(spectrum.get_py_ptr(), isobaric.get_py_ptr(), tolerance.get_py_ptr())
+
+
+def normalize_tmt_intensities(intensities: List[List[float]], method: str = 'sample_loading') -> List[List[float]]:
+    """Normalize reporter ion intensities
+
+    Args:
+        intensities (List[List[float]]): The intensities, one row of channels per spectrum
+        method (str, optional): The normalization, 'sum' (each row sums to 1), 'median' (equal channel medians),
+            'sample_loading' (equal channel totals) or 'vsn' (variance stabilizing transformation, see
+            vsn_normalize). Defaults to 'sample_loading'.
+
+    Returns:
+        List[List[float]]: The normalized intensities
+    """
+    return psc.normalize_tmt_intensities(intensities, method)
+
+
+class VsnParams:
+    """Fitted variance stabilizing transformation of each channel, h(y) = asinh((y - mu) / sigma)
+
+    Args:
+        mu (List[float]): The location of each channel
+        sigma (List[float]): The scale of each channel
+    """
+    def __init__(self, mu: List[float], sigma: List[float]):
+        self.__vsn_params_ptr = psc.PyVsnParams(mu, sigma)
+
+    @classmethod
+    def from_py_vsn_params(cls, vsn_params: psc.PyVsnParams):
+        instance = cls.__new__(cls)
+        instance.__vsn_params_ptr = vsn_params
+        return instance
+
+    @property
+    def mu(self) -> List[float]:
+        return self.__vsn_params_ptr.mu
+
+    @property
+    def sigma(self) -> List[float]:
+        return self.__vsn_params_ptr.sigma
+
+    def __repr__(self):
+        return f"VsnParams(mu={self.mu}, sigma={self.sigma})"
+
+    def get_py_ptr(self):
+        return self.__vsn_params_ptr
+
+
+def fit_vsn(intensities: List[List[float]]) -> VsnParams:
+    """Fit variance stabilizing normalization (VSN) parameters, only rows without missing values are used
+
+    Args:
+        intensities (List[List[float]]): The intensities, one row of channels per spectrum or feature
+
+    Returns:
+        VsnParams: The fitted transformation of each channel
+    """
+    return VsnParams.from_py_vsn_params(psc.fit_vsn(intensities))
+
+
+def apply_vsn(intensities: List[List[float]], params: VsnParams) -> List[List[float]]:
+    """Apply fitted VSN parameters, e.g. to new data of the same channels
+
+    Args:
+        intensities (List[List[float]]): The intensities, one row of channels per spectrum or feature
+        params (VsnParams): The fitted parameters
+
+    Returns:
+        List[List[float]]: The transformed intensities, missing (0.0) values are kept
+    """
+    return psc.apply_vsn(intensities, params.get_py_ptr())
+
+
+def vsn_normalize(intensities: List[List[float]]) -> List[List[float]]:
+    """Variance stabilizing normalization, the arsinh transformation of each channel is fitted so that
+    transformed values of a row agree across channels
+
+    Args:
+        intensities (List[List[float]]): The intensities, one row of channels per spectrum or feature
+
+    Returns:
+        List[List[float]]: The transformed intensities, missing (0.0) values are kept
+    """
+    return psc.vsn_normalize(intensities)
+
+
+def correct_isotope_impurities(intensities: List[float], impurity_matrix: List[List[float]]) -> List[float]:
+    """Correct reporter ion intensities for isotopic impurities of the labeling reagents
+
+    Args:
+        intensities (List[float]): The observed intensity per channel
+        impurity_matrix (List[List[float]]): The fraction of channel j's signal observed in channel i, as
+            provided by the kit manufacturer
+
+    Returns:
+        List[float]: The corrected, non-negative intensities
+    """
+    return psc.correct_isotope_impurities(intensities, impurity_matrix)
+
+
+def validate_impurity_matrix(matrix: ImpurityMatrix) -> bool:
+    """Check that an impurity matrix is square, diagonally dominant and invertible
+
+    Args:
+        matrix (ImpurityMatrix): The impurity matrix
+
+    Returns:
+        bool: Whether the matrix can be used for correction
+    """
+    return psc.validate_impurity_matrix(matrix.get_py_ptr())
+
+
+def apply_impurity_correction(intensities: List[float], matrix: ImpurityMatrix) -> List[float]:
+    """Correct reporter ion intensities with the impurity matrix of the TMT lot
+
+    Args:
+        intensities (List[float]): The observed intensity per channel
+        matrix (ImpurityMatrix): The impurity matrix
+
+    Returns:
+        List[float]: The corrected, non-negative intensities
+    """
+    return psc.apply_impurity_correction(intensities, matrix.get_py_ptr())
+
+
+def extract_sps_ms3_intensities(ms3_spectrum: ProcessedSpectrum, ms2_psm: Feature, db: IndexedDatabase,
+                                isobaric: Isobaric, isolation_window: float = 2.0,
+                                reporter_tolerance: Tolerance = Tolerance(ppm=(-20.0, 20.0))) -> List[float]:
+    """Extract the reporter ion intensities of an SPS-MS3 spectrum for the PSM of its MS2 scan. The SPS precursors
+    are checked against the labelled b and y ions of the peptide, the intensities are weighted by the fraction
+    matched
+
+    Args:
+        ms3_spectrum (ProcessedSpectrum): The MS3 spectrum, keeping its reporter region (e.g. read with read_mzml)
+        ms2_psm (Feature): The PSM of the MS2 scan
+        db (IndexedDatabase): The database searched
+        isobaric (Isobaric): The isobaric label, e.g. Isobaric('tmt11')
+        isolation_window (float, optional): The SPS isolation window width in Th. Defaults to 2.0.
+        reporter_tolerance (Tolerance, optional): The reporter ion tolerance. Defaults to Tolerance(ppm=(-20.0, 20.0)).
+
+    Returns:
+        List[float]: The intensity per channel, all 0.0 if no SPS precursor matches
+    """
+    return psc.extract_sps_ms3_intensities(ms3_spectrum.get_py_ptr(), ms2_psm.get_py_ptr(), db.get_py_ptr(),
+                                           isobaric.get_py_ptr(), isolation_window, reporter_tolerance.get_py_ptr())
+
+
+def score_collection_with_sps(ms2_spectra: List[ProcessedSpectrum], ms3_spectra: List[ProcessedSpectrum],
+                              db: IndexedDatabase, scorer: Scorer, isobaric: Isobaric, isolation_window: float = 2.0,
+                              num_threads: int = 4,
+                              reporter_tolerance: Tolerance = Tolerance(ppm=(-20.0, 20.0))) -> List[SpsQuantResult]:
+    """Score MS2 spectra and quantify the best PSM of each with the MS3 scan referring to it
+
+    Args:
+        ms2_spectra (List[ProcessedSpectrum]): The MS2 spectra
+        ms3_spectra (List[ProcessedSpectrum]): The MS3 spectra, linked by the spectrum reference of their precursors
+        db (IndexedDatabase): The database
+        scorer (Scorer): The scorer
+        isobaric (Isobaric): The isobaric label
+        isolation_window (float, optional): The SPS isolation window width in Th. Defaults to 2.0.
+        num_threads (int, optional): The number of threads. Defaults to 4.
+        reporter_tolerance (Tolerance, optional): The reporter ion tolerance. Defaults to Tolerance(ppm=(-20.0, 20.0)).
+
+    Returns:
+        List[SpsQuantResult]: The quantified PSMs, MS2 spectra without identification or MS3 scan are skipped
+    """
+    results = psc.score_collection_with_sps([s.get_py_ptr() for s in ms2_spectra],
+                                            [s.get_py_ptr() for s in ms3_spectra], db.get_py_ptr(),
+                                            scorer.get_py_ptr(), isobaric.get_py_ptr(), isolation_window,
+                                            num_threads, reporter_tolerance.get_py_ptr())
+    return [SpsQuantResult.from_py_sps_quant_result(r) for r in results]
@@ -0,0 +1,168 @@
+from typing import List, Dict, Tuple
+
+import sagepy_connector
+from sagepy.core.database import IndexedDatabase
+from sagepy.core.enzyme import Enzyme
+from sagepy.core.scoring import Feature
+
+psc = sagepy_connector.py_utility
+
+
+def filter_by_modification(psms: List[Feature], db: IndexedDatabase, unimod_id: int) -> List[Feature]:
+    """Select the PSMs whose peptide carries a modification, e.g. all phospho-PSMs
+
+    Args:
+        psms (List[Feature]): The PSMs
+        db (IndexedDatabase): The database searched
+        unimod_id (int): The Unimod accession of the modification, e.g. 21 for phosphorylation
+
+    Returns:
+        List[Feature]: The PSMs carrying the modification
+    """
+    result = psc.filter_by_modification([p.get_py_ptr() for p in psms], db.get_py_ptr(), unimod_id)
+    return [Feature.from_py_feature(p) for p in result]
+
+
+def get_modification_positions(psm: Feature, db: IndexedDatabase, unimod_id: int) -> List[int]:
+    """Get the positions of a modification in the peptide of a PSM
+
+    Args:
+        psm (Feature): The PSM
+        db (IndexedDatabase): The database searched
+        unimod_id (int): The Unimod accession of the modification
+
+    Returns:
+        List[int]: The 0-based residue positions, N-terminal modifications are at position 0
+    """
+    return psc.get_modification_positions(psm.get_py_ptr(), db.get_py_ptr(), unimod_id)
+
+
+def count_modifications(psms: List[Feature], db: IndexedDatabase) -> Dict[int, int]:
+    """Count the occurrences of all modifications in the peptides of a PSM collection
+
+    Args:
+        psms (List[Feature]): The PSMs
+        db (IndexedDatabase): The database searched
+
+    Returns:
+        Dict[int, int]: The number of occurrences by Unimod accession
+    """
+    return psc.count_modifications([p.get_py_ptr() for p in psms], db.get_py_ptr())
+
+
+def sequence_to_proforma(psm: Feature, db: IndexedDatabase, use_mass_notation: bool = False) -> str:
+    """Get the ProForma 2.0 sequence of the peptide of a PSM, e.g. [UNIMOD:1]-PEPTM[UNIMOD:35]IDE
+
+    Args:
+        psm (Feature): The PSM
+        db (IndexedDatabase): The database searched
+        use_mass_notation (bool, optional): Write modifications as mass shifts, e.g. PEPTM[+15.9949]IDE.
+            Defaults to False.
+
+    Returns:
+        str: The ProForma sequence
+    """
+    return psc.sequence_to_proforma(psm.get_py_ptr(), db.get_py_ptr(), use_mass_notation)
+
+
+def proforma_to_unimod_sequence(proforma: str) -> str:
+    """Convert a ProForma 2.0 sequence to UNIMOD bracket notation, e.g. [Acetyl]-PEPTM[+15.9949]IDE to
+    [UNIMOD:1]PEPTM[UNIMOD:35]IDE. Mass shifts without known Unimod accession are kept as [+mass]
+
+    Args:
+        proforma (str): The ProForma sequence
+
+    Returns:
+        str: The UNIMOD-annotated sequence
+    """
+    return psc.proforma_to_unimod_sequence(proforma)
+
+
+def dedup_psms_per_peptide(psms: List[Feature], db: IndexedDatabase, score_field: str = 'discriminant_score',
+                           keep_top_n: int = 1) -> List[Feature]:
+    """Keep the best PSMs of each peptide, PSMs are grouped by modified sequence and charge
+
+    Args:
+        psms (List[Feature]): The PSMs
+        db (IndexedDatabase): The database searched
+        score_field (str, optional): The score ranking the PSMs, 'discriminant_score' or a feature name, higher is
+            better except for q-values and posterior_error. Defaults to 'discriminant_score'.
+        keep_top_n (int, optional): The number of PSMs kept per peptide. Defaults to 1.
+
+    Returns:
+        List[Feature]: The kept PSMs
+    """
+    result = psc.dedup_psms_per_peptide([p.get_py_ptr() for p in psms], db.get_py_ptr(), score_field, keep_top_n)
+    return [Feature.from_py_feature(p) for p in result]
+
+
+def dedup_psms_per_spectrum(psms: List[Feature]) -> List[Feature]:
+    """Keep the top ranked PSM of each spectrum
+
+    Args:
+        psms (List[Feature]): The PSMs
+
+    Returns:
+        List[Feature]: One PSM per file_id and spec_id
+    """
+    result = psc.dedup_psms_per_spectrum([p.get_py_ptr() for p in psms])
+    return [Feature.from_py_feature(p) for p in result]
+
+
+def missed_cleavage_distribution(psms: List[Feature]) -> Dict[int, int]:
+    """Count the PSMs per number of missed cleavages
+
+    Args:
+        psms (List[Feature]): The PSMs
+
+    Returns:
+        Dict[int, int]: The number of PSMs of each missed cleavage count
+    """
+    return psc.missed_cleavage_distribution([p.get_py_ptr() for p in psms])
+
+
+def compute_cleavage_efficiency(psms: List[Feature]) -> float:
+    """Fraction of PSMs without missed cleavages
+
+    Args:
+        psms (List[Feature]): The PSMs
+
+    Returns:
+        float: The cleavage efficiency, 0 for no PSMs
+    """
+    return psc.compute_cleavage_efficiency([p.get_py_ptr() for p in psms])
+
+
+def check_enzyme_specificity(psm: Feature, db: IndexedDatabase, enzyme: Enzyme) -> Tuple[bool, bool]:
+    """Check whether the termini of the peptide of a PSM agree with the cleavage rule of an enzyme. Protein termini
+    are specific, the database keeps no flanking residues, so only the terminus decided by the peptide's own residue
+    is checked against the rule, the other is taken from the semi-enzymatic flag of the digest
+
+    Args:
+        psm (Feature): The PSM
+        db (IndexedDatabase): The database searched
+        enzyme (Enzyme): The enzyme
+
+    Returns:
+        Tuple[bool, bool]: Whether the N-terminus and the C-terminus are specific
+    """
+    return psc.check_enzyme_specificity(psm.get_py_ptr(), db.get_py_ptr(), enzyme.get_py_ptr())
+
+
+def filter_by_specificity(psms: List[Feature], db: IndexedDatabase, enzyme: Enzyme,
+                          min_specificity: int = 2) -> List[Feature]:
+    """Select the PSMs with enough enzyme specific termini, see check_enzyme_specificity
+
+    Args:
+        psms (List[Feature]): The PSMs
+        db (IndexedDatabase): The database searched
+        enzyme (Enzyme): The enzyme
+        min_specificity (int, optional): The minimal number of specific termini, 0 (non-specific), 1 (semi) or 2
+            (fully specific). Defaults to 2.
+
+    Returns:
+        List[Feature]: The selected PSMs
+    """
+    result = psc.filter_by_specificity([p.get_py_ptr() for p in psms], db.get_py_ptr(), enzyme.get_py_ptr(),
+                                       min_specificity)
+    return [Feature.from_py_feature(p) for p in result]